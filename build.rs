@@ -1,5 +1,5 @@
 use std::env;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
 /// Remove an existing symlink (or file) at `dst`, then create a new symlink
@@ -51,7 +51,60 @@ fn find_torch_from_python() -> Option<PathBuf> {
     PathBuf::from(torch_init).parent().map(|p| p.to_path_buf())
 }
 
+/// The `(major, minor)` version in the `torch/version.h` under one of
+/// `include_dirs`.
+fn torch_version(include_dirs: &[PathBuf]) -> Option<(u32, u32)> {
+    let header = include_dirs
+        .iter()
+        .flat_map(|dir| {
+            [
+                dir.join("torch").join("version.h"),
+                dir.join("torch/csrc/api/include/torch/version.h"),
+            ]
+        })
+        .find(|path| path.exists())?;
+    let text = std::fs::read_to_string(header).ok()?;
+    let define = |name: &str| {
+        text.lines()
+            .find_map(|line| line.trim().strip_prefix("#define ")?.strip_prefix(name))
+            .and_then(|value| value.trim().parse().ok())
+    };
+    Some((
+        define("TORCH_VERSION_MAJOR ")?,
+        define("TORCH_VERSION_MINOR ")?,
+    ))
+}
+
+/// Fail the build if the bridge is compiled against another libtorch
+/// version than the one torch-sys links (at `tch_root`), which would
+/// otherwise surface as undefined symbols at link or load time.
+fn check_same_libtorch(include_dirs: &[PathBuf], tch_root: &Path) {
+    let bridge = torch_version(include_dirs);
+    let linked = torch_version(&[tch_root.join("include")]);
+    if let (Some(bridge), Some(linked)) = (bridge, linked)
+        && bridge != linked
+    {
+        panic!(
+            "aoti-rs compiles against libtorch {}.{} ({}) but tch links libtorch {}.{} ({}). \
+             Point LIBTORCH (or LIBTORCH_INCLUDE and LIBTORCH_LIB) at the libtorch tch uses.",
+            bridge.0,
+            bridge.1,
+            include_dirs[0].display(),
+            linked.0,
+            linked.1,
+            tch_root.display(),
+        );
+    }
+}
+
 fn main() {
+    // The `fake` feature swaps the C++ bridge for `src/fake.rs`, so there
+    // is nothing to compile or link; tch downloads its own libtorch.
+    println!("cargo::rustc-check-cfg=cfg(aoti_cuda)");
+    if env::var_os("CARGO_FEATURE_FAKE").is_some() {
+        return;
+    }
+
     // Locate libtorch. We try, in order:
     // 1. Explicit LIBTORCH / LIBTORCH_INCLUDE / LIBTORCH_LIB env vars
     // 2. DEP_TCH_LIBTORCH_LIB exported by torch-sys (derive include from ../include)
@@ -110,6 +163,10 @@ fn main() {
         );
     });
 
+    if let Some(ref tch_root) = torch_root_from_dep {
+        check_same_libtorch(&include_dirs, tch_root);
+    }
+
     // Detect CUDA support: presence of libtorch_cuda.so in the lib dir.  Set
     // AOTI_RS_NO_CUDA=1 to force a CPU-only build even when libtorch ships
     // with CUDA support (useful for type-checking in environments without
@@ -120,7 +177,6 @@ fn main() {
     // Expose CUDA availability to the Rust side as cfg(aoti_cuda) so that
     // CUDA-only APIs (AOTIModelBuilder::<Cuda>::build, etc.) are compiled
     // out — not just failing at runtime — when CUDA support is absent.
-    println!("cargo::rustc-check-cfg=cfg(aoti_cuda)");
     if has_cuda {
         println!("cargo:rustc-cfg=aoti_cuda");
     }
@@ -6,19 +6,43 @@
 #endif
 #include <stdexcept>
 #include <string>
+#include <unordered_map>
 #include <vector>
 
 namespace aoti_rs {
 
+namespace {
+
+// The tensor behind a pointer from Rust.  Rust validates inputs before
+// calling in; this backs that up for every path into the container, whose
+// kernels dereference null or undefined tensors without checking.
+at::Tensor& checked_tensor(const void* ptr, const char* what, size_t index) {
+    if (ptr == nullptr) {
+        throw std::invalid_argument(
+            std::string(what) + " " + std::to_string(index) + " is null");
+    }
+    at::Tensor& tensor =
+        *const_cast<at::Tensor*>(reinterpret_cast<const at::Tensor*>(ptr));
+    if (!tensor.defined()) {
+        throw std::invalid_argument(
+            std::string(what) + " " + std::to_string(index) + " is undefined");
+    }
+    return tensor;
+}
+
+} // namespace
+
 std::unique_ptr<torch::inductor::AOTIModelContainerRunner> runner_new(
-    rust::Str model_so_path,
-    rust::Str cubin_dir,
+    rust::Slice<const uint8_t> model_so_path,
+    rust::Slice<const uint8_t> cubin_dir,
     bool is_cuda,
     int8_t device_index,
     size_t num_runners,
     bool run_single_threaded) {
-    std::string so_path(model_so_path);
-    std::string cubin(cubin_dir);
+    // Raw path bytes, which needn't be UTF-8.
+    std::string so_path(
+        reinterpret_cast<const char*>(model_so_path.data()), model_so_path.size());
+    std::string cubin(reinterpret_cast<const char*>(cubin_dir.data()), cubin_dir.size());
 
     if (is_cuda) {
 #ifdef USE_CUDA
@@ -55,11 +79,10 @@ rust::Vec<OwnedTensor> runner_run(
     const rust::Vec<TensorPtr>& inputs) {
     std::vector<at::Tensor> cpp_inputs;
     cpp_inputs.reserve(inputs.size());
-    for (const auto& t : inputs) {
+    for (size_t i = 0; i < inputs.size(); ++i) {
         // The pointer is a const at::Tensor* from tch-rs (via torch-sys).
         // tch::Tensor is a repr(C) wrapper around *mut C_tensor, which is at::Tensor.
-        const at::Tensor* tensor_ptr = reinterpret_cast<const at::Tensor*>(t.ptr);
-        cpp_inputs.push_back(*tensor_ptr);
+        cpp_inputs.push_back(checked_tensor(inputs[i].ptr, "input", i));
     }
 
     std::vector<at::Tensor> outputs = runner.run(cpp_inputs);
@@ -81,15 +104,18 @@ rust::Vec<OwnedTensor> runner_boxed_run(
     rust::Vec<TensorPtr>& inputs) {
     std::vector<at::Tensor> cpp_inputs;
     cpp_inputs.reserve(inputs.size());
-    for (const auto& t : inputs) {
+    // Check every input before moving out of any, so a bad one leaves the
+    // rest intact.
+    for (size_t i = 0; i < inputs.size(); ++i) {
+        checked_tensor(inputs[i].ptr, "input", i);
+    }
+    for (size_t i = 0; i < inputs.size(); ++i) {
         // Rust passes the inputs by value (Vec<DeviceTensor<_>>), so each
         // at::Tensor is exclusively owned by this call and we can move out
         // of it.  That keeps the use count at 1, which is what lets
         // boxed_run reuse input buffers in place.  Rust drops the empty
         // shells after the call returns.
-        at::Tensor* tensor_ptr =
-            const_cast<at::Tensor*>(reinterpret_cast<const at::Tensor*>(t.ptr));
-        cpp_inputs.push_back(std::move(*tensor_ptr));
+        cpp_inputs.push_back(std::move(checked_tensor(inputs[i].ptr, "input", i)));
     }
 
     std::vector<at::Tensor> outputs = runner.boxed_run(std::move(cpp_inputs));
@@ -127,4 +153,60 @@ rust::Vec<rust::String> runner_get_constant_fqns(
     return result;
 }
 
+rust::Vec<NamedTensor> runner_get_constants(
+    torch::inductor::AOTIModelContainerRunner& runner) {
+    auto constants = runner.extract_constants_map(/*use_inactive=*/false);
+    rust::Vec<NamedTensor> result;
+    result.reserve(constants.size());
+    for (auto& kv : constants) {
+        // A shallow copy: it shares the constant's storage, which Rust only
+        // inspects before dropping.
+        NamedTensor named;
+        named.name = rust::String(kv.first);
+        named.tensor.ptr = static_cast<void*>(new at::Tensor(kv.second));
+        result.push_back(std::move(named));
+    }
+    return result;
+}
+
+void runner_update_constant_buffer(
+    torch::inductor::AOTIModelContainerRunner& runner,
+    const rust::Vec<NamedTensorPtr>& constants,
+    bool use_inactive,
+    bool validate_full_update) {
+    // The container looks constants up by their internal names, while
+    // `extract_constants_map` (and so Rust) uses the original FQNs.
+    std::unordered_map<std::string, std::string> internal_names;
+    for (const auto& kv : runner.getConstantNamesToOriginalFQNs()) {
+        internal_names.emplace(kv.second, kv.first);
+    }
+    std::unordered_map<std::string, at::Tensor> tensor_map;
+    for (size_t i = 0; i < constants.size(); ++i) {
+        const auto& named = constants[i];
+        std::string name(named.name);
+        auto it = internal_names.find(name);
+        if (it == internal_names.end()) {
+            throw std::runtime_error("the model has no constant named " + name);
+        }
+        tensor_map.emplace(it->second, checked_tensor(named.tensor.ptr, "constant", i));
+    }
+    runner.update_constant_buffer(
+        tensor_map, use_inactive, validate_full_update);
+}
+
+void runner_swap_constant_buffer(
+    torch::inductor::AOTIModelContainerRunner& runner) {
+    runner.swap_constant_buffer();
+}
+
+void set_deterministic(bool enabled, bool warn_only) {
+    at::globalContext().setDeterministicAlgorithms(enabled, warn_only);
+    at::globalContext().setDeterministicCuDNN(enabled);
+}
+
+void set_allow_tf32(bool allowed) {
+    at::globalContext().setAllowTF32CuBLAS(allowed);
+    at::globalContext().setAllowTF32CuDNN(allowed);
+}
+
 } // namespace aoti_rs
@@ -2,8 +2,52 @@
 
 #include "rust/cxx.h"
 #include <torch/csrc/inductor/aoti_runner/model_container_runner.h>
+#include <c10/util/Exception.h>
 #include <memory>
 #include <cstdint>
+#include <exception>
+#include <string>
+
+// Replaces cxx's default exception handler for every bridged function
+// (https://cxx.rs/binding/result.html).  The default only catches
+// std::exception; anything else escaping a `noexcept` shim would call
+// std::terminate and take the process down.  Here every throw becomes an
+// error on the Rust side.  libtorch errors carry their C++ backtrace after
+// AOTI_RS_BACKTRACE_MARKER, which `Error::cpp_backtrace` splits off again.
+#define AOTI_RS_BACKTRACE_MARKER "\n\nC++ backtrace:\n"
+
+namespace rust {
+namespace behavior {
+
+template <typename Try, typename Fail>
+static void trycatch(Try &&func, Fail &&fail) noexcept try {
+    func();
+} catch (const c10::Error &e) {
+    // what() is the message followed by the backtrace, when libtorch
+    // recorded one.
+    std::string message = e.what_without_backtrace();
+    std::string full = e.what();
+    if (full.size() > message.size() &&
+        full.compare(0, message.size(), message) == 0) {
+        size_t start = full.find_first_not_of('\n', message.size());
+        if (start != std::string::npos) {
+            message += AOTI_RS_BACKTRACE_MARKER;
+            message += full.substr(start);
+        }
+    }
+    fail(message.c_str());
+} catch (const std::exception &e) {
+    fail(e.what());
+} catch (const std::string &s) {
+    fail(("C++ exception: " + s).c_str());
+} catch (const char *s) {
+    fail((std::string("C++ exception: ") + (s ? s : "(null)")).c_str());
+} catch (...) {
+    fail("unknown C++ exception (not derived from std::exception)");
+}
+
+} // namespace behavior
+} // namespace rust
 
 // Shared structs (TensorPtr, OwnedTensor) are defined by the cxx code
 // generator from the Rust bridge declaration.  We only need forward
@@ -12,6 +56,8 @@ namespace aoti_rs {
 
 struct TensorPtr;
 struct OwnedTensor;
+struct NamedTensor;
+struct NamedTensorPtr;
 
 // Construct an AOTIModelContainerRunner{Cpu,Cuda} from a pre-extracted
 // wrapper.so.  The .pt2 archive is extracted in Rust with a Zip64-aware
@@ -19,8 +65,8 @@ struct OwnedTensor;
 // which fails on archives whose internal wrapper.so pushes the central
 // directory past the 32-bit Zip offset boundary.
 std::unique_ptr<torch::inductor::AOTIModelContainerRunner> runner_new(
-    rust::Str model_so_path,
-    rust::Str cubin_dir,
+    rust::Slice<const uint8_t> model_so_path,
+    rust::Slice<const uint8_t> cubin_dir,
     bool is_cuda,
     int8_t device_index,
     size_t num_runners,
@@ -40,4 +86,24 @@ rust::Vec<rust::String> runner_get_call_spec(
 rust::Vec<rust::String> runner_get_constant_fqns(
     torch::inductor::AOTIModelContainerRunner& runner);
 
+rust::Vec<NamedTensor> runner_get_constants(
+    torch::inductor::AOTIModelContainerRunner& runner);
+
+// Update the active or inactive constant buffer from tensors keyed by their
+// original fully qualified names, as `runner_get_constants` reports them.
+void runner_update_constant_buffer(
+    torch::inductor::AOTIModelContainerRunner& runner,
+    const rust::Vec<NamedTensorPtr>& constants,
+    bool use_inactive,
+    bool validate_full_update);
+
+void runner_swap_constant_buffer(
+    torch::inductor::AOTIModelContainerRunner& runner);
+
+// Process-wide ATen settings for `aoti_rs::init`.  Deterministic mode also
+// turns on cuDNN's deterministic flag; TF32 covers both cuBLAS and cuDNN.
+void set_deterministic(bool enabled, bool warn_only);
+
+void set_allow_tf32(bool allowed);
+
 } // namespace aoti_rs
@@ -0,0 +1,9 @@
+//! Arbitrary strings as a package's serialized `in_spec`.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|spec: &str| {
+    let _ = aoti_rs::fuzz::input_count(spec);
+});
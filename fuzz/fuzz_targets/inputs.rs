@@ -0,0 +1,32 @@
+//! Arbitrary input sets against the checks `run` makes before calling into
+//! the runtime: whatever they accept, the compiled kernels may index as a
+//! dense block of `numel` elements from the storage offset, so that block
+//! must lie within the storage.
+
+#![no_main]
+
+use aoti_rs::fuzz::{FuzzTensor, check_inputs};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|case: (Vec<FuzzTensor>, Option<u8>)| {
+    let (specs, expected) = case;
+    let (specs, tensors): (Vec<_>, Vec<_>) = specs
+        .iter()
+        .filter_map(|spec| Some((spec, spec.build()?)))
+        .unzip();
+    if check_inputs(&tensors, expected.map(usize::from)).is_err() {
+        return;
+    }
+    for (spec, tensor) in specs.iter().zip(&tensors) {
+        assert!(tensor.defined());
+        let available = spec.storage as i64 - spec.offset as i64;
+        assert!(
+            tensor.numel() == 0 || tensor.numel() as i64 <= available,
+            "accepted {:?} / {:?} over {} elements from offset {}",
+            tensor.size(),
+            tensor.stride(),
+            spec.storage,
+            spec.offset
+        );
+    }
+});
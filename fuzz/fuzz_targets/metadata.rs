@@ -0,0 +1,16 @@
+//! Arbitrary bytes as a package's `*_metadata.json`, and the parsers that
+//! read values out of it.
+
+#![no_main]
+
+use aoti_rs::padding::Buckets;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(metadata) = aoti_rs::fuzz::parse_metadata(data) {
+        let _ = metadata.device_key();
+        if let Ok(buckets) = Buckets::from_metadata(&metadata) {
+            let _ = buckets.fit(usize::MAX);
+        }
+    }
+});
@@ -0,0 +1,41 @@
+//! Arbitrary input sets run through a real CPU package, set by
+//! `AOTI_RS_FUZZ_PT2` (and `AOTI_RS_FUZZ_MODEL_NAME`, default `model`).
+//! Errors are expected; a crash means something got past the checks into
+//! the runtime. Without the variable every case is skipped.
+
+#![no_main]
+
+use std::sync::{Mutex, OnceLock};
+
+use aoti_rs::fuzz::FuzzTensor;
+use aoti_rs::{AOTIModel, Cpu, DeviceTensor};
+use libfuzzer_sys::fuzz_target;
+
+fn model() -> &'static Option<Mutex<AOTIModel<Cpu>>> {
+    static MODEL: OnceLock<Option<Mutex<AOTIModel<Cpu>>>> = OnceLock::new();
+    MODEL.get_or_init(|| {
+        let path = std::env::var("AOTI_RS_FUZZ_PT2").ok()?;
+        let name = std::env::var("AOTI_RS_FUZZ_MODEL_NAME").unwrap_or_else(|_| "model".into());
+        let model = AOTIModel::<Cpu>::builder(path)
+            .model_name(name)
+            .build()
+            .expect("AOTI_RS_FUZZ_PT2 should be a loadable CPU package");
+        Some(Mutex::new(model))
+    })
+}
+
+fuzz_target!(|specs: Vec<FuzzTensor>| {
+    let Some(model) = model() else {
+        return;
+    };
+    let inputs: Vec<DeviceTensor<Cpu>> = specs
+        .iter()
+        .filter_map(FuzzTensor::build)
+        // Undefined tensors have no device to check; the `inputs` target
+        // covers them.
+        .filter(|t| t.defined())
+        .filter_map(|t| DeviceTensor::try_new(t).ok())
+        .collect();
+    let mut model = model.lock().unwrap();
+    let _ = model.run(&inputs);
+});
@@ -0,0 +1,70 @@
+/* C API for aoti-rs (cargo feature `capi`). See src/capi.rs for the full
+ * contract; build the library with
+ *
+ *   cargo rustc --release --features capi --crate-type cdylib
+ */
+#ifndef AOTI_RS_H
+#define AOTI_RS_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef enum {
+  AOTI_RS_OK = 0,
+  AOTI_RS_INVALID_ARGUMENT = 1,
+  AOTI_RS_ERROR = 2,
+  AOTI_RS_PANIC = 3,
+} AotiStatus;
+
+typedef enum {
+  AOTI_RS_FLOAT32 = 0,
+  AOTI_RS_FLOAT64 = 1,
+  AOTI_RS_FLOAT16 = 2,
+  AOTI_RS_BFLOAT16 = 3,
+  AOTI_RS_INT8 = 4,
+  AOTI_RS_INT16 = 5,
+  AOTI_RS_INT32 = 6,
+  AOTI_RS_INT64 = 7,
+  AOTI_RS_UINT8 = 8,
+  AOTI_RS_BOOL = 9,
+} AotiDtype;
+
+/* A dense, row-major host tensor. */
+typedef struct {
+  const void *data;
+  int32_t dtype; /* AotiDtype */
+  const int64_t *shape;
+  size_t ndim;
+} AotiTensorView;
+
+typedef struct AotiModel AotiModel;
+typedef struct AotiOutputs AotiOutputs;
+
+/* model_name may be NULL for "model". path is raw bytes on Unix and UTF-8
+ * elsewhere. */
+AotiStatus aoti_rs_load(const char *path, const char *model_name, AotiModel **out);
+void aoti_rs_free(AotiModel *model);
+
+AotiStatus aoti_rs_run(AotiModel *model, const AotiTensorView *inputs, size_t num_inputs,
+                       AotiOutputs **out);
+size_t aoti_rs_outputs_len(const AotiOutputs *outputs);
+/* The view borrows from `outputs` until aoti_rs_outputs_free. */
+AotiStatus aoti_rs_output_get(const AotiOutputs *outputs, size_t index, AotiTensorView *view);
+void aoti_rs_outputs_free(AotiOutputs *outputs);
+
+/* Free the returned string with aoti_rs_string_free. */
+AotiStatus aoti_rs_metadata_json(const AotiModel *model, char **out);
+void aoti_rs_string_free(char *s);
+
+/* Message of the last failure on this thread, or NULL. */
+const char *aoti_rs_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* AOTI_RS_H */
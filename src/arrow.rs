@@ -0,0 +1,214 @@
+//! Apache Arrow interop for batch scoring (feature `arrow`).
+//!
+//! A column becomes one input tensor whose leading dimension is the row
+//! count: primitive arrays map to `[rows]`, `FixedSizeList<T, n>` to
+//! `[rows, n]`, and nested fixed-size lists add one dimension per level.
+//! Outputs go the other way, so a `[rows, classes]` logits tensor becomes a
+//! `FixedSizeList<Float32, classes>` column that can be appended to the
+//! batch it was scored from.
+//!
+//! Each direction performs a single copy of the values buffer. Tensors have
+//! no notion of nulls, so columns containing nulls at any nesting level are
+//! rejected with [`Error::InvalidInput`].
+
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use arrow_array::cast::AsArray;
+use arrow_array::types::{
+    ArrowPrimitiveType, Float16Type, Float32Type, Float64Type, Int8Type, Int16Type, Int32Type,
+    Int64Type, UInt8Type,
+};
+use arrow_array::{Array, ArrayRef, BooleanArray, FixedSizeListArray, PrimitiveArray, RecordBatch};
+use arrow_buffer::ScalarBuffer;
+use arrow_schema::{DataType, Field, Schema};
+use tch::kind::Element;
+use tch::{Kind, Tensor};
+
+use crate::{Cpu, Device, DeviceTensor, Error};
+
+fn reject_nulls(array: &dyn Array) -> Result<(), Error> {
+    if array.null_count() > 0 {
+        return Err(Error::InvalidInput(format!(
+            "{} array contains {} nulls",
+            array.data_type(),
+            array.null_count()
+        )));
+    }
+    Ok(())
+}
+
+fn primitive_to_tensor<T>(array: &dyn Array) -> Result<Tensor, Error>
+where
+    T: ArrowPrimitiveType,
+    T::Native: Element,
+{
+    Ok(Tensor::f_from_slice(array.as_primitive::<T>().values())?)
+}
+
+fn tensor_to_primitive<T>(tensor: &Tensor) -> Result<ArrayRef, Error>
+where
+    T: ArrowPrimitiveType,
+    T::Native: Element,
+{
+    let numel = tensor.numel();
+    let mut data = vec![<T::Native as Element>::ZERO; numel];
+    tensor.f_copy_data(&mut data, numel)?;
+    Ok(Arc::new(PrimitiveArray::<T>::new(
+        ScalarBuffer::from(data),
+        None,
+    )))
+}
+
+/// Convert an Arrow column into a CPU tensor.
+///
+/// Supported leaf types are `Boolean`, `UInt8`, `Int8`–`Int64`, and
+/// `Float16`–`Float64`, optionally nested in any number of
+/// `FixedSizeList` levels.
+pub fn array_to_tensor(array: &dyn Array) -> Result<DeviceTensor<Cpu>, Error> {
+    let mut dims = vec![array.len() as i64];
+    let mut leaf = array;
+    while let DataType::FixedSizeList(_, size) = leaf.data_type() {
+        reject_nulls(leaf)?;
+        dims.push(i64::from(*size));
+        leaf = leaf.as_fixed_size_list().values().as_ref();
+    }
+    reject_nulls(leaf)?;
+
+    let flat = match leaf.data_type() {
+        DataType::Boolean => {
+            let values: Vec<bool> = leaf.as_boolean().values().iter().collect();
+            Tensor::f_from_slice(&values)?
+        }
+        DataType::UInt8 => primitive_to_tensor::<UInt8Type>(leaf)?,
+        DataType::Int8 => primitive_to_tensor::<Int8Type>(leaf)?,
+        DataType::Int16 => primitive_to_tensor::<Int16Type>(leaf)?,
+        DataType::Int32 => primitive_to_tensor::<Int32Type>(leaf)?,
+        DataType::Int64 => primitive_to_tensor::<Int64Type>(leaf)?,
+        DataType::Float16 => primitive_to_tensor::<Float16Type>(leaf)?,
+        DataType::Float32 => primitive_to_tensor::<Float32Type>(leaf)?,
+        DataType::Float64 => primitive_to_tensor::<Float64Type>(leaf)?,
+        other => return Err(Error::UnsupportedDtype(format!("arrow {other}"))),
+    };
+    Ok(DeviceTensor {
+        tensor: flat.f_reshape(&dims)?,
+        _device: PhantomData,
+    })
+}
+
+/// Convert a tensor into an Arrow column, nesting trailing dimensions as
+/// `FixedSizeList`s. A 0-dim tensor becomes a single-element array.
+pub fn tensor_to_array<D: Device>(tensor: &DeviceTensor<D>) -> Result<ArrayRef, Error> {
+    let contiguous = tensor.f_contiguous()?;
+    let mut array: ArrayRef = match contiguous.f_kind()? {
+        Kind::Bool => {
+            let numel = contiguous.numel();
+            let mut data = vec![false; numel];
+            contiguous.f_copy_data(&mut data, numel)?;
+            Arc::new(BooleanArray::from(data))
+        }
+        Kind::Uint8 => tensor_to_primitive::<UInt8Type>(&contiguous)?,
+        Kind::Int8 => tensor_to_primitive::<Int8Type>(&contiguous)?,
+        Kind::Int16 => tensor_to_primitive::<Int16Type>(&contiguous)?,
+        Kind::Int => tensor_to_primitive::<Int32Type>(&contiguous)?,
+        Kind::Int64 => tensor_to_primitive::<Int64Type>(&contiguous)?,
+        Kind::Half => tensor_to_primitive::<Float16Type>(&contiguous)?,
+        Kind::Float => tensor_to_primitive::<Float32Type>(&contiguous)?,
+        Kind::Double => tensor_to_primitive::<Float64Type>(&contiguous)?,
+        other => return Err(Error::UnsupportedDtype(format!("torch {other:?}"))),
+    };
+
+    let size = contiguous.size();
+    for &dim in size.iter().skip(1).rev() {
+        let field = Arc::new(Field::new_list_field(array.data_type().clone(), false));
+        let dim = i32::try_from(dim)
+            .map_err(|_| Error::InvalidInput(format!("dimension {dim} overflows i32")))?;
+        array = Arc::new(FixedSizeListArray::try_new(field, dim, array, None)?);
+    }
+    Ok(array)
+}
+
+/// Convert the named columns of `batch`, in order, into model inputs.
+pub fn record_batch_to_inputs(
+    batch: &RecordBatch,
+    columns: &[&str],
+) -> Result<Vec<DeviceTensor<Cpu>>, Error> {
+    columns
+        .iter()
+        .map(|&name| {
+            let column = batch.column_by_name(name).ok_or_else(|| {
+                Error::InvalidInput(format!("record batch has no column '{name}'"))
+            })?;
+            array_to_tensor(column.as_ref())
+        })
+        .collect()
+}
+
+/// Append `outputs` to `batch` as new non-nullable columns called `names`.
+///
+/// Each output's leading dimension must equal the batch's row count.
+pub fn append_outputs<D: Device>(
+    batch: &RecordBatch,
+    outputs: &[DeviceTensor<D>],
+    names: &[&str],
+) -> Result<RecordBatch, Error> {
+    if outputs.len() != names.len() {
+        return Err(Error::InvalidInput(format!(
+            "{} outputs but {} column names",
+            outputs.len(),
+            names.len()
+        )));
+    }
+    let schema = batch.schema();
+    let mut fields: Vec<_> = schema.fields().iter().cloned().collect();
+    let mut columns = batch.columns().to_vec();
+    for (output, &name) in outputs.iter().zip(names) {
+        let array = tensor_to_array(output)?;
+        fields.push(Arc::new(Field::new(name, array.data_type().clone(), false)));
+        columns.push(array);
+    }
+    Ok(RecordBatch::try_new(
+        Arc::new(Schema::new_with_metadata(fields, schema.metadata().clone())),
+        columns,
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::Float32Array;
+
+    #[test]
+    fn fixed_size_list_becomes_matrix() {
+        let values = Arc::new(Float32Array::from(vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]));
+        let field = Arc::new(Field::new_list_field(DataType::Float32, false));
+        let list = FixedSizeListArray::try_new(field, 3, values, None).unwrap();
+
+        let t = array_to_tensor(&list).unwrap();
+        assert_eq!(t.size(), &[2, 3]);
+        assert_eq!(t.double_value(&[1, 0]), 4.0);
+
+        let back = tensor_to_array(&t).unwrap();
+        assert_eq!(back.as_ref(), &list as &dyn Array);
+    }
+
+    #[test]
+    fn sliced_list_uses_sliced_values() {
+        let values = Arc::new(Float32Array::from(vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]));
+        let field = Arc::new(Field::new_list_field(DataType::Float32, false));
+        let list = FixedSizeListArray::try_new(field, 2, values, None).unwrap();
+
+        let t = array_to_tensor(&list.slice(1, 2)).unwrap();
+        assert_eq!(t.size(), &[2, 2]);
+        assert_eq!(t.double_value(&[0, 0]), 3.0);
+    }
+
+    #[test]
+    fn nulls_are_rejected() {
+        let column = Float32Array::from(vec![Some(1.0), None]);
+        assert!(matches!(
+            array_to_tensor(&column),
+            Err(Error::InvalidInput(_))
+        ));
+    }
+}
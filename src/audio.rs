@@ -0,0 +1,305 @@
+//! Audio preprocessing for speech models (feature `audio`).
+//!
+//! [`MelSpectrogram`] turns mono PCM into the `[n_mels, frames]` (or
+//! `[N, n_mels, frames]`) `Float` tensors speech models are usually
+//! exported against. It follows `torchaudio.transforms.MelSpectrogram`:
+//! reflect-padded centered frames, a periodic Hann window, a one-sided
+//! power spectrum, and a triangular mel filterbank on the HTK or Slaney
+//! scale. The spectral work runs in libtorch, so no FFT crate is needed.
+//!
+//! Resampling is out of scope: PCM must already be at the configured sample
+//! rate.
+
+use std::marker::PhantomData;
+
+use tch::{Kind, Tensor};
+
+use crate::{Cpu, DeviceTensor, Error};
+
+/// Convert signed 16-bit PCM samples to `f32` in `[-1, 1)`.
+pub fn pcm_i16_to_f32(samples: &[i16]) -> Vec<f32> {
+    samples.iter().map(|&s| f32::from(s) / 32768.0).collect()
+}
+
+/// Split `signal` into overlapping frames of `frame_len` samples every
+/// `hop` samples, as a `[frames, frame_len]` tensor. Trailing samples that
+/// don't fill a whole frame are dropped.
+pub fn frames(signal: &[f32], frame_len: usize, hop: usize) -> Result<DeviceTensor<Cpu>, Error> {
+    if frame_len == 0 || hop == 0 {
+        return Err(Error::InvalidInput(
+            "frame_len and hop must be at least 1".into(),
+        ));
+    }
+    if signal.len() < frame_len {
+        return Err(Error::InvalidInput(format!(
+            "{} samples is shorter than one {frame_len}-sample frame",
+            signal.len()
+        )));
+    }
+    Ok(DeviceTensor {
+        tensor: Tensor::f_from_slice(signal)?.f_unfold(0, frame_len as i64, hop as i64)?,
+        _device: PhantomData,
+    })
+}
+
+/// Frequency scale used to place the mel filters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MelScale {
+    /// `2595 * log10(1 + f / 700)`, torchaudio's default.
+    Htk,
+    /// Linear below 1 kHz and logarithmic above, librosa's default.
+    Slaney,
+}
+
+impl MelScale {
+    fn hz_to_mel(self, hz: f64) -> f64 {
+        match self {
+            MelScale::Htk => 2595.0 * (1.0 + hz / 700.0).log10(),
+            MelScale::Slaney if hz < 1000.0 => hz * 3.0 / 200.0,
+            MelScale::Slaney => 15.0 + (hz / 1000.0).ln() * 27.0 / 6.4f64.ln(),
+        }
+    }
+
+    fn mel_to_hz(self, mel: f64) -> f64 {
+        match self {
+            MelScale::Htk => 700.0 * (10f64.powf(mel / 2595.0) - 1.0),
+            MelScale::Slaney if mel < 15.0 => mel * 200.0 / 3.0,
+            MelScale::Slaney => 1000.0 * ((mel - 15.0) * 6.4f64.ln() / 27.0).exp(),
+        }
+    }
+}
+
+/// Compute mel spectrograms from mono `f32` PCM.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MelSpectrogram {
+    sample_rate: u32,
+    n_fft: usize,
+    win_length: usize,
+    hop_length: usize,
+    n_mels: usize,
+    f_min: f64,
+    f_max: Option<f64>,
+    power: f64,
+    center: bool,
+    scale: MelScale,
+    area_normalize: bool,
+    log_offset: Option<f64>,
+}
+
+impl MelSpectrogram {
+    /// A spectrogram with `n_fft`-point frames every `hop_length` samples,
+    /// `n_mels` HTK-scale filters spanning `0..sample_rate / 2`, and no log
+    /// compression.
+    pub fn new(sample_rate: u32, n_fft: usize, hop_length: usize, n_mels: usize) -> Self {
+        Self {
+            sample_rate,
+            n_fft,
+            win_length: n_fft,
+            hop_length,
+            n_mels,
+            f_min: 0.0,
+            f_max: None,
+            power: 2.0,
+            center: true,
+            scale: MelScale::Htk,
+            area_normalize: false,
+            log_offset: None,
+        }
+    }
+
+    /// Set the Hann window length (default: `n_fft`). Shorter windows are
+    /// zero-padded on both sides to `n_fft`.
+    pub fn win_length(mut self, win_length: usize) -> Self {
+        self.win_length = win_length;
+        self
+    }
+
+    /// Restrict the filterbank to `f_min..f_max` Hz (default: up to
+    /// Nyquist).
+    pub fn frequency_range(mut self, f_min: f64, f_max: Option<f64>) -> Self {
+        self.f_min = f_min;
+        self.f_max = f_max;
+        self
+    }
+
+    /// Exponent applied to the magnitude spectrum (default: 2, power).
+    pub fn power(mut self, power: f64) -> Self {
+        self.power = power;
+        self
+    }
+
+    /// Whether to reflect-pad the signal by `n_fft / 2` on both sides so
+    /// frame `t` is centered on sample `t * hop_length` (default: true).
+    pub fn center(mut self, center: bool) -> Self {
+        self.center = center;
+        self
+    }
+
+    /// Choose the mel scale, and whether to scale each filter to unit area
+    /// (librosa's `norm="slaney"`).
+    pub fn mel_scale(mut self, scale: MelScale, area_normalize: bool) -> Self {
+        self.scale = scale;
+        self.area_normalize = area_normalize;
+        self
+    }
+
+    /// Return `ln(mel + offset)` instead of the linear mel energies.
+    pub fn log(mut self, offset: Option<f64>) -> Self {
+        self.log_offset = offset;
+        self
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        if self.n_fft == 0 || self.hop_length == 0 || self.n_mels == 0 {
+            return Err(Error::InvalidInput(
+                "n_fft, hop_length and n_mels must be at least 1".into(),
+            ));
+        }
+        if self.win_length == 0 || self.win_length > self.n_fft {
+            return Err(Error::InvalidInput(format!(
+                "win_length {} must be in 1..={}",
+                self.win_length, self.n_fft
+            )));
+        }
+        Ok(())
+    }
+
+    /// The `[n_fft / 2 + 1, n_mels]` triangular filterbank.
+    pub fn filterbank(&self) -> Result<Tensor, Error> {
+        self.validate()?;
+        let n_freqs = self.n_fft / 2 + 1;
+        let nyquist = f64::from(self.sample_rate) / 2.0;
+        let f_max = self.f_max.unwrap_or(nyquist);
+        let (m_min, m_max) = (
+            self.scale.hz_to_mel(self.f_min),
+            self.scale.hz_to_mel(f_max),
+        );
+        let f_pts: Vec<f64> = (0..self.n_mels + 2)
+            .map(|i| {
+                let mel = m_min + (m_max - m_min) * i as f64 / (self.n_mels + 1) as f64;
+                self.scale.mel_to_hz(mel)
+            })
+            .collect();
+
+        let mut fb = vec![0f32; n_freqs * self.n_mels];
+        for bin in 0..n_freqs {
+            let freq = nyquist * bin as f64 / (n_freqs - 1).max(1) as f64;
+            for m in 0..self.n_mels {
+                let (lo, mid, hi) = (f_pts[m], f_pts[m + 1], f_pts[m + 2]);
+                let down = (freq - lo) / (mid - lo);
+                let up = (hi - freq) / (hi - mid);
+                let mut weight = down.min(up).max(0.0);
+                if self.area_normalize {
+                    weight *= 2.0 / (hi - lo);
+                }
+                fb[bin * self.n_mels + m] = weight as f32;
+            }
+        }
+        Ok(Tensor::f_from_slice(&fb)?.f_view([n_freqs as i64, self.n_mels as i64])?)
+    }
+
+    fn spectrogram(&self, signal: &Tensor, fb: &Tensor) -> Result<Tensor, Error> {
+        let n_fft = self.n_fft as i64;
+        let mut signal = signal.f_to_kind(Kind::Float)?;
+        if self.center {
+            let pad = n_fft / 2;
+            signal = signal
+                .f_view([1, -1])?
+                .f_pad([pad, pad], "reflect", None)?
+                .f_view([-1])?;
+        }
+        if signal.size()[0] < n_fft {
+            return Err(Error::InvalidInput(format!(
+                "{} samples is shorter than one {n_fft}-point frame",
+                signal.size()[0]
+            )));
+        }
+        let win = self.win_length as i64;
+        let left = (n_fft - win) / 2;
+        let window = Tensor::f_hann_window(win, (Kind::Float, tch::Device::Cpu))?.f_pad(
+            [left, n_fft - win - left],
+            "constant",
+            0.0,
+        )?;
+        let frames = signal
+            .f_unfold(0, n_fft, self.hop_length as i64)?
+            .f_mul(&window)?;
+        let mut mel = frames
+            .f_fft_rfft(None, -1, "backward")?
+            .f_abs()?
+            .f_pow_tensor_scalar(self.power)?
+            .f_matmul(fb)?
+            .f_transpose(0, 1)?;
+        if let Some(offset) = self.log_offset {
+            mel = mel.f_add_scalar(offset)?.f_log()?;
+        }
+        Ok(mel)
+    }
+
+    /// Compute the `[n_mels, frames]` spectrogram of one clip.
+    pub fn compute(&self, pcm: &[f32]) -> Result<DeviceTensor<Cpu>, Error> {
+        let fb = self.filterbank()?;
+        Ok(DeviceTensor {
+            tensor: self.spectrogram(&Tensor::f_from_slice(pcm)?, &fb)?,
+            _device: PhantomData,
+        })
+    }
+
+    /// Compute an `[N, n_mels, frames]` batch, zero-padding shorter clips at
+    /// the end to the length of the longest.
+    pub fn batch(&self, clips: &[&[f32]]) -> Result<DeviceTensor<Cpu>, Error> {
+        let longest = clips
+            .iter()
+            .map(|c| c.len())
+            .max()
+            .ok_or_else(|| Error::InvalidInput("cannot compute an empty batch".into()))?;
+        let fb = self.filterbank()?;
+        let mels = clips
+            .iter()
+            .map(|clip| {
+                let mut padded = clip.to_vec();
+                padded.resize(longest, 0.0);
+                self.spectrogram(&Tensor::f_from_slice(&padded)?, &fb)
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        Ok(DeviceTensor {
+            tensor: Tensor::f_stack(&mels, 0)?,
+            _device: PhantomData,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spectrogram_has_expected_shape() {
+        let pcm: Vec<f32> = (0..1600).map(|i| (i as f32 * 0.1).sin()).collect();
+        let mel = MelSpectrogram::new(16_000, 400, 160, 80)
+            .log(Some(1e-6))
+            .compute(&pcm)
+            .unwrap();
+        // Centered framing yields 1 + len / hop frames.
+        assert_eq!(mel.size(), &[80, 11]);
+        assert_eq!(mel.kind(), Kind::Float);
+    }
+
+    #[test]
+    fn htk_filterbank_rows_peak_at_one() {
+        let fb = MelSpectrogram::new(8_000, 256, 128, 10)
+            .filterbank()
+            .unwrap();
+        assert_eq!(fb.size(), &[129, 10]);
+        let peak = fb.max().double_value(&[]);
+        assert!(peak > 0.9 && peak <= 1.0, "peak {peak}");
+    }
+
+    #[test]
+    fn framing_drops_partial_frame() {
+        let signal: Vec<f32> = (0..10).map(|i| i as f32).collect();
+        let f = frames(&signal, 4, 3).unwrap();
+        assert_eq!(f.size(), &[3, 4]);
+        assert_eq!(f.double_value(&[2, 0]), 6.0);
+    }
+}
@@ -0,0 +1,259 @@
+//! `aoti-bench`: measure a package's latency and throughput.
+//!
+//! ```text
+//! aoti-bench model.pt2 --input 1x3x224x224 --batch-sizes 1,8,32
+//! aoti-bench model.pt2 --inputs example.npz --runners 1,2,4 --device cuda:0
+//! ```
+//!
+//! Every combination of device, runner count and batch size is measured
+//! in turn. Each runner is a replica in an [`AOTIModelPool`] driven by its
+//! own client thread, so runner counts above one measure concurrent
+//! throughput. CUDA runs are synchronized before they are timed.
+
+mod common;
+
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use aoti_rs::{AOTIModel, AOTIModelPool, Device, Error};
+use clap::Parser;
+use common::{DeviceArg, InputSpec};
+use serde_json::json;
+use tch::Tensor;
+
+/// Benchmark a .pt2 package across batch sizes, runner counts and devices.
+#[derive(Parser)]
+#[command(version)]
+struct Args {
+    /// Path of the .pt2 package.
+    package: String,
+    /// Model name within the package.
+    #[arg(long, default_value = "model")]
+    model: String,
+    /// Synthesize an input of this shape and dtype (e.g. `1x3x224x224` or
+    /// `8x128:i64`), once per model input, in order.
+    #[arg(
+        long = "input",
+        value_name = "SHAPE[:DTYPE]",
+        conflicts_with = "inputs"
+    )]
+    input: Vec<InputSpec>,
+    /// Read the inputs from a .safetensors, .npz or .npy file instead.
+    #[arg(long, value_name = "FILE")]
+    inputs: Option<String>,
+    /// Batch sizes to sweep, replacing the leading dimension of
+    /// synthesized inputs (default: their own).
+    #[arg(long, value_delimiter = ',', requires = "input")]
+    batch_sizes: Vec<i64>,
+    /// Concurrent runners to sweep.
+    #[arg(long, value_delimiter = ',', default_value = "1")]
+    runners: Vec<usize>,
+    /// Devices to sweep: `cpu`, `cuda` or `cuda:N`.
+    #[arg(long = "device", value_delimiter = ',', default_value = "cpu")]
+    devices: Vec<DeviceArg>,
+    /// Untimed runs per runner before measuring.
+    #[arg(long, default_value_t = 10)]
+    warmup: usize,
+    /// Timed runs per configuration, shared among the runners.
+    #[arg(long, default_value_t = 100)]
+    iterations: usize,
+    /// Print JSON instead of a table.
+    #[arg(long)]
+    json: bool,
+}
+
+/// One measured configuration.
+struct Row {
+    device: DeviceArg,
+    runners: usize,
+    batch: Option<i64>,
+    /// Latency of every timed run, sorted.
+    latencies: Vec<Duration>,
+    wall: Duration,
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    if args.input.is_empty() && args.inputs.is_none() {
+        eprintln!("aoti-bench: pass --input SHAPE[:DTYPE] for each input, or --inputs FILE");
+        return ExitCode::FAILURE;
+    }
+    let mut rows = Vec::new();
+    for &device in &args.devices {
+        for &runners in &args.runners {
+            match bench_device(&args, device, runners.max(1)) {
+                Ok(measured) => rows.extend(measured),
+                Err(err) => {
+                    eprintln!("aoti-bench: {device}, {runners} runner(s): {err}");
+                    return ExitCode::FAILURE;
+                }
+            }
+        }
+    }
+    if args.json {
+        let rows: Vec<_> = rows.iter().map(to_json).collect();
+        println!("{:#}", json!(rows));
+    } else {
+        print_table(&rows);
+    }
+    ExitCode::SUCCESS
+}
+
+fn bench_device(args: &Args, device: DeviceArg, runners: usize) -> Result<Vec<Row>, Error> {
+    match device {
+        DeviceArg::Cpu => bench(args, device, runners, || {
+            common::load_cpu(&args.package, &args.model, 1)
+        }),
+        #[cfg(aoti_cuda)]
+        DeviceArg::Cuda(index) => bench(args, device, runners, || {
+            common::load_cuda(&args.package, &args.model, 1, index)
+        }),
+        #[cfg(not(aoti_cuda))]
+        DeviceArg::Cuda(_) => Err(common::no_cuda(device)),
+    }
+}
+
+/// Measure every batch size on a pool of `runners` replicas from `load`.
+fn bench<D: Device>(
+    args: &Args,
+    device: DeviceArg,
+    runners: usize,
+    load: impl Fn() -> Result<AOTIModel<D>, Error>,
+) -> Result<Vec<Row>, Error> {
+    let pool = AOTIModelPool::from_fn(runners, |_| load())?;
+    let batches: Vec<Option<i64>> = if args.batch_sizes.is_empty() {
+        vec![None]
+    } else {
+        args.batch_sizes.iter().copied().map(Some).collect()
+    };
+    batches
+        .into_iter()
+        .map(|batch| {
+            let host: Vec<Tensor> = match &args.inputs {
+                Some(path) => common::read_inputs(path)?,
+                None => args
+                    .input
+                    .iter()
+                    .map(|spec| spec.synthesize(batch))
+                    .collect(),
+            };
+            // Tensors can't be shared between threads, so each runner gets
+            // its own copy.
+            let per_runner: Vec<Vec<_>> = (0..runners)
+                .map(|_| host.iter().map(|tensor| pool.upload(tensor)).collect())
+                .collect();
+            let sync = || {
+                if let tch::Device::Cuda(index) = pool.device() {
+                    tch::Cuda::synchronize(index as i64);
+                }
+            };
+            let remaining = AtomicUsize::new(args.iterations);
+            let started = Instant::now();
+            let latencies = std::thread::scope(|scope| {
+                let workers: Vec<_> = per_runner
+                    .into_iter()
+                    .map(|inputs| {
+                        let (pool, remaining, sync) = (&pool, &remaining, &sync);
+                        scope.spawn(move || -> Result<Vec<Duration>, Error> {
+                            for _ in 0..args.warmup {
+                                pool.run(&inputs)?;
+                            }
+                            sync();
+                            let mut timed = Vec::new();
+                            while remaining
+                                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| {
+                                    n.checked_sub(1)
+                                })
+                                .is_ok()
+                            {
+                                let start = Instant::now();
+                                pool.run(&inputs)?;
+                                sync();
+                                timed.push(start.elapsed());
+                            }
+                            Ok(timed)
+                        })
+                    })
+                    .collect();
+                workers
+                    .into_iter()
+                    .map(|worker| worker.join().expect("benchmark thread panicked"))
+                    .collect::<Result<Vec<_>, Error>>()
+            })?;
+            let wall = started.elapsed();
+            let mut latencies: Vec<Duration> = latencies.into_iter().flatten().collect();
+            latencies.sort();
+            Ok(Row {
+                device,
+                runners,
+                batch: batch.or_else(|| host.first().and_then(|t| t.size().first().copied())),
+                latencies,
+                wall,
+            })
+        })
+        .collect()
+}
+
+impl Row {
+    /// The nearest-rank `p`th percentile latency.
+    fn percentile(&self, p: f64) -> Duration {
+        if self.latencies.is_empty() {
+            return Duration::ZERO;
+        }
+        let rank = (p / 100.0 * self.latencies.len() as f64).ceil() as usize;
+        self.latencies[rank.clamp(1, self.latencies.len()) - 1]
+    }
+
+    fn mean(&self) -> Duration {
+        let total: Duration = self.latencies.iter().sum();
+        total / self.latencies.len().max(1) as u32
+    }
+
+    fn runs_per_second(&self) -> f64 {
+        self.latencies.len() as f64 / self.wall.as_secs_f64()
+    }
+
+    fn items_per_second(&self) -> f64 {
+        self.runs_per_second() * self.batch.unwrap_or(1) as f64
+    }
+}
+
+fn to_json(row: &Row) -> serde_json::Value {
+    let ms = |d: Duration| d.as_secs_f64() * 1e3;
+    json!({
+        "device": row.device.to_string(),
+        "runners": row.runners,
+        "batch": row.batch,
+        "runs": row.latencies.len(),
+        "p50_ms": ms(row.percentile(50.0)),
+        "p90_ms": ms(row.percentile(90.0)),
+        "p99_ms": ms(row.percentile(99.0)),
+        "mean_ms": ms(row.mean()),
+        "max_ms": ms(row.latencies.last().copied().unwrap_or_default()),
+        "runs_per_s": row.runs_per_second(),
+        "items_per_s": row.items_per_second(),
+    })
+}
+
+fn print_table(rows: &[Row]) {
+    println!(
+        "{:<8} {:>7} {:>6} {:>10} {:>10} {:>10} {:>10} {:>12} {:>12}",
+        "DEVICE", "RUNNERS", "BATCH", "P50 ms", "P90 ms", "P99 ms", "MEAN ms", "RUNS/s", "ITEMS/s"
+    );
+    let ms = |d: Duration| format!("{:.3}", d.as_secs_f64() * 1e3);
+    for row in rows {
+        println!(
+            "{:<8} {:>7} {:>6} {:>10} {:>10} {:>10} {:>10} {:>12.1} {:>12.1}",
+            row.device.to_string(),
+            row.runners,
+            row.batch.map_or("-".to_string(), |b| b.to_string()),
+            ms(row.percentile(50.0)),
+            ms(row.percentile(90.0)),
+            ms(row.percentile(99.0)),
+            ms(row.mean()),
+            row.runs_per_second(),
+            row.items_per_second(),
+        );
+    }
+}
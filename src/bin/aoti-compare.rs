@@ -0,0 +1,211 @@
+//! `aoti-compare`: check that two packages compute the same thing.
+//!
+//! ```text
+//! aoti-compare old.pt2 new.pt2 --input 1x3x224x224 --seed 7
+//! aoti-compare old.pt2 new.pt2 --inputs golden.safetensors --rtol 1e-3 --atol 1e-5
+//! ```
+//!
+//! Both packages run on the same inputs, synthesized from a seed or read
+//! from a file, and each output pair is compared element-wise as
+//! `torch.isclose` does (`|candidate - baseline| <= atol + rtol * |baseline|`,
+//! NaNs equal). The exit code suits a CI gate: 0 if every output is within
+//! tolerance, 1 if any differs (in values, shape, dtype or count), 2 if a
+//! package couldn't be loaded or run.
+
+mod common;
+
+use std::process::ExitCode;
+
+use aoti_rs::Error;
+use aoti_rs::compare::{OutputDiff, Tolerance, compare_tensors};
+use clap::Parser;
+use common::{DeviceArg, InputSpec};
+use serde_json::json;
+use tch::Tensor;
+
+/// Compare two .pt2 packages' outputs on identical inputs.
+#[derive(Parser)]
+#[command(version)]
+struct Args {
+    /// The reference package, e.g. the current export.
+    baseline: String,
+    /// The package checked against it, e.g. a new export.
+    candidate: String,
+    /// Model name within the baseline package.
+    #[arg(long, default_value = "model")]
+    model: String,
+    /// Model name within the candidate package (default: `--model`).
+    #[arg(long)]
+    candidate_model: Option<String>,
+    /// Synthesize an input of this shape and dtype (e.g. `1x3x224x224` or
+    /// `8x128:i64`), once per model input, in order.
+    #[arg(
+        long = "input",
+        value_name = "SHAPE[:DTYPE]",
+        conflicts_with = "inputs"
+    )]
+    input: Vec<InputSpec>,
+    /// Read the inputs from a .safetensors, .npz or .npy file instead.
+    #[arg(long, value_name = "FILE")]
+    inputs: Option<String>,
+    /// Seed for synthesized inputs.
+    #[arg(long, default_value_t = 0)]
+    seed: i64,
+    /// Relative tolerance.
+    #[arg(long, default_value_t = Tolerance::default().rtol)]
+    rtol: f64,
+    /// Absolute tolerance.
+    #[arg(long, default_value_t = Tolerance::default().atol)]
+    atol: f64,
+    /// Run both on `cpu`, `cuda` or `cuda:N` (default: each package's own
+    /// device).
+    #[arg(long)]
+    device: Option<DeviceArg>,
+    /// Print JSON instead of a table.
+    #[arg(long)]
+    json: bool,
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    if args.input.is_empty() && args.inputs.is_none() {
+        eprintln!("aoti-compare: pass --input SHAPE[:DTYPE] for each input, or --inputs FILE");
+        return ExitCode::from(2);
+    }
+    let (baseline, candidate) = match run_both(&args) {
+        Ok(outputs) => outputs,
+        Err(err) => {
+            eprintln!("aoti-compare: {err}");
+            return ExitCode::from(2);
+        }
+    };
+    let tolerance = Tolerance {
+        rtol: args.rtol,
+        atol: args.atol,
+    };
+    let diffs = match baseline
+        .iter()
+        .zip(&candidate)
+        .enumerate()
+        .map(|(index, (b, c))| compare_tensors(index, b, c, tolerance))
+        .collect::<Result<Vec<_>, Error>>()
+    {
+        Ok(diffs) => diffs,
+        Err(err) => {
+            eprintln!("aoti-compare: {err}");
+            return ExitCode::from(2);
+        }
+    };
+    let same_count = baseline.len() == candidate.len();
+    let passed = same_count && diffs.iter().all(OutputDiff::passed);
+    if args.json {
+        let json = json!({
+            "passed": passed,
+            "rtol": args.rtol,
+            "atol": args.atol,
+            "baseline_outputs": baseline.len(),
+            "candidate_outputs": candidate.len(),
+            "outputs": diffs.iter().map(to_json).collect::<Vec<_>>(),
+        });
+        println!("{json:#}");
+    } else {
+        print_table(&diffs);
+        if !same_count {
+            println!(
+                "output count differs: baseline {}, candidate {}",
+                baseline.len(),
+                candidate.len()
+            );
+        }
+        println!(
+            "{} (rtol {}, atol {})",
+            if passed { "PASSED" } else { "FAILED" },
+            args.rtol,
+            args.atol
+        );
+    }
+    if passed {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+fn run_both(args: &Args) -> Result<(Vec<Tensor>, Vec<Tensor>), Error> {
+    let inputs = match &args.inputs {
+        Some(path) => common::read_inputs(path)?,
+        None => {
+            tch::manual_seed(args.seed);
+            args.input
+                .iter()
+                .map(|spec| spec.synthesize(None))
+                .collect()
+        }
+    };
+    let candidate_model = args.candidate_model.as_deref().unwrap_or(&args.model);
+    let baseline = common::run_on_host(&args.baseline, &args.model, args.device, &inputs)
+        .map_err(|e| Error::Model(format!("baseline {}: {e}", args.baseline)))?;
+    let candidate = common::run_on_host(&args.candidate, candidate_model, args.device, &inputs)
+        .map_err(|e| Error::Model(format!("candidate {}: {e}", args.candidate)))?;
+    Ok((baseline, candidate))
+}
+
+fn to_json(diff: &OutputDiff) -> serde_json::Value {
+    let mut json = json!({
+        "index": diff.index,
+        "passed": diff.passed(),
+        "baseline": {"dtype": format!("{:?}", diff.expected.0), "shape": diff.expected.1},
+        "candidate": {"dtype": format!("{:?}", diff.actual.0), "shape": diff.actual.1},
+    });
+    if let Some(values) = &diff.values {
+        json["mismatched"] = json!(values.mismatched);
+        json["max_abs_diff"] = json!(values.max_abs_diff);
+        json["mean_abs_diff"] = json!(values.mean_abs_diff);
+        json["max_rel_diff"] = json!(values.max_rel_diff);
+        if let Some((index, baseline, candidate)) = &values.first_mismatch {
+            json["first_mismatch"] =
+                json!({"index": index, "baseline": baseline, "candidate": candidate});
+        }
+    }
+    json
+}
+
+fn print_table(diffs: &[OutputDiff]) {
+    println!(
+        "{:<6} {:<24} {:>12} {:>12} {:>16}  RESULT",
+        "OUTPUT", "DTYPE/SHAPE", "MAX ABS", "MAX REL", "MISMATCHED"
+    );
+    for diff in diffs {
+        let (kind, shape) = &diff.expected;
+        let described = format!("{kind:?} {shape:?}");
+        match &diff.values {
+            Some(values) => {
+                println!(
+                    "{:<6} {:<24} {:>12.3e} {:>12.3e} {:>16}  {}",
+                    diff.index,
+                    described,
+                    values.max_abs_diff,
+                    values.max_rel_diff,
+                    format!("{}/{}", values.mismatched, values.numel),
+                    if values.mismatched == 0 {
+                        "ok"
+                    } else {
+                        "DIFFERS"
+                    }
+                );
+                if let Some((index, baseline, candidate)) = &values.first_mismatch {
+                    println!(
+                        "       first at {index:?}: baseline {baseline}, candidate {candidate}"
+                    );
+                }
+            }
+            None => {
+                let (kind, shape) = &diff.actual;
+                println!(
+                    "{:<6} {:<24} candidate is {kind:?} {shape:?}  DIFFERS",
+                    diff.index, described
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,106 @@
+//! `aoti-export`: export a saved model to a `.pt2` package via Python.
+//!
+//! ```text
+//! aoti-export resnet.pt resnet.pt2 --input 8x3x224x224 --dynamic 0.0=batch:1:64
+//! aoti-export ranker.pt ranker.pt2 --input 4x128:i64 --input 4x128:bool \
+//!     --dynamic 0.0=batch --dynamic 1.0=batch --device cuda --python .venv/bin/python
+//! ```
+//!
+//! A thin front-end to [`PackageExporter`]: the model is a TorchScript
+//! archive or a `torch.save`d module, exported on random inputs of the
+//! `--input` shapes. `--dynamic INPUT.DIM=NAME[:MIN:MAX]` leaves a
+//! dimension symbolic; dimensions sharing a name must match at runtime.
+
+mod common;
+
+use std::process::ExitCode;
+use std::str::FromStr;
+
+use aoti_rs::Error;
+use aoti_rs::export::{DynamicDim, PackageExporter};
+use clap::Parser;
+use common::{DeviceArg, InputSpec};
+
+/// Export a TorchScript archive or pickled module to a .pt2 package.
+#[derive(Parser)]
+#[command(version)]
+struct Args {
+    /// The TorchScript archive or `torch.save`d module.
+    model: String,
+    /// Path of the .pt2 package to write.
+    package: String,
+    /// An example input's shape and dtype (e.g. `1x3x224x224` or
+    /// `8x128:i64`), once per model input, in order.
+    #[arg(long = "input", value_name = "SHAPE[:DTYPE]", required = true)]
+    inputs: Vec<InputSpec>,
+    /// Leave a dimension dynamic, e.g. `0.0=batch:1:64`.
+    #[arg(long, value_name = "INPUT.DIM=NAME[:MIN:MAX]")]
+    dynamic: Vec<DynamicArg>,
+    /// Export for `cpu`, `cuda` or `cuda:N`.
+    #[arg(long, default_value = "cpu")]
+    device: DeviceArg,
+    /// The Python interpreter, with PyTorch 2.6+ installed.
+    #[arg(long, default_value = "python3")]
+    python: String,
+}
+
+/// A parsed `--dynamic` argument.
+#[derive(Clone)]
+struct DynamicArg(DynamicDim);
+
+impl FromStr for DynamicArg {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        let invalid =
+            || format!("invalid dynamic dimension \"{text}\", expected e.g. 0.0=batch:1:64");
+        let (position, name) = text.split_once('=').ok_or_else(invalid)?;
+        let (input, dim) = position.split_once('.').ok_or_else(invalid)?;
+        let (input, dim) = (
+            input.parse().map_err(|_| invalid())?,
+            dim.parse().map_err(|_| invalid())?,
+        );
+        let dynamic = match name.split(':').collect::<Vec<_>>()[..] {
+            [name] if !name.is_empty() => DynamicDim::new(input, dim, name),
+            [name, min, max] if !name.is_empty() => DynamicDim::new(input, dim, name).range(
+                min.parse().map_err(|_| invalid())?,
+                max.parse().map_err(|_| invalid())?,
+            ),
+            _ => return Err(invalid()),
+        };
+        Ok(Self(dynamic))
+    }
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    match export(&args) {
+        Ok(()) => {
+            eprintln!("aoti-export: wrote {}", args.package);
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("aoti-export: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn export(args: &Args) -> Result<(), Error> {
+    let device = match args.device {
+        DeviceArg::Cpu => tch::Device::Cpu,
+        DeviceArg::Cuda(index) => tch::Device::Cuda(index.max(0) as usize),
+    };
+    let exporter = args.inputs.iter().fold(
+        PackageExporter::new(&args.model)
+            .device(device)
+            .python(&args.python),
+        |exporter, spec| exporter.input(&spec.shape, spec.kind),
+    );
+    args.dynamic
+        .iter()
+        .fold(exporter, |exporter, DynamicArg(dim)| {
+            exporter.dynamic(dim.clone())
+        })
+        .export(&args.package)
+}
@@ -0,0 +1,232 @@
+//! `aoti-inspect`: print what a `.pt2` package contains.
+//!
+//! ```text
+//! aoti-inspect model.pt2            # every model, as text
+//! aoti-inspect model.pt2 --json     # the same as JSON
+//! aoti-inspect model.pt2 --no-load  # archive only: models, device, metadata
+//! ```
+//!
+//! Call specs and constants need the model loaded; if that fails (a CUDA
+//! package on a machine without CUDA, say) the rest is still printed, with
+//! the error noted.
+
+use std::collections::BTreeMap;
+use std::process::ExitCode;
+
+use aoti_rs::{
+    AOTIModel, AnyAOTIModel, CallSpec, ConstantInfo, ConstantStats, Device, Error,
+    load_metadata_from_package, package_models,
+};
+use clap::Parser;
+use serde_json::{Value, json};
+
+/// Print a .pt2 package's models, target device, metadata, call spec and
+/// constants.
+#[derive(Parser)]
+#[command(version)]
+struct Args {
+    /// Path of the .pt2 package.
+    package: String,
+    /// Inspect only this model (default: every model in the package).
+    #[arg(long)]
+    model: Option<String>,
+    /// Print JSON instead of text.
+    #[arg(long)]
+    json: bool,
+    /// Read only the archive, without loading models for their call spec
+    /// and constants.
+    #[arg(long)]
+    no_load: bool,
+}
+
+/// What was found out about one model.
+struct Report {
+    name: String,
+    device: String,
+    metadata: BTreeMap<String, String>,
+    /// The call spec and constants, or why the model couldn't be loaded.
+    loaded: Option<Result<(CallSpec, Vec<ConstantInfo>), Error>>,
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    match inspect(&args) {
+        Ok(reports) => {
+            if args.json {
+                let json = json!({
+                    "package": args.package,
+                    "models": reports.iter().map(to_json).collect::<Vec<_>>(),
+                });
+                println!("{json:#}");
+            } else {
+                print_text(&args.package, &reports);
+            }
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("aoti-inspect: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn inspect(args: &Args) -> Result<Vec<Report>, Error> {
+    let mut names = package_models(&args.package)?;
+    if let Some(model) = &args.model {
+        if !names.contains(model) {
+            return Err(Error::InvalidInput(format!(
+                "{} has no model '{model}' (models: {})",
+                args.package,
+                names.join(", ")
+            )));
+        }
+        names = vec![model.clone()];
+    }
+    if names.is_empty() {
+        // Older packages keep a single model's artifacts at the top level.
+        names.push("model".to_string());
+    }
+    names
+        .into_iter()
+        .map(|name| {
+            let metadata: BTreeMap<_, _> = load_metadata_from_package(&args.package, &name)?
+                .into_iter()
+                .collect();
+            let device = metadata
+                .get("AOTI_DEVICE_KEY")
+                .cloned()
+                .unwrap_or_else(|| "cpu".to_string());
+            let loaded = (!args.no_load).then(|| load(&args.package, &name));
+            Ok(Report {
+                name,
+                device,
+                metadata,
+                loaded,
+            })
+        })
+        .collect()
+}
+
+fn load(package: &str, name: &str) -> Result<(CallSpec, Vec<ConstantInfo>), Error> {
+    fn describe<D: Device>(
+        mut model: AOTIModel<D>,
+    ) -> Result<(CallSpec, Vec<ConstantInfo>), Error> {
+        Ok((model.call_spec()?, model.constants()?))
+    }
+    match AnyAOTIModel::load_named(package, name)? {
+        AnyAOTIModel::Cpu(model) => describe(model),
+        #[cfg(aoti_cuda)]
+        AnyAOTIModel::Cuda(model) => describe(model),
+        _ => Err(Error::Model("unsupported device".into())),
+    }
+}
+
+fn to_json(report: &Report) -> Value {
+    let mut json = json!({
+        "name": report.name,
+        "device": report.device,
+        "metadata": report.metadata,
+    });
+    match &report.loaded {
+        Some(Ok((spec, constants))) => {
+            json["call_spec"] = json!({"in_spec": spec.in_spec, "out_spec": spec.out_spec});
+            json["constants"] = constants
+                .iter()
+                .map(|c| json!({"name": c.name, "dtype": format!("{:?}", c.dtype), "shape": c.shape, "bytes": c.bytes}))
+                .collect();
+        }
+        Some(Err(err)) => json["load_error"] = json!(err.to_string()),
+        None => {}
+    }
+    json
+}
+
+fn print_text(package: &str, reports: &[Report]) {
+    println!("package: {package}");
+    let names: Vec<_> = reports.iter().map(|r| r.name.as_str()).collect();
+    println!("models:  {}", names.join(", "));
+    for report in reports {
+        println!();
+        println!("model '{}'", report.name);
+        println!("  device: {}", report.device);
+        println!("  metadata:");
+        for (key, value) in &report.metadata {
+            println!("    {key} = {value}");
+        }
+        match &report.loaded {
+            Some(Ok((spec, constants))) => {
+                println!("  call spec:");
+                println!("    in:  {}", spec.in_spec);
+                println!("    out: {}", spec.out_spec);
+                print_constants(constants);
+            }
+            Some(Err(err)) => println!("  not loaded: {err}"),
+            None => {}
+        }
+    }
+}
+
+fn print_constants(constants: &[ConstantInfo]) {
+    let stats = ConstantStats::of(constants);
+    let by_dtype: Vec<String> = stats
+        .by_dtype
+        .iter()
+        .map(|d| format!("{:?} {}", d.dtype, human_bytes(d.bytes)))
+        .collect();
+    println!(
+        "  constants: {} ({}; {} elements; {})",
+        stats.constants,
+        human_bytes(stats.bytes),
+        stats.parameters,
+        by_dtype.join(", ")
+    );
+    let rows: Vec<[String; 4]> = constants
+        .iter()
+        .map(|c| {
+            [
+                c.name.clone(),
+                format!("{:?}", c.dtype),
+                format!("{:?}", c.shape),
+                human_bytes(c.bytes),
+            ]
+        })
+        .collect();
+    let header = ["NAME", "DTYPE", "SHAPE", "SIZE"].map(String::from);
+    let widths: Vec<usize> = (0..4)
+        .map(|i| {
+            std::iter::once(&header)
+                .chain(&rows)
+                .map(|row| row[i].len())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    for row in std::iter::once(&header).chain(&rows) {
+        println!(
+            "    {:<w0$}  {:<w1$}  {:<w2$}  {:>w3$}",
+            row[0],
+            row[1],
+            row[2],
+            row[3],
+            w0 = widths[0],
+            w1 = widths[1],
+            w2 = widths[2],
+            w3 = widths[3],
+        );
+    }
+}
+
+fn human_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
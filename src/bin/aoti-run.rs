@@ -0,0 +1,131 @@
+//! `aoti-run`: run a package on inputs from a file.
+//!
+//! ```text
+//! aoti-run model.pt2 --inputs in.safetensors --output out.safetensors
+//! aoti-run model.pt2 --inputs in.npz --input-names ids,mask --output-names logits
+//! aoti-run model.pt2 --inputs x.npy | jq '.[0].shape'
+//! ```
+//!
+//! Inputs are passed to the model in file order, or in `--input-names`
+//! order. Outputs are named `output0`, `output1`, ... unless
+//! `--output-names` is given, and written in the format the `--output`
+//! extension names; without `--output` they are printed to stdout as JSON.
+//! The device is the package's own unless `--device` is given.
+
+mod common;
+
+use std::collections::HashMap;
+use std::process::ExitCode;
+
+use aoti_rs::{AOTIModel, Device, DeviceTensor, Error};
+use clap::Parser;
+use common::{DeviceArg, ModelTask};
+use serde_json::json;
+
+/// Run a .pt2 package on inputs read from a .safetensors, .npz or .npy file.
+#[derive(Parser)]
+#[command(version)]
+struct Args {
+    /// Path of the .pt2 package.
+    package: String,
+    /// Model name within the package.
+    #[arg(long, default_value = "model")]
+    model: String,
+    /// The .safetensors, .npz or .npy file to read the inputs from.
+    #[arg(long, value_name = "FILE")]
+    inputs: String,
+    /// Pass these tensors from the input file, in this order (default:
+    /// every tensor, in file order).
+    #[arg(long, value_delimiter = ',', value_name = "NAMES")]
+    input_names: Vec<String>,
+    /// The .safetensors, .npz or .npy file to write the outputs to
+    /// (default: print them as JSON).
+    #[arg(long, value_name = "FILE")]
+    output: Option<String>,
+    /// Names for the outputs, one per output.
+    #[arg(long, value_delimiter = ',', value_name = "NAMES")]
+    output_names: Vec<String>,
+    /// Load on `cpu`, `cuda` or `cuda:N` (default: the package's device).
+    #[arg(long)]
+    device: Option<DeviceArg>,
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    match common::with_model(&args.package, &args.model, args.device, Run(&args)) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("aoti-run: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Runs the model on the input file and writes or prints the outputs.
+struct Run<'a>(&'a Args);
+
+impl ModelTask for Run<'_> {
+    type Output = ();
+
+    fn run<D: Device>(self, mut model: AOTIModel<D>) -> Result<(), Error> {
+        let args = self.0;
+        let mut tensors = common::read_tensors(&args.inputs)?;
+        if !args.input_names.is_empty() {
+            let mut by_name: HashMap<_, _> = tensors.into_iter().collect();
+            tensors = args
+                .input_names
+                .iter()
+                .map(|name| {
+                    let tensor = by_name.remove(name).ok_or_else(|| {
+                        Error::InvalidInput(format!("{} has no tensor '{name}'", args.inputs))
+                    })?;
+                    Ok((name.clone(), tensor))
+                })
+                .collect::<Result<_, Error>>()?;
+        }
+        let inputs: Vec<_> = tensors.iter().map(|(_, t)| model.upload(t)).collect();
+        let outputs = model.run(&inputs)?;
+
+        let names: Vec<String> = if args.output_names.is_empty() {
+            (0..outputs.len()).map(|i| format!("output{i}")).collect()
+        } else if args.output_names.len() == outputs.len() {
+            args.output_names.clone()
+        } else {
+            return Err(Error::InvalidInput(format!(
+                "the model returned {} outputs but {} names were given",
+                outputs.len(),
+                args.output_names.len()
+            )));
+        };
+        match &args.output {
+            Some(path) => {
+                let named: Vec<_> = names.iter().map(String::as_str).zip(&outputs).collect();
+                common::write_tensors(path, &named)
+            }
+            None => {
+                let json = names
+                    .iter()
+                    .zip(&outputs)
+                    .map(|(name, output)| to_json(name, output))
+                    .collect::<Result<Vec<_>, Error>>()?;
+                println!("{:#}", json!(json));
+                Ok(())
+            }
+        }
+    }
+}
+
+/// An output's name, dtype, shape and values (flattened, as numbers).
+fn to_json<D: Device>(name: &str, output: &DeviceTensor<D>) -> Result<serde_json::Value, Error> {
+    let values = output
+        .f_to_device(tch::Device::Cpu)?
+        .f_to_kind(tch::Kind::Double)?
+        .f_flatten(0, -1)?;
+    let values = Vec::<f64>::try_from(&values)?;
+    Ok(json!({
+        "name": name,
+        "dtype": format!("{:?}", output.kind()),
+        "shape": output.size(),
+        "values": values,
+    }))
+}
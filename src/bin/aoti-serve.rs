@@ -0,0 +1,146 @@
+//! `aoti-serve`: serve the models in a TOML config over HTTP and gRPC.
+//!
+//! ```text
+//! aoti-serve serve.toml
+//! AOTI_RS_HTTP=0.0.0.0:9000 aoti-serve env:SERVE_CONFIG
+//! ```
+//!
+//! Every `[models.NAME]` table (see [`ServeConfig`]) is loaded into a
+//! [`ModelRegistry`] as version 1 of `NAME`, with `pool_size` replicas of
+//! `runners` runners each, and the pools are served on each configured
+//! front-end until one fails. A process serves one device kind: the
+//! models may be on different CUDA devices, but not some on the CPU and
+//! some on CUDA. `warmup` is ignored, as the server has no representative
+//! inputs to warm with.
+
+use std::process::ExitCode;
+
+#[cfg(aoti_cuda)]
+use aoti_rs::Cuda;
+use aoti_rs::registry::{ModelRegistry, ModelSpec};
+use aoti_rs::{AOTIModel, AOTIModelBuilder, Cpu, Device, Error, ServeConfig};
+use clap::Parser;
+
+/// Serve the models of a TOML config on its HTTP and gRPC addresses.
+#[derive(Parser)]
+#[command(version)]
+struct Args {
+    /// The TOML config file, or `env:NAME` to read it from a variable.
+    config: String,
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    match serve_config(&args.config) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("aoti-serve: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn serve_config(source: &str) -> Result<(), Error> {
+    let config = ServeConfig::load(source)?;
+    if config.models.is_empty() {
+        return Err(Error::InvalidInput(format!("{source} has no [models.*]")));
+    }
+    if config.http.is_none() && config.grpc.is_none() {
+        return Err(Error::InvalidInput(format!(
+            "{source} sets neither \"http\" nor \"grpc\""
+        )));
+    }
+    #[cfg(not(feature = "http"))]
+    if let Some(addr) = config.http {
+        return Err(not_built("http", addr));
+    }
+    #[cfg(not(feature = "grpc"))]
+    if let Some(addr) = config.grpc {
+        return Err(not_built("grpc", addr));
+    }
+    let cuda = config
+        .models
+        .values()
+        .filter(|m| m.device.starts_with("cuda"));
+    match cuda.count() {
+        0 => serve::<Cpu>(&config, AOTIModelBuilder::<Cpu>::build),
+        #[cfg(aoti_cuda)]
+        n if n == config.models.len() => serve::<Cuda>(&config, AOTIModelBuilder::<Cuda>::build),
+        #[cfg(not(aoti_cuda))]
+        n if n == config.models.len() => Err(Error::InvalidInput(
+            "CUDA models were configured but aoti-rs was built without CUDA support".into(),
+        )),
+        _ => Err(Error::InvalidInput(
+            "models on the CPU and on CUDA can't be served by one process".into(),
+        )),
+    }
+}
+
+/// Load every model with `build` and serve them until a front-end fails.
+fn serve<D: Device>(
+    config: &ServeConfig,
+    build: fn(AOTIModelBuilder<D>) -> Result<AOTIModel<D>, Error>,
+) -> Result<(), Error> {
+    let models = config.models.clone();
+    let registry =
+        ModelRegistry::<D>::with_loader(move |spec| build(models[&spec.name].builder()?));
+    let mut pools = Vec::new();
+    for (name, model) in &config.models {
+        let spec = ModelSpec::new(name, 1, &model.path)
+            .model_name(&model.model_name)
+            .replicas(model.pool_size);
+        eprintln!("aoti-serve: loading {name} from {}", model.path);
+        pools.push((name.clone(), registry.load(spec)?));
+    }
+
+    let (failed, failure) = std::sync::mpsc::channel::<Error>();
+    #[cfg(feature = "http")]
+    if let Some(addr) = config.http {
+        let server = pools.iter().fold(
+            aoti_rs::serve::http::HttpServer::new(),
+            |server, (name, pool)| server.model(name, pool.clone()),
+        );
+        let failed = failed.clone();
+        std::thread::spawn(move || {
+            eprintln!("aoti-serve: HTTP on {addr}");
+            let _ = failed.send(block_on(server.serve(addr)).err().unwrap_or_else(stopped));
+        });
+    }
+    #[cfg(feature = "grpc")]
+    if let Some(addr) = config.grpc {
+        let server = pools.iter().fold(
+            aoti_rs::serve::grpc::PredictServer::new(),
+            |server, (name, pool)| server.model(name, pool.clone()),
+        );
+        let failed = failed.clone();
+        std::thread::spawn(move || {
+            eprintln!("aoti-serve: gRPC on {addr}");
+            let _ = failed.send(block_on(server.serve(addr)).err().unwrap_or_else(stopped));
+        });
+    }
+    drop(failed);
+    // The front-ends only return on failure; report the first.
+    Err(failure.recv().unwrap_or_else(|_| stopped()))
+}
+
+/// Run `future` on a runtime of its own; inference runs on its blocking
+/// pool.
+#[cfg(any(feature = "http", feature = "grpc"))]
+fn block_on<T>(future: impl Future<Output = Result<T, Error>>) -> Result<T, Error> {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?
+        .block_on(future)
+}
+
+fn stopped() -> Error {
+    Error::Model("a front-end stopped".into())
+}
+
+#[cfg(not(all(feature = "http", feature = "grpc")))]
+fn not_built(transport: &str, addr: std::net::SocketAddr) -> Error {
+    Error::InvalidInput(format!(
+        "\"{transport}\" = \"{addr}\" is configured but aoti-rs was built without the \
+         `{transport}` feature"
+    ))
+}
@@ -0,0 +1,205 @@
+//! `aoti-validate`: gate a package on loading and running cleanly.
+//!
+//! ```text
+//! aoti-validate model.pt2 --input 1x3x224x224 --device cuda:0
+//! aoti-validate model.pt2 --inputs example.npz --iterations 20 --json
+//! ```
+//!
+//! The package is loaded on the requested device (default: its own) and
+//! run repeatedly on the same inputs, which are synthesized from
+//! `--input` specs (a package's call spec describes the input structure
+//! but not shapes) or read from a file. Validation fails, with exit code
+//! 1, if the model doesn't load or a run fails, if an output has NaN or
+//! infinite values, or if the outputs' count, shapes or dtypes change
+//! between iterations.
+
+mod common;
+
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+
+use aoti_rs::{AOTIModel, Device, Error};
+use clap::Parser;
+use common::{DeviceArg, InputSpec, ModelTask};
+use serde_json::json;
+use tch::{Kind, Tensor};
+
+/// Load a .pt2 package, run it and sanity-check the outputs.
+#[derive(Parser)]
+#[command(version)]
+struct Args {
+    /// Path of the .pt2 package.
+    package: String,
+    /// Model name within the package.
+    #[arg(long, default_value = "model")]
+    model: String,
+    /// Synthesize an input of this shape and dtype (e.g. `1x3x224x224` or
+    /// `8x128:i64`), once per model input, in order.
+    #[arg(
+        long = "input",
+        value_name = "SHAPE[:DTYPE]",
+        conflicts_with = "inputs"
+    )]
+    input: Vec<InputSpec>,
+    /// Read the inputs from a .safetensors, .npz or .npy file instead.
+    #[arg(long, value_name = "FILE")]
+    inputs: Option<String>,
+    /// Load on `cpu`, `cuda` or `cuda:N` (default: the package's device).
+    #[arg(long)]
+    device: Option<DeviceArg>,
+    /// Runs to make.
+    #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u32).range(1..))]
+    iterations: u32,
+    /// Print JSON instead of text.
+    #[arg(long)]
+    json: bool,
+}
+
+/// What the runs showed.
+#[derive(Default)]
+struct Report {
+    device: String,
+    /// Dtype and shape of each output of the first successful run.
+    outputs: Vec<(Kind, Vec<i64>)>,
+    latencies: Vec<Duration>,
+    failures: Vec<String>,
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    if args.input.is_empty() && args.inputs.is_none() {
+        eprintln!("aoti-validate: pass --input SHAPE[:DTYPE] for each input, or --inputs FILE");
+        return ExitCode::from(2);
+    }
+    let report = match &args.inputs {
+        Some(path) => common::read_inputs(path),
+        None => Ok(args
+            .input
+            .iter()
+            .map(|spec| spec.synthesize(None))
+            .collect()),
+    }
+    .and_then(|inputs| {
+        let validate = Validate {
+            inputs,
+            iterations: args.iterations,
+        };
+        common::with_model(&args.package, &args.model, args.device, validate)
+    })
+    .unwrap_or_else(|err| Report {
+        failures: vec![err.to_string()],
+        ..Report::default()
+    });
+    if args.json {
+        println!("{:#}", to_json(&args.package, &report));
+    } else {
+        print_text(&args.package, &report);
+    }
+    if report.failures.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+/// Runs a loaded model `iterations` times, checking every run.
+struct Validate {
+    inputs: Vec<Tensor>,
+    iterations: u32,
+}
+
+impl ModelTask for Validate {
+    type Output = Report;
+
+    fn run<D: Device>(self, mut model: AOTIModel<D>) -> Result<Report, Error> {
+        let mut report = Report {
+            device: format!("{:?}", model.device()),
+            ..Report::default()
+        };
+        let inputs: Vec<_> = self.inputs.iter().map(|t| model.upload(t)).collect();
+        let mut first = None;
+        for iteration in 0..self.iterations {
+            let start = Instant::now();
+            let outputs = match model.run(&inputs) {
+                Ok(outputs) => outputs,
+                Err(err) => {
+                    report.failures.push(format!("run {iteration}: {err}"));
+                    continue;
+                }
+            };
+            report.latencies.push(start.elapsed());
+            let described: Vec<_> = outputs.iter().map(|t| (t.kind(), t.size())).collect();
+            match &first {
+                None => first = Some(described),
+                Some(first) if *first != described => report.failures.push(format!(
+                    "run {iteration}: outputs are {described:?}, the first run's were {first:?}"
+                )),
+                Some(_) => {}
+            }
+            for (index, output) in outputs.iter().enumerate() {
+                if let Some(problem) = non_finite(output)? {
+                    report
+                        .failures
+                        .push(format!("run {iteration}: output {index} has {problem}"));
+                }
+            }
+        }
+        report.outputs = first.unwrap_or_default();
+        Ok(report)
+    }
+}
+
+/// How many NaN and infinite values a floating-point tensor has, if any.
+fn non_finite(tensor: &Tensor) -> Result<Option<String>, Error> {
+    if !tensor.is_floating_point() {
+        return Ok(None);
+    }
+    let count = |t: Tensor| -> Result<i64, Error> { Ok(t.f_sum(Kind::Int64)?.f_int64_value(&[])?) };
+    let nan = count(tensor.f_isnan()?)?;
+    let inf = count(tensor.f_isinf()?)?;
+    Ok((nan + inf > 0).then(|| format!("{nan} NaN and {inf} infinite values")))
+}
+
+fn mean_ms(latencies: &[Duration]) -> f64 {
+    let total: Duration = latencies.iter().sum();
+    total.as_secs_f64() * 1e3 / latencies.len().max(1) as f64
+}
+
+fn to_json(package: &str, report: &Report) -> serde_json::Value {
+    json!({
+        "package": package,
+        "passed": report.failures.is_empty(),
+        "device": report.device,
+        "outputs": report
+            .outputs
+            .iter()
+            .map(|(kind, shape)| json!({"dtype": format!("{kind:?}"), "shape": shape}))
+            .collect::<Vec<_>>(),
+        "runs": report.latencies.len(),
+        "mean_ms": mean_ms(&report.latencies),
+        "failures": report.failures,
+    })
+}
+
+fn print_text(package: &str, report: &Report) {
+    println!("package: {package}");
+    if !report.device.is_empty() {
+        println!("device:  {}", report.device);
+    }
+    for (index, (kind, shape)) in report.outputs.iter().enumerate() {
+        println!("output {index}: {kind:?} {shape:?}");
+    }
+    if !report.latencies.is_empty() {
+        println!(
+            "runs:    {} ok, mean {:.3} ms",
+            report.latencies.len(),
+            mean_ms(&report.latencies)
+        );
+    }
+    for failure in &report.failures {
+        println!("FAILED:  {failure}");
+    }
+    if report.failures.is_empty() {
+        println!("PASSED");
+    }
+}
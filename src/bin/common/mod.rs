@@ -0,0 +1,255 @@
+//! Argument types and helpers shared by the `cli` binaries.
+
+// Each binary compiles this module on its own and uses only part of it.
+#![allow(dead_code)]
+
+use std::path::Path;
+use std::str::FromStr;
+
+#[cfg(aoti_cuda)]
+use aoti_rs::Cuda;
+use aoti_rs::{AOTIModel, AnyAOTIModel, Cpu, Device, DeviceTensor, Error};
+use tch::{Kind, Tensor};
+
+/// A synthesized input, written `2x4` or `2x4:f16` (default dtype `f32`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct InputSpec {
+    pub shape: Vec<i64>,
+    pub kind: Kind,
+}
+
+impl FromStr for InputSpec {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        let (shape, kind) = match text.split_once(':') {
+            Some((shape, kind)) => (shape, parse_kind(kind)?),
+            None => (text, Kind::Float),
+        };
+        let shape = if shape.is_empty() {
+            // A scalar.
+            Vec::new()
+        } else {
+            shape
+                .split('x')
+                .map(|dim| dim.parse::<i64>().ok().filter(|&d| d >= 0))
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(|| format!("invalid shape \"{shape}\", expected e.g. 2x4"))?
+        };
+        Ok(Self { shape, kind })
+    }
+}
+
+impl InputSpec {
+    /// A random host tensor of this shape and dtype, with the leading
+    /// dimension replaced by `batch` if given: normal floats, integers in
+    /// `0..10`, or `false`.
+    pub fn synthesize(&self, batch: Option<i64>) -> Tensor {
+        let mut shape = self.shape.clone();
+        if let (Some(batch), Some(first)) = (batch, shape.first_mut()) {
+            *first = batch;
+        }
+        let options = (self.kind, tch::Device::Cpu);
+        match self.kind {
+            Kind::Half | Kind::BFloat16 | Kind::Float | Kind::Double => {
+                Tensor::randn(shape.as_slice(), options)
+            }
+            Kind::Bool => Tensor::zeros(shape.as_slice(), options),
+            _ => Tensor::randint(10, shape.as_slice(), options),
+        }
+    }
+}
+
+fn parse_kind(kind: &str) -> Result<Kind, String> {
+    Ok(match kind {
+        "f16" | "half" | "float16" => Kind::Half,
+        "bf16" | "bfloat16" => Kind::BFloat16,
+        "f32" | "float" | "float32" => Kind::Float,
+        "f64" | "double" | "float64" => Kind::Double,
+        "i8" | "int8" => Kind::Int8,
+        "u8" | "uint8" => Kind::Uint8,
+        "i16" | "int16" => Kind::Int16,
+        "i32" | "int" | "int32" => Kind::Int,
+        "i64" | "long" | "int64" => Kind::Int64,
+        "bool" => Kind::Bool,
+        _ => return Err(format!("unknown dtype \"{kind}\"")),
+    })
+}
+
+/// A device to load on: `cpu`, `cuda` (the current device) or `cuda:N`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceArg {
+    Cpu,
+    Cuda(i8),
+}
+
+impl FromStr for DeviceArg {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        match text.split_once(':') {
+            None if text == "cpu" => Ok(Self::Cpu),
+            None if text == "cuda" => Ok(Self::Cuda(-1)),
+            Some(("cuda", index)) => index
+                .parse::<i8>()
+                .ok()
+                .filter(|&i| i >= 0)
+                .map(Self::Cuda)
+                .ok_or_else(|| format!("invalid CUDA index in \"{text}\"")),
+            _ => Err(format!("unknown device \"{text}\"")),
+        }
+    }
+}
+
+impl std::fmt::Display for DeviceArg {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Cpu => f.write_str("cpu"),
+            Self::Cuda(-1) => f.write_str("cuda"),
+            Self::Cuda(index) => write!(f, "cuda:{index}"),
+        }
+    }
+}
+
+/// Load a CPU model from `package`.
+pub fn load_cpu(package: &str, model: &str, runners: usize) -> Result<AOTIModel<Cpu>, Error> {
+    AOTIModel::<Cpu>::builder(package)
+        .model_name(model)
+        .num_runners(runners)
+        .build()
+}
+
+/// Load a CUDA model from `package` on device `index`.
+#[cfg(aoti_cuda)]
+pub fn load_cuda(
+    package: &str,
+    model: &str,
+    runners: usize,
+    index: i8,
+) -> Result<AOTIModel<Cuda>, Error> {
+    AOTIModel::<Cuda>::builder(package)
+        .model_name(model)
+        .num_runners(runners)
+        .device_index(index)
+        .build()
+}
+
+/// Work on a loaded model of either device type; see [`with_model`].
+pub trait ModelTask {
+    type Output;
+
+    fn run<D: Device>(self, model: AOTIModel<D>) -> Result<Self::Output, Error>;
+}
+
+/// Load `model` from `package` on `device` or, by default, the package's
+/// own device, and hand it to `task`.
+pub fn with_model<T: ModelTask>(
+    package: &str,
+    model: &str,
+    device: Option<DeviceArg>,
+    task: T,
+) -> Result<T::Output, Error> {
+    match device {
+        None => match AnyAOTIModel::load_named(package, model)? {
+            AnyAOTIModel::Cpu(model) => task.run(model),
+            #[cfg(aoti_cuda)]
+            AnyAOTIModel::Cuda(model) => task.run(model),
+            _ => Err(Error::Model("unsupported device".into())),
+        },
+        Some(DeviceArg::Cpu) => task.run(load_cpu(package, model, 1)?),
+        #[cfg(aoti_cuda)]
+        Some(DeviceArg::Cuda(index)) => task.run(load_cuda(package, model, 1, index)?),
+        #[cfg(not(aoti_cuda))]
+        Some(device @ DeviceArg::Cuda(_)) => Err(no_cuda(device)),
+    }
+}
+
+/// Run `model` from `package` once on host `inputs`, as [`with_model`]
+/// loads it, returning the outputs in host memory.
+pub fn run_on_host(
+    package: &str,
+    model: &str,
+    device: Option<DeviceArg>,
+    inputs: &[Tensor],
+) -> Result<Vec<Tensor>, Error> {
+    struct RunOnHost<'a>(&'a [Tensor]);
+
+    impl ModelTask for RunOnHost<'_> {
+        type Output = Vec<Tensor>;
+
+        fn run<D: Device>(self, mut model: AOTIModel<D>) -> Result<Vec<Tensor>, Error> {
+            let inputs: Vec<_> = self.0.iter().map(|t| model.upload(t)).collect();
+            model
+                .run(&inputs)?
+                .into_iter()
+                .map(|output| Ok(output.f_to_device(tch::Device::Cpu)?))
+                .collect()
+        }
+    }
+    with_model(package, model, device, RunOnHost(inputs))
+}
+
+/// The error for a CUDA device requested from a build without CUDA.
+pub fn no_cuda(device: DeviceArg) -> Error {
+    Error::InvalidInput(format!(
+        "{device} was requested but aoti-rs was built without CUDA support"
+    ))
+}
+
+/// Read named host tensors from a `.safetensors` file or `.npz` archive (in
+/// file order), or a `.npy` file (one tensor, named after the file stem).
+pub fn read_tensors(path: &str) -> Result<Vec<(String, Tensor)>, Error> {
+    let named = if path.ends_with(".safetensors") {
+        aoti_rs::safetensors::read_tensors(path)?
+    } else if path.ends_with(".npz") {
+        aoti_rs::npy::read_npz(path)?
+    } else if path.ends_with(".npy") {
+        let stem = Path::new(path)
+            .file_stem()
+            .map_or("input".into(), |stem| stem.to_string_lossy().into_owned());
+        vec![(stem, aoti_rs::npy::read_npy(path)?)]
+    } else {
+        return Err(unknown_format(path));
+    };
+    Ok(named
+        .into_iter()
+        .map(|(name, tensor)| (name, tensor.into_inner()))
+        .collect())
+}
+
+/// Read model inputs as [`read_tensors`] does, dropping the names.
+pub fn read_inputs(path: &str) -> Result<Vec<Tensor>, Error> {
+    Ok(read_tensors(path)?
+        .into_iter()
+        .map(|(_, tensor)| tensor)
+        .collect())
+}
+
+/// Write named tensors to a `.safetensors` file, `.npz` archive or, if there
+/// is exactly one, a `.npy` file.
+pub fn write_tensors<D: Device>(
+    path: &str,
+    tensors: &[(&str, &DeviceTensor<D>)],
+) -> Result<(), Error> {
+    if path.ends_with(".safetensors") {
+        aoti_rs::safetensors::write_tensors(path, tensors)
+    } else if path.ends_with(".npz") {
+        aoti_rs::npy::write_npz(path, tensors)
+    } else if path.ends_with(".npy") {
+        match tensors {
+            [(_, tensor)] => aoti_rs::npy::write_npy(path, tensor),
+            _ => Err(Error::InvalidInput(format!(
+                "{path}: a .npy file holds one tensor, not {}",
+                tensors.len()
+            ))),
+        }
+    } else {
+        Err(unknown_format(path))
+    }
+}
+
+fn unknown_format(path: &str) -> Error {
+    Error::InvalidInput(format!(
+        "{path}: expected a .safetensors, .npz or .npy file"
+    ))
+}
@@ -0,0 +1,260 @@
+//! Adapter for running an [`AOTIModel`] as an inference block inside a Burn
+//! application (feature `burn`).
+//!
+//! Burn tensors cross into libtorch through [`TensorData`]: inputs are read
+//! back to host memory on whatever Burn backend produced them, uploaded to
+//! the model's device, and outputs are copied back into tensors on the
+//! caller's Burn device. Dtypes are mapped one-to-one; Burn's `Flex32` and
+//! quantized dtypes, and torch's complex/quantized kinds, fail with
+//! [`Error::UnsupportedDtype`].
+//!
+//! [`BurnBlock`] implements Burn's `Module` as a constant (it holds no Burn
+//! parameters), so it can sit directly inside a `#[derive(Module)]` struct
+//! next to the Burn layers that feed it.
+
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex};
+
+use burn::module::{AutodiffModule, Content, Module, ModuleDisplay, ModuleDisplayDefault};
+use burn_tensor::backend::{AutodiffBackend, Backend};
+use burn_tensor::{BasicOps, DType, TensorData};
+use tch::{Kind, Tensor};
+
+use crate::{AOTIModel, Cpu, Device, DeviceTensor, Error};
+
+fn burn_dtype(kind: Kind) -> Result<DType, Error> {
+    Ok(match kind {
+        Kind::Uint8 => DType::U8,
+        Kind::Int8 => DType::I8,
+        Kind::Int16 => DType::I16,
+        Kind::Int => DType::I32,
+        Kind::Int64 => DType::I64,
+        Kind::Half => DType::F16,
+        Kind::BFloat16 => DType::BF16,
+        Kind::Float => DType::F32,
+        Kind::Double => DType::F64,
+        Kind::Bool => DType::Bool,
+        other => return Err(Error::UnsupportedDtype(format!("torch {other:?}"))),
+    })
+}
+
+fn tch_kind(dtype: DType) -> Result<Kind, Error> {
+    Ok(match dtype {
+        DType::U8 => Kind::Uint8,
+        DType::I8 => Kind::Int8,
+        DType::I16 => Kind::Int16,
+        DType::I32 => Kind::Int,
+        DType::I64 => Kind::Int64,
+        DType::F16 => Kind::Half,
+        DType::BF16 => Kind::BFloat16,
+        DType::F32 => Kind::Float,
+        DType::F64 => Kind::Double,
+        DType::Bool => Kind::Bool,
+        other => return Err(Error::UnsupportedDtype(format!("burn {other:?}"))),
+    })
+}
+
+impl DeviceTensor<Cpu> {
+    /// Copy Burn tensor data into a new CPU tensor with the equivalent dtype.
+    pub fn from_burn_data(data: &TensorData) -> Result<Self, Error> {
+        let kind = tch_kind(data.dtype)?;
+        let shape: Vec<i64> = data.shape.iter().map(|&d| d as i64).collect();
+        let tensor = Tensor::f_from_data_size(data.as_bytes(), &shape, kind)?;
+        Ok(Self {
+            tensor,
+            _device: PhantomData,
+        })
+    }
+}
+
+impl<D: Device> DeviceTensor<D> {
+    /// Copy the tensor into host-resident Burn tensor data.
+    pub fn to_burn_data(&self) -> Result<TensorData, Error> {
+        let kind = self.tensor.f_kind()?;
+        let dtype = burn_dtype(kind)?;
+        let numel = self.tensor.numel();
+        let mut bytes = vec![0u8; numel * kind.elt_size_in_bytes()];
+        self.tensor
+            .f_contiguous()?
+            .f_copy_data_u8(&mut bytes, numel)?;
+        let shape: Vec<usize> = self.tensor.size().iter().map(|&d| d as usize).collect();
+        Ok(TensorData::from_bytes_vec(bytes, shape, dtype))
+    }
+}
+
+/// An [`AOTIModel`] packaged as a Burn inference block.
+///
+/// Clones share the same underlying model; concurrent `forward` calls are
+/// serialized.
+pub struct BurnBlock<D: Device> {
+    model: Arc<Mutex<AOTIModel<D>>>,
+    device: tch::Device,
+}
+
+impl<D: Device> BurnBlock<D> {
+    /// Wrap `model`, uploading inputs to `device` before each run.
+    ///
+    /// Returns [`Error::TensorDeviceMismatch`] if `device` isn't of kind
+    /// `D`.
+    pub fn new(model: AOTIModel<D>, device: tch::Device) -> Result<Self, Error> {
+        if !D::matches(device) {
+            return Err(Error::TensorDeviceMismatch {
+                expected: D::KEY,
+                found: device,
+            });
+        }
+        Ok(Self {
+            model: Arc::new(Mutex::new(model)),
+            device,
+        })
+    }
+
+    /// Run the model on rank-erased tensor data.
+    pub fn forward_data(&self, inputs: &[TensorData]) -> Result<Vec<TensorData>, Error> {
+        let inputs = inputs
+            .iter()
+            .map(|data| {
+                let host = DeviceTensor::<Cpu>::from_burn_data(data)?;
+                DeviceTensor::<D>::try_new(host.into_inner().to_device(self.device))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let outputs = self
+            .model
+            .lock()
+            .map_err(|_| Error::Model("model mutex poisoned".into()))?
+            .run(inputs)?;
+        outputs.iter().map(DeviceTensor::to_burn_data).collect()
+    }
+
+    /// Run the model on Burn tensors, returning outputs on `device`.
+    ///
+    /// Every output must have rank `R`; use [`BurnBlock::forward_data`] for
+    /// models whose outputs differ in rank.
+    pub fn forward<B, const R: usize, K>(
+        &self,
+        inputs: Vec<burn_tensor::Tensor<B, R, K>>,
+        device: &B::Device,
+    ) -> Result<Vec<burn_tensor::Tensor<B, R, K>>, Error>
+    where
+        B: Backend,
+        K: BasicOps<B>,
+    {
+        let inputs: Vec<TensorData> = inputs
+            .into_iter()
+            .map(burn_tensor::Tensor::into_data)
+            .collect();
+        self.forward_data(&inputs)?
+            .into_iter()
+            .enumerate()
+            .map(|(i, data)| {
+                if data.shape.len() != R {
+                    return Err(Error::Model(format!(
+                        "output {i} has rank {}, expected {R}",
+                        data.shape.len()
+                    )));
+                }
+                Ok(burn_tensor::Tensor::from_data(data, device))
+            })
+            .collect()
+    }
+}
+
+impl<D: Device> Clone for BurnBlock<D> {
+    fn clone(&self) -> Self {
+        Self {
+            model: Arc::clone(&self.model),
+            device: self.device,
+        }
+    }
+}
+
+impl<D: Device> std::fmt::Debug for BurnBlock<D> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "BurnBlock<{}>({:?})", D::KEY, self.device)
+    }
+}
+
+// The model's weights live in libtorch, so Burn sees no parameters: records
+// are empty and device moves leave the block on its own device.
+impl<B: Backend, D: Device> Module<B> for BurnBlock<D> {
+    burn::constant!(module);
+}
+
+impl<B: AutodiffBackend, D: Device> AutodiffModule<B> for BurnBlock<D> {
+    burn::constant!(ad_module, BurnBlock<D>);
+}
+
+impl<D: Device> ModuleDisplayDefault for BurnBlock<D> {
+    fn content(&self, content: Content) -> Option<Content> {
+        content.add_formatted(&format!("{self:?}")).optional()
+    }
+}
+
+impl<D: Device> ModuleDisplay for BurnBlock<D> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tensor_data_round_trips() {
+        let data = TensorData::new(vec![1i64, 2, 3, 4, 5, 6], [2, 3]);
+        let t = DeviceTensor::<Cpu>::from_burn_data(&data).unwrap();
+        assert_eq!(t.size(), &[2, 3]);
+        assert_eq!(t.kind(), Kind::Int64);
+        assert_eq!(t.to_burn_data().unwrap(), data);
+    }
+
+    #[test]
+    fn quantized_kind_is_unsupported() {
+        assert!(matches!(
+            burn_dtype(Kind::QInt8),
+            Err(Error::UnsupportedDtype(_))
+        ));
+    }
+
+    #[cfg(feature = "fake")]
+    #[test]
+    fn composes_inside_a_burn_module() {
+        use burn::module::Param;
+        use burn_ndarray::NdArray;
+
+        use crate::fake::FakeModel;
+
+        type BurnTensor<B, const R: usize> = burn_tensor::Tensor<B, R>;
+
+        #[derive(Module, Debug)]
+        struct Shifted<B: Backend> {
+            bias: Param<BurnTensor<B, 1>>,
+            block: BurnBlock<Cpu>,
+        }
+
+        impl<B: Backend> Shifted<B> {
+            fn forward(&self, input: BurnTensor<B, 1>) -> BurnTensor<B, 1> {
+                let device = input.device();
+                let shifted = input + self.bias.val();
+                self.block
+                    .forward(vec![shifted], &device)
+                    .unwrap()
+                    .remove(0)
+            }
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let package = dir.path().join("model.pt2");
+        FakeModel::new()
+            .forward(|inputs, _| Ok(vec![inputs[0].f_mul_scalar(2)?]))
+            .write(&package, "model")
+            .unwrap();
+        let model = AOTIModel::<Cpu>::load(&package).unwrap();
+        let device = Default::default();
+        let shifted = Shifted::<NdArray> {
+            bias: Param::from_tensor(BurnTensor::ones([3], &device)),
+            block: BurnBlock::new(model, tch::Device::Cpu).unwrap(),
+        };
+        assert_eq!(shifted.num_params(), 3);
+        let shifted = shifted.to_device(&device);
+        let output = shifted.forward(BurnTensor::from_floats([1.0, 2.0, 3.0], &device));
+        assert_eq!(output.into_data(), TensorData::from([4.0f32, 6.0, 8.0]));
+    }
+}
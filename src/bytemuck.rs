@@ -0,0 +1,128 @@
+//! Byte-level access to tensor storage (feature `bytemuck`).
+//!
+//! [`DeviceTensor::<Cpu>::as_bytes`] and [`DeviceTensor::<Cpu>::as_slice`]
+//! borrow a contiguous host tensor's storage in place, so outputs can be
+//! written to a socket or file with no element-wise copy. Casts go through
+//! `bytemuck`, which checks size and alignment, and the element type must
+//! match the tensor's dtype exactly. Both are `unsafe`: the storage can be
+//! shared with other tensors, e.g. through `shallow_clone`, which could
+//! write to it while it is borrowed. Tensors on other devices, or
+//! non-contiguous ones, or any tensor whose storage may be written
+//! meanwhile, can be copied out with [`DeviceTensor::to_bytes`].
+
+use ::bytemuck::Pod;
+use tch::kind::Element;
+
+use crate::{Cpu, Device, DeviceTensor, Error};
+
+impl DeviceTensor<Cpu> {
+    /// Borrow the tensor's storage as raw bytes, in row-major order.
+    ///
+    /// Fails with [`Error::InvalidInput`] if the tensor is not contiguous.
+    ///
+    /// # Safety
+    ///
+    /// Nothing may write to the tensor's storage while the returned slice
+    /// is alive: not an in-place op on a tensor sharing it (a
+    /// `shallow_clone`, a view), nor a run it was donated to.
+    pub unsafe fn as_bytes(&self) -> Result<&[u8], Error> {
+        if !self.tensor.is_contiguous() {
+            return Err(Error::InvalidInput(
+                "cannot borrow a non-contiguous tensor as bytes; use to_bytes".into(),
+            ));
+        }
+        let len = self.tensor.numel() * self.tensor.f_kind()?.elt_size_in_bytes();
+        let ptr = self.tensor.data_ptr() as *const u8;
+        if len == 0 || ptr.is_null() {
+            return Ok(&[]);
+        }
+        // Safety: the tensor is a contiguous CPU tensor, so its `len` bytes
+        // start at `data_ptr` (which already includes the storage offset).
+        // The slice borrows `self`, keeping the storage alive, and the
+        // caller guarantees nothing writes to it meanwhile.
+        Ok(unsafe { std::slice::from_raw_parts(ptr, len) })
+    }
+
+    /// Borrow the tensor's storage as a slice of `T`, in row-major order.
+    ///
+    /// Fails with [`Error::TensorKindMismatch`] if `T` is not the tensor's
+    /// element type.
+    ///
+    /// # Safety
+    ///
+    /// As for [`DeviceTensor::as_bytes`]: nothing may write to the tensor's
+    /// storage while the returned slice is alive.
+    pub unsafe fn as_slice<T: Element + Pod>(&self) -> Result<&[T], Error> {
+        let found = self.tensor.f_kind()?;
+        if found != T::KIND {
+            return Err(Error::TensorKindMismatch {
+                expected: T::KIND,
+                found,
+            });
+        }
+        // Safety: the caller upholds `as_bytes`'s contract.
+        cast_slice(unsafe { self.as_bytes()? })
+    }
+}
+
+impl<D: Device> DeviceTensor<D> {
+    /// Copy the tensor's values, in row-major order, into a byte vector,
+    /// transferring it to host RAM first if necessary.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        let numel = self.tensor.numel();
+        let mut bytes = vec![0u8; numel * self.tensor.f_kind()?.elt_size_in_bytes()];
+        self.tensor
+            .f_contiguous()?
+            .f_copy_data_u8(&mut bytes, numel)?;
+        Ok(bytes)
+    }
+}
+
+/// Reinterpret `bytes` as a slice of `T`, failing with
+/// [`Error::InvalidInput`] if the length isn't a multiple of `T`'s size or
+/// the data isn't suitably aligned.
+pub fn cast_slice<T: Pod>(bytes: &[u8]) -> Result<&[T], Error> {
+    ::bytemuck::try_cast_slice(bytes).map_err(|e| {
+        Error::InvalidInput(format!(
+            "cannot view {} bytes as {}: {e}",
+            bytes.len(),
+            std::any::type_name::<T>()
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tch::Tensor;
+
+    #[test]
+    fn borrows_storage_without_copying() {
+        let t = DeviceTensor::<Cpu>::try_new(Tensor::from_slice(&[1.0f32, 2.0, 3.0])).unwrap();
+        // Safety: nothing else holds `t`'s storage.
+        unsafe {
+            assert_eq!(t.as_bytes().unwrap().len(), 12);
+            assert_eq!(t.as_slice::<f32>().unwrap(), &[1.0, 2.0, 3.0]);
+            assert_eq!(t.as_bytes().unwrap(), t.to_bytes().unwrap());
+            assert!(matches!(
+                t.as_slice::<i32>(),
+                Err(Error::TensorKindMismatch { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn transposed_tensor_must_be_copied() {
+        let t = Tensor::arange(6, (tch::Kind::Int64, tch::Device::Cpu))
+            .reshape([2, 3])
+            .tr();
+        let t = DeviceTensor::<Cpu>::try_new(t).unwrap();
+        // Safety: nothing else holds `t`'s storage.
+        assert!(matches!(
+            unsafe { t.as_bytes() },
+            Err(Error::InvalidInput(_))
+        ));
+        let bytes = t.to_bytes().unwrap();
+        assert_eq!(cast_slice::<u8>(&bytes).unwrap().len(), 48);
+    }
+}
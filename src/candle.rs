@@ -0,0 +1,122 @@
+//! Conversions between [`DeviceTensor`]s and `candle_core::Tensor` (feature
+//! `candle`).
+//!
+//! Both directions copy through host memory: candle and libtorch each own
+//! their allocations, and there is no shared deleter to hand one over to the
+//! other. Dtypes map one-to-one (`F32` ↔ `Float`, `BF16` ↔ `BFloat16`, ...);
+//! dtypes without a counterpart on the other side (candle's `U32` and
+//! sub-byte floats, torch's `Bool`/`Int8`/complex kinds) fail with
+//! [`Error::UnsupportedDtype`] rather than being cast.
+
+use std::marker::PhantomData;
+
+use candle_core::{DType, WithDType};
+use half::{bf16, f16};
+use tch::kind::Element;
+use tch::{Kind, Tensor};
+
+use crate::{Cpu, Device, DeviceTensor, Error};
+
+fn candle_to_tch<T: Element + WithDType>(tensor: &candle_core::Tensor) -> Result<Tensor, Error> {
+    let data = tensor.flatten_all()?.to_vec1::<T>()?;
+    let shape: Vec<i64> = tensor.dims().iter().map(|&d| d as i64).collect();
+    Ok(Tensor::f_from_slice(&data)?.f_reshape(&shape)?)
+}
+
+fn tch_to_candle<T: Element + WithDType>(
+    tensor: &Tensor,
+    device: &candle_core::Device,
+) -> Result<candle_core::Tensor, Error> {
+    let numel = tensor.numel();
+    let mut data = vec![T::ZERO; numel];
+    tensor.f_contiguous()?.f_copy_data(&mut data, numel)?;
+    let shape: Vec<usize> = tensor.size().iter().map(|&d| d as usize).collect();
+    Ok(candle_core::Tensor::from_vec(data, shape, device)?)
+}
+
+impl DeviceTensor<Cpu> {
+    /// Copy a candle tensor (on any candle device) into a new CPU tensor
+    /// with the equivalent dtype.
+    pub fn from_candle(tensor: &candle_core::Tensor) -> Result<Self, Error> {
+        let tensor = match tensor.dtype() {
+            DType::U8 => candle_to_tch::<u8>(tensor)?,
+            DType::I16 => candle_to_tch::<i16>(tensor)?,
+            DType::I32 => candle_to_tch::<i32>(tensor)?,
+            DType::I64 => candle_to_tch::<i64>(tensor)?,
+            DType::BF16 => candle_to_tch::<bf16>(tensor)?,
+            DType::F16 => candle_to_tch::<f16>(tensor)?,
+            DType::F32 => candle_to_tch::<f32>(tensor)?,
+            DType::F64 => candle_to_tch::<f64>(tensor)?,
+            other => return Err(Error::UnsupportedDtype(format!("candle {other:?}"))),
+        };
+        Ok(Self {
+            tensor,
+            _device: PhantomData,
+        })
+    }
+}
+
+impl<D: Device> DeviceTensor<D> {
+    /// Copy the tensor into a new candle tensor on `device`.
+    pub fn to_candle(&self, device: &candle_core::Device) -> Result<candle_core::Tensor, Error> {
+        match self.tensor.f_kind()? {
+            Kind::Uint8 => tch_to_candle::<u8>(&self.tensor, device),
+            Kind::Int16 => tch_to_candle::<i16>(&self.tensor, device),
+            Kind::Int => tch_to_candle::<i32>(&self.tensor, device),
+            Kind::Int64 => tch_to_candle::<i64>(&self.tensor, device),
+            Kind::BFloat16 => tch_to_candle::<bf16>(&self.tensor, device),
+            Kind::Half => tch_to_candle::<f16>(&self.tensor, device),
+            Kind::Float => tch_to_candle::<f32>(&self.tensor, device),
+            Kind::Double => tch_to_candle::<f64>(&self.tensor, device),
+            other => Err(Error::UnsupportedDtype(format!("torch {other:?}"))),
+        }
+    }
+}
+
+impl TryFrom<&candle_core::Tensor> for DeviceTensor<Cpu> {
+    type Error = Error;
+
+    fn try_from(tensor: &candle_core::Tensor) -> Result<Self, Error> {
+        Self::from_candle(tensor)
+    }
+}
+
+/// Copies to a candle tensor on `candle_core::Device::Cpu`; use
+/// [`DeviceTensor::to_candle`] to pick another candle device.
+impl<D: Device> TryFrom<&DeviceTensor<D>> for candle_core::Tensor {
+    type Error = Error;
+
+    fn try_from(tensor: &DeviceTensor<D>) -> Result<Self, Error> {
+        tensor.to_candle(&candle_core::Device::Cpu)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_through_candle() {
+        let c = candle_core::Tensor::arange(0f32, 6f32, &candle_core::Device::Cpu)
+            .unwrap()
+            .reshape((2, 3))
+            .unwrap();
+        let t = DeviceTensor::<Cpu>::from_candle(&c).unwrap();
+        assert_eq!(t.size(), &[2, 3]);
+        assert_eq!(t.kind(), Kind::Float);
+
+        let back = candle_core::Tensor::try_from(&t).unwrap();
+        assert_eq!(back.dims(), &[2, 3]);
+        assert_eq!(back.to_vec2::<f32>().unwrap(), c.to_vec2::<f32>().unwrap());
+    }
+
+    #[test]
+    fn bool_tensor_is_unsupported() {
+        let t = DeviceTensor::<Cpu>::try_new(Tensor::zeros([2], (Kind::Bool, tch::Device::Cpu)))
+            .unwrap();
+        assert!(matches!(
+            t.to_candle(&candle_core::Device::Cpu),
+            Err(Error::UnsupportedDtype(_))
+        ));
+    }
+}
@@ -0,0 +1,513 @@
+//! C API (feature `capi`).
+//!
+//! Exposes package loading and inference to C, C++, Go, and anything else
+//! with a C FFI, reusing this crate's Zip64-safe extraction, device
+//! dispatch, and ownership handling. The declarations live in
+//! `include/aoti_rs.h`; build the shared library with
+//!
+//! ```text
+//! cargo rustc --release --features capi --crate-type cdylib
+//! ```
+//!
+//! Conventions:
+//!
+//! - Every fallible function returns an [`AotiStatus`]; on failure,
+//!   [`aoti_rs_last_error`] describes the error on the calling thread.
+//! - Models and output sets are opaque handles, released with
+//!   [`aoti_rs_free`] and [`aoti_rs_outputs_free`].
+//! - Input tensors are host buffers described by [`AotiTensorView`]; they
+//!   are copied (and uploaded to the model's device) before the run, so the
+//!   caller keeps ownership. Outputs are returned in host memory, contiguous,
+//!   and stay valid until their output set is freed.
+//! - Panics never unwind into the caller; they are reported as
+//!   [`AotiStatus::Panic`].
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString, c_char, c_void};
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::path::Path;
+
+use tch::{Kind, Tensor};
+
+use crate::{AOTIModel, AnyAOTIModel, Device, Error};
+
+/// Result code of every fallible C API call.
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AotiStatus {
+    Ok = 0,
+    /// A pointer was null, a string wasn't UTF-8, or a tensor description
+    /// was malformed.
+    InvalidArgument = 1,
+    /// Loading or running the model failed.
+    Error = 2,
+    /// Rust code panicked; the handle involved should not be reused.
+    Panic = 3,
+}
+
+/// Element type of a tensor crossing the C API. The values are part of the
+/// ABI and will not change.
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AotiDtype {
+    Float32 = 0,
+    Float64 = 1,
+    Float16 = 2,
+    BFloat16 = 3,
+    Int8 = 4,
+    Int16 = 5,
+    Int32 = 6,
+    Int64 = 7,
+    UInt8 = 8,
+    Bool = 9,
+}
+
+impl AotiDtype {
+    fn from_raw(raw: i32) -> Option<Self> {
+        use AotiDtype::*;
+        [
+            Float32, Float64, Float16, BFloat16, Int8, Int16, Int32, Int64, UInt8, Bool,
+        ]
+        .into_iter()
+        .find(|&d| d as i32 == raw)
+    }
+
+    fn kind(self) -> Kind {
+        match self {
+            AotiDtype::Float32 => Kind::Float,
+            AotiDtype::Float64 => Kind::Double,
+            AotiDtype::Float16 => Kind::Half,
+            AotiDtype::BFloat16 => Kind::BFloat16,
+            AotiDtype::Int8 => Kind::Int8,
+            AotiDtype::Int16 => Kind::Int16,
+            AotiDtype::Int32 => Kind::Int,
+            AotiDtype::Int64 => Kind::Int64,
+            AotiDtype::UInt8 => Kind::Uint8,
+            AotiDtype::Bool => Kind::Bool,
+        }
+    }
+
+    fn from_kind(kind: Kind) -> Result<Self, Error> {
+        Ok(match kind {
+            Kind::Float => AotiDtype::Float32,
+            Kind::Double => AotiDtype::Float64,
+            Kind::Half => AotiDtype::Float16,
+            Kind::BFloat16 => AotiDtype::BFloat16,
+            Kind::Int8 => AotiDtype::Int8,
+            Kind::Int16 => AotiDtype::Int16,
+            Kind::Int => AotiDtype::Int32,
+            Kind::Int64 => AotiDtype::Int64,
+            Kind::Uint8 => AotiDtype::UInt8,
+            Kind::Bool => AotiDtype::Bool,
+            other => return Err(Error::UnsupportedDtype(format!("torch {other:?}"))),
+        })
+    }
+}
+
+/// A dense, row-major host tensor: `data` points at
+/// `product(shape) * sizeof(dtype)` bytes.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct AotiTensorView {
+    pub data: *const c_void,
+    /// An [`AotiDtype`] value.
+    pub dtype: i32,
+    pub shape: *const i64,
+    pub ndim: usize,
+}
+
+/// Opaque handle to a loaded model.
+pub struct AotiModel(AnyAOTIModel);
+
+/// Opaque handle to the outputs of one run.
+pub struct AotiOutputs {
+    tensors: Vec<Tensor>,
+    shapes: Vec<Vec<i64>>,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    let message = CString::new(message.replace('\0', " ")).expect("NULs were replaced");
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(message));
+}
+
+/// Run `f`, translating errors and panics into a status code.
+fn guard(f: impl FnOnce() -> Result<(), (AotiStatus, String)>) -> AotiStatus {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => AotiStatus::Ok,
+        Ok(Err((status, message))) => {
+            set_last_error(message);
+            status
+        }
+        Err(panic) => {
+            let message = panic
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".into());
+            set_last_error(format!("panic: {message}"));
+            AotiStatus::Panic
+        }
+    }
+}
+
+fn invalid(message: impl Into<String>) -> (AotiStatus, String) {
+    (AotiStatus::InvalidArgument, message.into())
+}
+
+fn failed(error: Error) -> (AotiStatus, String) {
+    (AotiStatus::Error, error.to_string())
+}
+
+/// # Safety
+/// `ptr` must be null or a valid NUL-terminated string.
+unsafe fn str_arg<'a>(ptr: *const c_char, name: &str) -> Result<&'a str, (AotiStatus, String)> {
+    if ptr.is_null() {
+        return Err(invalid(format!("{name} is null")));
+    }
+    // Safety: guaranteed by the caller.
+    unsafe { CStr::from_ptr(ptr) }
+        .to_str()
+        .map_err(|_| invalid(format!("{name} is not valid UTF-8")))
+}
+
+/// Filesystem paths are raw bytes on unix, so only other platforms require
+/// UTF-8.
+///
+/// # Safety
+/// `ptr` must be null or a valid NUL-terminated string.
+unsafe fn path_arg<'a>(ptr: *const c_char, name: &str) -> Result<&'a Path, (AotiStatus, String)> {
+    #[cfg(unix)]
+    {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+        if ptr.is_null() {
+            return Err(invalid(format!("{name} is null")));
+        }
+        // Safety: guaranteed by the caller.
+        let bytes = unsafe { CStr::from_ptr(ptr) }.to_bytes();
+        Ok(Path::new(OsStr::from_bytes(bytes)))
+    }
+    #[cfg(not(unix))]
+    {
+        // Safety: guaranteed by the caller.
+        unsafe { str_arg(ptr, name) }.map(Path::new)
+    }
+}
+
+/// # Safety
+/// `view` must satisfy the [`AotiTensorView`] contract.
+unsafe fn view_to_tensor(view: &AotiTensorView) -> Result<Tensor, (AotiStatus, String)> {
+    let dtype = AotiDtype::from_raw(view.dtype)
+        .ok_or_else(|| invalid(format!("unknown dtype {}", view.dtype)))?;
+    if view.ndim > 0 && view.shape.is_null() {
+        return Err(invalid("shape is null"));
+    }
+    let shape: &[i64] = if view.ndim == 0 {
+        &[]
+    } else {
+        // Safety: guaranteed by the caller.
+        unsafe { std::slice::from_raw_parts(view.shape, view.ndim) }
+    };
+    // A slice may span at most `isize::MAX` bytes.
+    let len = shape
+        .iter()
+        .try_fold(1usize, |acc, &d| {
+            usize::try_from(d).ok().and_then(|d| acc.checked_mul(d))
+        })
+        .and_then(|numel| numel.checked_mul(dtype.kind().elt_size_in_bytes()))
+        .filter(|&len| isize::try_from(len).is_ok())
+        .ok_or_else(|| invalid(format!("invalid shape {shape:?}")))?;
+    if len > 0 && view.data.is_null() {
+        return Err(invalid("data is null"));
+    }
+    let bytes: &[u8] = if len == 0 {
+        &[]
+    } else {
+        // Safety: guaranteed by the caller.
+        unsafe { std::slice::from_raw_parts(view.data as *const u8, len) }
+    };
+    Tensor::f_from_data_size(bytes, shape, dtype.kind()).map_err(|e| failed(e.into()))
+}
+
+fn run_typed<D: Device>(model: &mut AOTIModel<D>, inputs: &[Tensor]) -> Result<Vec<Tensor>, Error> {
+    let inputs: Vec<_> = inputs.iter().map(|t| model.upload(t)).collect();
+    model
+        .run(inputs)?
+        .into_iter()
+        .map(|out| Ok(out.f_to_device(tch::Device::Cpu)?.f_contiguous()?))
+        .collect()
+}
+
+/// Load the model called `model_name` (or `"model"` if null) from the
+/// `.pt2` package at `path`, on the device its metadata names. On Unix
+/// `path` is taken as raw bytes; elsewhere it must be UTF-8.
+///
+/// # Safety
+/// `path` and `model_name` must be null or valid NUL-terminated strings,
+/// and `out` must be valid for writing a pointer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn aoti_rs_load(
+    path: *const c_char,
+    model_name: *const c_char,
+    out: *mut *mut AotiModel,
+) -> AotiStatus {
+    guard(|| {
+        if out.is_null() {
+            return Err(invalid("out is null"));
+        }
+        // Safety: guaranteed by the caller.
+        let path = unsafe { path_arg(path, "path") }?;
+        let model_name = if model_name.is_null() {
+            "model"
+        } else {
+            // Safety: guaranteed by the caller.
+            unsafe { str_arg(model_name, "model_name") }?
+        };
+        let model = AnyAOTIModel::load_named(path, model_name).map_err(failed)?;
+        // Safety: `out` was checked above and is writable per the contract.
+        unsafe { *out = Box::into_raw(Box::new(AotiModel(model))) };
+        Ok(())
+    })
+}
+
+/// Release a model returned by [`aoti_rs_load`]. Null is ignored.
+///
+/// # Safety
+/// `model` must be null or a handle from [`aoti_rs_load`] that hasn't been
+/// freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn aoti_rs_free(model: *mut AotiModel) {
+    if !model.is_null() {
+        // Safety: guaranteed by the caller.
+        drop(unsafe { Box::from_raw(model) });
+    }
+}
+
+/// Run the model on `num_inputs` host tensors, storing a new output set in
+/// `*out`.
+///
+/// # Safety
+/// `model` must be a live handle not used concurrently from another thread,
+/// `inputs` must point at `num_inputs` valid [`AotiTensorView`]s (or be
+/// null if `num_inputs` is 0), and `out` must be valid for writing a
+/// pointer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn aoti_rs_run(
+    model: *mut AotiModel,
+    inputs: *const AotiTensorView,
+    num_inputs: usize,
+    out: *mut *mut AotiOutputs,
+) -> AotiStatus {
+    guard(|| {
+        if model.is_null() || out.is_null() || (inputs.is_null() && num_inputs > 0) {
+            return Err(invalid("model, inputs, or out is null"));
+        }
+        let views: &[AotiTensorView] = if num_inputs == 0 {
+            &[]
+        } else {
+            // Safety: guaranteed by the caller.
+            unsafe { std::slice::from_raw_parts(inputs, num_inputs) }
+        };
+        let tensors = views
+            .iter()
+            // Safety: guaranteed by the caller.
+            .map(|v| unsafe { view_to_tensor(v) })
+            .collect::<Result<Vec<_>, _>>()?;
+        // Safety: `model` was checked above and is live per the contract.
+        let model = unsafe { &mut *model };
+        let outputs = match &mut model.0 {
+            AnyAOTIModel::Cpu(m) => run_typed(m, &tensors),
+            #[cfg(aoti_cuda)]
+            AnyAOTIModel::Cuda(m) => run_typed(m, &tensors),
+        }
+        .map_err(failed)?;
+        let shapes = outputs.iter().map(Tensor::size).collect();
+        // Safety: `out` was checked above and is writable per the contract.
+        unsafe {
+            *out = Box::into_raw(Box::new(AotiOutputs {
+                tensors: outputs,
+                shapes,
+            }))
+        };
+        Ok(())
+    })
+}
+
+/// Number of tensors in an output set (0 for null).
+///
+/// # Safety
+/// `outputs` must be null or a live handle from [`aoti_rs_run`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn aoti_rs_outputs_len(outputs: *const AotiOutputs) -> usize {
+    // Safety: guaranteed by the caller.
+    unsafe { outputs.as_ref() }.map_or(0, |o| o.tensors.len())
+}
+
+/// Describe output `index` in `*view`. The view borrows from the output set
+/// and is valid until [`aoti_rs_outputs_free`].
+///
+/// # Safety
+/// `outputs` must be a live handle from [`aoti_rs_run`] and `view` must be
+/// valid for writing an [`AotiTensorView`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn aoti_rs_output_get(
+    outputs: *const AotiOutputs,
+    index: usize,
+    view: *mut AotiTensorView,
+) -> AotiStatus {
+    guard(|| {
+        // Safety: guaranteed by the caller.
+        let Some(outputs) = (unsafe { outputs.as_ref() }) else {
+            return Err(invalid("outputs is null"));
+        };
+        if view.is_null() {
+            return Err(invalid("view is null"));
+        }
+        let (Some(tensor), Some(shape)) = (outputs.tensors.get(index), outputs.shapes.get(index))
+        else {
+            return Err(invalid(format!(
+                "output index {index} out of range for {} outputs",
+                outputs.tensors.len()
+            )));
+        };
+        let dtype = AotiDtype::from_kind(tensor.kind()).map_err(failed)?;
+        // Safety: `view` was checked above and is writable per the contract.
+        unsafe {
+            *view = AotiTensorView {
+                data: tensor.data_ptr() as *const c_void,
+                dtype: dtype as i32,
+                shape: shape.as_ptr(),
+                ndim: shape.len(),
+            }
+        };
+        Ok(())
+    })
+}
+
+/// Release an output set. Null is ignored.
+///
+/// # Safety
+/// `outputs` must be null or a handle from [`aoti_rs_run`] that hasn't been
+/// freed; views obtained from it become dangling.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn aoti_rs_outputs_free(outputs: *mut AotiOutputs) {
+    if !outputs.is_null() {
+        // Safety: guaranteed by the caller.
+        drop(unsafe { Box::from_raw(outputs) });
+    }
+}
+
+/// Store the model's metadata as a JSON object string in `*out`, to be
+/// released with [`aoti_rs_string_free`].
+///
+/// # Safety
+/// `model` must be a live handle and `out` must be valid for writing a
+/// pointer.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn aoti_rs_metadata_json(
+    model: *const AotiModel,
+    out: *mut *mut c_char,
+) -> AotiStatus {
+    guard(|| {
+        // Safety: guaranteed by the caller.
+        let Some(model) = (unsafe { model.as_ref() }) else {
+            return Err(invalid("model is null"));
+        };
+        if out.is_null() {
+            return Err(invalid("out is null"));
+        }
+        let metadata = match &model.0 {
+            AnyAOTIModel::Cpu(m) => m.get_metadata(),
+            #[cfg(aoti_cuda)]
+            AnyAOTIModel::Cuda(m) => m.get_metadata(),
+        }
+        .map_err(failed)?;
+        let json = serde_json::to_string(&metadata).map_err(|e| failed(e.into()))?;
+        let json = CString::new(json).map_err(|_| invalid("metadata contains NUL"))?;
+        // Safety: `out` was checked above and is writable per the contract.
+        unsafe { *out = json.into_raw() };
+        Ok(())
+    })
+}
+
+/// Release a string returned by this API. Null is ignored.
+///
+/// # Safety
+/// `s` must be null or a string from this API that hasn't been freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn aoti_rs_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        // Safety: guaranteed by the caller.
+        drop(unsafe { CString::from_raw(s) });
+    }
+}
+
+/// The message of the last error on this thread, or null if there was
+/// none. Valid until the next failing call on the same thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn aoti_rs_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(std::ptr::null(), |s| s.as_ptr()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_reports_missing_package() {
+        let path = CString::new("/nonexistent/model.pt2").unwrap();
+        let mut model = std::ptr::null_mut();
+        let status = unsafe { aoti_rs_load(path.as_ptr(), std::ptr::null(), &mut model) };
+        assert_eq!(status, AotiStatus::Error);
+        assert!(model.is_null());
+        let message = unsafe { CStr::from_ptr(aoti_rs_last_error()) };
+        assert!(!message.to_bytes().is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn load_accepts_non_utf8_paths() {
+        let path = CString::new(b"/nonexistent/model-\xff.pt2".to_vec()).unwrap();
+        let mut model = std::ptr::null_mut();
+        let status = unsafe { aoti_rs_load(path.as_ptr(), std::ptr::null(), &mut model) };
+        assert_eq!(status, AotiStatus::Error);
+        assert!(model.is_null());
+        let message = unsafe { CStr::from_ptr(aoti_rs_last_error()) };
+        assert!(!message.to_string_lossy().contains("UTF-8"));
+    }
+
+    #[test]
+    fn views_become_tensors() {
+        let data = [1i32, 2, 3, 4, 5, 6];
+        let shape = [2i64, 3];
+        let view = AotiTensorView {
+            data: data.as_ptr() as *const c_void,
+            dtype: AotiDtype::Int32 as i32,
+            shape: shape.as_ptr(),
+            ndim: 2,
+        };
+        let t = unsafe { view_to_tensor(&view) }.unwrap();
+        assert_eq!(t.size(), &[2, 3]);
+        assert_eq!(t.int64_value(&[1, 2]), 6);
+
+        let bad = AotiTensorView { dtype: 42, ..view };
+        assert!(matches!(
+            unsafe { view_to_tensor(&bad) },
+            Err((AotiStatus::InvalidArgument, _))
+        ));
+        // The byte count overflows before any data is read.
+        let huge = [1i64 << 62, 8];
+        let overflowing = AotiTensorView {
+            shape: huge.as_ptr(),
+            ..view
+        };
+        assert!(matches!(
+            unsafe { view_to_tensor(&overflowing) },
+            Err((AotiStatus::InvalidArgument, _))
+        ));
+    }
+}
@@ -0,0 +1,203 @@
+//! Post-processing for classifiers: softmax, top-k, and label lookup.
+//!
+//! Logits are `[classes]` for a single example or `[N, classes]` for a
+//! batch, on any device; results are always copied back to the host.
+
+use std::path::Path;
+
+use tch::{Kind, Tensor};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{Device, DeviceTensor, Error};
+
+/// One ranked class.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Prediction {
+    pub index: usize,
+    pub label: String,
+    pub score: f32,
+}
+
+/// Class names, indexed by class id.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Labels(Vec<String>);
+
+impl Labels {
+    pub fn new(names: Vec<String>) -> Self {
+        Self(names)
+    }
+
+    /// Load labels from a file.
+    ///
+    /// `.json` files may hold either an array of names or an object mapping
+    /// ids to names (a Hugging Face `id2label`); anything else is read as
+    /// one name per line.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)?;
+        if path.extension().is_some_and(|e| e == "json") {
+            return Self::from_json(&text);
+        }
+        Ok(Self(
+            text.lines()
+                .map(str::trim_end)
+                .filter(|l| !l.is_empty())
+                .map(String::from)
+                .collect(),
+        ))
+    }
+
+    fn from_json(text: &str) -> Result<Self, Error> {
+        match serde_json::from_str(text)? {
+            serde_json::Value::Array(names) => names
+                .into_iter()
+                .map(|v| match v {
+                    serde_json::Value::String(s) => Ok(s),
+                    other => Err(Error::InvalidInput(format!(
+                        "label {other} is not a string"
+                    ))),
+                })
+                .collect::<Result<_, _>>()
+                .map(Self),
+            serde_json::Value::Object(map) => {
+                let mut entries = map
+                    .into_iter()
+                    .map(|(id, name)| {
+                        let id: usize = id.parse().map_err(|_| {
+                            Error::InvalidInput(format!("label id '{id}' is not an index"))
+                        })?;
+                        match name {
+                            serde_json::Value::String(s) => Ok((id, s)),
+                            other => Err(Error::InvalidInput(format!(
+                                "label {other} is not a string"
+                            ))),
+                        }
+                    })
+                    .collect::<Result<Vec<_>, Error>>()?;
+                entries.sort_by_key(|&(id, _)| id);
+                if entries.iter().enumerate().any(|(i, &(id, _))| i != id) {
+                    return Err(Error::InvalidInput(
+                        "label ids must be 0..n without gaps".into(),
+                    ));
+                }
+                Ok(Self(entries.into_iter().map(|(_, name)| name).collect()))
+            }
+            _ => Err(Error::InvalidInput(
+                "label JSON must be an array or an object".into(),
+            )),
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Option<&str> {
+        self.0.get(index).map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Softmax `logits` and return the `k` most likely labels of each
+    /// example, best first.
+    ///
+    /// Fails with [`Error::InvalidInput`] if the number of classes differs
+    /// from the number of labels.
+    pub fn classify<D: Device>(
+        &self,
+        logits: &DeviceTensor<D>,
+        k: usize,
+    ) -> Result<Vec<Vec<Prediction>>, Error> {
+        let classes = logits.size().last().copied().unwrap_or(0);
+        if classes as usize != self.len() {
+            return Err(Error::InvalidInput(format!(
+                "logits have {classes} classes but there are {} labels",
+                self.len()
+            )));
+        }
+        Ok(top_k(&softmax(logits)?, k)?
+            .into_iter()
+            .map(|row| {
+                row.into_iter()
+                    .map(|(index, score)| Prediction {
+                        index,
+                        label: self.0[index].clone(),
+                        score,
+                    })
+                    .collect()
+            })
+            .collect())
+    }
+}
+
+/// Softmax over the last dimension, computed in `Float`.
+pub fn softmax(logits: &Tensor) -> Result<Tensor, Error> {
+    Ok(logits.f_softmax(-1, Kind::Float)?)
+}
+
+/// The `k` largest entries along the last dimension of a `[classes]` or
+/// `[N, classes]` tensor, as `(class, value)` pairs per example, largest
+/// first. `k` is clamped to the number of classes.
+pub fn top_k(scores: &Tensor, k: usize) -> Result<Vec<Vec<(usize, f32)>>, Error> {
+    let scores = match scores.dim() {
+        1 => scores.f_unsqueeze(0)?,
+        2 => scores.shallow_clone(),
+        n => {
+            return Err(Error::InvalidInput(format!(
+                "expected [classes] or [N, classes] scores, got {n} dimensions"
+            )));
+        }
+    };
+    let classes = scores.size()[1];
+    let k = (k as i64).min(classes);
+    let (values, indices) = scores
+        .f_to_device(tch::Device::Cpu)?
+        .f_to_kind(Kind::Float)?
+        .f_topk(k, -1, true, true)?;
+    let rows = values.size()[0];
+    let values = Vec::<f32>::try_from(values.f_contiguous()?.f_view([-1])?)?;
+    let indices = Vec::<i64>::try_from(indices.f_contiguous()?.f_view([-1])?)?;
+    Ok((0..rows as usize)
+        .map(|row| {
+            let span = row * k as usize..(row + 1) * k as usize;
+            indices[span.clone()]
+                .iter()
+                .zip(&values[span])
+                .map(|(&i, &v)| (i as usize, v))
+                .collect()
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Cpu;
+
+    #[test]
+    fn classifies_batch() {
+        let labels = Labels::new(vec!["cat".into(), "dog".into(), "bird".into()]);
+        let logits = Tensor::from_slice(&[0.0f32, 2.0, 1.0, 3.0, 0.0, 0.0]).view([2, 3]);
+        let logits = DeviceTensor::<Cpu>::try_new(logits).unwrap();
+
+        let preds = labels.classify(&logits, 2).unwrap();
+        assert_eq!(preds.len(), 2);
+        assert_eq!(preds[0][0].label, "dog");
+        assert_eq!(preds[0][1].label, "bird");
+        assert_eq!(preds[1][0].label, "cat");
+        assert!(preds[1][0].score > 0.9);
+    }
+
+    #[test]
+    fn id2label_json_is_ordered_by_id() {
+        let labels = Labels::from_json(r#"{"1": "dog", "0": "cat"}"#).unwrap();
+        assert_eq!(labels.get(0), Some("cat"));
+        assert_eq!(labels.get(1), Some("dog"));
+        assert!(Labels::from_json(r#"{"0": "cat", "2": "dog"}"#).is_err());
+    }
+}
@@ -0,0 +1,552 @@
+//! Tolerance-aware comparison of inference outputs, as shadow deployments,
+//! [goldens](crate::golden) and `aoti-compare` check them.
+//!
+//! Elements are compared as `torch.isclose` does,
+//! `|actual - expected| <= atol + rtol * |expected|`, in double precision
+//! and with NaNs equal to each other. Outputs whose shape or dtype differs
+//! aren't compared element-wise at all.
+//!
+//! [`compare_models`] runs two models (or pools) on the same inputs and
+//! compares their outputs, for canary checks before promoting a new
+//! package.
+//!
+//! Where storing expected outputs is too costly, [`content_hash`] reduces
+//! one to a stable hash of its shape, dtype and rounded values, so CI can
+//! notice drift by comparing hashes.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::marker::PhantomData;
+
+use tch::{Kind, Tensor};
+
+use crate::generate::Run;
+use crate::{Device, DeviceTensor, Error};
+
+/// Element-wise closeness as in `torch.allclose`:
+/// `|actual - expected| <= atol + rtol * |expected|`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tolerance {
+    pub rtol: f64,
+    pub atol: f64,
+}
+
+impl Default for Tolerance {
+    /// PyTorch's defaults, `rtol = 1e-5` and `atol = 1e-8`.
+    fn default() -> Self {
+        Self {
+            rtol: 1e-5,
+            atol: 1e-8,
+        }
+    }
+}
+
+impl Tolerance {
+    /// Only equal values match.
+    pub const EXACT: Self = Self {
+        rtol: 0.0,
+        atol: 0.0,
+    };
+
+    /// `torch.testing.assert_close`'s default for `kind`: looser for the
+    /// 16-bit floats, exact for integers and booleans.
+    pub fn for_kind(kind: Kind) -> Self {
+        let (rtol, atol) = match kind {
+            Kind::Half | Kind::ComplexHalf => (1e-3, 1e-5),
+            Kind::BFloat16 => (1.6e-2, 1e-5),
+            Kind::Float | Kind::ComplexFloat => (1.3e-6, 1e-5),
+            Kind::Double | Kind::ComplexDouble => (1e-7, 1e-7),
+            _ => (0.0, 0.0),
+        };
+        Self { rtol, atol }
+    }
+}
+
+/// A [`Tolerance`] per dtype. The default uses [`Tolerance::for_kind`]
+/// for every dtype; converting a single [`Tolerance`] uses it for all.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Tolerances {
+    fallback: Option<Tolerance>,
+    kinds: HashMap<Kind, Tolerance>,
+}
+
+impl Tolerances {
+    /// `torch.testing.assert_close`'s defaults.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use `tolerance` for outputs of dtype `kind`.
+    pub fn kind(mut self, kind: Kind, tolerance: Tolerance) -> Self {
+        self.kinds.insert(kind, tolerance);
+        self
+    }
+
+    /// The tolerance for outputs of dtype `kind`.
+    pub fn get(&self, kind: Kind) -> Tolerance {
+        self.kinds
+            .get(&kind)
+            .copied()
+            .or(self.fallback)
+            .unwrap_or_else(|| Tolerance::for_kind(kind))
+    }
+}
+
+impl From<Tolerance> for Tolerances {
+    fn from(tolerance: Tolerance) -> Self {
+        Self {
+            fallback: Some(tolerance),
+            kinds: HashMap::new(),
+        }
+    }
+}
+
+/// How the values of two outputs of the same shape and dtype compared.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValueDiff {
+    /// Elements out of tolerance.
+    pub mismatched: i64,
+    pub numel: i64,
+    pub max_abs_diff: f64,
+    pub mean_abs_diff: f64,
+    /// Largest `|actual - expected| / |expected|`.
+    pub max_rel_diff: f64,
+    /// The first out-of-tolerance element in row-major order: its index,
+    /// expected and actual value.
+    pub first_mismatch: Option<(Vec<i64>, f64, f64)>,
+}
+
+/// How one output pair compared.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputDiff {
+    pub index: usize,
+    pub expected: (Kind, Vec<i64>),
+    pub actual: (Kind, Vec<i64>),
+    pub tolerance: Tolerance,
+    /// `None` if the shapes or dtypes differ.
+    pub values: Option<ValueDiff>,
+}
+
+impl OutputDiff {
+    pub fn passed(&self) -> bool {
+        matches!(&self.values, Some(v) if v.mismatched == 0)
+    }
+}
+
+impl fmt::Display for OutputDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let i = self.index;
+        let Some(values) = &self.values else {
+            let ((ek, es), (ak, as_)) = (&self.expected, &self.actual);
+            return write!(f, "output {i} is {ak:?} {as_:?}, expected {ek:?} {es:?}");
+        };
+        if values.mismatched == 0 {
+            return write!(
+                f,
+                "output {i} matches (max abs diff {:e})",
+                values.max_abs_diff
+            );
+        }
+        write!(
+            f,
+            "output {i} differs in {} of {} elements by up to {:e} (max rel {:e}; rtol {}, atol {})",
+            values.mismatched,
+            values.numel,
+            values.max_abs_diff,
+            values.max_rel_diff,
+            self.tolerance.rtol,
+            self.tolerance.atol
+        )?;
+        if let Some((index, expected, actual)) = &values.first_mismatch {
+            write!(f, "; first at {index:?}: expected {expected}, got {actual}")?;
+        }
+        Ok(())
+    }
+}
+
+/// How two lists of outputs compared.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comparison {
+    pub expected_outputs: usize,
+    pub actual_outputs: usize,
+    /// The pairs both lists have.
+    pub outputs: Vec<OutputDiff>,
+}
+
+impl Comparison {
+    pub fn passed(&self) -> bool {
+        self.expected_outputs == self.actual_outputs && self.outputs.iter().all(OutputDiff::passed)
+    }
+
+    /// The largest absolute difference over the outputs compared
+    /// element-wise.
+    pub fn max_abs_diff(&self) -> f64 {
+        self.outputs
+            .iter()
+            .filter_map(|o| o.values.as_ref())
+            .map(|v| v.max_abs_diff)
+            .fold(0.0, f64::max)
+    }
+}
+
+impl fmt::Display for Comparison {
+    /// Every way the outputs differ, one per line, or a summary if they
+    /// don't.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.passed() {
+            return write!(
+                f,
+                "all {} outputs match (max abs diff {:e})",
+                self.outputs.len(),
+                self.max_abs_diff()
+            );
+        }
+        let mut lines = Vec::new();
+        if self.expected_outputs != self.actual_outputs {
+            lines.push(format!(
+                "{} outputs, expected {}",
+                self.actual_outputs, self.expected_outputs
+            ));
+        }
+        lines.extend(
+            self.outputs
+                .iter()
+                .filter(|o| !o.passed())
+                .map(ToString::to_string),
+        );
+        f.write_str(&lines.join("\n"))
+    }
+}
+
+/// Compare output `index`, `actual`, with the `expected` one.
+pub fn compare_tensors(
+    index: usize,
+    expected: &Tensor,
+    actual: &Tensor,
+    tolerance: Tolerance,
+) -> Result<OutputDiff, Error> {
+    let mut diff = OutputDiff {
+        index,
+        expected: (expected.kind(), expected.size()),
+        actual: (actual.kind(), actual.size()),
+        tolerance,
+        values: None,
+    };
+    if diff.expected != diff.actual {
+        return Ok(diff);
+    }
+    let (e, a) = (
+        expected.f_to_kind(Kind::Double)?,
+        actual.f_to_kind(Kind::Double)?,
+    );
+    let close = a.f_isclose(&e, tolerance.rtol, tolerance.atol, true)?;
+    let numel = e.numel() as i64;
+    let mismatched = numel - close.f_sum(Kind::Int64)?.f_int64_value(&[])?;
+    let (max_abs_diff, mean_abs_diff, max_rel_diff) = if numel == 0 {
+        (0.0, 0.0, 0.0)
+    } else {
+        // NaNs compared with NaNs count as no difference.
+        let abs = a.f_sub(&e)?.f_abs()?.f_nan_to_num(0.0, None, None)?;
+        let rel = abs.f_div(&e.f_abs()?.f_clamp_min(f64::EPSILON)?)?;
+        let max = |t: &Tensor| {
+            t.f_nan_to_num(0.0, None, None)?
+                .f_max()?
+                .f_double_value(&[])
+        };
+        let mean = abs.f_mean(Kind::Double)?.f_double_value(&[])?;
+        (max(&abs)?, mean, max(&rel)?)
+    };
+    let first_mismatch = if mismatched == 0 {
+        None
+    } else {
+        let at = close.f_logical_not()?.f_nonzero()?.f_select(0, 0)?;
+        let at = Vec::<i64>::try_from(&at)?;
+        Some((at.clone(), e.f_double_value(&at)?, a.f_double_value(&at)?))
+    };
+    diff.values = Some(ValueDiff {
+        mismatched,
+        numel,
+        max_abs_diff,
+        mean_abs_diff,
+        max_rel_diff,
+        first_mismatch,
+    });
+    Ok(diff)
+}
+
+/// Compare each `actual` output with the `expected` one at its index,
+/// using the tolerance for the expected output's dtype.
+pub fn compare_outputs<D: Device>(
+    expected: &[DeviceTensor<D>],
+    actual: &[DeviceTensor<D>],
+    tolerances: &Tolerances,
+) -> Result<Comparison, Error> {
+    Ok(Comparison {
+        expected_outputs: expected.len(),
+        actual_outputs: actual.len(),
+        outputs: expected
+            .iter()
+            .zip(actual)
+            .enumerate()
+            .map(|(i, (e, a))| compare_tensors(i, e, a, tolerances.get(e.kind())))
+            .collect::<Result<_, _>>()?,
+    })
+}
+
+/// Run `baseline` and `candidate` (models or pools) on the same `inputs`
+/// and compare the candidate's outputs with the baseline's, using the
+/// tolerance for each baseline output's dtype. Inputs are copied to each
+/// model's device, and the candidate's outputs to the baseline's, so the
+/// two may sit on different GPUs.
+pub fn compare_models<D: Device>(
+    baseline: &mut impl Run<D>,
+    candidate: &mut impl Run<D>,
+    inputs: &[DeviceTensor<D>],
+    tolerances: impl Into<Tolerances>,
+) -> Result<Comparison, Error> {
+    let expected = baseline.run(&on_device(inputs, baseline.device())?)?;
+    let actual = candidate.run(&on_device(inputs, candidate.device())?)?;
+    compare_outputs(
+        &expected,
+        &on_device(&actual, baseline.device())?,
+        &tolerances.into(),
+    )
+}
+
+/// `tensors` on `device`, sharing storage with them if they're there
+/// already.
+fn on_device<D: Device>(
+    tensors: &[DeviceTensor<D>],
+    device: tch::Device,
+) -> Result<Vec<DeviceTensor<D>>, Error> {
+    tensors
+        .iter()
+        .map(|t| {
+            Ok(DeviceTensor {
+                tensor: t.f_to_device(device)?,
+                _device: PhantomData,
+            })
+        })
+        .collect()
+}
+
+/// Panic, listing every difference, unless `actual` matches `expected`
+/// within `tolerances` (a single [`Tolerance`] or per-dtype
+/// [`Tolerances`]).
+#[track_caller]
+pub fn assert_outputs_close<D: Device>(
+    expected: &[DeviceTensor<D>],
+    actual: &[DeviceTensor<D>],
+    tolerances: impl Into<Tolerances>,
+) {
+    match compare_outputs(expected, actual, &tolerances.into()) {
+        Ok(comparison) => assert!(comparison.passed(), "outputs differ:\n{comparison}"),
+        Err(err) => panic!("comparing outputs: {err}"),
+    }
+}
+
+/// A stable 64-bit hash of `tensor`'s dtype, shape and values, with
+/// floating-point values rounded to multiples of `resolution` first (or
+/// hashed exactly if it's 0), so runs that differ by less than that
+/// usually hash the same. A value near the midpoint between two multiples
+/// can still round either way, so pick a resolution well above the
+/// expected noise and fall back to [`compare_tensors`] when hashes differ.
+/// NaNs hash alike, as do `0.0` and `-0.0`. The hash is FNV-1a, the same
+/// on every platform and run.
+pub fn content_hash(tensor: &Tensor, resolution: f64) -> Result<u64, Error> {
+    if !(resolution >= 0.0 && resolution.is_finite()) {
+        return Err(Error::InvalidInput(format!(
+            "content hash resolution {resolution} is not a finite, non-negative number"
+        )));
+    }
+    let kind = tensor.kind();
+    let mut hash = Fnv1a::default();
+    hash.write(format!("{kind:?}").as_bytes());
+    let shape = tensor.size();
+    hash.write(&(shape.len() as u64).to_le_bytes());
+    for dim in &shape {
+        hash.write(&dim.to_le_bytes());
+    }
+    let flat = tensor.f_to_device(tch::Device::Cpu)?.f_flatten(0, -1)?;
+    match kind {
+        Kind::Half | Kind::BFloat16 | Kind::Float | Kind::Double => {
+            let values = Vec::<f64>::try_from(&flat.f_to_kind(Kind::Double)?)?;
+            for value in values {
+                // A tag byte keeps NaNs and infinities apart from finite
+                // values.
+                if !value.is_finite() {
+                    let tag = if value.is_nan() {
+                        1
+                    } else if value > 0.0 {
+                        2
+                    } else {
+                        3
+                    };
+                    hash.write(&[tag]);
+                    continue;
+                }
+                let quantized = if resolution == 0.0 {
+                    // `+ 0.0` turns -0.0 into 0.0.
+                    (value + 0.0).to_bits()
+                } else {
+                    (value / resolution).round() as i64 as u64
+                };
+                hash.write(&[0]);
+                hash.write(&quantized.to_le_bytes());
+            }
+        }
+        Kind::ComplexHalf | Kind::ComplexFloat | Kind::ComplexDouble => {
+            return Err(Error::InvalidInput(format!(
+                "content hashes of {kind:?} tensors aren't supported"
+            )));
+        }
+        _ => {
+            for value in Vec::<i64>::try_from(&flat.f_to_kind(Kind::Int64)?)? {
+                hash.write(&value.to_le_bytes());
+            }
+        }
+    }
+    Ok(hash.0)
+}
+
+/// One hash over every output's [`content_hash`], in order, to record per
+/// test case instead of the outputs themselves.
+pub fn outputs_hash<D: Device>(outputs: &[DeviceTensor<D>], resolution: f64) -> Result<u64, Error> {
+    let mut hash = Fnv1a::default();
+    hash.write(&(outputs.len() as u64).to_le_bytes());
+    for output in outputs {
+        hash.write(&content_hash(output, resolution)?.to_le_bytes());
+    }
+    Ok(hash.0)
+}
+
+impl<D: Device> DeviceTensor<D> {
+    /// This tensor's [`content_hash`].
+    pub fn content_hash(&self, resolution: f64) -> Result<u64, Error> {
+        content_hash(self, resolution)
+    }
+}
+
+/// 64-bit FNV-1a.
+struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Fnv1a {
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 = (self.0 ^ b as u64).wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Cpu;
+
+    fn output(values: &[f32]) -> DeviceTensor<Cpu> {
+        DeviceTensor {
+            tensor: Tensor::from_slice(values).reshape([2, -1]),
+            _device: PhantomData,
+        }
+    }
+
+    #[test]
+    fn mismatches_are_counted_and_located() {
+        let expected = output(&[1.0, 2.0, f32::NAN, 4.0]);
+        let actual = output(&[1.0, 2.5, f32::NAN, 4.5]);
+        let diff = compare_tensors(0, &expected, &actual, Tolerance::default()).unwrap();
+        let values = diff.values.clone().unwrap();
+        assert_eq!((values.mismatched, values.numel), (2, 4));
+        assert_eq!(values.max_abs_diff, 0.5);
+        assert_eq!(values.mean_abs_diff, 0.25);
+        assert_eq!(values.max_rel_diff, 0.25);
+        assert_eq!(values.first_mismatch, Some((vec![0, 1], 2.0, 2.5)));
+        assert!(diff.to_string().contains("first at [0, 1]"), "{diff}");
+
+        let loose = Tolerance {
+            rtol: 0.25,
+            atol: 0.0,
+        };
+        assert!(
+            compare_tensors(0, &expected, &actual, loose)
+                .unwrap()
+                .passed()
+        );
+        let reshaped = actual.reshape([4]);
+        assert_eq!(
+            compare_tensors(0, &expected, &reshaped, loose)
+                .unwrap()
+                .values,
+            None
+        );
+    }
+
+    struct Scale(f64);
+
+    impl Run<Cpu> for Scale {
+        fn run(&mut self, inputs: &[DeviceTensor<Cpu>]) -> Result<Vec<DeviceTensor<Cpu>>, Error> {
+            Ok(vec![DeviceTensor {
+                tensor: inputs[0].f_mul_scalar(self.0)?,
+                _device: PhantomData,
+            }])
+        }
+
+        fn device(&self) -> tch::Device {
+            tch::Device::Cpu
+        }
+    }
+
+    #[test]
+    fn models_are_compared_on_the_same_inputs() {
+        let inputs = [output(&[1.0, 2.0, 3.0, 4.0])];
+        let same = compare_models(&mut Scale(2.0), &mut Scale(2.0), &inputs, Tolerance::EXACT);
+        assert!(same.unwrap().passed());
+        let off =
+            compare_models(&mut Scale(2.0), &mut Scale(2.5), &inputs, Tolerance::EXACT).unwrap();
+        let values = off.outputs[0].values.clone().unwrap();
+        assert!(!off.passed());
+        assert_eq!((values.max_abs_diff, values.mean_abs_diff), (2.0, 1.25));
+    }
+
+    #[test]
+    fn content_hashes_ignore_noise_below_the_resolution() {
+        let hash = |t: &DeviceTensor<Cpu>| t.content_hash(1e-2).unwrap();
+        let a = output(&[1.0, 2.0, f32::NAN, 0.0]);
+        assert_eq!(hash(&a), hash(&output(&[1.0004, 1.9996, f32::NAN, -0.0])));
+        assert_ne!(hash(&a), hash(&output(&[1.02, 2.0, f32::NAN, 0.0])));
+        assert_ne!(hash(&a), content_hash(&a.reshape([4]), 1e-2).unwrap());
+        let double = a.to_kind(Kind::Double);
+        assert_ne!(hash(&a), content_hash(&double, 1e-2).unwrap());
+        assert!(a.content_hash(-1.0).is_err());
+
+        // Pinned, so the hash can't change between releases unnoticed.
+        let ints = Tensor::from_slice(&[1i64, 2]);
+        assert_eq!(content_hash(&ints, 0.0).unwrap(), 0x0741_98af_76ed_36e4);
+        assert_ne!(
+            outputs_hash(&[a], 1e-2).unwrap(),
+            outputs_hash::<Cpu>(&[], 1e-2).unwrap()
+        );
+    }
+
+    #[test]
+    fn tolerances_follow_the_dtype() {
+        let tolerances = Tolerances::new().kind(Kind::Half, Tolerance::EXACT);
+        assert_eq!(tolerances.get(Kind::Half), Tolerance::EXACT);
+        assert_eq!(tolerances.get(Kind::Float).rtol, 1.3e-6);
+        assert_eq!(tolerances.get(Kind::Int64), Tolerance::EXACT);
+        let uniform = Tolerances::from(Tolerance::default());
+        assert_eq!(uniform.get(Kind::Int64), Tolerance::default());
+
+        let expected = [output(&[1.0, 2.0])];
+        assert_outputs_close(&expected, &[output(&[1.0, 2.000001])], Tolerances::new());
+        let comparison = compare_outputs(&expected, &[], &Tolerances::new()).unwrap();
+        assert!(!comparison.passed());
+        assert_eq!(comparison.to_string(), "0 outputs, expected 1");
+    }
+}
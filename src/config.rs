@@ -0,0 +1,332 @@
+//! Model configuration from TOML and the environment (feature `config`).
+//!
+//! A service describes its model in a small TOML file, so it can be pointed
+//! at a new package or resized without recompiling:
+//!
+//! ```toml
+//! path = "/srv/models/ranker.pt2"
+//! model_name = "model"   # default "model"
+//! device = "cuda:0"      # "cpu" (default), "cuda" or "cuda:N"
+//! runners = 4            # runners per model (default 1)
+//! single_threaded = false
+//! warmup = 3             # warmup runs before serving (default 0)
+//! pool_size = 2          # replicas in the model's pool (default 1)
+//! ```
+//!
+//! Each key can be overridden with an `AOTI_RS_<KEY>` environment variable
+//! (`AOTI_RS_PATH`, `AOTI_RS_RUNNERS`, ...), taken as is for string keys
+//! and as a TOML value (`4`, `true`) otherwise; `path` may come from the
+//! environment alone. The TOML itself can also come from an environment variable, by
+//! passing `env:<NAME>` as the source.
+//!
+//! A server hosting several models ([`ServeConfig`], used by `aoti-serve`)
+//! lists them as tables of the same keys under `models`, beside the
+//! addresses to listen on:
+//!
+//! ```toml
+//! http = "0.0.0.0:8080"     # REST front-end (feature `http`)
+//! grpc = "0.0.0.0:50051"    # gRPC front-end (feature `grpc`)
+//!
+//! [models.ranker]
+//! path = "/srv/models/ranker.pt2"
+//! pool_size = 2
+//! ```
+//!
+//! There only `AOTI_RS_HTTP` and `AOTI_RS_GRPC` override the file.
+
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::path::Path;
+
+use toml::{Table, Value};
+
+use crate::{AOTIModelBuilder, Device, Error};
+
+const KEYS: [&str; 7] = [
+    "path",
+    "model_name",
+    "device",
+    "runners",
+    "single_threaded",
+    "warmup",
+    "pool_size",
+];
+
+/// A model's load and serving settings, parsed from TOML; see
+/// [`AOTIModelBuilder::from_config`].
+///
+/// `warmup` and `pool_size` aren't builder settings: apply them where the
+/// service builds its pool and has representative inputs, e.g.
+/// `AOTIModelPool::from_fn(config.pool_size, |_| config.builder()?.build())`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModelConfig {
+    /// Path (or, with `object-store`, URL) of the `.pt2` package.
+    pub path: String,
+    pub model_name: String,
+    /// `"cpu"`, `"cuda"` (the current device) or `"cuda:N"`.
+    pub device: String,
+    pub runners: usize,
+    pub single_threaded: bool,
+    /// Warmup runs to make before serving.
+    pub warmup: usize,
+    /// Replicas in the model's pool.
+    pub pool_size: usize,
+}
+
+impl ModelConfig {
+    /// Read the config from a TOML file, or from the environment variable
+    /// `NAME` if `source` is `env:NAME`, applying `AOTI_RS_*` overrides.
+    pub fn load(source: &str) -> Result<Self, Error> {
+        Self::parse(&read_source(source)?, |key| std::env::var(key).ok())
+            .map_err(|e| Error::InvalidInput(format!("config {source}: {e}")))
+    }
+
+    /// Parse `text`, taking overrides from `env`.
+    fn parse(text: &str, env: impl Fn(&str) -> Option<String>) -> Result<Self, String> {
+        let mut table: Table = text.parse().map_err(|e: toml::de::Error| e.to_string())?;
+        for key in KEYS {
+            if let Some(raw) = env(&format!("AOTI_RS_{}", key.to_ascii_uppercase())) {
+                table.insert(key.to_string(), override_value(key, &raw));
+            }
+        }
+        Self::from_table(&table)
+    }
+
+    fn from_table(table: &Table) -> Result<Self, String> {
+        check_keys(table, &KEYS)?;
+        let invalid = |key: &str| format!("\"{key}\" has the wrong type");
+        let string = |key: &str, default: &str| match table.get(key) {
+            Some(value) => value
+                .as_str()
+                .map(str::to_string)
+                .ok_or_else(|| invalid(key)),
+            None => Ok(default.to_string()),
+        };
+        let count = |key: &str, default: usize, min: usize| match table.get(key) {
+            Some(value) => value
+                .as_integer()
+                .and_then(|n| usize::try_from(n).ok())
+                .filter(|&n| n >= min)
+                .ok_or_else(|| format!("\"{key}\" must be an integer of at least {min}")),
+            None => Ok(default),
+        };
+        let config = Self {
+            path: table
+                .get("path")
+                .ok_or("no \"path\"")?
+                .as_str()
+                .ok_or_else(|| invalid("path"))?
+                .to_string(),
+            model_name: string("model_name", "model")?,
+            device: string("device", "cpu")?,
+            runners: count("runners", 1, 1)?,
+            single_threaded: match table.get("single_threaded") {
+                Some(value) => value.as_bool().ok_or_else(|| invalid("single_threaded"))?,
+                None => false,
+            },
+            warmup: count("warmup", 0, 0)?,
+            pool_size: count("pool_size", 1, 1)?,
+        };
+        config.device_index()?;
+        Ok(config)
+    }
+
+    /// The builder index for `device`: -1 for the CPU or the current CUDA
+    /// device.
+    fn device_index(&self) -> Result<i8, String> {
+        let invalid = || format!("unknown device \"{}\"", self.device);
+        match self.device.split_once(':') {
+            None if self.device == "cpu" || self.device == "cuda" => Ok(-1),
+            Some(("cuda", index)) => index
+                .parse::<i8>()
+                .ok()
+                .filter(|&i| i >= 0)
+                .ok_or_else(invalid),
+            _ => Err(invalid()),
+        }
+    }
+
+    /// A builder with these settings, for device `D`. Fails with
+    /// [`Error::InvalidInput`] if `device` is of another kind.
+    pub fn builder<D: Device>(&self) -> Result<AOTIModelBuilder<D>, Error> {
+        let index = self.device_index().map_err(Error::InvalidInput)?;
+        if self.device.starts_with("cuda") != D::IS_CUDA {
+            return Err(Error::InvalidInput(format!(
+                "config device \"{}\" is not a {} device",
+                self.device,
+                D::KEY
+            )));
+        }
+        let mut builder = AOTIModelBuilder::new(&self.path)
+            .model_name(&self.model_name)
+            .num_runners(self.runners)
+            .single_threaded(self.single_threaded);
+        builder.device_index = index;
+        Ok(builder)
+    }
+}
+
+/// The models a server hosts and where it listens, parsed from TOML; see
+/// the [module docs](self).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServeConfig {
+    /// Address for the REST front-end.
+    pub http: Option<SocketAddr>,
+    /// Address for the gRPC front-end.
+    pub grpc: Option<SocketAddr>,
+    /// Each model, by the name it's served under.
+    pub models: BTreeMap<String, ModelConfig>,
+}
+
+impl ServeConfig {
+    /// Read the config from a TOML file, or from the environment variable
+    /// `NAME` if `source` is `env:NAME`, applying the `AOTI_RS_HTTP` and
+    /// `AOTI_RS_GRPC` overrides.
+    pub fn load(source: &str) -> Result<Self, Error> {
+        Self::parse(&read_source(source)?, |key| std::env::var(key).ok())
+            .map_err(|e| Error::InvalidInput(format!("config {source}: {e}")))
+    }
+
+    fn parse(text: &str, env: impl Fn(&str) -> Option<String>) -> Result<Self, String> {
+        let table: Table = text.parse().map_err(|e: toml::de::Error| e.to_string())?;
+        check_keys(&table, &["http", "grpc", "models"])?;
+        let address = |key: &str| {
+            let raw = match env(&format!("AOTI_RS_{}", key.to_ascii_uppercase())) {
+                Some(raw) => raw,
+                None => match table.get(key) {
+                    Some(value) => value
+                        .as_str()
+                        .ok_or_else(|| format!("\"{key}\" has the wrong type"))?
+                        .to_string(),
+                    None => return Ok(None),
+                },
+            };
+            raw.parse()
+                .map(Some)
+                .map_err(|_| format!("\"{key}\" is not an address: \"{raw}\""))
+        };
+        let models = match table.get("models") {
+            Some(Value::Table(models)) => models
+                .iter()
+                .map(|(name, model)| {
+                    let model = model
+                        .as_table()
+                        .ok_or_else(|| format!("model \"{name}\" is not a table"))?;
+                    let config = ModelConfig::from_table(model)
+                        .map_err(|e| format!("model \"{name}\": {e}"))?;
+                    Ok((name.clone(), config))
+                })
+                .collect::<Result<_, String>>()?,
+            Some(_) => return Err("\"models\" has the wrong type".into()),
+            None => BTreeMap::new(),
+        };
+        Ok(Self {
+            http: address("http")?,
+            grpc: address("grpc")?,
+            models,
+        })
+    }
+}
+
+/// The config text at `source`: a file path, or `env:NAME`.
+fn read_source(source: &str) -> Result<String, Error> {
+    match source.strip_prefix("env:") {
+        Some(name) => std::env::var(name)
+            .map_err(|e| Error::InvalidInput(format!("config variable {name} is unusable: {e}"))),
+        None => Ok(std::fs::read_to_string(Path::new(source))?),
+    }
+}
+
+fn check_keys(table: &Table, keys: &[&str]) -> Result<(), String> {
+    match table.keys().find(|key| !keys.contains(&key.as_str())) {
+        Some(key) => Err(format!("unknown key \"{key}\"")),
+        None => Ok(()),
+    }
+}
+
+/// An override's value for `key`: the raw string for string keys, else
+/// `raw` parsed as TOML (left a string, and so rejected, if it doesn't
+/// parse).
+fn override_value(key: &str, raw: &str) -> Value {
+    if matches!(key, "path" | "model_name" | "device") {
+        return Value::String(raw.to_string());
+    }
+    format!("value = {raw}")
+        .parse::<Table>()
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| Value::String(raw.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Cpu;
+
+    #[test]
+    fn configs_fill_defaults_and_take_env_overrides() {
+        let text = "path = \"/srv/a.pt2\"\nruntime = 1\n";
+        assert!(
+            ModelConfig::parse(text, |_| None)
+                .unwrap_err()
+                .contains("runtime")
+        );
+
+        let config = ModelConfig::parse("path = \"/srv/a.pt2\"\nwarmup = 2", |key| match key {
+            "AOTI_RS_RUNNERS" => Some("4".into()),
+            "AOTI_RS_DEVICE" => Some("cuda:1".into()),
+            _ => None,
+        })
+        .unwrap();
+        assert_eq!(
+            config,
+            ModelConfig {
+                path: "/srv/a.pt2".into(),
+                model_name: "model".into(),
+                device: "cuda:1".into(),
+                runners: 4,
+                single_threaded: false,
+                warmup: 2,
+                pool_size: 1,
+            }
+        );
+        let from_env = ModelConfig::parse("", |key| {
+            (key == "AOTI_RS_PATH").then(|| "/srv/b.pt2".into())
+        });
+        assert_eq!(from_env.unwrap().path, "/srv/b.pt2");
+    }
+
+    #[test]
+    fn invalid_values_and_devices_are_rejected() {
+        let parse = |text: &str| ModelConfig::parse(text, |_| None);
+        assert!(parse("model_name = \"m\"").is_err());
+        assert!(parse("path = \"a.pt2\"\nrunners = 0").is_err());
+        assert!(parse("path = \"a.pt2\"\nsingle_threaded = \"yes\"").is_err());
+        assert!(parse("path = \"a.pt2\"\ndevice = \"mps\"").is_err());
+        assert!(parse("path = \"a.pt2\"\ndevice = \"cuda:x\"").is_err());
+        let cuda = parse("path = \"a.pt2\"\ndevice = \"cuda\"").unwrap();
+        assert!(matches!(cuda.builder::<Cpu>(), Err(Error::InvalidInput(_))));
+        let cpu = parse("path = \"a.pt2\"").unwrap();
+        assert!(cpu.builder::<Cpu>().is_ok());
+    }
+
+    #[test]
+    fn serve_configs_hold_model_tables() {
+        let text = "http = \"127.0.0.1:8080\"\n\
+                    [models.a]\npath = \"/srv/a.pt2\"\npool_size = 2\n\
+                    [models.b]\npath = \"/srv/b.pt2\"\n";
+        let config = ServeConfig::parse(text, |key| {
+            (key == "AOTI_RS_GRPC").then(|| "0.0.0.0:50051".into())
+        })
+        .unwrap();
+        assert_eq!(config.http, Some("127.0.0.1:8080".parse().unwrap()));
+        assert_eq!(config.grpc, Some("0.0.0.0:50051".parse().unwrap()));
+        assert_eq!(config.models.len(), 2);
+        assert_eq!(config.models["a"].pool_size, 2);
+
+        let parse = |text: &str| ServeConfig::parse(text, |_| None);
+        assert!(parse("http = \"localhost\"").is_err());
+        assert!(parse("[models.a]\nmodel_name = \"m\"").is_err());
+        assert!(parse("[models.a]\npath = \"a.pt2\"\nruntime = 1").is_err());
+    }
+}
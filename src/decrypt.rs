@@ -0,0 +1,84 @@
+//! Loading packages that are encrypted at rest.
+
+use std::io::{self, Read, Seek, Write};
+
+use tempfile::NamedTempFile;
+
+use crate::Error;
+
+/// Decrypts a package stored encrypted at rest; see
+/// [`AOTIModelBuilder::with_decryptor`](crate::AOTIModelBuilder::with_decryptor).
+///
+/// The crate ships no ciphers: wrap whichever your key management uses.
+/// Closures of the same shape implement this trait.
+pub trait PackageDecryptor: Send + Sync {
+    /// Stream the plaintext `.pt2` archive for `encrypted` into `plaintext`.
+    /// Fail (e.g. with [`io::Error::other`]) if the data doesn't
+    /// authenticate.
+    fn decrypt(&self, encrypted: &mut dyn Read, plaintext: &mut dyn Write) -> io::Result<()>;
+}
+
+impl<F> PackageDecryptor for F
+where
+    F: Fn(&mut dyn Read, &mut dyn Write) -> io::Result<()> + Send + Sync,
+{
+    fn decrypt(&self, encrypted: &mut dyn Read, plaintext: &mut dyn Write) -> io::Result<()> {
+        self(encrypted, plaintext)
+    }
+}
+
+/// Decrypt the package read from `encrypted` into a temp file readable
+/// only by this user and deleted when dropped, positioned at its start.
+pub(crate) fn decrypt_to_temp(
+    encrypted: impl Read,
+    decryptor: &dyn PackageDecryptor,
+) -> Result<NamedTempFile, Error> {
+    let mut encrypted = io::BufReader::new(encrypted);
+    let mut plaintext = NamedTempFile::new()?;
+    {
+        let mut out = io::BufWriter::new(plaintext.as_file_mut());
+        decryptor
+            .decrypt(&mut encrypted, &mut out)
+            .and_then(|()| out.flush())
+            .map_err(Error::Decryption)?;
+    }
+    plaintext.rewind()?;
+    Ok(plaintext)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn xor(encrypted: &mut dyn Read, plaintext: &mut dyn Write) -> io::Result<()> {
+        let mut bytes = Vec::new();
+        encrypted.read_to_end(&mut bytes)?;
+        plaintext.write_all(&bytes.iter().map(|b| b ^ 0x5a).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn packages_are_decrypted_into_a_temp_file() {
+        let mut encrypted = NamedTempFile::new().unwrap();
+        encrypted.write_all(&[b'P' ^ 0x5a, b'K' ^ 0x5a]).unwrap();
+        let mut plaintext = decrypt_to_temp(encrypted.reopen().unwrap(), &xor).unwrap();
+        let mut bytes = Vec::new();
+        plaintext.read_to_end(&mut bytes).unwrap();
+        assert_eq!(bytes, b"PK");
+    }
+
+    #[test]
+    fn decryption_failures_are_reported_as_such() {
+        let encrypted = NamedTempFile::new().unwrap();
+        let reject = |_: &mut dyn Read, _: &mut dyn Write| Err(io::Error::other("bad tag"));
+        assert!(matches!(
+            decrypt_to_temp(encrypted.reopen().unwrap(), &reject),
+            Err(Error::Decryption(e)) if e.to_string() == "bad tag"
+        ));
+        // Read errors reach the decryptor, which reports them.
+        let unreadable = std::fs::File::open(std::env::temp_dir()).unwrap();
+        assert!(matches!(
+            decrypt_to_temp(unreadable, &xor),
+            Err(Error::Decryption(_))
+        ));
+    }
+}
@@ -0,0 +1,314 @@
+//! Post-processing for object detectors: box format conversion, score
+//! thresholding, and non-maximum suppression.
+//!
+//! Detector heads disagree on layout, so [`DetectionDecoder`] accepts the
+//! three common ones: per-class scores (`[N, 4]` boxes plus `[N, C]`
+//! scores), already-labeled boxes (`[N, 4]` boxes, `[N]` scores, `[N]`
+//! classes), and YOLO-style packed rows (`[N, 4 + C]`). NMS runs on the
+//! host after thresholding, which is where the candidate count is small.
+
+use tch::Tensor;
+use tch::kind::Element;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::Error;
+
+/// How the four numbers of a box are laid out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BoxFormat {
+    /// Corners: `x1, y1, x2, y2`.
+    Xyxy,
+    /// Top-left corner and size: `x, y, w, h`.
+    Xywh,
+    /// Center and size: `cx, cy, w, h`.
+    Cxcywh,
+}
+
+impl BoxFormat {
+    fn to_xyxy(self, b: [f32; 4]) -> [f32; 4] {
+        match self {
+            BoxFormat::Xyxy => b,
+            BoxFormat::Xywh => [b[0], b[1], b[0] + b[2], b[1] + b[3]],
+            BoxFormat::Cxcywh => [
+                b[0] - b[2] / 2.0,
+                b[1] - b[3] / 2.0,
+                b[0] + b[2] / 2.0,
+                b[1] + b[3] / 2.0,
+            ],
+        }
+    }
+
+    fn xyxy_into(self, b: [f32; 4]) -> [f32; 4] {
+        match self {
+            BoxFormat::Xyxy => b,
+            BoxFormat::Xywh => [b[0], b[1], b[2] - b[0], b[3] - b[1]],
+            BoxFormat::Cxcywh => [
+                (b[0] + b[2]) / 2.0,
+                (b[1] + b[3]) / 2.0,
+                b[2] - b[0],
+                b[3] - b[1],
+            ],
+        }
+    }
+}
+
+/// Convert an `[N, 4]` tensor of boxes between formats.
+pub fn convert_boxes(boxes: &Tensor, from: BoxFormat, to: BoxFormat) -> Result<Tensor, Error> {
+    let converted: Vec<f32> = boxes_to_host(boxes)?
+        .into_iter()
+        .flat_map(|b| to.xyxy_into(from.to_xyxy(b)))
+        .collect();
+    Ok(Tensor::f_from_slice(&converted)?.f_view([-1, 4])?)
+}
+
+/// One detected object, with its box in `x1, y1, x2, y2` form.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Detection {
+    pub bbox: [f32; 4],
+    pub class: usize,
+    pub score: f32,
+}
+
+/// Intersection over union of two `x1, y1, x2, y2` boxes.
+pub fn iou(a: &[f32; 4], b: &[f32; 4]) -> f32 {
+    let w = (a[2].min(b[2]) - a[0].max(b[0])).max(0.0);
+    let h = (a[3].min(b[3]) - a[1].max(b[1])).max(0.0);
+    let inter = w * h;
+    let area = |r: &[f32; 4]| (r[2] - r[0]).max(0.0) * (r[3] - r[1]).max(0.0);
+    let union = area(a) + area(b) - inter;
+    if union > 0.0 { inter / union } else { 0.0 }
+}
+
+/// Greedy non-maximum suppression over `x1, y1, x2, y2` boxes.
+///
+/// Returns the indices of the kept boxes, highest score first. A box is
+/// dropped if its IoU with an already-kept box exceeds `iou_threshold`.
+pub fn nms(boxes: &[[f32; 4]], scores: &[f32], iou_threshold: f32) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..boxes.len().min(scores.len())).collect();
+    order.sort_by(|&a, &b| scores[b].total_cmp(&scores[a]));
+    let mut keep: Vec<usize> = Vec::new();
+    for i in order {
+        if keep
+            .iter()
+            .all(|&k| iou(&boxes[k], &boxes[i]) <= iou_threshold)
+        {
+            keep.push(i);
+        }
+    }
+    keep
+}
+
+fn boxes_to_host(boxes: &Tensor) -> Result<Vec<[f32; 4]>, Error> {
+    if boxes.dim() != 2 || boxes.size()[1] != 4 {
+        return Err(Error::InvalidInput(format!(
+            "expected [N, 4] boxes, got {:?}",
+            boxes.size()
+        )));
+    }
+    let flat = to_host::<f32>(boxes)?;
+    Ok(flat
+        .chunks_exact(4)
+        .map(|c| [c[0], c[1], c[2], c[3]])
+        .collect())
+}
+
+fn to_host<T: Element>(tensor: &Tensor) -> Result<Vec<T>, Error> {
+    let host = tensor
+        .f_to_device(tch::Device::Cpu)?
+        .f_to_kind(T::KIND)?
+        .f_contiguous()?;
+    let numel = host.numel();
+    let mut data = vec![T::ZERO; numel];
+    host.f_copy_data(&mut data, numel)?;
+    Ok(data)
+}
+
+/// Turns raw detector outputs into thresholded, de-duplicated
+/// [`Detection`]s.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DetectionDecoder {
+    format: BoxFormat,
+    score_threshold: f32,
+    iou_threshold: f32,
+    max_detections: usize,
+    class_agnostic: bool,
+}
+
+impl DetectionDecoder {
+    /// Decode boxes in `format` with a 0.25 score threshold, 0.45 IoU
+    /// threshold, and at most 300 detections, suppressing per class.
+    pub fn new(format: BoxFormat) -> Self {
+        Self {
+            format,
+            score_threshold: 0.25,
+            iou_threshold: 0.45,
+            max_detections: 300,
+            class_agnostic: false,
+        }
+    }
+
+    /// Drop candidates scoring below `threshold`.
+    pub fn score_threshold(mut self, threshold: f32) -> Self {
+        self.score_threshold = threshold;
+        self
+    }
+
+    /// Suppress boxes overlapping a better one by more than `threshold`.
+    pub fn iou_threshold(mut self, threshold: f32) -> Self {
+        self.iou_threshold = threshold;
+        self
+    }
+
+    pub fn max_detections(mut self, max: usize) -> Self {
+        self.max_detections = max;
+        self
+    }
+
+    /// Suppress across classes instead of within each class.
+    pub fn class_agnostic(mut self, agnostic: bool) -> Self {
+        self.class_agnostic = agnostic;
+        self
+    }
+
+    /// Decode `[N, 4]` boxes with `[N, C]` per-class scores, taking each
+    /// box's best class.
+    pub fn decode(&self, boxes: &Tensor, class_scores: &Tensor) -> Result<Vec<Detection>, Error> {
+        if class_scores.dim() != 2 {
+            return Err(Error::InvalidInput(format!(
+                "expected [N, C] class scores, got {:?}",
+                class_scores.size()
+            )));
+        }
+        let (scores, classes) = class_scores.f_max_dim(1, false)?;
+        self.decode_labeled(boxes, &scores, &classes)
+    }
+
+    /// Decode `[N, 4]` boxes that already carry an `[N]` score and `[N]`
+    /// class id each.
+    pub fn decode_labeled(
+        &self,
+        boxes: &Tensor,
+        scores: &Tensor,
+        classes: &Tensor,
+    ) -> Result<Vec<Detection>, Error> {
+        let boxes = boxes_to_host(boxes)?;
+        let scores = to_host::<f32>(scores)?;
+        let classes = to_host::<i64>(classes)?;
+        if scores.len() != boxes.len() || classes.len() != boxes.len() {
+            return Err(Error::InvalidInput(format!(
+                "{} boxes but {} scores and {} classes",
+                boxes.len(),
+                scores.len(),
+                classes.len()
+            )));
+        }
+
+        let candidates: Vec<Detection> = boxes
+            .into_iter()
+            .zip(scores)
+            .zip(classes)
+            .filter(|&((_, score), _)| score >= self.score_threshold)
+            .map(|((bbox, score), class)| Detection {
+                bbox: self.format.to_xyxy(bbox),
+                class: class.max(0) as usize,
+                score,
+            })
+            .collect();
+
+        let mut kept: Vec<Detection> = if self.class_agnostic {
+            self.suppress(candidates.iter())
+        } else {
+            let mut classes: Vec<usize> = candidates.iter().map(|d| d.class).collect();
+            classes.sort_unstable();
+            classes.dedup();
+            classes
+                .into_iter()
+                .flat_map(|c| self.suppress(candidates.iter().filter(|d| d.class == c)))
+                .collect()
+        };
+        kept.sort_by(|a, b| b.score.total_cmp(&a.score));
+        kept.truncate(self.max_detections);
+        Ok(kept)
+    }
+
+    /// Decode YOLO-style `[N, 4 + C]` rows (box first, then per-class
+    /// scores), or `[1, N, 4 + C]` with a leading batch dimension.
+    pub fn decode_packed(&self, output: &Tensor) -> Result<Vec<Detection>, Error> {
+        let output = match output.dim() {
+            2 => output.shallow_clone(),
+            3 if output.size()[0] == 1 => output.f_squeeze_dim(0)?,
+            _ => {
+                return Err(Error::InvalidInput(format!(
+                    "expected [N, 4 + C] or [1, N, 4 + C] rows, got {:?}",
+                    output.size()
+                )));
+            }
+        };
+        let width = output.size()[1];
+        if width < 5 {
+            return Err(Error::InvalidInput(format!(
+                "rows have {width} columns, need 4 box coordinates and at least 1 score"
+            )));
+        }
+        self.decode(
+            &output.f_narrow(1, 0, 4)?,
+            &output.f_narrow(1, 4, width - 4)?,
+        )
+    }
+
+    fn suppress<'a>(&self, candidates: impl Iterator<Item = &'a Detection>) -> Vec<Detection> {
+        let candidates: Vec<&Detection> = candidates.collect();
+        let boxes: Vec<[f32; 4]> = candidates.iter().map(|d| d.bbox).collect();
+        let scores: Vec<f32> = candidates.iter().map(|d| d.score).collect();
+        nms(&boxes, &scores, self.iou_threshold)
+            .into_iter()
+            .map(|i| candidates[i].clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nms_drops_overlapping_lower_score() {
+        let boxes = [
+            [0.0, 0.0, 10.0, 10.0],
+            [1.0, 1.0, 10.0, 10.0],
+            [20.0, 20.0, 30.0, 30.0],
+        ];
+        assert_eq!(nms(&boxes, &[0.8, 0.9, 0.5], 0.5), vec![1, 2]);
+    }
+
+    #[test]
+    fn cxcywh_round_trips() {
+        let boxes = Tensor::from_slice(&[5.0f32, 5.0, 4.0, 2.0]).view([1, 4]);
+        let xyxy = convert_boxes(&boxes, BoxFormat::Cxcywh, BoxFormat::Xyxy).unwrap();
+        assert_eq!(
+            Vec::<f32>::try_from(xyxy.view([-1])).unwrap(),
+            vec![3.0, 4.0, 7.0, 6.0]
+        );
+    }
+
+    #[test]
+    fn packed_rows_are_suppressed_per_class() {
+        // Two overlapping boxes of different classes survive; a third,
+        // overlapping box of the first class does not.
+        let rows = Tensor::from_slice(&[
+            0.0f32, 0.0, 10.0, 10.0, 0.9, 0.1, //
+            0.0, 0.0, 10.0, 10.0, 0.1, 0.8, //
+            1.0, 1.0, 10.0, 10.0, 0.7, 0.0, //
+            0.0, 0.0, 1.0, 1.0, 0.1, 0.1,
+        ])
+        .view([4, 6]);
+        let dets = DetectionDecoder::new(BoxFormat::Xyxy)
+            .decode_packed(&rows)
+            .unwrap();
+        assert_eq!(dets.len(), 2);
+        assert_eq!((dets[0].class, dets[1].class), (0, 1));
+    }
+}
@@ -0,0 +1,298 @@
+//! Helpers for sentence-embedding models: pooling token states into one
+//! vector per sequence, L2 normalization, and cosine similarity.
+//!
+//! Everything runs as libtorch ops on the tensors' own device, so pooled
+//! embeddings of a CUDA model stay in VRAM until the caller copies them out.
+//!
+//! With feature `text`, [`Embedder`] does the whole job for an encoder
+//! package: tokenize, run in batches, pool, normalize, copy out.
+
+use std::marker::PhantomData;
+
+use tch::{Kind, Tensor};
+
+use crate::{Device, DeviceTensor, Error};
+
+/// How token states are reduced to one embedding per sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pooling {
+    /// Average over the tokens the attention mask marks as real.
+    Mean,
+    /// Take the first token (`[CLS]`).
+    Cls,
+    /// Element-wise maximum over the real tokens.
+    Max,
+    /// Take the last real token, as decoder-based embedding models do;
+    /// works with padding on either side.
+    LastToken,
+}
+
+fn wrap<D: Device>(tensor: Tensor) -> DeviceTensor<D> {
+    DeviceTensor {
+        tensor,
+        _device: PhantomData,
+    }
+}
+
+/// Pool `[N, L, H]` token states into `[N, H]` embeddings.
+///
+/// `attention_mask` is the `[N, L]` mask given to the model (non-zero for
+/// real tokens); it is ignored by [`Pooling::Cls`].
+pub fn pool<D: Device>(
+    hidden: &DeviceTensor<D>,
+    attention_mask: &DeviceTensor<D>,
+    pooling: Pooling,
+) -> Result<DeviceTensor<D>, Error> {
+    let (size, mask_size) = (hidden.size(), attention_mask.size());
+    if size.len() != 3 || mask_size[..] != size[..2] {
+        return Err(Error::InvalidInput(format!(
+            "expected [N, L, H] states and an [N, L] mask, got {size:?} and {mask_size:?}"
+        )));
+    }
+    let pooled = match pooling {
+        Pooling::Cls => hidden.f_select(1, 0)?,
+        Pooling::Mean => {
+            let mask = attention_mask.f_to_kind(hidden.kind())?.f_unsqueeze(-1)?;
+            let summed = hidden
+                .f_mul(&mask)?
+                .f_sum_dim_intlist(1, false, None::<Kind>)?;
+            let counts = mask
+                .f_sum_dim_intlist(1, false, None::<Kind>)?
+                .f_clamp_min(1e-9)?;
+            summed.f_div(&counts)?
+        }
+        Pooling::Max => {
+            let padding = attention_mask.f_eq(0)?.f_unsqueeze(-1)?;
+            hidden
+                .f_masked_fill(&padding, f64::NEG_INFINITY)?
+                .f_amax(1, false)?
+        }
+        Pooling::LastToken => {
+            let positions = Tensor::f_arange(size[1], (Kind::Int64, hidden.device()))?;
+            let last = positions
+                .f_mul(&attention_mask.f_ne(0)?.f_to_kind(Kind::Int64)?)?
+                .f_amax(1, true)?;
+            let index = last
+                .f_unsqueeze(-1)?
+                .f_expand([size[0], 1, size[2]], false)?;
+            hidden.f_gather(1, &index, false)?.f_squeeze_dim(1)?
+        }
+    };
+    Ok(wrap(pooled))
+}
+
+/// Scale each vector along the last dimension to unit L2 norm. Zero
+/// vectors stay zero.
+pub fn l2_normalize<D: Device>(embeddings: &DeviceTensor<D>) -> Result<DeviceTensor<D>, Error> {
+    let norms = embeddings
+        .f_norm_scalaropt_dim(2, [-1], true)?
+        .f_clamp_min(1e-12)?;
+    Ok(wrap(embeddings.f_div(&norms)?))
+}
+
+/// Pairwise cosine similarity between `[N, H]` and `[M, H]` embeddings, as
+/// an `[N, M]` matrix.
+pub fn cosine_similarity<D: Device>(
+    a: &DeviceTensor<D>,
+    b: &DeviceTensor<D>,
+) -> Result<DeviceTensor<D>, Error> {
+    if a.dim() != 2 || b.dim() != 2 || a.size()[1] != b.size()[1] {
+        return Err(Error::InvalidInput(format!(
+            "expected [N, H] and [M, H] embeddings, got {:?} and {:?}",
+            a.size(),
+            b.size()
+        )));
+    }
+    let (a, b) = (l2_normalize(a)?, l2_normalize(b)?);
+    Ok(wrap(a.f_matmul(&b.f_transpose(0, 1)?)?))
+}
+
+/// Sentence embeddings from an encoder package: texts are tokenized,
+/// run `batch_size` at a time, pooled and (by default) L2-normalized.
+///
+/// The model gets `[input_ids, attention_mask]`, plus `token_type_ids`
+/// if enabled, and its first output must be `[N, L, H]` token states or
+/// already pooled `[N, H]` embeddings, which are used as they are.
+#[cfg(feature = "text")]
+pub struct Embedder<D: Device, M> {
+    model: M,
+    encoder: crate::text::TextEncoder,
+    pooling: Pooling,
+    normalize: bool,
+    batch_size: usize,
+    token_type_ids: bool,
+    _device: PhantomData<D>,
+}
+
+#[cfg(feature = "text")]
+impl<D: Device, M: crate::generate::Run<D>> Embedder<D, M> {
+    /// Mean pooling, normalized, 32 texts per run.
+    pub fn new(model: M, encoder: crate::text::TextEncoder) -> Self {
+        Self {
+            model,
+            encoder,
+            pooling: Pooling::Mean,
+            normalize: true,
+            batch_size: 32,
+            token_type_ids: false,
+            _device: PhantomData,
+        }
+    }
+
+    pub fn pooling(mut self, pooling: Pooling) -> Self {
+        self.pooling = pooling;
+        self
+    }
+
+    /// Whether to scale embeddings to unit length (default: true).
+    pub fn normalize(mut self, normalize: bool) -> Self {
+        self.normalize = normalize;
+        self
+    }
+
+    /// Texts per model run (at least 1).
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Pass `token_type_ids` as a third input, as BERT-style exports
+    /// that kept it expect (default: false).
+    pub fn token_type_ids(mut self, token_type_ids: bool) -> Self {
+        self.token_type_ids = token_type_ids;
+        self
+    }
+
+    /// Take the model and encoder back.
+    pub fn into_parts(self) -> (M, crate::text::TextEncoder) {
+        (self.model, self.encoder)
+    }
+
+    /// Embed `texts`, one vector each, in order.
+    pub fn embed(&mut self, texts: &[&str]) -> Result<Vec<Vec<f32>>, Error> {
+        let mut embeddings = Vec::with_capacity(texts.len());
+        for batch in texts.chunks(self.batch_size) {
+            let encoded = self.encoder.encode(batch)?;
+            let device = self.model.device();
+            let upload = |t: DeviceTensor<crate::Cpu>| -> Result<DeviceTensor<D>, Error> {
+                Ok(wrap(t.f_to_device(device)?))
+            };
+            let attention_mask = upload(encoded.attention_mask)?;
+            let mut inputs = vec![
+                upload(encoded.input_ids)?,
+                wrap(attention_mask.shallow_clone()),
+            ];
+            if self.token_type_ids {
+                inputs.push(upload(encoded.token_type_ids)?);
+            }
+            let output = self
+                .model
+                .run(&inputs)?
+                .into_iter()
+                .next()
+                .ok_or_else(|| Error::Model("the encoder returned no outputs".into()))?;
+            let mut pooled = match output.dim() {
+                2 => output,
+                _ => pool(&output, &attention_mask, self.pooling)?,
+            };
+            if self.normalize {
+                pooled = l2_normalize(&pooled)?;
+            }
+            let pooled = pooled
+                .f_to_kind(Kind::Float)?
+                .f_to_device(tch::Device::Cpu)?;
+            let width = pooled.size()[1] as usize;
+            let values = Vec::<f32>::try_from(&pooled.f_flatten(0, -1)?)?;
+            embeddings.extend(values.chunks(width.max(1)).map(<[f32]>::to_vec));
+        }
+        Ok(embeddings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Cpu;
+
+    fn cpu(t: Tensor) -> DeviceTensor<Cpu> {
+        DeviceTensor::try_new(t).unwrap()
+    }
+
+    #[test]
+    fn mean_pooling_skips_padding() {
+        let hidden =
+            cpu(Tensor::from_slice(&[1.0f32, 2.0, 3.0, 4.0, 100.0, 100.0]).view([1, 3, 2]));
+        let mask = cpu(Tensor::from_slice(&[1i64, 1, 0]).view([1, 3]));
+
+        let mean = pool(&hidden, &mask, Pooling::Mean).unwrap();
+        assert_eq!(mean.size(), &[1, 2]);
+        assert_eq!(mean.double_value(&[0, 0]), 2.0);
+        let max = pool(&hidden, &mask, Pooling::Max).unwrap();
+        assert_eq!(max.double_value(&[0, 1]), 4.0);
+        let cls = pool(&hidden, &mask, Pooling::Cls).unwrap();
+        assert_eq!(cls.double_value(&[0, 1]), 2.0);
+        let last = pool(&hidden, &mask, Pooling::LastToken).unwrap();
+        assert_eq!(last.size(), &[1, 2]);
+        assert_eq!(last.double_value(&[0, 0]), 3.0);
+    }
+
+    /// Token states `[id, 1]` per token.
+    #[cfg(feature = "text")]
+    struct Echo;
+
+    #[cfg(feature = "text")]
+    impl crate::generate::Run<Cpu> for Echo {
+        fn run(&mut self, inputs: &[DeviceTensor<Cpu>]) -> Result<Vec<DeviceTensor<Cpu>>, Error> {
+            let ids = inputs[0].f_to_kind(Kind::Float)?;
+            let hidden = Tensor::f_stack(&[ids.shallow_clone(), ids.f_ones_like()?], -1)?;
+            Ok(vec![cpu(hidden)])
+        }
+
+        fn device(&self) -> tch::Device {
+            tch::Device::Cpu
+        }
+    }
+
+    #[cfg(feature = "text")]
+    #[test]
+    fn embedder_pools_each_batch_in_order() {
+        use tokenizers::Tokenizer;
+        use tokenizers::models::wordlevel::WordLevel;
+        use tokenizers::pre_tokenizers::whitespace::Whitespace;
+
+        let vocab = [("[PAD]", 0), ("[UNK]", 1), ("hello", 2), ("world", 3)]
+            .into_iter()
+            .map(|(t, i)| (t.to_string(), i))
+            .collect();
+        let model = WordLevel::builder()
+            .vocab(vocab)
+            .unk_token("[UNK]".into())
+            .build()
+            .unwrap();
+        let mut tokenizer = Tokenizer::new(model);
+        tokenizer.with_pre_tokenizer(Some(Whitespace {}));
+        let encoder = crate::text::TextEncoder::new(tokenizer);
+
+        let texts = ["hello world", "hello", "world world"];
+        let mut embedder = Embedder::new(Echo, encoder).normalize(false).batch_size(2);
+        let embeddings = embedder.embed(&texts).unwrap();
+        assert_eq!(embeddings, [vec![2.5, 1.0], vec![2.0, 1.0], vec![3.0, 1.0]]);
+
+        let mut embedder = embedder.normalize(true).pooling(Pooling::LastToken);
+        let embeddings = embedder.embed(&texts[..2]).unwrap();
+        let norm = embeddings[1].iter().map(|x| x * x).sum::<f32>().sqrt();
+        assert!((norm - 1.0).abs() < 1e-6);
+        assert!((embeddings[0][0] / embeddings[0][1] - 3.0).abs() < 1e-5);
+        assert!(embedder.embed(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn cosine_similarity_of_parallel_vectors_is_one() {
+        let a = cpu(Tensor::from_slice(&[1.0f32, 0.0, 0.0, 3.0]).view([2, 2]));
+        let b = cpu(Tensor::from_slice(&[2.0f32, 0.0]).view([1, 2]));
+        let sim = cosine_similarity(&a, &b).unwrap();
+        assert_eq!(sim.size(), &[2, 1]);
+        assert!((sim.double_value(&[0, 0]) - 1.0).abs() < 1e-6);
+        assert!(sim.double_value(&[1, 0]).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,312 @@
+//! Producing `.pt2` packages by driving Python (feature `export`).
+//!
+//! [`PackageExporter`] runs a fixed Python script under an interpreter with
+//! PyTorch 2.6 or newer installed: it loads a TorchScript archive
+//! (`torch.jit.save`) or a pickled module (`torch.save(model)`), exports it
+//! with `torch.export` on random example inputs of the given shapes and
+//! dtypes, and compiles and packages the result with
+//! `torch._inductor.aoti_compile_and_package`.
+//!
+//! ```no_run
+//! use aoti_rs::export::{DynamicDim, PackageExporter};
+//!
+//! PackageExporter::new("resnet.pt")
+//!     .input(&[8, 3, 224, 224], tch::Kind::Float)
+//!     .dynamic(DynamicDim::new(0, 0, "batch").range(1, 64))
+//!     .export("resnet.pt2")?;
+//! # Ok::<(), aoti_rs::Error>(())
+//! ```
+//!
+//! TorchScript archives go through `torch._export.converter`, which
+//! doesn't support dynamic dimensions; export those from the original
+//! `nn.Module` instead.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use serde_json::json;
+use tch::Kind;
+
+use crate::Error;
+
+/// The export script; its single argument is the JSON job built by
+/// [`PackageExporter::job`].
+const SCRIPT: &str = r#"
+import json
+import sys
+
+import torch
+
+job = json.loads(sys.argv[1])
+if not hasattr(torch._inductor, "aoti_compile_and_package"):
+    sys.exit(f"torch {torch.__version__} is too old; 2.6 or newer is needed")
+device = torch.device(job["device"])
+
+try:
+    model, scripted = torch.jit.load(job["model"], map_location=device), True
+except RuntimeError:
+    model = torch.load(job["model"], map_location=device, weights_only=False)
+    scripted = False
+model.eval()
+
+def example(spec):
+    dtype = getattr(torch, spec["dtype"])
+    if dtype.is_floating_point:
+        return torch.randn(spec["shape"], dtype=dtype, device=device)
+    if dtype == torch.bool:
+        return torch.zeros(spec["shape"], dtype=dtype, device=device)
+    return torch.randint(0, 10, spec["shape"], dtype=dtype, device=device)
+
+args = tuple(example(spec) for spec in job["inputs"])
+dims = {}
+dynamic = [{} for _ in args]
+for d in job["dynamic"]:
+    if d["name"] not in dims:
+        dims[d["name"]] = torch.export.Dim(d["name"], min=d["min"], max=d["max"])
+    dynamic[d["input"]][d["dim"]] = dims[d["name"]]
+
+with torch.no_grad():
+    if scripted:
+        if dims:
+            sys.exit("dynamic dimensions aren't supported for TorchScript models")
+        from torch._export.converter import TS2EPConverter
+        program = TS2EPConverter(model, args).convert()
+    else:
+        shapes = tuple(d or None for d in dynamic) if dims else None
+        program = torch.export.export(model, args, dynamic_shapes=shapes)
+    torch._inductor.aoti_compile_and_package(program, package_path=job["package"])
+"#;
+
+/// Lines of the script's stderr kept in [`Error::Export`].
+const STDERR_TAIL: usize = 20;
+
+/// A dimension of an example input left symbolic in the export, so the
+/// package accepts other sizes for it. Dimensions given the same name
+/// must be equal at runtime, e.g. a batch shared by several inputs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DynamicDim {
+    /// Index of the input.
+    pub input: usize,
+    /// Index of the dimension within it.
+    pub dim: usize,
+    pub name: String,
+    /// Smallest and largest allowed size (default: unbounded).
+    pub range: Option<(i64, i64)>,
+}
+
+impl DynamicDim {
+    /// Dimension `dim` of input `input`, unbounded.
+    pub fn new(input: usize, dim: usize, name: impl Into<String>) -> Self {
+        Self {
+            input,
+            dim,
+            name: name.into(),
+            range: None,
+        }
+    }
+
+    /// Bound the dimension to `min..=max`.
+    pub fn range(mut self, min: i64, max: i64) -> Self {
+        self.range = Some((min, max));
+        self
+    }
+}
+
+/// Exports a saved model to a `.pt2` package; see the [module docs](self).
+#[derive(Debug, Clone)]
+pub struct PackageExporter {
+    model: PathBuf,
+    inputs: Vec<(Vec<i64>, Kind)>,
+    dynamic: Vec<DynamicDim>,
+    device: tch::Device,
+    python: PathBuf,
+}
+
+impl PackageExporter {
+    /// An exporter for the TorchScript archive or pickled module at
+    /// `model`, exporting on the CPU with `python3`.
+    pub fn new(model: impl Into<PathBuf>) -> Self {
+        Self {
+            model: model.into(),
+            inputs: Vec::new(),
+            dynamic: Vec::new(),
+            device: tch::Device::Cpu,
+            python: PathBuf::from("python3"),
+        }
+    }
+
+    /// Add an example input of this shape and dtype, in argument order.
+    pub fn input(mut self, shape: &[i64], kind: Kind) -> Self {
+        self.inputs.push((shape.to_vec(), kind));
+        self
+    }
+
+    /// Leave a dimension of an example input dynamic.
+    pub fn dynamic(mut self, dim: DynamicDim) -> Self {
+        self.dynamic.push(dim);
+        self
+    }
+
+    /// Export and compile for `device` (default: the CPU); the package
+    /// then loads on that kind of device.
+    pub fn device(mut self, device: tch::Device) -> Self {
+        self.device = device;
+        self
+    }
+
+    /// Run the script with this interpreter (default: `python3` on the
+    /// `PATH`), e.g. a virtualenv's.
+    pub fn python(mut self, python: impl Into<PathBuf>) -> Self {
+        self.python = python.into();
+        self
+    }
+
+    /// Export to a package at `package`. Fails with [`Error::Export`],
+    /// carrying the end of Python's error output, if the script does.
+    pub fn export(&self, package: impl AsRef<Path>) -> Result<(), Error> {
+        let job = self.job(package.as_ref())?;
+        run_script(&self.python, SCRIPT, &job.to_string())
+    }
+
+    /// The job passed to [`SCRIPT`], validating the dynamic dimensions
+    /// against the inputs.
+    fn job(&self, package: &Path) -> Result<serde_json::Value, Error> {
+        if self.inputs.is_empty() {
+            return Err(Error::InvalidInput("no example inputs were given".into()));
+        }
+        let inputs = self
+            .inputs
+            .iter()
+            .map(|(shape, kind)| Ok(json!({"shape": shape, "dtype": torch_dtype(*kind)?})))
+            .collect::<Result<Vec<_>, Error>>()?;
+        let dynamic = self
+            .dynamic
+            .iter()
+            .map(|d| {
+                let rank = self.inputs.get(d.input).map(|(shape, _)| shape.len());
+                if rank.is_none_or(|rank| d.dim >= rank) {
+                    return Err(Error::InvalidInput(format!(
+                        "dynamic dimension '{}' is dimension {} of input {}, which has no such \
+                         dimension",
+                        d.name, d.dim, d.input
+                    )));
+                }
+                let (min, max) = d.range.unzip();
+                Ok(json!({
+                    "input": d.input,
+                    "dim": d.dim,
+                    "name": d.name,
+                    "min": min,
+                    "max": max,
+                }))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        let device = match self.device {
+            tch::Device::Cpu => "cpu".to_string(),
+            tch::Device::Cuda(index) => format!("cuda:{index}"),
+            other => return Err(Error::InvalidInput(format!("can't export for {other:?}"))),
+        };
+        Ok(json!({
+            "model": self.model,
+            "package": package,
+            "device": device,
+            "inputs": inputs,
+            "dynamic": dynamic,
+        }))
+    }
+}
+
+#[cfg(feature = "proptest")]
+impl From<&PackageExporter> for crate::proptest::InputSpec {
+    /// The exporter's inputs and dynamic dimensions. Unbounded dimensions
+    /// range from 2, as `torch.export` specializes sizes 0 and 1, to
+    /// twice the example size.
+    fn from(exporter: &PackageExporter) -> Self {
+        let spec = exporter
+            .inputs
+            .iter()
+            .fold(Self::new(), |spec, (shape, kind)| spec.input(shape, *kind));
+        exporter.dynamic.iter().fold(spec, |spec, d| {
+            let example = exporter
+                .inputs
+                .get(d.input)
+                .and_then(|(shape, _)| shape.get(d.dim))
+                .copied()
+                .unwrap_or(1);
+            let (min, max) = d.range.unwrap_or((2, (2 * example).max(2)));
+            spec.dynamic(d.input, d.dim, d.name.clone(), min..=max)
+        })
+    }
+}
+
+/// The `torch` attribute naming `kind`.
+/// Run `python -c script arg`, failing with [`Error::Export`] and the end
+/// of its error output if it does.
+pub(crate) fn run_script(python: &Path, script: &str, arg: &str) -> Result<(), Error> {
+    let output = Command::new(python)
+        .arg("-c")
+        .arg(script)
+        .arg(arg)
+        .output()
+        .map_err(|e| Error::Export(format!("couldn't run {}: {e}", python.display())))?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let lines: Vec<_> = stderr.trim_end().lines().collect();
+    let tail = lines[lines.len().saturating_sub(STDERR_TAIL)..].join("\n");
+    Err(Error::Export(format!("{}: {tail}", output.status)))
+}
+
+fn torch_dtype(kind: Kind) -> Result<&'static str, Error> {
+    Ok(match kind {
+        Kind::Half => "float16",
+        Kind::BFloat16 => "bfloat16",
+        Kind::Float => "float32",
+        Kind::Double => "float64",
+        Kind::Int8 => "int8",
+        Kind::Uint8 => "uint8",
+        Kind::Int16 => "int16",
+        Kind::Int => "int32",
+        Kind::Int64 => "int64",
+        Kind::Bool => "bool",
+        _ => return Err(Error::UnsupportedDtype(format!("torch {kind:?}"))),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jobs_describe_inputs_and_dynamic_dims() {
+        let exporter = PackageExporter::new("m.pt")
+            .input(&[8, 16], Kind::Float)
+            .input(&[8], Kind::Int64)
+            .dynamic(DynamicDim::new(0, 0, "batch").range(1, 64))
+            .dynamic(DynamicDim::new(1, 0, "batch"));
+        let job = exporter.job(Path::new("m.pt2")).unwrap();
+        assert_eq!(job["device"], "cpu");
+        assert_eq!(job["inputs"][1], json!({"shape": [8], "dtype": "int64"}));
+        assert_eq!(
+            job["dynamic"][0],
+            json!({"input": 0, "dim": 0, "name": "batch", "min": 1, "max": 64})
+        );
+        assert_eq!(job["dynamic"][1]["max"], serde_json::Value::Null);
+
+        let out_of_range = exporter.dynamic(DynamicDim::new(1, 1, "seq"));
+        assert!(matches!(
+            out_of_range.job(Path::new("m.pt2")),
+            Err(Error::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn a_missing_interpreter_is_an_export_error() {
+        let result = PackageExporter::new("m.pt")
+            .input(&[1], Kind::Float)
+            .python("/nonexistent/python3")
+            .export("m.pt2");
+        assert!(matches!(result, Err(Error::Export(_))));
+    }
+}
@@ -0,0 +1,621 @@
+//! A pure-Rust stand-in for the AOTI runtime (feature `fake`), so code built
+//! on [`AOTIModel`](crate::AOTIModel) — pools, the registry, the servers —
+//! can be tested without the C++ bridge or an installed libtorch (the
+//! feature has tch download a CPU build).
+//!
+//! With the feature on, every model is fake. [`FakeModel::write`] saves a
+//! `.pt2` whose wrapper library only names a registered [`FakeModel`], and
+//! loading it gives a runner that computes outputs with a Rust closure.
+//! [`Faults`] injects load and run errors and latency, and can be changed
+//! while the model is loaded:
+//!
+//! ```ignore
+//! let model = FakeModel::new()
+//!     .forward(|inputs, _| Ok(vec![inputs[0].f_mul_scalar(2)?]))
+//!     .call_spec(in_spec, out_spec);
+//! let faults = model.faults();
+//! model.write(&path, "model")?;
+//! let pool = AOTIModelPool::from_fn(2, |_| AOTIModel::<Cpu>::load(&path))?;
+//! faults.fail_runs(3, "CUDA error: out of memory");
+//! faults.latency(Duration::from_millis(50));
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock, Mutex, PoisonError};
+use std::time::Duration;
+
+use tch::Tensor;
+
+use crate::Error;
+
+/// Computes a fake model's outputs from its inputs and active constants.
+type Forward =
+    dyn Fn(&[Tensor], &HashMap<String, Tensor>) -> Result<Vec<Tensor>, Error> + Send + Sync;
+
+/// Written models, by the id in their package's wrapper library.
+static MODELS: LazyLock<Mutex<HashMap<u64, Arc<Registered>>>> = LazyLock::new(Default::default);
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// What a fake wrapper library contains, followed by the model's id.
+const MAGIC: &str = "aoti-rs fake model ";
+
+struct Registered {
+    forward: Box<Forward>,
+    call_spec: Vec<String>,
+    constants: Mutex<Vec<(String, Tensor)>>,
+    faults: Faults,
+}
+
+/// A model for the fake runtime: by default it returns a copy of each
+/// input, has no constants and an empty call spec (so its input count
+/// isn't checked), and targets the CPU.
+pub struct FakeModel {
+    forward: Box<Forward>,
+    call_spec: Vec<String>,
+    constants: Vec<(String, Tensor)>,
+    metadata: BTreeMap<String, String>,
+    faults: Faults,
+}
+
+impl Default for FakeModel {
+    fn default() -> Self {
+        Self {
+            forward: Box::new(|inputs, _| Ok(inputs.iter().map(Tensor::copy).collect())),
+            call_spec: Vec::new(),
+            constants: Vec::new(),
+            metadata: BTreeMap::from([("AOTI_DEVICE_KEY".into(), "cpu".into())]),
+            faults: Faults::default(),
+        }
+    }
+}
+
+impl FakeModel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compute the outputs with `forward`, given the inputs and the active
+    /// constants by name. Its errors are returned from the run.
+    pub fn forward(
+        mut self,
+        forward: impl Fn(&[Tensor], &HashMap<String, Tensor>) -> Result<Vec<Tensor>, Error>
+        + Send
+        + Sync
+        + 'static,
+    ) -> Self {
+        self.forward = Box::new(forward);
+        self
+    }
+
+    /// Return a copy of `outputs` from every run.
+    pub fn outputs(self, outputs: Vec<Tensor>) -> Self {
+        let outputs = Mutex::new(outputs);
+        self.forward(move |_, _| {
+            let outputs = outputs.lock().unwrap_or_else(PoisonError::into_inner);
+            Ok(outputs.iter().map(Tensor::copy).collect())
+        })
+    }
+
+    /// The serialized pytree specs `get_call_spec` returns. Runs are
+    /// checked against the input count of `in_spec`, as for real packages.
+    pub fn call_spec(mut self, in_spec: impl Into<String>, out_spec: impl Into<String>) -> Self {
+        self.call_spec = vec![in_spec.into(), out_spec.into()];
+        self
+    }
+
+    /// Add a constant. Each loaded runner gets its own copy, in both
+    /// constant buffers.
+    pub fn constant(mut self, fqn: impl Into<String>, value: Tensor) -> Self {
+        self.constants.push((fqn.into(), value));
+        self
+    }
+
+    /// Set a key in the package's metadata, e.g. `AOTI_DEVICE_KEY`.
+    pub fn metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.metadata.insert(key.into(), value.into());
+        self
+    }
+
+    /// The model's fault injection, shared by every runner loaded from it.
+    pub fn faults(&self) -> Faults {
+        self.faults.clone()
+    }
+
+    /// Register the model and write a package for it to `path`, under
+    /// `model_name`. The package only loads in this process.
+    pub fn write(self, path: impl AsRef<Path>, model_name: &str) -> Result<(), Error> {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let dir = format!("{model_name}/data/aotinductor/model");
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(path)?);
+        let options = zip::write::SimpleFileOptions::default();
+        zip.start_file(format!("{dir}/{model_name}.wrapper.so"), options)?;
+        write!(zip, "{MAGIC}{id}")?;
+        zip.start_file(format!("{dir}/{model_name}_metadata.json"), options)?;
+        serde_json::to_writer(&mut zip, &self.metadata)?;
+        zip.finish()?;
+        MODELS
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(
+                id,
+                Arc::new(Registered {
+                    forward: self.forward,
+                    call_spec: self.call_spec,
+                    constants: Mutex::new(self.constants),
+                    faults: self.faults,
+                }),
+            );
+        Ok(())
+    }
+}
+
+/// Errors and latency injected into a [`FakeModel`]'s loads and runs.
+/// Injected errors are [`Error::Model`]s, which pools treat like runtime
+/// failures: a message containing "out of memory" is classed as
+/// [`ErrorClass::OutOfMemory`](crate::ErrorClass::OutOfMemory).
+#[derive(Debug, Clone, Default)]
+pub struct Faults(Arc<Mutex<FaultState>>);
+
+#[derive(Debug, Default)]
+struct FaultState {
+    failing_loads: usize,
+    load_error: String,
+    failing_runs: usize,
+    run_error: String,
+    latency: Duration,
+    runs: usize,
+}
+
+impl Faults {
+    /// Fail the next `n` loads with `message`.
+    pub fn fail_loads(&self, n: usize, message: impl Into<String>) {
+        let mut state = self.lock();
+        state.failing_loads = n;
+        state.load_error = message.into();
+    }
+
+    /// Fail the next `n` runs with `message`, after their latency.
+    pub fn fail_runs(&self, n: usize, message: impl Into<String>) {
+        let mut state = self.lock();
+        state.failing_runs = n;
+        state.run_error = message.into();
+    }
+
+    /// Make each run take at least `latency`.
+    pub fn latency(&self, latency: Duration) {
+        self.lock().latency = latency;
+    }
+
+    /// Runs started so far, failed ones included.
+    pub fn runs(&self) -> usize {
+        self.lock().runs
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, FaultState> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn load(&self) -> Result<(), Error> {
+        let mut state = self.lock();
+        if state.failing_loads == 0 {
+            return Ok(());
+        }
+        state.failing_loads -= 1;
+        Err(Error::Model(state.load_error.clone()))
+    }
+
+    fn run(&self) -> Result<(), Error> {
+        let (latency, result) = {
+            let mut state = self.lock();
+            state.runs += 1;
+            let result = if state.failing_runs == 0 {
+                Ok(())
+            } else {
+                state.failing_runs -= 1;
+                Err(Error::Model(state.run_error.clone()))
+            };
+            (state.latency, result)
+        };
+        std::thread::sleep(latency);
+        result
+    }
+}
+
+/// The cxx bridge's interface, implemented over [`FakeModel`]s.
+pub(crate) mod ffi {
+    // The signatures match the bridge's, `&Vec` arguments included.
+    #![allow(clippy::ptr_arg)]
+
+    use std::collections::HashMap;
+    #[cfg(unix)]
+    use std::ffi::OsStr;
+    #[cfg(unix)]
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+    use std::pin::Pin;
+    use std::sync::{Arc, PoisonError};
+
+    use tch::Tensor;
+
+    use super::{MAGIC, MODELS, Registered};
+    use crate::Error;
+
+    pub(crate) use std::ffi::c_void;
+
+    /// Stands in for `cxx::Exception`, so `AOTIModel` converts errors the
+    /// same way with either runtime.
+    pub(crate) struct FfiError(Error);
+
+    impl From<Error> for FfiError {
+        fn from(err: Error) -> Self {
+            Self(err)
+        }
+    }
+
+    impl From<std::io::Error> for FfiError {
+        fn from(err: std::io::Error) -> Self {
+            Self(err.into())
+        }
+    }
+
+    impl From<FfiError> for Error {
+        fn from(err: FfiError) -> Self {
+            err.0
+        }
+    }
+
+    pub(crate) struct UniquePtr<T>(Box<T>);
+
+    impl<T: Unpin> UniquePtr<T> {
+        pub(crate) fn pin_mut(&mut self) -> Pin<&mut T> {
+            Pin::new(&mut self.0)
+        }
+    }
+
+    pub(crate) struct TensorPtr {
+        pub(crate) ptr: *const c_void,
+    }
+
+    pub(crate) struct OwnedTensor {
+        pub(crate) ptr: *mut c_void,
+    }
+
+    pub(crate) struct NamedTensor {
+        pub(crate) name: String,
+        pub(crate) tensor: OwnedTensor,
+    }
+
+    pub(crate) struct NamedTensorPtr {
+        pub(crate) name: String,
+        pub(crate) tensor: TensorPtr,
+    }
+
+    pub(crate) struct AOTIModelContainerRunner {
+        model: Arc<Registered>,
+        /// The active and inactive constant buffers.
+        constants: [HashMap<String, Tensor>; 2],
+        active: usize,
+    }
+
+    /// A deep copy of a constant buffer.
+    fn copy(constants: &HashMap<String, Tensor>) -> HashMap<String, Tensor> {
+        constants
+            .iter()
+            .map(|(name, tensor)| (name.clone(), tensor.copy()))
+            .collect()
+    }
+
+    /// Hand `tensor` over to `Tensor::from_ptr` on the other side.
+    fn into_ptr(mut tensor: Tensor) -> *mut c_void {
+        let ptr = tensor.as_mut_ptr() as *mut c_void;
+        std::mem::forget(tensor);
+        ptr
+    }
+
+    /// # Safety
+    /// `ptr` must come from `Tensor::as_ptr` on a live tensor.
+    unsafe fn borrow(ptr: &TensorPtr) -> Tensor {
+        unsafe { Tensor::clone_from_ptr(ptr.ptr as *mut _) }
+    }
+
+    pub(crate) fn runner_new(
+        model_so_path: &[u8],
+        _cubin_dir: &[u8],
+        _is_cuda: bool,
+        _device_index: i8,
+        _num_runners: usize,
+        _run_single_threaded: bool,
+    ) -> Result<UniquePtr<AOTIModelContainerRunner>, FfiError> {
+        #[cfg(unix)]
+        let model_so_path = Path::new(OsStr::from_bytes(model_so_path));
+        #[cfg(not(unix))]
+        let model_so_path = Path::new(
+            std::str::from_utf8(model_so_path).map_err(|e| Error::InvalidPath(e.to_string()))?,
+        );
+        let contents = std::fs::read(model_so_path)?;
+        let model = std::str::from_utf8(&contents)
+            .ok()
+            .and_then(|contents| contents.strip_prefix(MAGIC)?.parse::<u64>().ok())
+            .and_then(|id| {
+                let models = MODELS.lock().unwrap_or_else(PoisonError::into_inner);
+                models.get(&id).cloned()
+            })
+            .ok_or_else(|| {
+                Error::Model(format!(
+                    "{} is not a FakeModel package; with feature `fake` no other \
+                     package loads",
+                    model_so_path.display()
+                ))
+            })?;
+        model.faults.load()?;
+        let active: HashMap<String, Tensor> = model
+            .constants
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .map(|(name, tensor)| (name.clone(), tensor.copy()))
+            .collect();
+        let inactive = copy(&active);
+        Ok(UniquePtr(Box::new(AOTIModelContainerRunner {
+            model,
+            constants: [active, inactive],
+            active: 0,
+        })))
+    }
+
+    pub(crate) fn runner_run(
+        runner: Pin<&mut AOTIModelContainerRunner>,
+        inputs: &Vec<TensorPtr>,
+    ) -> Result<Vec<OwnedTensor>, FfiError> {
+        let runner = runner.get_mut();
+        runner.model.faults.run()?;
+        // SAFETY: `tensors_to_ptrs` points at inputs the caller keeps alive
+        // for the call.
+        let inputs: Vec<Tensor> = inputs.iter().map(|ptr| unsafe { borrow(ptr) }).collect();
+        let outputs = (runner.model.forward)(&inputs, &runner.constants[runner.active])?;
+        Ok(outputs
+            .into_iter()
+            .map(|tensor| OwnedTensor {
+                ptr: into_ptr(tensor),
+            })
+            .collect())
+    }
+
+    pub(crate) fn runner_boxed_run(
+        runner: Pin<&mut AOTIModelContainerRunner>,
+        inputs: &mut Vec<TensorPtr>,
+    ) -> Result<Vec<OwnedTensor>, FfiError> {
+        runner_run(runner, inputs)
+    }
+
+    pub(crate) fn runner_get_call_spec(
+        runner: Pin<&mut AOTIModelContainerRunner>,
+    ) -> Result<Vec<String>, FfiError> {
+        Ok(runner.model.call_spec.clone())
+    }
+
+    pub(crate) fn runner_get_constant_fqns(
+        runner: Pin<&mut AOTIModelContainerRunner>,
+    ) -> Result<Vec<String>, FfiError> {
+        let mut fqns: Vec<String> = runner.constants[runner.active].keys().cloned().collect();
+        fqns.sort();
+        Ok(fqns)
+    }
+
+    pub(crate) fn runner_get_constants(
+        runner: Pin<&mut AOTIModelContainerRunner>,
+    ) -> Result<Vec<NamedTensor>, FfiError> {
+        Ok(runner.constants[runner.active]
+            .iter()
+            .map(|(name, tensor)| NamedTensor {
+                name: name.clone(),
+                tensor: OwnedTensor {
+                    ptr: into_ptr(tensor.shallow_clone()),
+                },
+            })
+            .collect())
+    }
+
+    /// Like the runtime, values are copied in, and an inactive update
+    /// starts from the active buffer.
+    pub(crate) fn runner_update_constant_buffer(
+        runner: Pin<&mut AOTIModelContainerRunner>,
+        constants: &Vec<NamedTensorPtr>,
+        use_inactive: bool,
+        _validate_full_update: bool,
+    ) -> Result<(), FfiError> {
+        let runner = runner.get_mut();
+        let mut updated = copy(&runner.constants[runner.active]);
+        for named in constants {
+            if !updated.contains_key(&named.name) {
+                return Err(Error::Model(format!(
+                    "the model has no constant named {}",
+                    named.name
+                ))
+                .into());
+            }
+            // SAFETY: the caller keeps the constants alive for the call.
+            let value = unsafe { borrow(&named.tensor) }.copy();
+            updated.insert(named.name.clone(), value);
+        }
+        let target = if use_inactive {
+            1 - runner.active
+        } else {
+            runner.active
+        };
+        runner.constants[target] = updated;
+        Ok(())
+    }
+
+    pub(crate) fn runner_swap_constant_buffer(
+        runner: Pin<&mut AOTIModelContainerRunner>,
+    ) -> Result<(), FfiError> {
+        let runner = runner.get_mut();
+        runner.active = 1 - runner.active;
+        Ok(())
+    }
+
+    /// A no-op: the fake runtime is deterministic and doesn't use TF32.
+    pub(crate) fn set_deterministic(_enabled: bool, _warn_only: bool) -> Result<(), FfiError> {
+        Ok(())
+    }
+
+    pub(crate) fn set_allow_tf32(_allowed: bool) -> Result<(), FfiError> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use tch::Kind;
+
+    use crate::{AOTIModel, Cpu, DeviceTensor, Dim, ErrorClass, OutputAlias, OutputSchema};
+
+    fn input() -> DeviceTensor<Cpu> {
+        DeviceTensor::try_new(Tensor::from_slice(&[1.0f32, 3.0])).unwrap()
+    }
+
+    #[test]
+    fn fake_packages_load_and_run_through_the_model_api() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("scale.pt2");
+        let leaf = r#"{"type": null, "context": null, "children_spec": []}"#;
+        FakeModel::new()
+            .forward(|inputs, constants| Ok(vec![inputs[0].f_mul(&constants["scale"])?]))
+            .constant("scale", Tensor::from_slice(&[2.0f32]))
+            .call_spec(
+                format!(r#"[1, {{"type": "builtins.tuple", "context": "null", "children_spec": [{leaf}]}}]"#),
+                "[1, {}]",
+            )
+            .write(&path, "scale")
+            .unwrap();
+        let mut model = AOTIModel::<Cpu>::builder(&path)
+            .model_name("scale")
+            .build()
+            .unwrap();
+        assert_eq!(model.get_metadata().unwrap()["AOTI_DEVICE_KEY"], "cpu");
+        assert_eq!(model.get_constant_fqns().unwrap(), ["scale"]);
+        assert!(matches!(
+            model.run(Vec::<DeviceTensor<Cpu>>::new()),
+            Err(Error::InvalidInput(_))
+        ));
+        assert_eq!(model.run(&[input()]).unwrap()[0].double_value(&[1]), 6.0);
+        let tracked = || {
+            let x = Tensor::from_slice(&[1.0f32, 3.0]).set_requires_grad(true);
+            DeviceTensor::try_new(x).unwrap()
+        };
+        assert!(matches!(
+            model.run(&[tracked()]),
+            Err(Error::InvalidInput(_))
+        ));
+        let mut detaching = AOTIModel::<Cpu>::builder(&path)
+            .model_name("scale")
+            .detach_grad_inputs(true)
+            .build()
+            .unwrap();
+        let outputs = detaching.run(vec![tracked()]).unwrap();
+        assert!(!outputs[0].requires_grad());
+
+        let pair = DeviceTensor::try_new(Tensor::from_slice(&[3.0f32, 3.0])).unwrap();
+        assert!(matches!(
+            model.update_inactive_constants(&HashMap::from([("scale".to_string(), pair)])),
+            Err(Error::InvalidInput(_))
+        ));
+        let three = DeviceTensor::try_new(Tensor::from_slice(&[3.0f32])).unwrap();
+        model
+            .update_inactive_constants(&HashMap::from([("scale".to_string(), three)]))
+            .unwrap();
+        assert_eq!(model.run(vec![input()]).unwrap()[0].double_value(&[1]), 6.0);
+        model.swap_constants().unwrap();
+        assert_eq!(model.run(vec![input()]).unwrap()[0].double_value(&[1]), 9.0);
+    }
+
+    #[test]
+    fn outputs_aliasing_inputs_are_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("view.pt2").to_string_lossy().into_owned();
+        FakeModel::new()
+            .forward(|inputs, _| Ok(vec![inputs[0].copy(), inputs[0].slice(0, 1, 2, 1)]))
+            .write(&path, "model")
+            .unwrap();
+        let mut model = AOTIModel::<Cpu>::builder(&path)
+            .check_output_aliasing(true)
+            .build()
+            .unwrap();
+        model.run(&[input()]).unwrap();
+        assert_eq!(
+            model.output_aliases(),
+            [OutputAlias {
+                output: 1,
+                input: 0
+            }]
+        );
+        model.run(vec![input()]).unwrap();
+        assert_eq!(model.output_aliases().len(), 1);
+        assert_eq!(model.stats().aliased_runs, 2);
+    }
+
+    // Other Unix file systems may refuse names that aren't UTF-8.
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn packages_load_from_paths_that_are_not_utf8() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir
+            .path()
+            .join(std::ffi::OsStr::from_bytes(b"model-\xff.pt2"));
+        FakeModel::new().write(&path, "model").unwrap();
+        assert_eq!(crate::package_models(&path).unwrap(), ["model"]);
+        let mut model = AOTIModel::<Cpu>::try_from(path.as_path()).unwrap();
+        assert_eq!(model.run(&[input()]).unwrap()[0].double_value(&[1]), 3.0);
+    }
+
+    #[test]
+    fn outputs_are_checked_against_the_declared_schema() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("echo.pt2").to_string_lossy().into_owned();
+        FakeModel::new().write(&path, "model").unwrap();
+        let mut model = AOTIModel::<Cpu>::builder(&path)
+            .output_schema(OutputSchema::new().output([Dim::Any], Kind::Float))
+            .build()
+            .unwrap();
+        model.run(&[input()]).unwrap();
+        model.set_output_schema(Some(OutputSchema::new().output([Dim::Any], Kind::Half)));
+        assert!(matches!(
+            model.run(vec![input()]),
+            Err(Error::OutputSchemaMismatch(_))
+        ));
+        assert_eq!(model.stats().runs, 2);
+    }
+
+    #[test]
+    fn faults_fail_loads_and_runs_and_add_latency() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("echo.pt2").to_string_lossy().into_owned();
+        let fake = FakeModel::new();
+        let faults = fake.faults();
+        fake.write(&path, "model").unwrap();
+
+        faults.fail_loads(1, "corrupt package");
+        assert!(AOTIModel::<Cpu>::load(&path).is_err());
+        let mut model = AOTIModel::<Cpu>::load(&path).unwrap();
+
+        faults.fail_runs(1, "CUDA error: out of memory");
+        faults.latency(Duration::from_millis(20));
+        let err = model.run(&[input()]).unwrap_err();
+        assert_eq!(ErrorClass::of(&err), ErrorClass::OutOfMemory);
+        let started = Instant::now();
+        let outputs = model.run(&[input()]).unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(20));
+        assert_eq!(outputs[0].double_value(&[1]), 3.0);
+        assert_eq!(faults.runs(), 2);
+        assert_eq!(model.stats().failures, 1);
+    }
+}
@@ -0,0 +1,70 @@
+//! Entry points for the cargo-fuzz targets under `fuzz/` (feature `fuzz`).
+//!
+//! The targets feed arbitrary bytes to the checks and parsers that stand
+//! between untrusted inputs and the C++ runtime: input validation, call
+//! spec and metadata parsing. [`FuzzTensor`] turns fuzzer data into real
+//! tensors with arbitrary dtypes, sizes, strides and offsets, including
+//! zero-sized dimensions and undefined tensors.
+
+use arbitrary::Arbitrary;
+use tch::{Kind, Tensor};
+
+use crate::Error;
+
+const KINDS: [Kind; 6] = [
+    Kind::Float,
+    Kind::Half,
+    Kind::Int64,
+    Kind::Int,
+    Kind::Uint8,
+    Kind::Bool,
+];
+
+/// A strided view over a small zeroed storage.
+#[derive(Debug, Clone, Arbitrary)]
+pub struct FuzzTensor {
+    /// Index into a few common dtypes.
+    pub kind: u8,
+    /// Elements in the storage.
+    pub storage: u8,
+    /// The view's storage offset.
+    pub offset: u8,
+    /// Sizes, each taken modulo 8; the rank is the shorter of these and
+    /// the strides, at most 6.
+    pub sizes: Vec<u8>,
+    pub strides: Vec<i8>,
+    pub undefined: bool,
+}
+
+impl FuzzTensor {
+    /// The view, or `None` if libtorch rejects it (e.g. it runs past the
+    /// storage or has negative strides).
+    pub fn build(&self) -> Option<Tensor> {
+        if self.undefined {
+            return Some(Tensor::new());
+        }
+        let kind = KINDS[self.kind as usize % KINDS.len()];
+        let rank = self.sizes.len().min(self.strides.len()).min(6);
+        let sizes: Vec<i64> = self.sizes[..rank].iter().map(|&s| (s % 8) as i64).collect();
+        let strides: Vec<i64> = self.strides[..rank].iter().map(|&s| s as i64).collect();
+        Tensor::f_zeros([self.storage as i64], (kind, tch::Device::Cpu))
+            .and_then(|storage| storage.f_as_strided(&sizes, &strides, self.offset as i64))
+            .ok()
+    }
+}
+
+/// Reject inputs as [`AOTIModel::run`](crate::AOTIModel::run) does
+/// before calling into the runtime.
+pub fn check_inputs(inputs: &[Tensor], expected: Option<usize>) -> Result<(), Error> {
+    crate::validate::check_inputs(inputs.iter(), expected)
+}
+
+/// The input count a serialized pytree `in_spec` describes.
+pub fn input_count(in_spec: &str) -> Result<usize, Error> {
+    crate::validate::input_count(in_spec)
+}
+
+/// Parse a package's `*_metadata.json`.
+pub fn parse_metadata(bytes: &[u8]) -> Result<crate::ModelMetadata, Error> {
+    Ok(crate::parse_metadata_json(bytes)?.into())
+}
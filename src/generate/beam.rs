@@ -0,0 +1,259 @@
+//! Beam search over a [`Generator`]'s decoder.
+
+use std::time::Instant;
+
+use tch::{Kind, Tensor};
+
+use crate::generate::{Decode, Generator};
+use crate::{Device, Error};
+
+/// Beam search settings for [`Generator::beam_search`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct BeamSearch {
+    /// Hypotheses kept per step.
+    pub width: usize,
+    /// Exponent of the length a hypothesis' log-probability is divided by:
+    /// above 0 favors longer outputs, below 0 shorter ones (default: 1).
+    pub length_penalty: f64,
+    /// Stop as soon as `width` hypotheses have ended, instead of once no
+    /// running beam can beat them (default: false).
+    pub early_stopping: bool,
+}
+
+impl BeamSearch {
+    pub fn new(width: usize) -> Self {
+        Self {
+            width,
+            length_penalty: 1.0,
+            early_stopping: false,
+        }
+    }
+
+    pub fn length_penalty(mut self, penalty: f64) -> Self {
+        self.length_penalty = penalty;
+        self
+    }
+
+    pub fn early_stopping(mut self, early_stopping: bool) -> Self {
+        self.early_stopping = early_stopping;
+        self
+    }
+
+    /// A hypothesis' score from its summed log-probability and length.
+    fn score(&self, logprob: f64, len: usize) -> f64 {
+        logprob / (len.max(1) as f64).powf(self.length_penalty)
+    }
+}
+
+/// A beam-search result.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hypothesis {
+    /// The generated tokens, ending with an end-of-sequence token or stop
+    /// sequence unless the search ran out of tokens or time first.
+    pub tokens: Vec<i64>,
+    /// Summed log-probability over the length penalty.
+    pub score: f64,
+}
+
+impl<D: Device, M: Decode<D>> Generator<D, M> {
+    /// Beam search from `prompt`, returning up to `search.width`
+    /// hypotheses, best first. The cache holds one beam per sequence, so
+    /// its batch must equal the width. The [`GenerateConfig`]'s stop
+    /// criteria end hypotheses and `max_time` the search; its sampling is
+    /// ignored. The processors apply to each beam.
+    ///
+    /// Seq2seq decoders that take encoder states can be wrapped in a
+    /// [`Decode`] implementation that appends them, repeated per beam.
+    ///
+    /// [`GenerateConfig`]: crate::generate::GenerateConfig
+    pub fn beam_search(
+        &mut self,
+        prompt: &[i64],
+        search: &BeamSearch,
+    ) -> Result<Vec<Hypothesis>, Error> {
+        let width = search.width;
+        if width == 0 || width != self.cache().config().batch {
+            return Err(Error::InvalidInput(format!(
+                "a beam width of {width} needs a cache batch of the same size, not {}",
+                self.cache().config().batch
+            )));
+        }
+        let mut ids = self.start(&vec![prompt.to_vec(); width])?;
+        let config = self.config().clone();
+        let started = Instant::now();
+        // Running beams' tokens and summed log-probabilities. They start
+        // out identical, so only the first is expanded at the first step.
+        let mut beams: Vec<(Vec<i64>, f64)> = (0..width)
+            .map(|i| (Vec::new(), if i == 0 { 0.0 } else { f64::NEG_INFINITY }))
+            .collect();
+        let mut ended: Vec<Hypothesis> = Vec::new();
+        for _ in 0..config.max_new_tokens {
+            if config.timed_out(started) {
+                break;
+            }
+            let logits = self.forward(&ids)?;
+            let history: Vec<Vec<i64>> = beams
+                .iter()
+                .map(|(tokens, _)| [prompt, tokens].concat())
+                .collect();
+            let logits = self.process(logits, &history)?;
+            let vocab = logits.size()[1];
+            let running: Vec<f64> = beams.iter().map(|(_, logprob)| *logprob).collect();
+            let running = Tensor::from_slice(&running)
+                .f_to_device(logits.device())?
+                .f_unsqueeze(1)?;
+            let candidates = logits
+                .f_log_softmax(-1, Kind::Double)?
+                .f_add(&running)?
+                .f_flatten(0, -1)?;
+            // Twice the width, so `width` beams go on even if all the
+            // others end here.
+            let k = (2 * width as i64).min(candidates.size()[0]);
+            let (values, indices) = candidates.f_topk(k, -1, true, true)?;
+            let values = Vec::<f64>::try_from(&values.f_to_device(tch::Device::Cpu)?)?;
+            let indices = Vec::<i64>::try_from(&indices.f_to_device(tch::Device::Cpu)?)?;
+
+            // Each surviving beam's parent and its tokens and log-probability.
+            let mut next: Vec<(usize, (Vec<i64>, f64))> = Vec::with_capacity(width);
+            for (rank, (&logprob, &index)) in values.iter().zip(&indices).enumerate() {
+                if logprob == f64::NEG_INFINITY || next.len() == width {
+                    break;
+                }
+                let parent = (index / vocab) as usize;
+                let mut tokens = beams[parent].0.clone();
+                tokens.push(index % vocab);
+                if config.stop_reason(&[prompt, &tokens].concat()).is_none() {
+                    next.push((parent, (tokens, logprob)));
+                } else if rank < width {
+                    ended.push(Hypothesis {
+                        score: search.score(logprob, tokens.len()),
+                        tokens,
+                    });
+                }
+            }
+            let Some((parent, (tokens, _))) = next.first().cloned() else {
+                beams.clear();
+                break;
+            };
+            // Fill the batch with dead copies if too few beams go on.
+            next.resize(width, (parent, (tokens, f64::NEG_INFINITY)));
+
+            let (parents, survivors): (Vec<usize>, Vec<_>) = next.into_iter().unzip();
+            self.cache_mut().reorder(&parents)?;
+            beams = survivors;
+            let last: Vec<i64> = beams
+                .iter()
+                .map(|(tokens, _)| tokens.last().copied().unwrap_or_default())
+                .collect();
+            ids = Tensor::from_slice(&last).f_unsqueeze(1)?;
+
+            if ended.len() >= width {
+                sort(&mut ended, width);
+                let worst = ended[width - 1].score;
+                let (tokens, best) = &beams[0];
+                if search.early_stopping || search.score(*best, tokens.len()) < worst {
+                    break;
+                }
+            }
+        }
+        ended.extend(
+            beams
+                .into_iter()
+                .filter(|(_, logprob)| *logprob > f64::NEG_INFINITY)
+                .map(|(tokens, logprob)| Hypothesis {
+                    score: search.score(logprob, tokens.len()),
+                    tokens,
+                }),
+        );
+        sort(&mut ended, width);
+        Ok(ended)
+    }
+}
+
+/// Keep the best `width` hypotheses, best first.
+fn sort(hypotheses: &mut Vec<Hypothesis>, width: usize) {
+    hypotheses.sort_by(|a, b| b.score.total_cmp(&a.score));
+    hypotheses.truncate(width);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::marker::PhantomData;
+
+    use super::*;
+    use crate::generate::{GenerateConfig, KvCache, KvCacheConfig};
+    use crate::{Cpu, DeviceTensor};
+
+    fn wrap(tensor: Tensor) -> DeviceTensor<Cpu> {
+        DeviceTensor {
+            tensor,
+            _device: PhantomData,
+        }
+    }
+
+    /// A Markov chain over four tokens, 3 being end-of-sequence: row `i`
+    /// holds the next-token probabilities after token `i`.
+    struct Chain;
+
+    const CHAIN: [[f32; 4]; 4] = [
+        [1e-9, 0.5, 0.4, 0.1],
+        [0.35, 0.25, 0.2, 0.2],
+        [0.05, 0.025, 0.025, 0.9],
+        [0.25, 0.25, 0.25, 0.25],
+    ];
+
+    impl Decode<Cpu> for Chain {
+        fn decode(
+            &mut self,
+            inputs: &[DeviceTensor<Cpu>],
+            cache: &mut KvCache<Cpu>,
+        ) -> Result<Vec<DeviceTensor<Cpu>>, Error> {
+            let [batch, steps] = inputs[0].size()[..] else {
+                unreachable!()
+            };
+            let table = Tensor::from_slice(CHAIN.as_flattened())
+                .f_view([4, 4])?
+                .f_log()?;
+            let logits = table
+                .f_index_select(0, &inputs[0].f_flatten(0, -1)?)?
+                .f_view([batch, steps, 4])?;
+            let entry = || {
+                wrap(Tensor::zeros(
+                    [batch, 1, steps, 1],
+                    (Kind::Float, tch::Device::Cpu),
+                ))
+            };
+            cache.update(vec![entry(), entry()])?;
+            Ok(vec![wrap(logits)])
+        }
+    }
+
+    fn generator(batch: usize) -> Generator<Cpu, Chain> {
+        let cache = KvCache::new(
+            KvCacheConfig::new(1, 1, 1, 8).batch(batch),
+            tch::Device::Cpu,
+        )
+        .unwrap();
+        Generator::new(Chain, cache, GenerateConfig::new(3).eos([3]))
+    }
+
+    #[test]
+    fn beams_find_likelier_outputs_than_greedy_decoding() {
+        assert_eq!(generator(1).generate(&[vec![0]]).unwrap(), [vec![1, 0, 1]]);
+
+        let search = BeamSearch::new(2).length_penalty(0.0);
+        let found = generator(2).beam_search(&[0], &search).unwrap();
+        let tokens: Vec<_> = found.iter().map(|h| h.tokens.clone()).collect();
+        assert_eq!(tokens, [vec![2, 3], vec![1, 0, 1]]);
+        assert!((found[0].score - 0.36f64.ln()).abs() < 1e-5);
+        assert!((found[1].score - 0.0875f64.ln()).abs() < 1e-5);
+    }
+
+    #[test]
+    fn beam_width_must_match_the_cache_batch() {
+        assert!(generator(2).beam_search(&[0], &BeamSearch::new(3)).is_err());
+        assert!(generator(1).beam_search(&[0], &BeamSearch::new(0)).is_err());
+        let found = generator(1).beam_search(&[2], &BeamSearch::new(1)).unwrap();
+        assert_eq!(found[0].tokens, [3]);
+    }
+}
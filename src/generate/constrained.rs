@@ -0,0 +1,184 @@
+//! Constrained decoding: an automaton decides which tokens may come next.
+
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::hash::Hash;
+
+use tch::{Kind, Tensor};
+
+use crate::Error;
+use crate::generate::LogitProcessor;
+use crate::generate::processors::{check, mask};
+
+/// A token-level automaton, e.g. a grammar or JSON schema compiled against
+/// the tokenizer's vocabulary, run over the generated tokens.
+pub trait Automaton: Send {
+    /// Everything the automaton needs to know about the tokens so far.
+    /// States are hashed to cache their masks, so keep them small.
+    type State: Clone + Eq + Hash + Send;
+
+    /// The state before any token is generated.
+    fn start(&self) -> Self::State;
+
+    /// The state after `token`, or `None` if `state` doesn't allow it.
+    fn next(&self, state: &Self::State, token: i64) -> Option<Self::State>;
+
+    /// The tokens `state` allows next. An empty list marks a complete
+    /// output, which is left unconstrained: it should allow the
+    /// end-of-sequence token until then.
+    fn allowed(&self, state: &Self::State) -> Vec<i64>;
+}
+
+/// A [`LogitProcessor`] that excludes every token the [`Automaton`]
+/// doesn't allow next, so sampling, beam search and speculative decoding
+/// only produce outputs it accepts.
+///
+/// The automaton starts after the prompt and is advanced by the tokens
+/// generated since the last step. A sequence continues from the longest
+/// history seen at that step it extends, so beams that were reordered or
+/// forked keep their state. Each state's allowed tokens are asked for once
+/// and kept as a mask on the logits' device; a step only stacks the masks
+/// of the sequences' states.
+pub struct Constrained<A: Automaton> {
+    automaton: A,
+    /// `[1, vocab]` `Bool` masks of the allowed tokens, by state.
+    masks: HashMap<A::State, Tensor>,
+    /// Each sequence's tokens and state at the last step.
+    rows: Vec<(Vec<i64>, A::State)>,
+}
+
+impl<A: Automaton> Constrained<A> {
+    pub fn new(automaton: A) -> Self {
+        Self {
+            automaton,
+            masks: HashMap::new(),
+            rows: Vec::new(),
+        }
+    }
+
+    pub fn automaton(&self) -> &A {
+        &self.automaton
+    }
+
+    /// The state after `tokens`, continuing from the last step's rows.
+    fn state(&self, tokens: &[i64]) -> Result<A::State, Error> {
+        let (known, mut state) = self
+            .rows
+            .iter()
+            .filter(|(seen, _)| tokens.starts_with(seen))
+            .max_by_key(|(seen, _)| seen.len())
+            .map(|(seen, state)| (seen.len(), state.clone()))
+            .unwrap_or_else(|| (tokens.len(), self.automaton.start()));
+        for &token in &tokens[known..] {
+            state = self.automaton.next(&state, token).ok_or_else(|| {
+                Error::InvalidInput(format!("token {token} breaks the decoding constraint"))
+            })?;
+        }
+        Ok(state)
+    }
+}
+
+impl<A: Automaton> LogitProcessor for Constrained<A> {
+    fn process(&mut self, logits: &Tensor, tokens: &[Vec<i64>]) -> Result<Tensor, Error> {
+        check(logits, tokens)?;
+        self.rows = tokens
+            .iter()
+            .map(|history| Ok((history.clone(), self.state(history)?)))
+            .collect::<Result<_, Error>>()?;
+        let mut masks = Vec::with_capacity(self.rows.len());
+        for (_, state) in &self.rows {
+            let allowed = match self.masks.entry(state.clone()) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    let allowed = self.automaton.allowed(state);
+                    let row = if allowed.is_empty() {
+                        let vocab = logits.size()[1];
+                        Tensor::f_ones([1, vocab], (Kind::Bool, logits.device()))?
+                    } else {
+                        mask(logits, 1, |_| allowed.clone())?
+                    };
+                    entry.insert(row)
+                }
+            };
+            masks.push(allowed.shallow_clone());
+        }
+        let allowed = Tensor::f_cat(&masks, 0)?;
+        Ok(logits
+            .f_to_kind(Kind::Float)?
+            .f_masked_fill(&allowed.f_logical_not()?, f64::NEG_INFINITY)?)
+    }
+
+    fn reset(&mut self) {
+        self.rows.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two tokens below 4, even then odd; anything after is unconstrained.
+    struct Alternate;
+
+    impl Automaton for Alternate {
+        type State = usize;
+
+        fn start(&self) -> usize {
+            0
+        }
+
+        fn next(&self, &state: &usize, token: i64) -> Option<usize> {
+            (state < 2 && token % 2 == state as i64 % 2).then_some(state + 1)
+        }
+
+        fn allowed(&self, &state: &usize) -> Vec<i64> {
+            (0..4)
+                .filter(|t| state < 2 && t % 2 == state as i64 % 2)
+                .collect()
+        }
+    }
+
+    fn values(t: &Tensor) -> Vec<f32> {
+        Vec::<f32>::try_from(&t.flatten(0, -1)).unwrap()
+    }
+
+    fn zeros(batch: i64) -> Tensor {
+        Tensor::zeros([batch, 4], (Kind::Float, tch::Device::Cpu))
+    }
+
+    #[test]
+    fn disallowed_tokens_are_masked_per_sequence() {
+        let inf = f32::NEG_INFINITY;
+        let mut constrained = Constrained::new(Alternate);
+        let masked = constrained.process(&zeros(1), &[vec![5]]).unwrap();
+        assert_eq!(values(&masked), [0.0, inf, 0.0, inf]);
+        // Two beams forked from the one sequence.
+        let masked = constrained
+            .process(&zeros(2), &[vec![5, 2], vec![5, 0]])
+            .unwrap();
+        assert_eq!(values(&masked), [inf, 0.0, inf, 0.0, inf, 0.0, inf, 0.0]);
+        let masked = constrained
+            .process(&zeros(2), &[vec![5, 0, 3], vec![5, 0, 1]])
+            .unwrap();
+        assert_eq!(values(&masked), [0.0; 8]);
+        assert_eq!(constrained.masks.len(), 3);
+    }
+
+    #[test]
+    fn reset_restarts_after_the_new_prompt() {
+        let inf = f32::NEG_INFINITY;
+        let mut constrained = Constrained::new(Alternate);
+        // Without the reset, 1 would have to follow 2 from state 0.
+        assert!(constrained.process(&zeros(1), &[vec![5, 2]]).is_ok());
+        constrained.reset();
+        let masked = constrained.process(&zeros(1), &[vec![5, 2, 1]]).unwrap();
+        assert_eq!(values(&masked), [0.0, inf, 0.0, inf]);
+        let masked = constrained.process(&zeros(1), &[vec![5, 2, 1, 2]]).unwrap();
+        assert_eq!(values(&masked), [inf, 0.0, inf, 0.0]);
+        assert!(
+            constrained
+                .process(&zeros(1), &[vec![5, 2, 1, 2, 2]])
+                .is_err()
+        );
+    }
+}
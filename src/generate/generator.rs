@@ -0,0 +1,541 @@
+//! The token-by-token decoding loop.
+
+use std::collections::VecDeque;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tch::Tensor;
+
+use crate::generate::{KvCache, LogitProcessor, PrefixCache, Sampler, Sampling};
+use crate::{AOTIModel, AOTIModelPool, Device, DeviceTensor, Error};
+
+fn wrap<D: Device>(tensor: Tensor) -> DeviceTensor<D> {
+    DeviceTensor {
+        tensor,
+        _device: PhantomData,
+    }
+}
+
+/// One decoding step of a decoder package: the step's inputs go in with
+/// the cache, the cache is updated and the other outputs, logits first,
+/// come back. Implemented by models and pools via their
+/// `run_with_cache`.
+pub trait Decode<D: Device> {
+    fn decode(
+        &mut self,
+        inputs: &[DeviceTensor<D>],
+        cache: &mut KvCache<D>,
+    ) -> Result<Vec<DeviceTensor<D>>, Error>;
+}
+
+impl<D: Device> Decode<D> for AOTIModel<D> {
+    fn decode(
+        &mut self,
+        inputs: &[DeviceTensor<D>],
+        cache: &mut KvCache<D>,
+    ) -> Result<Vec<DeviceTensor<D>>, Error> {
+        self.run_with_cache(inputs, cache)
+    }
+}
+
+impl<D: Device> Decode<D> for Arc<AOTIModelPool<D>> {
+    fn decode(
+        &mut self,
+        inputs: &[DeviceTensor<D>],
+        cache: &mut KvCache<D>,
+    ) -> Result<Vec<DeviceTensor<D>>, Error> {
+        self.run_with_cache(inputs, cache)
+    }
+}
+
+impl<D: Device, T: Decode<D> + ?Sized> Decode<D> for &mut T {
+    fn decode(
+        &mut self,
+        inputs: &[DeviceTensor<D>],
+        cache: &mut KvCache<D>,
+    ) -> Result<Vec<DeviceTensor<D>>, Error> {
+        (**self).decode(inputs, cache)
+    }
+}
+
+/// When and how [`Generator`] stops and what it passes the model.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GenerateConfig {
+    /// Tokens to generate per sequence, at most.
+    pub max_new_tokens: usize,
+    /// Tokens that end a sequence; they are yielded, then the sequence
+    /// stops.
+    pub eos_token_ids: Vec<i64>,
+    /// Token sequences that end a sequence once its tokens end with one,
+    /// however many steps it took to generate; like end-of-sequence
+    /// tokens, they are yielded.
+    pub stop_sequences: Vec<Vec<i64>>,
+    /// Stop every sequence once generation has run this long.
+    pub max_time: Option<Duration>,
+    /// Pass [`KvCache::positions`] after the token ids (default: true).
+    pub positions: bool,
+    /// How tokens are picked from the logits (default: greedy).
+    pub sampling: Sampling,
+}
+
+impl GenerateConfig {
+    pub fn new(max_new_tokens: usize) -> Self {
+        Self {
+            max_new_tokens,
+            eos_token_ids: Vec::new(),
+            stop_sequences: Vec::new(),
+            max_time: None,
+            positions: true,
+            sampling: Sampling::greedy(),
+        }
+    }
+
+    /// Set the end-of-sequence tokens.
+    pub fn eos(mut self, ids: impl IntoIterator<Item = i64>) -> Self {
+        self.eos_token_ids = ids.into_iter().collect();
+        self
+    }
+
+    /// Set the stop sequences.
+    pub fn stop_sequences(mut self, sequences: impl IntoIterator<Item = Vec<i64>>) -> Self {
+        self.stop_sequences = sequences.into_iter().collect();
+        self
+    }
+
+    /// Set the time limit.
+    pub fn max_time(mut self, limit: Duration) -> Self {
+        self.max_time = Some(limit);
+        self
+    }
+
+    /// Set whether the model takes positions after the token ids.
+    pub fn positions(mut self, positions: bool) -> Self {
+        self.positions = positions;
+        self
+    }
+
+    /// Set how tokens are picked from the logits.
+    pub fn sampling(mut self, sampling: Sampling) -> Self {
+        self.sampling = sampling;
+        self
+    }
+
+    /// Whether a sequence whose tokens so far are `tokens` has just ended,
+    /// with an end-of-sequence token or a stop sequence.
+    pub(super) fn stop_reason(&self, tokens: &[i64]) -> Option<FinishReason> {
+        let last = tokens.last()?;
+        if self.eos_token_ids.contains(last) {
+            Some(FinishReason::Eos)
+        } else if self
+            .stop_sequences
+            .iter()
+            .any(|stop| !stop.is_empty() && tokens.ends_with(stop))
+        {
+            Some(FinishReason::StopSequence)
+        } else {
+            None
+        }
+    }
+
+    /// Whether generation that began at `started` is out of time.
+    pub(super) fn timed_out(&self, started: Instant) -> bool {
+        self.max_time
+            .is_some_and(|limit| started.elapsed() >= limit)
+    }
+}
+
+/// Why a sequence stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FinishReason {
+    /// It generated an end-of-sequence token.
+    Eos,
+    /// Its tokens ended with a stop sequence.
+    StopSequence,
+    /// It reached `max_new_tokens`.
+    MaxNewTokens,
+    /// Generation ran out of `max_time`.
+    MaxTime,
+}
+
+/// A token generated for one sequence of a batch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Token {
+    /// Index of the sequence in the batch.
+    pub sequence: usize,
+    pub id: i64,
+}
+
+/// Token-by-token decoding with a decoder package and its [`KvCache`].
+///
+/// Each step passes `[batch, steps]` `Int64` token ids (and, by default,
+/// their positions) and reads `[batch, steps, vocab]` or `[batch, vocab]`
+/// logits from the model's first output, picking the next tokens with a
+/// [`Sampler`] after any [`LogitProcessor`]s. The prompt goes in as one
+/// prefill step, then one token per step.
+pub struct Generator<D: Device, M> {
+    model: M,
+    cache: KvCache<D>,
+    config: GenerateConfig,
+    processors: Vec<Box<dyn LogitProcessor>>,
+    prefixes: Option<PrefixCache<D>>,
+    /// Prompts whose blocks go into `prefixes` after the prefill step.
+    unstored: Option<Vec<Vec<i64>>>,
+}
+
+impl<D: Device, M: Decode<D>> Generator<D, M> {
+    pub fn new(model: M, cache: KvCache<D>, config: GenerateConfig) -> Self {
+        Self {
+            model,
+            cache,
+            config,
+            processors: Vec::new(),
+            prefixes: None,
+            unstored: None,
+        }
+    }
+
+    /// Add a processor, applied to the logits after those already added.
+    pub fn processor(mut self, processor: impl LogitProcessor + 'static) -> Self {
+        self.processors.push(Box::new(processor));
+        self
+    }
+
+    /// Reuse cached prompt prefixes: each prefill starts from the longest
+    /// prefix `prefixes` holds for all the prompts, then stores the
+    /// prompts' own blocks.
+    pub fn prefix_cache(mut self, prefixes: PrefixCache<D>) -> Self {
+        self.prefixes = Some(prefixes);
+        self
+    }
+
+    pub fn prefixes(&self) -> Option<&PrefixCache<D>> {
+        self.prefixes.as_ref()
+    }
+
+    pub fn config(&self) -> &GenerateConfig {
+        &self.config
+    }
+
+    pub fn cache(&self) -> &KvCache<D> {
+        &self.cache
+    }
+
+    pub(super) fn cache_mut(&mut self) -> &mut KvCache<D> {
+        &mut self.cache
+    }
+
+    /// Take the model and cache back.
+    pub fn into_parts(self) -> (M, KvCache<D>) {
+        (self.model, self.cache)
+    }
+
+    /// Start generating from `prompts`, one per sequence of the cache's
+    /// batch, all of the same length. The cache is emptied first and the
+    /// sampler reseeded; tokens are produced lazily as the iterator is
+    /// advanced.
+    pub fn tokens(&mut self, prompts: &[Vec<i64>]) -> Result<Tokens<'_, D, M>, Error> {
+        let next = self.start(prompts)?;
+        let history = prompts.to_vec();
+        let sampler = Sampler::new(self.config.sampling.clone());
+        Ok(Tokens {
+            finished: vec![None; prompts.len()],
+            started: Instant::now(),
+            sampler,
+            history,
+            generator: self,
+            next,
+            produced: 0,
+            pending: VecDeque::new(),
+            done: false,
+        })
+    }
+
+    /// Generate to completion, returning each sequence's new tokens.
+    pub fn generate(&mut self, prompts: &[Vec<i64>]) -> Result<Vec<Vec<i64>>, Error> {
+        let mut generated = vec![Vec::new(); prompts.len()];
+        for token in self.tokens(prompts)? {
+            let token = token?;
+            generated[token.sequence].push(token.id);
+        }
+        Ok(generated)
+    }
+
+    /// Check that `prompts` fill the cache's batch and empty the cache,
+    /// returning the prompts as host `[batch, len]` ids. With a prefix
+    /// cache, the cache starts out holding a cached prefix instead and
+    /// only the rest of the prompts is returned.
+    pub(super) fn start(&mut self, prompts: &[Vec<i64>]) -> Result<Tensor, Error> {
+        let batch = self.cache.config().batch;
+        if prompts.len() != batch {
+            return Err(Error::InvalidInput(format!(
+                "got {} prompts for a cache batch of {batch}",
+                prompts.len()
+            )));
+        }
+        let len = prompts[0].len();
+        if len == 0 || prompts.iter().any(|p| p.len() != len) {
+            return Err(Error::InvalidInput(
+                "prompts must be non-empty and of the same length".into(),
+            ));
+        }
+        self.reset()?;
+        let cached = match &mut self.prefixes {
+            Some(prefixes) => {
+                self.unstored = Some(prompts.to_vec());
+                prefixes.restore(prompts, &mut self.cache)?
+            }
+            None => 0,
+        };
+        let ids: Vec<i64> = prompts.iter().flat_map(|p| &p[cached..]).copied().collect();
+        Ok(Tensor::from_slice(&ids).f_view([batch as i64, (len - cached) as i64])?)
+    }
+
+    /// Empty the cache and reset the processors for a new generation.
+    pub(super) fn reset(&mut self) -> Result<(), Error> {
+        for processor in &mut self.processors {
+            processor.reset();
+        }
+        self.cache.reset_all()
+    }
+
+    /// Run one step on host `[batch, steps]` ids, returning the logits of
+    /// each sequence's last position, `[batch, vocab]`.
+    pub(super) fn forward(&mut self, ids: &Tensor) -> Result<Tensor, Error> {
+        let logits = self.run(ids)?;
+        match logits.dim() {
+            3 => Ok(logits.f_select(1, -1)?),
+            2 => Ok(logits),
+            _ => Err(Error::InvalidInput(format!(
+                "expected [batch, steps, vocab] or [batch, vocab] logits, got {:?}",
+                logits.size()
+            ))),
+        }
+    }
+
+    /// [`Generator::forward`], returning the logits of every position,
+    /// `[batch, steps, vocab]`.
+    pub(super) fn forward_all(&mut self, ids: &Tensor) -> Result<Tensor, Error> {
+        let logits = self.run(ids)?;
+        match logits.dim() {
+            3 => Ok(logits),
+            2 if ids.size()[1] == 1 => Ok(logits.f_unsqueeze(1)?),
+            _ => Err(Error::InvalidInput(format!(
+                "expected [batch, steps, vocab] logits for every position, got {:?}",
+                logits.size()
+            ))),
+        }
+    }
+
+    /// Apply the processors to `[batch, vocab]` logits, given each
+    /// sequence's tokens so far.
+    pub(super) fn process(&mut self, logits: Tensor, tokens: &[Vec<i64>]) -> Result<Tensor, Error> {
+        self.processors
+            .iter_mut()
+            .try_fold(logits, |logits, processor| {
+                processor.process(&logits, tokens)
+            })
+    }
+
+    /// The model's first output for a step on `ids`.
+    fn run(&mut self, ids: &Tensor) -> Result<Tensor, Error> {
+        let steps = ids.size()[1] as usize;
+        let mut inputs = vec![wrap(ids.f_to_device(self.cache.device())?)];
+        if self.config.positions {
+            inputs.push(self.cache.positions(steps)?);
+        }
+        let outputs = self.model.decode(&inputs, &mut self.cache)?;
+        if let Some(prompts) = self.unstored.take()
+            && let Some(prefixes) = &mut self.prefixes
+        {
+            prefixes.store(&prompts, &self.cache)?;
+        }
+        let logits = outputs
+            .into_iter()
+            .next()
+            .ok_or_else(|| Error::Model("the decoder returned no logits".into()))?;
+        Ok(logits.into_inner())
+    }
+}
+
+/// Tokens as [`Generator::tokens`] decodes them: a step runs whenever the
+/// previous step's tokens have all been taken. Each sequence stops on its
+/// own, at an end-of-sequence token or stop sequence; iteration ends once
+/// all have, at `max_new_tokens` or `max_time`, or after the first error.
+pub struct Tokens<'a, D: Device, M> {
+    generator: &'a mut Generator<D, M>,
+    sampler: Sampler,
+    /// Each sequence's tokens so far, prompt included.
+    history: Vec<Vec<i64>>,
+    /// Host `[batch, steps]` ids for the next step.
+    next: Tensor,
+    produced: usize,
+    started: Instant,
+    finished: Vec<Option<FinishReason>>,
+    pending: VecDeque<Token>,
+    done: bool,
+}
+
+impl<D: Device, M: Decode<D>> Tokens<'_, D, M> {
+    /// Why each sequence has stopped, once it has.
+    pub fn finished(&self) -> &[Option<FinishReason>] {
+        &self.finished
+    }
+
+    /// Stop every running sequence for `reason`.
+    fn finish_all(&mut self, reason: FinishReason) {
+        for finished in self.finished.iter_mut().filter(|f| f.is_none()) {
+            *finished = Some(reason);
+        }
+    }
+
+    fn step(&mut self) -> Result<(), Error> {
+        let config = &self.generator.config;
+        if self.produced == config.max_new_tokens {
+            self.finish_all(FinishReason::MaxNewTokens);
+        } else if config.timed_out(self.started) {
+            self.finish_all(FinishReason::MaxTime);
+        }
+        if self.finished.iter().all(Option::is_some) {
+            self.done = true;
+            return Ok(());
+        }
+        let logits = self.generator.forward(&self.next)?;
+        let logits = self.generator.process(logits, &self.history)?;
+        let ids = self.sampler.sample(&logits)?;
+        for (sequence, &id) in ids.iter().enumerate() {
+            if let Some(finished) = self.finished.get_mut(sequence)
+                && finished.is_none()
+            {
+                self.pending.push_back(Token { sequence, id });
+                self.history[sequence].push(id);
+                *finished = self.generator.config.stop_reason(&self.history[sequence]);
+            }
+        }
+        self.produced += 1;
+        if self.produced == self.generator.config.max_new_tokens {
+            self.finish_all(FinishReason::MaxNewTokens);
+        }
+        self.next = Tensor::from_slice(&ids).f_unsqueeze(1)?;
+        Ok(())
+    }
+}
+
+impl<D: Device, M: Decode<D>> Iterator for Tokens<'_, D, M> {
+    type Item = Result<Token, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(token) = self.pending.pop_front() {
+                return Some(Ok(token));
+            }
+            if self.done {
+                return None;
+            }
+            if let Err(err) = self.step() {
+                self.done = true;
+                return Some(Err(err));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Cpu;
+    use crate::generate::{BadWords, KvCacheConfig};
+    use tch::Kind;
+
+    /// Predicts `id + 1` modulo 8 for every token and caches zeros.
+    struct Counter;
+
+    impl Decode<Cpu> for Counter {
+        fn decode(
+            &mut self,
+            inputs: &[DeviceTensor<Cpu>],
+            cache: &mut KvCache<Cpu>,
+        ) -> Result<Vec<DeviceTensor<Cpu>>, Error> {
+            let [batch, steps] = inputs[0].size()[..] else {
+                unreachable!()
+            };
+            let next = inputs[0].f_add_scalar(1)?.f_remainder(8)?;
+            let logits = next.f_one_hot(8)?.f_to_kind(Kind::Float)?;
+            let entry = || {
+                wrap(Tensor::zeros(
+                    [batch, 1, steps, 1],
+                    (Kind::Float, tch::Device::Cpu),
+                ))
+            };
+            cache.update(vec![entry(), entry()])?;
+            Ok(vec![wrap(logits)])
+        }
+    }
+
+    fn generator(batch: usize, config: GenerateConfig) -> Generator<Cpu, Counter> {
+        let cache = KvCache::new(
+            KvCacheConfig::new(1, 1, 1, 16).batch(batch),
+            tch::Device::Cpu,
+        )
+        .unwrap();
+        Generator::new(Counter, cache, config)
+    }
+
+    #[test]
+    fn sequences_stop_at_their_own_eos() {
+        let mut generator = generator(2, GenerateConfig::new(4).eos([5]));
+        let generated = generator.generate(&[vec![0, 3], vec![6, 1]]).unwrap();
+        assert_eq!(generated, [vec![4, 5], vec![2, 3, 4, 5]]);
+        assert_eq!(generator.cache().lengths(), [5, 5]);
+
+        // A second call starts from an empty cache.
+        let generated = generator.generate(&[vec![7], vec![2]]).unwrap();
+        assert_eq!(generated, [vec![0, 1, 2, 3], vec![3, 4, 5]]);
+    }
+
+    #[test]
+    fn tokens_are_yielded_step_by_step() {
+        let mut generator = generator(1, GenerateConfig::new(3));
+        let mut tokens = generator.tokens(&[vec![1, 2]]).unwrap();
+        assert_eq!(
+            tokens.next().unwrap().unwrap(),
+            Token { sequence: 0, id: 3 }
+        );
+        assert_eq!(tokens.generator.cache().lengths(), [2]);
+        assert_eq!(tokens.next().unwrap().unwrap().id, 4);
+        assert_eq!(tokens.generator.cache().lengths(), [3]);
+        assert_eq!(tokens.map(|t| t.unwrap().id).collect::<Vec<_>>(), [5]);
+
+        assert!(generator.tokens(&[vec![1], vec![2]]).is_err());
+        assert!(generator.tokens(&[vec![]]).is_err());
+
+        // Processors see the logits first: with 3 banned, the first of the
+        // remaining (equal) logits wins.
+        let mut banned = generator.processor(BadWords(vec![vec![3]]));
+        assert_eq!(banned.generate(&[vec![1, 2]]).unwrap(), [vec![0, 1]]);
+    }
+
+    #[test]
+    fn sequences_finish_for_their_own_reasons() {
+        let config = GenerateConfig::new(4).stop_sequences([vec![3, 4]]);
+        let mut batched = generator(2, config);
+        let mut tokens = batched.tokens(&[vec![1, 2], vec![4, 5]]).unwrap();
+        let ids: Vec<_> = tokens
+            .by_ref()
+            .map(|t| t.map(|t| (t.sequence, t.id)).unwrap())
+            .collect();
+        assert_eq!(ids, [(0, 3), (1, 6), (0, 4), (1, 7), (1, 0), (1, 1)]);
+        assert_eq!(
+            tokens.finished(),
+            [
+                Some(FinishReason::StopSequence),
+                Some(FinishReason::MaxNewTokens)
+            ]
+        );
+
+        let mut timed = generator(1, GenerateConfig::new(4).max_time(Duration::ZERO));
+        let mut tokens = timed.tokens(&[vec![1]]).unwrap();
+        assert!(tokens.next().is_none());
+        assert_eq!(tokens.finished(), [Some(FinishReason::MaxTime)]);
+    }
+}
@@ -0,0 +1,494 @@
+//! KV-cache tensors for decoder packages.
+
+use std::marker::PhantomData;
+
+use tch::{Kind, Tensor};
+
+use crate::{AOTIModel, AOTIModelPool, Device, DeviceTensor, Error};
+
+fn wrap<D: Device>(tensor: Tensor) -> DeviceTensor<D> {
+    DeviceTensor {
+        tensor,
+        _device: PhantomData,
+    }
+}
+
+/// How a decoder package passes its cache.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KvLayout {
+    /// Fixed `[batch, kv_heads, max_len, head_dim]` buffers are passed
+    /// whole every step and the model returns only the step's entries,
+    /// `[batch, kv_heads, steps, head_dim]`, which are written after each
+    /// sequence's filled length. Sequences can be at different lengths;
+    /// pass [`KvCache::positions`] so the model knows where each one is.
+    #[default]
+    Static,
+    /// The cache is `[batch, kv_heads, len, head_dim]`: the model returns
+    /// the past entries with the step's appended, and those are passed
+    /// back on the next step. All sequences share one length.
+    Growing,
+}
+
+/// The shape of a decoder's cache.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KvCacheConfig {
+    /// Decoder layers, each with a key and a value tensor.
+    pub layers: usize,
+    /// Key/value heads per layer (fewer than the query heads under
+    /// grouped-query attention).
+    pub kv_heads: usize,
+    pub head_dim: usize,
+    /// Longest sequence the cache holds, prompt included.
+    pub max_len: usize,
+    /// Sequences decoded together (default: 1).
+    pub batch: usize,
+    /// Cache dtype (default: `Float`).
+    pub kind: Kind,
+    pub layout: KvLayout,
+}
+
+impl KvCacheConfig {
+    /// A static-layout, single-sequence `Float` cache.
+    pub fn new(layers: usize, kv_heads: usize, head_dim: usize, max_len: usize) -> Self {
+        Self {
+            layers,
+            kv_heads,
+            head_dim,
+            max_len,
+            batch: 1,
+            kind: Kind::Float,
+            layout: KvLayout::Static,
+        }
+    }
+
+    /// Set the number of sequences decoded together.
+    pub fn batch(mut self, batch: usize) -> Self {
+        self.batch = batch;
+        self
+    }
+
+    /// Set the cache dtype, e.g. `Half` for a half-precision export.
+    pub fn kind(mut self, kind: Kind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    /// Set how the package passes its cache.
+    pub fn layout(mut self, layout: KvLayout) -> Self {
+        self.layout = layout;
+        self
+    }
+}
+
+/// A decoder's KV cache on device `D`.
+///
+/// The model takes the cache as its last inputs, layer by layer, key
+/// before value (`k0, v0, k1, v1, ...`), and returns it the same way as
+/// its last outputs. [`AOTIModel::run_with_cache`] passes and updates it;
+/// [`KvCache::inputs`] and [`KvCache::update`] do the same by hand for
+/// packages with another calling convention.
+pub struct KvCache<D: Device> {
+    config: KvCacheConfig,
+    device: tch::Device,
+    /// Key and value tensor of each layer.
+    entries: Vec<[Tensor; 2]>,
+    /// Filled length of each sequence.
+    lengths: Vec<usize>,
+    _device: PhantomData<D>,
+}
+
+impl<D: Device> KvCache<D> {
+    /// Allocate an empty cache on `device`, zeroed in the static layout.
+    pub fn new(config: KvCacheConfig, device: tch::Device) -> Result<Self, Error> {
+        if !D::matches(device) {
+            return Err(Error::TensorDeviceMismatch {
+                expected: D::KEY,
+                found: device,
+            });
+        }
+        if config.layers == 0 || config.batch == 0 || config.max_len == 0 {
+            return Err(Error::InvalidInput(format!(
+                "a KV cache needs at least one layer, sequence and position: {config:?}"
+            )));
+        }
+        let len = match config.layout {
+            KvLayout::Static => config.max_len,
+            KvLayout::Growing => 0,
+        };
+        let shape = [
+            config.batch as i64,
+            config.kv_heads as i64,
+            len as i64,
+            config.head_dim as i64,
+        ];
+        let entries = (0..config.layers)
+            .map(|_| {
+                Ok([
+                    Tensor::f_zeros(shape, (config.kind, device))?,
+                    Tensor::f_zeros(shape, (config.kind, device))?,
+                ])
+            })
+            .collect::<Result<_, Error>>()?;
+        Ok(Self {
+            lengths: vec![0; config.batch],
+            config,
+            device,
+            entries,
+            _device: PhantomData,
+        })
+    }
+
+    /// Allocate an empty cache on `model`'s device.
+    pub fn for_model(model: &AOTIModel<D>, config: KvCacheConfig) -> Result<Self, Error> {
+        Self::new(config, model.device())
+    }
+
+    pub fn config(&self) -> &KvCacheConfig {
+        &self.config
+    }
+
+    pub fn device(&self) -> tch::Device {
+        self.device
+    }
+
+    /// The filled length of each sequence.
+    pub fn lengths(&self) -> &[usize] {
+        &self.lengths
+    }
+
+    /// Device memory the cache tensors occupy.
+    pub fn bytes(&self) -> usize {
+        self.entries
+            .iter()
+            .flatten()
+            .map(|t| t.numel() * self.config.kind.elt_size_in_bytes())
+            .sum()
+    }
+
+    /// The cache tensors to pass to the model, `k0, v0, k1, v1, ...`.
+    /// They share storage with the cache.
+    pub fn inputs(&self) -> Vec<DeviceTensor<D>> {
+        self.entries
+            .iter()
+            .flatten()
+            .map(|t| wrap(t.shallow_clone()))
+            .collect()
+    }
+
+    /// Store the cache tensors a model returned, in [`KvCache::inputs`]
+    /// order, returning how many positions each sequence advanced. Nothing
+    /// changes if they have the wrong shape or dtype or would overflow
+    /// `max_len`.
+    pub fn update(&mut self, outputs: Vec<DeviceTensor<D>>) -> Result<usize, Error> {
+        let config = &self.config;
+        if outputs.len() != 2 * config.layers {
+            return Err(Error::InvalidInput(format!(
+                "expected {} cache tensors for {} layers, got {}",
+                2 * config.layers,
+                config.layers,
+                outputs.len()
+            )));
+        }
+        let first = outputs[0].size();
+        let len = first.get(2).copied().unwrap_or(0).max(0) as usize;
+        let expected = [
+            config.batch as i64,
+            config.kv_heads as i64,
+            len as i64,
+            config.head_dim as i64,
+        ];
+        for (i, output) in outputs.iter().enumerate() {
+            if output.size() != expected {
+                return Err(Error::InvalidInput(format!(
+                    "cache output {i} is {:?}, expected {expected:?}",
+                    output.size()
+                )));
+            }
+            if output.kind() != config.kind {
+                return Err(Error::TensorKindMismatch {
+                    expected: config.kind,
+                    found: output.kind(),
+                });
+            }
+        }
+        let longest = self.lengths.iter().copied().max().unwrap_or(0);
+        let steps = match config.layout {
+            KvLayout::Static => {
+                if longest + len > config.max_len {
+                    return Err(self.full(longest + len));
+                }
+                for (entry, new) in self.entries.iter_mut().flatten().zip(&outputs) {
+                    for (b, &filled) in self.lengths.iter().enumerate() {
+                        entry
+                            .f_narrow(0, b as i64, 1)?
+                            .f_narrow(2, filled as i64, len as i64)?
+                            .f_copy_(&new.f_narrow(0, b as i64, 1)?)?;
+                    }
+                }
+                len
+            }
+            KvLayout::Growing => {
+                if len < longest {
+                    return Err(Error::InvalidInput(format!(
+                        "cache outputs hold {len} positions, fewer than the {longest} passed in"
+                    )));
+                }
+                if len > config.max_len {
+                    return Err(self.full(len));
+                }
+                let mut outputs = outputs.into_iter().map(DeviceTensor::into_inner);
+                for entry in &mut self.entries {
+                    if let (Some(key), Some(value)) = (outputs.next(), outputs.next()) {
+                        *entry = [key, value];
+                    }
+                }
+                len - longest
+            }
+        };
+        for filled in &mut self.lengths {
+            *filled += steps;
+        }
+        Ok(steps)
+    }
+
+    fn full(&self, needed: usize) -> Error {
+        Error::InvalidInput(format!(
+            "the KV cache holds {} positions but {needed} are needed; truncate or reset it",
+            self.config.max_len
+        ))
+    }
+
+    /// The `[batch, steps]` `Int64` positions of a step's tokens: each
+    /// sequence's filled length onwards.
+    pub fn positions(&self, steps: usize) -> Result<DeviceTensor<D>, Error> {
+        let starts: Vec<i64> = self.lengths.iter().map(|&len| len as i64).collect();
+        let starts = Tensor::from_slice(&starts)
+            .f_to_device(self.device)?
+            .f_unsqueeze(1)?;
+        let offsets = Tensor::f_arange(steps as i64, (Kind::Int64, self.device))?.f_unsqueeze(0)?;
+        Ok(wrap(starts.f_add(&offsets)?))
+    }
+
+    /// The filled part of sequence `seq`'s cache, as `[1, kv_heads, len,
+    /// head_dim]` key and value views per layer.
+    pub fn sequence(&self, seq: usize) -> Result<Vec<[DeviceTensor<D>; 2]>, Error> {
+        let len = self.length(seq)? as i64;
+        self.entries
+            .iter()
+            .map(|[key, value]| {
+                let view = |t: &Tensor| -> Result<DeviceTensor<D>, Error> {
+                    Ok(wrap(t.f_narrow(0, seq as i64, 1)?.f_narrow(2, 0, len)?))
+                };
+                Ok([view(key)?, view(value)?])
+            })
+            .collect()
+    }
+
+    /// Shorten sequence `seq` to at most `len` positions, e.g. to drop
+    /// rejected speculative tokens. A growing cache's sequences share one
+    /// length, so with more than one of them use
+    /// [`KvCache::truncate_all`].
+    pub fn truncate(&mut self, seq: usize, len: usize) -> Result<(), Error> {
+        let filled = self.length(seq)?;
+        match self.config.layout {
+            KvLayout::Static => {
+                self.lengths[seq] = filled.min(len);
+                Ok(())
+            }
+            KvLayout::Growing if self.config.batch == 1 => self.truncate_all(len),
+            KvLayout::Growing => Err(Error::InvalidInput(
+                "a growing KV cache's sequences share one length; use truncate_all".into(),
+            )),
+        }
+    }
+
+    /// Empty sequence `seq`'s cache, e.g. to start a new sequence in its
+    /// slot; see [`KvCache::truncate`].
+    pub fn reset(&mut self, seq: usize) -> Result<(), Error> {
+        self.truncate(seq, 0)
+    }
+
+    /// Shorten every sequence to at most `len` positions.
+    pub fn truncate_all(&mut self, len: usize) -> Result<(), Error> {
+        if self.config.layout == KvLayout::Growing {
+            let longest = self.lengths.iter().copied().max().unwrap_or(0);
+            if len < longest {
+                for entry in self.entries.iter_mut().flatten() {
+                    *entry = entry.f_narrow(2, 0, len as i64)?.f_contiguous()?;
+                }
+            }
+        }
+        for filled in &mut self.lengths {
+            *filled = (*filled).min(len);
+        }
+        Ok(())
+    }
+
+    /// Empty every sequence's cache.
+    pub fn reset_all(&mut self) -> Result<(), Error> {
+        self.truncate_all(0)
+    }
+
+    /// Rearrange the batch so that sequence `i` becomes a copy of sequence
+    /// `sources[i]`, e.g. to follow the surviving beams of a beam search.
+    pub fn reorder(&mut self, sources: &[usize]) -> Result<(), Error> {
+        if sources.len() != self.config.batch {
+            return Err(Error::InvalidInput(format!(
+                "got {} sources for a batch of {}",
+                sources.len(),
+                self.config.batch
+            )));
+        }
+        let lengths = sources
+            .iter()
+            .map(|&seq| self.length(seq))
+            .collect::<Result<Vec<_>, _>>()?;
+        let index: Vec<i64> = sources.iter().map(|&seq| seq as i64).collect();
+        let index = Tensor::from_slice(&index).f_to_device(self.device)?;
+        self.entries = self
+            .entries
+            .iter()
+            .map(|[key, value]| {
+                Ok([
+                    key.f_index_select(0, &index)?,
+                    value.f_index_select(0, &index)?,
+                ])
+            })
+            .collect::<Result<_, Error>>()?;
+        self.lengths = lengths;
+        Ok(())
+    }
+
+    fn length(&self, seq: usize) -> Result<usize, Error> {
+        self.lengths.get(seq).copied().ok_or_else(|| {
+            Error::InvalidInput(format!(
+                "sequence {seq} is out of range for a batch of {}",
+                self.config.batch
+            ))
+        })
+    }
+
+    /// Run `run` on `inputs`, followed by the cache if `pass_cache`,
+    /// storing the cache outputs and returning the rest.
+    pub(super) fn step(
+        &mut self,
+        inputs: &[DeviceTensor<D>],
+        pass_cache: bool,
+        run: impl FnOnce(&[DeviceTensor<D>]) -> Result<Vec<DeviceTensor<D>>, Error>,
+    ) -> Result<Vec<DeviceTensor<D>>, Error> {
+        let passed = if pass_cache {
+            self.inputs()
+        } else {
+            Vec::new()
+        };
+        let all: Vec<_> = inputs
+            .iter()
+            .map(|t| wrap(t.shallow_clone()))
+            .chain(passed)
+            .collect();
+        let mut outputs = run(&all)?;
+        drop(all);
+        let cached = 2 * self.config.layers;
+        if outputs.len() < cached {
+            return Err(Error::InvalidInput(format!(
+                "the model returned {} outputs, fewer than the {cached} cache tensors",
+                outputs.len()
+            )));
+        }
+        let cache = outputs.split_off(outputs.len() - cached);
+        self.update(cache)?;
+        Ok(outputs)
+    }
+}
+
+impl<D: Device> AOTIModel<D> {
+    /// Run one decoding step: `inputs` followed by `cache`'s tensors go in,
+    /// the model's last `2 * layers` outputs are stored back into `cache`,
+    /// and the other outputs (typically the logits) are returned.
+    pub fn run_with_cache(
+        &mut self,
+        inputs: &[DeviceTensor<D>],
+        cache: &mut KvCache<D>,
+    ) -> Result<Vec<DeviceTensor<D>>, Error> {
+        cache.step(inputs, true, |all| self.run(all).map(Vec::from))
+    }
+}
+
+impl<D: Device> AOTIModelPool<D> {
+    /// [`AOTIModel::run_with_cache`] on any idle replica. The cache belongs
+    /// to the caller, so consecutive steps may run on different replicas.
+    pub fn run_with_cache(
+        &self,
+        inputs: &[DeviceTensor<D>],
+        cache: &mut KvCache<D>,
+    ) -> Result<Vec<DeviceTensor<D>>, Error> {
+        cache.step(inputs, true, |all| self.run(all).map(Vec::from))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Cpu;
+
+    fn steps(batch: i64, len: i64, value: f32) -> Vec<DeviceTensor<Cpu>> {
+        (0..2)
+            .map(|_| {
+                wrap(Tensor::full(
+                    [batch, 1, len, 1],
+                    value as f64,
+                    (Kind::Float, tch::Device::Cpu),
+                ))
+            })
+            .collect()
+    }
+
+    fn values(t: &DeviceTensor<Cpu>) -> Vec<f32> {
+        Vec::<f32>::try_from(&t.flatten(0, -1)).unwrap()
+    }
+
+    #[test]
+    fn static_caches_fill_each_sequence_from_its_own_length() {
+        let config = KvCacheConfig::new(1, 1, 1, 4).batch(2);
+        let mut cache = KvCache::<Cpu>::new(config, tch::Device::Cpu).unwrap();
+        assert_eq!(cache.update(steps(2, 2, 1.0)).unwrap(), 2);
+        cache.truncate(1, 1).unwrap();
+        assert_eq!(cache.update(steps(2, 1, 2.0)).unwrap(), 1);
+        assert_eq!(cache.lengths(), [3, 2]);
+        assert_eq!(values(&cache.sequence(0).unwrap()[0][0]), [1.0, 1.0, 2.0]);
+        assert_eq!(values(&cache.sequence(1).unwrap()[0][1]), [1.0, 2.0]);
+        assert_eq!(
+            Vec::<i64>::try_from(&cache.positions(2).unwrap().flatten(0, -1)).unwrap(),
+            [3, 4, 2, 3]
+        );
+
+        // Sequence 0 would overflow; neither sequence changes.
+        assert!(cache.update(steps(2, 2, 3.0)).is_err());
+        assert_eq!(cache.lengths(), [3, 2]);
+        cache.reset(0).unwrap();
+        assert_eq!(cache.lengths(), [0, 2]);
+        assert!(cache.reset(2).is_err());
+    }
+
+    #[test]
+    fn growing_caches_are_replaced_and_truncated_together() {
+        let config = KvCacheConfig::new(1, 1, 1, 4)
+            .batch(2)
+            .layout(KvLayout::Growing);
+        let mut cache = KvCache::<Cpu>::new(config, tch::Device::Cpu).unwrap();
+        assert_eq!(cache.inputs()[0].size(), [2, 1, 0, 1]);
+        assert_eq!(cache.update(steps(2, 3, 1.0)).unwrap(), 3);
+        assert_eq!(cache.inputs()[1].size(), [2, 1, 3, 1]);
+        assert!(cache.truncate(0, 1).is_err());
+        cache.truncate_all(1).unwrap();
+        assert_eq!(cache.lengths(), [1, 1]);
+        assert_eq!(cache.inputs()[0].size(), [2, 1, 1, 1]);
+        assert!(cache.update(steps(2, 5, 1.0)).is_err());
+        let mut one_tensor = steps(2, 2, 1.0);
+        one_tensor.truncate(1);
+        assert!(matches!(
+            cache.update(one_tensor),
+            Err(Error::InvalidInput(_))
+        ));
+    }
+}
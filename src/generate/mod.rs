@@ -0,0 +1,40 @@
+//! Autoregressive decoding with AOTI-exported decoders.
+//!
+//! Decoders exported with an explicit KV cache take the cache tensors as
+//! inputs after their own and return the updated entries as their last
+//! outputs. [`KvCache`] allocates those tensors, tracks how much of each
+//! sequence's cache is filled, and feeds them through
+//! [`AOTIModel::run_with_cache`](crate::AOTIModel::run_with_cache).
+//! [`Generator`] drives the decoding loop on top, greedily or with
+//! [`Sampling`] after any [`LogitProcessor`]s (such as [`Constrained`],
+//! which only lets through what an [`Automaton`] allows), yielding tokens
+//! as an iterator or, with feature `tokio`, a [`TokenStream`];
+//! [`Generator::beam_search`] runs a [`BeamSearch`] instead, and
+//! [`SpeculativeDecoder`] pairs it with a draft model. [`PrefillDecode`]
+//! runs packages exported separately for the prompt and for later steps
+//! as one decoder, and a [`PrefixCache`] saves prefilling prompt prefixes
+//! seen before.
+
+mod beam;
+mod constrained;
+mod generator;
+mod kv;
+mod prefill;
+mod prefix;
+mod processors;
+mod sampling;
+mod speculative;
+#[cfg(feature = "tokio")]
+mod stream;
+
+pub use beam::{BeamSearch, Hypothesis};
+pub use constrained::{Automaton, Constrained};
+pub use generator::{Decode, FinishReason, GenerateConfig, Generator, Token, Tokens};
+pub use kv::{KvCache, KvCacheConfig, KvLayout};
+pub use prefill::{PrefillDecode, Run, Signature};
+pub use prefix::PrefixCache;
+pub use processors::{BadWords, LogitBias, LogitProcessor, RepetitionPenalty};
+pub use sampling::{Sampler, Sampling, temperature, top_k, top_p};
+pub use speculative::SpeculativeDecoder;
+#[cfg(feature = "tokio")]
+pub use stream::TokenStream;
@@ -0,0 +1,237 @@
+//! Separate prefill and decode packages behind one [`Decode`].
+
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use tch::Tensor;
+
+use crate::generate::{Decode, KvCache};
+use crate::{AOTIModel, AOTIModelPool, Device, DeviceTensor, Error};
+
+fn wrap<D: Device>(tensor: Tensor) -> DeviceTensor<D> {
+    DeviceTensor {
+        tensor,
+        _device: PhantomData,
+    }
+}
+
+/// A package run on plain inputs, for [`PrefillDecode`],
+/// [`Embedder`](crate::embedding::Embedder) and
+/// [`Golden`](crate::golden::Golden).
+pub trait Run<D: Device> {
+    fn run(&mut self, inputs: &[DeviceTensor<D>]) -> Result<Vec<DeviceTensor<D>>, Error>;
+
+    /// The device inputs must be on.
+    fn device(&self) -> tch::Device;
+}
+
+impl<D: Device> Run<D> for AOTIModel<D> {
+    fn run(&mut self, inputs: &[DeviceTensor<D>]) -> Result<Vec<DeviceTensor<D>>, Error> {
+        AOTIModel::run(self, inputs).map(Vec::from)
+    }
+
+    fn device(&self) -> tch::Device {
+        AOTIModel::device(self)
+    }
+}
+
+impl<D: Device> Run<D> for Arc<AOTIModelPool<D>> {
+    fn run(&mut self, inputs: &[DeviceTensor<D>]) -> Result<Vec<DeviceTensor<D>>, Error> {
+        AOTIModelPool::run(self, inputs).map(Vec::from)
+    }
+
+    fn device(&self) -> tch::Device {
+        AOTIModelPool::device(self)
+    }
+}
+
+impl<D: Device, T: Run<D> + ?Sized> Run<D> for &mut T {
+    fn run(&mut self, inputs: &[DeviceTensor<D>]) -> Result<Vec<DeviceTensor<D>>, Error> {
+        (**self).run(inputs)
+    }
+
+    fn device(&self) -> tch::Device {
+        (**self).device()
+    }
+}
+
+/// What a package takes after the `[batch, steps]` token ids. Either way
+/// it returns the logits first and the cache, `k0, v0, k1, v1, ...`, last.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Signature {
+    /// `[batch, steps]` `Int64` positions, from [`KvCache::positions`].
+    pub positions: bool,
+    /// The cache tensors, after everything else.
+    pub cache: bool,
+}
+
+impl Signature {
+    /// Ids and positions in, the full prompt's cache entries out.
+    pub const PREFILL: Self = Self {
+        positions: true,
+        cache: false,
+    };
+    /// Ids, positions and the cache in, the updated cache out.
+    pub const DECODE: Self = Self {
+        positions: true,
+        cache: true,
+    };
+
+    pub fn positions(mut self, positions: bool) -> Self {
+        self.positions = positions;
+        self
+    }
+
+    pub fn cache(mut self, cache: bool) -> Self {
+        self.cache = cache;
+        self
+    }
+}
+
+/// A prefill package and a decode package sharing one [`KvCache`].
+///
+/// A step on an empty cache runs the prefill package, whose cache outputs
+/// fill the cache; every later step runs the decode package on it. Each
+/// package is called with its own [`Signature`] (by default
+/// [`Signature::PREFILL`] and [`Signature::DECODE`]), so only the token
+/// ids are taken from the caller's inputs: pair it with a
+/// [`GenerateConfig`] whose `positions` is off.
+///
+/// [`GenerateConfig`]: crate::generate::GenerateConfig
+pub struct PrefillDecode<P, Dm> {
+    prefill: P,
+    decode: Dm,
+    prefill_signature: Signature,
+    decode_signature: Signature,
+}
+
+impl<P, Dm> PrefillDecode<P, Dm> {
+    pub fn new(prefill: P, decode: Dm) -> Self {
+        Self {
+            prefill,
+            decode,
+            prefill_signature: Signature::PREFILL,
+            decode_signature: Signature::DECODE,
+        }
+    }
+
+    /// Set how the prefill package is called.
+    pub fn prefill_signature(mut self, signature: Signature) -> Self {
+        self.prefill_signature = signature;
+        self
+    }
+
+    /// Set how the decode package is called.
+    pub fn decode_signature(mut self, signature: Signature) -> Self {
+        self.decode_signature = signature;
+        self
+    }
+
+    /// Take the prefill and decode packages back.
+    pub fn into_parts(self) -> (P, Dm) {
+        (self.prefill, self.decode)
+    }
+}
+
+impl<D: Device, P: Run<D>, Dm: Run<D>> Decode<D> for PrefillDecode<P, Dm> {
+    fn decode(
+        &mut self,
+        inputs: &[DeviceTensor<D>],
+        cache: &mut KvCache<D>,
+    ) -> Result<Vec<DeviceTensor<D>>, Error> {
+        let ids = inputs
+            .first()
+            .ok_or_else(|| Error::InvalidInput("a decoding step needs token ids".into()))?;
+        if cache.lengths().iter().all(|&len| len == 0) {
+            call(&mut self.prefill, self.prefill_signature, ids, cache)
+        } else {
+            call(&mut self.decode, self.decode_signature, ids, cache)
+        }
+    }
+}
+
+fn call<D: Device>(
+    model: &mut impl Run<D>,
+    signature: Signature,
+    ids: &DeviceTensor<D>,
+    cache: &mut KvCache<D>,
+) -> Result<Vec<DeviceTensor<D>>, Error> {
+    let mut inputs = vec![wrap(ids.shallow_clone())];
+    if signature.positions {
+        inputs.push(cache.positions(ids.size()[1] as usize)?);
+    }
+    cache.step(&inputs, signature.cache, |all| model.run(all))
+}
+
+#[cfg(test)]
+mod tests {
+    use tch::Kind;
+
+    use super::*;
+    use crate::Cpu;
+    use crate::generate::{GenerateConfig, Generator, KvCacheConfig};
+
+    /// Predicts `id + 1` modulo 8, checking how many inputs it gets.
+    struct Shift {
+        inputs: usize,
+        calls: usize,
+    }
+
+    impl Run<Cpu> for Shift {
+        fn run(&mut self, inputs: &[DeviceTensor<Cpu>]) -> Result<Vec<DeviceTensor<Cpu>>, Error> {
+            assert_eq!(inputs.len(), self.inputs);
+            self.calls += 1;
+            let [batch, steps] = inputs[0].size()[..] else {
+                unreachable!()
+            };
+            let next = inputs[0].f_add_scalar(1)?.f_remainder(8)?;
+            let logits = next.f_one_hot(8)?.f_to_kind(Kind::Float)?;
+            let entry = || {
+                wrap(Tensor::zeros(
+                    [batch, 1, steps, 1],
+                    (Kind::Float, tch::Device::Cpu),
+                ))
+            };
+            Ok(vec![wrap(logits), entry(), entry()])
+        }
+
+        fn device(&self) -> tch::Device {
+            tch::Device::Cpu
+        }
+    }
+
+    fn shift(inputs: usize) -> Shift {
+        Shift { inputs, calls: 0 }
+    }
+
+    #[test]
+    fn prefill_runs_once_then_decode_takes_over() {
+        let cache =
+            KvCache::new(KvCacheConfig::new(1, 1, 1, 8).batch(2), tch::Device::Cpu).unwrap();
+        let model = PrefillDecode::new(shift(2), shift(4));
+        let mut generator = Generator::new(model, cache, GenerateConfig::new(3).positions(false));
+        let generated = generator.generate(&[vec![1, 2, 3], vec![5, 6, 7]]).unwrap();
+        assert_eq!(generated, [vec![4, 5, 6], vec![0, 1, 2]]);
+        let (model, cache) = generator.into_parts();
+        assert_eq!(cache.lengths(), [5, 5]);
+        let (prefill, decode) = model.into_parts();
+        assert_eq!((prefill.calls, decode.calls), (1, 2));
+    }
+
+    #[test]
+    fn signatures_choose_the_inputs() {
+        let mut cache = KvCache::new(KvCacheConfig::new(1, 1, 1, 8), tch::Device::Cpu).unwrap();
+        let ids = wrap(Tensor::from_slice(&[1i64, 2]).view([1, 2]));
+        let mut model = PrefillDecode::new(shift(1), shift(3))
+            .prefill_signature(Signature::PREFILL.positions(false))
+            .decode_signature(Signature::DECODE.positions(false));
+        model
+            .decode(std::slice::from_ref(&ids), &mut cache)
+            .unwrap();
+        model
+            .decode(std::slice::from_ref(&ids), &mut cache)
+            .unwrap();
+        assert_eq!(cache.lengths(), [4]);
+        assert!(model.decode(&[], &mut cache).is_err());
+    }
+}
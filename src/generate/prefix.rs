@@ -0,0 +1,285 @@
+//! Reusing the cache entries of prompt prefixes across generations.
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hasher};
+use std::marker::PhantomData;
+
+use tch::Tensor;
+
+use crate::generate::KvCache;
+use crate::{Device, DeviceTensor, Error};
+
+fn wrap<D: Device>(tensor: Tensor) -> DeviceTensor<D> {
+    DeviceTensor {
+        tensor,
+        _device: PhantomData,
+    }
+}
+
+/// Cache entries for prompt prefixes, kept across generations so prompts
+/// that share a prefix (a system prompt, few-shot examples) only prefill
+/// the rest.
+///
+/// Prompts are cut into blocks of `block` tokens and each block's entries
+/// are stored under the hash of the prompt up to its end, so any prompt
+/// starting with the same blocks finds them. Blocks are evicted least
+/// recently used first once they take more than `max_bytes`.
+///
+/// Attach one with [`Generator::prefix_cache`]. The stored entries are only
+/// valid for the model and cache configuration they came from. Since a
+/// restored prefix leaves the cache non-empty, a [`PrefillDecode`] runs its
+/// decode package on the rest of the prompt.
+///
+/// [`Generator::prefix_cache`]: crate::generate::Generator::prefix_cache
+/// [`PrefillDecode`]: crate::generate::PrefillDecode
+pub struct PrefixCache<D: Device> {
+    block: usize,
+    max_bytes: usize,
+    bytes: usize,
+    clock: u64,
+    blocks: HashMap<u64, Block>,
+    _device: PhantomData<D>,
+}
+
+struct Block {
+    /// The prompt up to the end of this block, to rule out hash collisions.
+    prefix: Vec<i64>,
+    /// `[1, kv_heads, block, head_dim]` key and value per layer.
+    entries: Vec<[Tensor; 2]>,
+    bytes: usize,
+    used: u64,
+}
+
+impl<D: Device> PrefixCache<D> {
+    pub fn new(block: usize, max_bytes: usize) -> Self {
+        Self {
+            block: block.max(1),
+            max_bytes,
+            bytes: 0,
+            clock: 0,
+            blocks: HashMap::new(),
+            _device: PhantomData,
+        }
+    }
+
+    /// Device memory the stored blocks occupy.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Number of stored blocks.
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.blocks.clear();
+        self.bytes = 0;
+    }
+
+    /// The hash of `prompt` up to the end of each whole block.
+    fn hashes(&self, prompt: &[i64]) -> Vec<(usize, u64)> {
+        let mut hasher = DefaultHasher::new();
+        prompt
+            .chunks_exact(self.block)
+            .enumerate()
+            .map(|(i, chunk)| {
+                for &token in chunk {
+                    hasher.write_i64(token);
+                }
+                ((i + 1) * self.block, hasher.finish())
+            })
+            .collect()
+    }
+
+    /// The stored blocks `prompt` starts with, in order.
+    fn lookup(&mut self, prompt: &[i64]) -> Vec<u64> {
+        self.clock += 1;
+        let mut found = Vec::new();
+        for (end, hash) in self.hashes(prompt) {
+            match self.blocks.get_mut(&hash) {
+                Some(block) if block.prefix == prompt[..end] => {
+                    block.used = self.clock;
+                    found.push(hash);
+                }
+                _ => break,
+            }
+        }
+        found
+    }
+
+    /// Fill the empty `cache` with the longest stored prefix all of
+    /// `prompts` share the blocks of, leaving at least their last token to
+    /// prefill. Returns the prefix length.
+    pub(super) fn restore(
+        &mut self,
+        prompts: &[Vec<i64>],
+        cache: &mut KvCache<D>,
+    ) -> Result<usize, Error> {
+        let found: Vec<_> = prompts
+            .iter()
+            .map(|prompt| self.lookup(&prompt[..prompt.len().saturating_sub(1)]))
+            .collect();
+        let blocks = found.iter().map(Vec::len).min().unwrap_or(0);
+        if blocks == 0 {
+            return Ok(0);
+        }
+        let mut entries = Vec::with_capacity(2 * cache.config().layers);
+        for layer in 0..cache.config().layers {
+            for kv in 0..2 {
+                let rows = found
+                    .iter()
+                    .map(|hashes| {
+                        let parts: Vec<_> = hashes[..blocks]
+                            .iter()
+                            .map(|hash| &self.blocks[hash].entries[layer][kv])
+                            .collect();
+                        Tensor::f_cat(&parts, 2)
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                entries.push(wrap(Tensor::f_cat(&rows, 0)?));
+            }
+        }
+        cache.update(entries)?;
+        Ok(blocks * self.block)
+    }
+
+    /// Store the whole blocks of each of `prompts` that aren't stored yet,
+    /// from `cache`, which holds at least the prompts.
+    pub(super) fn store(&mut self, prompts: &[Vec<i64>], cache: &KvCache<D>) -> Result<(), Error> {
+        self.clock += 1;
+        for (seq, prompt) in prompts.iter().enumerate() {
+            let mut filled = None;
+            for (end, hash) in self.hashes(prompt) {
+                if let Some(block) = self.blocks.get_mut(&hash)
+                    && block.prefix == prompt[..end]
+                {
+                    block.used = self.clock;
+                    continue;
+                }
+                if filled.is_none() {
+                    filled = Some(cache.sequence(seq)?);
+                }
+                let start = (end - self.block) as i64;
+                let entries = filled
+                    .iter()
+                    .flatten()
+                    .map(|[key, value]| {
+                        let copy = |t: &DeviceTensor<D>| -> Result<Tensor, Error> {
+                            Ok(t.f_narrow(2, start, self.block as i64)?.copy())
+                        };
+                        Ok([copy(key)?, copy(value)?])
+                    })
+                    .collect::<Result<Vec<_>, Error>>()?;
+                let bytes = entries
+                    .iter()
+                    .flatten()
+                    .map(|t| t.numel() * t.kind().elt_size_in_bytes())
+                    .sum();
+                let block = Block {
+                    prefix: prompt[..end].to_vec(),
+                    entries,
+                    bytes,
+                    used: self.clock,
+                };
+                if let Some(old) = self.blocks.insert(hash, block) {
+                    self.bytes -= old.bytes;
+                }
+                self.bytes += bytes;
+            }
+        }
+        self.evict();
+        Ok(())
+    }
+
+    /// Drop least recently used blocks, longest prefix first among equally
+    /// recent ones, until the rest fit in `max_bytes`.
+    fn evict(&mut self) {
+        while self.bytes > self.max_bytes {
+            let Some(hash) = self
+                .blocks
+                .iter()
+                .min_by_key(|(_, block)| (block.used, Reverse(block.prefix.len())))
+                .map(|(&hash, _)| hash)
+            else {
+                break;
+            };
+            if let Some(block) = self.blocks.remove(&hash) {
+                self.bytes -= block.bytes;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tch::Kind;
+
+    use super::*;
+    use crate::Cpu;
+    use crate::generate::KvCacheConfig;
+
+    /// A one-layer cache holding each position's index as its entries.
+    fn filled(batch: usize, len: usize) -> KvCache<Cpu> {
+        let mut cache = KvCache::new(
+            KvCacheConfig::new(1, 1, 1, 8).batch(batch),
+            tch::Device::Cpu,
+        )
+        .unwrap();
+        let positions = Tensor::arange(len as i64, (Kind::Float, tch::Device::Cpu))
+            .view([1, 1, len as i64, 1])
+            .repeat([batch as i64, 1, 1, 1]);
+        let entries = (0..2).map(|_| wrap(positions.copy())).collect();
+        cache.update(entries).unwrap();
+        cache
+    }
+
+    #[test]
+    fn prompts_sharing_blocks_reuse_them() {
+        let mut prefixes = PrefixCache::<Cpu>::new(2, 1 << 20);
+        prefixes
+            .store(&[vec![1, 2, 3, 4, 5]], &filled(1, 5))
+            .unwrap();
+        assert_eq!(prefixes.len(), 2);
+        assert_eq!(prefixes.bytes(), 2 * 2 * 2 * 4);
+
+        // Both prompts start with the first block; only one has the second.
+        let mut cache =
+            KvCache::new(KvCacheConfig::new(1, 1, 1, 8).batch(2), tch::Device::Cpu).unwrap();
+        let prompts = [vec![1, 2, 3, 4, 9], vec![1, 2, 7, 7, 7]];
+        assert_eq!(prefixes.restore(&prompts, &mut cache).unwrap(), 2);
+        assert_eq!(cache.lengths(), [2, 2]);
+        let key = Vec::<f32>::try_from(&cache.sequence(1).unwrap()[0][0].flatten(0, -1)).unwrap();
+        assert_eq!(key, [0.0, 1.0]);
+
+        // The last token is always left to prefill.
+        let mut cache = KvCache::new(KvCacheConfig::new(1, 1, 1, 8), tch::Device::Cpu).unwrap();
+        assert_eq!(
+            prefixes.restore(&[vec![1, 2, 3, 4]], &mut cache).unwrap(),
+            2
+        );
+        let mut cache = KvCache::new(KvCacheConfig::new(1, 1, 1, 8), tch::Device::Cpu).unwrap();
+        assert_eq!(prefixes.restore(&[vec![2, 1, 3]], &mut cache).unwrap(), 0);
+    }
+
+    #[test]
+    fn least_recently_used_blocks_are_evicted() {
+        // Room for three 16-byte blocks.
+        let mut prefixes = PrefixCache::<Cpu>::new(2, 48);
+        prefixes.store(&[vec![1, 2, 3, 4]], &filled(1, 4)).unwrap();
+        prefixes.store(&[vec![5, 6]], &filled(1, 2)).unwrap();
+        assert_eq!(prefixes.len(), 3);
+        // Using [1, 2, 3, 4] makes [5, 6] the least recently used.
+        prefixes.lookup(&[1, 2, 3, 4]);
+        prefixes.store(&[vec![7, 8]], &filled(1, 2)).unwrap();
+        assert_eq!(prefixes.bytes(), 48);
+        assert_eq!(prefixes.lookup(&[5, 6]).len(), 0);
+        assert_eq!(prefixes.lookup(&[1, 2, 3, 4]).len(), 2);
+        assert_eq!(prefixes.lookup(&[7, 8]).len(), 1);
+    }
+}
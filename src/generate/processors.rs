@@ -0,0 +1,158 @@
+//! Logit processors: adjustments applied to the logits before a token is
+//! picked.
+
+use std::collections::HashMap;
+
+use tch::{Kind, Tensor};
+
+use crate::Error;
+
+/// Adjusts a step's logits before sampling. A [`Generator`] applies its
+/// processors in the order they were added.
+///
+/// [`Generator`]: crate::generate::Generator
+pub trait LogitProcessor: Send {
+    /// Return new `[batch, vocab]` logits given the current ones and each
+    /// sequence's tokens so far, prompt included.
+    fn process(&mut self, logits: &Tensor, tokens: &[Vec<i64>]) -> Result<Tensor, Error>;
+
+    /// Forget any per-generation state; called as each generation starts.
+    fn reset(&mut self) {}
+}
+
+impl<F> LogitProcessor for F
+where
+    F: FnMut(&Tensor, &[Vec<i64>]) -> Result<Tensor, Error> + Send,
+{
+    fn process(&mut self, logits: &Tensor, tokens: &[Vec<i64>]) -> Result<Tensor, Error> {
+        self(logits, tokens)
+    }
+}
+
+/// A `[batch, vocab]` `Bool` mask, set where `select` lists a token for
+/// that row. Out-of-vocabulary ids are ignored.
+pub(super) fn mask(
+    logits: &Tensor,
+    rows: usize,
+    mut select: impl FnMut(usize) -> Vec<i64>,
+) -> Result<Tensor, Error> {
+    let vocab = logits.size()[1];
+    let mut mask = vec![false; rows * vocab as usize];
+    for row in 0..rows {
+        for token in select(row) {
+            if (0..vocab).contains(&token) {
+                mask[row * vocab as usize + token as usize] = true;
+            }
+        }
+    }
+    Ok(Tensor::from_slice(&mask)
+        .f_view([rows as i64, vocab])?
+        .f_to_device(logits.device())?)
+}
+
+pub(super) fn check(logits: &Tensor, tokens: &[Vec<i64>]) -> Result<(), Error> {
+    match logits.size()[..] {
+        [batch, _] if batch as usize == tokens.len() => Ok(()),
+        _ => Err(Error::InvalidInput(format!(
+            "expected [{}, vocab] logits, got {:?}",
+            tokens.len(),
+            logits.size()
+        ))),
+    }
+}
+
+/// Discourages tokens a sequence already contains: their logits are
+/// divided by `penalty` if positive and multiplied by it otherwise
+/// (the CTRL paper's penalty; 1 has no effect).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RepetitionPenalty(pub f64);
+
+impl LogitProcessor for RepetitionPenalty {
+    fn process(&mut self, logits: &Tensor, tokens: &[Vec<i64>]) -> Result<Tensor, Error> {
+        check(logits, tokens)?;
+        let seen = mask(logits, tokens.len(), |row| tokens[row].clone())?;
+        let logits = logits.f_to_kind(Kind::Float)?;
+        let penalized = logits
+            .f_div_scalar(self.0)?
+            .f_where_self(&logits.f_gt(0.0)?, &logits.f_mul_scalar(self.0)?)?;
+        Ok(penalized.f_where_self(&seen, &logits)?)
+    }
+}
+
+/// Bans token sequences: a sequence's last token is excluded whenever the
+/// tokens so far end with the rest of it, so single tokens are always
+/// excluded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BadWords(pub Vec<Vec<i64>>);
+
+impl LogitProcessor for BadWords {
+    fn process(&mut self, logits: &Tensor, tokens: &[Vec<i64>]) -> Result<Tensor, Error> {
+        check(logits, tokens)?;
+        let banned = mask(logits, tokens.len(), |row| {
+            self.0
+                .iter()
+                .filter_map(|word| {
+                    let (last, prefix) = word.split_last()?;
+                    tokens[row].ends_with(prefix).then_some(*last)
+                })
+                .collect()
+        })?;
+        Ok(logits
+            .f_to_kind(Kind::Float)?
+            .f_masked_fill(&banned, f64::NEG_INFINITY)?)
+    }
+}
+
+/// Adds a fixed bias to chosen tokens' logits, e.g. `-inf` to forbid one
+/// or a large value to force it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LogitBias(pub HashMap<i64, f64>);
+
+impl LogitProcessor for LogitBias {
+    fn process(&mut self, logits: &Tensor, _tokens: &[Vec<i64>]) -> Result<Tensor, Error> {
+        let vocab = logits.size().last().copied().unwrap_or(0);
+        let mut bias = vec![0f32; vocab as usize];
+        for (&token, &value) in &self.0 {
+            if let Some(slot) = usize::try_from(token).ok().and_then(|t| bias.get_mut(t)) {
+                *slot = value as f32;
+            }
+        }
+        let bias = Tensor::from_slice(&bias).f_to_device(logits.device())?;
+        Ok(logits.f_to_kind(Kind::Float)?.f_add(&bias)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(t: &Tensor) -> Vec<f32> {
+        Vec::<f32>::try_from(&t.flatten(0, -1)).unwrap()
+    }
+
+    #[test]
+    fn repetition_penalty_and_bias_rescale_logits() {
+        let logits = Tensor::from_slice(&[2.0f32, -2.0, 1.0, 4.0, -1.0, 1.0]).view([2, 3]);
+        let tokens = [vec![0, 1], vec![2, 7]];
+        let penalized = RepetitionPenalty(2.0).process(&logits, &tokens).unwrap();
+        assert_eq!(values(&penalized), [1.0, -4.0, 1.0, 4.0, -1.0, 0.5]);
+
+        let mut bias = LogitBias(HashMap::from([(1, 10.0), (-1, 1.0), (9, 1.0)]));
+        let biased = bias.process(&logits, &tokens).unwrap();
+        assert_eq!(values(&biased), [2.0, 8.0, 1.0, 4.0, 9.0, 1.0]);
+        assert!(
+            RepetitionPenalty(2.0)
+                .process(&logits, &tokens[..1])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn bad_words_ban_their_last_token_after_the_rest() {
+        let logits = Tensor::zeros([2, 4], (Kind::Float, tch::Device::Cpu));
+        let mut bad = BadWords(vec![vec![3], vec![0, 1, 2], vec![]]);
+        let inf = f32::NEG_INFINITY;
+        let banned = bad.process(&logits, &[vec![2, 0, 1], vec![1, 0]]).unwrap();
+        assert_eq!(values(&banned), [0.0, 0.0, inf, inf, 0.0, 0.0, 0.0, inf]);
+    }
+}
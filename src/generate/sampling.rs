@@ -0,0 +1,221 @@
+//! Logit filtering and token sampling.
+//!
+//! The filters take `[..., vocab]` logits and return `Float` logits of the
+//! same shape with the excluded tokens set to `-inf`, so they compose and
+//! feed straight into a softmax. Sampling draws from a seeded generator of
+//! its own rather than libtorch's global one, so a seed reproduces a
+//! generation regardless of what else runs in the process.
+
+use std::hash::{BuildHasher, RandomState};
+
+use tch::{Kind, Tensor};
+
+use crate::Error;
+
+/// Divide `logits` by `temperature`: below 1 sharpens the distribution,
+/// above 1 flattens it.
+pub fn temperature(logits: &Tensor, temperature: f64) -> Result<Tensor, Error> {
+    if !(temperature > 0.0 && temperature.is_finite()) {
+        return Err(Error::InvalidInput(format!(
+            "temperature must be positive, got {temperature}"
+        )));
+    }
+    Ok(logits.f_to_kind(Kind::Float)?.f_div_scalar(temperature)?)
+}
+
+/// Keep the `k` most likely tokens (ties with the `k`-th included).
+pub fn top_k(logits: &Tensor, k: usize) -> Result<Tensor, Error> {
+    let logits = logits.f_to_kind(Kind::Float)?;
+    let vocab = logits.size().last().copied().unwrap_or(0);
+    if k == 0 || k as i64 >= vocab {
+        return Ok(logits);
+    }
+    let (values, _) = logits.f_topk(k as i64, -1, true, true)?;
+    let kth = values.f_narrow(-1, k as i64 - 1, 1)?;
+    Ok(logits.f_masked_fill(&logits.f_lt_tensor(&kth)?, f64::NEG_INFINITY)?)
+}
+
+/// Keep the smallest set of most likely tokens whose probabilities sum to
+/// at least `p` (nucleus sampling). The most likely token is always kept.
+pub fn top_p(logits: &Tensor, p: f64) -> Result<Tensor, Error> {
+    let logits = logits.f_to_kind(Kind::Float)?;
+    if p >= 1.0 {
+        return Ok(logits);
+    }
+    let (sorted, indices) = logits.f_sort(-1, true)?;
+    let probs = sorted.f_softmax(-1, Kind::Float)?;
+    // Drop a token once the more likely ones already reach `p`.
+    let before = probs.f_cumsum(-1, Kind::Float)?.f_sub(&probs)?;
+    let drop = before.f_gt(p.max(0.0))?;
+    let drop = drop.f_zeros_like()?.f_scatter(-1, &indices, &drop)?;
+    Ok(logits.f_masked_fill(&drop, f64::NEG_INFINITY)?)
+}
+
+/// How [`Sampler`] picks tokens. The default is greedy decoding.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sampling {
+    /// Softmax temperature; 0 picks the most likely token (greedy).
+    pub temperature: f64,
+    /// Sample among this many most likely tokens only; 0 for all.
+    pub top_k: usize,
+    /// Sample within this much probability mass only; 1 for all.
+    pub top_p: f64,
+    /// Seed for reproducible sampling (default: a random one).
+    pub seed: Option<u64>,
+}
+
+impl Default for Sampling {
+    fn default() -> Self {
+        Self::greedy()
+    }
+}
+
+impl Sampling {
+    /// Always pick the most likely token.
+    pub fn greedy() -> Self {
+        Self::new(0.0)
+    }
+
+    /// Sample at `temperature` from the whole vocabulary.
+    pub fn new(temperature: f64) -> Self {
+        Self {
+            temperature,
+            top_k: 0,
+            top_p: 1.0,
+            seed: None,
+        }
+    }
+
+    pub fn top_k(mut self, k: usize) -> Self {
+        self.top_k = k;
+        self
+    }
+
+    pub fn top_p(mut self, p: f64) -> Self {
+        self.top_p = p;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn is_greedy(&self) -> bool {
+        self.temperature == 0.0
+    }
+
+    /// Apply the temperature, top-k and top-p filters, in that order.
+    pub fn filter(&self, logits: &Tensor) -> Result<Tensor, Error> {
+        let logits = temperature(logits, self.temperature)?;
+        top_p(&top_k(&logits, self.top_k)?, self.top_p)
+    }
+}
+
+/// Picks tokens from `[batch, vocab]` logits according to a [`Sampling`].
+pub struct Sampler {
+    sampling: Sampling,
+    /// SplitMix64 state.
+    state: u64,
+}
+
+impl Sampler {
+    pub fn new(sampling: Sampling) -> Self {
+        let state = sampling
+            .seed
+            .unwrap_or_else(|| RandomState::new().hash_one(std::time::Instant::now()));
+        Self { sampling, state }
+    }
+
+    pub fn sampling(&self) -> &Sampling {
+        &self.sampling
+    }
+
+    /// A uniform draw from `[0, 1)`.
+    fn uniform(&mut self) -> f64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        (z >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// One token id per row of `[batch, vocab]` logits.
+    pub fn sample(&mut self, logits: &Tensor) -> Result<Vec<i64>, Error> {
+        if logits.dim() != 2 {
+            return Err(Error::InvalidInput(format!(
+                "expected [batch, vocab] logits, got {:?}",
+                logits.size()
+            )));
+        }
+        let ids = if self.sampling.is_greedy() {
+            logits.f_argmax(-1, false)?
+        } else {
+            let probs = self.sampling.filter(logits)?.f_softmax(-1, Kind::Double)?;
+            let cumulative = probs.f_cumsum(-1, Kind::Double)?;
+            let draws: Vec<f64> = (0..logits.size()[0]).map(|_| self.uniform()).collect();
+            // Scale by each row's total so rounding can't push a draw past
+            // the last token.
+            let total = cumulative.f_narrow(-1, -1, 1)?;
+            let draws = Tensor::from_slice(&draws)
+                .f_to_device(logits.device())?
+                .f_unsqueeze(1)?
+                .f_mul(&total)?;
+            let vocab = logits.size()[1];
+            cumulative
+                .f_le_tensor(&draws)?
+                .f_sum_dim_intlist(-1, false, Kind::Int64)?
+                .f_clamp_max(vocab - 1)?
+        };
+        Ok(Vec::<i64>::try_from(
+            &ids.f_to_device(tch::Device::Cpu)?.f_to_kind(Kind::Int64)?,
+        )?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(t: &Tensor) -> Vec<f32> {
+        Vec::<f32>::try_from(&t.flatten(0, -1)).unwrap()
+    }
+
+    #[test]
+    fn filters_mask_unlikely_tokens() {
+        let logits = Tensor::from_slice(&[1.0f32, 3.0, 2.0, 0.0]).view([1, 4]);
+        let inf = f32::NEG_INFINITY;
+        assert_eq!(values(&top_k(&logits, 2).unwrap()), [inf, 3.0, 2.0, inf]);
+        assert_eq!(values(&top_k(&logits, 0).unwrap()), [1.0, 3.0, 2.0, 0.0]);
+        // Sorted, the probabilities are about 0.64, 0.24, 0.09 and 0.03.
+        assert_eq!(values(&top_p(&logits, 0.8).unwrap()), [inf, 3.0, 2.0, inf]);
+        assert_eq!(values(&top_p(&logits, 0.5).unwrap()), [inf, 3.0, inf, inf]);
+        assert_eq!(
+            values(&temperature(&logits, 2.0).unwrap()),
+            [0.5, 1.5, 1.0, 0.0]
+        );
+        assert!(temperature(&logits, 0.0).is_err());
+    }
+
+    #[test]
+    fn seeded_sampling_is_reproducible() {
+        let logits =
+            Tensor::from_slice(&[0.0f32, 0.0, 0.0, 0.0, 5.0, 5.0, -50.0, -50.0]).view([2, 4]);
+        let draw = |seed| {
+            let mut sampler = Sampler::new(Sampling::new(1.0).top_k(2).seed(seed));
+            (0..32)
+                .flat_map(|_| sampler.sample(&logits).unwrap())
+                .collect::<Vec<_>>()
+        };
+        let drawn = draw(7);
+        assert_eq!(drawn, draw(7));
+        assert_ne!(drawn, draw(8));
+        // Row 0 is uniform (top-k keeps ties); row 1 only has two tokens left.
+        assert!(drawn.iter().step_by(2).any(|&id| id != drawn[0]));
+        assert!(drawn.iter().skip(1).step_by(2).all(|&id| id < 2));
+
+        let mut greedy = Sampler::new(Sampling::greedy());
+        assert_eq!(greedy.sample(&logits.narrow(0, 1, 1)).unwrap(), [0]);
+    }
+}
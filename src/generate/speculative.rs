@@ -0,0 +1,238 @@
+//! Speculative decoding: a small draft model proposes tokens and the
+//! target model checks them all in one step.
+
+use std::time::Instant;
+
+use tch::Tensor;
+
+use crate::generate::{Decode, Generator, Sampler};
+use crate::{Device, Error};
+
+/// Decodes with a target [`Generator`], letting a draft generator guess
+/// `lookahead` tokens ahead.
+///
+/// Each round the draft proposes tokens one step at a time, then the
+/// target runs once on all of them and picks its own token at every
+/// position with its processors and
+/// [`Sampling`](crate::generate::Sampling). Proposals are accepted up to
+/// the first one the target disagrees with, whose place the target's
+/// token takes, so the output is exactly what the target alone would
+/// produce; only the number of target steps changes. Both caches are
+/// rolled back past rejected tokens.
+///
+/// Both generators decode one sequence (cache batch 1) and must share a
+/// tokenizer; the target's decoder must return logits for every position,
+/// `[1, steps, vocab]`. `max_new_tokens` and the stop criteria come from
+/// the target's config.
+pub struct SpeculativeDecoder<D: Device, T, Dr> {
+    target: Generator<D, T>,
+    draft: Generator<D, Dr>,
+    lookahead: usize,
+    proposed: usize,
+    accepted: usize,
+}
+
+impl<D: Device, T: Decode<D>, Dr: Decode<D>> SpeculativeDecoder<D, T, Dr> {
+    pub fn new(
+        target: Generator<D, T>,
+        draft: Generator<D, Dr>,
+        lookahead: usize,
+    ) -> Result<Self, Error> {
+        if lookahead == 0 {
+            return Err(Error::InvalidInput(
+                "speculative decoding needs a lookahead of at least one token".into(),
+            ));
+        }
+        if target.cache().config().batch != 1 || draft.cache().config().batch != 1 {
+            return Err(Error::InvalidInput(
+                "speculative decoding needs target and draft caches of batch 1".into(),
+            ));
+        }
+        Ok(Self {
+            target,
+            draft,
+            lookahead,
+            proposed: 0,
+            accepted: 0,
+        })
+    }
+
+    /// Take the target and draft generators back.
+    pub fn into_parts(self) -> (Generator<D, T>, Generator<D, Dr>) {
+        (self.target, self.draft)
+    }
+
+    /// The share of the last generation's draft proposals the target
+    /// accepted, or `None` if none were made.
+    pub fn acceptance_rate(&self) -> Option<f64> {
+        (self.proposed > 0).then(|| self.accepted as f64 / self.proposed as f64)
+    }
+
+    /// Generate from `prompt`, returning the new tokens.
+    pub fn generate(&mut self, prompt: &[i64]) -> Result<Vec<i64>, Error> {
+        let prompts = [prompt.to_vec()];
+        let ids = self.target.start(&prompts)?;
+        // The draft gets the prompt in its first round.
+        self.draft.reset()?;
+        let config = self.target.config().clone();
+        let started = Instant::now();
+        let mut sampler = Sampler::new(config.sampling.clone());
+        let mut draft_sampler = Sampler::new(self.draft.config().sampling.clone());
+        (self.proposed, self.accepted) = (0, 0);
+
+        let logits = self.target.forward(&ids)?;
+        let mut last = sampler.sample(&self.target.process(logits, &prompts)?)?[0];
+        let mut generated = vec![last];
+        // Tokens not yet in the draft's cache, ending with `last`, which
+        // isn't in the target's either.
+        let mut unseen = [prompt, &[last]].concat();
+        let stopped =
+            |generated: &[i64]| config.stop_reason(&[prompt, generated].concat()).is_some();
+        while generated.len() < config.max_new_tokens
+            && !stopped(&generated)
+            && !config.timed_out(started)
+        {
+            // Leave room for the target's own token after the proposals.
+            let k = self
+                .lookahead
+                .min(config.max_new_tokens - generated.len() - 1);
+            let drafted = self.draft.cache().lengths()[0];
+            let mut proposals = Vec::with_capacity(k);
+            for i in 0..k {
+                let feed = if i == 0 {
+                    &unseen[..]
+                } else {
+                    &proposals[i - 1..i]
+                };
+                let logits = self.draft.forward(&row(feed)?)?;
+                let history = [[prompt, &generated, &proposals].concat()];
+                let logits = self.draft.process(logits, &history)?;
+                proposals.push(draft_sampler.sample(&logits)?[0]);
+            }
+
+            let verified = self.target.cache().lengths()[0];
+            let logits = self
+                .target
+                .forward_all(&row(&[&[last], &proposals[..]].concat())?)?;
+            let mut accepted = 0;
+            for position in 0..=k {
+                let history = [[prompt, &generated].concat()];
+                let logits = logits.f_select(1, position as i64)?;
+                let logits = self.target.process(logits, &history)?;
+                let token = sampler.sample(&logits)?[0];
+                generated.push(token);
+                last = token;
+                if proposals.get(position) != Some(&token) {
+                    break;
+                }
+                accepted += 1;
+                if stopped(&generated) {
+                    break;
+                }
+            }
+            self.proposed += k;
+            self.accepted += accepted;
+
+            // Keep the step's first token and the accepted proposals; the
+            // target's own token after them goes in next round.
+            self.target
+                .cache_mut()
+                .truncate(0, verified + 1 + accepted)?;
+            if k == 0 {
+                unseen.push(last);
+            } else {
+                // The draft saw `unseen` and every proposal but the last.
+                let seen = accepted.min(k - 1);
+                let len = drafted + unseen.len() + seen;
+                self.draft.cache_mut().truncate(0, len)?;
+                unseen = proposals[seen..accepted].to_vec();
+                unseen.push(last);
+            }
+        }
+        generated.truncate(config.max_new_tokens);
+        Ok(generated)
+    }
+}
+
+/// `tokens` as host `[1, len]` ids.
+fn row(tokens: &[i64]) -> Result<Tensor, Error> {
+    Ok(Tensor::from_slice(tokens).f_unsqueeze(0)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::marker::PhantomData;
+
+    use tch::Kind;
+
+    use super::*;
+    use crate::generate::{GenerateConfig, KvCache, KvCacheConfig};
+    use crate::{Cpu, DeviceTensor};
+
+    fn wrap(tensor: Tensor) -> DeviceTensor<Cpu> {
+        DeviceTensor {
+            tensor,
+            _device: PhantomData,
+        }
+    }
+
+    /// Predicts `id + 1` modulo 8, or 0 after `wrong_after`.
+    struct Shift {
+        wrong_after: i64,
+    }
+
+    impl Decode<Cpu> for Shift {
+        fn decode(
+            &mut self,
+            inputs: &[DeviceTensor<Cpu>],
+            cache: &mut KvCache<Cpu>,
+        ) -> Result<Vec<DeviceTensor<Cpu>>, Error> {
+            let [batch, steps] = inputs[0].size()[..] else {
+                unreachable!()
+            };
+            let next = inputs[0].f_add_scalar(1)?.f_remainder(8)?;
+            let next = next.f_masked_fill(&inputs[0].f_eq(self.wrong_after)?, 0)?;
+            let logits = next.f_one_hot(8)?.f_to_kind(Kind::Float)?;
+            let entry = || {
+                wrap(Tensor::zeros(
+                    [batch, 1, steps, 1],
+                    (Kind::Float, tch::Device::Cpu),
+                ))
+            };
+            cache.update(vec![entry(), entry()])?;
+            Ok(vec![wrap(logits)])
+        }
+    }
+
+    fn decoder(config: GenerateConfig, lookahead: usize) -> SpeculativeDecoder<Cpu, Shift, Shift> {
+        let generator = |wrong_after, config| {
+            let cache = KvCache::new(KvCacheConfig::new(1, 1, 1, 16), tch::Device::Cpu).unwrap();
+            Generator::new(Shift { wrong_after }, cache, config)
+        };
+        SpeculativeDecoder::new(
+            generator(-1, config),
+            generator(3, GenerateConfig::new(0)),
+            lookahead,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn output_matches_the_target_alone() {
+        let mut decoder = decoder(GenerateConfig::new(6), 3);
+        assert_eq!(decoder.generate(&[0]).unwrap(), [1, 2, 3, 4, 5, 6]);
+        // 2, 3 and 0 were proposed after 1, 0 was rejected, then 5 after 4.
+        assert_eq!(decoder.acceptance_rate(), Some(0.75));
+        let (target, draft) = decoder.into_parts();
+        assert_eq!(target.cache().lengths(), [6]);
+        assert_eq!(draft.cache().lengths(), [5]);
+    }
+
+    #[test]
+    fn generation_stops_at_an_accepted_eos() {
+        let mut decoder = decoder(GenerateConfig::new(6).eos([3]), 3);
+        assert_eq!(decoder.generate(&[0]).unwrap(), [1, 2, 3]);
+        assert_eq!(decoder.generate(&[6]).unwrap(), [7, 0, 1, 2, 3]);
+        assert!(SpeculativeDecoder::new(decoder.target, decoder.draft, 0).is_err());
+    }
+}
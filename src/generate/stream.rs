@@ -0,0 +1,51 @@
+//! Generated tokens as a `futures::Stream`, for async services.
+
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::sync::mpsc;
+
+use crate::generate::{Decode, Generator, Token};
+use crate::{Device, Error};
+
+/// Tokens decoded on Tokio's blocking pool, from
+/// [`Generator::into_stream`]. Dropping the stream stops decoding after
+/// the current step.
+pub struct TokenStream {
+    receiver: mpsc::Receiver<Result<Token, Error>>,
+}
+
+impl futures::Stream for TokenStream {
+    type Item = Result<Token, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+impl<D, M> Generator<D, M>
+where
+    D: Device + 'static,
+    M: Decode<D> + Send + 'static,
+{
+    /// [`Generator::tokens`] as a stream, e.g. to forward each token as a
+    /// server-sent event while the rest are decoded. Must be called from
+    /// within a Tokio runtime; decoding runs on its blocking pool, at most
+    /// `buffer` tokens ahead of the consumer.
+    pub fn into_stream(mut self, prompts: Vec<Vec<i64>>, buffer: usize) -> TokenStream {
+        let (sender, receiver) = mpsc::channel(buffer.max(1));
+        tokio::task::spawn_blocking(move || match self.tokens(&prompts) {
+            Ok(tokens) => {
+                for token in tokens {
+                    if sender.blocking_send(token).is_err() {
+                        break;
+                    }
+                }
+            }
+            Err(err) => {
+                let _ = sender.blocking_send(Err(err));
+            }
+        });
+        TokenStream { receiver }
+    }
+}
@@ -0,0 +1,321 @@
+//! Golden record-and-replay regression tests.
+//!
+//! A [`Golden`] directory holds recorded cases, each a run's inputs and
+//! outputs in `<name>.safetensors` (as `input.<i>` and `output.<i>`), and a
+//! `manifest.json` listing them. Record the cases once with a known-good
+//! package and libtorch; after updating either, replay them to check that
+//! the outputs haven't moved beyond a tolerance, by default
+//! `torch.testing.assert_close`'s for each dtype:
+//!
+//! ```ignore
+//! let golden = Golden::open("tests/golden/classifier")?;
+//! golden.assert_replays(&mut model, Tolerances::new());
+//! ```
+
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+
+use serde_json::{Value, json};
+use tch::Tensor;
+
+use crate::compare::{Comparison, Tolerances, compare_outputs};
+use crate::generate::Run;
+use crate::{Device, DeviceTensor, Error, safetensors};
+
+const MANIFEST: &str = "manifest.json";
+
+fn wrap<D: Device>(tensor: Tensor) -> DeviceTensor<D> {
+    DeviceTensor {
+        tensor,
+        _device: PhantomData,
+    }
+}
+
+/// A case's inputs and the outputs they produced.
+pub type Recording<D> = (Vec<DeviceTensor<D>>, Vec<DeviceTensor<D>>);
+
+/// One recorded run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Case {
+    pub name: String,
+    pub inputs: usize,
+    pub outputs: usize,
+}
+
+/// How a replayed case compared with its recording.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replayed {
+    pub name: String,
+    pub comparison: Comparison,
+}
+
+impl Replayed {
+    pub fn passed(&self) -> bool {
+        self.comparison.passed()
+    }
+}
+
+/// A directory of recorded cases.
+#[derive(Debug)]
+pub struct Golden {
+    dir: PathBuf,
+    cases: Vec<Case>,
+}
+
+impl Golden {
+    /// Open `dir`, creating it if needed; a directory without a manifest
+    /// has no cases yet.
+    pub fn open(dir: impl Into<PathBuf>) -> Result<Self, Error> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+        let manifest = dir.join(MANIFEST);
+        let cases = if manifest.exists() {
+            parse_manifest(&std::fs::read(&manifest)?)
+                .map_err(|err| Error::Model(format!("{}: {err}", manifest.display())))?
+        } else {
+            Vec::new()
+        };
+        Ok(Self { dir, cases })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn cases(&self) -> &[Case] {
+        &self.cases
+    }
+
+    /// Record `inputs` and the `outputs` they produced as the case `name`,
+    /// replacing any earlier recording of it. Names become file names, so
+    /// they are limited to ASCII letters, digits, `-`, `_` and `.`.
+    pub fn record<D: Device>(
+        &mut self,
+        name: &str,
+        inputs: &[DeviceTensor<D>],
+        outputs: &[DeviceTensor<D>],
+    ) -> Result<(), Error> {
+        let valid = |c: char| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.');
+        if name.is_empty() || name.starts_with('.') || !name.chars().all(valid) {
+            return Err(Error::InvalidInput(format!(
+                "'{name}' is not a valid golden case name"
+            )));
+        }
+        let names: Vec<String> = (0..inputs.len())
+            .map(|i| format!("input.{i}"))
+            .chain((0..outputs.len()).map(|i| format!("output.{i}")))
+            .collect();
+        let named: Vec<_> = names
+            .iter()
+            .map(String::as_str)
+            .zip(inputs.iter().chain(outputs))
+            .collect();
+        safetensors::write_tensors(self.dir.join(format!("{name}.safetensors")), &named)?;
+        let case = Case {
+            name: name.to_string(),
+            inputs: inputs.len(),
+            outputs: outputs.len(),
+        };
+        match self.cases.iter_mut().find(|c| c.name == name) {
+            Some(existing) => *existing = case,
+            None => self.cases.push(case),
+        }
+        self.write_manifest()
+    }
+
+    /// Run `model` on `inputs` and record the outputs as the case `name`.
+    pub fn record_run<D: Device>(
+        &mut self,
+        model: &mut impl Run<D>,
+        name: &str,
+        inputs: &[DeviceTensor<D>],
+    ) -> Result<Vec<DeviceTensor<D>>, Error> {
+        let outputs = model.run(inputs)?;
+        self.record(name, inputs, &outputs)?;
+        Ok(outputs)
+    }
+
+    /// A recorded case's inputs and outputs, on `device`.
+    pub fn load<D: Device>(&self, case: &Case, device: tch::Device) -> Result<Recording<D>, Error> {
+        let path = self.dir.join(format!("{}.safetensors", case.name));
+        let mut tensors: HashMap<_, _> = safetensors::read_tensors(&path)?.into_iter().collect();
+        let mut take = |prefix: &str, count: usize| {
+            (0..count)
+                .map(|i| {
+                    let tensor = tensors.remove(&format!("{prefix}.{i}")).ok_or_else(|| {
+                        Error::Model(format!("{} has no tensor '{prefix}.{i}'", path.display()))
+                    })?;
+                    Ok(wrap(tensor.f_to_device(device)?))
+                })
+                .collect::<Result<Vec<_>, Error>>()
+        };
+        Ok((take("input", case.inputs)?, take("output", case.outputs)?))
+    }
+
+    /// Run `model` on every case's inputs and compare the outputs with the
+    /// recorded ones within `tolerances` (a single
+    /// [`Tolerance`](crate::compare::Tolerance) or per-dtype
+    /// [`Tolerances`]). Fails only if a case can't be loaded or run.
+    pub fn replay<D: Device>(
+        &self,
+        model: &mut impl Run<D>,
+        tolerances: impl Into<Tolerances>,
+    ) -> Result<Vec<Replayed>, Error> {
+        let tolerances = tolerances.into();
+        self.cases
+            .iter()
+            .map(|case| {
+                let (inputs, expected) = self.load(case, model.device())?;
+                let actual = model.run(&inputs)?;
+                Ok(Replayed {
+                    name: case.name.clone(),
+                    comparison: compare_outputs(&expected, &actual, &tolerances)?,
+                })
+            })
+            .collect()
+    }
+
+    /// [`Golden::replay`], panicking with every divergence if any case
+    /// diverges, can't be run, or there are no cases.
+    pub fn assert_replays<D: Device>(
+        &self,
+        model: &mut impl Run<D>,
+        tolerances: impl Into<Tolerances>,
+    ) {
+        assert!(
+            !self.cases.is_empty(),
+            "{} has no golden cases",
+            self.dir.display()
+        );
+        let replayed = match self.replay(model, tolerances) {
+            Ok(replayed) => replayed,
+            Err(err) => panic!("replaying {}: {err}", self.dir.display()),
+        };
+        let failures: Vec<String> = replayed
+            .iter()
+            .filter(|r| !r.passed())
+            .map(|r| format!("{}:\n{}", r.name, r.comparison))
+            .collect();
+        assert!(
+            failures.is_empty(),
+            "{} of {} golden cases diverged:\n{}",
+            failures.len(),
+            replayed.len(),
+            failures.join("\n")
+        );
+    }
+
+    fn write_manifest(&self) -> Result<(), Error> {
+        let cases: Vec<Value> = self
+            .cases
+            .iter()
+            .map(|c| json!({"name": c.name, "inputs": c.inputs, "outputs": c.outputs}))
+            .collect();
+        let manifest = json!({"version": 1, "cases": cases});
+        std::fs::write(
+            self.dir.join(MANIFEST),
+            serde_json::to_vec_pretty(&manifest)?,
+        )?;
+        Ok(())
+    }
+}
+
+fn parse_manifest(bytes: &[u8]) -> Result<Vec<Case>, String> {
+    let value: Value = serde_json::from_slice(bytes).map_err(|err| err.to_string())?;
+    let cases = value
+        .get("cases")
+        .and_then(Value::as_array)
+        .ok_or("no \"cases\" array")?;
+    cases
+        .iter()
+        .map(|case| {
+            let count = |key: &str| {
+                case.get(key)
+                    .and_then(Value::as_u64)
+                    .map(|n| n as usize)
+                    .ok_or(format!("a case has no \"{key}\" count"))
+            };
+            Ok(Case {
+                name: case
+                    .get("name")
+                    .and_then(Value::as_str)
+                    .ok_or("a case has no \"name\"")?
+                    .to_string(),
+                inputs: count("inputs")?,
+                outputs: count("outputs")?,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Cpu;
+    use crate::compare::Tolerance;
+
+    /// Doubles its one input, optionally off by `drift`.
+    struct Double {
+        drift: f64,
+    }
+
+    impl Run<Cpu> for Double {
+        fn run(&mut self, inputs: &[DeviceTensor<Cpu>]) -> Result<Vec<DeviceTensor<Cpu>>, Error> {
+            Ok(vec![wrap(
+                inputs[0].f_mul_scalar(2)?.f_add_scalar(self.drift)?,
+            )])
+        }
+
+        fn device(&self) -> tch::Device {
+            tch::Device::Cpu
+        }
+    }
+
+    fn input(values: &[f32]) -> Vec<DeviceTensor<Cpu>> {
+        vec![wrap(Tensor::from_slice(values))]
+    }
+
+    #[test]
+    fn recorded_cases_replay_within_tolerance() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut golden = Golden::open(dir.path()).unwrap();
+        let mut model = Double { drift: 0.0 };
+        golden
+            .record_run(&mut model, "small", &input(&[1.0, 2.0]))
+            .unwrap();
+        golden
+            .record_run(&mut model, "large", &input(&[1e3]))
+            .unwrap();
+        golden
+            .record_run(&mut model, "small", &input(&[3.0]))
+            .unwrap();
+
+        let golden = Golden::open(dir.path()).unwrap();
+        assert_eq!(golden.cases().len(), 2);
+        let tolerance = Tolerance {
+            rtol: 0.0,
+            atol: 1e-3,
+        };
+        golden.assert_replays(&mut Double { drift: 1e-4 }, tolerance);
+        let replayed = golden
+            .replay(&mut Double { drift: 0.1 }, tolerance)
+            .unwrap();
+        assert!(replayed.iter().all(|r| !r.passed()));
+        assert_eq!(replayed[0].name, "small");
+    }
+
+    #[test]
+    fn names_and_manifests_are_checked() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut golden = Golden::open(dir.path()).unwrap();
+        for name in ["", "../escape", ".hidden", "a b"] {
+            assert!(matches!(
+                golden.record::<Cpu>(name, &[], &[]),
+                Err(Error::InvalidInput(_))
+            ));
+        }
+        std::fs::write(dir.path().join(MANIFEST), r#"{"cases": [{"name": "x"}]}"#).unwrap();
+        assert!(matches!(Golden::open(dir.path()), Err(Error::Model(_))));
+    }
+}
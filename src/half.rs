@@ -0,0 +1,116 @@
+//! Half-precision host buffers (feature `half`).
+//!
+//! Models exported in `float16`/`bfloat16` take and return half-precision
+//! tensors; these conversions move `half::f16` / `half::bf16` slices in and
+//! out of them directly, without an intermediate `f32` copy. As with the
+//! other conversions in this crate, reading a buffer requires the tensor's
+//! dtype to match exactly; use [`DeviceTensor::to_f16`] or
+//! [`DeviceTensor::to_bf16`] to downcast `Float`/`Double` outputs first, on
+//! the tensor's own device.
+
+use std::marker::PhantomData;
+
+use ::half::{bf16, f16};
+use tch::kind::Element;
+use tch::{Kind, Tensor};
+
+use crate::{Cpu, Device, DeviceTensor, Error};
+
+fn from_slice<T: Element>(data: &[T], shape: &[i64]) -> Result<DeviceTensor<Cpu>, Error> {
+    Ok(DeviceTensor {
+        tensor: Tensor::f_from_slice(data)?.f_reshape(shape)?,
+        _device: PhantomData,
+    })
+}
+
+fn to_vec<T: Element>(tensor: &Tensor) -> Result<Vec<T>, Error> {
+    let found = tensor.f_kind()?;
+    if found != T::KIND {
+        return Err(Error::TensorKindMismatch {
+            expected: T::KIND,
+            found,
+        });
+    }
+    let numel = tensor.numel();
+    let mut data = vec![T::ZERO; numel];
+    tensor.f_contiguous()?.f_copy_data(&mut data, numel)?;
+    Ok(data)
+}
+
+impl DeviceTensor<Cpu> {
+    /// Copy `f16` values into a new `Half` tensor of the given shape.
+    pub fn from_f16(data: &[f16], shape: &[i64]) -> Result<Self, Error> {
+        from_slice(data, shape)
+    }
+
+    /// Copy `bf16` values into a new `BFloat16` tensor of the given shape.
+    pub fn from_bf16(data: &[bf16], shape: &[i64]) -> Result<Self, Error> {
+        from_slice(data, shape)
+    }
+}
+
+impl<D: Device> DeviceTensor<D> {
+    /// Copy a `Half` tensor's values, in row-major order, to the host.
+    pub fn to_f16_vec(&self) -> Result<Vec<f16>, Error> {
+        to_vec(&self.tensor)
+    }
+
+    /// Copy a `BFloat16` tensor's values, in row-major order, to the host.
+    pub fn to_bf16_vec(&self) -> Result<Vec<bf16>, Error> {
+        to_vec(&self.tensor)
+    }
+
+    /// Downcast a floating-point tensor to `Half` on its own device.
+    pub fn to_f16(&self) -> Result<Self, Error> {
+        self.to_float_kind(Kind::Half)
+    }
+
+    /// Downcast a floating-point tensor to `BFloat16` on its own device.
+    pub fn to_bf16(&self) -> Result<Self, Error> {
+        self.to_float_kind(Kind::BFloat16)
+    }
+
+    fn to_float_kind(&self, kind: Kind) -> Result<Self, Error> {
+        let found = self.tensor.f_kind()?;
+        if !matches!(
+            found,
+            Kind::Half | Kind::BFloat16 | Kind::Float | Kind::Double
+        ) {
+            return Err(Error::TensorKindMismatch {
+                expected: kind,
+                found,
+            });
+        }
+        Ok(Self {
+            tensor: self.tensor.f_to_kind(kind)?,
+            _device: PhantomData,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn f16_round_trips() {
+        let data: Vec<f16> = [1.0f32, -2.5, 0.125]
+            .into_iter()
+            .map(f16::from_f32)
+            .collect();
+        let t = DeviceTensor::<Cpu>::from_f16(&data, &[3]).unwrap();
+        assert_eq!(t.kind(), Kind::Half);
+        assert_eq!(t.to_f16_vec().unwrap(), data);
+    }
+
+    #[test]
+    fn float_output_must_be_downcast_first() {
+        let t = DeviceTensor::<Cpu>::try_new(Tensor::from_slice(&[1.5f32, 2.0])).unwrap();
+        assert!(matches!(
+            t.to_bf16_vec(),
+            Err(Error::TensorKindMismatch { .. })
+        ));
+        let bf = t.to_bf16().unwrap().to_bf16_vec().unwrap();
+        assert_eq!(bf, vec![bf16::from_f32(1.5), bf16::from_f32(2.0)]);
+    }
+}
@@ -0,0 +1,210 @@
+//! Process-wide torch settings, applied once before any model loads.
+//!
+//! Reproducible inference depends on global state that libtorch keeps per
+//! process: the random seed, whether nondeterministic kernels may run,
+//! whether CUDA matmuls and convolutions may use TF32, and the thread
+//! pools. [`init`] sets them in one place:
+//!
+//! ```ignore
+//! aoti_rs::init(InitConfig::reproducible(42).threads(4))?;
+//! let model = AOTIModel::<Cuda>::load("model.pt2")?;
+//! ```
+//!
+//! Deterministic cuBLAS also needs `CUBLAS_WORKSPACE_CONFIG=:4096:8` (or
+//! `:16:8`) in the environment before CUDA starts; `init` doesn't set it,
+//! as changing the environment of a running process isn't thread-safe.
+
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::{Error, ffi};
+
+static APPLIED: OnceLock<InitConfig> = OnceLock::new();
+static MODEL_LOADED: AtomicBool = AtomicBool::new(false);
+
+/// The settings [`init`] applies. Those left unset keep libtorch's
+/// defaults.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InitConfig {
+    seed: Option<u64>,
+    deterministic: Option<bool>,
+    warn_only: bool,
+    tf32: Option<bool>,
+    cudnn_benchmark: Option<bool>,
+    threads: Option<usize>,
+    interop_threads: Option<usize>,
+}
+
+impl InitConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Seed every device's generator, deterministic kernels only, no TF32
+    /// and no cuDNN autotuning: runs repeat bit for bit on the same
+    /// hardware and software.
+    pub fn reproducible(seed: u64) -> Self {
+        Self::new()
+            .seed(seed)
+            .deterministic(true)
+            .tf32(false)
+            .cudnn_benchmark(false)
+    }
+
+    /// Seed the CPU and CUDA generators.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Make operations without a deterministic kernel fail
+    /// (`torch.use_deterministic_algorithms`).
+    pub fn deterministic(mut self, enabled: bool) -> Self {
+        self.deterministic = Some(enabled);
+        self
+    }
+
+    /// With [`deterministic`](Self::deterministic), warn about
+    /// nondeterministic operations instead of failing them.
+    pub fn warn_only(mut self, warn_only: bool) -> Self {
+        self.warn_only = warn_only;
+        self
+    }
+
+    /// Allow TF32 in CUDA matmuls and convolutions: faster on Ampere and
+    /// later, but with a 10-bit mantissa.
+    pub fn tf32(mut self, allowed: bool) -> Self {
+        self.tf32 = Some(allowed);
+        self
+    }
+
+    /// Let cuDNN benchmark convolution algorithms per shape and keep the
+    /// fastest, which may differ between runs.
+    pub fn cudnn_benchmark(mut self, enabled: bool) -> Self {
+        self.cudnn_benchmark = Some(enabled);
+        self
+    }
+
+    /// Threads for intra-op parallelism on the CPU.
+    pub fn threads(mut self, n: usize) -> Self {
+        self.threads = Some(n);
+        self
+    }
+
+    /// Threads for inter-op parallelism on the CPU.
+    pub fn interop_threads(mut self, n: usize) -> Self {
+        self.interop_threads = Some(n);
+        self
+    }
+}
+
+/// Apply `config` to libtorch's global state. Call it once, before
+/// loading any model: later calls succeed only with the same config, and
+/// calls after a model has loaded fail, since the runtime may already
+/// have read the settings.
+pub fn init(config: InitConfig) -> Result<(), Error> {
+    init_once(&APPLIED, &MODEL_LOADED, config)
+}
+
+fn init_once(
+    applied: &OnceLock<InitConfig>,
+    model_loaded: &AtomicBool,
+    config: InitConfig,
+) -> Result<(), Error> {
+    if let Some(applied) = applied.get() {
+        return if *applied == config {
+            Ok(())
+        } else {
+            Err(Error::InvalidInput(format!(
+                "aoti_rs::init already ran with {applied:?}"
+            )))
+        };
+    }
+    if model_loaded.load(Ordering::Acquire) {
+        return Err(Error::InvalidInput(
+            "aoti_rs::init must run before any model loads".into(),
+        ));
+    }
+    for (name, n) in [
+        ("threads", config.threads),
+        ("interop_threads", config.interop_threads),
+    ] {
+        if n.is_some_and(|n| n == 0 || n > i32::MAX as usize) {
+            return Err(Error::InvalidInput(format!(
+                "{name} must be between 1 and {}",
+                i32::MAX
+            )));
+        }
+    }
+    let mut result = Ok(());
+    let applied = applied.get_or_init(|| {
+        result = apply(&config);
+        config.clone()
+    });
+    if *applied != config {
+        // Another thread initialized first.
+        return Err(Error::InvalidInput(format!(
+            "aoti_rs::init already ran with {applied:?}"
+        )));
+    }
+    result
+}
+
+fn apply(config: &InitConfig) -> Result<(), Error> {
+    if let Some(seed) = config.seed {
+        // Seeds every device's default generator, as `torch.manual_seed`.
+        tch::manual_seed(seed as i64);
+    }
+    if let Some(enabled) = config.deterministic {
+        ffi::set_deterministic(enabled, config.warn_only)?;
+    }
+    if let Some(allowed) = config.tf32 {
+        ffi::set_allow_tf32(allowed)?;
+    }
+    if let Some(enabled) = config.cudnn_benchmark {
+        tch::Cuda::cudnn_set_benchmark(enabled);
+    }
+    if let Some(n) = config.threads {
+        tch::set_num_threads(n as i32);
+    }
+    if let Some(n) = config.interop_threads {
+        tch::set_num_interop_threads(n as i32);
+    }
+    Ok(())
+}
+
+/// Record that a model has loaded, after which [`init`] refuses to run.
+pub(crate) fn model_loaded() {
+    MODEL_LOADED.store(true, Ordering::Release);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reproducible_config_sets_every_knob() {
+        let config = InitConfig::reproducible(7).threads(2);
+        assert_eq!(config.seed, Some(7));
+        assert_eq!(config.deterministic, Some(true));
+        assert_eq!(config.tf32, Some(false));
+        assert_eq!(config.cudnn_benchmark, Some(false));
+        assert_eq!(config.threads, Some(2));
+        assert_eq!(config.interop_threads, None);
+    }
+
+    #[test]
+    fn init_applies_once_before_models_load() {
+        let (applied, loaded) = (OnceLock::new(), AtomicBool::new(false));
+        let init = |config| init_once(&applied, &loaded, config);
+        assert!(init(InitConfig::new().threads(0)).is_err());
+        init(InitConfig::new().seed(3)).unwrap();
+        init(InitConfig::new().seed(3)).unwrap();
+        assert!(init(InitConfig::new().seed(4)).is_err());
+
+        let fresh = OnceLock::new();
+        loaded.store(true, Ordering::Release);
+        assert!(init_once(&fresh, &loaded, InitConfig::new().seed(3)).is_err());
+        assert!(fresh.get().is_none());
+    }
+}
@@ -0,0 +1,467 @@
+//! How a run takes its inputs.
+
+use std::sync::Arc;
+
+use tch::Tensor;
+use tch::kind::Element;
+
+use crate::{Device, DeviceTensor, Error};
+
+/// Whether a run borrows its inputs or takes them over, for
+/// [`AOTIModel::run`](crate::AOTIModel::run),
+/// [`AOTIModelPool::run`](crate::AOTIModelPool::run) and
+/// [`Routed::run`](crate::registry::Routed::run).
+///
+/// Inputs passed by reference are borrowed and inputs passed by value are
+/// donated, so the mode is usually implied:
+///
+/// ```ignore
+/// let outputs = model.run(&inputs)?; // InputMode::Borrow
+/// let outputs = model.run(inputs)?; // InputMode::Donate: `inputs` is gone
+/// let outputs = model.run(&image)?; // one input, borrowed
+/// ```
+pub enum InputMode<'a, D: Device> {
+    /// The runtime reads the inputs and leaves them as they were, so the
+    /// caller can use them again, e.g. to retry the run.
+    Borrow(&'a [DeviceTensor<D>]),
+    /// The runtime takes the inputs and may reuse their memory for
+    /// intermediates and outputs, freeing it sooner. The move keeps the
+    /// caller from touching the tensors afterwards; other tensors sharing
+    /// their storage (views, `shallow_clone`s) must not be read after the
+    /// run either, as their contents are unspecified.
+    Donate(Vec<DeviceTensor<D>>),
+}
+
+impl<D: Device> InputMode<'_, D> {
+    pub fn as_slice(&self) -> &[DeviceTensor<D>] {
+        match self {
+            InputMode::Borrow(inputs) => inputs,
+            InputMode::Donate(inputs) => inputs,
+        }
+    }
+
+    pub fn is_donated(&self) -> bool {
+        matches!(self, InputMode::Donate(_))
+    }
+}
+
+impl<'a, D: Device> From<&'a [DeviceTensor<D>]> for InputMode<'a, D> {
+    fn from(inputs: &'a [DeviceTensor<D>]) -> Self {
+        InputMode::Borrow(inputs)
+    }
+}
+
+impl<'a, D: Device> From<&'a Vec<DeviceTensor<D>>> for InputMode<'a, D> {
+    fn from(inputs: &'a Vec<DeviceTensor<D>>) -> Self {
+        InputMode::Borrow(inputs)
+    }
+}
+
+impl<'a, D: Device, const N: usize> From<&'a [DeviceTensor<D>; N]> for InputMode<'a, D> {
+    fn from(inputs: &'a [DeviceTensor<D>; N]) -> Self {
+        InputMode::Borrow(inputs)
+    }
+}
+
+impl<'a, D: Device> From<&'a DeviceTensor<D>> for InputMode<'a, D> {
+    fn from(input: &'a DeviceTensor<D>) -> Self {
+        InputMode::Borrow(std::slice::from_ref(input))
+    }
+}
+
+impl<D: Device> From<DeviceTensor<D>> for InputMode<'_, D> {
+    fn from(input: DeviceTensor<D>) -> Self {
+        InputMode::Donate(vec![input])
+    }
+}
+
+impl<D: Device> From<Vec<DeviceTensor<D>>> for InputMode<'_, D> {
+    fn from(inputs: Vec<DeviceTensor<D>>) -> Self {
+        InputMode::Donate(inputs)
+    }
+}
+
+impl<D: Device, const N: usize> From<[DeviceTensor<D>; N]> for InputMode<'_, D> {
+    fn from(inputs: [DeviceTensor<D>; N]) -> Self {
+        InputMode::Donate(inputs.into())
+    }
+}
+
+/// Anything a run takes as its inputs, for [`AOTIModel::run`](crate::AOTIModel::run),
+/// [`AOTIModelPool::run`](crate::AOTIModelPool::run) and
+/// [`Routed::run`](crate::registry::Routed::run).
+///
+/// Whatever converts into an [`InputMode`] is taken as it is: a slice,
+/// `Vec` or array of tensors, or a single one. A tuple takes one input per
+/// element, each an [`IntoArgument`], and is donated:
+///
+/// ```ignore
+/// let outputs = model.run(&image)?;
+/// let outputs = model.run((latents, text_embeddings, 981i64))?;
+/// ```
+///
+/// Host values,
+/// for scalar control inputs like a diffusion timestep or a sampling
+/// temperature, are uploaded to the model's device as one input:
+///
+/// - an `f32` or `i64` becomes a 0-dim tensor
+/// - a `&[f32]` or `&[i64]` (or a reference to an array or `Vec` of them)
+///   becomes a 1-dim tensor
+/// - [`Shaped`] values become a tensor of the given shape
+///
+/// ```ignore
+/// let outputs = model.run(0.7f32)?;
+/// let outputs = model.run(&[1i64, 5, 9])?;
+/// ```
+///
+/// Optional arguments are passed as a `Vec` or array of
+/// `Option<DeviceTensor<D>>`, or a tuple with `Option` elements, one per
+/// argument of the call spec, which are donated. A `None` stands for an argument exported as `None`, which the
+/// package takes no input for; it is checked against the spec, as is each
+/// tensor. A package exported with a placeholder tensor for an optional
+/// argument, e.g. an empty one, can't be told apart from any other tensor
+/// argument, so needs that placeholder passed.
+///
+/// ```ignore
+/// // forward(x, mask=None, bias=None), exported with a bias but no mask
+/// let outputs = model.run([Some(x), None, Some(bias)])?;
+/// ```
+pub trait IntoInputs<'a, D: Device> {
+    /// Convert into inputs for the model `target` describes.
+    fn into_inputs(self, target: &InputTarget) -> Result<InputMode<'a, D>, Error>;
+}
+
+impl<'a, D: Device, T: Into<InputMode<'a, D>>> IntoInputs<'a, D> for T {
+    fn into_inputs(self, _target: &InputTarget) -> Result<InputMode<'a, D>, Error> {
+        Ok(self.into())
+    }
+}
+
+/// The model a run's inputs are converted for: its device and, if its call
+/// spec could be read, the arguments it takes.
+#[derive(Clone, Debug)]
+pub struct InputTarget {
+    device: tch::Device,
+    /// Flat inputs per argument, positional then keyword: 0 for an
+    /// argument exported as `None`.
+    arguments: Option<Arc<[usize]>>,
+}
+
+impl InputTarget {
+    /// A model on `device` whose call spec is unknown.
+    pub fn new(device: tch::Device) -> Self {
+        Self {
+            device,
+            arguments: None,
+        }
+    }
+
+    pub(crate) fn with_arguments(mut self, arguments: Option<Arc<[usize]>>) -> Self {
+        self.arguments = arguments;
+        self
+    }
+
+    pub fn device(&self) -> tch::Device {
+        self.device
+    }
+
+    /// The tensors of `inputs`, one per argument, after checking them
+    /// against the call spec. Without one, `None`s can't be placed.
+    fn place<T>(&self, inputs: Vec<Option<T>>) -> Result<Vec<T>, Error> {
+        let Some(arguments) = &self.arguments else {
+            if inputs.iter().any(Option::is_none) {
+                return Err(Error::InvalidInput(
+                    "the call spec is unknown, so `None` inputs can't be placed".into(),
+                ));
+            }
+            return Ok(inputs.into_iter().flatten().collect());
+        };
+        if inputs.len() != arguments.len() {
+            return Err(Error::InvalidInput(format!(
+                "{} arguments given, the call spec takes {}",
+                inputs.len(),
+                arguments.len()
+            )));
+        }
+        for (i, (input, &flat)) in inputs.iter().zip(arguments.iter()).enumerate() {
+            let problem = match (input, flat) {
+                (None, 0) | (Some(_), 1) => continue,
+                (Some(_), 0) => "was exported as None, so must be None".to_string(),
+                (None, 1) => "was exported as a tensor, so can't be None".to_string(),
+                (_, n) => format!("takes {n} flat inputs, not an optional tensor"),
+            };
+            return Err(Error::InvalidInput(format!("argument {i} {problem}")));
+        }
+        Ok(inputs.into_iter().flatten().collect())
+    }
+}
+
+impl<'a, D: Device> IntoInputs<'a, D> for Vec<Option<DeviceTensor<D>>> {
+    fn into_inputs(self, target: &InputTarget) -> Result<InputMode<'a, D>, Error> {
+        target.place(self).map(InputMode::Donate)
+    }
+}
+
+impl<'a, D: Device, const N: usize> IntoInputs<'a, D> for [Option<DeviceTensor<D>>; N] {
+    fn into_inputs(self, target: &InputTarget) -> Result<InputMode<'a, D>, Error> {
+        Vec::from(self).into_inputs(target)
+    }
+}
+
+/// Host values uploaded as one input of `shape`, e.g. a `[1, 4]`
+/// conditioning vector:
+///
+/// ```ignore
+/// let outputs = model.run(Shaped::new(&[0.1f32, 0.2, 0.3, 0.4], [1, 4]))?;
+/// ```
+pub struct Shaped<'v, T> {
+    values: &'v [T],
+    shape: Vec<i64>,
+}
+
+impl<'v, T: Element> Shaped<'v, T> {
+    pub fn new(values: &'v [T], shape: impl Into<Vec<i64>>) -> Self {
+        Self {
+            values,
+            shape: shape.into(),
+        }
+    }
+}
+
+impl<D: Device, T: Element> IntoArgument<D> for Shaped<'_, T> {
+    fn into_argument(self, target: &InputTarget) -> Result<Option<DeviceTensor<D>>, Error> {
+        let numel = self.shape.iter().try_fold(1usize, |acc, &d| {
+            usize::try_from(d).ok().and_then(|d| acc.checked_mul(d))
+        });
+        if numel != Some(self.values.len()) {
+            return Err(Error::InvalidInput(format!(
+                "{} values don't fill shape {:?}",
+                self.values.len(),
+                self.shape
+            )));
+        }
+        let tensor = Tensor::f_from_slice(self.values)?.f_reshape(&self.shape)?;
+        upload(tensor, target.device()).map(Some)
+    }
+}
+
+impl<'a, D: Device, T: Element> IntoInputs<'a, D> for Shaped<'_, T> {
+    fn into_inputs(self, target: &InputTarget) -> Result<InputMode<'a, D>, Error> {
+        one_argument(self, target)
+    }
+}
+
+macro_rules! host_inputs {
+    ($($t:ty),*) => {$(
+        impl<D: Device> IntoArgument<D> for $t {
+            fn into_argument(self, target: &InputTarget) -> Result<Option<DeviceTensor<D>>, Error> {
+                upload(Tensor::from(self), target.device()).map(Some)
+            }
+        }
+
+        impl<D: Device> IntoArgument<D> for &[$t] {
+            fn into_argument(self, target: &InputTarget) -> Result<Option<DeviceTensor<D>>, Error> {
+                upload(Tensor::f_from_slice(self)?, target.device()).map(Some)
+            }
+        }
+
+        impl<D: Device> IntoArgument<D> for &Vec<$t> {
+            fn into_argument(self, target: &InputTarget) -> Result<Option<DeviceTensor<D>>, Error> {
+                self.as_slice().into_argument(target)
+            }
+        }
+
+        impl<D: Device, const N: usize> IntoArgument<D> for &[$t; N] {
+            fn into_argument(self, target: &InputTarget) -> Result<Option<DeviceTensor<D>>, Error> {
+                self.as_slice().into_argument(target)
+            }
+        }
+
+        impl<'a, D: Device> IntoInputs<'a, D> for $t {
+            fn into_inputs(self, target: &InputTarget) -> Result<InputMode<'a, D>, Error> {
+                one_argument(self, target)
+            }
+        }
+
+        impl<'a, D: Device> IntoInputs<'a, D> for &[$t] {
+            fn into_inputs(self, target: &InputTarget) -> Result<InputMode<'a, D>, Error> {
+                one_argument(self, target)
+            }
+        }
+
+        impl<'a, D: Device> IntoInputs<'a, D> for &Vec<$t> {
+            fn into_inputs(self, target: &InputTarget) -> Result<InputMode<'a, D>, Error> {
+                one_argument(self, target)
+            }
+        }
+
+        impl<'a, D: Device, const N: usize> IntoInputs<'a, D> for &[$t; N] {
+            fn into_inputs(self, target: &InputTarget) -> Result<InputMode<'a, D>, Error> {
+                one_argument(self, target)
+            }
+        }
+    )*};
+}
+
+host_inputs!(f32, i64);
+
+/// One argument of a tuple of inputs, e.g. `model.run((latents, 0.5f32))`:
+/// a tensor, which is donated, an optional one, placed as in
+/// [`IntoInputs`], or host values, uploaded as there.
+pub trait IntoArgument<D: Device> {
+    /// Convert into the argument's input for the model `target`
+    /// describes, `None` for an argument left out.
+    fn into_argument(self, target: &InputTarget) -> Result<Option<DeviceTensor<D>>, Error>;
+}
+
+impl<D: Device> IntoArgument<D> for DeviceTensor<D> {
+    fn into_argument(self, _target: &InputTarget) -> Result<Option<DeviceTensor<D>>, Error> {
+        Ok(Some(self))
+    }
+}
+
+impl<D: Device> IntoArgument<D> for Option<DeviceTensor<D>> {
+    fn into_argument(self, _target: &InputTarget) -> Result<Option<DeviceTensor<D>>, Error> {
+        Ok(self)
+    }
+}
+
+macro_rules! tuple_inputs {
+    ($($arg:ident),+) => {
+        impl<'a, D: Device, $($arg: IntoArgument<D>),+> IntoInputs<'a, D> for ($($arg,)+) {
+            #[allow(non_snake_case)]
+            fn into_inputs(self, target: &InputTarget) -> Result<InputMode<'a, D>, Error> {
+                let ($($arg,)+) = self;
+                let arguments = vec![$($arg.into_argument(target)?),+];
+                // Only a `None` needs the call spec to place it.
+                if arguments.iter().all(Option::is_some) {
+                    return Ok(InputMode::Donate(arguments.into_iter().flatten().collect()));
+                }
+                target.place(arguments).map(InputMode::Donate)
+            }
+        }
+    };
+}
+
+tuple_inputs!(A);
+tuple_inputs!(A, B);
+tuple_inputs!(A, B, C);
+tuple_inputs!(A, B, C, E);
+tuple_inputs!(A, B, C, E, F);
+tuple_inputs!(A, B, C, E, F, G);
+tuple_inputs!(A, B, C, E, F, G, H);
+tuple_inputs!(A, B, C, E, F, G, H, J);
+
+/// A run on just `argument`.
+fn one_argument<'a, D: Device>(
+    argument: impl IntoArgument<D>,
+    target: &InputTarget,
+) -> Result<InputMode<'a, D>, Error> {
+    let input = argument.into_argument(target)?;
+    Ok(InputMode::Donate(input.into_iter().collect()))
+}
+
+/// `tensor` copied to `device`.
+fn upload<D: Device>(tensor: Tensor, device: tch::Device) -> Result<DeviceTensor<D>, Error> {
+    DeviceTensor::try_new(tensor.f_to_device(device)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use tch::Kind;
+
+    use super::*;
+    use crate::Cpu;
+
+    fn input() -> DeviceTensor<Cpu> {
+        DeviceTensor::try_new(Tensor::zeros([2], (Kind::Float, tch::Device::Cpu))).unwrap()
+    }
+
+    #[test]
+    fn references_borrow_and_values_donate() {
+        let single = input();
+        assert!(!InputMode::from(&single).is_donated());
+        assert_eq!(InputMode::from(single).as_slice().len(), 1);
+
+        let inputs = vec![input(), input()];
+        let borrowed = InputMode::from(&inputs);
+        assert!(!borrowed.is_donated());
+        assert_eq!(borrowed.as_slice().len(), 2);
+        assert!(!InputMode::from(&[input()]).is_donated());
+        assert!(!InputMode::from(&inputs[..1]).is_donated());
+
+        assert!(InputMode::from([input()]).is_donated());
+        let donated = InputMode::from(inputs);
+        assert!(donated.is_donated());
+        assert_eq!(donated.as_slice().len(), 2);
+    }
+
+    #[test]
+    fn host_values_upload_as_one_input() {
+        let cpu = &InputTarget::new(tch::Device::Cpu);
+        let shapes = |inputs: InputMode<'_, Cpu>| -> Vec<Vec<i64>> {
+            inputs.as_slice().iter().map(|t| t.size()).collect()
+        };
+        let timestep = IntoInputs::<Cpu>::into_inputs(0.5f32, cpu).unwrap();
+        assert!(timestep.is_donated());
+        assert_eq!(timestep.as_slice()[0].kind(), Kind::Float);
+        assert_eq!(shapes(timestep), [Vec::<i64>::new()]);
+        let ids = IntoInputs::<Cpu>::into_inputs(&[1i64, 5, 9], cpu).unwrap();
+        assert_eq!(ids.as_slice()[0].kind(), Kind::Int64);
+        assert_eq!(shapes(ids), [[3]]);
+
+        let values = [0.1f32, 0.2, 0.3, 0.4, 0.5, 0.6];
+        let shaped = IntoInputs::<Cpu>::into_inputs(Shaped::new(&values, [2, 3]), cpu).unwrap();
+        assert_eq!(shapes(shaped), [[2, 3]]);
+        for shape in [[4, 2], [-2, -3], [i64::MAX, 2]] {
+            assert!(matches!(
+                IntoInputs::<Cpu>::into_inputs(Shaped::new(&values, shape), cpu),
+                Err(Error::InvalidInput(_))
+            ));
+        }
+        let tuple = (input(), 981i64, Shaped::new(&values, [3, 2]));
+        let tuple = IntoInputs::<Cpu>::into_inputs(tuple, cpu).unwrap();
+        assert!(tuple.is_donated());
+        assert_eq!(shapes(tuple), [vec![2], vec![], vec![3, 2]]);
+
+        let inputs = vec![input()];
+        assert!(
+            !IntoInputs::<Cpu>::into_inputs(&inputs, cpu)
+                .unwrap()
+                .is_donated()
+        );
+    }
+
+    #[test]
+    fn optional_inputs_are_placed_per_the_call_spec() {
+        // forward(x, mask=None, bias), taking a tensor for x and bias.
+        let target = InputTarget::new(tch::Device::Cpu).with_arguments(Some(vec![1, 0, 1].into()));
+        let placed = target.place(vec![Some("x"), None, Some("bias")]).unwrap();
+        assert_eq!(placed, ["x", "bias"]);
+        let mode = IntoInputs::<Cpu>::into_inputs([Some(input()), None, Some(input())], &target);
+        assert_eq!(mode.unwrap().as_slice().len(), 2);
+        let tuple = (input(), None, Some(input()));
+        assert_eq!(
+            IntoInputs::<Cpu>::into_inputs(tuple, &target)
+                .unwrap()
+                .as_slice()
+                .len(),
+            2
+        );
+        let misplaced = (input(), input(), None::<DeviceTensor<Cpu>>);
+        assert!(IntoInputs::<Cpu>::into_inputs(misplaced, &target).is_err());
+
+        let invalid = |inputs: Vec<Option<&str>>, target: &InputTarget| {
+            matches!(target.place(inputs), Err(Error::InvalidInput(_)))
+        };
+        assert!(invalid(
+            vec![Some("x"), Some("mask"), Some("bias")],
+            &target
+        ));
+        assert!(invalid(vec![None, None, Some("bias")], &target));
+        assert!(invalid(vec![Some("x"), None], &target));
+        let unknown = InputTarget::new(tch::Device::Cpu);
+        assert!(invalid(vec![Some("x"), None], &unknown));
+        assert_eq!(unknown.place(vec![Some("x")]).unwrap(), ["x"]);
+    }
+}
@@ -0,0 +1,95 @@
+//! Tracking of the tensors the C++ side hands to Rust (feature
+//! `leak-check`), for debugging the FFI boundary.
+//!
+//! Runs and constant queries return `at::Tensor*`s the runtime allocated
+//! with `new`, which Rust must adopt exactly once: a pointer dropped on an
+//! error path leaks its tensor, and one adopted twice is freed twice. With
+//! this feature every such pointer is recorded as it crosses the boundary
+//! and struck off as it is adopted; adopting an unrecorded pointer, or
+//! receiving one that is still live, panics at once. Leaks show up as
+//! pointers still live after the calls that received them have returned:
+//!
+//! ```ignore
+//! let outputs = model.run(&inputs)?;
+//! aoti_rs::leak::assert_none_outstanding();
+//! ```
+//!
+//! The record is process-wide, so check it when no other thread is in the
+//! middle of a call.
+
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex, MutexGuard, PoisonError};
+
+/// Live pointers, by address, and the call that handed each over.
+static LIVE: LazyLock<Mutex<HashMap<usize, &'static str>>> = LazyLock::new(Default::default);
+
+fn live() -> MutexGuard<'static, HashMap<usize, &'static str>> {
+    LIVE.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Record tensors `origin` handed over.
+pub(crate) fn received<T>(ptrs: impl IntoIterator<Item = *mut T>, origin: &'static str) {
+    let mut live = live();
+    for ptr in ptrs {
+        if let Some(previous) = live.insert(ptr as usize, origin) {
+            panic!("{origin} handed over tensor {ptr:p}, which {previous} already had");
+        }
+    }
+}
+
+/// Strike off a tensor as Rust adopts it.
+pub(crate) fn consumed<T>(ptr: *mut T) {
+    if live().remove(&(ptr as usize)).is_none() {
+        panic!("tensor {ptr:p} was adopted twice, or without being received");
+    }
+}
+
+/// Tensors received from the runtime and not adopted yet.
+pub fn outstanding() -> usize {
+    live().len()
+}
+
+/// Panic if any tensor received from the runtime wasn't adopted, naming
+/// the calls that handed them over.
+pub fn assert_none_outstanding() {
+    let live = live();
+    if !live.is_empty() {
+        let mut origins: Vec<&str> = live.values().copied().collect();
+        origins.sort_unstable();
+        panic!("{} tensors leaked, from {origins:?}", live.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Tests share the record with any model running concurrently, so they
+    // only look at their own pointers.
+    fn is_live<T>(ptr: *mut T) -> bool {
+        live().contains_key(&(ptr as usize))
+    }
+
+    #[test]
+    fn received_tensors_are_adopted_once() {
+        let mut slots = [0u8; 2];
+        let (a, b) = (&mut slots[0] as *mut u8, &mut slots[1] as *mut u8);
+        received([a, b], "runner_run");
+        assert!(is_live(a) && is_live(b));
+        consumed(a);
+        consumed(b);
+        assert!(!is_live(a) && !is_live(b));
+        let twice = std::panic::catch_unwind(|| consumed(a));
+        assert!(twice.is_err());
+    }
+
+    #[test]
+    fn handing_over_a_live_tensor_again_panics() {
+        let mut slot = 0u8;
+        let ptr = &mut slot as *mut u8;
+        received([ptr], "runner_get_constants");
+        let again = std::panic::catch_unwind(|| received([ptr], "runner_run"));
+        assert!(again.is_err());
+        consumed(ptr);
+    }
+}
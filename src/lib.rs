@@ -33,14 +33,151 @@
 //! If the target device of a package is only known at runtime, use
 //! [`AnyAOTIModel`], which inspects the package metadata and dispatches to
 //! the right typed model.
+//!
+//! `use aoti_rs::prelude::*` brings in the common types: models, the pool
+//! and registry, tensors and errors.
+//!
+//! # tch versions
+//!
+//! Tensors cross this crate's API as `tch::Tensor`s, so a downstream crate
+//! must use the same `tch` this one was built with: a second `tch` version
+//! has distinct types and links its own `torch-sys`, which fails late and
+//! obscurely. Use the re-exported [`tch`] instead of depending on it
+//! directly, or pin the same exact version (`=0.24.0`).
+//!
+//! Each supported tch line has a feature, currently only `tch-0-24`.
+//! Enabling it records which line a downstream crate was written for: when
+//! a release of this crate moves to another line, the feature is gone and
+//! the dependency fails to resolve instead of compiling against a `tch`
+//! the rest of the crate graph doesn't use. Independently, the build fails
+//! with both versions named if the C++ bridge finds a different libtorch
+//! than the one `torch-sys` links.
 
 use std::collections::HashMap;
 use std::marker::PhantomData;
+use std::ops::Range;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use tch::Tensor;
 use tempfile::TempDir;
 
+#[cfg(feature = "arrow")]
+pub mod arrow;
+#[cfg(feature = "audio")]
+pub mod audio;
+#[cfg(feature = "burn")]
+pub mod burn;
+#[cfg(feature = "bytemuck")]
+pub mod bytemuck;
+#[cfg(feature = "candle")]
+pub mod candle;
+#[cfg(feature = "capi")]
+pub mod capi;
+pub mod classification;
+pub mod compare;
+#[cfg(feature = "config")]
+mod config;
+mod decrypt;
+pub mod detection;
+pub mod embedding;
+#[cfg(feature = "export")]
+pub mod export;
+#[cfg(feature = "fake")]
+pub mod fake;
+#[cfg(feature = "fuzz")]
+pub mod fuzz;
+pub mod generate;
+pub mod golden;
+#[cfg(feature = "half")]
+pub mod half;
+mod init;
+mod input;
+#[cfg(feature = "leak-check")]
+pub mod leak;
+#[cfg(feature = "ndarray")]
+pub mod ndarray;
+#[cfg(feature = "npy")]
+pub mod npy;
+#[cfg(feature = "otel")]
+mod otel;
+mod outputs;
+pub mod padding;
+#[cfg(feature = "polars")]
+pub mod polars;
+mod pool;
+pub mod prelude;
+mod progress;
+#[cfg(feature = "proptest")]
+pub mod proptest;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod registry;
+#[cfg(feature = "object-store")]
+mod remote;
+pub mod repro;
+mod request;
+pub mod safetensors;
+mod schema;
+#[cfg(any(
+    feature = "flight",
+    feature = "grpc",
+    feature = "http",
+    all(feature = "ipc", unix)
+))]
+pub mod serve;
+pub mod stress;
+mod summary;
+#[cfg(feature = "test-support")]
+pub mod test_support;
+#[cfg(feature = "text")]
+pub mod text;
+pub mod torchscript;
+#[cfg(feature = "tracing")]
+mod trace;
+#[cfg(feature = "uniffi")]
+pub mod uniffi;
+mod validate;
+#[cfg(feature = "signatures")]
+mod verify;
+#[cfg(feature = "vision")]
+pub mod vision;
+
+#[cfg(feature = "uniffi")]
+::uniffi::setup_scaffolding!();
+
+/// The `tch` this crate is built against; see the [crate docs](crate#tch-versions).
+pub use tch;
+
+#[cfg(feature = "config")]
+pub use config::{ModelConfig, ServeConfig};
+pub use decrypt::PackageDecryptor;
+pub use init::{InitConfig, init};
+pub use input::{InputMode, InputTarget, IntoArgument, IntoInputs, Shaped};
+pub use outputs::Outputs;
+pub use pool::{
+    AOTIModelPool, CircuitBreaker, DriftAlert, DriftMonitor, DriftProfile, DriftReason, ErrorClass,
+    Health, LoraAdapter, OutputStats, RateLimit, RateLimiter, RatePermit, RetryPolicy, RunLog,
+};
+#[cfg(feature = "fault-injection")]
+pub use pool::{Fault, FaultInjector};
+pub use progress::{LoadPhase, LoadProgress, Loading};
+pub use request::RequestId;
+pub use schema::{Dim, OutputSchema};
+pub use summary::{
+    CallSpec, ConstantInfo, ConstantStats, DtypeStats, ModelMetadata, ModelSummary, OutputAlias,
+    RunStats,
+};
+#[cfg(feature = "signatures")]
+pub use verify::TrustedKeys;
+
+#[cfg(not(feature = "fake"))]
+use cxx::{Exception as FfiError, UniquePtr};
+#[cfg(feature = "fake")]
+use fake::ffi::{self, FfiError, UniquePtr};
+
+#[cfg(not(feature = "fake"))]
 #[cxx::bridge(namespace = "aoti_rs")]
 mod ffi {
     #[namespace = ""]
@@ -57,6 +194,16 @@ mod ffi {
         ptr: *mut c_void,
     }
 
+    struct NamedTensor {
+        name: String,
+        tensor: OwnedTensor,
+    }
+
+    struct NamedTensorPtr {
+        name: String,
+        tensor: TensorPtr,
+    }
+
     #[namespace = "torch::inductor"]
     unsafe extern "C++" {
         type AOTIModelContainerRunner;
@@ -65,9 +212,10 @@ mod ffi {
     unsafe extern "C++" {
         include!("csrc/aoti.h");
 
+        // Paths are passed as their raw bytes, as they needn't be UTF-8.
         fn runner_new(
-            model_so_path: &str,
-            cubin_dir: &str,
+            model_so_path: &[u8],
+            cubin_dir: &[u8],
             is_cuda: bool,
             device_index: i8,
             num_runners: usize,
@@ -89,6 +237,23 @@ mod ffi {
         fn runner_get_constant_fqns(
             runner: Pin<&mut AOTIModelContainerRunner>,
         ) -> Result<Vec<String>>;
+
+        fn runner_get_constants(
+            runner: Pin<&mut AOTIModelContainerRunner>,
+        ) -> Result<Vec<NamedTensor>>;
+
+        fn runner_update_constant_buffer(
+            runner: Pin<&mut AOTIModelContainerRunner>,
+            constants: &Vec<NamedTensorPtr>,
+            use_inactive: bool,
+            validate_full_update: bool,
+        ) -> Result<()>;
+
+        fn runner_swap_constant_buffer(runner: Pin<&mut AOTIModelContainerRunner>) -> Result<()>;
+
+        fn set_deterministic(enabled: bool, warn_only: bool) -> Result<()>;
+
+        fn set_allow_tf32(allowed: bool) -> Result<()>;
     }
 }
 
@@ -104,15 +269,66 @@ pub enum Error {
     #[error("JSON error: {0}")]
     Json(#[from] serde_json::Error),
 
-    #[error(transparent)]
-    Ffi(#[from] cxx::Exception),
+    /// An exception thrown by the C++ runtime. Displays its message only;
+    /// see [`Error::cpp_backtrace`].
+    #[error("{}", split_cpp_backtrace(.0.what()).0)]
+    Ffi(cxx::Exception),
 
     #[error("invalid path: {0}")]
     InvalidPath(String),
 
+    #[error("invalid input: {0}")]
+    InvalidInput(String),
+
     #[error("model error: {0}")]
     Model(String),
 
+    #[error("model '{model}' is at its limit of {limit} concurrent requests")]
+    Overloaded { model: String, limit: usize },
+
+    #[error("model pool is shut down")]
+    ShutDown,
+
+    #[error("every replica is quarantined after repeated failures")]
+    Quarantined,
+
+    #[error("rate limit exceeded")]
+    RateLimited { retry_after: Option<Duration> },
+
+    #[error("the request's deadline passed before the run started")]
+    DeadlineExceeded,
+
+    /// A build didn't finish within
+    /// [`AOTIModelBuilder::load_timeout`]; `phase` is the last one it
+    /// reported.
+    #[error("loading the package timed out after {timeout:?} ({})", phase_name(.phase))]
+    LoadTimeout {
+        timeout: Duration,
+        phase: Option<LoadPhase>,
+    },
+
+    /// An error raised while a [`RequestId`] was scoped; see
+    /// [`Error::root`].
+    #[error("request {id}: {source}")]
+    Request { id: RequestId, source: Box<Error> },
+
+    #[error("untrusted package {0}")]
+    UntrustedPackage(String),
+
+    #[error("could not decrypt the package: {0}")]
+    Decryption(std::io::Error),
+
+    #[cfg(feature = "export")]
+    #[error("export failed: {0}")]
+    Export(String),
+
+    #[error("SHA-256 of {path} is {found}, but {expected} was expected")]
+    ChecksumMismatch {
+        path: String,
+        expected: String,
+        found: String,
+    },
+
     #[error("model package targets device '{found}' but was loaded as a {expected} model")]
     ModelDeviceMismatch {
         expected: &'static str,
@@ -124,6 +340,111 @@ pub enum Error {
         expected: &'static str,
         found: tch::Device,
     },
+
+    #[error("tensor has dtype {found:?} but {expected:?} was required")]
+    TensorKindMismatch {
+        expected: tch::Kind,
+        found: tch::Kind,
+    },
+
+    /// Outputs that don't match the model's declared
+    /// [`OutputSchema`]; see [`AOTIModelBuilder::output_schema`].
+    #[error("outputs don't match the declared schema: {0}")]
+    OutputSchemaMismatch(String),
+
+    /// [`Outputs`] converted into a tuple of another length.
+    #[error("expected {expected} outputs, but the model returned {found}")]
+    OutputCountMismatch { expected: usize, found: usize },
+
+    #[error("unsupported dtype: {0}")]
+    UnsupportedDtype(String),
+
+    #[error(transparent)]
+    Tch(#[from] tch::TchError),
+
+    #[cfg(feature = "text")]
+    #[error("tokenizer error: {0}")]
+    Tokenizer(tokenizers::Error),
+
+    #[cfg(feature = "ndarray")]
+    #[error(transparent)]
+    Shape(#[from] ::ndarray::ShapeError),
+
+    #[cfg(feature = "arrow")]
+    #[error(transparent)]
+    Arrow(#[from] arrow_schema::ArrowError),
+
+    #[cfg(feature = "candle")]
+    #[error(transparent)]
+    Candle(#[from] candle_core::Error),
+
+    #[cfg(feature = "polars")]
+    #[error(transparent)]
+    Polars(#[from] ::polars::error::PolarsError),
+}
+
+impl From<cxx::Exception> for Error {
+    fn from(err: cxx::Exception) -> Self {
+        Error::Ffi(err)
+    }
+}
+
+fn phase_name(phase: &Option<LoadPhase>) -> &'static str {
+    match phase {
+        None => "before extracting it",
+        Some(LoadPhase::Download) => "while downloading it",
+        Some(LoadPhase::Extract) => "while extracting it",
+        Some(LoadPhase::Load) => "while loading it into the runtime",
+    }
+}
+
+/// Separates a C++ exception's message from the backtrace the shim appends
+/// to libtorch errors (`AOTI_RS_BACKTRACE_MARKER` in `csrc/aoti.h`).
+const CPP_BACKTRACE_MARKER: &str = "\n\nC++ backtrace:\n";
+
+fn split_cpp_backtrace(what: &str) -> (&str, Option<&str>) {
+    match what.split_once(CPP_BACKTRACE_MARKER) {
+        Some((message, backtrace)) => (message, Some(backtrace)),
+        None => (what, None),
+    }
+}
+
+impl Error {
+    /// The C++ backtrace libtorch recorded for an [`Error::Ffi`], if any.
+    pub fn cpp_backtrace(&self) -> Option<&str> {
+        match self.root() {
+            Error::Ffi(err) => split_cpp_backtrace(err.what()).1,
+            _ => None,
+        }
+    }
+
+    /// The error with any [`Error::Request`] wrapper removed.
+    pub fn root(&self) -> &Error {
+        match self {
+            Error::Request { source, .. } => source.root(),
+            err => err,
+        }
+    }
+
+    /// The ID of the request this error was raised for, if one was scoped.
+    pub fn request_id(&self) -> Option<&str> {
+        match self {
+            Error::Request { id, .. } => Some(id.as_str()),
+            _ => None,
+        }
+    }
+
+    /// Wrap in [`Error::Request`] if a request ID is scoped on this thread
+    /// and the error isn't wrapped already.
+    pub(crate) fn in_request(self) -> Error {
+        match RequestId::current() {
+            Some(id) if !matches!(self, Error::Request { .. }) => Error::Request {
+                id,
+                source: Box::new(self),
+            },
+            _ => self,
+        }
+    }
 }
 
 mod sealed {
@@ -136,7 +457,7 @@ mod sealed {
 ///
 /// This trait is sealed: the only implementors are [`Cpu`] and [`Cuda`],
 /// matching the two runner kinds libtorch's AOTI runtime provides.
-pub trait Device: sealed::Sealed + 'static {
+pub trait Device: sealed::Sealed + Send + Sync + 'static {
     /// Device key as it appears in `.pt2` metadata (`AOTI_DEVICE_KEY`).
     const KEY: &'static str;
     /// Whether this device kind is CUDA.
@@ -210,11 +531,20 @@ fn device_kind(key: &str) -> &str {
     key.split(':').next().unwrap_or(key)
 }
 
+/// Render a runtime device the way PyTorch does (`"cpu"`, `"cuda:1"`).
+fn device_string(device: tch::Device) -> String {
+    match device {
+        tch::Device::Cpu => Cpu::KEY.to_string(),
+        tch::Device::Cuda(index) => format!("{}:{index}", Cuda::KEY),
+        other => format!("{other:?}").to_lowercase(),
+    }
+}
+
 /// A `tch::Tensor` whose placement on device kind `D` has been verified.
 ///
-/// This is the only input type accepted by [`AOTIModel::run`] and
-/// [`AOTIModel::boxed_run`], which makes the device of every tensor crossing
-/// the FFI boundary part of its compile-time type. The placement is checked
+/// This is the only input type accepted by [`AOTIModel::run`], which makes
+/// the device of every tensor crossing the FFI boundary part of its
+/// compile-time type. The placement is checked
 /// once, at construction; the wrapper then dereferences to `&tch::Tensor`
 /// for read access. No `&mut Tensor` access is exposed, so the placement
 /// cannot be invalidated after construction.
@@ -336,6 +666,24 @@ fn tensors_to_ptrs<D: Device>(tensors: &[DeviceTensor<D>]) -> Vec<ffi::TensorPtr
         .collect()
 }
 
+/// Take ownership of a tensor the C++ side handed over.
+///
+/// # Safety
+/// `owned.ptr` must be a heap-allocated `at::Tensor*` created with
+/// `new at::Tensor(...)`, not adopted before.
+unsafe fn adopt(owned: ffi::OwnedTensor) -> Tensor {
+    #[cfg(feature = "leak-check")]
+    leak::consumed(owned.ptr);
+    unsafe { Tensor::from_ptr(owned.ptr as *mut _) }
+}
+
+/// Record the tensors a call handed over, with feature `leak-check`.
+#[cfg_attr(not(feature = "leak-check"), allow(unused_variables))]
+fn received<'a>(owned: impl IntoIterator<Item = &'a ffi::OwnedTensor>, origin: &'static str) {
+    #[cfg(feature = "leak-check")]
+    leak::received(owned.into_iter().map(|ot| ot.ptr), origin);
+}
+
 /// Convert `OwnedTensor` values from C++ into device-typed `tch::Tensor`s.
 ///
 /// # Safety
@@ -349,7 +697,7 @@ fn owned_to_tensors<D: Device>(owned: Vec<ffi::OwnedTensor>) -> Vec<DeviceTensor
     owned
         .into_iter()
         .map(|ot| {
-            let tensor = unsafe { Tensor::from_ptr(ot.ptr as *mut _) };
+            let tensor = unsafe { adopt(ot) };
             debug_assert!(
                 D::matches(tensor.device()),
                 "AOTI returned an output on {:?} from a {} model",
@@ -364,14 +712,67 @@ fn owned_to_tensors<D: Device>(owned: Vec<ffi::OwnedTensor>) -> Vec<DeviceTensor
         .collect()
 }
 
+/// Fail unless `path` is a readable file, naming it in the error: I/O
+/// errors keep their kind (e.g. `NotFound`, `PermissionDenied`), and a
+/// directory is an [`Error::InvalidPath`].
+pub(crate) fn check_package(path: &Path) -> Result<(), Error> {
+    let context = |e: std::io::Error| {
+        Error::Io(std::io::Error::new(
+            e.kind(),
+            format!("{}: {e}", path.display()),
+        ))
+    };
+    if std::fs::metadata(path).map_err(context)?.is_dir() {
+        return Err(Error::InvalidPath(format!(
+            "{} is a directory, not a .pt2 package",
+            path.display()
+        )));
+    }
+    std::fs::File::open(path).map_err(context)?;
+    Ok(())
+}
+
+/// `path` as the bytes the C++ side opens it by: its raw bytes on Unix,
+/// where paths needn't be UTF-8, and UTF-8 elsewhere.
+fn path_bytes(path: &Path) -> Result<&[u8], Error> {
+    #[cfg(unix)]
+    let bytes = std::os::unix::ffi::OsStrExt::as_bytes(path.as_os_str());
+    #[cfg(not(unix))]
+    let bytes = path
+        .to_str()
+        .ok_or_else(|| Error::InvalidPath(format!("{} is not valid UTF-8", path.display())))?
+        .as_bytes();
+    Ok(bytes)
+}
+
 /// Extract every non-directory entry of a `.pt2` archive into a fresh temp dir,
 /// using a Zip64-aware reader.  This bypasses libtorch's miniz-based extractor,
 /// which fails on archives whose internal `wrapper.so` pushes the central
 /// directory past the 32-bit Zip offset boundary.
-fn extract_pt2(pt2_path: &str) -> Result<TempDir, Error> {
-    let file = std::fs::File::open(pt2_path)?;
-    let mut archive = zip::ZipArchive::new(file)?;
+///
+/// Reports [`LoadPhase::Extract`] progress in uncompressed bytes after each
+/// entry. Reads from any seekable reader: the package file, its verified
+/// copy or a decrypted one.
+fn extract_archive(
+    archive: impl std::io::Read + std::io::Seek,
+    report: progress::Report<'_>,
+) -> Result<TempDir, Error> {
+    let mut archive = zip::ZipArchive::new(archive)?;
     let dir = tempfile::tempdir()?;
+    let mut total = 0;
+    for i in 0..archive.len() {
+        total += archive.by_index_raw(i)?.size();
+    }
+    let mut done = 0;
+    let mut extracted = |bytes| {
+        done += bytes;
+        report(LoadProgress {
+            phase: LoadPhase::Extract,
+            bytes_done: done,
+            bytes_total: Some(total),
+        });
+    };
+    extracted(0);
     for i in 0..archive.len() {
         let mut entry = archive.by_index(i)?;
         let name = entry.name().to_string();
@@ -387,7 +788,7 @@ fn extract_pt2(pt2_path: &str) -> Result<TempDir, Error> {
             std::fs::create_dir_all(parent)?;
         }
         let mut out = std::fs::File::create(&outpath)?;
-        std::io::copy(&mut entry, &mut out)?;
+        extracted(std::io::copy(&mut entry, &mut out)?);
     }
     Ok(dir)
 }
@@ -491,7 +892,7 @@ fn read_metadata_from_dir(dir: &Path, model_name: &str) -> Result<HashMap<String
 /// Read metadata directly from a `.pt2` archive without fully extracting it,
 /// by streaming a single `*_metadata.json` entry.
 fn read_metadata_from_zip(
-    pt2_path: &str,
+    pt2_path: &Path,
     model_name: &str,
 ) -> Result<HashMap<String, String>, Error> {
     let file = std::fs::File::open(pt2_path)?;
@@ -528,15 +929,32 @@ fn read_metadata_from_zip(
     parse_metadata_json(&buf)
 }
 
+/// Names of the models in a `.pt2` package (its top-level directories with
+/// compiled AOTInductor artifacts), sorted. Reads only the archive's index.
+pub fn package_models(model_package_path: impl AsRef<Path>) -> Result<Vec<String>, Error> {
+    let archive = zip::ZipArchive::new(std::fs::File::open(model_package_path)?)?;
+    let mut models: Vec<String> = archive
+        .file_names()
+        .filter_map(|name| {
+            let (model, rest) = name.split_once('/')?;
+            rest.starts_with("data/aotinductor/")
+                .then(|| model.to_string())
+        })
+        .collect();
+    models.sort();
+    models.dedup();
+    Ok(models)
+}
+
 /// Load metadata from a model package without fully loading the model.
 ///
 /// Streams just the metadata JSON entry from the zip, so it's cheap even on
 /// multi-GB packages.
 pub fn load_metadata_from_package(
-    model_package_path: &str,
+    model_package_path: impl AsRef<Path>,
     model_name: &str,
 ) -> Result<HashMap<String, String>, Error> {
-    read_metadata_from_zip(model_package_path, model_name)
+    read_metadata_from_zip(model_package_path.as_ref(), model_name)
 }
 
 /// Builder for configuring and creating an [`AOTIModel`].
@@ -546,27 +964,61 @@ pub fn load_metadata_from_package(
 /// `build()` for the CUDA variant only exists when the crate was built with
 /// CUDA support (`cfg(aoti_cuda)`).
 pub struct AOTIModelBuilder<D: Device> {
-    path: String,
+    path: PathBuf,
     model_name: String,
     run_single_threaded: bool,
     num_runners: usize,
     device_index: i8,
+    detach_grad_inputs: bool,
+    check_output_aliasing: bool,
+    output_schema: Option<OutputSchema>,
+    load_timeout: Option<Duration>,
+    #[cfg(feature = "object-store")]
+    cache_dir: Option<PathBuf>,
+    #[cfg(feature = "object-store")]
+    sha256: Option<String>,
+    decryptor: Option<std::sync::Arc<dyn PackageDecryptor>>,
+    #[cfg(feature = "signatures")]
+    trusted_keys: Option<std::sync::Arc<TrustedKeys>>,
+    #[cfg(feature = "signatures")]
+    signature: Option<PathBuf>,
     _device: PhantomData<D>,
 }
 
 impl<D: Device> AOTIModelBuilder<D> {
     /// Create a builder for the given `.pt2` model package path.
-    pub fn new(model_package_path: impl Into<String>) -> Self {
+    pub fn new(model_package_path: impl AsRef<Path>) -> Self {
         Self {
-            path: model_package_path.into(),
+            path: model_package_path.as_ref().to_path_buf(),
             model_name: "model".to_string(),
             run_single_threaded: false,
             num_runners: 1,
             device_index: -1,
+            detach_grad_inputs: false,
+            check_output_aliasing: cfg!(debug_assertions),
+            output_schema: None,
+            load_timeout: None,
+            #[cfg(feature = "object-store")]
+            cache_dir: None,
+            #[cfg(feature = "object-store")]
+            sha256: None,
+            decryptor: None,
+            #[cfg(feature = "signatures")]
+            trusted_keys: None,
+            #[cfg(feature = "signatures")]
+            signature: None,
             _device: PhantomData,
         }
     }
 
+    /// A builder configured from the TOML file at `source` (or in the
+    /// environment variable `NAME`, for `env:NAME`), with `AOTI_RS_*`
+    /// environment overrides; see [`ModelConfig`].
+    #[cfg(feature = "config")]
+    pub fn from_config(source: &str) -> Result<Self, Error> {
+        ModelConfig::load(source)?.builder()
+    }
+
     /// Set the model name within the package (default: `"model"`).
     pub fn model_name(mut self, name: impl Into<String>) -> Self {
         self.model_name = name.into();
@@ -586,10 +1038,197 @@ impl<D: Device> AOTIModelBuilder<D> {
         self
     }
 
+    /// Detach inputs that require grad before running, instead of failing
+    /// the run with [`Error::InvalidInput`] (default: `false`). The
+    /// detached tensors share the inputs' storage; gradients never flow
+    /// through the model either way.
+    pub fn detach_grad_inputs(mut self, detach: bool) -> Self {
+        self.detach_grad_inputs = detach;
+        self
+    }
+
+    /// After each run, check whether any output shares memory with an
+    /// input (default: on in debug builds). The runtime may return an
+    /// input or a view of one as an output, and may write outputs into
+    /// [donated](InputMode::Donate) inputs' buffers, so a caller still
+    /// holding such an input (or a shallow clone of it) would change the
+    /// output by mutating it. Aliases are reported by
+    /// [`AOTIModel::output_aliases`] and counted in
+    /// [`RunStats::aliased_runs`].
+    pub fn check_output_aliasing(mut self, check: bool) -> Self {
+        self.check_output_aliasing = check;
+        self
+    }
+
+    /// Check every run's outputs against `schema`, failing the run with
+    /// [`Error::OutputSchemaMismatch`] if they differ, e.g. after a
+    /// re-export changed an output's dtype. The runtime call itself
+    /// succeeded, so the run still counts in [`RunStats::runs`].
+    pub fn output_schema(mut self, schema: OutputSchema) -> Self {
+        self.output_schema = Some(schema);
+        self
+    }
+
+    /// Give up on `build` and `build_with_progress` after `timeout`,
+    /// failing with [`Error::LoadTimeout`] rather than hanging, e.g. on a
+    /// broken driver during CUDA context creation. The load runs on a
+    /// watchdog thread that can't be cancelled: a timed-out load carries on
+    /// in the background, and its model is dropped if it ever finishes.
+    /// For [`build_async`](AOTIModelBuilder::build_async), use
+    /// [`Loading::wait_timeout`] instead.
+    pub fn load_timeout(mut self, timeout: Duration) -> Self {
+        self.load_timeout = Some(timeout);
+        self
+    }
+
+    /// Decrypt the package with `decryptor` before extracting it. The
+    /// plaintext archive goes to a temp file readable only by this user and
+    /// is deleted once extracted; the extracted model files stay in the
+    /// model's private temp directory while it is loaded, as the runtime
+    /// maps them from disk.
+    pub fn with_decryptor(mut self, decryptor: impl PackageDecryptor + 'static) -> Self {
+        self.decryptor = Some(std::sync::Arc::new(decryptor));
+        self
+    }
+
+    /// Refuse to load the package unless its detached signature was made
+    /// by one of `keys`, failing with [`Error::UntrustedPackage`]; see
+    /// the `signatures` feature docs for the format.
+    #[cfg(feature = "signatures")]
+    pub fn trusted_keys(mut self, keys: TrustedKeys) -> Self {
+        self.trusted_keys = Some(std::sync::Arc::new(keys));
+        self
+    }
+
+    /// Read the signature from `path` instead of `<package>.sig`.
+    #[cfg(feature = "signatures")]
+    pub fn signature_file(mut self, path: impl AsRef<Path>) -> Self {
+        self.signature = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Download remote packages into `dir` instead of the default cache.
+    #[cfg(feature = "object-store")]
+    pub fn cache_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.cache_dir = Some(dir.into());
+        self
+    }
+
+    /// Require the package's SHA-256 to be `hex`, failing with
+    /// [`Error::ChecksumMismatch`] otherwise. Remote packages are checked
+    /// as they download, local ones before loading.
+    #[cfg(feature = "object-store")]
+    pub fn sha256(mut self, hex: impl Into<String>) -> Self {
+        self.sha256 = Some(hex.into());
+        self
+    }
+
+    /// [`AOTIModelBuilder::build_inner`], on a watchdog thread if there is
+    /// a load timeout.
+    fn build_watched(self, report: progress::Report<'_>) -> Result<AOTIModel<D>, Error> {
+        match self.load_timeout {
+            None => self.build_inner(report),
+            Some(timeout) => Loading::spawn(move |r| self.build_inner(r)).wait_for(timeout, report),
+        }
+    }
+
+    fn build_inner(self, report: progress::Report<'_>) -> Result<AOTIModel<D>, Error> {
+        #[cfg(feature = "otel")]
+        let span = otel::span(
+            "aoti.load",
+            vec![
+                opentelemetry::KeyValue::new("aoti.model.path", self.path.display().to_string()),
+                opentelemetry::KeyValue::new("aoti.model.name", self.model_name.clone()),
+                opentelemetry::KeyValue::new("aoti.device", D::KEY),
+            ],
+        );
+        #[cfg(feature = "tracing")]
+        let traced = tracing::debug_span!(
+            "aoti_ffi",
+            op = "load",
+            model = %self.model_name,
+            path = %self.path.display(),
+            device = D::KEY,
+            duration_us = tracing::field::Empty,
+        );
+        #[cfg(feature = "tracing")]
+        let (_entered, started) = (traced.enter(), Instant::now());
+        let result = self.load(report);
+        #[cfg(feature = "otel")]
+        otel::finish(span, &result);
+        #[cfg(feature = "tracing")]
+        trace::finish(&traced, started.elapsed(), &result);
+        result
+    }
+
+    /// Reject settings the runtime would fail on with an opaque exception,
+    /// or abort on: the container asserts on its runner count and device
+    /// index rather than throwing.
+    fn validate(&self) -> Result<(), Error> {
+        if self.num_runners == 0 {
+            return Err(Error::InvalidInput("num_runners must be at least 1".into()));
+        }
+        if D::IS_CUDA {
+            let visible = tch::Cuda::device_count();
+            if visible == 0 {
+                return Err(Error::InvalidInput(
+                    "no CUDA device is visible to load a CUDA model on".into(),
+                ));
+            }
+            if self.device_index < -1 || i64::from(self.device_index) >= visible {
+                return Err(Error::InvalidInput(format!(
+                    "CUDA device {} doesn't exist; {visible} are visible (-1 picks the current one)",
+                    self.device_index,
+                )));
+            }
+        }
+        Ok(())
+    }
+
     /// Extract the package, validate its device metadata against `D`, and
     /// construct the runner.
-    fn build_inner(self) -> Result<AOTIModel<D>, Error> {
-        let temp_dir = extract_pt2(&self.path)?;
+    fn load(self, report: progress::Report<'_>) -> Result<AOTIModel<D>, Error> {
+        self.validate()?;
+        #[cfg(feature = "object-store")]
+        let local = remote::resolve(
+            &self.path,
+            self.cache_dir.as_deref(),
+            self.sha256.as_deref(),
+            report,
+        )?;
+        #[cfg(not(feature = "object-store"))]
+        let local = self.path.clone();
+        check_package(&local)?;
+        // With trusted keys, the package is read once, into the private
+        // copy that was verified, so swapping the file after the check
+        // can't change what is loaded.
+        #[cfg(feature = "signatures")]
+        let verified = if let Some(keys) = &self.trusted_keys {
+            let signature = self.signature.clone().unwrap_or_else(|| {
+                let mut signature = self.path.clone().into_os_string();
+                signature.push(".sig");
+                signature.into()
+            });
+            // A remote package's signature is fetched the same way.
+            #[cfg(feature = "object-store")]
+            let signature =
+                remote::resolve(&signature, self.cache_dir.as_deref(), None, &mut |_| {})?;
+            Some(verify::verify_package(&local, &signature, keys)?)
+        } else {
+            None
+        };
+        #[cfg(not(feature = "signatures"))]
+        let verified = None;
+        let package = match verified {
+            Some(package) => package,
+            None => std::fs::File::open(&local)?,
+        };
+        let temp_dir = match &self.decryptor {
+            Some(decryptor) => {
+                extract_archive(decrypt::decrypt_to_temp(package, &**decryptor)?, report)?
+            }
+            None => extract_archive(package, report)?,
+        };
         let so_path = find_wrapper_so(temp_dir.path(), &self.model_name)?;
         let metadata = read_metadata_from_dir(temp_dir.path(), &self.model_name)?;
 
@@ -606,25 +1245,64 @@ impl<D: Device> AOTIModelBuilder<D> {
 
         let cubin_dir = so_path
             .parent()
-            .ok_or_else(|| Error::InvalidPath(format!("{} has no parent", so_path.display())))?
-            .to_string_lossy()
-            .into_owned();
-        let so_path_str = so_path.to_str().ok_or_else(|| {
-            Error::InvalidPath(format!("{} is not valid UTF-8", so_path.display()))
-        })?;
-
-        let inner = ffi::runner_new(
-            so_path_str,
-            &cubin_dir,
+            .ok_or_else(|| Error::InvalidPath(format!("{} has no parent", so_path.display())))?;
+
+        let so_size = std::fs::metadata(&so_path)?.len();
+        let loading = |bytes_done| LoadProgress {
+            phase: LoadPhase::Load,
+            bytes_done,
+            bytes_total: Some(so_size),
+        };
+        report(loading(0));
+        let mut inner = ffi::runner_new(
+            path_bytes(&so_path)?,
+            path_bytes(cubin_dir)?,
             D::IS_CUDA,
             self.device_index,
             self.num_runners,
             self.run_single_threaded,
         )?;
+        report(loading(so_size));
+        init::model_loaded();
+        // Packages whose call spec can't be read or parsed are still
+        // usable; their input count just isn't checked, and their outputs
+        // aren't named.
+        let call_spec = ffi::runner_get_call_spec(inner.pin_mut()).ok();
+        let num_inputs = call_spec
+            .as_ref()
+            .and_then(|spec| spec.first().map(|s| validate::input_count(s)))
+            .and_then(Result::ok);
+        let output_names = call_spec
+            .as_ref()
+            .and_then(|spec| outputs::output_names(spec.get(1)?))
+            .map(Arc::from);
+        let arguments = call_spec
+            .as_ref()
+            .and_then(|spec| validate::argument_inputs(spec.first()?))
+            .map(Arc::from);
+
+        // The runner treats a negative index as "the current device", which
+        // is device 0 unless the process changed it.
+        let device = if D::IS_CUDA {
+            tch::Device::Cuda(self.device_index.max(0) as usize)
+        } else {
+            tch::Device::Cpu
+        };
 
         Ok(AOTIModel {
             inner,
             metadata,
+            device,
+            input_target: InputTarget::new(device).with_arguments(arguments),
+            path: self.path,
+            model_name: self.model_name,
+            stats: RunStats::default(),
+            num_inputs,
+            output_names,
+            detach_grad_inputs: self.detach_grad_inputs,
+            check_output_aliasing: self.check_output_aliasing,
+            output_aliases: Vec::new(),
+            output_schema: self.output_schema,
             _temp_dir: temp_dir,
             _device: PhantomData,
         })
@@ -634,7 +1312,20 @@ impl<D: Device> AOTIModelBuilder<D> {
 impl AOTIModelBuilder<Cpu> {
     /// Build the model, extracting the package and constructing the CPU runner.
     pub fn build(self) -> Result<AOTIModel<Cpu>, Error> {
-        self.build_inner()
+        self.build_watched(&mut |_| {})
+    }
+
+    /// [`build`](Self::build), calling `progress` as each phase advances.
+    pub fn build_with_progress(
+        self,
+        mut progress: impl FnMut(LoadProgress) + Send,
+    ) -> Result<AOTIModel<Cpu>, Error> {
+        self.build_watched(&mut progress)
+    }
+
+    /// [`build`](Self::build) on a background thread.
+    pub fn build_async(self) -> Loading<Cpu> {
+        Loading::spawn(move |report| self.build_inner(report))
     }
 }
 
@@ -648,7 +1339,20 @@ impl AOTIModelBuilder<Cuda> {
 
     /// Build the model, extracting the package and constructing the CUDA runner.
     pub fn build(self) -> Result<AOTIModel<Cuda>, Error> {
-        self.build_inner()
+        self.build_watched(&mut |_| {})
+    }
+
+    /// [`build`](Self::build), calling `progress` as each phase advances.
+    pub fn build_with_progress(
+        self,
+        mut progress: impl FnMut(LoadProgress) + Send,
+    ) -> Result<AOTIModel<Cuda>, Error> {
+        self.build_watched(&mut progress)
+    }
+
+    /// [`build`](Self::build) on a background thread.
+    pub fn build_async(self) -> Loading<Cuda> {
+        Loading::spawn(move |report| self.build_inner(report))
     }
 }
 
@@ -670,8 +1374,24 @@ impl AOTIModelBuilder<Cuda> {
 /// let outputs = model.run(&[input]).unwrap();
 /// ```
 pub struct AOTIModel<D: Device> {
-    inner: cxx::UniquePtr<ffi::AOTIModelContainerRunner>,
+    inner: UniquePtr<ffi::AOTIModelContainerRunner>,
     metadata: HashMap<String, String>,
+    device: tch::Device,
+    path: PathBuf,
+    model_name: String,
+    stats: RunStats,
+    /// Flat inputs per the call spec, checked before each run.
+    num_inputs: Option<usize>,
+    /// What inputs passed as [`IntoInputs`] are converted for.
+    input_target: InputTarget,
+    /// Output names per the call spec, given to each run's [`Outputs`].
+    output_names: Option<Arc<[String]>>,
+    /// Detach inputs that require grad rather than reject them.
+    detach_grad_inputs: bool,
+    check_output_aliasing: bool,
+    /// Outputs of the last checked run that alias an input.
+    output_aliases: Vec<OutputAlias>,
+    output_schema: Option<OutputSchema>,
     // The runner mmaps `wrapper.so` and reads `.cubin` kernel files lazily
     // during inference, so the extracted directory must outlive `inner`.
     _temp_dir: TempDir,
@@ -685,53 +1405,253 @@ unsafe impl<D: Device> Send for AOTIModel<D> {}
 
 impl AOTIModel<Cpu> {
     /// Load a `.pt2` model package targeting the CPU with default settings.
-    pub fn load(model_package_path: impl Into<String>) -> Result<Self, Error> {
+    pub fn load(model_package_path: impl AsRef<Path>) -> Result<Self, Error> {
         AOTIModelBuilder::<Cpu>::new(model_package_path).build()
     }
 }
 
+impl TryFrom<&Path> for AOTIModel<Cpu> {
+    type Error = Error;
+
+    /// [`AOTIModel::load`].
+    fn try_from(model_package_path: &Path) -> Result<Self, Error> {
+        Self::load(model_package_path)
+    }
+}
+
 #[cfg(aoti_cuda)]
 impl AOTIModel<Cuda> {
     /// Load a `.pt2` model package targeting CUDA with default settings.
-    pub fn load(model_package_path: impl Into<String>) -> Result<Self, Error> {
+    pub fn load(model_package_path: impl AsRef<Path>) -> Result<Self, Error> {
         AOTIModelBuilder::<Cuda>::new(model_package_path).build()
     }
 }
 
+#[cfg(aoti_cuda)]
+impl TryFrom<&Path> for AOTIModel<Cuda> {
+    type Error = Error;
+
+    /// [`AOTIModel::load`].
+    fn try_from(model_package_path: &Path) -> Result<Self, Error> {
+        Self::load(model_package_path)
+    }
+}
+
 impl<D: Device> AOTIModel<D> {
     /// Create a builder for more control over loading options.
-    pub fn builder(model_package_path: impl Into<String>) -> AOTIModelBuilder<D> {
+    pub fn builder(model_package_path: impl AsRef<Path>) -> AOTIModelBuilder<D> {
         AOTIModelBuilder::new(model_package_path)
     }
 
+    /// The device this model runs on.
+    pub fn device(&self) -> tch::Device {
+        self.device
+    }
+
+    /// Copy (if necessary) `tensor` onto this model's device, returning an
+    /// input ready for [`AOTIModel::run`].
+    pub fn upload(&self, tensor: &Tensor) -> DeviceTensor<D> {
+        DeviceTensor {
+            tensor: tensor.to_device(self.device),
+            _device: PhantomData,
+        }
+    }
+
     /// Run inference on the given input tensors.
     ///
     /// Device placement is enforced at compile time by [`DeviceTensor<D>`];
-    /// shapes and dtypes must match the model export and are checked at
-    /// runtime by the AOTI runtime. Outputs are returned on the model's
-    /// device, carrying the same type-level tag.
-    pub fn run(&mut self, inputs: &[DeviceTensor<D>]) -> Result<Vec<DeviceTensor<D>>, Error> {
-        let ptrs = tensors_to_ptrs(inputs);
-        let owned = ffi::runner_run(self.inner.pin_mut(), &ptrs)?;
-        Ok(owned_to_tensors(owned))
+    /// the input count, and that no input is undefined, on another GPU,
+    /// overlaps itself in memory or requires grad (see
+    /// [`AOTIModelBuilder::detach_grad_inputs`]), are checked before the
+    /// call; shapes and dtypes must match the model export and are checked
+    /// at runtime by the AOTI runtime. Outputs are returned on the model's
+    /// device, carrying the same type-level tag, and can be looked up by
+    /// name if the package returns a dict (see [`Outputs`]).
+    ///
+    /// Inputs passed by reference are borrowed; inputs passed by value (a
+    /// `Vec` or array) are donated to the runtime, which may then reuse
+    /// their storage. See [`InputMode`]. Scalars and slices of host values
+    /// are uploaded as one input, and optional arguments can be passed as
+    /// `Option`s; see [`IntoInputs`].
+    pub fn run<'a>(&mut self, inputs: impl IntoInputs<'a, D>) -> Result<Outputs<D>, Error> {
+        let outputs = match inputs.into_inputs(&self.input_target)? {
+            InputMode::Borrow(inputs) => self.run_borrowed(inputs),
+            InputMode::Donate(inputs) => self.run_donated(inputs),
+        }?;
+        Ok(Outputs::new(outputs, self.output_names.clone()))
     }
 
     /// Run inference, transferring ownership of the input tensors to the
     /// runtime so it can reuse their storage for in-place optimization.
-    ///
-    /// Taking the inputs by value is what makes the optimization possible:
-    /// the runtime may only steal a tensor's buffer when nothing else
-    /// references it, which the type system guarantees here.
-    pub fn boxed_run(
-        &mut self,
-        inputs: Vec<DeviceTensor<D>>,
-    ) -> Result<Vec<DeviceTensor<D>>, Error> {
+    #[deprecated(note = "use `run`, which donates inputs passed by value")]
+    pub fn boxed_run(&mut self, inputs: Vec<DeviceTensor<D>>) -> Result<Outputs<D>, Error> {
+        self.run(inputs)
+    }
+
+    fn run_borrowed(&mut self, inputs: &[DeviceTensor<D>]) -> Result<Vec<DeviceTensor<D>>, Error> {
+        validate::check_inputs(inputs.iter().map(|t| &t.tensor), self.num_inputs)?;
+        validate::check_devices(inputs.iter().map(|t| &t.tensor), self.device)?;
+        let detached = self.detach_grad(inputs)?;
+        let inputs = detached.as_deref().unwrap_or(inputs);
+        #[cfg(feature = "otel")]
+        let span = self.run_span(inputs.len());
+        #[cfg(feature = "tracing")]
+        let traced = self.traced("run", inputs);
+        #[cfg(feature = "tracing")]
+        let _entered = traced.enter();
+        let extents = self.input_extents(inputs);
+        let ptrs = tensors_to_ptrs(inputs);
+        let start = Instant::now();
+        let owned = ffi::runner_run(self.inner.pin_mut(), &ptrs);
+        if let Ok(owned) = &owned {
+            received(owned, "runner_run");
+        }
+        let elapsed = start.elapsed();
+        self.stats.record(elapsed, owned.is_ok());
+        let result = owned.map(owned_to_tensors).map_err(Error::from);
+        if let (Some(extents), Ok(outputs)) = (extents, &result) {
+            self.record_aliases(&extents, outputs);
+        }
+        let result = result.and_then(|outputs| self.check_output_schema(outputs));
+        #[cfg(feature = "otel")]
+        otel::finish(span, &result);
+        #[cfg(feature = "tracing")]
+        trace::finish_run(&traced, elapsed, &result);
+        result
+    }
+
+    /// [`InputMode::Donate`]: taking the inputs by value is what lets the
+    /// runtime reuse their storage, since it may only steal a tensor's
+    /// buffer when nothing else references it.
+    fn run_donated(&mut self, inputs: Vec<DeviceTensor<D>>) -> Result<Vec<DeviceTensor<D>>, Error> {
+        validate::check_inputs(inputs.iter().map(|t| &t.tensor), self.num_inputs)?;
+        validate::check_devices(inputs.iter().map(|t| &t.tensor), self.device)?;
+        let inputs = self.detach_grad(&inputs)?.unwrap_or(inputs);
+        #[cfg(feature = "otel")]
+        let span = self.run_span(inputs.len());
+        #[cfg(feature = "tracing")]
+        let traced = self.traced("boxed_run", &inputs);
+        #[cfg(feature = "tracing")]
+        let _entered = traced.enter();
+        // Taken before the runtime moves out of the inputs.
+        let extents = self.input_extents(&inputs);
         let mut ptrs = tensors_to_ptrs(&inputs);
-        let owned = ffi::runner_boxed_run(self.inner.pin_mut(), &mut ptrs)?;
+        let start = Instant::now();
+        let owned = ffi::runner_boxed_run(self.inner.pin_mut(), &mut ptrs);
+        if let Ok(owned) = &owned {
+            received(owned, "runner_boxed_run");
+        }
+        let elapsed = start.elapsed();
+        self.stats.record(elapsed, owned.is_ok());
         // The C++ side moved out of the input tensors; `inputs` now holds
         // empty shells that must stay alive until the call returns.
         drop(inputs);
-        Ok(owned_to_tensors(owned))
+        let result = owned.map(owned_to_tensors).map_err(Error::from);
+        if let (Some(extents), Ok(outputs)) = (extents, &result) {
+            self.record_aliases(&extents, outputs);
+        }
+        let result = result.and_then(|outputs| self.check_output_schema(outputs));
+        #[cfg(feature = "otel")]
+        otel::finish(span, &result);
+        #[cfg(feature = "tracing")]
+        trace::finish_run(&traced, elapsed, &result);
+        result
+    }
+
+    /// The inputs' memory extents, if the model checks output aliasing.
+    fn input_extents(&self, inputs: &[DeviceTensor<D>]) -> Option<Vec<Option<Range<usize>>>> {
+        self.check_output_aliasing
+            .then(|| inputs.iter().map(|t| validate::extent(&t.tensor)).collect())
+    }
+
+    fn record_aliases(&mut self, extents: &[Option<Range<usize>>], outputs: &[DeviceTensor<D>]) {
+        self.output_aliases = validate::aliases(extents, outputs.iter().map(|t| &t.tensor));
+        if !self.output_aliases.is_empty() {
+            self.stats.aliased_runs += 1;
+        }
+    }
+
+    fn check_output_schema(
+        &self,
+        outputs: Vec<DeviceTensor<D>>,
+    ) -> Result<Vec<DeviceTensor<D>>, Error> {
+        if let Some(schema) = &self.output_schema {
+            schema.check(outputs.iter().map(|t| &t.tensor))?;
+        }
+        Ok(outputs)
+    }
+
+    /// Detached copies of `inputs` if any requires grad and the model
+    /// detaches them; fails if it doesn't.
+    fn detach_grad(
+        &self,
+        inputs: &[DeviceTensor<D>],
+    ) -> Result<Option<Vec<DeviceTensor<D>>>, Error> {
+        let tensors = inputs.iter().map(|t| &t.tensor);
+        if !validate::check_grad(tensors, self.detach_grad_inputs)? {
+            return Ok(None);
+        }
+        Ok(Some(
+            inputs
+                .iter()
+                .map(|t| DeviceTensor {
+                    tensor: t.tensor.detach(),
+                    _device: PhantomData,
+                })
+                .collect(),
+        ))
+    }
+
+    #[cfg(feature = "tracing")]
+    fn traced(&self, op: &'static str, inputs: &[DeviceTensor<D>]) -> tracing::Span {
+        tracing::debug_span!(
+            "aoti_ffi",
+            op,
+            model = %self.model_name,
+            device = %device_string(self.device),
+            request_id = RequestId::current().as_ref().map(RequestId::as_str),
+            inputs = ?trace::shapes(inputs),
+            outputs = tracing::field::Empty,
+            duration_us = tracing::field::Empty,
+        )
+    }
+
+    /// Make a metadata query through the runtime, traced with feature
+    /// `tracing`.
+    fn query<T>(
+        &mut self,
+        #[cfg_attr(not(feature = "tracing"), allow(unused_variables))] op: &'static str,
+        call: impl FnOnce(std::pin::Pin<&mut ffi::AOTIModelContainerRunner>) -> Result<T, FfiError>,
+    ) -> Result<T, Error> {
+        #[cfg(feature = "tracing")]
+        let traced = tracing::debug_span!(
+            "aoti_ffi",
+            op,
+            model = %self.model_name,
+            device = %device_string(self.device),
+            duration_us = tracing::field::Empty,
+        );
+        #[cfg(feature = "tracing")]
+        let (_entered, started) = (traced.enter(), Instant::now());
+        let result = call(self.inner.pin_mut()).map_err(Error::from);
+        #[cfg(feature = "tracing")]
+        trace::finish(&traced, started.elapsed(), &result);
+        result
+    }
+
+    #[cfg(feature = "otel")]
+    fn run_span(&self, inputs: usize) -> opentelemetry::global::BoxedSpan {
+        use opentelemetry::KeyValue;
+        let mut attributes = vec![
+            KeyValue::new("aoti.model.name", self.model_name.clone()),
+            KeyValue::new("aoti.model.path", self.path.display().to_string()),
+            KeyValue::new("aoti.device", device_string(self.device)),
+            KeyValue::new("aoti.inputs", inputs as i64),
+        ];
+        if let Some(id) = RequestId::current() {
+            attributes.push(KeyValue::new("aoti.request_id", id.to_string()));
+        }
+        otel::span("aoti.run", attributes)
     }
 
     /// Get model metadata as a key-value map.
@@ -743,12 +1663,165 @@ impl<D: Device> AOTIModel<D> {
 
     /// Get the call specification strings for the model.
     pub fn get_call_spec(&mut self) -> Result<Vec<String>, Error> {
-        Ok(ffi::runner_get_call_spec(self.inner.pin_mut())?)
+        self.query("get_call_spec", ffi::runner_get_call_spec)
     }
 
     /// Get the fully qualified names of all constants in the model.
     pub fn get_constant_fqns(&mut self) -> Result<Vec<String>, Error> {
-        Ok(ffi::runner_get_constant_fqns(self.inner.pin_mut())?)
+        self.query("get_constant_fqns", ffi::runner_get_constant_fqns)
+    }
+
+    /// Get the name, dtype, shape and size of every constant (weights,
+    /// buffers) the model holds, sorted by name.
+    pub fn constants(&mut self) -> Result<Vec<ConstantInfo>, Error> {
+        let named = self.query("get_constants", ffi::runner_get_constants)?;
+        received(named.iter().map(|n| &n.tensor), "runner_get_constants");
+        let mut constants: Vec<ConstantInfo> = named
+            .into_iter()
+            .map(|named| {
+                // SAFETY: `runner_get_constants` heap-allocates each tensor
+                // with `new at::Tensor(...)` and hands over ownership.
+                let tensor = unsafe { adopt(named.tensor) };
+                let kind = tensor.kind();
+                ConstantInfo {
+                    name: named.name,
+                    dtype: kind,
+                    shape: tensor.size(),
+                    bytes: tensor.numel() as u64 * kind.elt_size_in_bytes() as u64,
+                }
+            })
+            .collect();
+        constants.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(constants)
+    }
+
+    /// Get the number of constants, their elements and bytes, in total
+    /// and per dtype.
+    pub fn constant_stats(&mut self) -> Result<ConstantStats, Error> {
+        Ok(ConstantStats::of(&self.constants()?))
+    }
+
+    /// Get the active value of every constant, keyed by fully qualified
+    /// name. The tensors share storage with the model's constant buffer.
+    pub fn constant_tensors(&mut self) -> Result<HashMap<String, DeviceTensor<D>>, Error> {
+        let named = self.query("get_constants", ffi::runner_get_constants)?;
+        received(named.iter().map(|n| &n.tensor), "runner_get_constants");
+        let constants = named
+            .into_iter()
+            .map(|named| {
+                // SAFETY: as in `AOTIModel::constants`.
+                let tensor = unsafe { adopt(named.tensor) };
+                let tensor = DeviceTensor {
+                    tensor,
+                    _device: PhantomData,
+                };
+                (named.name, tensor)
+            })
+            .collect();
+        Ok(constants)
+    }
+
+    /// Copy `constants`, keyed by fully qualified name, into the inactive
+    /// constant buffer; every other constant gets its active value. Runs
+    /// keep using the active buffer until [`AOTIModel::swap_constants`], so
+    /// new weights can be staged without pausing inference, at the cost of
+    /// a second copy of the constants in device memory.
+    ///
+    /// Each value must have its constant's dtype, shape and device, or the
+    /// update fails with [`Error::InvalidInput`].
+    pub fn update_inactive_constants(
+        &mut self,
+        constants: &HashMap<String, DeviceTensor<D>>,
+    ) -> Result<(), Error> {
+        let current = self.constant_tensors()?;
+        for (name, tensor) in constants {
+            // Unknown names are reported by the runtime.
+            if let Some(current) = current.get(name) {
+                validate::check_constant(name, tensor, current)?;
+            }
+        }
+        let named: Vec<ffi::NamedTensorPtr> = constants
+            .iter()
+            .map(|(name, tensor)| ffi::NamedTensorPtr {
+                name: name.clone(),
+                tensor: ffi::TensorPtr {
+                    ptr: tensor.tensor.as_ptr() as *const ffi::c_void,
+                },
+            })
+            .collect();
+        self.query("update_constant_buffer", |runner| {
+            ffi::runner_update_constant_buffer(runner, &named, true, false)
+        })
+    }
+
+    /// Make the inactive constant buffer active, and the active one
+    /// inactive.
+    pub fn swap_constants(&mut self) -> Result<(), Error> {
+        self.query("swap_constant_buffer", ffi::runner_swap_constant_buffer)
+    }
+
+    /// Get the call specification as its `in_spec`/`out_spec` pair.
+    pub fn call_spec(&mut self) -> Result<CallSpec, Error> {
+        self.get_call_spec()?.try_into()
+    }
+
+    /// The outputs of the last successful run that share memory with one of its
+    /// inputs, if the model checks for them (see
+    /// [`AOTIModelBuilder::check_output_aliasing`]). Copy such an output
+    /// before mutating the input, or vice versa.
+    pub fn output_aliases(&self) -> &[OutputAlias] {
+        &self.output_aliases
+    }
+
+    /// The names [`Outputs`] of this model's runs have, if its call spec
+    /// gives them.
+    pub fn output_names(&self) -> Option<&[String]> {
+        self.output_names.as_deref()
+    }
+
+    /// What inputs to this model are converted for; see [`IntoInputs`].
+    pub fn input_target(&self) -> &InputTarget {
+        &self.input_target
+    }
+
+    /// [`AOTIModel::output_names`], to share with other holders of outputs.
+    pub(crate) fn shared_output_names(&self) -> Option<Arc<[String]>> {
+        self.output_names.clone()
+    }
+
+    /// The schema every run's outputs are checked against, if any.
+    pub fn output_schema(&self) -> Option<&OutputSchema> {
+        self.output_schema.as_ref()
+    }
+
+    /// Check future runs' outputs against `schema`, or stop checking them;
+    /// see [`AOTIModelBuilder::output_schema`].
+    pub fn set_output_schema(&mut self, schema: Option<OutputSchema>) {
+        self.output_schema = schema;
+    }
+
+    /// Timing statistics for the `run` calls made so far.
+    pub fn stats(&self) -> &RunStats {
+        &self.stats
+    }
+
+    /// Reset the run statistics, e.g. after warm-up runs.
+    pub fn reset_stats(&mut self) {
+        self.stats = RunStats::default();
+    }
+
+    /// Collect the model's package, metadata, call spec, constants, and run
+    /// statistics into one plain-data value.
+    pub fn summary(&mut self) -> Result<ModelSummary, Error> {
+        Ok(ModelSummary {
+            package_path: self.path.display().to_string(),
+            model_name: self.model_name.clone(),
+            device: device_string(self.device),
+            metadata: self.metadata.clone().into(),
+            call_spec: self.call_spec()?,
+            constant_fqns: self.get_constant_fqns()?,
+            stats: self.stats.clone(),
+        })
     }
 }
 
@@ -769,13 +1842,21 @@ impl AnyAOTIModel {
     /// Load a `.pt2` package, dispatching on its `AOTI_DEVICE_KEY` metadata
     /// (missing metadata is treated as CPU). Uses the default model name
     /// `"model"`.
-    pub fn load(model_package_path: &str) -> Result<Self, Error> {
+    pub fn load(model_package_path: impl AsRef<Path>) -> Result<Self, Error> {
         Self::load_named(model_package_path, "model")
     }
 
     /// Load a `.pt2` package by model name, dispatching on its
     /// `AOTI_DEVICE_KEY` metadata (missing metadata is treated as CPU).
-    pub fn load_named(model_package_path: &str, model_name: &str) -> Result<Self, Error> {
+    pub fn load_named(
+        model_package_path: impl AsRef<Path>,
+        model_name: &str,
+    ) -> Result<Self, Error> {
+        let model_package_path = model_package_path.as_ref();
+        #[cfg(feature = "object-store")]
+        let fetched = remote::resolve(model_package_path, None, None, &mut |_| {})?;
+        #[cfg(feature = "object-store")]
+        let model_package_path = fetched.as_path();
         let metadata = read_metadata_from_zip(model_package_path, model_name)?;
         let is_cuda = metadata
             .get("AOTI_DEVICE_KEY")
@@ -794,7 +1875,8 @@ impl AnyAOTIModel {
             #[cfg(not(aoti_cuda))]
             {
                 Err(Error::Model(format!(
-                    "'{model_package_path}' targets CUDA but aoti-rs was built without CUDA support"
+                    "'{}' targets CUDA but aoti-rs was built without CUDA support",
+                    model_package_path.display()
                 )))
             }
         } else {
@@ -851,6 +1933,43 @@ mod tests {
         }
     }
 
+    #[test]
+    fn cpp_backtraces_are_split_from_messages() {
+        let what = "shape mismatch\n\nC++ backtrace:\nframe #0: at::foo()";
+        assert_eq!(
+            split_cpp_backtrace(what),
+            ("shape mismatch", Some("frame #0: at::foo()"))
+        );
+        assert_eq!(split_cpp_backtrace("bad_alloc"), ("bad_alloc", None));
+    }
+
+    #[test]
+    fn builds_fail_on_bad_settings_and_paths_before_the_runtime() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("missing.pt2");
+        match AOTIModel::<Cpu>::load(&missing) {
+            Err(Error::Io(e)) => {
+                assert_eq!(e.kind(), std::io::ErrorKind::NotFound);
+                assert!(e.to_string().contains("missing.pt2"));
+            }
+            other => panic!("expected a not-found error, got {:?}", other.err()),
+        }
+        assert!(matches!(
+            AOTIModel::<Cpu>::load(dir.path()),
+            Err(Error::InvalidPath(_))
+        ));
+        assert!(matches!(
+            AOTIModel::<Cpu>::builder(&missing).num_runners(0).build(),
+            Err(Error::InvalidInput(_))
+        ));
+        if tch::Cuda::device_count() == 0 {
+            assert!(matches!(
+                AOTIModelBuilder::<Cuda>::new(&missing).validate(),
+                Err(Error::InvalidInput(_))
+            ));
+        }
+    }
+
     #[test]
     fn device_kind_strips_index() {
         assert_eq!(device_kind("cuda"), "cuda");
@@ -0,0 +1,195 @@
+//! Conversions between [`DeviceTensor`]s and `ndarray` arrays (feature
+//! `ndarray`).
+//!
+//! Element types map to dtypes through [`tch::kind::Element`] (`f32` ↔
+//! `Float`, `i64` ↔ `Int64`, ...). Conversions never cast: asking for an
+//! `ArrayD<f32>` from an `Int64` tensor fails with
+//! [`Error::TensorKindMismatch`] rather than silently rounding.
+//!
+//! Array → tensor conversions always copy, because libtorch cannot adopt a
+//! Rust allocation without a custom deleter. Tensor → array conversions
+//! either copy ([`DeviceTensor::to_ndarray`], works on any device) or borrow
+//! the tensor's storage in place ([`DeviceTensor::as_array_view`], CPU only
+//! and `unsafe`, since other tensors sharing the storage could write to it
+//! while it is borrowed).
+
+use std::marker::PhantomData;
+use std::ptr::NonNull;
+
+use ndarray::{ArrayBase, ArrayD, ArrayViewD, Data, Dimension, IxDyn, ShapeBuilder};
+use tch::Tensor;
+use tch::kind::Element;
+
+use crate::{Cpu, Device, DeviceTensor, Error};
+
+fn check_kind<T: Element>(tensor: &Tensor) -> Result<(), Error> {
+    let found = tensor.f_kind()?;
+    if found == T::KIND {
+        Ok(())
+    } else {
+        Err(Error::TensorKindMismatch {
+            expected: T::KIND,
+            found,
+        })
+    }
+}
+
+fn shape_usize(tensor: &Tensor) -> Vec<usize> {
+    tensor.size().iter().map(|&d| d as usize).collect()
+}
+
+impl DeviceTensor<Cpu> {
+    /// Copy an array of any layout into a new CPU tensor with the matching
+    /// dtype and shape.
+    pub fn from_ndarray<S, Dm>(array: &ArrayBase<S, Dm>) -> Result<Self, Error>
+    where
+        S: Data,
+        S::Elem: Element + Copy,
+        Dm: Dimension,
+    {
+        let standard = array.as_standard_layout();
+        let slice = standard
+            .as_slice()
+            .expect("standard-layout arrays are contiguous");
+        let shape: Vec<i64> = array.shape().iter().map(|&d| d as i64).collect();
+        let tensor = Tensor::f_from_slice(slice)?.f_reshape(&shape)?;
+        Ok(Self {
+            tensor,
+            _device: PhantomData,
+        })
+    }
+
+    /// Borrow the tensor's storage as an array view, without copying.
+    ///
+    /// Works for any stride pattern libtorch produces (including
+    /// non-contiguous and expanded tensors), as long as the dtype matches
+    /// `T`.
+    ///
+    /// # Safety
+    ///
+    /// Nothing may write to the tensor's storage while the view is alive:
+    /// not an in-place op on a tensor sharing it (a `shallow_clone`, a
+    /// view), nor a run it was donated to. Use
+    /// [`DeviceTensor::to_ndarray`] when that can't be ruled out.
+    pub unsafe fn as_array_view<T: Element>(&self) -> Result<ArrayViewD<'_, T>, Error> {
+        check_kind::<T>(&self.tensor)?;
+        let shape = shape_usize(&self.tensor);
+        let strides: Vec<usize> = self.tensor.stride().iter().map(|&s| s as usize).collect();
+        // libtorch may hand out a null data pointer for empty tensors;
+        // ndarray requires a non-null, aligned pointer even then.
+        let ptr = NonNull::new(self.tensor.data_ptr() as *mut T)
+            .unwrap_or(NonNull::dangling())
+            .as_ptr() as *const T;
+        // Safety: the dtype was checked above, torch strides are
+        // non-negative element counts, and `data_ptr` already accounts for
+        // the storage offset. The view borrows `self`, so the storage stays
+        // alive, and the caller guarantees nothing writes to it meanwhile.
+        Ok(unsafe { ArrayViewD::from_shape_ptr(IxDyn(&shape).strides(IxDyn(&strides)), ptr) })
+    }
+}
+
+impl<D: Device> DeviceTensor<D> {
+    /// Copy the tensor into a new array, transferring it to host RAM first
+    /// if necessary.
+    pub fn to_ndarray<T: Element + Copy>(&self) -> Result<ArrayD<T>, Error> {
+        check_kind::<T>(&self.tensor)?;
+        let numel = self.tensor.numel();
+        let mut data = vec![T::ZERO; numel];
+        self.tensor.f_contiguous()?.f_copy_data(&mut data, numel)?;
+        Ok(ArrayD::from_shape_vec(
+            IxDyn(&shape_usize(&self.tensor)),
+            data,
+        )?)
+    }
+}
+
+impl<S, Dm> TryFrom<&ArrayBase<S, Dm>> for DeviceTensor<Cpu>
+where
+    S: Data,
+    S::Elem: Element + Copy,
+    Dm: Dimension,
+{
+    type Error = Error;
+
+    fn try_from(array: &ArrayBase<S, Dm>) -> Result<Self, Error> {
+        Self::from_ndarray(array)
+    }
+}
+
+impl<S, Dm> TryFrom<ArrayBase<S, Dm>> for DeviceTensor<Cpu>
+where
+    S: Data,
+    S::Elem: Element + Copy,
+    Dm: Dimension,
+{
+    type Error = Error;
+
+    fn try_from(array: ArrayBase<S, Dm>) -> Result<Self, Error> {
+        Self::from_ndarray(&array)
+    }
+}
+
+impl<T: Element + Copy, D: Device> TryFrom<&DeviceTensor<D>> for ArrayD<T> {
+    type Error = Error;
+
+    fn try_from(tensor: &DeviceTensor<D>) -> Result<Self, Error> {
+        tensor.to_ndarray()
+    }
+}
+
+impl<T: Element + Copy, D: Device> TryFrom<DeviceTensor<D>> for ArrayD<T> {
+    type Error = Error;
+
+    fn try_from(tensor: DeviceTensor<D>) -> Result<Self, Error> {
+        tensor.to_ndarray()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::{Array2, array};
+
+    #[test]
+    fn round_trips_through_tensor() {
+        let a: Array2<f32> = array![[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]];
+        let t = DeviceTensor::<Cpu>::try_from(&a).unwrap();
+        assert_eq!(t.size(), &[2, 3]);
+        let back: ArrayD<f32> = (&t).try_into().unwrap();
+        assert_eq!(back, a.into_dyn());
+    }
+
+    #[test]
+    fn transposed_input_keeps_logical_order() {
+        let a: Array2<i64> = array![[1, 2, 3], [4, 5, 6]];
+        let t = DeviceTensor::<Cpu>::from_ndarray(&a.t()).unwrap();
+        assert_eq!(t.size(), &[3, 2]);
+        assert_eq!(t.to_ndarray::<i64>().unwrap(), a.t().into_dyn());
+    }
+
+    #[test]
+    fn view_follows_strides() {
+        let t = Tensor::arange(6, (tch::Kind::Float, tch::Device::Cpu))
+            .reshape([2, 3])
+            .tr();
+        let t = DeviceTensor::<Cpu>::try_new(t).unwrap();
+        // Safety: nothing else holds `t`'s storage.
+        let view = unsafe { t.as_array_view::<f32>() }.unwrap();
+        assert_eq!(view.shape(), &[3, 2]);
+        assert_eq!(view[[2, 1]], 5.0);
+    }
+
+    #[test]
+    fn dtype_mismatch_is_rejected() {
+        let t =
+            DeviceTensor::<Cpu>::try_new(Tensor::zeros([2], (tch::Kind::Int64, tch::Device::Cpu)))
+                .unwrap();
+        assert!(matches!(
+            t.to_ndarray::<f32>(),
+            Err(Error::TensorKindMismatch {
+                expected: tch::Kind::Float,
+                found: tch::Kind::Int64,
+            })
+        ));
+    }
+}
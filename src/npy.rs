@@ -0,0 +1,141 @@
+//! NumPy `.npy` / `.npz` reading and writing (feature `npy`).
+//!
+//! A thin layer over tch's NumPy support that produces and accepts
+//! [`DeviceTensor`]s, mirroring [`crate::safetensors`]: files are read into
+//! host memory, and device-resident or non-contiguous tensors are copied to
+//! contiguous host memory before being written.
+//!
+//! Supported dtypes are `float16/32/64`, `int8/16/32/64` and `uint8` in both
+//! directions, plus `bool` and `complex64/128` when reading. Arrays saved in
+//! Fortran order (`np.asfortranarray`) are rejected; re-save them with
+//! `np.ascontiguousarray` first.
+
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::path::Path;
+
+use tch::Tensor;
+
+use crate::{AOTIModel, Cpu, Device, DeviceTensor, Error};
+
+fn host(tensor: &Tensor) -> Result<Tensor, Error> {
+    Ok(tensor.f_to_device(tch::Device::Cpu)?.f_contiguous()?)
+}
+
+/// Read a single array from a `.npy` file.
+pub fn read_npy(path: impl AsRef<Path>) -> Result<DeviceTensor<Cpu>, Error> {
+    Ok(DeviceTensor {
+        tensor: Tensor::read_npy(path)?,
+        _device: PhantomData,
+    })
+}
+
+/// Write `tensor` to a `.npy` file.
+pub fn write_npy<D: Device>(path: impl AsRef<Path>, tensor: &DeviceTensor<D>) -> Result<(), Error> {
+    Ok(host(tensor)?.write_npy(path)?)
+}
+
+/// Read every array in a `.npz` archive, in archive order, named as
+/// `np.savez` named them (without the `.npy` suffix).
+pub fn read_npz(path: impl AsRef<Path>) -> Result<Vec<(String, DeviceTensor<Cpu>)>, Error> {
+    Ok(Tensor::read_npz(path)?
+        .into_iter()
+        .map(|(name, tensor)| {
+            (
+                name,
+                DeviceTensor {
+                    tensor,
+                    _device: PhantomData,
+                },
+            )
+        })
+        .collect())
+}
+
+/// Write `tensors` to an uncompressed `.npz` archive under the given names.
+pub fn write_npz<D: Device>(
+    path: impl AsRef<Path>,
+    tensors: &[(&str, &DeviceTensor<D>)],
+) -> Result<(), Error> {
+    let host = tensors
+        .iter()
+        .map(|&(name, tensor)| Ok((name, host(tensor)?)))
+        .collect::<Result<Vec<_>, Error>>()?;
+    Ok(Tensor::write_npz(&host, path)?)
+}
+
+/// Load the arrays called `names`, in that order, from a `.npz` archive and
+/// upload them to `model`'s device, ready for [`AOTIModel::run`].
+///
+/// Fails with [`Error::InvalidInput`] if the archive has no array by one of
+/// the names; extra arrays are ignored.
+pub fn load_inputs<D: Device>(
+    model: &AOTIModel<D>,
+    path: impl AsRef<Path>,
+    names: &[&str],
+) -> Result<Vec<DeviceTensor<D>>, Error> {
+    let path = path.as_ref();
+    let mut arrays: HashMap<_, _> = read_npz(path)?.into_iter().collect();
+    names
+        .iter()
+        .map(|&name| {
+            let array = arrays.remove(name).ok_or_else(|| {
+                Error::InvalidInput(format!("{} has no array '{name}'", path.display()))
+            })?;
+            Ok(model.upload(&array))
+        })
+        .collect()
+}
+
+/// Write model `outputs` to a `.npz` archive, naming them `names` in order.
+pub fn save_outputs<D: Device>(
+    path: impl AsRef<Path>,
+    outputs: &[DeviceTensor<D>],
+    names: &[&str],
+) -> Result<(), Error> {
+    if outputs.len() != names.len() {
+        return Err(Error::InvalidInput(format!(
+            "{} outputs but {} names",
+            outputs.len(),
+            names.len()
+        )));
+    }
+    let named: Vec<_> = names.iter().copied().zip(outputs).collect();
+    write_npz(path, &named)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn npy_round_trips_transposed_tensor() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("x.npy");
+        let t = DeviceTensor::<Cpu>::try_new(
+            Tensor::arange(6, (tch::Kind::Float, tch::Device::Cpu))
+                .reshape([2, 3])
+                .tr(),
+        )
+        .unwrap();
+        write_npy(&path, &t).unwrap();
+
+        let back = read_npy(&path).unwrap();
+        assert_eq!(back.size(), &[3, 2]);
+        assert_eq!(back.double_value(&[2, 1]), 5.0);
+    }
+
+    #[test]
+    fn npz_keeps_names_and_dtypes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.npz");
+        let ids = DeviceTensor::<Cpu>::try_new(Tensor::from_slice(&[7i64, 8])).unwrap();
+        let mask = DeviceTensor::<Cpu>::try_new(Tensor::from_slice(&[1u8, 0])).unwrap();
+        save_outputs(&path, &[ids, mask], &["ids", "mask"]).unwrap();
+
+        let read: HashMap<_, _> = read_npz(&path).unwrap().into_iter().collect();
+        assert_eq!(read["ids"].kind(), tch::Kind::Int64);
+        assert_eq!(read["mask"].kind(), tch::Kind::Uint8);
+        assert_eq!(read["ids"].int64_value(&[1]), 8);
+    }
+}
@@ -0,0 +1,129 @@
+//! OpenTelemetry spans for model loading and inference (feature `otel`).
+//!
+//! Spans go to the global tracer provider under the `aoti-rs` scope, so
+//! they are no-ops until the application installs one:
+//!
+//! - `aoti.load` around [`AOTIModelBuilder::build`](crate::AOTIModelBuilder),
+//! - `aoti.queue_wait` while a pool run waits for a busy replica,
+//! - `aoti.run` around each `run` FFI call,
+//! - `aoti.route` around runs made through a registry
+//!   [`Routed`](crate::registry::Routed), carrying the served name and
+//!   version,
+//! - `aoti.predict` around each request handled by the HTTP, gRPC or Flight
+//!   servers, parented to the trace context the request carried, as read
+//!   by the global text-map propagator.
+//!
+//! Spans carry `aoti.model.name`, `aoti.model.version`, `aoti.model.path`
+//! and `aoti.device` attributes where known, and record errors as the span
+//! status.
+
+use opentelemetry::global::{self, BoxedSpan};
+use opentelemetry::trace::{Span, SpanKind, Status, TraceContextExt, Tracer};
+use opentelemetry::{Context, ContextGuard, KeyValue};
+
+use crate::Error;
+
+/// Instrumentation scope of every span.
+const SCOPE: &str = "aoti-rs";
+
+/// Start an internal span as a child of the current context.
+pub(crate) fn span(name: &'static str, attributes: Vec<KeyValue>) -> BoxedSpan {
+    let tracer = global::tracer(SCOPE);
+    tracer
+        .span_builder(name)
+        .with_kind(SpanKind::Internal)
+        .with_attributes(attributes)
+        .start(&tracer)
+}
+
+/// Make `span` the current span until the guard drops, so spans started
+/// meanwhile on this thread become its children. The span ends when the
+/// guard drops.
+pub(crate) fn enter(span: BoxedSpan) -> ContextGuard {
+    Context::current_with_span(span).attach()
+}
+
+/// Start a server span for a request carrying `parent` and make it current
+/// until the guard drops.
+#[cfg(any(feature = "flight", feature = "grpc", feature = "http"))]
+pub(crate) fn enter_request(
+    parent: &Context,
+    name: &'static str,
+    attributes: Vec<KeyValue>,
+) -> ContextGuard {
+    let tracer = global::tracer(SCOPE);
+    let span = tracer
+        .span_builder(name)
+        .with_kind(SpanKind::Server)
+        .with_attributes(attributes)
+        .start_with_context(&tracer, parent);
+    parent.with_span(span).attach()
+}
+
+/// Record a failed `result` on the current span.
+pub(crate) fn record<T>(result: &Result<T, Error>) {
+    if let Err(err) = result {
+        let cx = Context::current();
+        let span = cx.span();
+        span.record_error(err);
+        span.set_status(Status::error(err.to_string()));
+    }
+}
+
+/// Record a failed `result` on `span` and end it.
+pub(crate) fn finish<T>(mut span: BoxedSpan, result: &Result<T, Error>) {
+    if let Err(err) = result {
+        span.record_error(err);
+        span.set_status(Status::error(err.to_string()));
+    }
+    span.end();
+}
+
+/// The trace context carried by request `headers` (e.g. W3C
+/// `traceparent`), per the global propagator.
+#[cfg(any(feature = "flight", feature = "grpc", feature = "http"))]
+pub(crate) fn extract(headers: &http::HeaderMap) -> Context {
+    global::get_text_map_propagator(|propagator| propagator.extract(&Headers(headers)))
+}
+
+#[cfg(any(feature = "flight", feature = "grpc", feature = "http"))]
+struct Headers<'a>(&'a http::HeaderMap);
+
+#[cfg(any(feature = "flight", feature = "grpc", feature = "http"))]
+impl opentelemetry::propagation::Extractor for Headers<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|v| v.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(http::HeaderName::as_str).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failures_are_recorded_without_a_provider() {
+        // With no provider installed every span is a no-op; recording must
+        // still be safe.
+        let _guard = enter(span("aoti.test", vec![KeyValue::new("k", "v")]));
+        record(&Err::<(), _>(Error::Model("boom".into())));
+        finish(span("aoti.test", Vec::new()), &Ok::<_, Error>(()));
+    }
+
+    #[cfg(any(feature = "flight", feature = "grpc", feature = "http"))]
+    #[test]
+    fn headers_are_read_case_insensitively() {
+        use opentelemetry::propagation::Extractor;
+
+        let mut headers = http::HeaderMap::new();
+        headers.insert("traceparent", "00-abc-def-01".parse().unwrap());
+        headers.insert("x-bin", http::HeaderValue::from_bytes(&[0xff]).unwrap());
+        let extractor = Headers(&headers);
+        assert_eq!(extractor.get("TraceParent"), Some("00-abc-def-01"));
+        assert_eq!(extractor.get("x-bin"), None);
+        assert_eq!(extractor.keys().len(), 2);
+    }
+}
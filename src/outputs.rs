@@ -0,0 +1,263 @@
+//! Run outputs, addressable by position and, when the package's call spec
+//! names them, by name.
+//!
+//! Models exported to return a dict name each output by its key; outputs
+//! in nested containers get dotted paths of keys and positions, e.g.
+//! `"logits"`, `"past.0"`:
+//!
+//! ```ignore
+//! let outputs = model.run(&inputs)?;
+//! let logits = &outputs["logits"];
+//! let hidden = outputs.get("hidden").unwrap_or(&outputs[1]);
+//! ```
+//!
+//! They also convert into a tuple of up to 8 tensors, failing with
+//! [`Error::OutputCountMismatch`] if the model returns another number:
+//!
+//! ```ignore
+//! let (logits, hidden) = model.run(&inputs)?.try_into()?;
+//! ```
+
+use std::ops::{Deref, Index};
+use std::sync::Arc;
+
+use serde_json::Value;
+
+use crate::{Device, DeviceTensor, Error};
+
+/// The outputs of a run, in the package's flat output order. Dereferences
+/// to a slice, and can be indexed by position or by name.
+pub struct Outputs<D: Device> {
+    tensors: Vec<DeviceTensor<D>>,
+    names: Option<Arc<[String]>>,
+}
+
+impl<D: Device> Outputs<D> {
+    /// `names` are dropped unless there is one per tensor.
+    pub(crate) fn new(tensors: Vec<DeviceTensor<D>>, names: Option<Arc<[String]>>) -> Self {
+        let names = names.filter(|names| names.len() == tensors.len());
+        Self { tensors, names }
+    }
+
+    /// Each output's name, if the call spec gives them.
+    pub fn names(&self) -> Option<&[String]> {
+        self.names.as_deref()
+    }
+
+    /// The position of the output called `name`.
+    pub fn position(&self, name: &str) -> Option<usize> {
+        self.names.as_ref()?.iter().position(|n| n == name)
+    }
+
+    /// The output called `name`.
+    pub fn get(&self, name: &str) -> Option<&DeviceTensor<D>> {
+        self.position(name).map(|i| &self.tensors[i])
+    }
+
+    pub fn into_vec(self) -> Vec<DeviceTensor<D>> {
+        self.tensors
+    }
+}
+
+impl<D: Device> Deref for Outputs<D> {
+    type Target = [DeviceTensor<D>];
+
+    fn deref(&self) -> &[DeviceTensor<D>] {
+        &self.tensors
+    }
+}
+
+impl<D: Device> Index<usize> for Outputs<D> {
+    type Output = DeviceTensor<D>;
+
+    fn index(&self, index: usize) -> &DeviceTensor<D> {
+        &self.tensors[index]
+    }
+}
+
+/// Panics if there is no output called `name`; see [`Outputs::get`].
+impl<D: Device> Index<&str> for Outputs<D> {
+    type Output = DeviceTensor<D>;
+
+    fn index(&self, name: &str) -> &DeviceTensor<D> {
+        match self.get(name) {
+            Some(output) => output,
+            None => panic!("no output named {name:?} (outputs: {:?})", self.names()),
+        }
+    }
+}
+
+impl<D: Device> IntoIterator for Outputs<D> {
+    type Item = DeviceTensor<D>;
+    type IntoIter = std::vec::IntoIter<DeviceTensor<D>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.tensors.into_iter()
+    }
+}
+
+impl<'a, D: Device> IntoIterator for &'a Outputs<D> {
+    type Item = &'a DeviceTensor<D>;
+    type IntoIter = std::slice::Iter<'a, DeviceTensor<D>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.tensors.iter()
+    }
+}
+
+impl<D: Device> From<Outputs<D>> for Vec<DeviceTensor<D>> {
+    fn from(outputs: Outputs<D>) -> Self {
+        outputs.tensors
+    }
+}
+
+impl<D: Device> std::fmt::Debug for Outputs<D> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.names {
+            Some(names) => f
+                .debug_map()
+                .entries(names.iter().zip(&self.tensors))
+                .finish(),
+            None => f.debug_list().entries(&self.tensors).finish(),
+        }
+    }
+}
+
+macro_rules! tuple_outputs {
+    ($($out:ident),+) => {
+        impl<D: Device> TryFrom<Outputs<D>> for ($(tuple_outputs!(@tensor $out),)+) {
+            type Error = Error;
+
+            fn try_from(outputs: Outputs<D>) -> Result<Self, Error> {
+                let expected = [$(stringify!($out)),+].len();
+                if outputs.len() != expected {
+                    return Err(Error::OutputCountMismatch {
+                        expected,
+                        found: outputs.len(),
+                    });
+                }
+                let mut outputs = outputs.into_iter();
+                Ok(($(tuple_outputs!(@next outputs $out),)+))
+            }
+        }
+    };
+    (@tensor $out:ident) => { DeviceTensor<D> };
+    (@next $outputs:ident $out:ident) => { $outputs.next().expect("output count checked") };
+}
+
+tuple_outputs!(A);
+tuple_outputs!(A, B);
+tuple_outputs!(A, B, C);
+tuple_outputs!(A, B, C, E);
+tuple_outputs!(A, B, C, E, F);
+tuple_outputs!(A, B, C, E, F, G);
+tuple_outputs!(A, B, C, E, F, G, H);
+tuple_outputs!(A, B, C, E, F, G, H, J);
+
+/// Names for the leaves of a serialized pytree `out_spec`, if it has any
+/// dict in it: each leaf's path of dict keys and container positions,
+/// joined with dots. `None` for outputs returned as a tensor, tuple or list,
+/// or if the spec can't be parsed.
+pub(crate) fn output_names(out_spec: &str) -> Option<Vec<String>> {
+    let spec: Value = serde_json::from_str(out_spec).ok()?;
+    let tree = match &spec {
+        Value::Array(parts) if parts.len() == 2 => &parts[1],
+        _ => return None,
+    };
+    let mut names = Vec::new();
+    let named = collect(tree, &mut Vec::new(), &mut names)?;
+    named.then_some(names)
+}
+
+/// Push the paths of `node`'s leaves, returning whether any of it is a dict.
+fn collect(node: &Value, path: &mut Vec<String>, names: &mut Vec<String>) -> Option<bool> {
+    let node = node.as_object()?;
+    let kind = node.get("type")?;
+    if kind.is_null() {
+        names.push(path.join("."));
+        return Some(false);
+    }
+    let children = node.get("children_spec")?.as_array()?;
+    // A dict's context is its keys, as a JSON list inside a string.
+    let keys: Option<Vec<String>> = match kind.as_str() {
+        Some("builtins.dict" | "collections.OrderedDict") => {
+            let keys: Vec<Value> = serde_json::from_str(node.get("context")?.as_str()?).ok()?;
+            if keys.len() != children.len() {
+                return None;
+            }
+            let keys = keys.into_iter().map(|key| match key {
+                Value::String(key) => key,
+                key => key.to_string(),
+            });
+            Some(keys.collect())
+        }
+        _ => None,
+    };
+    let mut named = keys.is_some();
+    for (i, child) in children.iter().enumerate() {
+        let segment = keys
+            .as_ref()
+            .map_or_else(|| i.to_string(), |keys| keys[i].clone());
+        path.push(segment);
+        named |= collect(child, path, names)?;
+        path.pop();
+    }
+    Some(named)
+}
+
+#[cfg(test)]
+mod tests {
+    use tch::Tensor;
+
+    use super::*;
+    use crate::Cpu;
+
+    const LEAF: &str = r#"{"type": null, "context": null, "children_spec": []}"#;
+
+    #[test]
+    fn dict_outputs_are_named_by_key_path() {
+        let spec = format!(
+            r#"[1, {{"type": "builtins.dict", "context": "[\"logits\", \"past\"]", "children_spec": [
+                {LEAF},
+                {{"type": "builtins.tuple", "context": "null", "children_spec": [{LEAF}, {LEAF}]}}
+            ]}}]"#
+        );
+        assert_eq!(output_names(&spec).unwrap(), ["logits", "past.0", "past.1"]);
+        let tuple = format!(
+            r#"[1, {{"type": "builtins.tuple", "context": "null", "children_spec": [{LEAF}, {LEAF}]}}]"#
+        );
+        assert_eq!(output_names(&tuple), None);
+        assert_eq!(output_names(&format!("[1, {LEAF}]")), None);
+        assert_eq!(output_names("not json"), None);
+    }
+
+    #[test]
+    fn outputs_index_by_position_and_name() {
+        let tensor = |v: f32| DeviceTensor::<Cpu>::try_new(Tensor::from_slice(&[v])).unwrap();
+        let names: Arc<[String]> = vec!["logits".to_string(), "hidden".to_string()].into();
+        let outputs = Outputs::new(vec![tensor(1.0), tensor(2.0)], Some(names.clone()));
+        assert_eq!(outputs["hidden"].double_value(&[0]), 2.0);
+        assert_eq!(outputs[0].double_value(&[0]), 1.0);
+        assert_eq!(outputs.position("hidden"), Some(1));
+        assert!(outputs.get("missing").is_none());
+        assert_eq!(outputs.len(), 2);
+
+        let (logits, hidden) = outputs.try_into().unwrap();
+        assert_eq!(logits.double_value(&[0]), 1.0);
+        assert_eq!(hidden.double_value(&[0]), 2.0);
+        let three = Outputs::new(vec![tensor(1.0), tensor(2.0), tensor(3.0)], None);
+        let result: Result<(DeviceTensor<Cpu>, DeviceTensor<Cpu>), _> = three.try_into();
+        assert!(matches!(
+            result,
+            Err(Error::OutputCountMismatch {
+                expected: 2,
+                found: 3
+            })
+        ));
+
+        let unnamed = Outputs::new(vec![tensor(1.0)], Some(names));
+        assert_eq!(unnamed.names(), None);
+        assert!(unnamed.get("logits").is_none());
+        assert_eq!(unnamed.into_vec().len(), 1);
+    }
+}
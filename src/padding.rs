@@ -0,0 +1,243 @@
+//! Batching variable-length token sequences: padding them to a length the
+//! package accepts, building the attention mask, and stripping the padding
+//! from the outputs again.
+//!
+//! Packages exported with a dynamic sequence length still perform best (or
+//! only work) at a few sizes; [`Buckets`] lists them, so a batch is padded
+//! to the smallest one that fits rather than to its own longest sequence.
+
+use std::marker::PhantomData;
+
+use tch::Tensor;
+
+use crate::{Cpu, Device, DeviceTensor, Error, ModelMetadata};
+
+fn wrap<D: Device>(tensor: Tensor) -> DeviceTensor<D> {
+    DeviceTensor {
+        tensor,
+        _device: PhantomData,
+    }
+}
+
+/// The sequence lengths a package was compiled for.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Buckets(Vec<usize>);
+
+impl Buckets {
+    /// Any length: batches are padded to their longest sequence.
+    pub fn any() -> Self {
+        Self(Vec::new())
+    }
+
+    /// Exactly these lengths, in any order.
+    pub fn new(lengths: impl IntoIterator<Item = usize>) -> Self {
+        let mut lengths: Vec<usize> = lengths.into_iter().collect();
+        lengths.sort_unstable();
+        lengths.dedup();
+        Self(lengths)
+    }
+
+    /// The lengths a package's metadata records: a comma-separated
+    /// `seq_len_buckets`, or else a single `max_seq_len`, or else any.
+    pub fn from_metadata(metadata: &ModelMetadata) -> Result<Self, Error> {
+        let Some((key, value)) = ["seq_len_buckets", "max_seq_len"]
+            .into_iter()
+            .find_map(|key| Some((key, metadata.get(key)?)))
+        else {
+            return Ok(Self::any());
+        };
+        let lengths = value
+            .split(',')
+            .map(|len| {
+                len.trim()
+                    .parse()
+                    .map_err(|_| Error::Model(format!("metadata {key}='{value}' is not a length")))
+            })
+            .collect::<Result<Vec<usize>, _>>()?;
+        Ok(Self::new(lengths))
+    }
+
+    /// The length to pad a batch whose longest sequence is `longest` to.
+    pub fn fit(&self, longest: usize) -> Result<usize, Error> {
+        if self.0.is_empty() {
+            return Ok(longest);
+        }
+        self.0
+            .iter()
+            .copied()
+            .find(|&len| len >= longest)
+            .ok_or_else(|| {
+                Error::InvalidInput(format!(
+                    "a sequence of {longest} tokens is longer than the package's longest \
+                     bucket, {}",
+                    self.0[self.0.len() - 1]
+                ))
+            })
+    }
+}
+
+/// Which end of a sequence padding goes on.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Side {
+    /// After the tokens, as encoders usually expect.
+    #[default]
+    Right,
+    /// Before the tokens, as decoders generating from a batch expect.
+    Left,
+}
+
+/// Pads token sequences into a batch.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Padder {
+    pad_id: i64,
+    side: Side,
+    buckets: Buckets,
+}
+
+impl Padder {
+    /// Pad with `pad_id` on the right, to any length.
+    pub fn new(pad_id: i64) -> Self {
+        Self {
+            pad_id,
+            ..Self::default()
+        }
+    }
+
+    pub fn side(mut self, side: Side) -> Self {
+        self.side = side;
+        self
+    }
+
+    pub fn buckets(mut self, buckets: Buckets) -> Self {
+        self.buckets = buckets;
+        self
+    }
+
+    /// Pad `sequences` to the smallest bucket that fits the longest.
+    pub fn pad(&self, sequences: &[Vec<i64>]) -> Result<Padded, Error> {
+        if sequences.is_empty() {
+            return Err(Error::InvalidInput("cannot pad an empty batch".into()));
+        }
+        let lengths: Vec<usize> = sequences.iter().map(Vec::len).collect();
+        let len = self
+            .buckets
+            .fit(lengths.iter().copied().max().unwrap_or(0))?;
+        let mut ids: Vec<i64> = Vec::with_capacity(sequences.len() * len);
+        let mut mask: Vec<i64> = Vec::with_capacity(sequences.len() * len);
+        for sequence in sequences {
+            let padding = len - sequence.len();
+            let (pad_ids, pad_mask) = (vec![self.pad_id; padding], vec![0i64; padding]);
+            let real = vec![1i64; sequence.len()];
+            match self.side {
+                Side::Right => {
+                    ids.extend(sequence.iter().chain(&pad_ids));
+                    mask.extend(real.iter().chain(&pad_mask));
+                }
+                Side::Left => {
+                    ids.extend(pad_ids.iter().chain(sequence));
+                    mask.extend(pad_mask.iter().chain(&real));
+                }
+            }
+        }
+        let shape = [sequences.len() as i64, len as i64];
+        Ok(Padded {
+            input_ids: wrap(Tensor::f_from_slice(&ids)?.f_view(shape)?),
+            attention_mask: wrap(Tensor::f_from_slice(&mask)?.f_view(shape)?),
+            lengths,
+            side: self.side,
+        })
+    }
+}
+
+/// A padded batch, each tensor `[N, L]` `Int64`.
+#[derive(Debug)]
+pub struct Padded {
+    pub input_ids: DeviceTensor<Cpu>,
+    /// 1 for real tokens, 0 for padding.
+    pub attention_mask: DeviceTensor<Cpu>,
+    /// Each sequence's unpadded length.
+    pub lengths: Vec<usize>,
+    side: Side,
+}
+
+impl Padded {
+    /// Split an `[N, L, ...]` output into each sequence's `[len, ...]`
+    /// positions, dropping those that were padding. The parts are views
+    /// on the output's device.
+    pub fn unpad<D: Device>(
+        &self,
+        output: &DeviceTensor<D>,
+    ) -> Result<Vec<DeviceTensor<D>>, Error> {
+        let size = output.size();
+        let padded = self.input_ids.size();
+        if size.len() < 2 || size[..2] != padded[..] {
+            return Err(Error::InvalidInput(format!(
+                "expected an output starting with the batch's {padded:?}, got {size:?}"
+            )));
+        }
+        self.lengths
+            .iter()
+            .enumerate()
+            .map(|(i, &len)| {
+                let start = match self.side {
+                    Side::Right => 0,
+                    Side::Left => padded[1] - len as i64,
+                };
+                Ok(wrap(
+                    output
+                        .f_select(0, i as i64)?
+                        .f_narrow(0, start, len as i64)?,
+                ))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn values(t: &Tensor) -> Vec<i64> {
+        Vec::<i64>::try_from(&t.flatten(0, -1)).unwrap()
+    }
+
+    #[test]
+    fn batches_pad_to_a_bucket_and_unpad_back() {
+        let sequences = [vec![1, 2, 3], vec![4]];
+        let padder = Padder::new(0).buckets(Buckets::new([8, 2, 4]));
+        let right = padder.pad(&sequences).unwrap();
+        assert_eq!(values(&right.input_ids), [1, 2, 3, 0, 4, 0, 0, 0]);
+        assert_eq!(values(&right.attention_mask), [1, 1, 1, 0, 1, 0, 0, 0]);
+
+        let left = padder.side(Side::Left).pad(&sequences).unwrap();
+        assert_eq!(values(&left.input_ids), [0, 1, 2, 3, 0, 0, 0, 4]);
+        let output = wrap::<Cpu>(left.input_ids.f_unsqueeze(-1).unwrap());
+        let parts = left.unpad(&output).unwrap();
+        assert_eq!(parts[0].size(), [3, 1]);
+        assert_eq!(values(&parts[0]), [1, 2, 3]);
+        assert_eq!(values(&parts[1]), [4]);
+        assert!(
+            right
+                .unpad(&right.input_ids.f_narrow(1, 0, 2).map(wrap::<Cpu>).unwrap())
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn buckets_come_from_metadata() {
+        let metadata = |key: &str, value: &str| {
+            ModelMetadata::from(HashMap::from([(key.to_string(), value.to_string())]))
+        };
+        let buckets = Buckets::from_metadata(&metadata("seq_len_buckets", "128, 64")).unwrap();
+        assert_eq!(buckets.fit(65).unwrap(), 128);
+        assert!(buckets.fit(129).is_err());
+        let single = Buckets::from_metadata(&metadata("max_seq_len", "16")).unwrap();
+        assert_eq!(single.fit(3).unwrap(), 16);
+        assert!(Buckets::from_metadata(&metadata("max_seq_len", "long")).is_err());
+        let any = Buckets::from_metadata(&metadata("other", "1")).unwrap();
+        assert_eq!(any.fit(7).unwrap(), 7);
+        assert!(Padder::new(0).pad(&[]).is_err());
+    }
+}
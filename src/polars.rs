@@ -0,0 +1,148 @@
+//! Polars DataFrame scoring (feature `polars`).
+//!
+//! Built on the [`arrow`](crate::arrow) conversions: columns are handed
+//! between Polars and arrow-rs through the Arrow C data interface, which
+//! shares buffers instead of copying them, so the supported dtypes and
+//! shapes are exactly the Arrow ones. Numeric columns become `[rows]`
+//! tensors and `Array` columns (Polars' fixed-size lists) add trailing
+//! dimensions; on the way out, `[rows, k]` outputs become `Array` columns of
+//! width `k`.
+
+use polars::prelude::{CompatLevel, DataFrame, IntoColumn, Series};
+use polars_arrow::ffi as polars_ffi;
+
+use crate::arrow::{array_to_tensor, tensor_to_array};
+use crate::{AOTIModel, Device, Error};
+
+/// Which DataFrame columns feed the model, in input order, and what to name
+/// the output columns, in output order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnMapping {
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+}
+
+impl ColumnMapping {
+    pub fn new<I, O>(inputs: I, outputs: O) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+        O: IntoIterator,
+        O::Item: Into<String>,
+    {
+        Self {
+            inputs: inputs.into_iter().map(Into::into).collect(),
+            outputs: outputs.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+fn series_to_arrow(series: &Series) -> Result<arrow_array::ArrayRef, Error> {
+    let series = series.rechunk();
+    let field = series.field().to_arrow(CompatLevel::oldest());
+    let c_array = polars_ffi::export_array_to_c(series.to_arrow(0, CompatLevel::oldest()));
+    let c_schema = polars_ffi::export_field_to_c(&field);
+    // Safety: both structs are `#[repr(C)]` mirrors of the C data
+    // interface's ArrowArray/ArrowSchema, freshly exported above, and
+    // ownership (including the release callbacks) moves to arrow-rs.
+    let data = unsafe {
+        let c_array: arrow_array::ffi::FFI_ArrowArray = std::mem::transmute(c_array);
+        let c_schema: arrow_array::ffi::FFI_ArrowSchema = std::mem::transmute(c_schema);
+        arrow_array::ffi::from_ffi(c_array, &c_schema)?
+    };
+    Ok(arrow_array::make_array(data))
+}
+
+fn arrow_to_polars(
+    array: &arrow_array::ArrayRef,
+) -> Result<Box<dyn polars_arrow::array::Array>, Error> {
+    let (c_array, c_schema) = arrow_array::ffi::to_ffi(&array.to_data())?;
+    // Safety: as in `series_to_arrow`, in the other direction.
+    let array = unsafe {
+        let c_array: polars_ffi::ArrowArray = std::mem::transmute(c_array);
+        let c_schema: polars_ffi::ArrowSchema = std::mem::transmute(c_schema);
+        let field = polars_ffi::import_field_from_c(&c_schema)?;
+        polars_ffi::import_array_from_c(c_array, field.dtype)?
+    };
+    Ok(array)
+}
+
+/// Score `df` in batches of at most `batch_size` rows, returning `df` with
+/// the model's outputs appended as the columns named in `mapping.outputs`.
+///
+/// Each batch's input columns are uploaded to the model's device and
+/// donated to [`AOTIModel::run`]; every output's leading dimension
+/// must be the batch's row count.
+pub fn score_dataframe<D: Device>(
+    model: &mut AOTIModel<D>,
+    df: &DataFrame,
+    mapping: &ColumnMapping,
+    batch_size: usize,
+) -> Result<DataFrame, Error> {
+    if batch_size == 0 {
+        return Err(Error::InvalidInput("batch_size must be at least 1".into()));
+    }
+    if df.height() == 0 {
+        return Err(Error::InvalidInput(
+            "cannot score an empty DataFrame".into(),
+        ));
+    }
+
+    let mut chunks: Vec<Vec<Box<dyn polars_arrow::array::Array>>> =
+        vec![Vec::new(); mapping.outputs.len()];
+    for offset in (0..df.height()).step_by(batch_size) {
+        let batch = df.slice(offset as i64, batch_size);
+        let inputs = mapping
+            .inputs
+            .iter()
+            .map(|name| {
+                let series = batch.column(name)?.as_materialized_series();
+                let tensor = array_to_tensor(series_to_arrow(series)?.as_ref())?;
+                Ok(model.upload(&tensor))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let outputs = model.run(inputs)?;
+        if outputs.len() != mapping.outputs.len() {
+            return Err(Error::InvalidInput(format!(
+                "model returned {} outputs but {} output columns were named",
+                outputs.len(),
+                mapping.outputs.len()
+            )));
+        }
+        for (column, output) in chunks.iter_mut().zip(&outputs) {
+            column.push(arrow_to_polars(&tensor_to_array(output)?)?);
+        }
+    }
+
+    let columns = mapping
+        .outputs
+        .iter()
+        .zip(chunks)
+        .map(|(name, arrays)| {
+            Ok(Series::from_arrow_chunks(name.as_str().into(), arrays)?.into_column())
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    Ok(df.hstack(&columns)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use polars::prelude::NamedFrom;
+
+    #[test]
+    fn numeric_column_round_trips_through_arrow() {
+        let series = Series::new("x".into(), &[1.0f32, 2.0, 3.0]);
+        let array = series_to_arrow(&series).unwrap();
+        let tensor = array_to_tensor(array.as_ref()).unwrap();
+        assert_eq!(tensor.size(), &[3]);
+
+        let back = Series::from_arrow(
+            "x".into(),
+            arrow_to_polars(&tensor_to_array(&tensor).unwrap()).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(back, series);
+    }
+}
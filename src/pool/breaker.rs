@@ -0,0 +1,234 @@
+//! Per-replica circuit breaking: quarantine a replica after consecutive
+//! failures, recover it in the background, and optionally fail over to a
+//! CPU pool while every replica is out.
+
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, PoisonError, Weak};
+use std::time::{Duration, Instant};
+
+use super::AOTIModelPool;
+use super::lora::Loaded;
+use crate::{AOTIModel, Cpu, Device, DeviceTensor, Error};
+
+type Rebuild<D> = dyn Fn(usize) -> Result<AOTIModel<D>, Error> + Send + Sync;
+
+/// When to take a pool's replicas out of rotation, and how to bring them
+/// back; see [`AOTIModelPool::with_circuit_breaker`].
+pub struct CircuitBreaker<D: Device> {
+    threshold: u32,
+    cooldown: Duration,
+    max_cooldown: Duration,
+    rebuild: Option<Box<Rebuild<D>>>,
+    fallback: Option<Arc<AOTIModelPool<Cpu>>>,
+}
+
+impl<D: Device> CircuitBreaker<D> {
+    /// Quarantine a replica after `threshold` (at least 1) consecutive
+    /// failed runs, retrying it after 5 seconds and backing off to 5
+    /// minutes between attempts.
+    pub fn new(threshold: u32) -> Self {
+        Self {
+            threshold: threshold.max(1),
+            cooldown: Duration::from_secs(5),
+            max_cooldown: Duration::from_secs(300),
+            rebuild: None,
+            fallback: None,
+        }
+    }
+
+    /// Wait `initial` before the first recovery attempt, doubling the wait
+    /// after each failed attempt up to `max`.
+    pub fn cooldown(mut self, initial: Duration, max: Duration) -> Self {
+        self.cooldown = initial;
+        self.max_cooldown = max.max(initial);
+        self
+    }
+
+    /// Reload a quarantined replica with `rebuild`, called with its index,
+    /// before testing it. Errors such as sticky CUDA faults poison the
+    /// loaded model, so without this a replica is only retried as is.
+    pub fn rebuild(
+        mut self,
+        rebuild: impl Fn(usize) -> Result<AOTIModel<D>, Error> + Send + Sync + 'static,
+    ) -> Self {
+        self.rebuild = Some(Box::new(rebuild));
+        self
+    }
+
+    /// Serve runs on `pool` while every replica is quarantined, copying
+    /// inputs to the CPU and outputs back to the pool's device.
+    pub fn fallback(mut self, pool: Arc<AOTIModelPool<Cpu>>) -> Self {
+        self.fallback = Some(pool);
+        self
+    }
+
+    pub(super) fn fallback_pool(&self) -> Option<&AOTIModelPool<Cpu>> {
+        self.fallback.as_deref()
+    }
+}
+
+/// One replica and its breaker state.
+pub(super) struct Replica<D: Device> {
+    index: usize,
+    /// `None` once released by [`AOTIModelPool::shutdown`].
+    pub(super) model: Mutex<Option<AOTIModel<D>>>,
+    /// The LoRA weights `model` holds; only changed with `model` locked.
+    pub(super) adapter: Mutex<Loaded<D>>,
+    failures: AtomicU32,
+    quarantined: AtomicBool,
+}
+
+impl<D: Device> Replica<D> {
+    pub(super) fn new(index: usize, model: Option<AOTIModel<D>>) -> Self {
+        Self {
+            index,
+            model: Mutex::new(model),
+            adapter: Mutex::new(Loaded::Base),
+            failures: AtomicU32::new(0),
+            quarantined: AtomicBool::new(false),
+        }
+    }
+
+    pub(super) fn index(&self) -> usize {
+        self.index
+    }
+
+    pub(super) fn is_quarantined(&self) -> bool {
+        self.quarantined.load(Ordering::Acquire)
+    }
+
+    /// Count a run's outcome, returning whether this failure is the one
+    /// that trips the breaker. Errors raised before the runtime is reached
+    /// say nothing about the replica and aren't counted.
+    fn record(&self, result: &Result<impl Sized, Error>, threshold: u32) -> bool {
+        match result {
+            Ok(_) => {
+                self.failures.store(0, Ordering::Relaxed);
+                false
+            }
+            Err(Error::Ffi(_) | Error::Tch(_) | Error::Model(_)) => {
+                let failures = self.failures.fetch_add(1, Ordering::Relaxed) + 1;
+                failures >= threshold && !self.quarantined.swap(true, Ordering::AcqRel)
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+/// Record a run on `replica`, starting its recovery if it just tripped.
+pub(super) fn observe<D: Device, T>(
+    breaker: &Arc<CircuitBreaker<D>>,
+    replica: &Arc<Replica<D>>,
+    probe: &Arc<Mutex<Option<Vec<DeviceTensor<D>>>>>,
+    result: &Result<T, Error>,
+) {
+    if replica.record(result, breaker.threshold) {
+        let (breaker, replica, probe) = (breaker.clone(), Arc::downgrade(replica), probe.clone());
+        std::thread::spawn(move || recover(&breaker, &replica, &probe));
+    }
+}
+
+/// Retry a quarantined replica with backoff until it passes the pool's
+/// health probe (or a call-spec round trip, without one), the pool is
+/// dropped, or it is shut down.
+fn recover<D: Device>(
+    breaker: &CircuitBreaker<D>,
+    replica: &Weak<Replica<D>>,
+    probe: &Mutex<Option<Vec<DeviceTensor<D>>>>,
+) {
+    let mut wait = breaker.cooldown;
+    loop {
+        std::thread::sleep(wait);
+        wait = (wait * 2).min(breaker.max_cooldown);
+        let Some(replica) = replica.upgrade() else {
+            return;
+        };
+        let mut model = replica.model.lock().unwrap_or_else(PoisonError::into_inner);
+        if model.is_none() {
+            return;
+        }
+        if let Some(rebuild) = &breaker.rebuild {
+            match rebuild(replica.index) {
+                Ok(rebuilt) => {
+                    *model = Some(rebuilt);
+                    *replica
+                        .adapter
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner) = Loaded::Base;
+                }
+                Err(_) => continue,
+            }
+        }
+        let Some(model) = model.as_mut() else {
+            return;
+        };
+        let probe = probe.lock().unwrap_or_else(PoisonError::into_inner);
+        let healthy = match probe.as_deref() {
+            Some(inputs) => model.run(inputs).is_ok(),
+            None => model.get_call_spec().is_ok(),
+        };
+        if healthy {
+            replica.failures.store(0, Ordering::Relaxed);
+            replica.quarantined.store(false, Ordering::Release);
+            return;
+        }
+    }
+}
+
+/// Run on the CPU fallback pool, moving outputs back to `device`.
+pub(super) fn run_on_fallback<D: Device>(
+    fallback: &AOTIModelPool<Cpu>,
+    inputs: &[DeviceTensor<D>],
+    device: tch::Device,
+    deadline: Option<Instant>,
+) -> Result<Vec<DeviceTensor<D>>, Error> {
+    let inputs: Vec<DeviceTensor<Cpu>> = inputs
+        .iter()
+        .map(|t| DeviceTensor {
+            tensor: t.to_device(tch::Device::Cpu),
+            _device: PhantomData,
+        })
+        .collect();
+    let outputs = match deadline {
+        Some(deadline) => fallback.run_before(deadline, &inputs)?,
+        None => fallback.run(&inputs)?,
+    };
+    Ok(outputs
+        .into_iter()
+        .map(|t| DeviceTensor {
+            tensor: t.to_device(device),
+            _device: PhantomData,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ffi_error() -> Result<(), Error> {
+        Err(Error::Model("CUDA error: an illegal memory access".into()))
+    }
+
+    #[test]
+    fn consecutive_failures_trip_once() {
+        let replica = Replica::<Cpu>::new(0, None);
+        assert!(!replica.record(&ffi_error(), 3));
+        assert!(!replica.record(&ffi_error(), 3));
+        assert!(replica.record(&ffi_error(), 3));
+        assert!(replica.is_quarantined());
+        assert!(!replica.record(&ffi_error(), 3));
+    }
+
+    #[test]
+    fn successes_and_caller_errors_do_not_trip() {
+        let replica = Replica::<Cpu>::new(0, None);
+        assert!(!replica.record(&ffi_error(), 2));
+        assert!(!replica.record(&Ok(()), 2));
+        assert!(!replica.record(&ffi_error(), 2));
+        let invalid: Result<(), Error> = Err(Error::InvalidInput("rank".into()));
+        assert!(!replica.record(&invalid, 2));
+        assert!(!replica.is_quarantined());
+    }
+}
@@ -0,0 +1,520 @@
+//! Online monitoring of output statistics against a recorded baseline.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, PoisonError};
+
+use serde_json::{Value, json};
+use tch::{Kind, Tensor};
+
+use crate::{Device, DeviceTensor, Error};
+
+/// Count, mean, standard deviation and range of an output's finite
+/// elements over many runs, plus how many elements were NaN or infinite.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OutputStats {
+    pub count: u64,
+    pub mean: f64,
+    /// Sum of squared differences from the mean (Welford's `M2`).
+    m2: f64,
+    pub min: f64,
+    pub max: f64,
+    pub non_finite: u64,
+}
+
+impl Default for OutputStats {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            mean: 0.0,
+            m2: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            non_finite: 0,
+        }
+    }
+}
+
+impl OutputStats {
+    /// The population standard deviation.
+    pub fn std(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            (self.m2 / self.count as f64).sqrt()
+        }
+    }
+
+    fn of(tensor: &Tensor) -> Result<Self, Error> {
+        let values = tensor.f_to_kind(Kind::Double)?;
+        let finite = values.f_masked_select(&values.f_isfinite()?)?;
+        let count = finite.numel() as u64;
+        let non_finite = values.numel() as u64 - count;
+        if count == 0 {
+            return Ok(Self {
+                non_finite,
+                ..Self::default()
+            });
+        }
+        let mean = finite.f_mean(Kind::Double)?.f_double_value(&[])?;
+        let m2 = finite
+            .f_sub_scalar(mean)?
+            .f_square()?
+            .f_sum(Kind::Double)?
+            .f_double_value(&[])?;
+        Ok(Self {
+            count,
+            mean,
+            m2,
+            min: finite.f_min()?.f_double_value(&[])?,
+            max: finite.f_max()?.f_double_value(&[])?,
+            non_finite,
+        })
+    }
+
+    /// Fold `other` in (Chan et al.'s parallel update).
+    fn merge(&mut self, other: &Self) {
+        self.non_finite += other.non_finite;
+        if other.count == 0 {
+            return;
+        }
+        let count = self.count + other.count;
+        let delta = other.mean - self.mean;
+        self.mean += delta * other.count as f64 / count as f64;
+        self.m2 += other.m2 + delta * delta * (self.count * other.count) as f64 / count as f64;
+        self.count = count;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+
+    fn to_json(self) -> Value {
+        json!({
+            "count": self.count,
+            "mean": self.mean,
+            "std": self.std(),
+            "min": self.min,
+            "max": self.max,
+            "non_finite": self.non_finite,
+        })
+    }
+
+    fn from_json(value: &Value) -> Option<Self> {
+        let count = value["count"].as_u64()?;
+        let std = value["std"].as_f64()?;
+        Some(Self {
+            count,
+            mean: value["mean"].as_f64()?,
+            m2: std * std * count as f64,
+            // Empty stats serialize their infinite range as null.
+            min: value["min"].as_f64().unwrap_or(f64::INFINITY),
+            max: value["max"].as_f64().unwrap_or(f64::NEG_INFINITY),
+            non_finite: value["non_finite"].as_u64()?,
+        })
+    }
+}
+
+/// [`OutputStats`] per output index, e.g. recorded over a validation set
+/// as the baseline for a [`DriftMonitor`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DriftProfile {
+    pub outputs: BTreeMap<usize, OutputStats>,
+    /// Runs observed.
+    pub runs: u64,
+}
+
+impl DriftProfile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add one run's outputs to the statistics.
+    pub fn observe<D: Device>(&mut self, outputs: &[DeviceTensor<D>]) -> Result<(), Error> {
+        let stats = outputs
+            .iter()
+            .map(|t| OutputStats::of(t))
+            .collect::<Result<Vec<_>, Error>>()?;
+        for (i, stats) in stats.iter().enumerate() {
+            self.outputs.entry(i).or_default().merge(stats);
+        }
+        self.runs += 1;
+        Ok(())
+    }
+
+    /// Write the profile as JSON.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let outputs: serde_json::Map<String, Value> = self
+            .outputs
+            .iter()
+            .map(|(i, stats)| (i.to_string(), stats.to_json()))
+            .collect();
+        let json = json!({"version": 1, "runs": self.runs, "outputs": outputs});
+        std::fs::write(path, serde_json::to_vec_pretty(&json)?)?;
+        Ok(())
+    }
+
+    /// Read a profile written by [`DriftProfile::save`].
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let json: Value = serde_json::from_slice(&std::fs::read(path)?)?;
+        let invalid = || Error::InvalidInput(format!("{} is not a drift profile", path.display()));
+        if json["version"] != 1 {
+            return Err(invalid());
+        }
+        let outputs = json["outputs"]
+            .as_object()
+            .ok_or_else(invalid)?
+            .iter()
+            .map(|(i, stats)| Some((i.parse().ok()?, OutputStats::from_json(stats)?)))
+            .collect::<Option<_>>()
+            .ok_or_else(invalid)?;
+        Ok(Self {
+            outputs,
+            runs: json["runs"].as_u64().ok_or_else(invalid)?,
+        })
+    }
+}
+
+/// How an output's current statistics depart from the baseline.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DriftReason {
+    /// The mean moved by more than the allowed number of baseline standard
+    /// deviations.
+    Mean { sigmas: f64 },
+    /// The standard deviation grew or shrank by more than the allowed
+    /// factor.
+    Std { ratio: f64 },
+    /// Values fell outside the baseline's range, widened by the allowed
+    /// margin.
+    Range,
+    /// NaNs or infinities appeared where the baseline had none.
+    NonFinite,
+}
+
+/// An output that started drifting, as passed to the
+/// [`DriftMonitor::on_drift`] hook.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DriftAlert {
+    pub output: usize,
+    pub reasons: Vec<DriftReason>,
+    pub baseline: OutputStats,
+    pub current: OutputStats,
+}
+
+impl fmt::Display for DriftAlert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (b, c) = (&self.baseline, &self.current);
+        write!(
+            f,
+            "output {} drifted ({:?}): mean {:e} (baseline {:e}), std {:e} ({:e}), \
+             range [{:e}, {:e}] ([{:e}, {:e}]), {} non-finite",
+            self.output,
+            self.reasons,
+            c.mean,
+            b.mean,
+            c.std(),
+            b.std(),
+            c.min,
+            c.max,
+            b.min,
+            b.max,
+            c.non_finite
+        )
+    }
+}
+
+type Hook = Box<dyn Fn(&DriftAlert) + Send + Sync>;
+
+/// Tracks running statistics of a model's outputs in production and
+/// raises an alert when they depart from a [`DriftProfile`] recorded from
+/// known-good runs, catching silent weight or package mismatches that
+/// don't make runs fail. Attach it to a pool with
+/// [`AOTIModelPool::with_drift_monitor`](crate::AOTIModelPool::with_drift_monitor)
+/// or feed it outputs with [`DriftMonitor::observe`].
+///
+/// Statistics accumulate over a window of runs (default 1000) and are
+/// checked after every run once [`min_runs`](Self::min_runs) have been
+/// seen; a full window starts over. An output's alert fires when it
+/// starts drifting, and again only after a clean window.
+pub struct DriftMonitor {
+    baseline: DriftProfile,
+    outputs: Option<Vec<usize>>,
+    max_mean_shift: f64,
+    max_std_ratio: f64,
+    range_margin: f64,
+    min_runs: u64,
+    window: u64,
+    hook: Option<Hook>,
+    state: Mutex<Window>,
+    alerts: AtomicU64,
+    errors: AtomicU64,
+}
+
+#[derive(Default)]
+struct Window {
+    current: DriftProfile,
+    drifting: BTreeMap<usize, bool>,
+}
+
+impl DriftMonitor {
+    /// Watch every output in `baseline`, alerting when a mean shifts by
+    /// more than 3 baseline standard deviations, a standard deviation
+    /// changes by more than 2x, values leave the baseline range widened by
+    /// 10% on each side, or non-finite values appear.
+    pub fn new(baseline: DriftProfile) -> Self {
+        Self {
+            baseline,
+            outputs: None,
+            max_mean_shift: 3.0,
+            max_std_ratio: 2.0,
+            range_margin: 0.1,
+            min_runs: 10,
+            window: 1000,
+            hook: None,
+            state: Mutex::new(Window::default()),
+            alerts: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+        }
+    }
+
+    /// Only watch these outputs.
+    pub fn outputs(mut self, outputs: &[usize]) -> Self {
+        self.outputs = Some(outputs.to_vec());
+        self
+    }
+
+    /// Alert when a mean moves by more than `sigmas` baseline standard
+    /// deviations.
+    pub fn max_mean_shift(mut self, sigmas: f64) -> Self {
+        self.max_mean_shift = sigmas;
+        self
+    }
+
+    /// Alert when a standard deviation grows or shrinks by more than
+    /// `ratio`.
+    pub fn max_std_ratio(mut self, ratio: f64) -> Self {
+        self.max_std_ratio = ratio;
+        self
+    }
+
+    /// Alert on values outside the baseline range widened by `margin`
+    /// times its width on each side.
+    pub fn range_margin(mut self, margin: f64) -> Self {
+        self.range_margin = margin;
+        self
+    }
+
+    /// Runs to see in a window before checking it.
+    pub fn min_runs(mut self, runs: u64) -> Self {
+        self.min_runs = runs;
+        self
+    }
+
+    /// Runs per window.
+    pub fn window(mut self, runs: u64) -> Self {
+        self.window = runs.max(1);
+        self
+    }
+
+    /// Call `hook` with each alert, e.g. to log it or bump a metric. It
+    /// runs on the thread that made the run, after it finished.
+    pub fn on_drift(mut self, hook: impl Fn(&DriftAlert) + Send + Sync + 'static) -> Self {
+        self.hook = Some(Box::new(hook));
+        self
+    }
+
+    /// Alerts raised so far.
+    pub fn alerts(&self) -> u64 {
+        self.alerts.load(Ordering::Relaxed)
+    }
+
+    /// Runs whose outputs couldn't be summarized, e.g. because an output
+    /// the baseline has was missing.
+    pub fn errors(&self) -> u64 {
+        self.errors.load(Ordering::Relaxed)
+    }
+
+    /// The current window's statistics.
+    pub fn current(&self) -> DriftProfile {
+        self.lock().current.clone()
+    }
+
+    /// Whether `output` is drifting as of the last check.
+    pub fn is_drifting(&self, output: usize) -> bool {
+        self.lock().drifting.get(&output).copied().unwrap_or(false)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Window> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Add one run's outputs and check for drift, calling the hook with any
+    /// new alerts. Summarizing the outputs synchronizes with their device.
+    pub fn observe<D: Device>(
+        &self,
+        outputs: &[DeviceTensor<D>],
+    ) -> Result<Vec<DriftAlert>, Error> {
+        let watched: Vec<usize> = match &self.outputs {
+            Some(outputs) => outputs.clone(),
+            None => self.baseline.outputs.keys().copied().collect(),
+        };
+        let stats = watched
+            .iter()
+            .map(|&i| {
+                let output = outputs.get(i).ok_or_else(|| {
+                    Error::InvalidInput(format!("the run has no output {i} to monitor"))
+                })?;
+                Ok((i, OutputStats::of(output)?))
+            })
+            .collect::<Result<Vec<_>, Error>>()
+            .inspect_err(|_| {
+                self.errors.fetch_add(1, Ordering::Relaxed);
+            })?;
+
+        let mut alerts = Vec::new();
+        {
+            let mut window = self.lock();
+            for (i, stats) in &stats {
+                window.current.outputs.entry(*i).or_default().merge(stats);
+            }
+            window.current.runs += 1;
+            if window.current.runs >= self.min_runs {
+                for &i in &watched {
+                    let (Some(baseline), Some(current)) = (
+                        self.baseline.outputs.get(&i),
+                        window.current.outputs.get(&i).copied(),
+                    ) else {
+                        continue;
+                    };
+                    let reasons = self.reasons(baseline, &current);
+                    let was_drifting = window.drifting.insert(i, !reasons.is_empty());
+                    if !reasons.is_empty() && was_drifting != Some(true) {
+                        alerts.push(DriftAlert {
+                            output: i,
+                            reasons,
+                            baseline: *baseline,
+                            current,
+                        });
+                    }
+                }
+            }
+            if window.current.runs >= self.window {
+                window.current = DriftProfile::new();
+            }
+        }
+        self.alerts
+            .fetch_add(alerts.len() as u64, Ordering::Relaxed);
+        if let Some(hook) = &self.hook {
+            alerts.iter().for_each(hook);
+        }
+        Ok(alerts)
+    }
+
+    fn reasons(&self, baseline: &OutputStats, current: &OutputStats) -> Vec<DriftReason> {
+        let mut reasons = Vec::new();
+        if current.non_finite > 0 && baseline.non_finite == 0 {
+            reasons.push(DriftReason::NonFinite);
+        }
+        if current.count == 0 || baseline.count == 0 {
+            return reasons;
+        }
+        let (b_std, c_std) = (baseline.std(), current.std());
+        let shift = (current.mean - baseline.mean).abs();
+        if shift > 0.0 {
+            let sigmas = shift / b_std;
+            if sigmas > self.max_mean_shift {
+                reasons.push(DriftReason::Mean { sigmas });
+            }
+        }
+        if b_std > 0.0 {
+            let ratio = c_std / b_std;
+            if ratio > self.max_std_ratio || ratio * self.max_std_ratio < 1.0 {
+                reasons.push(DriftReason::Std { ratio });
+            }
+        } else if c_std > 0.0 {
+            reasons.push(DriftReason::Std {
+                ratio: f64::INFINITY,
+            });
+        }
+        let margin = (baseline.max - baseline.min) * self.range_margin;
+        if current.min < baseline.min - margin || current.max > baseline.max + margin {
+            reasons.push(DriftReason::Range);
+        }
+        reasons
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::marker::PhantomData;
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::Cpu;
+
+    fn outputs(values: &[f32]) -> Vec<DeviceTensor<Cpu>> {
+        vec![DeviceTensor {
+            tensor: Tensor::from_slice(values),
+            _device: PhantomData,
+        }]
+    }
+
+    #[test]
+    fn profiles_merge_runs_and_round_trip() {
+        let mut profile = DriftProfile::new();
+        profile.observe(&outputs(&[1.0, 2.0])).unwrap();
+        profile.observe(&outputs(&[3.0, 4.0, f32::NAN])).unwrap();
+        let stats = profile.outputs[&0];
+        assert_eq!((stats.count, stats.non_finite, profile.runs), (4, 1, 2));
+        assert_eq!((stats.mean, stats.min, stats.max), (2.5, 1.0, 4.0));
+        assert!((stats.std() - 1.25f64.sqrt()).abs() < 1e-12);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("profile.json");
+        profile.save(&path).unwrap();
+        let loaded = DriftProfile::load(&path).unwrap();
+        assert_eq!(loaded.runs, 2);
+        assert_eq!(loaded.outputs[&0].mean, 2.5);
+        assert!((loaded.outputs[&0].std() - stats.std()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn alerts_fire_once_when_outputs_drift() {
+        let mut baseline = DriftProfile::new();
+        baseline.observe(&outputs(&[-1.0, 0.0, 1.0])).unwrap();
+        let fired = Arc::new(AtomicU64::new(0));
+        let monitor = DriftMonitor::new(baseline).min_runs(2).on_drift({
+            let fired = fired.clone();
+            move |_| {
+                fired.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+
+        assert!(
+            monitor
+                .observe(&outputs(&[-1.0, 0.0, 1.0]))
+                .unwrap()
+                .is_empty()
+        );
+        assert!(
+            monitor
+                .observe(&outputs(&[-0.9, 0.1, 0.9]))
+                .unwrap()
+                .is_empty()
+        );
+        assert!(!monitor.is_drifting(0));
+
+        let alerts = monitor.observe(&outputs(&[30.0, 30.0, f32::NAN])).unwrap();
+        assert_eq!(alerts.len(), 1);
+        assert!(alerts[0].reasons.contains(&DriftReason::Range));
+        assert!(alerts[0].reasons.contains(&DriftReason::NonFinite));
+        assert!(monitor.is_drifting(0));
+        // Still drifting: no second alert.
+        assert!(monitor.observe(&outputs(&[30.0])).unwrap().is_empty());
+        assert_eq!((monitor.alerts(), fired.load(Ordering::Relaxed)), (1, 1));
+
+        assert!(monitor.observe::<Cpu>(&[]).is_err());
+        assert_eq!(monitor.errors(), 1);
+    }
+}
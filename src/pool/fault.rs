@@ -0,0 +1,186 @@
+//! Synthetic failures injected at the replica layer (feature
+//! `fault-injection`), for testing how a service handles a pool in
+//! trouble: retries, circuit breaking, timeouts and failover.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use crate::Error;
+
+/// What an injected fault does to a run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fault {
+    /// Hold the replica this long before running, like a stalled device;
+    /// the run then goes ahead. Exercises deadlines, health-check
+    /// timeouts and queueing behind a slow replica.
+    Hang(Duration),
+    /// Fail with [`Error::DeadlineExceeded`], as if the request's deadline
+    /// passed while it waited.
+    Timeout,
+    /// Fail with a CUDA allocation error, classed as
+    /// [`ErrorClass::OutOfMemory`](crate::ErrorClass::OutOfMemory).
+    OutOfMemory,
+    /// Fail with [`Error::Model`] and this message, classed as
+    /// [`ErrorClass::Runtime`](crate::ErrorClass::Runtime).
+    Runtime(String),
+}
+
+/// Faults injected into an [`AOTIModelPool`](crate::AOTIModelPool)'s runs;
+/// see [`AOTIModelPool::with_fault_injector`](crate::AOTIModelPool::with_fault_injector).
+/// Clones share their faults, so a test can keep one to change them while
+/// the pool serves:
+///
+/// ```ignore
+/// let faults = FaultInjector::default();
+/// let pool = AOTIModelPool::new(replicas)?
+///     .with_circuit_breaker(CircuitBreaker::new(3))
+///     .with_fault_injector(faults.clone());
+/// faults.fail_runs(2, Fault::OutOfMemory);
+/// faults.poison(1);
+/// ```
+///
+/// Faults apply once a run holds a replica, before it reaches the runtime,
+/// so the circuit breaker counts injected failures as the replica's.
+/// Recovery probes and [`AOTIModelPool::with_replica`](crate::AOTIModelPool::with_replica)
+/// calls go around the injector: a poisoned replica passes its probe after
+/// the breaker's cooldown and is quarantined again once it has failed
+/// enough runs, until it is healed.
+#[derive(Debug, Clone, Default)]
+pub struct FaultInjector(Arc<Mutex<InjectorState>>);
+
+#[derive(Debug, Default)]
+struct InjectorState {
+    failing_runs: usize,
+    run_fault: Option<Fault>,
+    replicas: BTreeMap<usize, Fault>,
+    injected: usize,
+}
+
+impl FaultInjector {
+    /// Inject `fault` into the next `n` runs, on whichever replicas they
+    /// get.
+    pub fn fail_runs(&self, n: usize, fault: Fault) {
+        let mut state = self.lock();
+        state.failing_runs = n;
+        state.run_fault = Some(fault);
+    }
+
+    /// Inject `fault` into every run on replica `index` until it is
+    /// [healed](FaultInjector::heal). Takes precedence over
+    /// [`FaultInjector::fail_runs`], whose count it leaves alone.
+    pub fn fail_replica(&self, index: usize, fault: Fault) {
+        self.lock().replicas.insert(index, fault);
+    }
+
+    /// Fail every run on replica `index` with a sticky CUDA error until it
+    /// is healed.
+    pub fn poison(&self, index: usize) {
+        self.fail_replica(
+            index,
+            Fault::Runtime("CUDA error: an illegal memory access was encountered".into()),
+        );
+    }
+
+    /// Stop injecting faults into replica `index`.
+    pub fn heal(&self, index: usize) {
+        self.lock().replicas.remove(&index);
+    }
+
+    /// Stop injecting faults altogether.
+    pub fn clear(&self) {
+        let mut state = self.lock();
+        state.failing_runs = 0;
+        state.run_fault = None;
+        state.replicas.clear();
+    }
+
+    /// Faults injected so far, hangs included.
+    pub fn injected(&self) -> usize {
+        self.lock().injected
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, InjectorState> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Apply the fault due for a run on replica `index`, if any.
+    pub(super) fn before_run(&self, index: usize) -> Result<(), Error> {
+        let fault = {
+            let mut state = self.lock();
+            let fault = match state.replicas.get(&index) {
+                Some(fault) => Some(fault.clone()),
+                None if state.failing_runs > 0 => {
+                    state.failing_runs -= 1;
+                    state.run_fault.clone()
+                }
+                None => None,
+            };
+            state.injected += usize::from(fault.is_some());
+            fault
+        };
+        match fault {
+            None => Ok(()),
+            Some(Fault::Hang(duration)) => {
+                std::thread::sleep(duration);
+                Ok(())
+            }
+            Some(Fault::Timeout) => Err(Error::DeadlineExceeded),
+            Some(Fault::OutOfMemory) => {
+                Err(Error::Model("CUDA out of memory (injected fault)".into()))
+            }
+            Some(Fault::Runtime(message)) => Err(Error::Model(message)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ErrorClass;
+
+    #[test]
+    fn run_faults_are_used_up_in_order() {
+        let faults = FaultInjector::default();
+        faults.fail_runs(2, Fault::OutOfMemory);
+        let err = faults.before_run(0).unwrap_err();
+        assert_eq!(ErrorClass::of(&err), ErrorClass::OutOfMemory);
+        assert!(faults.before_run(1).is_err());
+        assert!(faults.before_run(0).is_ok());
+        assert_eq!(faults.injected(), 2);
+
+        faults.fail_runs(1, Fault::Timeout);
+        assert!(matches!(faults.before_run(0), Err(Error::DeadlineExceeded)));
+        faults.fail_runs(1, Fault::Hang(Duration::from_millis(10)));
+        let started = std::time::Instant::now();
+        assert!(faults.before_run(0).is_ok());
+        assert!(started.elapsed() >= Duration::from_millis(10));
+        assert_eq!(faults.injected(), 4);
+    }
+
+    #[test]
+    fn poisoned_replicas_fail_until_healed() {
+        let faults = FaultInjector::default();
+        faults.poison(1);
+        faults.fail_runs(1, Fault::Runtime("kernel failed".into()));
+        for _ in 0..3 {
+            let err = faults.before_run(1).unwrap_err();
+            assert_eq!(ErrorClass::of(&err), ErrorClass::Runtime);
+            assert!(err.to_string().contains("illegal memory access"));
+        }
+        // The run fault is still pending for other replicas.
+        assert!(
+            faults
+                .before_run(0)
+                .unwrap_err()
+                .to_string()
+                .contains("kernel failed")
+        );
+        faults.heal(1);
+        assert!(faults.before_run(1).is_ok());
+
+        faults.poison(0);
+        faults.clear();
+        assert!(faults.before_run(0).is_ok());
+    }
+}
@@ -0,0 +1,217 @@
+//! JSON-lines audit log of pool runs.
+
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde_json::{Value, json};
+use tch::Tensor;
+
+use crate::{Device, DeviceTensor, Error, RequestId};
+
+/// Appends one JSON object per run to a file or writer, e.g. to debug a
+/// production issue or collect an offline evaluation set. Attach it to
+/// pools with [`AOTIModelPool::with_run_log`](crate::AOTIModelPool::with_run_log):
+///
+/// ```text
+/// {"timestamp":"2026-01-02T03:04:05.678Z","model":"ranker","latency_us":812,
+///  "inputs":[{"dtype":"Float","shape":[1,16],"fnv1a64":"9c3f…"}],
+///  "outcome":"ok","outputs":[{"dtype":"Float","shape":[1,4]}]}
+/// ```
+///
+/// Failed runs have `"outcome":"error"` and an `"error"` message instead of
+/// `"outputs"`. Runs made while a [`RequestId`] is scoped carry it as
+/// `"request_id"`. Latency includes waiting for a replica. Logging never
+/// fails a run: write errors are counted in
+/// [`RunLog::write_errors`] and otherwise ignored.
+pub struct RunLog {
+    out: Mutex<Box<dyn Write + Send>>,
+    hash_inputs: bool,
+    write_errors: AtomicU64,
+}
+
+impl RunLog {
+    /// Log to `out`, flushing after every line.
+    pub fn to_writer(out: impl Write + Send + 'static) -> Self {
+        Self {
+            out: Mutex::new(Box::new(out)),
+            hash_inputs: false,
+            write_errors: AtomicU64::new(0),
+        }
+    }
+
+    /// Append to the file at `path`, creating it if needed.
+    pub fn append_to(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self::to_writer(file))
+    }
+
+    /// Add an FNV-1a hash of each input's bytes, so identical requests can
+    /// be spotted. Inputs not on the CPU are copied there to be hashed.
+    pub fn hash_inputs(mut self, hash: bool) -> Self {
+        self.hash_inputs = hash;
+        self
+    }
+
+    /// Lines that couldn't be written.
+    pub fn write_errors(&self) -> u64 {
+        self.write_errors.load(Ordering::Relaxed)
+    }
+
+    /// Describe `inputs` before a run, which may consume them.
+    pub(super) fn describe_inputs<D: Device>(&self, inputs: &[DeviceTensor<D>]) -> Value {
+        inputs
+            .iter()
+            .map(|t| {
+                let mut entry = describe(t);
+                if self.hash_inputs {
+                    entry["fnv1a64"] = match fnv1a64(t) {
+                        Ok(hash) => json!(format!("{hash:016x}")),
+                        Err(_) => Value::Null,
+                    };
+                }
+                entry
+            })
+            .collect()
+    }
+
+    /// Append the line for one finished run.
+    pub(super) fn record<D: Device>(
+        &self,
+        model: &str,
+        inputs: Value,
+        latency: Duration,
+        result: &Result<Vec<DeviceTensor<D>>, Error>,
+    ) {
+        let mut line = json!({
+            "timestamp": rfc3339(SystemTime::now()),
+            "model": model,
+            "latency_us": latency.as_micros() as u64,
+            "inputs": inputs,
+        });
+        if let Some(id) = RequestId::current() {
+            line["request_id"] = json!(id.as_str());
+        }
+        match result {
+            Ok(outputs) => {
+                line["outcome"] = json!("ok");
+                line["outputs"] = outputs.iter().map(|t| describe(t)).collect();
+            }
+            Err(err) => {
+                line["outcome"] = json!("error");
+                line["error"] = json!(err.to_string());
+            }
+        }
+        let mut out = self.out.lock().unwrap_or_else(PoisonError::into_inner);
+        let written = serde_json::to_writer(&mut *out, &line)
+            .map_err(std::io::Error::from)
+            .and_then(|()| out.write_all(b"\n"))
+            .and_then(|()| out.flush());
+        if written.is_err() {
+            self.write_errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+fn describe(tensor: &Tensor) -> Value {
+    json!({
+        "dtype": format!("{:?}", tensor.kind()),
+        "shape": tensor.size(),
+    })
+}
+
+/// FNV-1a over a tensor's bytes in row-major order; stable across runs and
+/// platforms, unlike `std`'s hasher.
+fn fnv1a64(tensor: &Tensor) -> Result<u64, Error> {
+    let host = tensor.f_to_device(tch::Device::Cpu)?.f_contiguous()?;
+    let numel = host.numel();
+    let mut bytes = vec![0u8; numel * host.kind().elt_size_in_bytes()];
+    host.f_copy_data_u8(&mut bytes, numel)?;
+    Ok(bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    }))
+}
+
+/// `time` as an RFC 3339 UTC timestamp with millisecond precision.
+fn rfc3339(time: SystemTime) -> String {
+    let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since.as_secs();
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+    // Days since 1970-01-01 to a civil date (Howard Hinnant's algorithm).
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        rem / 3_600,
+        rem / 60 % 60,
+        rem % 60,
+        since.subsec_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Cpu;
+    use std::sync::Arc;
+
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn runs_are_logged_one_json_object_per_line() {
+        let out = Shared::default();
+        let log = RunLog::to_writer(out.clone()).hash_inputs(true);
+        let inputs =
+            DeviceTensor::<Cpu>::try_new_all(vec![Tensor::from_slice(&[1u8, 2]).view([1, 2])])
+                .unwrap();
+        let described = log.describe_inputs(&inputs);
+        log.record(
+            "m",
+            described.clone(),
+            Duration::from_micros(42),
+            &Ok(inputs),
+        );
+        log.record::<Cpu>("m", described, Duration::ZERO, &Err(Error::ShutDown));
+        let text = String::from_utf8(out.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<Value> = text
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["latency_us"], 42);
+        assert_eq!(lines[0]["inputs"][0]["shape"], json!([1, 2]));
+        // FNV-1a of the bytes [1, 2].
+        assert_eq!(lines[0]["inputs"][0]["fnv1a64"], "082f2407b4e8902a");
+        assert_eq!(lines[0]["outputs"][0]["dtype"], "Uint8");
+        assert_eq!(lines[1]["outcome"], "error");
+        assert_eq!(lines[1]["error"], "model pool is shut down");
+    }
+
+    #[test]
+    fn timestamps_are_rfc3339_utc() {
+        let t = UNIX_EPOCH + Duration::from_millis(1_709_210_096_789);
+        assert_eq!(rfc3339(t), "2024-02-29T12:34:56.789Z");
+        assert_eq!(rfc3339(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+    }
+}
@@ -0,0 +1,401 @@
+//! Multi-LoRA serving: registered adapters' merged weights are swapped into
+//! a replica's constants as the runs it gets ask for them.
+
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use tch::{Kind, Tensor};
+
+use super::{AOTIModelPool, Replica};
+use crate::{AOTIModel, Device, DeviceTensor, Error, InputMode};
+
+/// A LoRA adapter: low-rank updates `scale * B @ A` to some of a model's
+/// linear weights (`[out, in]` constants).
+#[derive(Debug)]
+pub struct LoraAdapter {
+    scale: f64,
+    /// `A` (`[rank, in]`) and `B` (`[out, rank]`) by constant name.
+    targets: HashMap<String, [Tensor; 2]>,
+}
+
+impl LoraAdapter {
+    /// An adapter with no targets yet, scaling its updates by `scale`
+    /// (usually `alpha / rank`).
+    pub fn new(scale: f64) -> Self {
+        Self {
+            scale,
+            targets: HashMap::new(),
+        }
+    }
+
+    /// Update the constant with fully qualified name `constant` (as
+    /// [`AOTIModel::constants`] reports it) by `scale * b @ a`.
+    pub fn target(mut self, constant: impl Into<String>, a: Tensor, b: Tensor) -> Self {
+        self.targets.insert(constant.into(), [a, b]);
+        self
+    }
+}
+
+/// A registered adapter's merged weights, behind a lock as tensors can't
+/// be shared between threads.
+pub(super) struct Adapter<D: Device> {
+    merged: Mutex<HashMap<String, DeviceTensor<D>>>,
+}
+
+/// The pool's registered adapters and the base value of every constant
+/// any of them has targeted.
+pub(super) struct Adapters<D: Device> {
+    base: HashMap<String, DeviceTensor<D>>,
+    registered: HashMap<String, Arc<Adapter<D>>>,
+}
+
+impl<D: Device> Adapters<D> {
+    pub(super) fn new() -> Self {
+        Self {
+            base: HashMap::new(),
+            registered: HashMap::new(),
+        }
+    }
+}
+
+/// The weights a replica's active constants hold.
+pub(super) enum Loaded<D: Device> {
+    Base,
+    Adapter(Arc<Adapter<D>>),
+    /// A swap failed part way.
+    Unknown,
+}
+
+impl<D: Device> Loaded<D> {
+    fn is(&self, wanted: Option<&Arc<Adapter<D>>>) -> bool {
+        match (self, wanted) {
+            (Loaded::Base, None) => true,
+            (Loaded::Adapter(loaded), Some(wanted)) => Arc::ptr_eq(loaded, wanted),
+            _ => false,
+        }
+    }
+}
+
+impl<D: Device> AOTIModelPool<D> {
+    /// Register `adapter` under `name` for [`AOTIModelPool::run_with_adapter`],
+    /// replacing any adapter of that name. Its merged weights are computed
+    /// now and kept on the pool's device, alongside a copy of the base
+    /// weights it targets.
+    ///
+    /// Once any adapter is registered, every run selects its weights: plain
+    /// runs get the base model. Switching a replica's weights stages them
+    /// in its inactive constant buffer, which doubles the model's constant
+    /// memory, so runs are steered to replicas that already hold theirs.
+    /// [`AOTIModelPool::with_replica`] gets whichever weights its replica
+    /// holds.
+    pub fn register_adapter(
+        &self,
+        name: impl Into<String>,
+        adapter: LoraAdapter,
+    ) -> Result<(), Error> {
+        let missing: Vec<String> = {
+            let adapters = self.adapters.lock().unwrap_or_else(PoisonError::into_inner);
+            adapter
+                .targets
+                .keys()
+                .filter(|constant| !adapters.base.contains_key(*constant))
+                .cloned()
+                .collect()
+        };
+        // No registered adapter targets these, so any replica holds their
+        // base values.
+        let mut base = HashMap::new();
+        if !missing.is_empty() {
+            let mut constants = self.with_replica(AOTIModel::constant_tensors)??;
+            for constant in missing {
+                let tensor = constants.remove(&constant).ok_or_else(|| {
+                    Error::InvalidInput(format!("the model has no constant named {constant}"))
+                })?;
+                base.insert(constant, wrap(tensor.copy()));
+            }
+        }
+        let mut adapters = self.adapters.lock().unwrap_or_else(PoisonError::into_inner);
+        for (constant, tensor) in base {
+            adapters.base.entry(constant).or_insert(tensor);
+        }
+        let merged = adapter
+            .targets
+            .iter()
+            .map(|(constant, update)| {
+                let weight = merge(&adapters.base[constant], update, adapter.scale)?;
+                Ok((constant.clone(), wrap(weight)))
+            })
+            .collect::<Result<_, Error>>()?;
+        adapters.registered.insert(
+            name.into(),
+            Arc::new(Adapter {
+                merged: Mutex::new(merged),
+            }),
+        );
+        Ok(())
+    }
+
+    /// Unregister an adapter, returning whether it was registered. Replicas
+    /// holding its weights keep them until their next run.
+    pub fn unregister_adapter(&self, name: &str) -> bool {
+        self.adapters
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .registered
+            .remove(name)
+            .is_some()
+    }
+
+    /// Names of the registered adapters, sorted.
+    pub fn adapters(&self) -> Vec<String> {
+        let adapters = self.adapters.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut names: Vec<String> = adapters.registered.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// [`AOTIModelPool::run`] with a registered adapter's weights, or the
+    /// base model's with `None`. Fails with [`Error::InvalidInput`] for an
+    /// unknown adapter. Runs with an adapter never go to a circuit
+    /// breaker's fallback pool, which only has the base weights.
+    pub fn run_with_adapter(
+        &self,
+        adapter: Option<&str>,
+        inputs: &[DeviceTensor<D>],
+    ) -> Result<Vec<DeviceTensor<D>>, Error> {
+        let adapter = self.adapter(adapter)?;
+        self.dispatch(InputMode::Borrow(inputs), None, adapter.as_ref())
+    }
+
+    /// Run several requests, each with its own adapter (or none): requests
+    /// for the same adapter are batched into one run by concatenating each
+    /// input along dimension 0, so the package needs a dynamic batch
+    /// dimension, and each output is split back per request. Outputs come
+    /// back in request order; the first failing run fails the call.
+    pub fn run_grouped_by_adapter(
+        &self,
+        requests: &[(Option<&str>, Vec<DeviceTensor<D>>)],
+    ) -> Result<Vec<Vec<DeviceTensor<D>>>, Error> {
+        let mut outputs: Vec<Option<Vec<DeviceTensor<D>>>> = Vec::new();
+        outputs.resize_with(requests.len(), || None);
+        let names: Vec<Option<&str>> = requests.iter().map(|(name, _)| *name).collect();
+        for (name, members) in group(&names) {
+            let adapter = self.adapter(name)?;
+            let inputs: Vec<&[DeviceTensor<D>]> =
+                members.iter().map(|&i| &requests[i].1[..]).collect();
+            let (batched, sizes) = concat(&inputs)?;
+            let ran = self.dispatch(InputMode::Borrow(&batched), None, adapter.as_ref())?;
+            for (i, split) in members.into_iter().zip(split(ran, &sizes)?) {
+                outputs[i] = Some(split);
+            }
+        }
+        Ok(outputs.into_iter().flatten().collect())
+    }
+
+    fn adapter(&self, name: Option<&str>) -> Result<Option<Arc<Adapter<D>>>, Error> {
+        let Some(name) = name else {
+            return Ok(None);
+        };
+        let adapters = self.adapters.lock().unwrap_or_else(PoisonError::into_inner);
+        match adapters.registered.get(name) {
+            Some(adapter) => Ok(Some(adapter.clone())),
+            None => Err(Error::InvalidInput(format!(
+                "no LoRA adapter named {name} is registered"
+            ))),
+        }
+    }
+
+    /// Whether runs have to select weights: some adapter is registered or
+    /// was loaded.
+    pub(super) fn selects_adapters(&self, wanted: Option<&Arc<Adapter<D>>>) -> bool {
+        wanted.is_some()
+            || !self
+                .adapters
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .base
+                .is_empty()
+    }
+
+    /// An idle replica that already holds `wanted`'s weights, if any.
+    pub(super) fn idle_with(&self, wanted: Option<&Arc<Adapter<D>>>) -> Option<&Arc<Replica<D>>> {
+        self.replicas.iter().find(|replica| {
+            !replica.is_quarantined()
+                && replica
+                    .adapter
+                    .try_lock()
+                    .is_ok_and(|loaded| loaded.is(wanted))
+        })
+    }
+
+    /// Make `model`'s active constants hold `wanted`'s weights, or the base
+    /// model's.
+    pub(super) fn select_adapter(
+        &self,
+        replica: &Replica<D>,
+        model: &mut AOTIModel<D>,
+        wanted: Option<&Arc<Adapter<D>>>,
+    ) -> Result<(), Error> {
+        let mut loaded = replica
+            .adapter
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if loaded.is(wanted) {
+            return Ok(());
+        }
+        let mut constants: HashMap<String, DeviceTensor<D>> = self
+            .adapters
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .base
+            .iter()
+            .map(|(name, tensor)| (name.clone(), wrap(tensor.shallow_clone())))
+            .collect();
+        if let Some(adapter) = wanted {
+            let merged = adapter
+                .merged
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            for (name, tensor) in merged.iter() {
+                constants.insert(name.clone(), wrap(tensor.shallow_clone()));
+            }
+        }
+        *loaded = Loaded::Unknown;
+        model.update_inactive_constants(&constants)?;
+        model.swap_constants()?;
+        *loaded = match wanted {
+            Some(adapter) => Loaded::Adapter(adapter.clone()),
+            None => Loaded::Base,
+        };
+        Ok(())
+    }
+}
+
+fn wrap<D: Device>(tensor: Tensor) -> DeviceTensor<D> {
+    DeviceTensor {
+        tensor,
+        _device: PhantomData,
+    }
+}
+
+/// `weight + scale * b @ a`, computed in `Float` and returned in the
+/// weight's dtype.
+fn merge(weight: &Tensor, [a, b]: &[Tensor; 2], scale: f64) -> Result<Tensor, Error> {
+    let device = weight.device();
+    let a = a.f_to_device(device)?.f_to_kind(Kind::Float)?;
+    let update = b
+        .f_to_device(device)?
+        .f_to_kind(Kind::Float)?
+        .f_matmul(&a)?
+        .f_mul_scalar(scale)?;
+    if update.size() != weight.size() {
+        return Err(Error::InvalidInput(format!(
+            "a LoRA update of shape {:?} doesn't fit a weight of shape {:?}",
+            update.size(),
+            weight.size()
+        )));
+    }
+    Ok(weight
+        .f_to_kind(Kind::Float)?
+        .f_add(&update)?
+        .f_to_kind(weight.kind())?)
+}
+
+/// Request indices by adapter, adapters in order of first request.
+fn group<'a>(names: &[Option<&'a str>]) -> Vec<(Option<&'a str>, Vec<usize>)> {
+    let mut groups: Vec<(Option<&str>, Vec<usize>)> = Vec::new();
+    for (i, &name) in names.iter().enumerate() {
+        match groups.iter_mut().find(|(group, _)| *group == name) {
+            Some((_, members)) => members.push(i),
+            None => groups.push((name, vec![i])),
+        }
+    }
+    groups
+}
+
+/// Concatenate requests' inputs along dimension 0, returning each request's
+/// batch size.
+fn concat<D: Device>(
+    requests: &[&[DeviceTensor<D>]],
+) -> Result<(Vec<DeviceTensor<D>>, Vec<i64>), Error> {
+    let first = requests.first().copied().unwrap_or_default();
+    if requests.iter().any(|inputs| inputs.len() != first.len()) {
+        return Err(Error::InvalidInput(
+            "requests batched together must take the same number of inputs".into(),
+        ));
+    }
+    let sizes = requests
+        .iter()
+        .map(|inputs| {
+            inputs
+                .first()
+                .map_or(0, |t| t.size().first().copied().unwrap_or(1))
+        })
+        .collect();
+    let batched = (0..first.len())
+        .map(|i| {
+            let parts: Vec<&Tensor> = requests.iter().map(|inputs| &*inputs[i]).collect();
+            Ok(wrap(Tensor::f_cat(&parts, 0)?))
+        })
+        .collect::<Result<_, Error>>()?;
+    Ok((batched, sizes))
+}
+
+/// Split each output along dimension 0 into requests of `sizes`.
+fn split<D: Device>(
+    outputs: Vec<DeviceTensor<D>>,
+    sizes: &[i64],
+) -> Result<Vec<Vec<DeviceTensor<D>>>, Error> {
+    let mut split: Vec<Vec<DeviceTensor<D>>> = sizes.iter().map(|_| Vec::new()).collect();
+    for output in outputs {
+        for (request, part) in split.iter_mut().zip(output.f_split_with_sizes(sizes, 0)?) {
+            request.push(wrap(part));
+        }
+    }
+    Ok(split)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Cpu;
+
+    #[test]
+    fn updates_are_merged_into_the_base_weight() {
+        let weight = Tensor::from_slice(&[1.0f32, 0.0, 0.0, 1.0]).view([2, 2]);
+        let a = Tensor::from_slice(&[1.0f32, 2.0]).view([1, 2]);
+        let b = Tensor::from_slice(&[1.0f32, -1.0]).view([2, 1]);
+        let merged = merge(&weight, &[a.shallow_clone(), b], 0.5).unwrap();
+        let values = Vec::<f32>::try_from(&merged.flatten(0, -1)).unwrap();
+        assert_eq!(values, [1.5, 1.0, -0.5, 0.0]);
+        let wrong = Tensor::ones([3, 1], (Kind::Float, tch::Device::Cpu));
+        assert!(merge(&weight, &[a, wrong], 1.0).is_err());
+    }
+
+    #[test]
+    fn requests_are_batched_by_adapter() {
+        let groups = group(&[Some("fr"), None, Some("de"), Some("fr"), None]);
+        assert_eq!(
+            groups,
+            [
+                (Some("fr"), vec![0, 3]),
+                (None, vec![1, 4]),
+                (Some("de"), vec![2]),
+            ]
+        );
+
+        let request = |rows: i64| {
+            vec![wrap::<Cpu>(Tensor::zeros(
+                [rows, 3],
+                (Kind::Float, tch::Device::Cpu),
+            ))]
+        };
+        let (one, two) = (request(1), request(2));
+        let (batched, sizes) = concat(&[&one[..], &two[..]]).unwrap();
+        assert_eq!((batched[0].size(), sizes.clone()), (vec![3, 3], vec![1, 2]));
+        let split = split(batched, &sizes).unwrap();
+        assert_eq!(split[1][0].size(), [2, 3]);
+        assert!(concat(&[&one[..], &[]]).is_err());
+    }
+}
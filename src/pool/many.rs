@@ -0,0 +1,140 @@
+//! Runs over many input batches, with results in input order.
+
+use std::sync::{Mutex, PoisonError};
+
+use super::AOTIModelPool;
+use crate::{AOTIModel, Device, Error, IntoInputs, Outputs, RequestId};
+
+impl<D: Device> AOTIModel<D> {
+    /// Run each of `batches` in turn, returning each batch's outputs or
+    /// error in the order of `batches`: a failed batch doesn't stop the
+    /// rest.
+    pub fn run_many<'a, I>(&mut self, batches: I) -> Vec<Result<Outputs<D>, Error>>
+    where
+        I: IntoIterator,
+        I::Item: IntoInputs<'a, D>,
+    {
+        batches.into_iter().map(|inputs| self.run(inputs)).collect()
+    }
+}
+
+impl<D: Device> AOTIModelPool<D> {
+    /// Run each of `batches` as [`AOTIModelPool::run`], up to
+    /// `parallelism` at once, returning each batch's outputs or error in
+    /// the order of `batches`: a failed batch doesn't stop the rest.
+    ///
+    /// A `parallelism` of 0 or 1 runs the batches in turn on the calling
+    /// thread; the pool's [`len`](AOTIModelPool::len) keeps every replica
+    /// busy. Batches are taken from the iterator as runs finish, so it can
+    /// produce them lazily, and run on other threads, so they must be
+    /// owned (a `Vec` or array, which is then donated). The caller's
+    /// [`RequestId`] goes with them.
+    pub fn run_many<'a, I>(&self, batches: I, parallelism: usize) -> Vec<Result<Outputs<D>, Error>>
+    where
+        I: IntoIterator,
+        I::IntoIter: Send,
+        I::Item: IntoInputs<'a, D> + Send,
+    {
+        let batches = batches.into_iter();
+        if parallelism <= 1 {
+            return batches.map(|inputs| self.run(inputs)).collect();
+        }
+        let batches = Mutex::new(batches.enumerate());
+        let request = RequestId::current();
+        let mut results: Vec<_> = std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..parallelism)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut results = Vec::new();
+                        loop {
+                            let next = batches
+                                .lock()
+                                .unwrap_or_else(PoisonError::into_inner)
+                                .next();
+                            let Some((i, inputs)) = next else {
+                                return results;
+                            };
+                            let result = match &request {
+                                Some(id) => id.scope(|| self.run(inputs)),
+                                None => self.run(inputs),
+                            };
+                            results.push((i, result));
+                        }
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|w| w.join().expect("run_many thread panicked"))
+                .collect()
+        });
+        results.sort_unstable_by_key(|(i, _)| *i);
+        results.into_iter().map(|(_, result)| result).collect()
+    }
+}
+
+#[cfg(all(test, feature = "fake"))]
+mod tests {
+    use tch::Tensor;
+
+    use super::*;
+    use crate::fake::FakeModel;
+    use crate::{Cpu, DeviceTensor};
+
+    /// Echoes its input, failing on negative ones.
+    fn load(dir: &tempfile::TempDir) -> AOTIModel<Cpu> {
+        let path = dir.path().join("model.pt2");
+        if !path.exists() {
+            FakeModel::new()
+                .forward(|inputs, _| {
+                    if inputs[0].double_value(&[0]) < 0.0 {
+                        return Err(Error::Model("negative input".into()));
+                    }
+                    Ok(vec![inputs[0].copy()])
+                })
+                .write(&path, "model")
+                .unwrap();
+        }
+        AOTIModel::<Cpu>::load(&path).unwrap()
+    }
+
+    fn batch(value: f32) -> Vec<DeviceTensor<Cpu>> {
+        vec![DeviceTensor::try_new(Tensor::from_slice(&[value])).unwrap()]
+    }
+
+    fn values(results: &[Result<Outputs<Cpu>, Error>]) -> Vec<Option<f64>> {
+        results
+            .iter()
+            .map(|r| r.as_ref().ok().map(|outputs| outputs[0].double_value(&[0])))
+            .collect()
+    }
+
+    #[test]
+    fn model_runs_keep_going_past_failed_batches() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut model = load(&dir);
+        let batches = [batch(1.0), batch(-1.0), batch(3.0)];
+        let results = model.run_many(&batches);
+        assert_eq!(values(&results), [Some(1.0), None, Some(3.0)]);
+        assert_eq!(model.stats().failures, 1);
+    }
+
+    #[test]
+    fn pool_results_come_back_in_input_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let pool = AOTIModelPool::from_fn(2, |_| Ok(load(&dir))).unwrap();
+        let inputs = [1.0, -2.0, 3.0, 4.0, -5.0, 6.0, 7.0];
+        let expected: Vec<_> = inputs
+            .iter()
+            .map(|&v| (v >= 0.0).then_some(v as f64))
+            .collect();
+        for parallelism in [1, 3] {
+            let results = pool.run_many(inputs.iter().map(|&v| batch(v)), parallelism);
+            assert_eq!(values(&results), expected);
+        }
+        assert!(
+            pool.run_many(Vec::<Vec<DeviceTensor<Cpu>>>::new(), 2)
+                .is_empty()
+        );
+    }
+}
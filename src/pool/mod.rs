@@ -0,0 +1,684 @@
+//! A fixed set of model replicas shared between threads.
+//!
+//! A single [`AOTIModel`] needs `&mut self` to run, so sharing one between
+//! request handlers serializes them. [`AOTIModelPool`] holds several
+//! replicas of the same package behind their own locks and hands each call
+//! to an idle one, which is what the serving front-ends in
+//! [`crate::serve`] execute against.
+//!
+//! [`AOTIModelPool::shutdown`] drains a pool for a graceful exit, e.g. on
+//! SIGTERM: new runs are refused with [`Error::ShutDown`], in-flight runs
+//! get until a deadline to finish, and each replica is dropped as soon as
+//! it is no longer running.
+//!
+//! [`AOTIModelPool::health_check`] backs orchestrator probes: it runs a
+//! cached probe inference (or, without one, a call-spec round trip through
+//! the runtime) with a timeout and reports readiness and liveness.
+//!
+//! With a [`CircuitBreaker`] ([`AOTIModelPool::with_circuit_breaker`]), a
+//! replica failing several runs in a row (e.g. after a sticky CUDA error)
+//! is quarantined and retried in the background, and runs can fail over to
+//! a CPU pool while no replica is healthy.
+//!
+//! A [`RunLog`] ([`AOTIModelPool::with_run_log`]) appends a JSON line per
+//! run for auditing and building offline evaluation sets.
+//!
+//! A [`RateLimiter`] ([`AOTIModelPool::with_rate_limiter`]) enforces a
+//! requests-per-second and concurrent-batch-item quota, refusing excess
+//! runs with [`Error::RateLimited`].
+//!
+//! A [`RetryPolicy`] ([`AOTIModelPool::with_retry_policy`]) retries runs
+//! that failed for transient reasons, such as a CUDA allocation failure,
+//! while errors no retry can fix fail at once.
+//!
+//! Registered [`LoraAdapter`]s ([`AOTIModelPool::register_adapter`]) let
+//! each run pick fine-tuned weights on top of the one base package
+//! ([`AOTIModelPool::run_with_adapter`]); replicas swap them in through
+//! their inactive constant buffers.
+//!
+//! [`AOTIModelPool::run_many`] runs a stream of batches across the
+//! replicas and returns every batch's outputs or error in order.
+//!
+//! A [`DriftMonitor`] ([`AOTIModelPool::with_drift_monitor`]) tracks
+//! running statistics of the outputs and alerts when they depart from a
+//! baseline [`DriftProfile`], e.g. after a mismatched package is deployed.
+//!
+//! With the `fault-injection` feature, a `FaultInjector`
+//! (`AOTIModelPool::with_fault_injector`) makes runs hang or fail on
+//! demand, to test a service's retry and circuit-breaking paths without
+//! real device faults.
+
+mod breaker;
+mod drift;
+#[cfg(feature = "fault-injection")]
+mod fault;
+mod log;
+mod lora;
+mod many;
+mod rate;
+mod retry;
+
+pub use breaker::CircuitBreaker;
+pub use drift::{DriftAlert, DriftMonitor, DriftProfile, DriftReason, OutputStats};
+#[cfg(feature = "fault-injection")]
+pub use fault::{Fault, FaultInjector};
+pub use log::RunLog;
+pub use lora::LoraAdapter;
+pub use rate::{RateLimit, RateLimiter, RatePermit};
+pub use retry::{ErrorClass, RetryPolicy};
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+use tch::Tensor;
+
+use crate::{
+    AOTIModel, Device, DeviceTensor, Error, InputMode, InputTarget, IntoInputs, ModelMetadata,
+    Outputs,
+};
+use breaker::Replica;
+use lora::{Adapter, Adapters};
+
+/// Replicas of one model, each runnable by one thread at a time.
+///
+/// The pool is `Send + Sync`; wrap it in an `Arc` to share it.
+pub struct AOTIModelPool<D: Device> {
+    replicas: Vec<Arc<Replica<D>>>,
+    next: AtomicUsize,
+    metadata: ModelMetadata,
+    output_names: Option<Arc<[String]>>,
+    input_target: InputTarget,
+    device: tch::Device,
+    lifecycle: Mutex<Lifecycle>,
+    drained: Condvar,
+    /// Shared with breaker recovery threads, which test replicas with it.
+    probe: Arc<Mutex<Option<Vec<DeviceTensor<D>>>>>,
+    probing: AtomicBool,
+    breaker: Option<Arc<CircuitBreaker<D>>>,
+    /// The model name to log runs under, and the log.
+    run_log: Option<(String, Arc<RunLog>)>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    retry: Option<RetryPolicy>,
+    drift: Option<Arc<DriftMonitor>>,
+    #[cfg(feature = "fault-injection")]
+    faults: Option<FaultInjector>,
+    adapters: Mutex<Adapters<D>>,
+}
+
+#[derive(Default)]
+struct Lifecycle {
+    shut_down: bool,
+    /// Runs admitted and not yet finished, including those waiting for a
+    /// replica.
+    in_flight: usize,
+}
+
+impl<D: Device> AOTIModelPool<D> {
+    /// Pool already-loaded replicas. They are assumed to be loaded from the
+    /// same package; metadata is taken from the first.
+    ///
+    /// Fails with [`Error::InvalidInput`] if `replicas` is empty.
+    pub fn new(replicas: Vec<AOTIModel<D>>) -> Result<Self, Error> {
+        let first = replicas
+            .first()
+            .ok_or_else(|| Error::InvalidInput("a model pool needs at least one replica".into()))?;
+        let metadata = ModelMetadata::from(first.get_metadata()?);
+        let output_names = first.shared_output_names();
+        let input_target = first.input_target().clone();
+        let device = first.device();
+        Ok(Self {
+            replicas: replicas
+                .into_iter()
+                .enumerate()
+                .map(|(i, r)| Arc::new(Replica::new(i, Some(r))))
+                .collect(),
+            next: AtomicUsize::new(0),
+            metadata,
+            output_names,
+            input_target,
+            device,
+            lifecycle: Mutex::new(Lifecycle::default()),
+            drained: Condvar::new(),
+            probe: Arc::new(Mutex::new(None)),
+            probing: AtomicBool::new(false),
+            breaker: None,
+            run_log: None,
+            rate_limiter: None,
+            retry: None,
+            drift: None,
+            #[cfg(feature = "fault-injection")]
+            faults: None,
+            adapters: Mutex::new(Adapters::new()),
+        })
+    }
+
+    /// Quarantine replicas that keep failing, per `breaker`. Only failures
+    /// from the runtime count, not inputs rejected before reaching it; note
+    /// that the runtime's own shape checks do count, so a client sending
+    /// malformed inputs can trip it.
+    ///
+    /// Quarantined replicas are skipped, and each is retried on a
+    /// background thread after the breaker's cooldown. When all are out,
+    /// runs go to the breaker's fallback pool or fail with
+    /// [`Error::Quarantined`]; [`AOTIModelPool::with_replica`] always fails.
+    pub fn with_circuit_breaker(mut self, breaker: CircuitBreaker<D>) -> Self {
+        self.breaker = Some(Arc::new(breaker));
+        self
+    }
+
+    /// Append a line to `log` for every run, labelled with `model`. Runs
+    /// refused because the pool is shut down or quarantined are logged
+    /// too; [`AOTIModelPool::with_replica`] calls are not. One log can be
+    /// shared by several pools.
+    pub fn with_run_log(mut self, model: impl Into<String>, log: Arc<RunLog>) -> Self {
+        self.run_log = Some((model.into(), log));
+        self
+    }
+
+    /// Refuse runs beyond `limiter`'s quota with [`Error::RateLimited`],
+    /// without waiting. Runs through [`AOTIModelPool::with_replica`] are
+    /// not limited.
+    pub fn with_rate_limiter(mut self, limiter: Arc<RateLimiter>) -> Self {
+        self.rate_limiter = Some(limiter);
+        self
+    }
+
+    /// Feed the outputs of every successful run to `monitor`. Runs with an
+    /// adapter are skipped, as their outputs aren't the base model's, and
+    /// so are [`AOTIModelPool::with_replica`] calls. Outputs the monitor
+    /// can't summarize are counted in [`DriftMonitor::errors`] without
+    /// failing the run.
+    pub fn with_drift_monitor(mut self, monitor: Arc<DriftMonitor>) -> Self {
+        self.drift = Some(monitor);
+        self
+    }
+
+    /// Retry failed runs per `policy`, with the same deadline and each
+    /// attempt on whichever replica is free. Only runs on
+    /// [borrowed](InputMode::Borrow) inputs retry: donated inputs are
+    /// handed to the runtime, which may have consumed them by the time it
+    /// fails.
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry = Some(policy);
+        self
+    }
+
+    /// Inject `faults` into runs once they hold a replica, as if the
+    /// replica or the device had failed; see [`FaultInjector`].
+    #[cfg(feature = "fault-injection")]
+    pub fn with_fault_injector(mut self, faults: FaultInjector) -> Self {
+        self.faults = Some(faults);
+        self
+    }
+
+    /// Indices of the replicas currently quarantined by the circuit
+    /// breaker.
+    pub fn quarantined(&self) -> Vec<usize> {
+        self.replicas
+            .iter()
+            .filter(|r| r.is_quarantined())
+            .map(|r| r.index())
+            .collect()
+    }
+
+    /// Load `n` replicas by calling `load` with each replica's index, e.g.
+    /// to pin replicas to different CUDA streams or runner counts.
+    pub fn from_fn(
+        n: usize,
+        load: impl FnMut(usize) -> Result<AOTIModel<D>, Error>,
+    ) -> Result<Self, Error> {
+        Self::new((0..n).map(load).collect::<Result<_, _>>()?)
+    }
+
+    /// Number of replicas, including any released by a shutdown.
+    pub fn len(&self) -> usize {
+        self.replicas.len()
+    }
+
+    /// Always `false`: a pool holds at least one replica.
+    pub fn is_empty(&self) -> bool {
+        self.replicas.is_empty()
+    }
+
+    /// The device every replica runs on.
+    pub fn device(&self) -> tch::Device {
+        self.device
+    }
+
+    /// Metadata of the pooled package.
+    pub fn metadata(&self) -> &ModelMetadata {
+        &self.metadata
+    }
+
+    /// The first replica's [`AOTIModel::output_names`].
+    pub fn output_names(&self) -> Option<&[String]> {
+        self.output_names.as_deref()
+    }
+
+    /// The first replica's [`AOTIModel::input_target`].
+    pub fn input_target(&self) -> &InputTarget {
+        &self.input_target
+    }
+
+    /// Copy (if necessary) `tensor` onto the pool's device, returning an
+    /// input ready for [`AOTIModelPool::run`].
+    pub fn upload(&self, tensor: &Tensor) -> DeviceTensor<D> {
+        DeviceTensor {
+            tensor: tensor.to_device(self.device),
+            _device: std::marker::PhantomData,
+        }
+    }
+
+    /// Call `f` with exclusive access to a replica, preferring an idle one
+    /// and otherwise waiting for the next in round-robin order.
+    ///
+    /// Fails with [`Error::ShutDown`] once the pool is shutting down.
+    pub fn with_replica<R>(&self, f: impl FnOnce(&mut AOTIModel<D>) -> R) -> Result<R, Error> {
+        let _admitted = self.admit()?;
+        let (_, mut guard) = self.acquire().ok_or(Error::Quarantined)?;
+        // Runs left waiting past the shutdown deadline find their replica
+        // already released.
+        let model = guard.as_mut().ok_or(Error::ShutDown)?;
+        let result = f(model);
+        if self.is_shut_down() {
+            guard.take();
+        }
+        Ok(result)
+    }
+
+    /// Run inference on an available replica, borrowing or donating the
+    /// inputs as in [`AOTIModel::run`].
+    ///
+    /// Errors are wrapped in [`Error::Request`] while a
+    /// [`RequestId`](crate::RequestId) is
+    /// [scoped](crate::RequestId::scope).
+    pub fn run<'a>(&self, inputs: impl IntoInputs<'a, D>) -> Result<Outputs<D>, Error> {
+        let inputs = inputs
+            .into_inputs(&self.input_target)
+            .map_err(Error::in_request)?;
+        self.dispatch_named(inputs, None)
+    }
+
+    /// Run inference on an available replica, handing the inputs to the
+    /// runtime.
+    #[deprecated(note = "use `run`, which donates inputs passed by value")]
+    pub fn boxed_run(&self, inputs: Vec<DeviceTensor<D>>) -> Result<Outputs<D>, Error> {
+        self.run(inputs)
+    }
+
+    /// [`AOTIModelPool::run`] for a request whose caller gives up at
+    /// `deadline`: if it has passed when the run would start, including
+    /// after waiting for a busy replica, the run is skipped and fails with
+    /// [`Error::DeadlineExceeded`]. A run that has started is never cut
+    /// short.
+    pub fn run_before<'a>(
+        &self,
+        deadline: Instant,
+        inputs: impl IntoInputs<'a, D>,
+    ) -> Result<Outputs<D>, Error> {
+        let inputs = inputs
+            .into_inputs(&self.input_target)
+            .map_err(Error::in_request)?;
+        self.dispatch_named(inputs, Some(deadline))
+    }
+
+    /// Donating [`AOTIModelPool::run_before`].
+    #[deprecated(note = "use `run_before`, which donates inputs passed by value")]
+    pub fn boxed_run_before(
+        &self,
+        deadline: Instant,
+        inputs: Vec<DeviceTensor<D>>,
+    ) -> Result<Outputs<D>, Error> {
+        self.run_before(deadline, inputs)
+    }
+
+    /// [`AOTIModelPool::dispatch`] for a run with no adapter, naming the
+    /// outputs.
+    fn dispatch_named(
+        &self,
+        inputs: InputMode<'_, D>,
+        deadline: Option<Instant>,
+    ) -> Result<Outputs<D>, Error> {
+        let outputs = self.dispatch(inputs, deadline, None)?;
+        Ok(Outputs::new(outputs, self.output_names.clone()))
+    }
+
+    fn dispatch(
+        &self,
+        inputs: InputMode<'_, D>,
+        deadline: Option<Instant>,
+        adapter: Option<&Arc<Adapter<D>>>,
+    ) -> Result<Vec<DeviceTensor<D>>, Error> {
+        let result = match &self.run_log {
+            None => self
+                .attempt(inputs, deadline, adapter)
+                .map_err(Error::in_request),
+            Some((model, log)) => {
+                let started = Instant::now();
+                // Described up front, as donated inputs are consumed.
+                let described = log.describe_inputs(inputs.as_slice());
+                let result = self
+                    .attempt(inputs, deadline, adapter)
+                    .map_err(Error::in_request);
+                log.record(model, described, started.elapsed(), &result);
+                result
+            }
+        };
+        if let (Some(monitor), Ok(outputs), None) = (&self.drift, &result, adapter) {
+            // Failures are counted by the monitor.
+            let _ = monitor.observe(outputs);
+        }
+        result
+    }
+
+    /// [`AOTIModelPool::execute`], retried per the pool's policy if the
+    /// inputs are borrowed.
+    fn attempt(
+        &self,
+        inputs: InputMode<'_, D>,
+        deadline: Option<Instant>,
+        adapter: Option<&Arc<Adapter<D>>>,
+    ) -> Result<Vec<DeviceTensor<D>>, Error> {
+        match (&self.retry, inputs) {
+            (Some(policy), InputMode::Borrow(inputs)) => policy.run(deadline, || {
+                self.execute(InputMode::Borrow(inputs), deadline, adapter)
+            }),
+            (_, inputs) => self.execute(inputs, deadline, adapter),
+        }
+    }
+
+    fn execute(
+        &self,
+        inputs: InputMode<'_, D>,
+        deadline: Option<Instant>,
+        adapter: Option<&Arc<Adapter<D>>>,
+    ) -> Result<Vec<DeviceTensor<D>>, Error> {
+        let expired = || deadline.is_some_and(|d| Instant::now() >= d);
+        if expired() {
+            return Err(Error::DeadlineExceeded);
+        }
+        let _permit = match &self.rate_limiter {
+            Some(limiter) => Some(limiter.try_acquire(batch_items(inputs.as_slice()))?),
+            None => None,
+        };
+        let _admitted = self.admit()?;
+        let selects = self.selects_adapters(adapter);
+        let acquired = match selects.then(|| self.idle_with(adapter)).flatten() {
+            Some(replica) => self.acquire_from(replica),
+            None => self.acquire(),
+        };
+        let Some((replica, mut guard)) = acquired else {
+            return match self.breaker.as_ref().and_then(|b| b.fallback_pool()) {
+                Some(fallback) if adapter.is_none() => {
+                    breaker::run_on_fallback(fallback, inputs.as_slice(), self.device, deadline)
+                }
+                _ => Err(Error::Quarantined),
+            };
+        };
+        let model = guard.as_mut().ok_or(Error::ShutDown)?;
+        let result = if expired() {
+            Err(Error::DeadlineExceeded)
+        } else if selects && let Err(err) = self.select_adapter(replica, model, adapter) {
+            Err(err)
+        } else if let Err(err) = self.inject_fault(replica) {
+            Err(err)
+        } else {
+            model.run(inputs).map(Vec::from)
+        };
+        if let Some(breaker) = &self.breaker {
+            breaker::observe(breaker, replica, &self.probe, &result);
+        }
+        if self.is_shut_down() {
+            guard.take();
+        }
+        result
+    }
+
+    /// Apply the fault injector's fault for a run on `replica`, if any.
+    #[cfg(feature = "fault-injection")]
+    fn inject_fault(&self, replica: &Replica<D>) -> Result<(), Error> {
+        match &self.faults {
+            Some(faults) => faults.before_run(replica.index()),
+            None => Ok(()),
+        }
+    }
+
+    #[cfg(not(feature = "fault-injection"))]
+    fn inject_fault(&self, _replica: &Replica<D>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Stop accepting runs and wait up to `grace` for in-flight ones to
+    /// finish, then release the replicas. Returns whether every run
+    /// finished in time; runs still going past the deadline keep their
+    /// replica until they return, and runs still waiting for one fail with
+    /// [`Error::ShutDown`].
+    ///
+    /// Metadata stays readable afterwards. Calling this again waits for any
+    /// stragglers.
+    pub fn shutdown(&self, grace: Duration) -> bool {
+        let deadline = Instant::now() + grace;
+        let mut lifecycle = self
+            .lifecycle
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        lifecycle.shut_down = true;
+        while lifecycle.in_flight > 0 {
+            let Some(left) = deadline.checked_duration_since(Instant::now()) else {
+                break;
+            };
+            lifecycle = self
+                .drained
+                .wait_timeout(lifecycle, left)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+        let drained = lifecycle.in_flight == 0;
+        drop(lifecycle);
+        for replica in &self.replicas {
+            match replica.model.try_lock() {
+                Ok(mut guard) => drop(guard.take()),
+                Err(std::sync::TryLockError::Poisoned(p)) => drop(p.into_inner().take()),
+                // Still running; released when the run returns.
+                Err(std::sync::TryLockError::WouldBlock) => {}
+            }
+        }
+        drained
+    }
+
+    /// Whether [`AOTIModelPool::shutdown`] has been called.
+    pub fn is_shut_down(&self) -> bool {
+        self.lifecycle
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .shut_down
+    }
+
+    /// Use `inputs` as the synthetic request run by
+    /// [`AOTIModelPool::health_check`]; keep it small, as it competes with
+    /// real requests for a replica.
+    pub fn set_health_probe(&self, inputs: Vec<DeviceTensor<D>>) {
+        *self.probe.lock().unwrap_or_else(PoisonError::into_inner) = Some(inputs);
+    }
+
+    /// Probe the pool, waiting at most `timeout`: run the
+    /// [health probe](AOTIModelPool::set_health_probe) if one is set,
+    /// otherwise fetch the call spec from a replica, which still exercises
+    /// replica locking and the runtime.
+    ///
+    /// The probe queues behind real requests for a replica, so a saturated
+    /// pool can look [`Health::Unresponsive`]; give liveness probes a
+    /// timeout well above a request's latency. Only one probe runs at a
+    /// time: while an earlier one is stuck, checks report unresponsive
+    /// without starting another.
+    pub fn health_check(self: &Arc<Self>, timeout: Duration) -> Health {
+        if self.is_shut_down() {
+            return Health::ShuttingDown;
+        }
+        if self.probing.swap(true, Ordering::AcqRel) {
+            return Health::Unresponsive;
+        }
+        let (done, result) = mpsc::channel();
+        let pool = self.clone();
+        std::thread::spawn(move || {
+            let started = Instant::now();
+            let outcome = pool.run_probe().map(|()| started.elapsed());
+            pool.probing.store(false, Ordering::Release);
+            let _ = done.send(outcome);
+        });
+        match result.recv_timeout(timeout) {
+            Ok(Ok(latency)) => Health::Ready { latency },
+            Ok(Err(Error::ShutDown)) => Health::ShuttingDown,
+            Ok(Err(err)) => Health::Failing(err.to_string()),
+            Err(_) => Health::Unresponsive,
+        }
+    }
+
+    fn run_probe(&self) -> Result<(), Error> {
+        let probe = self.probe.lock().unwrap_or_else(PoisonError::into_inner);
+        self.with_replica(|model| match probe.as_deref() {
+            Some(inputs) => model.run(inputs).map(drop),
+            None => model.get_call_spec().map(drop),
+        })?
+    }
+
+    /// Count a run as in flight until the returned guard drops.
+    fn admit(&self) -> Result<Admitted<'_, D>, Error> {
+        let mut lifecycle = self
+            .lifecycle
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if lifecycle.shut_down {
+            return Err(Error::ShutDown);
+        }
+        lifecycle.in_flight += 1;
+        Ok(Admitted(self))
+    }
+
+    /// Lock `preferred` if it is idle, otherwise any replica as in
+    /// [`AOTIModelPool::acquire`].
+    fn acquire_from<'a>(
+        &'a self,
+        preferred: &'a Arc<Replica<D>>,
+    ) -> Option<(&'a Arc<Replica<D>>, Held<'a, D>)> {
+        match preferred.model.try_lock() {
+            Ok(guard) => Some((preferred, guard)),
+            Err(std::sync::TryLockError::Poisoned(p)) => Some((preferred, p.into_inner())),
+            Err(std::sync::TryLockError::WouldBlock) => self.acquire(),
+        }
+    }
+
+    /// Lock a replica that isn't quarantined, or `None` if all are.
+    fn acquire(&self) -> Option<(&Arc<Replica<D>>, Held<'_, D>)> {
+        let n = self.replicas.len();
+        let start = self.next.fetch_add(1, Ordering::Relaxed) % n;
+        let mut candidates = (0..n)
+            .map(|i| &self.replicas[(start + i) % n])
+            .filter(|r| !r.is_quarantined());
+        let first = candidates.next()?;
+        // A panic while a replica was held (e.g. in a `with_replica`
+        // closure) leaves nothing half-updated on the Rust side, so poisoned
+        // locks are recovered rather than propagated.
+        std::iter::once(first)
+            .chain(candidates)
+            .find_map(|r| match r.model.try_lock() {
+                Ok(guard) => Some((r, guard)),
+                Err(std::sync::TryLockError::Poisoned(p)) => Some((r, p.into_inner())),
+                Err(std::sync::TryLockError::WouldBlock) => None,
+            })
+            .or_else(|| {
+                #[cfg(feature = "otel")]
+                let _wait = crate::otel::span("aoti.queue_wait", Vec::new());
+                let guard = first.model.lock().unwrap_or_else(PoisonError::into_inner);
+                Some((first, guard))
+            })
+    }
+}
+
+/// Outcome of [`AOTIModelPool::health_check`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Health {
+    /// The probe succeeded within the timeout.
+    Ready { latency: Duration },
+    /// The pool is shutting down and refuses new requests.
+    ShuttingDown,
+    /// The probe returned an error: the process responds but the model
+    /// can't serve.
+    Failing(String),
+    /// The probe didn't finish within the timeout, or an earlier probe
+    /// still hasn't.
+    Unresponsive,
+}
+
+impl Health {
+    /// Whether the pool should receive traffic (a readiness probe).
+    pub fn is_ready(&self) -> bool {
+        matches!(self, Health::Ready { .. })
+    }
+
+    /// Whether the pool is still making progress (a liveness probe); only
+    /// an unresponsive pool warrants a restart.
+    pub fn is_live(&self) -> bool {
+        !matches!(self, Health::Unresponsive)
+    }
+}
+
+/// Batch items in a request, as counted by [`RateLimit::max_batch_items`].
+fn batch_items<D: Device>(inputs: &[DeviceTensor<D>]) -> usize {
+    inputs
+        .first()
+        .and_then(|t| t.size().first().copied())
+        .map_or(1, |n| n.max(0) as usize)
+}
+
+/// A locked replica slot.
+type Held<'a, D> = MutexGuard<'a, Option<AOTIModel<D>>>;
+
+/// An in-flight run; finishing it may complete a shutdown's drain.
+struct Admitted<'a, D: Device>(&'a AOTIModelPool<D>);
+
+impl<D: Device> Drop for Admitted<'_, D> {
+    fn drop(&mut self) {
+        let mut lifecycle = self
+            .0
+            .lifecycle
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        lifecycle.in_flight -= 1;
+        if lifecycle.in_flight == 0 {
+            self.0.drained.notify_all();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Cpu;
+
+    #[test]
+    fn empty_pool_is_rejected() {
+        assert!(matches!(
+            AOTIModelPool::<Cpu>::new(Vec::new()),
+            Err(Error::InvalidInput(_))
+        ));
+        assert!(matches!(
+            AOTIModelPool::<Cpu>::from_fn(2, |_| AOTIModel::<Cpu>::load("/nonexistent/model.pt2")),
+            Err(Error::Io(_))
+        ));
+    }
+
+    #[test]
+    fn only_unresponsive_pools_fail_liveness() {
+        let ready = Health::Ready {
+            latency: Duration::from_millis(3),
+        };
+        assert!(ready.is_ready() && ready.is_live());
+        for health in [Health::ShuttingDown, Health::Failing("oom".into())] {
+            assert!(!health.is_ready() && health.is_live());
+        }
+        assert!(!Health::Unresponsive.is_live());
+    }
+}
@@ -0,0 +1,167 @@
+//! Token-bucket request quotas and concurrent batch-item caps.
+
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::Error;
+
+/// A quota enforced by a [`RateLimiter`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    per_second: f64,
+    burst: u32,
+    max_batch_items: Option<usize>,
+}
+
+impl RateLimit {
+    /// Allow `requests` per second on average, with bursts of up to
+    /// `requests` (at least 1) at once.
+    pub fn per_second(requests: f64) -> Self {
+        Self {
+            per_second: requests.max(0.0),
+            burst: requests.ceil().max(1.0) as u32,
+            max_batch_items: None,
+        }
+    }
+
+    /// Allow bursts of up to `requests` (at least 1) after idling.
+    pub fn burst(mut self, requests: u32) -> Self {
+        self.burst = requests.max(1);
+        self
+    }
+
+    /// Also cap the batch items being run at once, counting each request
+    /// as the leading dimension of its first input (1 for scalars).
+    pub fn max_batch_items(mut self, items: usize) -> Self {
+        self.max_batch_items = Some(items);
+        self
+    }
+}
+
+/// Enforces a [`RateLimit`]; share one between pools with
+/// [`AOTIModelPool::with_rate_limiter`](crate::AOTIModelPool::with_rate_limiter)
+/// to give them a common quota, or call [`RateLimiter::try_acquire`] directly
+/// to keep per-tenant quotas in front of a pool.
+pub struct RateLimiter {
+    limit: RateLimit,
+    state: Mutex<Bucket>,
+}
+
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+    items_in_flight: usize,
+}
+
+impl RateLimiter {
+    /// A limiter whose bucket starts full.
+    pub fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            state: Mutex::new(Bucket {
+                tokens: f64::from(limit.burst),
+                refilled: Instant::now(),
+                items_in_flight: 0,
+            }),
+        }
+    }
+
+    pub fn limit(&self) -> RateLimit {
+        self.limit
+    }
+
+    /// Take a request token and reserve `items` batch items until the
+    /// permit drops. Never waits: fails with [`Error::RateLimited`] when the
+    /// bucket is empty or the items don't fit beside those in flight, and
+    /// with [`Error::InvalidInput`] if `items` alone exceed the cap.
+    pub fn try_acquire(self: &Arc<Self>, items: usize) -> Result<RatePermit, Error> {
+        self.try_acquire_at(items, Instant::now())
+    }
+
+    fn try_acquire_at(self: &Arc<Self>, items: usize, now: Instant) -> Result<RatePermit, Error> {
+        let max_items = self.limit.max_batch_items.unwrap_or(usize::MAX);
+        if items > max_items {
+            return Err(Error::InvalidInput(format!(
+                "a batch of {items} items exceeds the limit of {max_items}"
+            )));
+        }
+        let mut bucket = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let elapsed = now.saturating_duration_since(bucket.refilled).as_secs_f64();
+        bucket.tokens =
+            (bucket.tokens + elapsed * self.limit.per_second).min(f64::from(self.limit.burst));
+        bucket.refilled = bucket.refilled.max(now);
+        if bucket.items_in_flight + items > max_items {
+            return Err(Error::RateLimited { retry_after: None });
+        }
+        if bucket.tokens < 1.0 {
+            let retry_after = (self.limit.per_second > 0.0)
+                .then(|| Duration::from_secs_f64((1.0 - bucket.tokens) / self.limit.per_second));
+            return Err(Error::RateLimited { retry_after });
+        }
+        bucket.tokens -= 1.0;
+        bucket.items_in_flight += items;
+        Ok(RatePermit {
+            limiter: self.clone(),
+            items,
+        })
+    }
+}
+
+/// Batch items reserved by [`RateLimiter::try_acquire`], released on drop.
+pub struct RatePermit {
+    limiter: Arc<RateLimiter>,
+    items: usize,
+}
+
+impl Drop for RatePermit {
+    fn drop(&mut self) {
+        let mut bucket = self
+            .limiter
+            .state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        bucket.items_in_flight -= self.items;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_refill_at_the_configured_rate() {
+        let limiter = Arc::new(RateLimiter::new(RateLimit::per_second(2.0)));
+        let start = Instant::now();
+        let _a = limiter.try_acquire_at(1, start).unwrap();
+        let _b = limiter.try_acquire_at(1, start).unwrap();
+        match limiter.try_acquire_at(1, start) {
+            Err(Error::RateLimited {
+                retry_after: Some(wait),
+            }) => assert_eq!(wait, Duration::from_millis(500)),
+            other => panic!("expected a rate limit, got {:?}", other.err()),
+        }
+        assert!(
+            limiter
+                .try_acquire_at(1, start + Duration::from_millis(500))
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn batch_items_are_capped_while_in_flight() {
+        let limiter = Arc::new(RateLimiter::new(
+            RateLimit::per_second(100.0).max_batch_items(8),
+        ));
+        assert!(matches!(
+            limiter.try_acquire(9),
+            Err(Error::InvalidInput(_))
+        ));
+        let held = limiter.try_acquire(6).unwrap();
+        assert!(matches!(
+            limiter.try_acquire(3),
+            Err(Error::RateLimited { retry_after: None })
+        ));
+        drop(held);
+        assert!(limiter.try_acquire(8).is_ok());
+    }
+}
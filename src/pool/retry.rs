@@ -0,0 +1,160 @@
+//! Retrying runs that failed for transient reasons.
+
+use std::time::{Duration, Instant};
+
+use crate::Error;
+
+/// How an [`Error`] bears on retrying the run that raised it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorClass {
+    /// An allocator failure, e.g. CUDA running out of memory while other
+    /// runs hold buffers; likely to pass.
+    OutOfMemory,
+    /// Any other error from the runtime or libtorch. May be transient, may
+    /// be a model bug.
+    Runtime,
+    /// Refused without running: rate limited, overloaded or quarantined.
+    Unavailable,
+    /// Retrying can't help: bad inputs, a shut-down pool, an expired
+    /// deadline, I/O errors and the like.
+    Permanent,
+}
+
+impl ErrorClass {
+    pub fn of(err: &Error) -> Self {
+        match err.root() {
+            Error::Ffi(_) | Error::Tch(_) | Error::Model(_) => {
+                let message = err.to_string();
+                let oom = ["out of memory", "CUBLAS_STATUS_ALLOC_FAILED", "bad_alloc"]
+                    .iter()
+                    .any(|needle| message.contains(needle));
+                if oom {
+                    ErrorClass::OutOfMemory
+                } else {
+                    ErrorClass::Runtime
+                }
+            }
+            Error::RateLimited { .. } | Error::Overloaded { .. } | Error::Quarantined => {
+                ErrorClass::Unavailable
+            }
+            _ => ErrorClass::Permanent,
+        }
+    }
+}
+
+/// When [`AOTIModelPool::run`](crate::AOTIModelPool::run) retries a failed
+/// run; see [`AOTIModelPool::with_retry_policy`](crate::AOTIModelPool::with_retry_policy).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    backoff: Duration,
+    max_backoff: Duration,
+    retry_on: Vec<ErrorClass>,
+}
+
+impl RetryPolicy {
+    /// Make up to `max_attempts` (at least 1) attempts in all, retrying
+    /// [`ErrorClass::OutOfMemory`] only, after 10 ms and then doubling up to
+    /// 1 second.
+    pub fn new(max_attempts: u32) -> Self {
+        Self {
+            max_attempts: max_attempts.max(1),
+            backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_secs(1),
+            retry_on: vec![ErrorClass::OutOfMemory],
+        }
+    }
+
+    /// Wait `initial` before the first retry, doubling the wait for each
+    /// further one up to `max`.
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.backoff = initial;
+        self.max_backoff = max.max(initial);
+        self
+    }
+
+    /// Retry errors of these classes. [`ErrorClass::Permanent`] errors are
+    /// never retried, even if listed.
+    pub fn retry_on(mut self, classes: &[ErrorClass]) -> Self {
+        self.retry_on = classes.to_vec();
+        self
+    }
+
+    fn retries(&self, err: &Error) -> bool {
+        let class = ErrorClass::of(err);
+        class != ErrorClass::Permanent && self.retry_on.contains(&class)
+    }
+
+    /// Call `attempt` until it succeeds, fails permanently, runs out of
+    /// attempts, or the next attempt would start after `deadline`.
+    pub(super) fn run<T>(
+        &self,
+        deadline: Option<Instant>,
+        mut attempt: impl FnMut() -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let mut wait = self.backoff;
+        let mut attempts = 1;
+        loop {
+            let err = match attempt() {
+                Ok(value) => return Ok(value),
+                Err(err) => err,
+            };
+            let too_late = deadline.is_some_and(|d| Instant::now() + wait >= d);
+            if attempts >= self.max_attempts || too_late || !self.retries(&err) {
+                return Err(err);
+            }
+            std::thread::sleep(wait);
+            wait = (wait * 2).min(self.max_backoff);
+            attempts += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors_are_classified_by_cause() {
+        let oom = Error::Model("CUDA out of memory. Tried to allocate 2.00 GiB".into());
+        assert_eq!(ErrorClass::of(&oom), ErrorClass::OutOfMemory);
+        let bug = Error::Model("index out of range".into());
+        assert_eq!(ErrorClass::of(&bug), ErrorClass::Runtime);
+        let limited = Error::RateLimited { retry_after: None };
+        assert_eq!(ErrorClass::of(&limited), ErrorClass::Unavailable);
+        let shape = Error::InvalidInput("expected rank 2".into());
+        assert_eq!(ErrorClass::of(&shape), ErrorClass::Permanent);
+    }
+
+    #[test]
+    fn only_listed_classes_are_retried_up_to_the_limit() {
+        let policy = RetryPolicy::new(3)
+            .backoff(Duration::ZERO, Duration::ZERO)
+            .retry_on(&[ErrorClass::OutOfMemory, ErrorClass::Permanent]);
+        let mut calls = 0;
+        let result: Result<(), _> = policy.run(None, || {
+            calls += 1;
+            Err(Error::Model("out of memory".into()))
+        });
+        assert!(result.is_err());
+        assert_eq!(calls, 3);
+
+        calls = 0;
+        let result: Result<(), _> = policy.run(None, || {
+            calls += 1;
+            Err(Error::InvalidInput("shape".into()))
+        });
+        assert!(result.is_err());
+        assert_eq!(calls, 1);
+
+        calls = 0;
+        let result = policy.run(None, || {
+            calls += 1;
+            match calls {
+                1 => Err(Error::Model("out of memory".into())),
+                n => Ok(n),
+            }
+        });
+        assert_eq!(result.unwrap(), 2);
+    }
+}
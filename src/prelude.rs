@@ -0,0 +1,38 @@
+//! The types most code using this crate needs, for a glob import:
+//!
+//! ```ignore
+//! use aoti_rs::prelude::*;
+//!
+//! let pool = AOTIModelPool::from_fn(4, |_| AOTIModel::<Cuda>::load("model.pt2"))?;
+//! let input = DeviceTensor::<Cuda>::try_new(Tensor::zeros([1, 3], (Kind::Float, tch::Device::Cuda(0))))?;
+//! let outputs = pool.run(&[input])?;
+//! ```
+//!
+//! [`Run`](crate::generate::Run) and the other traits with a `run` method
+//! are left out: with them in scope, `run` on an `Arc<AOTIModelPool>`
+//! resolves to the trait's `&mut self` method instead of the pool's own.
+//! `tch::Device` is left out for [`Device`], so `tch` itself is exported
+//! to reach it.
+
+pub use crate::registry::{ModelRegistry, ModelSpec};
+pub use crate::{
+    AOTIModel, AOTIModelBuilder, AOTIModelPool, AnyAOTIModel, Cpu, Cuda, Device, DeviceTensor,
+    Error, ErrorClass, OutputSchema, tch,
+};
+pub use tch::{Kind, Tensor};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_prelude_alone_reaches_a_device_tensor() {
+        let zeros = || Tensor::zeros([2, 3], (Kind::Float, tch::Device::Cpu));
+        let input = DeviceTensor::<Cpu>::try_new(zeros()).unwrap();
+        assert_eq!(input.size(), [2, 3]);
+        assert!(matches!(
+            DeviceTensor::<Cuda>::try_new(zeros()),
+            Err(Error::TensorDeviceMismatch { .. })
+        ));
+    }
+}
@@ -0,0 +1,260 @@
+//! Progress reporting and background loading for large packages.
+
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+use crate::{AOTIModel, Device, Error};
+
+/// A stage of [`AOTIModelBuilder::build`](crate::AOTIModelBuilder).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadPhase {
+    /// Fetching a remote package (feature `object-store`).
+    Download,
+    /// Unpacking the archive into a temporary directory.
+    Extract,
+    /// Loading the compiled model into the runtime.
+    Load,
+}
+
+/// How far a load has got. Byte counts are per phase: compressed bytes
+/// for downloads, uncompressed bytes for extraction, and the size of the
+/// model library for loading.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoadProgress {
+    pub phase: LoadPhase,
+    pub bytes_done: u64,
+    /// `None` when the size isn't known up front.
+    pub bytes_total: Option<u64>,
+}
+
+/// Callback receiving [`LoadProgress`] updates as a build runs.
+pub(crate) type Report<'a> = &'a mut (dyn FnMut(LoadProgress) + Send);
+
+/// A package loading on a background thread, from
+/// [`AOTIModelBuilder::build_async`](crate::AOTIModelBuilder).
+///
+/// Await it from any async runtime, or block on it with
+/// [`Loading::wait`]; poll [`Loading::progress`] meanwhile to report
+/// loading state. Dropping it doesn't cancel the load; the model is
+/// dropped when the load finishes.
+pub struct Loading<D: Device> {
+    shared: Arc<Shared<D>>,
+}
+
+struct Shared<D: Device> {
+    state: Mutex<State<D>>,
+    done: Condvar,
+}
+
+struct State<D: Device> {
+    progress: Option<LoadProgress>,
+    result: Option<Result<AOTIModel<D>, Error>>,
+    finished: bool,
+    waker: Option<Waker>,
+}
+
+impl<D: Device> Loading<D> {
+    /// Run `build` on a new thread, recording the progress it reports.
+    pub(crate) fn spawn(
+        build: impl FnOnce(Report<'_>) -> Result<AOTIModel<D>, Error> + Send + 'static,
+    ) -> Self {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                progress: None,
+                result: None,
+                finished: false,
+                waker: None,
+            }),
+            done: Condvar::new(),
+        });
+        let worker = shared.clone();
+        std::thread::spawn(move || {
+            let mut report = |progress| {
+                worker.lock().progress = Some(progress);
+                worker.done.notify_all();
+            };
+            let result = std::panic::catch_unwind(AssertUnwindSafe(|| build(&mut report)))
+                .unwrap_or_else(|_| Err(Error::Model("loading the package panicked".into())));
+            let mut state = worker.lock();
+            state.result = Some(result);
+            state.finished = true;
+            let waker = state.waker.take();
+            drop(state);
+            worker.done.notify_all();
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        });
+        Self { shared }
+    }
+
+    /// The latest progress reported, or `None` before the first update.
+    pub fn progress(&self) -> Option<LoadProgress> {
+        self.shared.lock().progress
+    }
+
+    /// Whether the load has finished, successfully or not.
+    pub fn is_finished(&self) -> bool {
+        self.shared.lock().finished
+    }
+
+    /// Block until the load finishes.
+    pub fn wait(self) -> Result<AOTIModel<D>, Error> {
+        let mut state = self.shared.lock();
+        loop {
+            if let Some(result) = state.result.take() {
+                return result;
+            }
+            if state.finished {
+                return Err(Error::Model("the load's result was already taken".into()));
+            }
+            state = self
+                .shared
+                .done
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+}
+
+impl<D: Device> Loading<D> {
+    /// Block until the load finishes, or fail with [`Error::LoadTimeout`]
+    /// once `timeout` has passed. The load itself can't be cancelled: it
+    /// carries on in the background, and the model is dropped if it ever
+    /// finishes.
+    pub fn wait_timeout(self, timeout: Duration) -> Result<AOTIModel<D>, Error> {
+        self.wait_for(timeout, &mut |_| {})
+    }
+
+    /// [`Loading::wait_timeout`], passing progress updates seen while
+    /// waiting on to `report`; updates in quick succession may be
+    /// coalesced.
+    pub(crate) fn wait_for(
+        self,
+        timeout: Duration,
+        report: Report<'_>,
+    ) -> Result<AOTIModel<D>, Error> {
+        let deadline = Instant::now() + timeout;
+        let mut reported = None;
+        let mut state = self.shared.lock();
+        loop {
+            if let Some(progress) = state.progress
+                && reported != Some(progress)
+            {
+                reported = Some(progress);
+                // Reported unlocked, as the callback may take a while.
+                drop(state);
+                report(progress);
+                state = self.shared.lock();
+                continue;
+            }
+            if let Some(result) = state.result.take() {
+                return result;
+            }
+            if state.finished {
+                return Err(Error::Model("the load's result was already taken".into()));
+            }
+            let Some(left) = deadline.checked_duration_since(Instant::now()) else {
+                return Err(Error::LoadTimeout {
+                    timeout,
+                    phase: state.progress.map(|p| p.phase),
+                });
+            };
+            state = self
+                .shared
+                .done
+                .wait_timeout(state, left)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+    }
+}
+
+impl<D: Device> Shared<D> {
+    fn lock(&self) -> std::sync::MutexGuard<'_, State<D>> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<D: Device> Future for Loading<D> {
+    type Output = Result<AOTIModel<D>, Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.shared.lock();
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None if state.finished => Poll::Ready(Err(Error::Model(
+                "the load's result was already taken".into(),
+            ))),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Cpu;
+
+    #[test]
+    fn progress_and_errors_reach_the_handle() {
+        let loading = Loading::<Cpu>::spawn(|report| {
+            report(LoadProgress {
+                phase: LoadPhase::Extract,
+                bytes_done: 10,
+                bytes_total: Some(10),
+            });
+            Err(Error::InvalidPath("missing.pt2".into()))
+        });
+        while !loading.is_finished() {
+            std::thread::yield_now();
+        }
+        assert_eq!(loading.progress().unwrap().phase, LoadPhase::Extract);
+        assert!(matches!(loading.wait(), Err(Error::InvalidPath(_))));
+    }
+
+    #[test]
+    fn waits_time_out_with_the_phase_reached() {
+        let (release, released) = std::sync::mpsc::channel::<()>();
+        let loading = Loading::<Cpu>::spawn(move |report| {
+            report(LoadProgress {
+                phase: LoadPhase::Load,
+                bytes_done: 0,
+                bytes_total: None,
+            });
+            // Stands in for a runtime hung creating a CUDA context.
+            let _ = released.recv();
+            Err(Error::Model("released".into()))
+        });
+        let mut seen = Vec::new();
+        let result = loading.wait_for(Duration::from_millis(50), &mut |p| seen.push(p.phase));
+        assert!(matches!(
+            result,
+            Err(Error::LoadTimeout {
+                phase: Some(LoadPhase::Load),
+                ..
+            })
+        ));
+        assert_eq!(seen, [LoadPhase::Load]);
+        drop(release);
+
+        let quick = Loading::<Cpu>::spawn(|_| Err(Error::InvalidPath("missing.pt2".into())));
+        assert!(matches!(
+            quick.wait_timeout(Duration::from_secs(10)),
+            Err(Error::InvalidPath(_))
+        ));
+    }
+
+    #[test]
+    fn panics_become_errors() {
+        let loading = Loading::<Cpu>::spawn(|_| panic!("boom"));
+        assert!(matches!(loading.wait(), Err(Error::Model(_))));
+    }
+}
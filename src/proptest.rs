@@ -0,0 +1,289 @@
+//! Property-based testing of packages with `proptest` (feature `proptest`).
+//!
+//! A package doesn't record the shapes it was exported for, so
+//! [`InputSpec`] restates them the way `export::PackageExporter` takes
+//! them (and converts from one, with feature `export`): each input's
+//! example shape and dtype, plus the dimensions left dynamic. Its
+//! [`strategy`](InputSpec::strategy) draws input sets the package
+//! accepts, sizing each dynamic dimension within its range (equally
+//! wherever the name is shared) and filling the tensors with values from
+//! each input's range, so tests can hammer `run` with varied but legal
+//! inputs:
+//!
+//! ```ignore
+//! let spec = InputSpec::new()
+//!     .input(&[1, 16], Kind::Int64)
+//!     .values(0.0, 32000.0)
+//!     .dynamic(0, 0, "batch", 1..=8)
+//!     .dynamic(0, 1, "seq", 1..=512);
+//! proptest!(|(inputs in spec.strategy::<Cpu>(tch::Device::Cpu)?)| {
+//!     let outputs = model.run(&inputs).unwrap();
+//!     prop_assert_eq!(outputs[0].size()[0], inputs[0].size()[0]);
+//! });
+//! ```
+
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+use std::ops::RangeInclusive;
+
+use proptest::collection::vec;
+use proptest::strategy::{BoxedStrategy, Just, Strategy};
+use tch::{Kind, Tensor};
+
+use crate::{Device, DeviceTensor, Error};
+
+/// One input's example shape, dtype and value range.
+#[derive(Debug, Clone, PartialEq)]
+struct Input {
+    shape: Vec<i64>,
+    kind: Kind,
+    values: (f64, f64),
+}
+
+/// A dimension left dynamic in the export.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Dynamic {
+    input: usize,
+    dim: usize,
+    name: String,
+    sizes: RangeInclusive<i64>,
+}
+
+/// The inputs a package was exported for.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InputSpec {
+    inputs: Vec<Input>,
+    dynamic: Vec<Dynamic>,
+}
+
+impl InputSpec {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an input of this shape and dtype, in argument order. Its values
+    /// come from `[-1, 1)` for floating-point dtypes, `{0, 1}` for `Bool`
+    /// and `0..10` for integers, like the exporter's example inputs.
+    pub fn input(mut self, shape: &[i64], kind: Kind) -> Self {
+        let values = if is_float(kind) {
+            (-1.0, 1.0)
+        } else if kind == Kind::Bool {
+            (0.0, 2.0)
+        } else {
+            (0.0, 10.0)
+        };
+        self.inputs.push(Input {
+            shape: shape.to_vec(),
+            kind,
+            values,
+        });
+        self
+    }
+
+    /// Draw the last added input's values from `[low, high)`, truncated
+    /// for integer dtypes: e.g. token ids below the vocabulary size.
+    pub fn values(mut self, low: f64, high: f64) -> Self {
+        if let Some(input) = self.inputs.last_mut() {
+            input.values = (low, high);
+        }
+        self
+    }
+
+    /// Let dimension `dim` of input `input` take any size in `sizes`.
+    /// Dimensions given the same name always get the same size.
+    pub fn dynamic(
+        mut self,
+        input: usize,
+        dim: usize,
+        name: impl Into<String>,
+        sizes: RangeInclusive<i64>,
+    ) -> Self {
+        self.dynamic.push(Dynamic {
+            input,
+            dim,
+            name: name.into(),
+            sizes,
+        });
+        self
+    }
+
+    /// Input sets drawn on `device`, which must be of kind `D`. Shrinking
+    /// moves dynamic sizes toward their minimum and values toward the
+    /// bottom of their range.
+    pub fn strategy<D: Device>(
+        &self,
+        device: tch::Device,
+    ) -> Result<BoxedStrategy<Vec<DeviceTensor<D>>>, Error> {
+        if !D::matches(device) {
+            return Err(Error::InvalidInput(format!(
+                "{device:?} is not a {} device",
+                D::KEY
+            )));
+        }
+        let names = self.names()?;
+        let inputs = self.inputs.clone();
+        // For each input dimension, the index of its name, if dynamic.
+        let dims: Vec<Vec<Option<usize>>> = inputs
+            .iter()
+            .enumerate()
+            .map(|(i, input)| {
+                (0..input.shape.len())
+                    .map(|dim| {
+                        let d = self.dynamic.iter().find(|d| d.input == i && d.dim == dim)?;
+                        names.keys().position(|name| *name == d.name)
+                    })
+                    .collect()
+            })
+            .collect();
+        let sizes: Vec<RangeInclusive<i64>> = names.into_values().collect();
+        Ok(sizes
+            .prop_flat_map(move |sizes| {
+                let shapes: Vec<Vec<i64>> = inputs
+                    .iter()
+                    .zip(&dims)
+                    .map(|(input, dims)| {
+                        input
+                            .shape
+                            .iter()
+                            .zip(dims)
+                            .map(|(&size, name)| name.map_or(size, |n| sizes[n]))
+                            .collect()
+                    })
+                    .collect();
+                let values: Vec<_> = inputs
+                    .iter()
+                    .zip(&shapes)
+                    .map(|(input, shape)| {
+                        let numel = shape.iter().product::<i64>() as usize;
+                        vec(input.values.0..input.values.1, numel)
+                    })
+                    .collect();
+                (Just(shapes), values)
+            })
+            .prop_map({
+                let kinds: Vec<Kind> = self.inputs.iter().map(|i| i.kind).collect();
+                move |(shapes, values)| {
+                    shapes
+                        .iter()
+                        .zip(&values)
+                        .zip(&kinds)
+                        .map(|((shape, values), &kind)| {
+                            let tensor = Tensor::from_slice(&values[..]);
+                            let tensor = if is_float(kind) {
+                                tensor
+                            } else {
+                                tensor.floor()
+                            };
+                            DeviceTensor {
+                                tensor: tensor.to_kind(kind).reshape(&shape[..]).to_device(device),
+                                _device: PhantomData,
+                            }
+                        })
+                        .collect()
+                }
+            })
+            .boxed())
+    }
+
+    /// Each dynamic dimension name's sizes, after checking the spec.
+    fn names(&self) -> Result<BTreeMap<String, RangeInclusive<i64>>, Error> {
+        for (i, input) in self.inputs.iter().enumerate() {
+            let (low, high) = input.values;
+            if low.is_nan() || high.is_nan() || low >= high {
+                return Err(Error::InvalidInput(format!(
+                    "input {i}'s values [{low}, {high}) are empty"
+                )));
+            }
+            if input.shape.iter().any(|&size| size < 0) {
+                return Err(Error::InvalidInput(format!(
+                    "input {i} has a negative size in {:?}",
+                    input.shape
+                )));
+            }
+        }
+        let mut names = BTreeMap::new();
+        for d in &self.dynamic {
+            let rank = self.inputs.get(d.input).map(|input| input.shape.len());
+            if rank.is_none_or(|rank| d.dim >= rank) {
+                return Err(Error::InvalidInput(format!(
+                    "dynamic dimension '{}' is dimension {} of input {}, which has no such \
+                     dimension",
+                    d.name, d.dim, d.input
+                )));
+            }
+            if d.sizes.is_empty() || *d.sizes.start() < 0 {
+                return Err(Error::InvalidInput(format!(
+                    "dynamic dimension '{}' has no valid sizes in {:?}",
+                    d.name, d.sizes
+                )));
+            }
+            let sizes = names.entry(d.name.clone()).or_insert(d.sizes.clone());
+            if *sizes != d.sizes {
+                return Err(Error::InvalidInput(format!(
+                    "dynamic dimension '{}' is given both {:?} and {:?}",
+                    d.name, sizes, d.sizes
+                )));
+            }
+        }
+        Ok(names)
+    }
+}
+
+fn is_float(kind: Kind) -> bool {
+    matches!(
+        kind,
+        Kind::Half | Kind::BFloat16 | Kind::Float | Kind::Double
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::test_runner::TestRunner;
+
+    use super::*;
+    use crate::Cpu;
+
+    fn spec() -> InputSpec {
+        InputSpec::new()
+            .input(&[2, 16], Kind::Int64)
+            .values(5.0, 7.0)
+            .input(&[2, 16, 4], Kind::Half)
+            .input(&[3], Kind::Bool)
+            .dynamic(0, 0, "batch", 1..=3)
+            .dynamic(0, 1, "seq", 0..=5)
+            .dynamic(1, 0, "batch", 1..=3)
+            .dynamic(1, 1, "seq", 0..=5)
+    }
+
+    #[test]
+    fn drawn_inputs_respect_the_spec() {
+        let strategy = spec().strategy::<Cpu>(tch::Device::Cpu).unwrap();
+        TestRunner::default()
+            .run(&strategy, |inputs| {
+                let (ids, x, mask) = (&inputs[0], &inputs[1], &inputs[2]);
+                assert_eq!(ids.kind(), Kind::Int64);
+                assert_eq!(x.kind(), Kind::Half);
+                assert_eq!(mask.kind(), Kind::Bool);
+                let (batch, seq) = (ids.size()[0], ids.size()[1]);
+                assert!((1..=3).contains(&batch) && (0..=5).contains(&seq));
+                assert_eq!(x.size(), [batch, seq, 4]);
+                assert_eq!(mask.size(), [3]);
+                if ids.numel() > 0 {
+                    assert!(ids.min().int64_value(&[]) >= 5);
+                    assert!(ids.max().int64_value(&[]) <= 6);
+                }
+                Ok(())
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn inconsistent_specs_are_rejected() {
+        let strategy = |spec: InputSpec| spec.strategy::<Cpu>(tch::Device::Cpu).err();
+        assert!(strategy(spec().dynamic(1, 0, "batch", 1..=4)).is_some());
+        assert!(strategy(spec().dynamic(2, 1, "len", 1..=4)).is_some());
+        assert!(strategy(spec().dynamic(2, 0, "len", 4..=1)).is_some());
+        assert!(strategy(spec().input(&[1], Kind::Float).values(1.0, 1.0)).is_some());
+        assert!(strategy(spec()).is_none());
+    }
+}
@@ -0,0 +1,178 @@
+//! Python bindings (feature `python`).
+//!
+//! Exposes [`AnyAOTIModel`] to Python as `aoti_rs.AOTIModel`, so a model
+//! can be prototyped against the exact runtime that serves it. Tensors
+//! cross the boundary as `torch.Tensor` objects through libtorch's own
+//! Python wrappers (tch's `python-extension`), sharing storage rather than
+//! copying: inputs are moved to the model's device only if they aren't
+//! there already, and outputs come back on the model's device.
+//!
+//! Build the extension with maturin, e.g.
+//! `maturin develop --features python`, and `import torch` before
+//! `import aoti_rs` so libtorch's Python types are registered:
+//!
+//! ```python
+//! import torch, aoti_rs
+//! model = aoti_rs.AOTIModel("model.pt2")
+//! (out,) = model.run([torch.randn(1, 3, 224, 224)])
+//! ```
+//!
+//! The GIL is released while the model runs.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use pyo3::exceptions::{PyRuntimeError, PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use tch::Tensor;
+use tch::python::CPyObject;
+
+use crate::{AOTIModel, AnyAOTIModel, Device, Error, device_string};
+
+impl From<Error> for PyErr {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::InvalidInput(_)
+            | Error::InvalidPath(_)
+            | Error::TensorDeviceMismatch { .. }
+            | Error::TensorKindMismatch { .. } => PyValueError::new_err(err.to_string()),
+            _ => PyRuntimeError::new_err(err.to_string()),
+        }
+    }
+}
+
+fn run_typed<D: Device>(model: &mut AOTIModel<D>, inputs: &[Tensor]) -> Result<Vec<Tensor>, Error> {
+    let inputs: Vec<_> = inputs.iter().map(|t| model.upload(t)).collect();
+    Ok(model
+        .run(inputs)?
+        .into_iter()
+        .map(|out| out.into_inner())
+        .collect())
+}
+
+/// A loaded AOTInductor model package.
+#[pyclass(name = "AOTIModel", module = "aoti_rs")]
+pub struct PyAOTIModel {
+    model: Mutex<AnyAOTIModel>,
+    device: String,
+}
+
+impl PyAOTIModel {
+    fn lock(&self) -> PyResult<std::sync::MutexGuard<'_, AnyAOTIModel>> {
+        self.model
+            .lock()
+            .map_err(|_| PyRuntimeError::new_err("model was poisoned by an earlier panic"))
+    }
+}
+
+#[pymethods]
+impl PyAOTIModel {
+    /// Load `model_name` from the package at `path`, on the device its
+    /// metadata names.
+    #[new]
+    #[pyo3(signature = (path, model_name = "model"))]
+    fn new(py: Python<'_>, path: &str, model_name: &str) -> PyResult<Self> {
+        let model = py.detach(|| AnyAOTIModel::load_named(path, model_name))?;
+        let device = match &model {
+            AnyAOTIModel::Cpu(m) => device_string(m.device()),
+            #[cfg(aoti_cuda)]
+            AnyAOTIModel::Cuda(m) => device_string(m.device()),
+        };
+        Ok(Self {
+            model: Mutex::new(model),
+            device,
+        })
+    }
+
+    /// The device the model runs on, e.g. `"cpu"` or `"cuda:0"`.
+    #[getter]
+    fn device(&self) -> &str {
+        &self.device
+    }
+
+    /// Run the model on a list of `torch.Tensor`s, returning a list of
+    /// output tensors on the model's device.
+    fn run(&self, py: Python<'_>, inputs: Vec<Bound<'_, PyAny>>) -> PyResult<Vec<Py<PyAny>>> {
+        let tensors = inputs
+            .iter()
+            .enumerate()
+            .map(|(i, obj)| {
+                // Safety: `obj` is a live Python object for the duration of
+                // the call.
+                unsafe { Tensor::pyobject_unpack(obj.as_ptr() as *mut CPyObject) }
+                    .map_err(|e| PyErr::from(Error::from(e)))?
+                    .ok_or_else(|| {
+                        PyTypeError::new_err(format!(
+                            "input {i} is a {}, not a torch.Tensor",
+                            obj.get_type()
+                        ))
+                    })
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+
+        let outputs = py.detach(move || -> PyResult<Vec<Tensor>> {
+            let mut model = self.lock()?;
+            Ok(match &mut *model {
+                AnyAOTIModel::Cpu(m) => run_typed(m, &tensors),
+                #[cfg(aoti_cuda)]
+                AnyAOTIModel::Cuda(m) => run_typed(m, &tensors),
+            }?)
+        })?;
+
+        outputs
+            .iter()
+            .map(|t| {
+                let ptr = t.pyobject_wrap().map_err(Error::from)?;
+                // Safety: `pyobject_wrap` returns a new reference.
+                Ok(unsafe { Bound::from_owned_ptr(py, ptr as *mut pyo3::ffi::PyObject) }.unbind())
+            })
+            .collect()
+    }
+
+    /// The package's metadata as a `dict[str, str]`.
+    fn metadata(&self) -> PyResult<HashMap<String, String>> {
+        let model = self.lock()?;
+        Ok(match &*model {
+            AnyAOTIModel::Cpu(m) => m.get_metadata(),
+            #[cfg(aoti_cuda)]
+            AnyAOTIModel::Cuda(m) => m.get_metadata(),
+        }?)
+    }
+
+    /// The serialized `(in_spec, out_spec)` pytree specs.
+    fn call_spec(&self) -> PyResult<(String, String)> {
+        let mut model = self.lock()?;
+        let spec = match &mut *model {
+            AnyAOTIModel::Cpu(m) => m.call_spec(),
+            #[cfg(aoti_cuda)]
+            AnyAOTIModel::Cuda(m) => m.call_spec(),
+        }?;
+        Ok((spec.in_spec, spec.out_spec))
+    }
+
+    fn __repr__(&self) -> String {
+        format!("AOTIModel(device={:?})", self.device)
+    }
+}
+
+/// The `aoti_rs` Python module.
+#[pymodule]
+fn aoti_rs(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyAOTIModel>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_input_maps_to_value_error() {
+        Python::initialize();
+        Python::attach(|py| {
+            let err = PyErr::from(Error::InvalidInput("bad shape".into()));
+            assert!(err.is_instance_of::<PyValueError>(py));
+            let err = PyErr::from(Error::Model("boom".into()));
+            assert!(err.is_instance_of::<PyRuntimeError>(py));
+        });
+    }
+}
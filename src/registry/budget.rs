@@ -0,0 +1,77 @@
+//! Device-memory accounting for a budgeted registry: how much a model is
+//! expected to occupy, and which models to evict to make room.
+
+use std::path::Path;
+
+use super::ModelSpec;
+use crate::Error;
+
+/// Bytes `spec` is expected to occupy once loaded: its
+/// [`ModelSpec::memory_bytes`] if set, otherwise the package's
+/// uncompressed size (weights plus compiled kernels) once per replica,
+/// since every replica holds its own copy of the constants.
+pub(super) fn footprint(spec: &ModelSpec) -> Result<u64, Error> {
+    if let Some(bytes) = spec.memory_bytes {
+        return Ok(bytes);
+    }
+    Ok(package_size(&spec.path)?.saturating_mul(spec.replicas as u64))
+}
+
+/// Total uncompressed size of a `.pt2` archive's entries, read from its
+/// central directory without extracting anything.
+fn package_size(path: &Path) -> Result<u64, Error> {
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(path)?)?;
+    let mut total = 0u64;
+    for i in 0..archive.len() {
+        total = total.saturating_add(archive.by_index_raw(i)?.size());
+    }
+    Ok(total)
+}
+
+/// Choose which of the `resident` models — `(key, bytes, last used)` —
+/// to evict, least recently used first, so `incoming` more bytes fit in
+/// `budget`. `incoming` must not exceed `budget` by itself.
+pub(super) fn victims<K>(mut resident: Vec<(K, u64, u64)>, incoming: u64, budget: u64) -> Vec<K> {
+    let mut used: u64 = resident.iter().map(|&(_, bytes, _)| bytes).sum();
+    resident.sort_by_key(|&(_, _, last_used)| last_used);
+    let mut evicted = Vec::new();
+    for (key, bytes, _) in resident {
+        if used.saturating_add(incoming) <= budget {
+            break;
+        }
+        used -= bytes;
+        evicted.push(key);
+    }
+    evicted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn least_recently_used_models_are_evicted_first() {
+        let resident = vec![("a", 40, 3), ("b", 30, 1), ("c", 20, 2)];
+        assert!(victims(resident.clone(), 10, 100).is_empty());
+        assert_eq!(victims(resident.clone(), 20, 100), ["b"]);
+        assert_eq!(victims(resident.clone(), 60, 100), ["b", "c"]);
+        assert_eq!(victims(resident, 100, 100), ["b", "c", "a"]);
+    }
+
+    #[test]
+    fn footprint_scales_package_size_by_replicas() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut zip = zip::ZipWriter::new(file.reopen().unwrap());
+        zip.start_file(
+            "model/data/weights",
+            zip::write::SimpleFileOptions::default(),
+        )
+        .unwrap();
+        zip.write_all(&[0u8; 1000]).unwrap();
+        zip.finish().unwrap();
+        let spec = ModelSpec::new("m", 1, file.path()).replicas(3);
+        assert_eq!(footprint(&spec).unwrap(), 3000);
+        assert_eq!(footprint(&spec.memory_bytes(7)).unwrap(), 7);
+    }
+}
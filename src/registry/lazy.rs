@@ -0,0 +1,165 @@
+//! Models registered to load on first use, with concurrent first requests
+//! sharing a single load.
+
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+
+use super::ModelSpec;
+use crate::Error;
+
+/// A registered version and the load, if any, running for it.
+pub(super) struct Lazy<T> {
+    pub(super) spec: ModelSpec,
+    flight: Mutex<Option<Arc<Flight<T>>>>,
+}
+
+/// One in-progress load; callers arriving meanwhile wait for its result.
+struct Flight<T> {
+    result: Mutex<Option<Result<T, String>>>,
+    done: Condvar,
+}
+
+impl<T: Clone> Lazy<T> {
+    pub(super) fn new(spec: ModelSpec) -> Self {
+        Self {
+            spec,
+            flight: Mutex::new(None),
+        }
+    }
+
+    /// Run `load` unless another caller already is, in which case wait for
+    /// and share its result. A failed load is reported to every caller
+    /// that waited on it; the next call tries again.
+    pub(super) fn load(
+        &self,
+        load: impl FnOnce(&ModelSpec) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let (flight, leader) = {
+            let mut slot = self.flight.lock().unwrap_or_else(PoisonError::into_inner);
+            match &*slot {
+                Some(flight) => (flight.clone(), false),
+                None => {
+                    let flight = Arc::new(Flight {
+                        result: Mutex::new(None),
+                        done: Condvar::new(),
+                    });
+                    *slot = Some(flight.clone());
+                    (flight, true)
+                }
+            }
+        };
+        if !leader {
+            return flight.wait().map_err(|e| {
+                Error::Model(format!(
+                    "loading model '{}' version {} failed: {e}",
+                    self.spec.name, self.spec.version
+                ))
+            });
+        }
+        // Publishes a result even if `load` panics, so waiters never hang.
+        let landing = Landing {
+            lazy: self,
+            flight: &flight,
+        };
+        let result = load(&self.spec);
+        landing.finish(result.as_ref().map(T::clone).map_err(Error::to_string));
+        result
+    }
+}
+
+impl<T> Flight<T> {
+    fn wait(&self) -> Result<T, String>
+    where
+        T: Clone,
+    {
+        let mut result = self.result.lock().unwrap_or_else(PoisonError::into_inner);
+        loop {
+            if let Some(result) = &*result {
+                return result.clone();
+            }
+            result = self
+                .done
+                .wait(result)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+}
+
+/// Ends the leader's flight: records its result for the waiters and clears
+/// the slot, so later calls start afresh.
+struct Landing<'a, T> {
+    lazy: &'a Lazy<T>,
+    flight: &'a Arc<Flight<T>>,
+}
+
+impl<T> Landing<'_, T> {
+    fn finish(self, result: Result<T, String>) {
+        *self
+            .flight
+            .result
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(result);
+    }
+}
+
+impl<T> Drop for Landing<'_, T> {
+    fn drop(&mut self) {
+        let mut result = self
+            .flight
+            .result
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        result.get_or_insert_with(|| Err("the load panicked".into()));
+        drop(result);
+        self.lazy
+            .flight
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+        self.flight.done.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Barrier;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[test]
+    fn concurrent_callers_share_one_load() {
+        let lazy = Lazy::new(ModelSpec::new("m", 1, "m.pt2"));
+        let loads = AtomicUsize::new(0);
+        let start = Barrier::new(4);
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..4)
+                .map(|_| {
+                    scope.spawn(|| {
+                        start.wait();
+                        lazy.load(|spec| {
+                            loads.fetch_add(1, Ordering::SeqCst);
+                            std::thread::sleep(Duration::from_millis(100));
+                            Ok(spec.version)
+                        })
+                    })
+                })
+                .collect();
+            for handle in handles {
+                assert_eq!(handle.join().unwrap().unwrap(), 1);
+            }
+        });
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn failed_or_panicked_loads_are_retried() {
+        let lazy = Lazy::<u64>::new(ModelSpec::new("m", 1, "m.pt2"));
+        let failed = lazy.load(|_| Err(Error::InvalidPath("m.pt2".into())));
+        assert!(matches!(failed, Err(Error::InvalidPath(_))));
+        let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            lazy.load(|_| panic!("boom"))
+        }));
+        assert!(panicked.is_err());
+        assert_eq!(lazy.load(|_| Ok(7)).unwrap(), 7);
+    }
+}
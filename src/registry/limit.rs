@@ -0,0 +1,150 @@
+//! Per-model concurrency limits, so one hot model can't take every
+//! execution slot on a device it shares with others.
+
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+
+use crate::Error;
+
+/// How many requests for one model may run, and wait to run, at once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConcurrencyLimit {
+    /// Requests running at once.
+    pub max_in_flight: usize,
+    /// Requests waiting for a slot; any beyond this are rejected with
+    /// [`Error::Overloaded`].
+    pub max_queued: usize,
+}
+
+impl ConcurrencyLimit {
+    /// At most `max_in_flight` concurrent requests, rejecting the rest
+    /// rather than queueing them.
+    pub fn new(max_in_flight: usize) -> Self {
+        Self {
+            max_in_flight,
+            max_queued: 0,
+        }
+    }
+
+    /// Let up to `n` requests wait for a slot.
+    pub fn queue(mut self, n: usize) -> Self {
+        self.max_queued = n;
+        self
+    }
+}
+
+/// A model's current load against its [`ConcurrencyLimit`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LimitStats {
+    pub in_flight: usize,
+    pub queued: usize,
+    /// Requests rejected with [`Error::Overloaded`] since the limit was set.
+    pub rejected: u64,
+}
+
+/// Counting semaphore with a bounded wait queue.
+pub(super) struct Limiter {
+    model: String,
+    limit: ConcurrencyLimit,
+    state: Mutex<LimitStats>,
+    freed: Condvar,
+}
+
+impl Limiter {
+    pub(super) fn new(model: &str, limit: ConcurrencyLimit) -> Result<Self, Error> {
+        if limit.max_in_flight == 0 {
+            return Err(Error::InvalidInput(
+                "a concurrency limit must allow at least one request".into(),
+            ));
+        }
+        Ok(Self {
+            model: model.to_string(),
+            limit,
+            state: Mutex::new(LimitStats::default()),
+            freed: Condvar::new(),
+        })
+    }
+
+    /// Take a slot, waiting in the queue if there is room in it.
+    pub(super) fn acquire(self: &Arc<Self>) -> Result<Permit, Error> {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        if state.in_flight >= self.limit.max_in_flight {
+            if state.queued >= self.limit.max_queued {
+                state.rejected += 1;
+                return Err(Error::Overloaded {
+                    model: self.model.clone(),
+                    limit: self.limit.max_in_flight,
+                });
+            }
+            state.queued += 1;
+            while state.in_flight >= self.limit.max_in_flight {
+                state = self
+                    .freed
+                    .wait(state)
+                    .unwrap_or_else(PoisonError::into_inner);
+            }
+            state.queued -= 1;
+        }
+        state.in_flight += 1;
+        Ok(Permit(self.clone()))
+    }
+
+    pub(super) fn stats(&self) -> LimitStats {
+        *self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// A slot held until dropped.
+pub(super) struct Permit(Arc<Limiter>);
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let mut state = self.0.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.in_flight -= 1;
+        drop(state);
+        self.0.freed.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_over_the_limit_are_rejected() {
+        let limiter = Arc::new(Limiter::new("m", ConcurrencyLimit::new(2)).unwrap());
+        let a = limiter.acquire().unwrap();
+        let _b = limiter.acquire().unwrap();
+        assert!(matches!(
+            limiter.acquire(),
+            Err(Error::Overloaded { limit: 2, .. })
+        ));
+        drop(a);
+        let _c = limiter.acquire().unwrap();
+        assert_eq!(
+            limiter.stats(),
+            LimitStats {
+                in_flight: 2,
+                queued: 0,
+                rejected: 1
+            }
+        );
+        assert!(Limiter::new("m", ConcurrencyLimit::new(0)).is_err());
+    }
+
+    #[test]
+    fn queued_requests_wait_for_a_slot() {
+        let limiter = Arc::new(Limiter::new("m", ConcurrencyLimit::new(1).queue(1)).unwrap());
+        let held = limiter.acquire().unwrap();
+        let waiter = {
+            let limiter = limiter.clone();
+            std::thread::spawn(move || limiter.acquire().map(drop))
+        };
+        while limiter.stats().queued == 0 {
+            std::thread::yield_now();
+        }
+        assert!(limiter.acquire().is_err());
+        drop(held);
+        waiter.join().unwrap().unwrap();
+        assert_eq!(limiter.stats().in_flight, 0);
+    }
+}
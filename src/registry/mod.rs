@@ -0,0 +1,1029 @@
+//! Named, versioned model pools with load/unload lifecycle.
+//!
+//! [`ModelRegistry`] owns an [`AOTIModelPool`] per `(name, version)` and
+//! hands out `Arc`s to them, so every front-end in a process shares the
+//! same replicas. Models come from explicit [`ModelSpec`]s, a JSON
+//! manifest ([`ModelRegistry::load_manifest`]) or a TF-Serving style
+//! directory tree ([`ModelRegistry::load_dir`]):
+//!
+//! ```text
+//! models/
+//!   resnet/
+//!     1/resnet.pt2
+//!     2/resnet.pt2
+//!   bert/
+//!     7/bert.pt2
+//! ```
+//!
+//! Unloading removes a model from the registry; its replicas are released
+//! once the last in-flight caller drops its `Arc`, never while the
+//! registry's lock is held.
+//!
+//! Reloading ([`ModelRegistry::reload`], or automatically with
+//! [`ModelRegistry::watch`]) loads and warms the new package beside the
+//! old one, then swaps it in: new lookups get the new pool while requests
+//! already holding the old one finish on it. Replace packages by renaming
+//! a fully-written file over the old one so a reload never sees a partial
+//! write.
+//!
+//! Several versions of a model can be loaded at once. By default lookups
+//! get the newest; [`ModelRegistry::set_traffic`] instead splits
+//! [`ModelRegistry::route`] calls across versions by weight, for canary
+//! rollouts, with each version's request count, errors and latency in
+//! [`ModelRegistry::version_stats`]. A version can also shadow a model
+//! ([`ModelRegistry::set_shadow`]): routed requests are mirrored to it in
+//! the background and its outputs compared with the served ones, without
+//! affecting responses. Per-model concurrency limits
+//! ([`ModelRegistry::set_concurrency_limit`]) keep one hot model from
+//! occupying every slot on a device shared with others.
+//!
+//! With a memory budget ([`ModelRegistry::with_memory_budget`]), loading a
+//! model that wouldn't fit first evicts the least recently used ones, so
+//! one GPU can host more models than fit at once. Evicted models are
+//! released like unloaded ones and must be loaded again before use.
+//!
+//! Models can also be registered without loading them
+//! ([`ModelRegistry::register`]): the first request for one through
+//! [`ModelRegistry::get_or_load`] or [`ModelRegistry::route_or_load`], or
+//! an explicit [`ModelRegistry::prefetch`], loads it, and concurrent first
+//! requests wait for that one load rather than starting their own. A
+//! registered model that is later evicted loads again on its next request.
+
+mod budget;
+mod lazy;
+mod limit;
+mod shadow;
+mod traffic;
+
+pub use crate::compare::Tolerance;
+pub use limit::{ConcurrencyLimit, LimitStats};
+pub use shadow::ShadowStats;
+pub use traffic::{Routed, VersionStats};
+
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime};
+
+use serde_json::Value;
+
+use crate::{AOTIModel, AOTIModelPool, AnyAOTIModel, Device, Error};
+use lazy::Lazy;
+use limit::Limiter;
+use shadow::Shadow;
+use traffic::{Counters, TrafficSplit};
+
+/// Where to find one model version and how to load it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModelSpec {
+    /// Name the model is served under.
+    pub name: String,
+    pub version: u64,
+    /// Path of the `.pt2` package.
+    pub path: PathBuf,
+    /// Model name within the package (default: `"model"`).
+    pub model_name: String,
+    /// Replicas in the model's pool (default: 1).
+    pub replicas: usize,
+    /// Device memory the loaded model occupies, counted against the
+    /// registry's memory budget (default: estimated from the package).
+    pub memory_bytes: Option<u64>,
+}
+
+impl ModelSpec {
+    /// A spec with the default package model name and one replica.
+    pub fn new(name: impl Into<String>, version: u64, path: impl Into<PathBuf>) -> Self {
+        Self {
+            name: name.into(),
+            version,
+            path: path.into(),
+            model_name: "model".to_string(),
+            replicas: 1,
+            memory_bytes: None,
+        }
+    }
+
+    /// Set the model name within the package.
+    pub fn model_name(mut self, name: impl Into<String>) -> Self {
+        self.model_name = name.into();
+        self
+    }
+
+    /// Set the number of replicas.
+    pub fn replicas(mut self, n: usize) -> Self {
+        self.replicas = n;
+        self
+    }
+
+    /// Set the device memory the loaded model occupies, e.g. as measured,
+    /// instead of estimating it from the package.
+    pub fn memory_bytes(mut self, bytes: u64) -> Self {
+        self.memory_bytes = Some(bytes);
+        self
+    }
+}
+
+type Loader<D> = dyn Fn(&ModelSpec) -> Result<AOTIModel<D>, Error> + Send + Sync;
+type Warmup<D> = dyn Fn(&ModelSpec, &AOTIModelPool<D>) -> Result<(), Error> + Send + Sync;
+type Registered<D> = BTreeMap<String, BTreeMap<u64, Arc<Lazy<Arc<AOTIModelPool<D>>>>>>;
+
+/// A loaded version: its spec, shared pool, the package's modification
+/// time when it was loaded, its routing stats (kept across reloads), and
+/// its memory footprint and last lookup for budgeted eviction.
+struct Entry<D: Device> {
+    spec: ModelSpec,
+    pool: Arc<AOTIModelPool<D>>,
+    modified: Option<SystemTime>,
+    counters: Arc<Counters>,
+    bytes: u64,
+    last_used: AtomicU64,
+}
+
+/// Models served by one process, by name and version.
+///
+/// The registry is `Send + Sync`; loads run without holding its lock, so
+/// lookups keep working while a large package is being loaded.
+pub struct ModelRegistry<D: Device> {
+    loader: Box<Loader<D>>,
+    warmup: Option<Box<Warmup<D>>>,
+    models: RwLock<BTreeMap<String, BTreeMap<u64, Entry<D>>>>,
+    traffic: RwLock<HashMap<String, TrafficSplit>>,
+    shadows: RwLock<HashMap<String, Arc<Shadow<D>>>>,
+    limits: RwLock<HashMap<String, Arc<Limiter>>>,
+    /// Versions to load on first use, kept once loaded so they can load
+    /// again after an eviction.
+    registered: RwLock<Registered<D>>,
+    memory_budget: Option<u64>,
+    /// Serializes budgeted loads, so two can't both claim the same room.
+    loading: Mutex<()>,
+    /// Logical clock stamping each entry's last lookup.
+    clock: AtomicU64,
+}
+
+impl<D: Device> Default for ModelRegistry<D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<D: Device> ModelRegistry<D> {
+    /// An empty registry loading replicas with [`AnyAOTIModel::load_named`].
+    pub fn new() -> Self {
+        Self::with_loader(|spec| {
+            AnyAOTIModel::load_named(&spec.path, &spec.model_name)?.try_into_typed()
+        })
+    }
+
+    /// An empty registry loading each replica with `loader`, e.g. to pick a
+    /// CUDA device or runner count per model.
+    pub fn with_loader(
+        loader: impl Fn(&ModelSpec) -> Result<AOTIModel<D>, Error> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            loader: Box::new(loader),
+            warmup: None,
+            models: RwLock::new(BTreeMap::new()),
+            traffic: RwLock::new(HashMap::new()),
+            shadows: RwLock::new(HashMap::new()),
+            limits: RwLock::new(HashMap::new()),
+            registered: RwLock::new(BTreeMap::new()),
+            memory_budget: None,
+            loading: Mutex::new(()),
+            clock: AtomicU64::new(0),
+        }
+    }
+
+    /// Run `warmup` on every freshly loaded pool before it becomes visible,
+    /// e.g. a few representative inferences so the first real request
+    /// doesn't pay for lazy initialization. A failing warmup fails the load.
+    pub fn with_warmup(
+        mut self,
+        warmup: impl Fn(&ModelSpec, &AOTIModelPool<D>) -> Result<(), Error> + Send + Sync + 'static,
+    ) -> Self {
+        self.warmup = Some(Box::new(warmup));
+        self
+    }
+
+    /// Keep loaded models within `bytes` of device memory, evicting the
+    /// least recently looked-up models when a load wouldn't fit. Footprints
+    /// are [`ModelSpec::memory_bytes`] or, by default, the package's
+    /// uncompressed size per replica; custom loaders reading something
+    /// other than `.pt2` archives must set them.
+    ///
+    /// Budgeted loads run one at a time. A version being reloaded isn't
+    /// evicted for its own replacement, so headroom for both copies is
+    /// needed while the new one loads.
+    pub fn with_memory_budget(mut self, bytes: u64) -> Self {
+        self.memory_budget = Some(bytes);
+        self
+    }
+
+    /// The budget set with [`ModelRegistry::with_memory_budget`].
+    pub fn memory_budget(&self) -> Option<u64> {
+        self.memory_budget
+    }
+
+    /// Device memory counted against the budget by the loaded models.
+    pub fn memory_used(&self) -> u64 {
+        let models = self.models.read().unwrap_or_else(PoisonError::into_inner);
+        models
+            .values()
+            .flat_map(BTreeMap::values)
+            .map(|entry| entry.bytes)
+            .sum()
+    }
+
+    /// Load `spec`, replacing any model already registered under the same
+    /// name and version. With a memory budget, least recently used models
+    /// are evicted first to make room; a model larger than the whole budget
+    /// fails with [`Error::InvalidInput`].
+    pub fn load(&self, spec: ModelSpec) -> Result<Arc<AOTIModelPool<D>>, Error> {
+        let _loading = self
+            .memory_budget
+            .map(|_| self.loading.lock().unwrap_or_else(PoisonError::into_inner));
+        let bytes = match self.memory_budget {
+            Some(budget) => {
+                let bytes = budget::footprint(&spec)?;
+                if bytes > budget {
+                    return Err(Error::InvalidInput(format!(
+                        "model '{}' version {} needs {bytes} bytes, over the {budget}-byte memory budget",
+                        spec.name, spec.version
+                    )));
+                }
+                self.make_room(&spec, bytes, budget);
+                bytes
+            }
+            None => spec.memory_bytes.unwrap_or(0),
+        };
+        // Taken before loading, so a write racing the load is seen as a
+        // change by the next reload check.
+        let modified = modified(&spec.path);
+        let pool = Arc::new(AOTIModelPool::from_fn(spec.replicas, |_| {
+            (self.loader)(&spec)
+        })?);
+        if let Some(warmup) = &self.warmup {
+            warmup(&spec, &pool)?;
+        }
+        let mut models = self.models.write().unwrap_or_else(PoisonError::into_inner);
+        let versions = models.entry(spec.name.clone()).or_default();
+        let counters = versions
+            .get(&spec.version)
+            .map(|old| old.counters.clone())
+            .unwrap_or_default();
+        let replaced = versions.insert(
+            spec.version,
+            Entry {
+                spec,
+                pool: pool.clone(),
+                modified,
+                counters,
+                bytes,
+                last_used: AtomicU64::new(self.tick()),
+            },
+        );
+        // A replaced version is dropped after the lock is released.
+        drop(models);
+        drop(replaced);
+        Ok(pool)
+    }
+
+    /// Evict least recently used versions, other than the one `spec`
+    /// replaces, until `bytes` more fit in `budget`.
+    fn make_room(&self, spec: &ModelSpec, bytes: u64, budget: u64) {
+        let mut models = self.models.write().unwrap_or_else(PoisonError::into_inner);
+        let resident = models
+            .iter()
+            .flat_map(|(name, versions)| {
+                versions
+                    .iter()
+                    .map(move |(&version, entry)| ((name.clone(), version), entry))
+            })
+            .filter(|((name, version), _)| (name, *version) != (&spec.name, spec.version))
+            .map(|(key, entry)| (key, entry.bytes, entry.last_used.load(Ordering::Relaxed)))
+            .collect();
+        let mut evicted = Vec::new();
+        let mut emptied = Vec::new();
+        for (name, version) in budget::victims(resident, bytes, budget) {
+            let Some(versions) = models.get_mut(&name) else {
+                continue;
+            };
+            evicted.extend(versions.remove(&version));
+            if versions.is_empty() {
+                models.remove(&name);
+                emptied.push(name);
+            }
+        }
+        // Evicted pools are released after the lock, once their in-flight
+        // callers finish.
+        drop(models);
+        let mut shadows = self.shadows.write().unwrap_or_else(PoisonError::into_inner);
+        shadows.retain(|name, shadow| {
+            !evicted
+                .iter()
+                .any(|e| &e.spec.name == name && e.spec.version == shadow.version())
+        });
+        drop(shadows);
+        for name in emptied {
+            self.clear_traffic(&name);
+        }
+        drop(evicted);
+    }
+
+    /// Stamp a lookup for LRU eviction.
+    fn tick(&self) -> u64 {
+        self.clock.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    fn touch(&self, entry: &Entry<D>) {
+        entry.last_used.store(self.tick(), Ordering::Relaxed);
+    }
+
+    /// Register `spec` to be loaded on its first request instead of now,
+    /// replacing any registration under the same name and version. Nothing
+    /// is read from disk until then, so a bad package only fails the
+    /// requests for it.
+    pub fn register(&self, spec: ModelSpec) {
+        self.registered
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(spec.name.clone())
+            .or_default()
+            .insert(spec.version, Arc::new(Lazy::new(spec)));
+    }
+
+    /// Load `name`'s registered `version` now, if it isn't loaded already,
+    /// e.g. ahead of expected traffic. Concurrent calls (and first
+    /// requests) share one load. Fails with [`Error::InvalidInput`] if the
+    /// version is neither loaded nor registered.
+    pub fn prefetch(&self, name: &str, version: u64) -> Result<Arc<AOTIModelPool<D>>, Error> {
+        if let Some(pool) = self.get_version(name, version) {
+            return Ok(pool);
+        }
+        let lazy = self
+            .registered
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(name)
+            .and_then(|versions| versions.get(&version))
+            .cloned()
+            .ok_or_else(|| {
+                Error::InvalidInput(format!(
+                    "model '{name}' version {version} is not registered"
+                ))
+            })?;
+        lazy.load(|spec| {
+            // A load that finished just before this one started counts.
+            match self.get_version(name, version) {
+                Some(pool) => Ok(pool),
+                None => self.load(spec.clone()),
+            }
+        })
+    }
+
+    /// The newest version of `name`, loading it first if it is only
+    /// registered. Fails with [`Error::InvalidInput`] if `name` is neither
+    /// loaded nor registered.
+    pub fn get_or_load(&self, name: &str) -> Result<Arc<AOTIModelPool<D>>, Error> {
+        match self.newest_known(name) {
+            Some(version) => self.prefetch(name, version),
+            None => Err(Error::InvalidInput(format!(
+                "model '{name}' is not registered"
+            ))),
+        }
+    }
+
+    /// [`ModelRegistry::route`], first loading the newest registered
+    /// version of `name` if none is loaded.
+    pub fn route_or_load(&self, name: &str) -> Result<Routed<D>, Error> {
+        if let Some(routed) = self.route(name) {
+            return Ok(routed);
+        }
+        self.get_or_load(name)?;
+        // Only an unload racing this call leaves nothing to route to.
+        self.route(name)
+            .ok_or_else(|| Error::InvalidInput(format!("model '{name}' is not loaded")))
+    }
+
+    /// The newest version of `name` that is loaded or registered.
+    fn newest_known(&self, name: &str) -> Option<u64> {
+        let loaded = self
+            .models
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(name)
+            .and_then(|versions| versions.last_key_value().map(|(&v, _)| v));
+        let registered = self
+            .registered
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(name)
+            .and_then(|versions| versions.last_key_value().map(|(&v, _)| v));
+        loaded.max(registered)
+    }
+
+    /// Load `name`'s `version` again from its package and swap it in.
+    ///
+    /// Fails with [`Error::InvalidInput`] if that version isn't loaded; if
+    /// loading or warming fails, the old pool stays in place.
+    pub fn reload(&self, name: &str, version: u64) -> Result<Arc<AOTIModelPool<D>>, Error> {
+        let spec = {
+            let models = self.models.read().unwrap_or_else(PoisonError::into_inner);
+            models
+                .get(name)
+                .and_then(|versions| versions.get(&version))
+                .map(|entry| entry.spec.clone())
+        };
+        let spec = spec.ok_or_else(|| {
+            Error::InvalidInput(format!("model '{name}' version {version} is not loaded"))
+        })?;
+        self.load(spec)
+    }
+
+    /// Specs of loaded versions whose package file has changed on disk
+    /// since it was loaded.
+    pub fn changed(&self) -> Vec<ModelSpec> {
+        let models = self.models.read().unwrap_or_else(PoisonError::into_inner);
+        models
+            .values()
+            .flat_map(BTreeMap::values)
+            .filter(|entry| modified(&entry.spec.path).is_some_and(|m| Some(m) != entry.modified))
+            .map(|entry| entry.spec.clone())
+            .collect()
+    }
+
+    /// Poll package files every `interval` on a background thread,
+    /// reloading versions whose file changed and reporting each attempt to
+    /// `on_reload`. Polling stops when the returned watcher is dropped.
+    pub fn watch(
+        self: &Arc<Self>,
+        interval: Duration,
+        mut on_reload: impl FnMut(&ModelSpec, Result<(), Error>) + Send + 'static,
+    ) -> RegistryWatcher {
+        let registry = Arc::downgrade(self);
+        let (stop, stopped) = mpsc::channel::<()>();
+        let thread = std::thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                let Some(registry) = registry.upgrade() else {
+                    return;
+                };
+                for spec in registry.changed() {
+                    let result = registry.load(spec.clone()).map(drop);
+                    on_reload(&spec, result);
+                }
+            }
+        });
+        RegistryWatcher {
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+
+    /// Load every model version found under `root` (`<name>/<version>/` with
+    /// one `.pt2` file each), returning their specs.
+    pub fn load_dir(&self, root: impl AsRef<Path>) -> Result<Vec<ModelSpec>, Error> {
+        self.load_all(scan_dir(root.as_ref())?)
+    }
+
+    /// [`ModelRegistry::load_dir`], but [registering](ModelRegistry::register)
+    /// the versions found instead of loading them.
+    pub fn register_dir(&self, root: impl AsRef<Path>) -> Result<Vec<ModelSpec>, Error> {
+        Ok(self.register_all(scan_dir(root.as_ref())?))
+    }
+
+    /// Load every model listed in a JSON manifest, returning their specs:
+    ///
+    /// ```json
+    /// {"models": [{"name": "resnet", "version": 2, "path": "resnet-2.pt2",
+    ///              "model_name": "model", "replicas": 4}]}
+    /// ```
+    ///
+    /// `model_name`, `replicas` and `memory_bytes` are optional; relative
+    /// paths are resolved against the manifest's directory.
+    pub fn load_manifest(&self, path: impl AsRef<Path>) -> Result<Vec<ModelSpec>, Error> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)?;
+        let base = path.parent().unwrap_or(Path::new(""));
+        self.load_all(parse_manifest(&text, base)?)
+    }
+
+    /// [`ModelRegistry::load_manifest`], but
+    /// [registering](ModelRegistry::register) the models listed instead of
+    /// loading them.
+    pub fn register_manifest(&self, path: impl AsRef<Path>) -> Result<Vec<ModelSpec>, Error> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)?;
+        let base = path.parent().unwrap_or(Path::new(""));
+        Ok(self.register_all(parse_manifest(&text, base)?))
+    }
+
+    fn load_all(&self, specs: Vec<ModelSpec>) -> Result<Vec<ModelSpec>, Error> {
+        for spec in &specs {
+            self.load(spec.clone())?;
+        }
+        Ok(specs)
+    }
+
+    fn register_all(&self, specs: Vec<ModelSpec>) -> Vec<ModelSpec> {
+        for spec in &specs {
+            self.register(spec.clone());
+        }
+        specs
+    }
+
+    /// The newest loaded version of `name`.
+    pub fn get(&self, name: &str) -> Option<Arc<AOTIModelPool<D>>> {
+        let models = self.models.read().unwrap_or_else(PoisonError::into_inner);
+        let (_, entry) = models.get(name)?.last_key_value()?;
+        self.touch(entry);
+        Some(entry.pool.clone())
+    }
+
+    /// A specific version of `name`.
+    pub fn get_version(&self, name: &str, version: u64) -> Option<Arc<AOTIModelPool<D>>> {
+        let models = self.models.read().unwrap_or_else(PoisonError::into_inner);
+        let entry = models.get(name)?.get(&version)?;
+        self.touch(entry);
+        Some(entry.pool.clone())
+    }
+
+    /// Split [`ModelRegistry::route`] calls for `name` across versions in
+    /// proportion to their weights, e.g. `&[(1, 95), (2, 5)]` for a 5%
+    /// canary of version 2. Versions must be loaded; if one is unloaded
+    /// later, its share goes to the rest.
+    pub fn set_traffic(&self, name: &str, weights: &[(u64, u32)]) -> Result<(), Error> {
+        let models = self.models.read().unwrap_or_else(PoisonError::into_inner);
+        let versions = models
+            .get(name)
+            .ok_or_else(|| Error::InvalidInput(format!("model '{name}' is not loaded")))?;
+        if let Some((version, _)) = weights.iter().find(|(v, _)| !versions.contains_key(v)) {
+            return Err(Error::InvalidInput(format!(
+                "model '{name}' version {version} is not loaded"
+            )));
+        }
+        let split = TrafficSplit::new(weights)?;
+        self.traffic
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(name.to_string(), split);
+        Ok(())
+    }
+
+    /// Send all of `name`'s routed traffic to its newest version again.
+    pub fn clear_traffic(&self, name: &str) {
+        self.traffic
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(name);
+    }
+
+    /// Pick a version of `name` according to its traffic split (or the
+    /// newest, without one).
+    pub fn route(&self, name: &str) -> Option<Routed<D>> {
+        self.route_with(name, None)
+    }
+
+    /// Like [`ModelRegistry::route`], but requests with equal `key` (a user
+    /// or session id, say) always get the same version while the split is
+    /// unchanged.
+    pub fn route_by_key(&self, name: &str, key: impl Hash) -> Option<Routed<D>> {
+        let mut hasher = std::hash::DefaultHasher::new();
+        key.hash(&mut hasher);
+        self.route_with(name, Some(hasher.finish()))
+    }
+
+    fn route_with(&self, name: &str, key: Option<u64>) -> Option<Routed<D>> {
+        let models = self.models.read().unwrap_or_else(PoisonError::into_inner);
+        let versions = models.get(name)?;
+        let picked = self
+            .traffic
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(name)
+            .and_then(|split| split.pick(|v| versions.contains_key(&v), key));
+        let (&version, entry) = match picked {
+            Some(version) => versions.get_key_value(&version)?,
+            None => versions.last_key_value()?,
+        };
+        self.touch(entry);
+        let shadow = self
+            .shadows
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(name)
+            .filter(|shadow| shadow.version() != version)
+            .cloned();
+        let limiter = self
+            .limits
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(name)
+            .cloned();
+        Some(Routed {
+            name: name.to_string(),
+            version,
+            pool: entry.pool.clone(),
+            counters: entry.counters.clone(),
+            shadow,
+            limiter,
+        })
+    }
+
+    /// Cap the runs made through [`Routed`] for `name`, across all its
+    /// versions. Requests beyond `limit.max_in_flight` wait in a queue of up
+    /// to `limit.max_queued`, and any beyond that fail with
+    /// [`Error::Overloaded`] so callers can shed load or retry elsewhere.
+    ///
+    /// The limit applies to later routes, and is kept across reloads and
+    /// evictions but removed by [`ModelRegistry::unload`]. It needn't be set
+    /// before the model is loaded.
+    pub fn set_concurrency_limit(&self, name: &str, limit: ConcurrencyLimit) -> Result<(), Error> {
+        let limiter = Arc::new(Limiter::new(name, limit)?);
+        self.limits
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(name.to_string(), limiter);
+        Ok(())
+    }
+
+    /// Let `name`'s routed runs proceed without limit again.
+    pub fn clear_concurrency_limit(&self, name: &str) {
+        self.limits
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(name);
+    }
+
+    /// `name`'s current load against its concurrency limit.
+    pub fn limit_stats(&self, name: &str) -> Option<LimitStats> {
+        let limits = self.limits.read().unwrap_or_else(PoisonError::into_inner);
+        Some(limits.get(name)?.stats())
+    }
+
+    /// Mirror requests routed to `name` to its `version`, comparing that
+    /// version's outputs with the served ones within `tolerance`. Mirroring
+    /// never delays or fails a request: copies are queued for a background
+    /// thread and dropped when it falls behind. Requests routed to the
+    /// shadow version itself aren't mirrored.
+    ///
+    /// The shadow keeps the pool it starts with; call this again after
+    /// reloading its version.
+    pub fn set_shadow(&self, name: &str, version: u64, tolerance: Tolerance) -> Result<(), Error> {
+        let pool = self.get_version(name, version).ok_or_else(|| {
+            Error::InvalidInput(format!("model '{name}' version {version} is not loaded"))
+        })?;
+        let shadow = Arc::new(Shadow::spawn(version, pool, tolerance));
+        self.shadows
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(name.to_string(), shadow);
+        Ok(())
+    }
+
+    /// Stop mirroring `name`'s traffic, returning the final comparison.
+    pub fn clear_shadow(&self, name: &str) -> Option<ShadowStats> {
+        let shadow = self
+            .shadows
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(name)?;
+        Some(shadow.stats())
+    }
+
+    /// How `name`'s shadow has compared so far.
+    pub fn shadow_stats(&self, name: &str) -> Option<ShadowStats> {
+        let shadows = self.shadows.read().unwrap_or_else(PoisonError::into_inner);
+        Some(shadows.get(name)?.stats())
+    }
+
+    /// Stats of each loaded version of `name` for requests made through
+    /// [`Routed`], oldest version first.
+    pub fn version_stats(&self, name: &str) -> Vec<(u64, VersionStats)> {
+        let models = self.models.read().unwrap_or_else(PoisonError::into_inner);
+        models
+            .get(name)
+            .map(|versions| {
+                versions
+                    .iter()
+                    .map(|(&version, entry)| (version, entry.counters.snapshot()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Names of the loaded models, sorted.
+    pub fn names(&self) -> Vec<String> {
+        let models = self.models.read().unwrap_or_else(PoisonError::into_inner);
+        models.keys().cloned().collect()
+    }
+
+    /// Loaded versions of `name`, oldest first.
+    pub fn versions(&self, name: &str) -> Vec<u64> {
+        let models = self.models.read().unwrap_or_else(PoisonError::into_inner);
+        models
+            .get(name)
+            .map(|versions| versions.keys().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// Specs of every loaded model version.
+    pub fn specs(&self) -> Vec<ModelSpec> {
+        let models = self.models.read().unwrap_or_else(PoisonError::into_inner);
+        models
+            .values()
+            .flat_map(|versions| versions.values().map(|entry| entry.spec.clone()))
+            .collect()
+    }
+
+    /// Unload and unregister every version of `name`, returning whether it
+    /// was loaded or registered.
+    pub fn unload(&self, name: &str) -> bool {
+        let unregistered = self
+            .registered
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(name);
+        let removed = self
+            .models
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(name);
+        self.clear_traffic(name);
+        self.clear_shadow(name);
+        self.clear_concurrency_limit(name);
+        removed.is_some() || unregistered.is_some()
+    }
+
+    /// Unload and unregister one version of `name`, returning whether it
+    /// was loaded or registered.
+    pub fn unload_version(&self, name: &str, version: u64) -> bool {
+        let unregistered = remove_version(
+            &mut self
+                .registered
+                .write()
+                .unwrap_or_else(PoisonError::into_inner),
+            name,
+            version,
+        );
+        let removed = remove_version(
+            &mut self.models.write().unwrap_or_else(PoisonError::into_inner),
+            name,
+            version,
+        );
+        // Dropped here, after the lock is released.
+        removed.is_some() || unregistered.is_some()
+    }
+}
+
+/// Remove `name`'s `version` from `map`, and `name` too once it has none.
+fn remove_version<T>(
+    map: &mut BTreeMap<String, BTreeMap<u64, T>>,
+    name: &str,
+    version: u64,
+) -> Option<T> {
+    let versions = map.get_mut(name)?;
+    let removed = versions.remove(&version);
+    if versions.is_empty() {
+        map.remove(name);
+    }
+    removed
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Stops a [`ModelRegistry::watch`] polling thread when dropped.
+pub struct RegistryWatcher {
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for RegistryWatcher {
+    fn drop(&mut self) {
+        // Dropping the sender wakes the thread, which then exits.
+        self.stop.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Find `<name>/<version>/<file>.pt2` packages under `root`. Entries whose
+/// directory name isn't a version number are skipped.
+fn scan_dir(root: &Path) -> Result<Vec<ModelSpec>, Error> {
+    let mut specs = Vec::new();
+    for model_dir in sorted_dirs(root)? {
+        let name = model_dir
+            .file_name()
+            .and_then(|n| n.to_str())
+            .ok_or_else(|| {
+                Error::InvalidPath(format!("{} is not valid UTF-8", model_dir.display()))
+            })?
+            .to_string();
+        for version_dir in sorted_dirs(&model_dir)? {
+            let Some(version) = version_dir
+                .file_name()
+                .and_then(|n| n.to_str())
+                .and_then(|n| n.parse::<u64>().ok())
+            else {
+                continue;
+            };
+            let packages: Vec<PathBuf> = std::fs::read_dir(&version_dir)?
+                .map(|entry| Ok(entry?.path()))
+                .collect::<Result<Vec<_>, std::io::Error>>()?
+                .into_iter()
+                .filter(|p| p.extension().is_some_and(|e| e == "pt2"))
+                .collect();
+            match packages.as_slice() {
+                [package] => specs.push(ModelSpec::new(name.clone(), version, package)),
+                [] => {
+                    return Err(Error::InvalidPath(format!(
+                        "no .pt2 package in {}",
+                        version_dir.display()
+                    )));
+                }
+                _ => {
+                    return Err(Error::InvalidPath(format!(
+                        "multiple .pt2 packages in {}",
+                        version_dir.display()
+                    )));
+                }
+            }
+        }
+    }
+    Ok(specs)
+}
+
+fn sorted_dirs(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut dirs = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            dirs.push(entry.path());
+        }
+    }
+    dirs.sort();
+    Ok(dirs)
+}
+
+/// Parse a registry manifest, resolving relative paths against `base`.
+fn parse_manifest(text: &str, base: &Path) -> Result<Vec<ModelSpec>, Error> {
+    let value: Value = serde_json::from_str(text)?;
+    let entries = value
+        .get("models")
+        .and_then(Value::as_array)
+        .ok_or_else(|| Error::InvalidInput("manifest has no \"models\" array".into()))?;
+    entries
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| {
+            let field = |key: &str| {
+                entry.get(key).ok_or_else(|| {
+                    Error::InvalidInput(format!("manifest model {i} has no \"{key}\""))
+                })
+            };
+            let invalid = |key: &str| {
+                Error::InvalidInput(format!("manifest model {i} has an invalid \"{key}\""))
+            };
+            let name = field("name")?.as_str().ok_or_else(|| invalid("name"))?;
+            let version = field("version")?
+                .as_u64()
+                .ok_or_else(|| invalid("version"))?;
+            let path = field("path")?.as_str().ok_or_else(|| invalid("path"))?;
+            let mut spec = ModelSpec::new(name, version, base.join(path));
+            if let Some(model_name) = entry.get("model_name") {
+                spec.model_name = model_name
+                    .as_str()
+                    .ok_or_else(|| invalid("model_name"))?
+                    .to_string();
+            }
+            if let Some(replicas) = entry.get("replicas") {
+                spec.replicas = replicas
+                    .as_u64()
+                    .filter(|&n| n > 0)
+                    .ok_or_else(|| invalid("replicas"))? as usize;
+            }
+            if let Some(bytes) = entry.get("memory_bytes") {
+                spec.memory_bytes = Some(bytes.as_u64().ok_or_else(|| invalid("memory_bytes"))?);
+            }
+            Ok(spec)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Cpu;
+
+    #[test]
+    fn manifests_resolve_paths_and_defaults() {
+        let specs = parse_manifest(
+            r#"{"models": [
+                {"name": "a", "version": 2, "path": "a.pt2", "replicas": 3},
+                {"name": "b", "version": 1, "path": "/abs/b.pt2", "model_name": "m"}
+            ]}"#,
+            Path::new("/srv"),
+        )
+        .unwrap();
+        assert_eq!(
+            specs,
+            [
+                ModelSpec::new("a", 2, "/srv/a.pt2").replicas(3),
+                ModelSpec::new("b", 1, "/abs/b.pt2").model_name("m"),
+            ]
+        );
+        assert!(matches!(
+            parse_manifest(r#"{"models": [{"name": "a", "path": "a.pt2"}]}"#, Path::new("")),
+            Err(Error::InvalidInput(m)) if m.contains("version")
+        ));
+    }
+
+    #[test]
+    fn directory_layout_is_scanned_by_name_and_version() {
+        let root = tempfile::tempdir().unwrap();
+        for dir in ["resnet/1", "resnet/10", "resnet/latest", "bert/3"] {
+            std::fs::create_dir_all(root.path().join(dir)).unwrap();
+        }
+        for file in [
+            "resnet/1/r.pt2",
+            "resnet/10/r.pt2",
+            "bert/3/b.pt2",
+            "bert/3/README",
+        ] {
+            std::fs::write(root.path().join(file), b"").unwrap();
+        }
+        let found: Vec<_> = scan_dir(root.path())
+            .unwrap()
+            .into_iter()
+            .map(|s| (s.name, s.version))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("bert".into(), 3),
+                ("resnet".into(), 1),
+                ("resnet".into(), 10)
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_models_are_absent() {
+        let registry = ModelRegistry::<Cpu>::new();
+        assert!(registry.get("missing").is_none());
+        assert!(registry.versions("missing").is_empty());
+        assert!(!registry.unload("missing"));
+        assert!(matches!(
+            registry.reload("missing", 1),
+            Err(Error::InvalidInput(_))
+        ));
+        assert!(registry.changed().is_empty());
+        assert!(registry.route("missing").is_none());
+        assert!(registry.set_traffic("missing", &[(1, 1)]).is_err());
+        assert!(
+            registry
+                .set_shadow("missing", 1, Tolerance::default())
+                .is_err()
+        );
+        assert!(
+            registry
+                .load(ModelSpec::new("m", 1, "/nonexistent.pt2"))
+                .is_err()
+        );
+        assert!(registry.names().is_empty());
+    }
+
+    #[test]
+    fn registered_models_load_on_first_request() {
+        let registry = ModelRegistry::<Cpu>::new();
+        registry.register(ModelSpec::new("m", 1, "/nonexistent.pt2"));
+        assert!(registry.get("m").is_none());
+        assert!(registry.names().is_empty());
+        assert!(matches!(registry.get_or_load("m"), Err(Error::Io(_))));
+        assert!(registry.route_or_load("m").is_err());
+        assert!(matches!(
+            registry.prefetch("m", 2),
+            Err(Error::InvalidInput(m)) if m.contains("not registered")
+        ));
+        assert!(registry.unload_version("m", 1));
+        assert!(matches!(
+            registry.get_or_load("m"),
+            Err(Error::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn models_over_the_memory_budget_are_refused() {
+        let registry = ModelRegistry::<Cpu>::new().with_memory_budget(100);
+        assert!(matches!(
+            registry.load(ModelSpec::new("m", 1, "/nonexistent.pt2").memory_bytes(101)),
+            Err(Error::InvalidInput(m)) if m.contains("memory budget")
+        ));
+        assert_eq!(registry.memory_used(), 0);
+    }
+}
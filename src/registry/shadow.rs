@@ -0,0 +1,199 @@
+//! Shadow deployment: mirror a model's traffic to another version and
+//! compare outputs without affecting responses.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, SyncSender};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use crate::compare::{Tolerance, compare_outputs};
+use crate::{AOTIModelPool, Device, DeviceTensor};
+
+/// Mirrored requests waiting for the shadow beyond this are dropped, so a
+/// slow shadow never backs up the primary.
+const QUEUE_DEPTH: usize = 64;
+
+/// How a shadow version's outputs compared with the primary's.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ShadowStats {
+    /// Version receiving the mirrored traffic.
+    pub version: u64,
+    /// Requests queued for the shadow.
+    pub mirrored: u64,
+    /// Requests not mirrored because the shadow's queue was full.
+    pub dropped: u64,
+    /// Mirrored requests whose outputs were within tolerance.
+    pub matched: u64,
+    /// Mirrored requests with an output out of tolerance or of a different
+    /// shape, dtype or count.
+    pub diverged: u64,
+    /// Mirrored requests the shadow failed to run.
+    pub errors: u64,
+    /// Largest element-wise absolute difference seen.
+    pub max_abs_diff: f64,
+    /// Why the most recent diverging request diverged.
+    pub last_divergence: Option<String>,
+}
+
+#[derive(Default)]
+struct Counters {
+    mirrored: AtomicU64,
+    dropped: AtomicU64,
+    matched: AtomicU64,
+    diverged: AtomicU64,
+    errors: AtomicU64,
+    /// `f64` bits; non-negative floats order like their bit patterns.
+    max_abs_diff: AtomicU64,
+    last_divergence: Mutex<Option<String>>,
+}
+
+type Job<D> = (Vec<DeviceTensor<D>>, Vec<DeviceTensor<D>>);
+
+/// A shadow version fed by a bounded queue and a comparison thread.
+pub(super) struct Shadow<D: Device> {
+    version: u64,
+    queue: SyncSender<Job<D>>,
+    counters: Arc<Counters>,
+}
+
+impl<D: Device> Shadow<D> {
+    /// Start comparing `pool`'s outputs against mirrored primary outputs.
+    /// The thread exits once the shadow is dropped.
+    pub(super) fn spawn(version: u64, pool: Arc<AOTIModelPool<D>>, tolerance: Tolerance) -> Self {
+        let (queue, jobs) = mpsc::sync_channel::<Job<D>>(QUEUE_DEPTH);
+        let counters = Arc::new(Counters::default());
+        let stats = counters.clone();
+        std::thread::spawn(move || {
+            for (inputs, expected) in jobs {
+                match pool.run(inputs) {
+                    Ok(actual) => match compare(&expected, &actual, tolerance) {
+                        Ok(diff) => {
+                            stats
+                                .max_abs_diff
+                                .fetch_max(diff.to_bits(), Ordering::Relaxed);
+                            stats.matched.fetch_add(1, Ordering::Relaxed);
+                        }
+                        Err(reason) => {
+                            stats.diverged.fetch_add(1, Ordering::Relaxed);
+                            *stats
+                                .last_divergence
+                                .lock()
+                                .unwrap_or_else(PoisonError::into_inner) = Some(reason);
+                        }
+                    },
+                    Err(_) => {
+                        stats.errors.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            }
+        });
+        Self {
+            version,
+            queue,
+            counters,
+        }
+    }
+
+    pub(super) fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Queue a primary request's inputs, already copied with
+    /// [`deep_copy`], and a copy of its outputs for comparison.
+    pub(super) fn mirror(&self, inputs: Vec<DeviceTensor<D>>, outputs: &[DeviceTensor<D>]) {
+        let counter = match self.queue.try_send((inputs, deep_copy(outputs))) {
+            Ok(()) => &self.counters.mirrored,
+            Err(_) => &self.counters.dropped,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(super) fn stats(&self) -> ShadowStats {
+        let c = &self.counters;
+        ShadowStats {
+            version: self.version,
+            mirrored: c.mirrored.load(Ordering::Relaxed),
+            dropped: c.dropped.load(Ordering::Relaxed),
+            matched: c.matched.load(Ordering::Relaxed),
+            diverged: c.diverged.load(Ordering::Relaxed),
+            errors: c.errors.load(Ordering::Relaxed),
+            max_abs_diff: f64::from_bits(c.max_abs_diff.load(Ordering::Relaxed)),
+            last_divergence: c
+                .last_divergence
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clone(),
+        }
+    }
+}
+
+/// Copies sharing no storage with `tensors`, so neither callers reusing
+/// their buffers nor a runtime stealing boxed inputs can change what the
+/// shadow sees.
+pub(super) fn deep_copy<D: Device>(tensors: &[DeviceTensor<D>]) -> Vec<DeviceTensor<D>> {
+    tensors
+        .iter()
+        .map(|t| DeviceTensor {
+            tensor: t.copy(),
+            _device: std::marker::PhantomData,
+        })
+        .collect()
+}
+
+/// Compare output lists, returning the largest absolute difference if every
+/// pair is within `tolerance`, or why they diverge.
+fn compare<D: Device>(
+    expected: &[DeviceTensor<D>],
+    actual: &[DeviceTensor<D>],
+    tolerance: Tolerance,
+) -> Result<f64, String> {
+    if expected.len() != actual.len() {
+        return Err(format!(
+            "{} outputs, primary had {}",
+            actual.len(),
+            expected.len()
+        ));
+    }
+    let comparison =
+        compare_outputs(expected, actual, &tolerance.into()).map_err(|err| err.to_string())?;
+    if comparison.passed() {
+        Ok(comparison.max_abs_diff())
+    } else {
+        Err(comparison.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tch::Tensor;
+
+    use super::*;
+    use crate::Cpu;
+
+    fn outputs(values: &[f32]) -> Vec<DeviceTensor<Cpu>> {
+        vec![DeviceTensor::try_new(Tensor::from_slice(values)).unwrap()]
+    }
+
+    #[test]
+    fn outputs_within_tolerance_match() {
+        let diff = compare(
+            &outputs(&[1.0, 2.0]),
+            &outputs(&[1.0, 2.0001]),
+            Tolerance {
+                rtol: 1e-3,
+                atol: 0.0,
+            },
+        )
+        .unwrap();
+        assert!((diff - 1e-4).abs() < 1e-6);
+    }
+
+    #[test]
+    fn divergence_is_explained() {
+        let tight = Tolerance::default();
+        let err = compare(&outputs(&[1.0, 2.0]), &outputs(&[1.0, 2.5]), tight).unwrap_err();
+        assert!(err.contains("output 0 differs"), "{err}");
+        let err = compare(&outputs(&[1.0]), &outputs(&[1.0, 2.0]), tight).unwrap_err();
+        assert!(err.contains("primary had"), "{err}");
+        assert!(compare(&outputs(&[1.0]), &[], tight).is_err());
+    }
+}
@@ -0,0 +1,217 @@
+//! Weighted traffic splitting between versions of one model, and the
+//! per-version counters that let a canary be compared with its baseline.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use super::limit::Limiter;
+use super::shadow::{Shadow, deep_copy};
+use crate::{AOTIModelPool, Device, DeviceTensor, Error, IntoInputs, Outputs};
+
+/// Relative weights of the versions a model's traffic is split across.
+pub(super) struct TrafficSplit {
+    weights: Vec<(u64, u32)>,
+    next: AtomicU64,
+}
+
+impl TrafficSplit {
+    pub(super) fn new(weights: &[(u64, u32)]) -> Result<Self, Error> {
+        if weights.iter().all(|&(_, w)| w == 0) {
+            return Err(Error::InvalidInput(
+                "a traffic split needs at least one non-zero weight".into(),
+            ));
+        }
+        Ok(Self {
+            weights: weights.to_vec(),
+            next: AtomicU64::new(0),
+        })
+    }
+
+    /// Pick a version among those for which `loaded` holds. With a `key`
+    /// the choice is a pure function of it, so a caller keyed by e.g. user
+    /// id always lands on the same version; otherwise successive calls are
+    /// spread according to the weights.
+    pub(super) fn pick(&self, loaded: impl Fn(u64) -> bool, key: Option<u64>) -> Option<u64> {
+        let live: Vec<(u64, u64)> = self
+            .weights
+            .iter()
+            .filter(|&&(version, weight)| weight > 0 && loaded(version))
+            .map(|&(version, weight)| (version, weight as u64))
+            .collect();
+        let total: u64 = live.iter().map(|(_, w)| w).sum();
+        if total == 0 {
+            return None;
+        }
+        let seed = key.unwrap_or_else(|| self.next.fetch_add(1, Ordering::Relaxed));
+        let mut point = mix(seed) % total;
+        live.into_iter().find_map(|(version, weight)| {
+            if point < weight {
+                Some(version)
+            } else {
+                point -= weight;
+                None
+            }
+        })
+    }
+}
+
+/// The splitmix64 finalizer: spreads consecutive counters (or similar
+/// keys) evenly across the weight range.
+fn mix(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^ (x >> 31)
+}
+
+/// Live counters behind [`VersionStats`].
+#[derive(Default)]
+pub(super) struct Counters {
+    requests: AtomicU64,
+    errors: AtomicU64,
+    latency_ns: AtomicU64,
+}
+
+impl Counters {
+    fn record(&self, elapsed: Duration, ok: bool) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        if !ok {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+        }
+        self.latency_ns
+            .fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    pub(super) fn snapshot(&self) -> VersionStats {
+        VersionStats {
+            requests: self.requests.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+            total_latency: Duration::from_nanos(self.latency_ns.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// Requests served by one model version through [`Routed`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VersionStats {
+    pub requests: u64,
+    /// Requests whose run returned an error.
+    pub errors: u64,
+    /// Wall time spent in runs, including waiting for a replica.
+    pub total_latency: Duration,
+}
+
+impl VersionStats {
+    /// Mean latency per request, or `None` before the first.
+    pub fn mean_latency(&self) -> Option<Duration> {
+        (self.requests > 0).then(|| {
+            Duration::from_nanos((self.total_latency.as_nanos() / self.requests as u128) as u64)
+        })
+    }
+
+    /// Fraction of requests that failed, or `None` before the first.
+    pub fn error_rate(&self) -> Option<f64> {
+        (self.requests > 0).then(|| self.errors as f64 / self.requests as f64)
+    }
+}
+
+/// The version a request was routed to, recording the runs made through
+/// it in that version's [`VersionStats`] and mirroring them to the model's
+/// shadow, if it has one. Runs count against the model's
+/// [`ConcurrencyLimit`](super::ConcurrencyLimit); a run rejected by it
+/// fails with [`Error::Overloaded`] and isn't recorded.
+pub struct Routed<D: Device> {
+    /// Name the model is served under.
+    pub name: String,
+    pub version: u64,
+    pub(super) pool: Arc<AOTIModelPool<D>>,
+    pub(super) counters: Arc<Counters>,
+    pub(super) shadow: Option<Arc<Shadow<D>>>,
+    pub(super) limiter: Option<Arc<Limiter>>,
+}
+
+impl<D: Device> Routed<D> {
+    /// The version's pool, e.g. to [`AOTIModelPool::upload`] inputs. Runs
+    /// made on it directly are neither counted nor limited.
+    pub fn pool(&self) -> &Arc<AOTIModelPool<D>> {
+        &self.pool
+    }
+
+    /// Run inference as [`AOTIModelPool::run`].
+    pub fn run<'a>(&self, inputs: impl IntoInputs<'a, D>) -> Result<Outputs<D>, Error> {
+        let inputs = inputs
+            .into_inputs(self.pool.input_target())
+            .map_err(Error::in_request)?;
+        let Some(shadow) = &self.shadow else {
+            return self.timed(|| self.pool.run(inputs));
+        };
+        // The runtime may reuse donated inputs' storage, so the shadow's
+        // copies are taken first.
+        let mirrored = deep_copy(inputs.as_slice());
+        let outputs = self.timed(|| self.pool.run(inputs))?;
+        shadow.mirror(mirrored, &outputs);
+        Ok(outputs)
+    }
+
+    /// Run inference as [`AOTIModelPool::run`], donating the inputs.
+    #[deprecated(note = "use `run`, which donates inputs passed by value")]
+    pub fn boxed_run(&self, inputs: Vec<DeviceTensor<D>>) -> Result<Outputs<D>, Error> {
+        self.run(inputs)
+    }
+
+    fn timed<T>(&self, f: impl FnOnce() -> Result<T, Error>) -> Result<T, Error> {
+        #[cfg(feature = "otel")]
+        let _span = crate::otel::enter(crate::otel::span(
+            "aoti.route",
+            vec![
+                opentelemetry::KeyValue::new("aoti.model.name", self.name.clone()),
+                opentelemetry::KeyValue::new("aoti.model.version", self.version as i64),
+            ],
+        ));
+        let result = self.limited(f);
+        #[cfg(feature = "otel")]
+        crate::otel::record(&result);
+        result
+    }
+
+    fn limited<T>(&self, f: impl FnOnce() -> Result<T, Error>) -> Result<T, Error> {
+        let _permit = self
+            .limiter
+            .as_ref()
+            .map(|l| l.acquire())
+            .transpose()
+            .map_err(Error::in_request)?;
+        let started = Instant::now();
+        let result = f();
+        self.counters.record(started.elapsed(), result.is_ok());
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_follow_weights_and_skip_unloaded_versions() {
+        let split = TrafficSplit::new(&[(1, 90), (2, 10), (3, 50)]).unwrap();
+        let mut counts = [0u32; 4];
+        for _ in 0..10_000 {
+            counts[split.pick(|v| v != 3, None).unwrap() as usize] += 1;
+        }
+        assert_eq!(counts[3], 0);
+        assert!((8_700..9_300).contains(&counts[1]), "{counts:?}");
+        assert_eq!(split.pick(|_| false, None), None);
+        assert!(TrafficSplit::new(&[(1, 0)]).is_err());
+    }
+
+    #[test]
+    fn keyed_picks_are_sticky() {
+        let split = TrafficSplit::new(&[(1, 50), (2, 50)]).unwrap();
+        for key in 0..100 {
+            let first = split.pick(|_| true, Some(key));
+            assert!((0..5).all(|_| split.pick(|_| true, Some(key)) == first));
+        }
+    }
+}
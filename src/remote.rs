@@ -0,0 +1,196 @@
+//! Fetching packages from object storage and HTTP (feature `object-store`).
+//!
+//! [`AOTIModelBuilder`](crate::AOTIModelBuilder) and
+//! [`AnyAOTIModel`](crate::AnyAOTIModel) accept `s3://`, `gs://`,
+//! `http://` and `https://` URLs in place of a path. The package is
+//! downloaded once into a cache directory (`AOTI_RS_CACHE_DIR`, or
+//! `aoti-rs-cache` under the system temp directory) and loaded from there.
+//! Credentials and regions come from the usual environment variables
+//! (`AWS_ACCESS_KEY_ID`, `AWS_REGION`, `GOOGLE_SERVICE_ACCOUNT`, ...).
+//!
+//! With an expected SHA-256, a download that doesn't match is discarded
+//! with [`Error::ChecksumMismatch`], and a cached copy that doesn't match is
+//! downloaded again. Without one, a cached copy is always reused.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use futures::StreamExt;
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use crate::progress::Report;
+use crate::{Error, LoadPhase, LoadProgress};
+
+const SCHEMES: [&str; 5] = ["s3", "s3a", "gs", "http", "https"];
+
+/// Whether `path` names a remote package rather than a local file.
+fn is_remote(path: &str) -> bool {
+    Url::parse(path).is_ok_and(|url| SCHEMES.contains(&url.scheme()))
+}
+
+/// The local file to load for `path`: the cached copy of a remote package,
+/// or `path` itself, checked against `sha256` if given. Downloads report
+/// [`LoadPhase::Download`] progress.
+pub(crate) fn resolve(
+    path: &Path,
+    cache_dir: Option<&Path>,
+    sha256: Option<&str>,
+    report: Report<'_>,
+) -> Result<PathBuf, Error> {
+    if let Some(url) = path.to_str().filter(|p| is_remote(p)) {
+        return fetch(url, cache_dir, sha256, report);
+    }
+    if let Some(expected) = sha256 {
+        crate::check_package(path)?;
+        let found = hash_file(path)?;
+        if !found.eq_ignore_ascii_case(expected) {
+            return Err(Error::ChecksumMismatch {
+                path: path.display().to_string(),
+                expected: expected.to_string(),
+                found,
+            });
+        }
+    }
+    Ok(path.to_path_buf())
+}
+
+/// The local copy of the package at `url`, downloading it into `cache_dir`
+/// (or the default cache) unless a usable copy is there already.
+fn fetch(
+    url: &str,
+    cache_dir: Option<&Path>,
+    sha256: Option<&str>,
+    report: Report<'_>,
+) -> Result<PathBuf, Error> {
+    let parsed = Url::parse(url).map_err(|e| Error::InvalidPath(format!("{url}: {e}")))?;
+    let dir = cache_dir.map_or_else(default_cache_dir, Path::to_path_buf);
+    std::fs::create_dir_all(&dir)?;
+    let target = dir.join(cache_name(&parsed));
+    if target.exists() {
+        match sha256 {
+            None => return Ok(target),
+            Some(expected) if hash_file(&target)?.eq_ignore_ascii_case(expected) => {
+                return Ok(target);
+            }
+            Some(_) => {}
+        }
+    }
+    // Download beside the target and rename, so concurrent loaders never
+    // see a partial package.
+    let partial = tempfile::NamedTempFile::new_in(&dir)?;
+    let found = download(&parsed, partial.as_file(), report)?;
+    if let Some(expected) = sha256
+        && !found.eq_ignore_ascii_case(expected)
+    {
+        return Err(Error::ChecksumMismatch {
+            path: url.to_string(),
+            expected: expected.to_string(),
+            found,
+        });
+    }
+    partial.persist(&target).map_err(|e| e.error)?;
+    Ok(target)
+}
+
+fn default_cache_dir() -> PathBuf {
+    std::env::var_os("AOTI_RS_CACHE_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| std::env::temp_dir().join("aoti-rs-cache"))
+}
+
+/// A file name unique to `url` that keeps its last path segment readable.
+fn cache_name(url: &Url) -> String {
+    let digest = hex(&Sha256::digest(url.as_str().as_bytes()));
+    let file = url
+        .path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .filter(|name| !name.is_empty())
+        .unwrap_or("package.pt2");
+    format!("{}-{file}", &digest[..16])
+}
+
+/// Stream `url` into `file`, returning the SHA-256 of what was written.
+///
+/// The download runs on its own thread and runtime, so loading works the
+/// same from synchronous code and from inside an async runtime.
+fn download(url: &Url, file: &File, report: Report<'_>) -> Result<String, Error> {
+    let store_error = |e: object_store::Error| Error::Io(std::io::Error::other(e));
+    let env = std::env::vars().map(|(key, value)| (key.to_ascii_lowercase(), value));
+    let (store, path) = object_store::parse_url_opts(url, env).map_err(store_error)?;
+    let fetch = async {
+        let object = store.get(&path).await.map_err(store_error)?;
+        let mut progress = LoadProgress {
+            phase: LoadPhase::Download,
+            bytes_done: 0,
+            bytes_total: Some(object.meta.size),
+        };
+        report(progress);
+        let mut chunks = object.into_stream();
+        let mut hasher = Sha256::new();
+        let mut out = BufWriter::new(file);
+        while let Some(chunk) = chunks.next().await {
+            let chunk = chunk.map_err(store_error)?;
+            hasher.update(&chunk);
+            out.write_all(&chunk)?;
+            progress.bytes_done += chunk.len() as u64;
+            report(progress);
+        }
+        out.flush()?;
+        Ok(hex(&hasher.finalize()))
+    };
+    std::thread::scope(|scope| {
+        scope
+            .spawn(|| {
+                tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()?
+                    .block_on(fetch)
+            })
+            .join()
+            .unwrap_or_else(|_| Err(Error::Model(format!("downloading {url} panicked"))))
+    })
+}
+
+fn hash_file(path: &Path) -> Result<String, Error> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hex(&hasher.finalize()))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn urls_are_recognized_and_named_stably() {
+        assert!(is_remote("s3://models/ranker/v3/model.pt2"));
+        assert!(is_remote("https://example.com/model.pt2"));
+        assert!(!is_remote("/srv/models/model.pt2"));
+        assert!(!is_remote("model.pt2"));
+        let url = Url::parse("gs://bucket/a/model.pt2").unwrap();
+        assert_eq!(cache_name(&url), cache_name(&url.clone()));
+        assert!(cache_name(&url).ends_with("-model.pt2"));
+        let other = Url::parse("gs://bucket/b/model.pt2").unwrap();
+        assert_ne!(cache_name(&url), cache_name(&other));
+    }
+
+    #[test]
+    fn cached_packages_are_checked_against_the_expected_digest() {
+        let dir = tempfile::tempdir().unwrap();
+        // Nothing listens on the discard port, so any download fails.
+        let url = "http://127.0.0.1:9/model.pt2";
+        let cached = dir.path().join(cache_name(&Url::parse(url).unwrap()));
+        std::fs::write(&cached, b"abc").unwrap();
+        let abc = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+        let fetch = |sha256| fetch(url, Some(dir.path()), sha256, &mut |_| {});
+        assert_eq!(fetch(Some(abc)).unwrap(), cached);
+        assert_eq!(fetch(None).unwrap(), cached);
+        assert!(fetch(Some(&"0".repeat(64))).is_err());
+    }
+}
@@ -0,0 +1,209 @@
+//! Reproduction bundles for bug reports.
+//!
+//! When a run fails in a way that looks like a bug, in this crate, the
+//! package or PyTorch, [`capture_repro`] writes one zip with everything
+//! needed to replay it elsewhere:
+//!
+//! ```text
+//! repro.json            the error, model details, inputs and environment
+//! inputs.safetensors    the inputs, copied to host memory
+//! ```
+//!
+//! ```ignore
+//! if let Err(err) = model.run(&inputs) {
+//!     aoti_rs::repro::capture_repro(&mut model, &inputs, &err, "repro.zip")?;
+//! }
+//! ```
+//!
+//! The package itself isn't included, as it may be large or confidential;
+//! `repro.json` records its path and size to attach it separately. Parts
+//! that can't be captured, e.g. because the runtime is wedged after the
+//! error, are recorded as errors in their place rather than failing the
+//! capture.
+
+use std::io::Write;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json::{Value, json};
+use tch::Tensor;
+
+use crate::{AOTIModel, Device, DeviceTensor, Error, device_string};
+
+/// Environment variables worth recording, by prefix.
+const ENV_PREFIXES: &[&str] = &[
+    "AOTI_",
+    "CUBLAS_",
+    "CUDA_",
+    "CUDNN_",
+    "LD_LIBRARY_PATH",
+    "LIBTORCH",
+    "OMP_",
+    "PYTORCH_",
+    "TORCH_",
+];
+
+/// Write a reproduction bundle for `error`, raised by running `model` on
+/// `inputs`, to the zip at `path`.
+pub fn capture_repro<D: Device>(
+    model: &mut AOTIModel<D>,
+    inputs: &[DeviceTensor<D>],
+    error: &Error,
+    path: impl AsRef<Path>,
+) -> Result<(), Error> {
+    let dir = tempfile::tempdir()?;
+    let tensors = dir.path().join("inputs.safetensors");
+    let written = write_inputs(&tensors, inputs);
+    let report = json!({
+        "version": 1,
+        "created_unix": SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs()),
+        "error": describe_error(error),
+        "model": describe_model(model),
+        "inputs": inputs.iter().map(|t| describe_tensor(t)).collect::<Vec<_>>(),
+        "inputs_file": written.as_ref().map_or_else(
+            |err| json!({"error": err.to_string()}),
+            |()| json!("inputs.safetensors"),
+        ),
+        "environment": environment(),
+    });
+
+    let mut zip = zip::ZipWriter::new(std::fs::File::create(path)?);
+    let options = zip::write::SimpleFileOptions::default();
+    zip.start_file("repro.json", options)?;
+    zip.write_all(&serde_json::to_vec_pretty(&report)?)?;
+    if written.is_ok() {
+        zip.start_file("inputs.safetensors", options)?;
+        std::io::copy(&mut std::fs::File::open(&tensors)?, &mut zip)?;
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+/// Save `inputs` as `input_0`, `input_1`, ... in host memory.
+fn write_inputs<D: Device>(path: &Path, inputs: &[DeviceTensor<D>]) -> Result<(), Error> {
+    let names: Vec<String> = (0..inputs.len()).map(|i| format!("input_{i}")).collect();
+    let host = inputs
+        .iter()
+        .zip(&names)
+        .map(|(t, name)| {
+            let t = t
+                .f_detach()?
+                .f_to_device(tch::Device::Cpu)?
+                .f_contiguous()?;
+            Ok((name.as_str(), t))
+        })
+        .collect::<Result<Vec<(&str, Tensor)>, Error>>()?;
+    Ok(Tensor::write_safetensors(&host, path)?)
+}
+
+fn describe_error(error: &Error) -> Value {
+    json!({
+        "message": error.to_string(),
+        "debug": format!("{error:?}"),
+        "request_id": error.request_id(),
+        "cpp_backtrace": error.cpp_backtrace(),
+    })
+}
+
+fn describe_model<D: Device>(model: &mut AOTIModel<D>) -> Value {
+    fn or_error<T: Into<Value>>(result: Result<T, Error>) -> Value {
+        result.map_or_else(|err| json!({"error": err.to_string()}), Into::into)
+    }
+    let stats = model.stats();
+    json!({
+        "package": model.path.display().to_string(),
+        "package_bytes": std::fs::metadata(&model.path).ok().map(|m| m.len()),
+        "model_name": model.model_name,
+        "device": device_string(model.device),
+        "stats": {"runs": stats.runs, "failures": stats.failures},
+        "metadata": or_error(model.get_metadata().map(|m| json!(m))),
+        "call_spec": or_error(model.get_call_spec()),
+        "constants": or_error(model.constants().map(|constants| {
+            constants
+                .into_iter()
+                .map(|c| json!({"name": c.name, "dtype": format!("{:?}", c.dtype), "shape": c.shape}))
+                .collect::<Vec<_>>()
+        })),
+    })
+}
+
+fn describe_tensor(tensor: &Tensor) -> Value {
+    if !tensor.defined() {
+        return json!({"defined": false});
+    }
+    json!({
+        "dtype": format!("{:?}", tensor.kind()),
+        "shape": tensor.size(),
+        "stride": tensor.stride(),
+        "device": device_string(tensor.device()),
+        "contiguous": tensor.is_contiguous(),
+        "requires_grad": tensor.requires_grad(),
+    })
+}
+
+fn environment() -> Value {
+    let vars: serde_json::Map<String, Value> = std::env::vars()
+        .filter(|(name, _)| ENV_PREFIXES.iter().any(|p| name.starts_with(p)))
+        .map(|(name, value)| (name, Value::String(value)))
+        .collect();
+    json!({
+        "aoti_rs": env!("CARGO_PKG_VERSION"),
+        "os": std::env::consts::OS,
+        "arch": std::env::consts::ARCH,
+        "cuda_available": tch::Cuda::is_available(),
+        "cuda_devices": tch::Cuda::device_count(),
+        "cudnn_available": tch::Cuda::cudnn_is_available(),
+        "threads": tch::get_num_threads(),
+        "env": vars,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tensors_are_described_with_their_layout() {
+        let x = Tensor::zeros([2, 3], (tch::Kind::Float, tch::Device::Cpu)).tr();
+        let described = describe_tensor(&x);
+        assert_eq!(described["dtype"], "Float");
+        assert_eq!(described["shape"], json!([3, 2]));
+        assert_eq!(described["stride"], json!([1, 3]));
+        assert_eq!(described["contiguous"], false);
+        assert_eq!(describe_tensor(&Tensor::new())["defined"], false);
+        assert!(environment()["aoti_rs"].is_string());
+    }
+
+    #[cfg(feature = "fake")]
+    #[test]
+    fn bundles_hold_the_report_and_inputs() {
+        use std::io::Read;
+
+        use crate::Cpu;
+        use crate::fake::FakeModel;
+
+        let dir = tempfile::tempdir().unwrap();
+        let package = dir.path().join("model.pt2");
+        FakeModel::new().write(&package, "model").unwrap();
+        let mut model = AOTIModel::<Cpu>::load(&package).unwrap();
+        let inputs = [DeviceTensor::try_new(Tensor::from_slice(&[1.0f32, 2.0])).unwrap()];
+        let error = Error::Model("kernel failed".into());
+        let bundle = dir.path().join("repro.zip");
+        capture_repro(&mut model, &inputs, &error, &bundle).unwrap();
+
+        let mut zip = zip::ZipArchive::new(std::fs::File::open(&bundle).unwrap()).unwrap();
+        let mut report = String::new();
+        zip.by_name("repro.json")
+            .unwrap()
+            .read_to_string(&mut report)
+            .unwrap();
+        let report: Value = serde_json::from_str(&report).unwrap();
+        assert_eq!(report["error"]["message"], "model error: kernel failed");
+        assert_eq!(report["model"]["metadata"]["AOTI_DEVICE_KEY"], "cpu");
+        assert_eq!(report["inputs"][0]["shape"], json!([2]));
+        assert_eq!(report["inputs_file"], "inputs.safetensors");
+        assert!(zip.by_name("inputs.safetensors").is_ok());
+    }
+}
@@ -0,0 +1,114 @@
+//! Caller-supplied request IDs, for correlating inference with upstream
+//! requests.
+//!
+//! [`RequestId::scope`] makes an ID current on the calling thread; while it
+//! is, pool runs attach it to
+//!
+//! - errors, wrapped as [`Error::Request`](crate::Error::Request),
+//! - [`RunLog`](crate::RunLog) lines, as `"request_id"`,
+//! - the `aoti.run` OpenTelemetry span (feature `otel`) and the `aoti_ffi`
+//!   tracing span (feature `tracing`), as `aoti.request_id`/`request_id`.
+//!
+//! The HTTP, gRPC and Flight servers scope each request to its
+//! `x-request-id` header, if it has one.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::sync::Arc;
+
+/// Header the serving front-ends read request IDs from.
+#[cfg(any(feature = "flight", feature = "grpc", feature = "http"))]
+pub(crate) const HEADER: &str = "x-request-id";
+
+thread_local! {
+    static CURRENT: RefCell<Option<RequestId>> = const { RefCell::new(None) };
+}
+
+/// An opaque ID naming one upstream request.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RequestId(Arc<str>);
+
+impl RequestId {
+    pub fn new(id: impl Into<Arc<str>>) -> Self {
+        Self(id.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// The ID scoped on this thread, if any.
+    pub fn current() -> Option<RequestId> {
+        CURRENT.with(|current| current.borrow().clone())
+    }
+
+    /// Call `f` with this ID current on this thread, restoring the previous
+    /// one afterwards (even if `f` panics). The ID doesn't follow work `f`
+    /// hands to other threads.
+    pub fn scope<R>(&self, f: impl FnOnce() -> R) -> R {
+        struct Restore(Option<RequestId>);
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                CURRENT.with(|current| *current.borrow_mut() = self.0.take());
+            }
+        }
+
+        let _restore = Restore(CURRENT.with(|current| current.replace(Some(self.clone()))));
+        f()
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<&str> for RequestId {
+    fn from(id: &str) -> Self {
+        Self::new(id)
+    }
+}
+
+impl From<String> for RequestId {
+    fn from(id: String) -> Self {
+        Self::new(id)
+    }
+}
+
+/// The ID in request `headers`, if present and valid UTF-8.
+#[cfg(any(feature = "flight", feature = "grpc", feature = "http"))]
+pub(crate) fn from_headers(headers: &::http::HeaderMap) -> Option<RequestId> {
+    let id = headers.get(HEADER)?.to_str().ok()?;
+    (!id.is_empty()).then(|| RequestId::from(id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+
+    #[test]
+    fn scopes_nest_and_restore() {
+        assert_eq!(RequestId::current(), None);
+        RequestId::from("outer").scope(|| {
+            RequestId::from("inner").scope(|| {
+                assert_eq!(RequestId::current().unwrap().as_str(), "inner");
+            });
+            assert_eq!(RequestId::current().unwrap().as_str(), "outer");
+            let _ = std::panic::catch_unwind(|| RequestId::from("p").scope(|| panic!()));
+            assert_eq!(RequestId::current().unwrap().as_str(), "outer");
+        });
+        assert_eq!(RequestId::current(), None);
+    }
+
+    #[test]
+    fn errors_are_tagged_once() {
+        assert!(matches!(Error::ShutDown.in_request(), Error::ShutDown));
+        let err = RequestId::from("r-1").scope(|| Error::ShutDown.in_request().in_request());
+        assert_eq!(err.request_id(), Some("r-1"));
+        assert!(matches!(err.root(), Error::ShutDown));
+        assert_eq!(err.to_string(), "request r-1: model pool is shut down");
+    }
+}
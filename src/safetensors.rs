@@ -0,0 +1,124 @@
+//! Reading model inputs from, and writing outputs to, `.safetensors` files.
+//!
+//! Tensors keep their dtype in both directions (`F32` ↔ `Float`, `BF16` ↔
+//! `BFloat16`, ...); kinds the format has no dtype for, such as complex or
+//! quantized tensors, fail with [`Error::Tch`]. Files are always read into
+//! host memory and then copied to the model's device, and outputs are
+//! copied back to host memory before being written, so the same file works
+//! for CPU and CUDA models alike.
+
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::path::Path;
+
+use tch::Tensor;
+
+use crate::{AOTIModel, Cpu, Device, DeviceTensor, Error};
+
+/// Read every tensor in a `.safetensors` file into host memory, in file
+/// order.
+pub fn read_tensors(path: impl AsRef<Path>) -> Result<Vec<(String, DeviceTensor<Cpu>)>, Error> {
+    Ok(Tensor::read_safetensors(path)?
+        .into_iter()
+        .map(|(name, tensor)| {
+            (
+                name,
+                DeviceTensor {
+                    tensor,
+                    _device: PhantomData,
+                },
+            )
+        })
+        .collect())
+}
+
+/// Write `tensors` to a `.safetensors` file under the given names.
+///
+/// Non-contiguous and device-resident tensors are copied to contiguous host
+/// memory first.
+pub fn write_tensors<D: Device>(
+    path: impl AsRef<Path>,
+    tensors: &[(&str, &DeviceTensor<D>)],
+) -> Result<(), Error> {
+    let host = tensors
+        .iter()
+        .map(|&(name, tensor)| Ok((name, tensor.f_to_device(tch::Device::Cpu)?.f_contiguous()?)))
+        .collect::<Result<Vec<_>, Error>>()?;
+    Ok(Tensor::write_safetensors(&host, path)?)
+}
+
+/// Load the tensors called `names`, in that order, and upload them to
+/// `model`'s device, ready for [`AOTIModel::run`].
+///
+/// Fails with [`Error::InvalidInput`] if the file has no tensor by one of
+/// the names; extra tensors in the file are ignored.
+pub fn load_inputs<D: Device>(
+    model: &AOTIModel<D>,
+    path: impl AsRef<Path>,
+    names: &[&str],
+) -> Result<Vec<DeviceTensor<D>>, Error> {
+    let path = path.as_ref();
+    let mut tensors: HashMap<_, _> = read_tensors(path)?.into_iter().collect();
+    names
+        .iter()
+        .map(|&name| {
+            let tensor = tensors.remove(name).ok_or_else(|| {
+                Error::InvalidInput(format!("{} has no tensor '{name}'", path.display()))
+            })?;
+            Ok(model.upload(&tensor))
+        })
+        .collect()
+}
+
+/// Write model `outputs` to a `.safetensors` file, naming them `names` in
+/// order.
+pub fn save_outputs<D: Device>(
+    path: impl AsRef<Path>,
+    outputs: &[DeviceTensor<D>],
+    names: &[&str],
+) -> Result<(), Error> {
+    if outputs.len() != names.len() {
+        return Err(Error::InvalidInput(format!(
+            "{} outputs but {} names",
+            outputs.len(),
+            names.len()
+        )));
+    }
+    let named: Vec<_> = names.iter().copied().zip(outputs).collect();
+    write_tensors(path, &named)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tensors_round_trip_with_dtype() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("io.safetensors");
+        let ids = DeviceTensor::<Cpu>::try_new(Tensor::from_slice(&[1i64, 2, 3])).unwrap();
+        let x = DeviceTensor::<Cpu>::try_new(
+            Tensor::arange(6, (tch::Kind::Half, tch::Device::Cpu))
+                .reshape([2, 3])
+                .tr(),
+        )
+        .unwrap();
+        save_outputs(&path, &[ids, x], &["ids", "x"]).unwrap();
+
+        let read: HashMap<_, _> = read_tensors(&path).unwrap().into_iter().collect();
+        assert_eq!(read["ids"].kind(), tch::Kind::Int64);
+        assert_eq!(read["x"].kind(), tch::Kind::Half);
+        assert_eq!(read["x"].size(), &[3, 2]);
+        assert_eq!(read["x"].double_value(&[2, 1]), 5.0);
+    }
+
+    #[test]
+    fn name_count_must_match_outputs() {
+        let dir = tempfile::tempdir().unwrap();
+        let t = DeviceTensor::<Cpu>::try_new(Tensor::from_slice(&[1.0f32])).unwrap();
+        assert!(matches!(
+            save_outputs(dir.path().join("x.safetensors"), &[t], &[]),
+            Err(Error::InvalidInput(_))
+        ));
+    }
+}
@@ -0,0 +1,194 @@
+//! Declared expectations for a model's outputs.
+//!
+//! The runtime returns whatever the compiled graph produces, so a package
+//! re-exported with a changed head, or a runtime upgrade that changes a
+//! dtype, is only noticed where the outputs are used. An [`OutputSchema`]
+//! attached to the model ([`AOTIModelBuilder::output_schema`]) checks
+//! every run's outputs at the boundary instead:
+//!
+//! ```ignore
+//! let schema = OutputSchema::new()
+//!     .output([Dim::from("batch"), Dim::Fixed(1000)], Kind::Float)
+//!     .output(["batch"], Kind::Int64);
+//! let model = AOTIModel::<Cpu>::builder(path).output_schema(schema).build()?;
+//! ```
+//!
+//! [`AOTIModelBuilder::output_schema`]: crate::AOTIModelBuilder::output_schema
+
+use std::collections::HashMap;
+use std::fmt;
+
+use tch::{Kind, Tensor};
+
+use crate::Error;
+
+/// One dimension of an expected output shape.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Dim {
+    /// Any size, e.g. a dynamic sequence length.
+    Any,
+    /// Exactly this size.
+    Fixed(i64),
+    /// Any size, as long as every dimension with this name, across all
+    /// outputs, has the same one: e.g. the batch size.
+    Named(String),
+}
+
+impl From<i64> for Dim {
+    fn from(size: i64) -> Self {
+        Dim::Fixed(size)
+    }
+}
+
+impl From<&str> for Dim {
+    fn from(name: &str) -> Self {
+        Dim::Named(name.to_string())
+    }
+}
+
+impl fmt::Display for Dim {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Dim::Any => f.write_str("*"),
+            Dim::Fixed(size) => write!(f, "{size}"),
+            Dim::Named(name) => f.write_str(name),
+        }
+    }
+}
+
+/// The outputs a model is expected to return: how many, and each one's
+/// shape and dtype.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OutputSchema {
+    outputs: Vec<(Vec<Dim>, Kind)>,
+}
+
+impl OutputSchema {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Expect an output of this shape and dtype, in output order.
+    pub fn output(mut self, shape: impl IntoIterator<Item = impl Into<Dim>>, kind: Kind) -> Self {
+        self.outputs
+            .push((shape.into_iter().map(Into::into).collect(), kind));
+        self
+    }
+
+    /// Check `outputs` against the schema, failing with
+    /// [`Error::OutputSchemaMismatch`] at the first difference.
+    pub fn check<'a>(&self, outputs: impl IntoIterator<Item = &'a Tensor>) -> Result<(), Error> {
+        let outputs: Vec<&Tensor> = outputs.into_iter().collect();
+        let mismatch = |reason: String| Err(Error::OutputSchemaMismatch(reason));
+        if outputs.len() != self.outputs.len() {
+            return mismatch(format!(
+                "expected {} outputs, got {}",
+                self.outputs.len(),
+                outputs.len()
+            ));
+        }
+        // Each named dimension's size, and the output that set it.
+        let mut named: HashMap<&str, (i64, usize)> = HashMap::new();
+        for (i, (tensor, (shape, kind))) in outputs.iter().zip(&self.outputs).enumerate() {
+            if !tensor.defined() {
+                return mismatch(format!("output {i} is undefined"));
+            }
+            if tensor.kind() != *kind {
+                return mismatch(format!(
+                    "output {i} has dtype {:?}, expected {kind:?}",
+                    tensor.kind()
+                ));
+            }
+            let size = tensor.size();
+            let fits = size.len() == shape.len()
+                && size.iter().zip(shape).all(|(&n, dim)| match dim {
+                    Dim::Fixed(expected) => n == *expected,
+                    Dim::Any | Dim::Named(_) => true,
+                });
+            if !fits {
+                return mismatch(format!(
+                    "output {i} has shape {size:?}, expected [{}]",
+                    shape
+                        .iter()
+                        .map(Dim::to_string)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
+            }
+            for (&n, dim) in size.iter().zip(shape) {
+                let Dim::Named(name) = dim else { continue };
+                match named.get(name.as_str()) {
+                    Some(&(expected, from)) if expected != n => {
+                        return mismatch(format!(
+                            "output {i} has {name} = {n}, but output {from} has {name} = {expected}"
+                        ));
+                    }
+                    Some(_) => {}
+                    None => {
+                        named.insert(name.as_str(), (n, i));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl From<Vec<(Vec<Dim>, Kind)>> for OutputSchema {
+    fn from(outputs: Vec<(Vec<Dim>, Kind)>) -> Self {
+        Self { outputs }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema() -> OutputSchema {
+        OutputSchema::new()
+            .output([Dim::from("batch"), Dim::Any, Dim::Fixed(4)], Kind::Float)
+            .output(["batch"], Kind::Int64)
+    }
+
+    fn outputs(batch: [i64; 2], features: i64, labels: Kind) -> Vec<Tensor> {
+        vec![
+            Tensor::zeros([batch[0], 7, features], (Kind::Float, tch::Device::Cpu)),
+            Tensor::zeros([batch[1]], (labels, tch::Device::Cpu)),
+        ]
+    }
+
+    #[test]
+    fn matching_outputs_pass_with_wildcards() {
+        schema().check(&outputs([3, 3], 4, Kind::Int64)).unwrap();
+        schema().check(&outputs([1, 1], 4, Kind::Int64)).unwrap();
+        assert!(OutputSchema::new().check([]).is_ok());
+        assert_eq!(
+            OutputSchema::from(vec![(vec![Dim::Any], Kind::Half)]),
+            OutputSchema::new().output([Dim::Any], Kind::Half)
+        );
+    }
+
+    #[test]
+    fn mismatches_name_the_output_and_the_difference() {
+        let reason = |outputs: &[Tensor]| match schema().check(outputs) {
+            Err(Error::OutputSchemaMismatch(reason)) => reason,
+            other => panic!("expected a mismatch, got {other:?}"),
+        };
+        assert_eq!(
+            reason(&outputs([3, 3], 4, Kind::Int64)[..1]),
+            "expected 2 outputs, got 1"
+        );
+        assert_eq!(
+            reason(&outputs([3, 3], 5, Kind::Int64)),
+            "output 0 has shape [3, 7, 5], expected [batch, *, 4]"
+        );
+        assert_eq!(
+            reason(&outputs([3, 3], 4, Kind::Int)),
+            "output 1 has dtype Int, expected Int64"
+        );
+        assert_eq!(
+            reason(&outputs([3, 2], 4, Kind::Int64)),
+            "output 1 has batch = 2, but output 0 has batch = 3"
+        );
+    }
+}
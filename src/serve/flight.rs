@@ -0,0 +1,297 @@
+//! Arrow Flight bulk scoring (feature `flight`).
+//!
+//! [`FlightServer`] scores record batches streamed over Flight's
+//! `DoExchange`: the client opens an exchange whose descriptor path (or
+//! command) names a model, streams batches in, and receives each batch back
+//! with the model's outputs appended as columns. Columns map to tensors as
+//! in [`crate::arrow`], so there is no JSON or per-row overhead, and
+//! batches are scored as they arrive rather than after the upload ends.
+//!
+//! ```no_run
+//! # async fn serve() -> Result<(), aoti_rs::Error> {
+//! use std::sync::Arc;
+//! use aoti_rs::{AOTIModel, AOTIModelPool, Cpu};
+//! use aoti_rs::serve::flight::FlightServer;
+//!
+//! let pool = AOTIModelPool::<Cpu>::from_fn(2, |_| AOTIModel::<Cpu>::load("model.pt2"))?;
+//! FlightServer::new()
+//!     .model("churn", Arc::new(pool), &["features"], &["logits"])
+//!     .serve("0.0.0.0:50052".parse().unwrap())
+//!     .await
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use arrow_array::RecordBatch;
+use arrow_flight::decode::FlightRecordBatchStream;
+use arrow_flight::encode::FlightDataEncoderBuilder;
+use arrow_flight::error::FlightError;
+use arrow_flight::flight_service_server::{FlightService, FlightServiceServer};
+use arrow_flight::{
+    Action, ActionType, Criteria, Empty, FlightData, FlightDescriptor, FlightInfo,
+    HandshakeRequest, HandshakeResponse, PollInfo, PutResult, SchemaResult, Ticket,
+};
+use futures::stream::{self, BoxStream, StreamExt, TryStreamExt};
+use tonic::{Request, Response, Status, Streaming};
+
+use super::predict;
+use crate::arrow::{append_outputs, record_batch_to_inputs};
+use crate::{AOTIModelPool, Cpu, Device, DeviceTensor, Error};
+
+/// Largest message either side accepts unless configured otherwise.
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 1 << 30;
+
+/// A pool plus the columns it reads and writes.
+struct FlightModel<D: Device> {
+    pool: Arc<AOTIModelPool<D>>,
+    inputs: Vec<String>,
+    outputs: Vec<String>,
+}
+
+impl<D: Device> FlightModel<D> {
+    async fn score(
+        &self,
+        batch: RecordBatch,
+        headers: &http::HeaderMap,
+    ) -> Result<RecordBatch, Error> {
+        let columns: Vec<&str> = self.inputs.iter().map(String::as_str).collect();
+        let inputs = record_batch_to_inputs(&batch, &columns)?
+            .into_iter()
+            .map(DeviceTensor::into_inner)
+            .collect();
+        let outputs = predict(self.pool.clone(), inputs, headers).await?;
+        if outputs.len() != self.outputs.len() {
+            return Err(Error::Model(format!(
+                "model returned {} outputs but {} output columns are configured",
+                outputs.len(),
+                self.outputs.len()
+            )));
+        }
+        let outputs = DeviceTensor::<Cpu>::try_new_all(outputs)?;
+        let names: Vec<&str> = self.outputs.iter().map(String::as_str).collect();
+        append_outputs(&batch, &outputs, &names)
+    }
+}
+
+/// A Flight service scoring record batches with named model pools.
+pub struct FlightServer<D: Device> {
+    models: Arc<HashMap<String, Arc<FlightModel<D>>>>,
+    max_message_size: usize,
+}
+
+impl<D: Device> Clone for FlightServer<D> {
+    fn clone(&self) -> Self {
+        Self {
+            models: self.models.clone(),
+            max_message_size: self.max_message_size,
+        }
+    }
+}
+
+impl<D: Device> Default for FlightServer<D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<D: Device> FlightServer<D> {
+    /// A server with no models; add them with [`FlightServer::model`].
+    pub fn new() -> Self {
+        Self {
+            models: Arc::new(HashMap::new()),
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+        }
+    }
+
+    /// Serve `pool` under `name`, feeding it the `inputs` columns in order
+    /// and appending its outputs as the `outputs` columns.
+    pub fn model(
+        mut self,
+        name: impl Into<String>,
+        pool: Arc<AOTIModelPool<D>>,
+        inputs: &[&str],
+        outputs: &[&str],
+    ) -> Self {
+        let model = FlightModel {
+            pool,
+            inputs: inputs.iter().map(|s| s.to_string()).collect(),
+            outputs: outputs.iter().map(|s| s.to_string()).collect(),
+        };
+        Arc::make_mut(&mut self.models).insert(name.into(), Arc::new(model));
+        self
+    }
+
+    /// Reject messages larger than `bytes` in either direction (default
+    /// [`DEFAULT_MAX_MESSAGE_SIZE`]); a batch over it fails its exchange.
+    pub fn max_message_size(mut self, bytes: usize) -> Self {
+        self.max_message_size = bytes;
+        self
+    }
+
+    /// Listen on `addr` until the process exits or the transport fails.
+    pub async fn serve(self, addr: SocketAddr) -> Result<(), Error> {
+        let max_message_size = self.max_message_size;
+        let service = FlightServiceServer::new(self)
+            .max_decoding_message_size(max_message_size)
+            .max_encoding_message_size(max_message_size);
+        tonic::transport::Server::builder()
+            .add_service(service)
+            .serve(addr)
+            .await
+            .map_err(|e| Error::Model(format!("Flight transport error: {e}")))
+    }
+
+    /// The model named by a descriptor's first path element or its UTF-8
+    /// command; with no descriptor, the only model if there is one.
+    fn model_for(
+        &self,
+        descriptor: Option<&FlightDescriptor>,
+    ) -> Result<Arc<FlightModel<D>>, Status> {
+        let name = match descriptor {
+            Some(d) if !d.path.is_empty() => d.path[0].clone(),
+            Some(d) if !d.cmd.is_empty() => std::str::from_utf8(&d.cmd)
+                .map_err(|_| Status::invalid_argument("descriptor command is not UTF-8"))?
+                .to_string(),
+            _ if self.models.len() == 1 => {
+                return Ok(self.models.values().next().expect("one model").clone());
+            }
+            _ => {
+                return Err(Status::invalid_argument(
+                    "exchange descriptor names no model",
+                ));
+            }
+        };
+        self.models
+            .get(&name)
+            .cloned()
+            .ok_or_else(|| Status::not_found(format!("no model named '{name}' is served")))
+    }
+}
+
+fn unsupported<T>(method: &str) -> Result<T, Status> {
+    Err(Status::unimplemented(format!(
+        "{method} is not supported; score batches with DoExchange"
+    )))
+}
+
+#[tonic::async_trait]
+impl<D: Device> FlightService for FlightServer<D> {
+    type HandshakeStream = BoxStream<'static, Result<HandshakeResponse, Status>>;
+    type ListFlightsStream = BoxStream<'static, Result<FlightInfo, Status>>;
+    type DoGetStream = BoxStream<'static, Result<FlightData, Status>>;
+    type DoPutStream = BoxStream<'static, Result<PutResult, Status>>;
+    type DoActionStream = BoxStream<'static, Result<arrow_flight::Result, Status>>;
+    type ListActionsStream = BoxStream<'static, Result<ActionType, Status>>;
+    type DoExchangeStream = BoxStream<'static, Result<FlightData, Status>>;
+
+    async fn do_exchange(
+        &self,
+        request: Request<Streaming<FlightData>>,
+    ) -> Result<Response<Self::DoExchangeStream>, Status> {
+        let headers = Arc::new(request.metadata().as_ref().clone());
+        let mut incoming = request.into_inner();
+        let first = incoming
+            .message()
+            .await?
+            .ok_or_else(|| Status::invalid_argument("exchange carried no data"))?;
+        let model = self.model_for(first.flight_descriptor.as_ref())?;
+
+        let data = stream::once(async { Ok(first) }).chain(incoming.map_err(FlightError::from));
+        let scored = FlightRecordBatchStream::new_from_flight_data(data).and_then(move |batch| {
+            let (model, headers) = (model.clone(), headers.clone());
+            async move {
+                model
+                    .score(batch, &headers)
+                    .await
+                    .map_err(|e| FlightError::from(Status::from(e)))
+            }
+        });
+        let encoded = FlightDataEncoderBuilder::new()
+            .build(scored)
+            .map_err(Status::from);
+        Ok(Response::new(encoded.boxed()))
+    }
+
+    async fn handshake(
+        &self,
+        _request: Request<Streaming<HandshakeRequest>>,
+    ) -> Result<Response<Self::HandshakeStream>, Status> {
+        unsupported("Handshake")
+    }
+
+    async fn list_flights(
+        &self,
+        _request: Request<Criteria>,
+    ) -> Result<Response<Self::ListFlightsStream>, Status> {
+        unsupported("ListFlights")
+    }
+
+    async fn get_flight_info(
+        &self,
+        _request: Request<FlightDescriptor>,
+    ) -> Result<Response<FlightInfo>, Status> {
+        unsupported("GetFlightInfo")
+    }
+
+    async fn poll_flight_info(
+        &self,
+        _request: Request<FlightDescriptor>,
+    ) -> Result<Response<PollInfo>, Status> {
+        unsupported("PollFlightInfo")
+    }
+
+    async fn get_schema(
+        &self,
+        _request: Request<FlightDescriptor>,
+    ) -> Result<Response<SchemaResult>, Status> {
+        unsupported("GetSchema")
+    }
+
+    async fn do_get(
+        &self,
+        _request: Request<Ticket>,
+    ) -> Result<Response<Self::DoGetStream>, Status> {
+        unsupported("DoGet")
+    }
+
+    async fn do_put(
+        &self,
+        _request: Request<Streaming<FlightData>>,
+    ) -> Result<Response<Self::DoPutStream>, Status> {
+        unsupported("DoPut")
+    }
+
+    async fn do_action(
+        &self,
+        _request: Request<Action>,
+    ) -> Result<Response<Self::DoActionStream>, Status> {
+        unsupported("DoAction")
+    }
+
+    async fn list_actions(
+        &self,
+        _request: Request<Empty>,
+    ) -> Result<Response<Self::ListActionsStream>, Status> {
+        Ok(Response::new(stream::empty().boxed()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn descriptor_must_name_a_served_model() {
+        let server = FlightServer::<Cpu>::new();
+        let descriptor = FlightDescriptor::new_path(vec!["missing".to_string()]);
+        let status = server.model_for(Some(&descriptor)).err().unwrap();
+        assert_eq!(status.code(), tonic::Code::NotFound);
+        let status = server.model_for(None).err().unwrap();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        assert_eq!(server.max_message_size, DEFAULT_MAX_MESSAGE_SIZE);
+        assert_eq!(server.max_message_size(1 << 20).max_message_size, 1 << 20);
+    }
+}
@@ -0,0 +1,289 @@
+//! gRPC serving with tonic (feature `grpc`).
+//!
+//! [`PredictServer`] implements the `aoti_rs.v1.Predict` service from
+//! `proto/aoti_rs.proto` over one or more named [`AOTIModelPool`]s. The
+//! messages and routing are written out here rather than generated, so
+//! building the crate doesn't need `protoc`; clients can generate stubs
+//! from the `.proto` file in any language.
+//!
+//! ```no_run
+//! # async fn serve() -> Result<(), aoti_rs::Error> {
+//! use std::sync::Arc;
+//! use aoti_rs::{AOTIModel, AOTIModelPool, Cpu};
+//! use aoti_rs::serve::grpc::PredictServer;
+//!
+//! let pool = AOTIModelPool::<Cpu>::from_fn(4, |_| AOTIModel::<Cpu>::load("model.pt2"))?;
+//! let server = PredictServer::new().model("resnet", Arc::new(pool));
+//! server.serve("0.0.0.0:50051".parse().unwrap()).await
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use tonic::codegen::{Body, BoxFuture, Service, StdError, http};
+use tonic::server::{NamedService, UnaryService};
+use tonic::{Request, Response, Status};
+use tonic_prost::ProstCodec;
+
+use super::{Models, predict, tensor_from_bytes, tensor_to_bytes};
+use crate::{AOTIModelPool, Device, Error, device_string};
+
+/// `aoti_rs.v1.Tensor`.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct TensorProto {
+    #[prost(string, tag = "1")]
+    pub dtype: String,
+    #[prost(int64, repeated, tag = "2")]
+    pub shape: Vec<i64>,
+    #[prost(bytes = "vec", tag = "3")]
+    pub data: Vec<u8>,
+}
+
+impl TensorProto {
+    /// Decode into a host tensor.
+    pub fn to_tensor(&self) -> Result<tch::Tensor, Error> {
+        tensor_from_bytes(&self.dtype, &self.shape, &self.data)
+    }
+
+    /// Encode a tensor from any device.
+    pub fn from_tensor(tensor: &tch::Tensor) -> Result<Self, Error> {
+        let (dtype, shape, data) = tensor_to_bytes(tensor)?;
+        Ok(Self {
+            dtype: dtype.to_string(),
+            shape,
+            data,
+        })
+    }
+}
+
+/// `aoti_rs.v1.PredictRequest`.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct PredictRequest {
+    #[prost(string, tag = "1")]
+    pub model_name: String,
+    #[prost(message, repeated, tag = "2")]
+    pub inputs: Vec<TensorProto>,
+}
+
+/// `aoti_rs.v1.PredictResponse`.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct PredictResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub outputs: Vec<TensorProto>,
+}
+
+/// `aoti_rs.v1.MetadataRequest`.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct MetadataRequest {
+    #[prost(string, tag = "1")]
+    pub model_name: String,
+}
+
+/// `aoti_rs.v1.MetadataResponse`.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct MetadataResponse {
+    #[prost(map = "string, string", tag = "1")]
+    pub metadata: HashMap<String, String>,
+    #[prost(string, tag = "2")]
+    pub device: String,
+    #[prost(uint32, tag = "3")]
+    pub replicas: u32,
+}
+
+/// Fully-qualified name of the service.
+pub const SERVICE_NAME: &str = "aoti_rs.v1.Predict";
+
+const PREDICT_PATH: &str = "/aoti_rs.v1.Predict/Predict";
+const METADATA_PATH: &str = "/aoti_rs.v1.Predict/GetMetadata";
+
+/// The `Predict` service, usable as a tonic service directly or through
+/// [`PredictServer::serve`].
+pub struct PredictServer<D: Device> {
+    models: Models<D>,
+}
+
+impl<D: Device> Clone for PredictServer<D> {
+    fn clone(&self) -> Self {
+        Self {
+            models: self.models.clone(),
+        }
+    }
+}
+
+impl<D: Device> Default for PredictServer<D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<D: Device> PredictServer<D> {
+    /// A server with no models; add them with [`PredictServer::model`].
+    pub fn new() -> Self {
+        Self {
+            models: Models::new(),
+        }
+    }
+
+    /// Serve `pool` under `name`.
+    pub fn model(mut self, name: impl Into<String>, pool: Arc<AOTIModelPool<D>>) -> Self {
+        self.models.insert(name.into(), pool);
+        self
+    }
+
+    /// Names of the served models.
+    pub fn model_names(&self) -> impl Iterator<Item = &str> {
+        self.models.names()
+    }
+
+    /// Handle a `Predict` call.
+    pub async fn predict(&self, request: PredictRequest) -> Result<PredictResponse, Status> {
+        self.predict_with_headers(request, &http::HeaderMap::new())
+            .await
+    }
+
+    /// Handle a `Predict` call that arrived with `headers`, which may carry
+    /// the caller's trace context.
+    async fn predict_with_headers(
+        &self,
+        request: PredictRequest,
+        headers: &http::HeaderMap,
+    ) -> Result<PredictResponse, Status> {
+        let pool = self.models.get(&request.model_name)?;
+        let inputs = request
+            .inputs
+            .iter()
+            .map(TensorProto::to_tensor)
+            .collect::<Result<Vec<_>, _>>()?;
+        let outputs = predict(pool, inputs, headers).await?;
+        Ok(PredictResponse {
+            outputs: outputs
+                .iter()
+                .map(TensorProto::from_tensor)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    /// Handle a `GetMetadata` call.
+    pub async fn metadata(&self, request: MetadataRequest) -> Result<MetadataResponse, Status> {
+        let pool = self.models.get(&request.model_name)?;
+        Ok(MetadataResponse {
+            metadata: pool
+                .metadata()
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            device: device_string(pool.device()),
+            replicas: pool.len() as u32,
+        })
+    }
+
+    /// Listen on `addr` until the process exits or the transport fails.
+    pub async fn serve(self, addr: SocketAddr) -> Result<(), Error> {
+        tonic::transport::Server::builder()
+            .add_service(self)
+            .serve(addr)
+            .await
+            .map_err(|e| Error::Model(format!("gRPC transport error: {e}")))
+    }
+}
+
+struct PredictCall<D: Device>(PredictServer<D>);
+
+impl<D: Device> UnaryService<PredictRequest> for PredictCall<D> {
+    type Response = PredictResponse;
+    type Future = BoxFuture<Response<PredictResponse>, Status>;
+
+    fn call(&mut self, request: Request<PredictRequest>) -> Self::Future {
+        let server = self.0.clone();
+        Box::pin(async move {
+            let headers = request.metadata().as_ref().clone();
+            server
+                .predict_with_headers(request.into_inner(), &headers)
+                .await
+                .map(Response::new)
+        })
+    }
+}
+
+struct MetadataCall<D: Device>(PredictServer<D>);
+
+impl<D: Device> UnaryService<MetadataRequest> for MetadataCall<D> {
+    type Response = MetadataResponse;
+    type Future = BoxFuture<Response<MetadataResponse>, Status>;
+
+    fn call(&mut self, request: Request<MetadataRequest>) -> Self::Future {
+        let server = self.0.clone();
+        Box::pin(async move {
+            server
+                .metadata(request.into_inner())
+                .await
+                .map(Response::new)
+        })
+    }
+}
+
+impl<D: Device, B> Service<http::Request<B>> for PredictServer<D>
+where
+    B: Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    type Response = http::Response<tonic::body::Body>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        let server = self.clone();
+        match req.uri().path() {
+            PREDICT_PATH => Box::pin(async move {
+                let mut grpc = tonic::server::Grpc::new(ProstCodec::default());
+                Ok(grpc.unary(PredictCall(server), req).await)
+            }),
+            METADATA_PATH => Box::pin(async move {
+                let mut grpc = tonic::server::Grpc::new(ProstCodec::default());
+                Ok(grpc.unary(MetadataCall(server), req).await)
+            }),
+            _ => Box::pin(async move { Ok(Status::unimplemented("").into_http()) }),
+        }
+    }
+}
+
+impl<D: Device> NamedService for PredictServer<D> {
+    const NAME: &'static str = SERVICE_NAME;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Cpu;
+    use prost::Message;
+
+    #[test]
+    fn tensor_proto_round_trips_through_protobuf() {
+        let t = tch::Tensor::from_slice(&[0.5f32, 1.5, -3.0]).view([1, 3]);
+        let proto = TensorProto::from_tensor(&t).unwrap();
+        let decoded = TensorProto::decode(proto.encode_to_vec().as_slice()).unwrap();
+        assert_eq!(decoded.dtype, "float32");
+        assert!(decoded.to_tensor().unwrap().equal(&t));
+    }
+
+    #[test]
+    fn unknown_model_is_invalid_argument() {
+        let server = PredictServer::<Cpu>::new();
+        let status = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(server.metadata(MetadataRequest {
+                model_name: "missing".into(),
+            }))
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    }
+}
@@ -0,0 +1,337 @@
+//! HTTP/REST serving with axum (feature `http`).
+//!
+//! [`HttpServer`] exposes named [`AOTIModelPool`]s under TF-Serving-style
+//! routes:
+//!
+//! - `GET /v1/models` lists the served models.
+//! - `GET /v1/models/{name}` returns a model's device, replica count and
+//!   package metadata.
+//! - `POST /v1/models/{name}:predict` runs inference.
+//!
+//! Prediction bodies are JSON by default,
+//! `{"inputs": [{"dtype": "float32", "shape": [1, 3], "data": [..]}]}`
+//! with row-major `data`, answered in the same shape under `"outputs"`.
+//! Large tensors can skip JSON: with `Content-Type` (or `Accept`) set to
+//! [`BINARY_CONTENT_TYPE`], the request (or response) body uses the
+//! framing of [`super::encode_tensors`].
+
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use axum::body::Bytes;
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tch::{Kind, Tensor};
+
+use super::{Models, decode_tensors, dtype_name, encode_tensors, parse_dtype, predict};
+use crate::{AOTIModelPool, Device, Error, device_string};
+
+/// Media type of binary-framed tensor payloads.
+pub const BINARY_CONTENT_TYPE: &str = "application/x-aoti-tensors";
+
+/// A tensor in a JSON request or response.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JsonTensor {
+    pub dtype: String,
+    pub shape: Vec<i64>,
+    /// Row-major values: numbers, or booleans for `"bool"` tensors.
+    pub data: Vec<Value>,
+}
+
+impl JsonTensor {
+    /// Build a host tensor, checking the value count against the shape.
+    pub fn to_tensor(&self) -> Result<Tensor, Error> {
+        let kind = parse_dtype(&self.dtype)?;
+        let numel = self.shape.iter().try_fold(1usize, |acc, &d| {
+            usize::try_from(d).ok().and_then(|d| acc.checked_mul(d))
+        });
+        if numel != Some(self.data.len()) {
+            return Err(Error::InvalidInput(format!(
+                "{} values do not fill shape {:?}",
+                self.data.len(),
+                self.shape
+            )));
+        }
+        let bad = |v: &Value| Error::InvalidInput(format!("{v} is not a valid {}", self.dtype));
+        let flat = match kind {
+            Kind::Bool => Tensor::f_from_slice(
+                &self
+                    .data
+                    .iter()
+                    .map(|v| {
+                        v.as_bool()
+                            .or(v.as_i64().map(|i| i != 0))
+                            .ok_or_else(|| bad(v))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?,
+            )?,
+            Kind::Float | Kind::Double | Kind::Half | Kind::BFloat16 => Tensor::f_from_slice(
+                &self
+                    .data
+                    .iter()
+                    .map(|v| v.as_f64().ok_or_else(|| bad(v)))
+                    .collect::<Result<Vec<_>, _>>()?,
+            )?,
+            _ => Tensor::f_from_slice(
+                &self
+                    .data
+                    .iter()
+                    .map(|v| v.as_i64().ok_or_else(|| bad(v)))
+                    .collect::<Result<Vec<_>, _>>()?,
+            )?,
+        };
+        Ok(flat.f_to_kind(kind)?.f_reshape(&self.shape)?)
+    }
+
+    /// Copy a tensor, from any device, into JSON form.
+    pub fn from_tensor(tensor: &Tensor) -> Result<Self, Error> {
+        let kind = tensor.f_kind()?;
+        let dtype = dtype_name(kind)?.to_string();
+        let flat = tensor
+            .f_to_device(tch::Device::Cpu)?
+            .f_contiguous()?
+            .f_view([-1])?;
+        let data = match kind {
+            Kind::Bool => Vec::<bool>::try_from(flat)?
+                .into_iter()
+                .map(Value::from)
+                .collect(),
+            Kind::Float | Kind::Double | Kind::Half | Kind::BFloat16 => {
+                Vec::<f64>::try_from(flat.f_to_kind(Kind::Double)?)?
+                    .into_iter()
+                    .map(Value::from)
+                    .collect()
+            }
+            _ => Vec::<i64>::try_from(flat.f_to_kind(Kind::Int64)?)?
+                .into_iter()
+                .map(Value::from)
+                .collect(),
+        };
+        Ok(Self {
+            dtype,
+            shape: tensor.size(),
+            data,
+        })
+    }
+}
+
+/// JSON body of a `:predict` request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PredictBody {
+    pub inputs: Vec<JsonTensor>,
+}
+
+/// JSON body of a `:predict` response.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PredictResult {
+    pub outputs: Vec<JsonTensor>,
+}
+
+/// JSON body of `GET /v1/models/{name}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelInfo {
+    pub name: String,
+    pub device: String,
+    pub replicas: usize,
+    pub metadata: BTreeMap<String, String>,
+}
+
+/// An error response: the crate error's message and an HTTP status.
+struct ApiError(StatusCode, String);
+
+impl From<Error> for ApiError {
+    fn from(err: Error) -> Self {
+        let status = match err.root() {
+            Error::InvalidInput(_)
+            | Error::UnsupportedDtype(_)
+            | Error::TensorKindMismatch { .. } => StatusCode::BAD_REQUEST,
+            Error::Overloaded { .. } | Error::ShutDown | Error::Quarantined => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            Error::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            Error::DeadlineExceeded => StatusCode::GATEWAY_TIMEOUT,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        ApiError(status, err.to_string())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(serde_json::json!({ "error": self.1 }))).into_response()
+    }
+}
+
+fn not_found(name: &str) -> ApiError {
+    ApiError(
+        StatusCode::NOT_FOUND,
+        format!("no model named '{name}' is served"),
+    )
+}
+
+/// The REST front-end.
+pub struct HttpServer<D: Device> {
+    models: Models<D>,
+}
+
+impl<D: Device> Clone for HttpServer<D> {
+    fn clone(&self) -> Self {
+        Self {
+            models: self.models.clone(),
+        }
+    }
+}
+
+impl<D: Device> Default for HttpServer<D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<D: Device> HttpServer<D> {
+    /// A server with no models; add them with [`HttpServer::model`].
+    pub fn new() -> Self {
+        Self {
+            models: Models::new(),
+        }
+    }
+
+    /// Serve `pool` under `name`.
+    pub fn model(mut self, name: impl Into<String>, pool: Arc<AOTIModelPool<D>>) -> Self {
+        self.models.insert(name.into(), pool);
+        self
+    }
+
+    /// The routes, for mounting into a larger axum application.
+    pub fn router(self) -> Router {
+        Router::new()
+            .route("/v1/models", get(list_models::<D>))
+            .route(
+                "/v1/models/{target}",
+                get(model_info::<D>).post(predict_route::<D>),
+            )
+            .with_state(self.models)
+    }
+
+    /// Listen on `addr` until the process exits or the listener fails.
+    pub async fn serve(self, addr: SocketAddr) -> Result<(), Error> {
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        axum::serve(listener, self.router()).await?;
+        Ok(())
+    }
+}
+
+async fn list_models<D: Device>(State(models): State<Models<D>>) -> Json<Value> {
+    let mut names: Vec<&str> = models.names().collect();
+    names.sort_unstable();
+    Json(serde_json::json!({ "models": names }))
+}
+
+async fn model_info<D: Device>(
+    State(models): State<Models<D>>,
+    Path(name): Path<String>,
+) -> Result<Json<ModelInfo>, ApiError> {
+    let pool = models.get(&name).map_err(|_| not_found(&name))?;
+    Ok(Json(ModelInfo {
+        device: device_string(pool.device()),
+        replicas: pool.len(),
+        metadata: pool
+            .metadata()
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+        name,
+    }))
+}
+
+async fn predict_route<D: Device>(
+    State(models): State<Models<D>>,
+    Path(target): Path<String>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, ApiError> {
+    let Some(name) = target.strip_suffix(":predict") else {
+        return Err(ApiError(
+            StatusCode::NOT_FOUND,
+            format!("unknown method in '{target}'; expected '{{name}}:predict'"),
+        ));
+    };
+    let pool = models.get(name).map_err(|_| not_found(name))?;
+
+    let is_binary = |name: header::HeaderName| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.contains(BINARY_CONTENT_TYPE))
+    };
+    let inputs = if is_binary(header::CONTENT_TYPE) {
+        decode_tensors(&body)?
+    } else {
+        let body: PredictBody = serde_json::from_slice(&body)
+            .map_err(|e| ApiError(StatusCode::BAD_REQUEST, format!("invalid JSON body: {e}")))?;
+        body.inputs
+            .iter()
+            .map(JsonTensor::to_tensor)
+            .collect::<Result<_, _>>()?
+    };
+
+    let outputs = predict(pool, inputs, &headers).await?;
+
+    if is_binary(header::ACCEPT) || is_binary(header::CONTENT_TYPE) {
+        let bytes = encode_tensors(&outputs)?;
+        Ok(([(header::CONTENT_TYPE, BINARY_CONTENT_TYPE)], bytes).into_response())
+    } else {
+        let outputs = outputs
+            .iter()
+            .map(JsonTensor::from_tensor)
+            .collect::<Result<_, _>>()?;
+        Ok(Json(PredictResult { outputs }).into_response())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_tensors_round_trip() {
+        let body: PredictBody = serde_json::from_str(
+            r#"{"inputs": [{"dtype": "int32", "shape": [2, 2], "data": [1, 2, 3, 4]}]}"#,
+        )
+        .unwrap();
+        let t = body.inputs[0].to_tensor().unwrap();
+        assert_eq!((t.kind(), t.size()), (Kind::Int, vec![2, 2]));
+        assert_eq!(JsonTensor::from_tensor(&t).unwrap(), body.inputs[0]);
+    }
+
+    #[test]
+    fn malformed_json_tensors_are_bad_requests() {
+        let short = JsonTensor {
+            dtype: "float32".into(),
+            shape: vec![3],
+            data: vec![Value::from(1.0)],
+        };
+        let err = ApiError::from(short.to_tensor().unwrap_err());
+        assert_eq!(err.0, StatusCode::BAD_REQUEST);
+        let wrong = JsonTensor {
+            dtype: "int64".into(),
+            shape: vec![1],
+            data: vec![Value::from("seven")],
+        };
+        assert!(matches!(wrong.to_tensor(), Err(Error::InvalidInput(_))));
+        // Rejected, though the element count would wrap to 0.
+        let overflowing = JsonTensor {
+            dtype: "float32".into(),
+            shape: vec![1 << 62, 4],
+            data: Vec::new(),
+        };
+        let err = ApiError::from(overflowing.to_tensor().unwrap_err());
+        assert!(err.0.is_client_error());
+    }
+}
@@ -0,0 +1,461 @@
+//! Unix-domain-socket serving (feature `ipc`, Unix only).
+//!
+//! [`IpcServer`] hosts named [`AOTIModelPool`]s in one process and
+//! [`IpcClient`] submits work to it from others, so a sidecar can own
+//! libtorch and the GPU while many small processes stay free of both. The
+//! protocol is plain blocking I/O on `std` sockets: every message is a
+//! `u64` little-endian length followed by that many bytes.
+//!
+//! A request starts with a one-byte operation and a `u16`-length model
+//! name (empty selects the only model); predictions carry their inputs in
+//! the framing of [`super::encode_tensors`]. A response starts with a
+//! one-byte status; on failure the rest is the UTF-8 error message.
+//!
+//! With the `shm` feature on Linux, [`IpcClient::shared_memory`] moves
+//! large predictions out of the socket: inputs and outputs travel as
+//! sealed memfds passed alongside the frame header.
+//!
+//! ```no_run
+//! # fn serve() -> Result<(), aoti_rs::Error> {
+//! use std::sync::Arc;
+//! use aoti_rs::{AOTIModel, AOTIModelPool, Cpu};
+//! use aoti_rs::serve::ipc::{IpcClient, IpcServer};
+//!
+//! // In the sidecar:
+//! let pool = AOTIModelPool::<Cpu>::from_fn(2, |_| AOTIModel::<Cpu>::load("model.pt2"))?;
+//! std::thread::spawn(move || {
+//!     IpcServer::new().model("ranker", Arc::new(pool)).serve("/tmp/aoti.sock")
+//! });
+//!
+//! // In a client process:
+//! let mut client = IpcClient::connect("/tmp/aoti.sock")?;
+//! let outputs = client.predict("ranker", &[tch::Tensor::zeros([1, 16], tch::kind::FLOAT_CPU)])?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::os::fd::{AsFd, BorrowedFd, OwnedFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::sync::Arc;
+
+use tch::Tensor;
+
+#[cfg(all(feature = "shm", target_os = "linux"))]
+use super::shm;
+use super::{Framed, Models, decode_tensors, encode_tensors, run_on_host};
+use crate::{AOTIModelPool, Device, Error};
+
+/// Largest message either side accepts unless configured otherwise.
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 1 << 30;
+
+const OP_PREDICT: u8 = 0;
+const OP_METADATA: u8 = 1;
+const OP_MODELS: u8 = 2;
+/// Like [`OP_PREDICT`], with the inputs and outputs in attached memfds.
+#[cfg(all(feature = "shm", target_os = "linux"))]
+const OP_PREDICT_SHM: u8 = 3;
+
+const STATUS_OK: u8 = 0;
+const STATUS_INVALID_INPUT: u8 = 1;
+const STATUS_ERROR: u8 = 2;
+
+/// A received message and the descriptor, if any, passed along with it.
+struct Frame {
+    payload: Vec<u8>,
+    fd: Option<OwnedFd>,
+}
+
+fn write_frame(
+    stream: &mut UnixStream,
+    payload: &[u8],
+    fd: Option<BorrowedFd<'_>>,
+) -> io::Result<()> {
+    match fd {
+        #[cfg(all(feature = "shm", target_os = "linux"))]
+        Some(fd) => shm::send_frame(stream, payload, fd),
+        _ => {
+            stream.write_all(&(payload.len() as u64).to_le_bytes())?;
+            stream.write_all(payload)?;
+            stream.flush()
+        }
+    }
+}
+
+/// Fill `header`, keeping any descriptor passed with it. Returns `false`
+/// if the peer closed the connection instead.
+#[cfg(all(feature = "shm", target_os = "linux"))]
+fn read_header(
+    stream: &mut UnixStream,
+    header: &mut [u8; 8],
+    fd: &mut Option<OwnedFd>,
+) -> io::Result<bool> {
+    let mut filled = 0;
+    while filled < header.len() {
+        let (n, received) = shm::recv_with_fd(stream, &mut header[filled..])?;
+        if received.is_some() {
+            *fd = received;
+        }
+        match n {
+            0 if filled == 0 => return Ok(false),
+            0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+            n => filled += n,
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(not(all(feature = "shm", target_os = "linux")))]
+fn read_header(
+    stream: &mut UnixStream,
+    header: &mut [u8; 8],
+    _fd: &mut Option<OwnedFd>,
+) -> io::Result<bool> {
+    match stream.read_exact(header) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Read one frame, or `None` if the peer closed the connection cleanly.
+fn read_frame(stream: &mut UnixStream, max_size: usize) -> Result<Option<Frame>, Error> {
+    let mut len = [0u8; 8];
+    let mut fd = None;
+    if !read_header(stream, &mut len, &mut fd)? {
+        return Ok(None);
+    }
+    let len = u64::from_le_bytes(len);
+    if len > max_size as u64 {
+        return Err(Error::InvalidInput(format!(
+            "{len}-byte message exceeds the {max_size}-byte limit"
+        )));
+    }
+    let mut payload = vec![0u8; len as usize];
+    stream.read_exact(&mut payload)?;
+    Ok(Some(Frame { payload, fd }))
+}
+
+fn encode_strings<'a>(out: &mut Vec<u8>, strings: impl ExactSizeIterator<Item = &'a str>) {
+    out.extend_from_slice(&(strings.len() as u32).to_le_bytes());
+    for s in strings {
+        out.extend_from_slice(&(s.len() as u32).to_le_bytes());
+        out.extend_from_slice(s.as_bytes());
+    }
+}
+
+fn decode_strings(mut bytes: &[u8]) -> Result<Vec<String>, Error> {
+    let truncated = || Error::InvalidInput("truncated string list".into());
+    let mut take = |n: usize| -> Result<&[u8], Error> {
+        if bytes.len() < n {
+            return Err(truncated());
+        }
+        let (head, rest) = bytes.split_at(n);
+        bytes = rest;
+        Ok(head)
+    };
+    let count = u32::from_le_bytes(take(4)?.try_into().expect("4 bytes"));
+    (0..count)
+        .map(|_| {
+            let len = u32::from_le_bytes(take(4)?.try_into().expect("4 bytes"));
+            String::from_utf8(take(len as usize)?.to_vec())
+                .map_err(|_| Error::InvalidInput("string is not UTF-8".into()))
+        })
+        .collect()
+}
+
+/// Hosts model pools on a Unix socket, one thread per connection.
+pub struct IpcServer<D: Device> {
+    models: Models<D>,
+    max_message_size: usize,
+}
+
+impl<D: Device> Clone for IpcServer<D> {
+    fn clone(&self) -> Self {
+        Self {
+            models: self.models.clone(),
+            max_message_size: self.max_message_size,
+        }
+    }
+}
+
+impl<D: Device> Default for IpcServer<D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<D: Device> IpcServer<D> {
+    /// A server with no models; add them with [`IpcServer::model`].
+    pub fn new() -> Self {
+        Self {
+            models: Models::new(),
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+        }
+    }
+
+    /// Serve `pool` under `name`.
+    pub fn model(mut self, name: impl Into<String>, pool: Arc<AOTIModelPool<D>>) -> Self {
+        self.models.insert(name.into(), pool);
+        self
+    }
+
+    /// Reject requests larger than `bytes`; the connection is closed.
+    pub fn max_message_size(mut self, bytes: usize) -> Self {
+        self.max_message_size = bytes;
+        self
+    }
+
+    /// Bind `path`, replacing a stale socket file left by an earlier run,
+    /// and serve until accepting a connection fails.
+    pub fn serve(self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let path = path.as_ref();
+        match std::fs::remove_file(path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+        self.serve_listener(UnixListener::bind(path)?)
+    }
+
+    /// Serve connections from an already-bound listener.
+    pub fn serve_listener(self, listener: UnixListener) -> Result<(), Error> {
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let server = self.clone();
+            std::thread::spawn(move || {
+                // A broken or oversized connection only ends that client.
+                let _ = server.handle_connection(stream);
+            });
+        }
+        Ok(())
+    }
+
+    fn handle_connection(&self, mut stream: UnixStream) -> Result<(), Error> {
+        while let Some(request) = read_frame(&mut stream, self.max_message_size)? {
+            let (response, fd) = match self.handle(request) {
+                Ok((body, fd)) => ([&[STATUS_OK][..], &body].concat(), fd),
+                Err(err) => {
+                    let status = match err {
+                        Error::InvalidInput(_)
+                        | Error::UnsupportedDtype(_)
+                        | Error::TensorKindMismatch { .. } => STATUS_INVALID_INPUT,
+                        _ => STATUS_ERROR,
+                    };
+                    // The client re-wraps the text in the matching variant.
+                    let message = match err {
+                        Error::InvalidInput(m) | Error::Model(m) => m,
+                        err => err.to_string(),
+                    };
+                    ([&[status][..], message.as_bytes()].concat(), None)
+                }
+            };
+            write_frame(&mut stream, &response, fd.as_ref().map(|fd| fd.as_fd()))?;
+        }
+        Ok(())
+    }
+
+    /// Answer one request with a response body and, for shared-memory
+    /// predictions, the memfd holding the outputs.
+    fn handle(&self, request: Frame) -> Result<(Vec<u8>, Option<OwnedFd>), Error> {
+        let malformed = || Error::InvalidInput("malformed request header".into());
+        let (&op, rest) = request.payload.split_first().ok_or_else(malformed)?;
+        let (name_len, rest) = rest.split_first_chunk::<2>().ok_or_else(malformed)?;
+        let name_len = u16::from_le_bytes(*name_len) as usize;
+        if rest.len() < name_len {
+            return Err(malformed());
+        }
+        let (name, body) = rest.split_at(name_len);
+        let name = std::str::from_utf8(name)
+            .map_err(|_| Error::InvalidInput("model name is not UTF-8".into()))?;
+
+        match op {
+            OP_MODELS => {
+                let mut names: Vec<&str> = self.models.names().collect();
+                names.sort_unstable();
+                let mut out = Vec::new();
+                encode_strings(&mut out, names.into_iter());
+                Ok((out, None))
+            }
+            OP_METADATA => {
+                let pool = self.models.get(name)?;
+                let mut out = Vec::new();
+                let pairs: Vec<&str> = pool.metadata().iter().flat_map(|(k, v)| [k, v]).collect();
+                encode_strings(&mut out, pairs.into_iter());
+                Ok((out, None))
+            }
+            OP_PREDICT => {
+                let pool = self.models.get(name)?;
+                let inputs = decode_tensors(body)?;
+                Ok((encode_tensors(&run_on_host(&pool, &inputs, None)?)?, None))
+            }
+            #[cfg(all(feature = "shm", target_os = "linux"))]
+            OP_PREDICT_SHM => {
+                let pool = self.models.get(name)?;
+                let fd = request.fd.ok_or_else(|| {
+                    Error::InvalidInput("shared-memory request carried no descriptor".into())
+                })?;
+                let inputs = shm::tensors_from_memfd(fd, self.max_message_size)?;
+                let outputs = shm::tensors_to_memfd(&run_on_host(&pool, &inputs, None)?)?;
+                Ok((Vec::new(), Some(outputs)))
+            }
+            _ => Err(Error::InvalidInput(format!("unsupported operation {op}"))),
+        }
+    }
+}
+
+/// A connection to an [`IpcServer`]. Requests on one client are answered
+/// in order; open several clients for concurrent work.
+///
+/// Transport failures, including the server hanging up, surface as
+/// [`Error::Io`]; errors raised by the server as [`Error::InvalidInput`] or
+/// [`Error::Model`].
+pub struct IpcClient {
+    stream: UnixStream,
+    max_message_size: usize,
+    #[cfg(all(feature = "shm", target_os = "linux"))]
+    shm_threshold: Option<usize>,
+}
+
+impl IpcClient {
+    /// Connect to the server listening on `path`.
+    pub fn connect(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Ok(Self::from_stream(UnixStream::connect(path)?))
+    }
+
+    /// Wrap an already-connected stream, e.g. one half of a socket pair.
+    pub fn from_stream(stream: UnixStream) -> Self {
+        Self {
+            stream,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            #[cfg(all(feature = "shm", target_os = "linux"))]
+            shm_threshold: None,
+        }
+    }
+
+    /// Reject responses larger than `bytes`.
+    pub fn max_message_size(mut self, bytes: usize) -> Self {
+        self.max_message_size = bytes;
+        self
+    }
+
+    /// Send predictions whose encoded inputs reach `min_bytes` through
+    /// shared memory; the server answers them the same way. The server
+    /// must also be built with `shm`.
+    #[cfg(all(feature = "shm", target_os = "linux"))]
+    pub fn shared_memory(mut self, min_bytes: usize) -> Self {
+        self.shm_threshold = Some(min_bytes);
+        self
+    }
+
+    /// Run `model` on `inputs`, from any device, returning host tensors.
+    pub fn predict(&mut self, model: &str, inputs: &[Tensor]) -> Result<Vec<Tensor>, Error> {
+        let framed = Framed::new(inputs)?;
+        #[cfg(all(feature = "shm", target_os = "linux"))]
+        if self.shm_threshold.is_some_and(|min| framed.len() >= min) {
+            let fd = shm::framed_to_memfd(&framed)?;
+            let response = self.call(OP_PREDICT_SHM, model, &[], Some(fd.as_fd()))?;
+            let fd = response.fd.ok_or_else(|| {
+                Error::Model("shared-memory response carried no descriptor".into())
+            })?;
+            return shm::tensors_from_memfd(fd, self.max_message_size);
+        }
+        let mut body = vec![0u8; framed.len()];
+        framed.write_to(&mut body)?;
+        decode_tensors(&self.call(OP_PREDICT, model, &body, None)?.payload)
+    }
+
+    /// Package metadata of `model`.
+    pub fn metadata(&mut self, model: &str) -> Result<HashMap<String, String>, Error> {
+        let flat = decode_strings(&self.call(OP_METADATA, model, &[], None)?.payload)?;
+        let mut pairs = flat.into_iter();
+        let mut metadata = HashMap::new();
+        while let (Some(k), Some(v)) = (pairs.next(), pairs.next()) {
+            metadata.insert(k, v);
+        }
+        Ok(metadata)
+    }
+
+    /// Names of the served models, sorted.
+    pub fn models(&mut self) -> Result<Vec<String>, Error> {
+        decode_strings(&self.call(OP_MODELS, "", &[], None)?.payload)
+    }
+
+    fn call(
+        &mut self,
+        op: u8,
+        model: &str,
+        body: &[u8],
+        fd: Option<BorrowedFd<'_>>,
+    ) -> Result<Frame, Error> {
+        let name_len = u16::try_from(model.len())
+            .map_err(|_| Error::InvalidInput(format!("model name '{model}' is too long")))?;
+        let mut request = Vec::with_capacity(3 + model.len() + body.len());
+        request.push(op);
+        request.extend_from_slice(&name_len.to_le_bytes());
+        request.extend_from_slice(model.as_bytes());
+        request.extend_from_slice(body);
+        write_frame(&mut self.stream, &request, fd)?;
+
+        let response = read_frame(&mut self.stream, self.max_message_size)?.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "IPC server closed the connection without answering",
+            )
+        })?;
+        let (&status, body) = response
+            .payload
+            .split_first()
+            .ok_or_else(|| Error::Model("empty IPC response".into()))?;
+        let message = || String::from_utf8_lossy(body).into_owned();
+        match status {
+            STATUS_OK => Ok(Frame {
+                payload: body.to_vec(),
+                fd: response.fd,
+            }),
+            STATUS_INVALID_INPUT => Err(Error::InvalidInput(message())),
+            _ => Err(Error::Model(message())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Cpu;
+
+    fn connected(server: IpcServer<Cpu>) -> IpcClient {
+        let (ours, theirs) = UnixStream::pair().unwrap();
+        std::thread::spawn(move || server.handle_connection(theirs));
+        IpcClient::from_stream(ours)
+    }
+
+    #[test]
+    fn client_sees_server_errors() {
+        let mut client = connected(IpcServer::new());
+        assert!(client.models().unwrap().is_empty());
+        let err = client
+            .predict("missing", &[Tensor::from(1.0f32)])
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidInput(m) if m.contains("missing")));
+        // The connection survives a failed request.
+        assert!(client.metadata("missing").is_err());
+        assert!(client.models().unwrap().is_empty());
+    }
+
+    #[test]
+    fn oversized_requests_close_the_connection() {
+        let mut client = connected(IpcServer::new().max_message_size(8));
+        let big = Tensor::zeros([64], tch::kind::FLOAT_CPU);
+        assert!(matches!(client.predict("", &[big]), Err(Error::Io(_))));
+    }
+
+    #[test]
+    fn string_lists_round_trip() {
+        let mut out = Vec::new();
+        encode_strings(&mut out, ["a", "", "ümlaut"].into_iter());
+        assert_eq!(decode_strings(&out).unwrap(), ["a", "", "ümlaut"]);
+        assert!(decode_strings(&out[..out.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,396 @@
+//! Network front-ends serving [`AOTIModelPool`]s.
+//!
+//! Each transport lives behind its own feature. They share the wire
+//! representation of a tensor defined here: a dtype name as PyTorch spells
+//! it (`"float32"`, `"int64"`, ...), a shape, and the row-major values as
+//! little-endian bytes. The async transports run inference on Tokio's
+//! blocking thread pool so a busy model never stalls the runtime.
+
+#[cfg(feature = "flight")]
+pub mod flight;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "http")]
+pub mod http;
+#[cfg(all(feature = "ipc", unix))]
+pub mod ipc;
+#[cfg(all(feature = "shm", target_os = "linux"))]
+mod shm;
+#[cfg(all(feature = "workers", unix))]
+pub mod workers;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+
+use tch::{Kind, Tensor};
+
+use crate::{AOTIModelPool, Device, Error};
+
+/// The dtype names accepted on the wire, paired with their tch kinds.
+const DTYPES: &[(&str, Kind)] = &[
+    ("float32", Kind::Float),
+    ("float64", Kind::Double),
+    ("float16", Kind::Half),
+    ("bfloat16", Kind::BFloat16),
+    ("int8", Kind::Int8),
+    ("int16", Kind::Int16),
+    ("int32", Kind::Int),
+    ("int64", Kind::Int64),
+    ("uint8", Kind::Uint8),
+    ("bool", Kind::Bool),
+];
+
+/// The wire name of a tensor kind.
+pub fn dtype_name(kind: Kind) -> Result<&'static str, Error> {
+    DTYPES
+        .iter()
+        .find(|(_, k)| *k == kind)
+        .map(|(name, _)| *name)
+        .ok_or_else(|| Error::UnsupportedDtype(format!("torch {kind:?}")))
+}
+
+/// The tensor kind for a wire dtype name.
+pub fn parse_dtype(name: &str) -> Result<Kind, Error> {
+    DTYPES
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, kind)| *kind)
+        .ok_or_else(|| Error::UnsupportedDtype(name.to_string()))
+}
+
+/// Build a host tensor from its wire form, checking that `data` holds
+/// exactly `product(shape)` elements.
+pub fn tensor_from_bytes(dtype: &str, shape: &[i64], data: &[u8]) -> Result<Tensor, Error> {
+    let kind = parse_dtype(dtype)?;
+    let numel = shape.iter().try_fold(1usize, |acc, &d| {
+        usize::try_from(d).ok().and_then(|d| acc.checked_mul(d))
+    });
+    if numel.and_then(|n| n.checked_mul(kind.elt_size_in_bytes())) != Some(data.len()) {
+        return Err(Error::InvalidInput(format!(
+            "{} bytes do not hold a {dtype} tensor of shape {shape:?}",
+            data.len()
+        )));
+    }
+    Ok(Tensor::f_from_data_size(data, shape, kind)?)
+}
+
+/// Copy a tensor, from any device, into its wire form
+/// `(dtype, shape, data)`.
+pub fn tensor_to_bytes(tensor: &Tensor) -> Result<(&'static str, Vec<i64>, Vec<u8>), Error> {
+    let kind = tensor.f_kind()?;
+    let dtype = dtype_name(kind)?;
+    let host = tensor.f_to_device(tch::Device::Cpu)?.f_contiguous()?;
+    let numel = host.numel();
+    let mut data = vec![0u8; numel * kind.elt_size_in_bytes()];
+    host.f_copy_data_u8(&mut data, numel)?;
+    Ok((dtype, host.size(), data))
+}
+
+/// Encode tensors in the compact binary framing used by the non-protobuf
+/// transports: a `u32` tensor count, then per tensor a `u8`-length dtype
+/// name, a `u8` rank, the `i64` dimensions, a `u64` byte length and the
+/// data. All integers are little-endian.
+pub fn encode_tensors(tensors: &[Tensor]) -> Result<Vec<u8>, Error> {
+    let framed = Framed::new(tensors)?;
+    let mut out = vec![0u8; framed.len()];
+    framed.write_to(&mut out)?;
+    Ok(out)
+}
+
+/// Tensors staged on the host for [`encode_tensors`], so callers can size
+/// a destination buffer before writing into it.
+pub(crate) struct Framed(Vec<(&'static str, Tensor)>);
+
+impl Framed {
+    pub(crate) fn new(tensors: &[Tensor]) -> Result<Self, Error> {
+        u32::try_from(tensors.len())
+            .map_err(|_| Error::InvalidInput(format!("too many tensors: {}", tensors.len())))?;
+        tensors
+            .iter()
+            .map(|tensor| {
+                let dtype = dtype_name(tensor.f_kind()?)?;
+                if tensor.dim() > u8::MAX as usize {
+                    return Err(Error::InvalidInput(format!(
+                        "tensor rank {} is too large",
+                        tensor.dim()
+                    )));
+                }
+                let host = tensor.f_to_device(tch::Device::Cpu)?.f_contiguous()?;
+                Ok((dtype, host))
+            })
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+
+    /// Encoded size in bytes.
+    pub(crate) fn len(&self) -> usize {
+        self.0.iter().fold(4, |len, (dtype, host)| {
+            len + 2
+                + dtype.len()
+                + 8 * host.dim()
+                + 8
+                + host.numel() * host.kind().elt_size_in_bytes()
+        })
+    }
+
+    /// Write the encoding into `out`, which must be exactly
+    /// [`Framed::len`] bytes.
+    pub(crate) fn write_to(&self, mut out: &mut [u8]) -> Result<(), Error> {
+        fn put<'a>(out: &mut &'a mut [u8], n: usize) -> &'a mut [u8] {
+            let (head, rest) = std::mem::take(out).split_at_mut(n);
+            *out = rest;
+            head
+        }
+        put(&mut out, 4).copy_from_slice(&(self.0.len() as u32).to_le_bytes());
+        for (dtype, host) in &self.0 {
+            let shape = host.size();
+            put(&mut out, 1)[0] = dtype.len() as u8;
+            put(&mut out, dtype.len()).copy_from_slice(dtype.as_bytes());
+            put(&mut out, 1)[0] = shape.len() as u8;
+            for dim in &shape {
+                put(&mut out, 8).copy_from_slice(&dim.to_le_bytes());
+            }
+            let numel = host.numel();
+            let len = numel * host.kind().elt_size_in_bytes();
+            put(&mut out, 8).copy_from_slice(&(len as u64).to_le_bytes());
+            host.f_copy_data_u8(put(&mut out, len), numel)?;
+        }
+        Ok(())
+    }
+}
+
+/// Decode tensors written by [`encode_tensors`] into host tensors.
+pub fn decode_tensors(bytes: &[u8]) -> Result<Vec<Tensor>, Error> {
+    let mut reader = Reader(bytes);
+    let count = u32::from_le_bytes(reader.take()?);
+    let mut tensors = Vec::new();
+    for _ in 0..count {
+        let [dtype_len] = reader.take()?;
+        let dtype = std::str::from_utf8(reader.bytes(dtype_len as usize)?)
+            .map_err(|_| Error::InvalidInput("dtype name is not UTF-8".into()))?;
+        let [rank] = reader.take()?;
+        let shape = (0..rank)
+            .map(|_| Ok(i64::from_le_bytes(reader.take()?)))
+            .collect::<Result<Vec<_>, Error>>()?;
+        let len = usize::try_from(u64::from_le_bytes(reader.take()?))
+            .map_err(|_| Error::InvalidInput("tensor data length overflows".into()))?;
+        tensors.push(tensor_from_bytes(dtype, &shape, reader.bytes(len)?)?);
+    }
+    if !reader.0.is_empty() {
+        return Err(Error::InvalidInput(format!(
+            "{} trailing bytes after {count} tensors",
+            reader.0.len()
+        )));
+    }
+    Ok(tensors)
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], Error> {
+        if self.0.len() < n {
+            return Err(Error::InvalidInput("truncated tensor payload".into()));
+        }
+        let (head, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(head)
+    }
+
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        Ok(self.bytes(N)?.try_into().expect("length checked"))
+    }
+}
+
+#[cfg(any(feature = "grpc", feature = "flight"))]
+impl From<Error> for tonic::Status {
+    fn from(err: Error) -> Self {
+        match err.root() {
+            Error::InvalidInput(_)
+            | Error::UnsupportedDtype(_)
+            | Error::TensorKindMismatch { .. } => tonic::Status::invalid_argument(err.to_string()),
+            Error::Overloaded { .. } | Error::ShutDown | Error::Quarantined => {
+                tonic::Status::unavailable(err.to_string())
+            }
+            Error::RateLimited { .. } => tonic::Status::resource_exhausted(err.to_string()),
+            Error::DeadlineExceeded => tonic::Status::deadline_exceeded(err.to_string()),
+            _ => tonic::Status::internal(err.to_string()),
+        }
+    }
+}
+
+/// Named pools served by one endpoint.
+pub(crate) struct Models<D: Device>(Arc<HashMap<String, Arc<AOTIModelPool<D>>>>);
+
+impl<D: Device> Clone for Models<D> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<D: Device> Models<D> {
+    pub(crate) fn new() -> Self {
+        Self(Arc::new(HashMap::new()))
+    }
+
+    pub(crate) fn insert(&mut self, name: String, pool: Arc<AOTIModelPool<D>>) {
+        Arc::make_mut(&mut self.0).insert(name, pool);
+    }
+
+    pub(crate) fn names(&self) -> impl Iterator<Item = &str> {
+        self.0.keys().map(String::as_str)
+    }
+
+    /// Look up `name`; an empty name selects the only model, if there is
+    /// exactly one.
+    pub(crate) fn get(&self, name: &str) -> Result<Arc<AOTIModelPool<D>>, Error> {
+        if name.is_empty() && self.0.len() == 1 {
+            return Ok(self.0.values().next().expect("one model").clone());
+        }
+        self.0
+            .get(name)
+            .cloned()
+            .ok_or_else(|| Error::InvalidInput(format!("no model named '{name}' is served")))
+    }
+}
+
+/// Run host `inputs` on `pool`, skipping the run if `deadline` passes
+/// first, and return host-resident outputs.
+pub(crate) fn run_on_host<D: Device>(
+    pool: &AOTIModelPool<D>,
+    inputs: &[Tensor],
+    deadline: Option<Instant>,
+) -> Result<Vec<Tensor>, Error> {
+    let inputs: Vec<_> = inputs.iter().map(|t| pool.upload(t)).collect();
+    match deadline {
+        Some(deadline) => pool.run_before(deadline, inputs)?,
+        None => pool.run(inputs)?,
+    }
+    .into_iter()
+    .map(|out| Ok(out.f_to_device(tch::Device::Cpu)?))
+    .collect()
+}
+
+/// [`run_on_host`] from async code, on Tokio's blocking pool, scoped to the
+/// request's `x-request-id` header and bounded by its `grpc-timeout` header
+/// if it has them. With feature `otel` the inference is traced as a child
+/// of the context in the request's `headers`.
+#[cfg(any(feature = "flight", feature = "grpc", feature = "http"))]
+pub(crate) async fn predict<D: Device>(
+    pool: Arc<AOTIModelPool<D>>,
+    inputs: Vec<Tensor>,
+    headers: &::http::HeaderMap,
+) -> Result<Vec<Tensor>, Error> {
+    #[cfg(feature = "otel")]
+    let parent = crate::otel::extract(headers);
+    let request_id = crate::request::from_headers(headers);
+    let deadline = headers
+        .get("grpc-timeout")
+        .and_then(|v| v.to_str().ok())
+        .and_then(parse_grpc_timeout)
+        .map(|timeout| Instant::now() + timeout);
+    tokio::task::spawn_blocking(move || {
+        #[cfg(feature = "otel")]
+        let _span = crate::otel::enter_request(&parent, "aoti.predict", {
+            let mut attributes = vec![opentelemetry::KeyValue::new(
+                "aoti.inputs",
+                inputs.len() as i64,
+            )];
+            if let Some(id) = &request_id {
+                attributes.push(opentelemetry::KeyValue::new(
+                    "aoti.request_id",
+                    id.to_string(),
+                ));
+            }
+            attributes
+        });
+        let run = || run_on_host(&pool, &inputs, deadline);
+        let result = match &request_id {
+            Some(id) => id.scope(run),
+            None => run(),
+        };
+        #[cfg(feature = "otel")]
+        crate::otel::record(&result);
+        result
+    })
+    .await
+    .map_err(|e| Error::Model(format!("inference task failed: {e}")))?
+}
+
+/// A `grpc-timeout` header value: at most 8 digits and a unit of `H`, `M`,
+/// `S`, `m`, `u` or `n`.
+#[cfg(any(feature = "flight", feature = "grpc", feature = "http"))]
+fn parse_grpc_timeout(value: &str) -> Option<std::time::Duration> {
+    use std::time::Duration;
+
+    let unit = value.chars().last()?;
+    let digits = &value[..value.len() - unit.len_utf8()];
+    if digits.is_empty() || digits.len() > 8 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let n: u64 = digits.parse().ok()?;
+    Some(match unit {
+        'H' => Duration::from_secs(n * 3_600),
+        'M' => Duration::from_secs(n * 60),
+        'S' => Duration::from_secs(n),
+        'm' => Duration::from_millis(n),
+        'u' => Duration::from_micros(n),
+        'n' => Duration::from_nanos(n),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wire_tensors_round_trip() {
+        let t = Tensor::from_slice(&[1i64, -2, 3, 4, 5, 6]).view([3, 2]);
+        let (dtype, shape, data) = tensor_to_bytes(&t).unwrap();
+        assert_eq!(
+            (dtype, shape.as_slice(), data.len()),
+            ("int64", &[3, 2][..], 48)
+        );
+        assert!(tensor_from_bytes(dtype, &shape, &data).unwrap().equal(&t));
+    }
+
+    #[test]
+    fn binary_framing_round_trips() {
+        let a = Tensor::from_slice(&[1.0f32, 2.0, 3.0, 4.0]).view([2, 2]);
+        let b = Tensor::from_slice(&[true, false]);
+        let bytes = encode_tensors(&[a.shallow_clone(), b.shallow_clone()]).unwrap();
+        let decoded = decode_tensors(&bytes).unwrap();
+        assert_eq!(decoded.len(), 2);
+        assert!(decoded[0].equal(&a) && decoded[1].equal(&b));
+        assert!(matches!(
+            decode_tensors(&bytes[..bytes.len() - 1]),
+            Err(Error::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn wire_tensors_are_validated() {
+        assert!(matches!(
+            tensor_from_bytes("float32", &[2, 2], &[0; 12]),
+            Err(Error::InvalidInput(_))
+        ));
+        assert!(matches!(
+            tensor_from_bytes("complex64", &[1], &[0; 8]),
+            Err(Error::UnsupportedDtype(_))
+        ));
+    }
+
+    #[cfg(any(feature = "flight", feature = "grpc", feature = "http"))]
+    #[test]
+    fn grpc_timeouts_are_parsed() {
+        use std::time::Duration;
+
+        assert_eq!(parse_grpc_timeout("250m"), Some(Duration::from_millis(250)));
+        assert_eq!(parse_grpc_timeout("2H"), Some(Duration::from_secs(7_200)));
+        for bad in ["", "m", "10", "123456789S", "1.5S", "-1S", "5x", "5é"] {
+            assert_eq!(parse_grpc_timeout(bad), None, "{bad}");
+        }
+    }
+}
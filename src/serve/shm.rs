@@ -0,0 +1,178 @@
+//! Shared-memory tensor payloads for [`super::ipc`] (feature `shm`, Linux
+//! only).
+//!
+//! Large payloads are written once into a sealed memfd and the descriptor
+//! travels over the socket as `SCM_RIGHTS` ancillary data, so the bytes
+//! never pass through the socket buffer. The receiver maps the file
+//! read-only and decodes it in place. Seals are checked before mapping: a
+//! peer that could still shrink or rewrite the file could fault or race
+//! the reader.
+
+use std::fs::File;
+use std::io::{self, IoSlice, IoSliceMut, Write};
+use std::os::fd::{AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::net::UnixStream;
+
+use memmap2::{Mmap, MmapMut};
+use nix::errno::Errno;
+use nix::fcntl::{FcntlArg, SealFlag, fcntl};
+use nix::sys::memfd::{MFdFlags, memfd_create};
+use nix::sys::socket::{ControlMessage, ControlMessageOwned, MsgFlags, recvmsg, sendmsg};
+use tch::Tensor;
+
+use super::{Framed, decode_tensors};
+use crate::Error;
+
+/// Seals a payload must carry before it is mapped.
+const REQUIRED_SEALS: SealFlag = SealFlag::F_SEAL_SHRINK
+    .union(SealFlag::F_SEAL_GROW)
+    .union(SealFlag::F_SEAL_WRITE);
+
+/// Encode `tensors` into a new sealed memfd.
+pub(crate) fn tensors_to_memfd(tensors: &[Tensor]) -> Result<OwnedFd, Error> {
+    framed_to_memfd(&Framed::new(tensors)?)
+}
+
+/// Write already-staged tensors into a new sealed memfd.
+pub(crate) fn framed_to_memfd(framed: &Framed) -> Result<OwnedFd, Error> {
+    let fd = memfd_create(
+        c"aoti-rs-tensors",
+        MFdFlags::MFD_CLOEXEC | MFdFlags::MFD_ALLOW_SEALING,
+    )
+    .map_err(io::Error::from)?;
+    let file = File::from(fd);
+    file.set_len(framed.len() as u64)?;
+    {
+        // SAFETY: the memfd was just created and is private to this
+        // process, so nothing else can resize it while it is mapped.
+        let mut map = unsafe { MmapMut::map_mut(&file)? };
+        framed.write_to(&mut map)?;
+    }
+    // F_SEAL_WRITE is refused while a writable mapping exists, so the map
+    // above must be gone by now.
+    fcntl(
+        &file,
+        FcntlArg::F_ADD_SEALS(REQUIRED_SEALS | SealFlag::F_SEAL_SEAL),
+    )
+    .map_err(io::Error::from)?;
+    Ok(file.into())
+}
+
+/// Decode the tensors in a sealed memfd received from a peer, refusing
+/// payloads over `max_size` bytes.
+pub(crate) fn tensors_from_memfd(fd: OwnedFd, max_size: usize) -> Result<Vec<Tensor>, Error> {
+    let seals = fcntl(&fd, FcntlArg::F_GET_SEALS).map_err(io::Error::from)?;
+    if !SealFlag::from_bits_truncate(seals).contains(REQUIRED_SEALS) {
+        return Err(Error::InvalidInput(
+            "shared-memory payload is not sealed against writes and resizing".into(),
+        ));
+    }
+    let file = File::from(fd);
+    let len = file.metadata()?.len();
+    if len > max_size as u64 {
+        return Err(Error::InvalidInput(format!(
+            "{len}-byte shared-memory payload exceeds the {max_size}-byte limit"
+        )));
+    }
+    // SAFETY: the seals checked above keep the file's size and contents
+    // fixed for as long as the mapping lives.
+    let map = unsafe { Mmap::map(&file)? };
+    decode_tensors(&map)
+}
+
+/// Write a length-prefixed frame with `fd` attached to its first bytes.
+pub(crate) fn send_frame(
+    stream: &mut UnixStream,
+    payload: &[u8],
+    fd: BorrowedFd<'_>,
+) -> io::Result<()> {
+    let header = (payload.len() as u64).to_le_bytes();
+    let fds = [fd.as_raw_fd()];
+    let sent = loop {
+        match sendmsg::<()>(
+            stream.as_raw_fd(),
+            &[IoSlice::new(&header)],
+            &[ControlMessage::ScmRights(&fds)],
+            MsgFlags::empty(),
+            None,
+        ) {
+            Err(Errno::EINTR) => continue,
+            result => break result?,
+        }
+    };
+    stream.write_all(&header[sent..])?;
+    stream.write_all(payload)?;
+    stream.flush()
+}
+
+/// Read into `buf`, returning the byte count and any descriptor that came
+/// with those bytes.
+pub(crate) fn recv_with_fd(
+    stream: &UnixStream,
+    buf: &mut [u8],
+) -> io::Result<(usize, Option<OwnedFd>)> {
+    let mut cmsg = nix::cmsg_space!(RawFd);
+    let mut iov = [IoSliceMut::new(buf)];
+    let msg = loop {
+        match recvmsg::<()>(
+            stream.as_raw_fd(),
+            &mut iov,
+            Some(&mut cmsg),
+            MsgFlags::MSG_CMSG_CLOEXEC,
+        ) {
+            Err(Errno::EINTR) => continue,
+            result => break result?,
+        }
+    };
+    let mut received = None;
+    for message in msg.cmsgs()? {
+        if let ControlMessageOwned::ScmRights(fds) = message {
+            for raw in fds {
+                // SAFETY: the kernel installed `raw` in this process for
+                // us; wrapping it ensures extras are closed, not leaked.
+                let fd = unsafe { OwnedFd::from_raw_fd(raw) };
+                received.get_or_insert(fd);
+            }
+        }
+    }
+    Ok((msg.bytes, received))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::fd::AsFd;
+
+    #[test]
+    fn memfd_payloads_round_trip() {
+        let t = Tensor::from_slice(&[1.0f32, 2.0, 3.0]).view([3, 1]);
+        let fd = tensors_to_memfd(&[t.shallow_clone()]).unwrap();
+        let decoded = tensors_from_memfd(fd, usize::MAX).unwrap();
+        assert!(decoded[0].equal(&t));
+    }
+
+    #[test]
+    fn unsealed_payloads_are_refused() {
+        let fd = memfd_create(c"unsealed", MFdFlags::MFD_CLOEXEC).unwrap();
+        File::from(fd.try_clone().unwrap()).set_len(4).unwrap();
+        assert!(matches!(
+            tensors_from_memfd(fd, usize::MAX),
+            Err(Error::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn descriptors_travel_with_frames() {
+        let (mut a, b) = UnixStream::pair().unwrap();
+        let fd = tensors_to_memfd(&[]).unwrap();
+        send_frame(&mut a, b"xy", fd.as_fd()).unwrap();
+        let mut header = [0u8; 8];
+        let (n, received) = recv_with_fd(&b, &mut header).unwrap();
+        assert_eq!((n, u64::from_le_bytes(header)), (8, 2));
+        assert!(
+            tensors_from_memfd(received.unwrap(), 64)
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,495 @@
+//! One model process per device (feature `workers`, Unix only).
+//!
+//! Running several CUDA devices from one process shares a single libtorch
+//! instance, allocator and CUDA context set between them, and one crash
+//! takes every device down. [`Supervisor`] instead spawns one worker
+//! process per device, each loading its own replica and serving it with
+//! [`IpcServer`], then load-balances [`Supervisor::predict`] calls across
+//! them and restarts workers that die.
+//!
+//! Workers are started by re-running a program (by default the current
+//! executable) with `AOTI_RS_WORKER_*` environment variables set. That
+//! program must call [`run_worker_from_env`] early in `main`:
+//!
+//! ```no_run
+//! use aoti_rs::serve::workers::{Supervisor, run_worker_from_env};
+//!
+//! fn main() -> Result<(), aoti_rs::Error> {
+//!     if let Some(result) = run_worker_from_env() {
+//!         return result;
+//!     }
+//!     let workers = Supervisor::builder("model.pt2").cuda_devices([0, 1]).spawn()?;
+//!     let outputs = workers.predict(&[tch::Tensor::zeros([1, 3], tch::kind::FLOAT_CPU)])?;
+//!     # let _ = outputs;
+//!     Ok(())
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use tch::Tensor;
+
+use super::ipc::{IpcClient, IpcServer};
+use crate::{AOTIModel, AOTIModelPool, Cpu, Error};
+
+const SOCKET_ENV: &str = "AOTI_RS_WORKER_SOCKET";
+const PACKAGE_ENV: &str = "AOTI_RS_WORKER_PACKAGE";
+const MODEL_NAME_ENV: &str = "AOTI_RS_WORKER_MODEL_NAME";
+const DEVICE_ENV: &str = "AOTI_RS_WORKER_DEVICE";
+const REPLICAS_ENV: &str = "AOTI_RS_WORKER_REPLICAS";
+
+/// Where one worker runs its replicas.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorkerDevice {
+    Cpu,
+    /// A CUDA ordinal as the supervisor sees it. The worker is started with
+    /// `CUDA_VISIBLE_DEVICES` narrowed to that device alone.
+    Cuda(usize),
+}
+
+/// If this process was started as a worker by a [`Supervisor`], load the
+/// model it was assigned and serve it until the supervisor stops it.
+///
+/// Returns `None` in any other process, which should carry on as usual.
+pub fn run_worker_from_env() -> Option<Result<(), Error>> {
+    let socket = std::env::var_os(SOCKET_ENV)?;
+    Some(run_worker(Path::new(&socket)))
+}
+
+fn run_worker(socket: &Path) -> Result<(), Error> {
+    let var = |name: &str| {
+        std::env::var(name).map_err(|_| Error::InvalidInput(format!("{name} is not set")))
+    };
+    // Paths needn't be UTF-8.
+    let package = PathBuf::from(
+        std::env::var_os(PACKAGE_ENV)
+            .ok_or_else(|| Error::InvalidInput(format!("{PACKAGE_ENV} is not set")))?,
+    );
+    let model_name = var(MODEL_NAME_ENV)?;
+    let replicas: usize = var(REPLICAS_ENV)?
+        .parse()
+        .map_err(|_| Error::InvalidInput(format!("{REPLICAS_ENV} is not a count")))?;
+
+    match var(DEVICE_ENV)?.as_str() {
+        "cpu" => {
+            let pool = AOTIModelPool::from_fn(replicas, |_| {
+                AOTIModel::<Cpu>::builder(&package)
+                    .model_name(model_name.as_str())
+                    .build()
+            })?;
+            IpcServer::new()
+                .model(model_name, Arc::new(pool))
+                .serve(socket)
+        }
+        #[cfg(aoti_cuda)]
+        "cuda" => {
+            // Only the assigned device is visible, as ordinal 0.
+            let pool = AOTIModelPool::from_fn(replicas, |_| {
+                AOTIModel::<crate::Cuda>::builder(&package)
+                    .model_name(model_name.as_str())
+                    .device_index(0)
+                    .build()
+            })?;
+            IpcServer::new()
+                .model(model_name, Arc::new(pool))
+                .serve(socket)
+        }
+        other => Err(Error::InvalidInput(format!(
+            "worker device '{other}' is not supported by this build"
+        ))),
+    }
+}
+
+/// The `CUDA_VISIBLE_DEVICES` value selecting `index`, relative to the
+/// parent's own visible set if it has one.
+fn visible_device(index: usize, parent: Option<&str>) -> Result<String, Error> {
+    match parent.filter(|p| !p.is_empty()) {
+        None => Ok(index.to_string()),
+        Some(parent) => parent
+            .split(',')
+            .nth(index)
+            .map(|d| d.trim().to_string())
+            .ok_or_else(|| {
+                Error::InvalidInput(format!(
+                    "CUDA device {index} is not among CUDA_VISIBLE_DEVICES={parent}"
+                ))
+            }),
+    }
+}
+
+/// Configures and starts a [`Supervisor`].
+pub struct SupervisorBuilder {
+    package: PathBuf,
+    model_name: String,
+    devices: Vec<WorkerDevice>,
+    replicas_per_worker: usize,
+    program: Option<PathBuf>,
+    args: Vec<OsString>,
+    socket_dir: Option<PathBuf>,
+    startup_timeout: Duration,
+}
+
+impl SupervisorBuilder {
+    /// Set the model name within the package (default: `"model"`).
+    pub fn model_name(mut self, name: impl Into<String>) -> Self {
+        self.model_name = name.into();
+        self
+    }
+
+    /// Start one worker per CUDA device.
+    pub fn cuda_devices(mut self, devices: impl IntoIterator<Item = usize>) -> Self {
+        self.devices = devices.into_iter().map(WorkerDevice::Cuda).collect();
+        self
+    }
+
+    /// Start `n` CPU workers.
+    pub fn cpu_workers(mut self, n: usize) -> Self {
+        self.devices = vec![WorkerDevice::Cpu; n];
+        self
+    }
+
+    /// Replicas each worker loads into its pool (default: 1).
+    pub fn replicas_per_worker(mut self, n: usize) -> Self {
+        self.replicas_per_worker = n;
+        self
+    }
+
+    /// Run `program` with `args` as the worker instead of the current
+    /// executable. It must call [`run_worker_from_env`].
+    pub fn program(
+        mut self,
+        program: impl Into<PathBuf>,
+        args: impl IntoIterator<Item = impl Into<OsString>>,
+    ) -> Self {
+        self.program = Some(program.into());
+        self.args = args.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Directory for the workers' sockets (default: a per-process
+    /// directory under the system temp dir).
+    pub fn socket_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.socket_dir = Some(dir.into());
+        self
+    }
+
+    /// How long a worker may take to load its model and start listening
+    /// (default: 5 minutes).
+    pub fn startup_timeout(mut self, timeout: Duration) -> Self {
+        self.startup_timeout = timeout;
+        self
+    }
+
+    /// Spawn every worker and wait until each is accepting connections.
+    pub fn spawn(self) -> Result<Supervisor, Error> {
+        if self.devices.is_empty() {
+            return Err(Error::InvalidInput(
+                "a supervisor needs at least one worker device".into(),
+            ));
+        }
+        let program = match &self.program {
+            Some(program) => program.clone(),
+            None => std::env::current_exe()?,
+        };
+        let socket_dir = match &self.socket_dir {
+            Some(dir) => dir.clone(),
+            None => std::env::temp_dir().join(format!("aoti-rs-workers-{}", std::process::id())),
+        };
+        std::fs::create_dir_all(&socket_dir)?;
+        let parent_visible = std::env::var("CUDA_VISIBLE_DEVICES").ok();
+
+        let mut supervisor = Supervisor {
+            workers: Vec::with_capacity(self.devices.len()),
+            next: AtomicUsize::new(0),
+        };
+        for (i, &device) in self.devices.iter().enumerate() {
+            let mut command = Command::new(&program);
+            command
+                .args(&self.args)
+                .stdin(Stdio::null())
+                .env(SOCKET_ENV, socket_dir.join(format!("worker-{i}.sock")))
+                .env(PACKAGE_ENV, &self.package)
+                .env(MODEL_NAME_ENV, &self.model_name)
+                .env(REPLICAS_ENV, self.replicas_per_worker.to_string());
+            match device {
+                WorkerDevice::Cpu => {
+                    command.env(DEVICE_ENV, "cpu");
+                }
+                WorkerDevice::Cuda(index) => {
+                    command.env(DEVICE_ENV, "cuda").env(
+                        "CUDA_VISIBLE_DEVICES",
+                        visible_device(index, parent_visible.as_deref())?,
+                    );
+                }
+            }
+            let worker = Worker {
+                device,
+                socket: socket_dir.join(format!("worker-{i}.sock")),
+                command: Mutex::new(command),
+                process: Mutex::new(None),
+                idle: Mutex::new(Vec::new()),
+                in_flight: AtomicUsize::new(0),
+                startup_timeout: self.startup_timeout,
+            };
+            // Dropping the supervisor on error stops workers already started.
+            let client = worker.start()?;
+            worker.release(client);
+            supervisor.workers.push(worker);
+        }
+        Ok(supervisor)
+    }
+}
+
+/// A running worker process and its idle connections.
+struct Worker {
+    device: WorkerDevice,
+    socket: PathBuf,
+    command: Mutex<Command>,
+    process: Mutex<Option<Child>>,
+    idle: Mutex<Vec<IpcClient>>,
+    in_flight: AtomicUsize,
+    startup_timeout: Duration,
+}
+
+impl Worker {
+    /// Spawn the process and wait for its socket.
+    fn start(&self) -> Result<IpcClient, Error> {
+        let mut process = self.process.lock().unwrap_or_else(PoisonError::into_inner);
+        self.start_locked(&mut process)
+    }
+
+    /// [`Worker::start`] with the `process` lock held, killing and reaping
+    /// any process it replaces so none is left running unowned.
+    fn start_locked(&self, process: &mut Option<Child>) -> Result<IpcClient, Error> {
+        if let Some(mut old) = process.take() {
+            let _ = old.kill();
+            let _ = old.wait();
+        }
+        let _ = std::fs::remove_file(&self.socket);
+        let child = process.insert(
+            self.command
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .spawn()?,
+        );
+        let started = Instant::now();
+        loop {
+            if let Some(status) = child.try_wait()? {
+                return Err(Error::Model(format!(
+                    "{:?} worker exited during startup: {status}",
+                    self.device
+                )));
+            }
+            match UnixStream::connect(&self.socket) {
+                Ok(stream) => return Ok(IpcClient::from_stream(stream)),
+                Err(_) if started.elapsed() < self.startup_timeout => {
+                    std::thread::sleep(Duration::from_millis(50));
+                }
+                Err(e) => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return Err(Error::Model(format!(
+                        "{:?} worker did not start listening within {:?}: {e}",
+                        self.device, self.startup_timeout
+                    )));
+                }
+            }
+        }
+    }
+
+    /// An idle connection, or a new one.
+    fn acquire(&self) -> Result<IpcClient, Error> {
+        let idle = self
+            .idle
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .pop();
+        match idle {
+            Some(client) => Ok(client),
+            None => Ok(IpcClient::from_stream(UnixStream::connect(&self.socket)?)),
+        }
+    }
+
+    fn release(&self, client: IpcClient) {
+        self.idle
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(client);
+    }
+
+    /// Restart the process if it has exited, returning whether it had.
+    ///
+    /// The check and the restart happen under one hold of the `process`
+    /// lock, so concurrent callers restart it once.
+    fn revive(&self) -> Result<bool, Error> {
+        let mut process = self.process.lock().unwrap_or_else(PoisonError::into_inner);
+        let exited = match process.as_mut() {
+            Some(child) => child.try_wait()?.is_some(),
+            None => true,
+        };
+        if exited {
+            self.idle
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clear();
+            let client = self.start_locked(&mut process)?;
+            drop(process);
+            self.release(client);
+        }
+        Ok(exited)
+    }
+
+    fn stop(&self) {
+        if let Some(mut child) = self
+            .process
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+        {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+        let _ = std::fs::remove_file(&self.socket);
+    }
+}
+
+/// Worker processes serving one model, one per device.
+///
+/// Calls go to the worker with the fewest requests in flight. A call that
+/// fails in transport restarts its worker if the process died, then is
+/// retried once. Dropping the supervisor kills the workers.
+pub struct Supervisor {
+    workers: Vec<Worker>,
+    next: AtomicUsize,
+}
+
+impl Supervisor {
+    /// Start configuring workers for the `.pt2` package at `package`.
+    pub fn builder(package: impl Into<PathBuf>) -> SupervisorBuilder {
+        SupervisorBuilder {
+            package: package.into(),
+            model_name: "model".to_string(),
+            devices: vec![WorkerDevice::Cpu],
+            replicas_per_worker: 1,
+            program: None,
+            args: Vec::new(),
+            socket_dir: None,
+            startup_timeout: Duration::from_secs(300),
+        }
+    }
+
+    /// Number of workers.
+    pub fn len(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Always `false`: a supervisor runs at least one worker.
+    pub fn is_empty(&self) -> bool {
+        self.workers.is_empty()
+    }
+
+    /// The device of each worker, in start order.
+    pub fn devices(&self) -> Vec<WorkerDevice> {
+        self.workers.iter().map(|w| w.device).collect()
+    }
+
+    /// Run the model on `inputs`, from any device, on the least busy
+    /// worker, returning host tensors.
+    pub fn predict(&self, inputs: &[Tensor]) -> Result<Vec<Tensor>, Error> {
+        self.with_client(|client| client.predict("", inputs))
+    }
+
+    /// Package metadata, as reported by a worker.
+    pub fn metadata(&self) -> Result<HashMap<String, String>, Error> {
+        self.with_client(|client| client.metadata(""))
+    }
+
+    /// Restart any worker whose process has exited, returning how many were
+    /// restarted. Calls already do this for the worker they land on.
+    pub fn restart_dead(&self) -> Result<usize, Error> {
+        let mut restarted = 0;
+        for worker in &self.workers {
+            restarted += worker.revive()? as usize;
+        }
+        Ok(restarted)
+    }
+
+    /// Stop every worker and remove its socket.
+    pub fn shutdown(self) {
+        // Drop does the work.
+    }
+
+    fn pick(&self) -> &Worker {
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        (0..self.workers.len())
+            .map(|i| &self.workers[(start + i) % self.workers.len()])
+            .min_by_key(|w| w.in_flight.load(Ordering::Relaxed))
+            .expect("a supervisor has at least one worker")
+    }
+
+    fn with_client<T>(
+        &self,
+        mut f: impl FnMut(&mut IpcClient) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let mut attempt = |worker: &Worker| {
+            worker.in_flight.fetch_add(1, Ordering::Relaxed);
+            let result = worker.acquire().and_then(|mut client| {
+                let result = f(&mut client);
+                // A connection that failed mid-call may be out of step.
+                if !matches!(result, Err(Error::Io(_))) {
+                    worker.release(client);
+                }
+                result
+            });
+            worker.in_flight.fetch_sub(1, Ordering::Relaxed);
+            result
+        };
+        let worker = self.pick();
+        match attempt(worker) {
+            Err(Error::Io(_)) => {
+                worker.revive()?;
+                attempt(self.pick())
+            }
+            result => result,
+        }
+    }
+}
+
+impl Drop for Supervisor {
+    fn drop(&mut self) {
+        for worker in &self.workers {
+            worker.stop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cuda_ordinals_follow_the_parents_visible_devices() {
+        assert_eq!(visible_device(1, None).unwrap(), "1");
+        assert_eq!(visible_device(1, Some("4, 6")).unwrap(), "6");
+        assert!(visible_device(2, Some("4,6")).is_err());
+    }
+
+    #[test]
+    fn workers_that_exit_during_startup_are_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let err = Supervisor::builder("model.pt2")
+            .program("false", std::iter::empty::<&str>())
+            .socket_dir(dir.path())
+            .spawn()
+            .err()
+            .unwrap();
+        assert!(matches!(err, Error::Model(m) if m.contains("exited during startup")));
+    }
+}
@@ -0,0 +1,404 @@
+//! Soak tests: concurrent traffic against a pool (or a model) for a set
+//! time, to check `num_runners` and pool sizes before production.
+//!
+//! ```ignore
+//! let inputs = [Tensor::randn([8, 3, 224, 224], (Kind::Float, Device::Cpu))];
+//! let config = SoakConfig::new(Duration::from_secs(600)).concurrency(16);
+//! let report = stress::run_soak(&pool, &inputs, &config)?;
+//! println!("{report}");
+//! assert!(report.latency_degradation().is_none_or(|d| d < 1.5));
+//! ```
+//!
+//! Traffic is split into windows (10 s by default) so slowdowns and leaks
+//! show up as trends: [`SoakReport::latency_degradation`] compares the
+//! median latency of the last window with the first, and
+//! [`SoakReport::memory_growth`] the process's resident memory at their
+//! ends (Linux only; device memory held by libtorch's caching allocator
+//! isn't included).
+
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use tch::Tensor;
+
+use crate::generate::Run;
+use crate::{AOTIModelPool, Device, DeviceTensor, Error};
+
+/// Error messages kept per soak.
+const ERROR_SAMPLES: usize = 10;
+
+/// Something many threads can run at once: a pool, or a model behind a
+/// `Mutex` (which serializes the runs, so only its queueing is tested).
+pub trait SoakTarget<D: Device>: Sync {
+    fn run(&self, inputs: &[DeviceTensor<D>]) -> Result<Vec<DeviceTensor<D>>, Error>;
+
+    /// The device inputs must be on.
+    fn device(&self) -> tch::Device;
+}
+
+impl<D: Device> SoakTarget<D> for AOTIModelPool<D> {
+    fn run(&self, inputs: &[DeviceTensor<D>]) -> Result<Vec<DeviceTensor<D>>, Error> {
+        AOTIModelPool::run(self, inputs).map(Vec::from)
+    }
+
+    fn device(&self) -> tch::Device {
+        AOTIModelPool::device(self)
+    }
+}
+
+impl<D: Device, M: Run<D> + Send> SoakTarget<D> for Mutex<M> {
+    fn run(&self, inputs: &[DeviceTensor<D>]) -> Result<Vec<DeviceTensor<D>>, Error> {
+        self.lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .run(inputs)
+    }
+
+    fn device(&self) -> tch::Device {
+        self.lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .device()
+    }
+}
+
+/// How long and how hard [`run_soak`] drives its target.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SoakConfig {
+    duration: Duration,
+    concurrency: usize,
+    rate: Option<f64>,
+    window: Duration,
+}
+
+impl SoakConfig {
+    /// Run for `duration` from 4 client threads, each starting its next
+    /// run as soon as the last finishes, in 10-second windows.
+    pub fn new(duration: Duration) -> Self {
+        Self {
+            duration,
+            concurrency: 4,
+            rate: None,
+            window: Duration::from_secs(10),
+        }
+    }
+
+    /// Client threads making runs at once (at least 1).
+    pub fn concurrency(mut self, threads: usize) -> Self {
+        self.concurrency = threads.max(1);
+        self
+    }
+
+    /// Start at most `per_second` runs a second in all, spread evenly over
+    /// the threads, instead of running flat out.
+    pub fn rate(mut self, per_second: f64) -> Self {
+        self.rate = Some(per_second).filter(|r| *r > 0.0);
+        self
+    }
+
+    /// The span latencies, errors and memory are summarized over.
+    pub fn window(mut self, window: Duration) -> Self {
+        self.window = window.max(Duration::from_millis(1));
+        self
+    }
+}
+
+/// One window of a soak.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SoakWindow {
+    /// When the window started, from the start of the soak.
+    pub start: Duration,
+    /// Successful runs that started in the window.
+    pub runs: u64,
+    pub errors: u64,
+    pub p50: Option<Duration>,
+    pub p99: Option<Duration>,
+    /// The process's resident memory at the end of the window.
+    pub rss_bytes: Option<u64>,
+}
+
+/// What [`run_soak`] observed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SoakReport {
+    pub elapsed: Duration,
+    pub runs: u64,
+    pub errors: u64,
+    /// The first few error messages.
+    pub error_samples: Vec<String>,
+    pub p50: Option<Duration>,
+    pub p99: Option<Duration>,
+    pub max: Option<Duration>,
+    /// The process's resident memory before the first run.
+    pub rss_start: Option<u64>,
+    pub windows: Vec<SoakWindow>,
+}
+
+impl SoakReport {
+    /// Successful runs per second.
+    pub fn throughput(&self) -> f64 {
+        self.runs as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+
+    /// The last window's median latency over the first's, if both had
+    /// successful runs: above 1 means runs got slower.
+    pub fn latency_degradation(&self) -> Option<f64> {
+        let first = self.windows.iter().find_map(|w| w.p50)?;
+        let last = self.windows.iter().rev().find_map(|w| w.p50)?;
+        Some(last.as_secs_f64() / first.as_secs_f64().max(f64::EPSILON))
+    }
+
+    /// Resident memory gained from the start of the soak to the end of the
+    /// last window, in bytes.
+    pub fn memory_growth(&self) -> Option<i64> {
+        let end = self.windows.last()?.rss_bytes?;
+        Some(end as i64 - self.rss_start? as i64)
+    }
+}
+
+impl fmt::Display for SoakReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} runs, {} errors in {:.1?} ({:.1}/s); p50 {:?}, p99 {:?}, max {:?}",
+            self.runs,
+            self.errors,
+            self.elapsed,
+            self.throughput(),
+            self.p50,
+            self.p99,
+            self.max
+        )?;
+        if let Some(degradation) = self.latency_degradation() {
+            write!(f, "; p50 last/first window {degradation:.2}")?;
+        }
+        if let Some(growth) = self.memory_growth() {
+            write!(f, "; RSS {:+} KiB", growth / 1024)?;
+        }
+        for error in &self.error_samples {
+            write!(f, "\n  error: {error}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A run, by when it started from the start of the soak.
+struct Sample {
+    at: Duration,
+    latency: Result<Duration, String>,
+}
+
+/// Drive `target` with `inputs` from `config.concurrency` threads until
+/// `config.duration` has passed, then report on the runs. Each thread runs
+/// its own copy of the inputs, on the target's device. Failed runs are
+/// counted and sampled, not returned: only copying the inputs can fail
+/// the soak itself.
+pub fn run_soak<D: Device>(
+    target: &impl SoakTarget<D>,
+    inputs: &[Tensor],
+    config: &SoakConfig,
+) -> Result<SoakReport, Error> {
+    let device = target.device();
+    // Tensors aren't `Sync`, so each thread gets its own handles.
+    let per_thread = (0..config.concurrency)
+        .map(|_| {
+            inputs
+                .iter()
+                .map(|t| DeviceTensor::try_new(t.f_to_device(device)?))
+                .collect::<Result<Vec<_>, Error>>()
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    let interval = config
+        .rate
+        .map(|rate| Duration::from_secs_f64(config.concurrency as f64 / rate));
+
+    let rss_start = rss_bytes();
+    let started = Instant::now();
+    let deadline = started + config.duration;
+    let (samples, rss) = std::thread::scope(|scope| {
+        let workers: Vec<_> = per_thread
+            .into_iter()
+            .enumerate()
+            .map(|(i, inputs)| {
+                scope.spawn(move || {
+                    let mut samples = Vec::new();
+                    // Stagger paced threads across the interval.
+                    let mut next = interval.map(|interval| {
+                        started + interval.mul_f64(i as f64 / config.concurrency as f64)
+                    });
+                    loop {
+                        if let Some(next) = next {
+                            std::thread::sleep(next.saturating_duration_since(Instant::now()));
+                        }
+                        let start = Instant::now();
+                        if start >= deadline {
+                            return samples;
+                        }
+                        let result = target.run(&inputs);
+                        samples.push(Sample {
+                            at: start - started,
+                            latency: result.map(|_| start.elapsed()).map_err(|e| e.to_string()),
+                        });
+                        next = next.zip(interval).map(|(next, interval)| next + interval);
+                    }
+                })
+            })
+            .collect();
+        // This thread samples memory at the end of each window.
+        let mut rss = Vec::new();
+        let mut window_end = started + config.window;
+        while window_end < deadline + config.window {
+            std::thread::sleep(window_end.saturating_duration_since(Instant::now()));
+            rss.push(rss_bytes());
+            window_end += config.window;
+        }
+        let samples: Vec<Sample> = workers
+            .into_iter()
+            .flat_map(|w| w.join().expect("soak thread panicked"))
+            .collect();
+        (samples, rss)
+    });
+    Ok(report(samples, rss, rss_start, config, started.elapsed()))
+}
+
+fn report(
+    samples: Vec<Sample>,
+    rss: Vec<Option<u64>>,
+    rss_start: Option<u64>,
+    config: &SoakConfig,
+    elapsed: Duration,
+) -> SoakReport {
+    let count = config
+        .duration
+        .div_duration_f64(config.window)
+        .ceil()
+        .max(1.0) as usize;
+    let mut windows: Vec<(Vec<Duration>, u64)> = vec![(Vec::new(), 0); count];
+    let mut all = Vec::new();
+    let mut error_samples = Vec::new();
+    for sample in samples {
+        let index = (sample.at.div_duration_f64(config.window) as usize).min(count - 1);
+        match sample.latency {
+            Ok(latency) => {
+                windows[index].0.push(latency);
+                all.push(latency);
+            }
+            Err(message) => {
+                windows[index].1 += 1;
+                if error_samples.len() < ERROR_SAMPLES {
+                    error_samples.push(message);
+                }
+            }
+        }
+    }
+    all.sort_unstable();
+    SoakReport {
+        elapsed,
+        runs: all.len() as u64,
+        errors: windows.iter().map(|(_, errors)| errors).sum(),
+        error_samples,
+        p50: percentile(&all, 50.0),
+        p99: percentile(&all, 99.0),
+        max: all.last().copied(),
+        rss_start,
+        windows: windows
+            .into_iter()
+            .enumerate()
+            .map(|(i, (mut latencies, errors))| {
+                latencies.sort_unstable();
+                SoakWindow {
+                    start: config.window * i as u32,
+                    runs: latencies.len() as u64,
+                    errors,
+                    p50: percentile(&latencies, 50.0),
+                    p99: percentile(&latencies, 99.0),
+                    rss_bytes: rss.get(i).copied().flatten(),
+                }
+            })
+            .collect(),
+    }
+}
+
+/// The nearest-rank `p`th percentile of `sorted`.
+fn percentile(sorted: &[Duration], p: f64) -> Option<Duration> {
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted.get(rank.clamp(1, sorted.len().max(1)) - 1).copied()
+}
+
+/// The process's resident set size, from `/proc` (so Linux only).
+fn rss_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmRSS:"))?;
+    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kib * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::marker::PhantomData;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use super::*;
+    use crate::Cpu;
+
+    /// Fails every third run.
+    struct Flaky(AtomicU64);
+
+    impl Run<Cpu> for &Flaky {
+        fn run(&mut self, inputs: &[DeviceTensor<Cpu>]) -> Result<Vec<DeviceTensor<Cpu>>, Error> {
+            if self.0.fetch_add(1, Ordering::Relaxed) % 3 == 2 {
+                return Err(Error::Model("flaky".into()));
+            }
+            std::thread::sleep(Duration::from_millis(1));
+            Ok(vec![DeviceTensor {
+                tensor: inputs[0].copy(),
+                _device: PhantomData,
+            }])
+        }
+
+        fn device(&self) -> tch::Device {
+            tch::Device::Cpu
+        }
+    }
+
+    #[test]
+    fn soaks_count_runs_and_errors_per_window() {
+        let flaky = Flaky(AtomicU64::new(0));
+        let target = Mutex::new(&flaky);
+        let config = SoakConfig::new(Duration::from_millis(200))
+            .concurrency(2)
+            .window(Duration::from_millis(100));
+        let report = run_soak(&target, &[Tensor::from_slice(&[1.0f32])], &config).unwrap();
+        assert_eq!(report.windows.len(), 2);
+        assert_eq!(report.runs + report.errors, flaky.0.load(Ordering::Relaxed));
+        assert!(report.runs > 0 && report.errors > 0);
+        assert_eq!(report.error_samples[0], "model error: flaky");
+        assert!(report.p50.unwrap() >= Duration::from_millis(1));
+        assert!(report.latency_degradation().is_some());
+    }
+
+    #[test]
+    fn windows_summarize_their_own_samples() {
+        let ms = Duration::from_millis;
+        let samples = [(0, Ok(2)), (5, Ok(4)), (12, Err(())), (25, Ok(9))]
+            .into_iter()
+            .map(|(at, latency)| Sample {
+                at: ms(at),
+                latency: latency.map(ms).map_err(|_| "failed".to_string()),
+            })
+            .collect();
+        let config = SoakConfig::new(ms(30)).window(ms(10));
+        let report = report(
+            samples,
+            vec![Some(100), None, Some(164)],
+            Some(64),
+            &config,
+            ms(30),
+        );
+        let runs: Vec<_> = report.windows.iter().map(|w| (w.runs, w.errors)).collect();
+        assert_eq!(runs, [(2, 0), (0, 1), (1, 0)]);
+        assert_eq!(report.windows[0].p50, Some(ms(2)));
+        assert_eq!(report.p99, Some(ms(9)));
+        assert_eq!(report.latency_degradation(), Some(4.5));
+        assert_eq!(report.memory_growth(), Some(100));
+        assert_eq!(percentile(&[], 50.0), None);
+    }
+}
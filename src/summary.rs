@@ -0,0 +1,376 @@
+//! Plain-data descriptions of a loaded model: its metadata, call spec, and
+//! run statistics, gathered into a [`ModelSummary`].
+//!
+//! With the `serde` feature every type here implements `Serialize` and
+//! `Deserialize`, so a service can emit them as JSON directly.
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::time::Duration;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use tch::Kind;
+
+use crate::Error;
+
+/// A package's `*_metadata.json` key-value map.
+///
+/// Keys are kept sorted so that serialized output is stable.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(transparent))]
+pub struct ModelMetadata(BTreeMap<String, String>);
+
+impl ModelMetadata {
+    /// Look up a metadata value.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).map(String::as_str)
+    }
+
+    /// The package's `AOTI_DEVICE_KEY` (e.g. `"cpu"`, `"cuda"`), if present.
+    pub fn device_key(&self) -> Option<&str> {
+        self.get("AOTI_DEVICE_KEY")
+    }
+
+    /// Iterate over the entries in key order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn into_inner(self) -> BTreeMap<String, String> {
+        self.0
+    }
+}
+
+impl From<HashMap<String, String>> for ModelMetadata {
+    fn from(map: HashMap<String, String>) -> Self {
+        Self(map.into_iter().collect())
+    }
+}
+
+impl From<BTreeMap<String, String>> for ModelMetadata {
+    fn from(map: BTreeMap<String, String>) -> Self {
+        Self(map)
+    }
+}
+
+/// The serialized pytree specs describing how a model's flat inputs and
+/// outputs map back onto the exported function's signature.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CallSpec {
+    pub in_spec: String,
+    pub out_spec: String,
+}
+
+/// The runner reports the call spec as `[in_spec, out_spec]`.
+impl TryFrom<Vec<String>> for CallSpec {
+    type Error = Error;
+
+    fn try_from(spec: Vec<String>) -> Result<Self, Error> {
+        match <[String; 2]>::try_from(spec) {
+            Ok([in_spec, out_spec]) => Ok(Self { in_spec, out_spec }),
+            Err(spec) => Err(Error::Model(format!(
+                "call spec has {} entries, expected 2",
+                spec.len()
+            ))),
+        }
+    }
+}
+
+/// One constant (weight or buffer) held by a model, as returned by
+/// [`AOTIModel::constants`](crate::AOTIModel::constants).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ConstantInfo {
+    pub name: String,
+    /// Serialized by name, e.g. `"Float"`.
+    #[cfg_attr(feature = "serde", serde(with = "kind_name"))]
+    pub dtype: Kind,
+    pub shape: Vec<i64>,
+    pub bytes: u64,
+}
+
+impl ConstantInfo {
+    /// The number of elements.
+    pub fn numel(&self) -> u64 {
+        self.shape.iter().product::<i64>() as u64
+    }
+}
+
+/// Totals over a model's constants, as returned by
+/// [`AOTIModel::constant_stats`](crate::AOTIModel::constant_stats), e.g.
+/// for a model card or to size how many replicas fit on a device.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ConstantStats {
+    pub constants: usize,
+    /// Elements across all constants. Packages don't tell trained
+    /// parameters from buffers such as batch norm statistics, so both
+    /// count.
+    pub parameters: u64,
+    pub bytes: u64,
+    /// The same totals per dtype, largest first.
+    pub by_dtype: Vec<DtypeStats>,
+}
+
+/// [`ConstantStats`] for the constants of one dtype.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DtypeStats {
+    #[cfg_attr(feature = "serde", serde(with = "kind_name"))]
+    pub dtype: Kind,
+    pub constants: usize,
+    pub parameters: u64,
+    pub bytes: u64,
+}
+
+impl ConstantStats {
+    pub fn of(constants: &[ConstantInfo]) -> Self {
+        let mut stats = Self::default();
+        for constant in constants {
+            let by_dtype = match stats
+                .by_dtype
+                .iter_mut()
+                .find(|d| d.dtype == constant.dtype)
+            {
+                Some(by_dtype) => by_dtype,
+                None => {
+                    stats.by_dtype.push(DtypeStats {
+                        dtype: constant.dtype,
+                        constants: 0,
+                        parameters: 0,
+                        bytes: 0,
+                    });
+                    stats.by_dtype.last_mut().unwrap()
+                }
+            };
+            by_dtype.constants += 1;
+            by_dtype.parameters += constant.numel();
+            by_dtype.bytes += constant.bytes;
+            stats.constants += 1;
+            stats.parameters += constant.numel();
+            stats.bytes += constant.bytes;
+        }
+        stats
+            .by_dtype
+            .sort_by(|a, b| b.bytes.cmp(&a.bytes).then(b.parameters.cmp(&a.parameters)));
+        stats
+    }
+}
+
+/// Serde for a `tch::Kind` as its variant name.
+#[cfg(feature = "serde")]
+mod kind_name {
+    use serde::de::Error as _;
+    use serde::{Deserialize, Deserializer, Serializer};
+    use tch::Kind;
+
+    const KINDS: [Kind; 20] = [
+        Kind::Uint8,
+        Kind::Int8,
+        Kind::Int16,
+        Kind::Int,
+        Kind::Int64,
+        Kind::Half,
+        Kind::Float,
+        Kind::Double,
+        Kind::ComplexHalf,
+        Kind::ComplexFloat,
+        Kind::ComplexDouble,
+        Kind::Bool,
+        Kind::QInt8,
+        Kind::QUInt8,
+        Kind::QInt32,
+        Kind::BFloat16,
+        Kind::Float8e5m2,
+        Kind::Float8e4m3fn,
+        Kind::Float8e5m2fnuz,
+        Kind::Float8e4m3fnuz,
+    ];
+
+    pub fn serialize<S: Serializer>(kind: &Kind, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&format_args!("{kind:?}"))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Kind, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        KINDS
+            .into_iter()
+            .find(|kind| format!("{kind:?}") == name)
+            .ok_or_else(|| D::Error::custom(format!("unknown dtype \"{name}\"")))
+    }
+}
+
+/// Cumulative timing for a model's `run` calls.
+///
+/// Times cover the FFI call only, from handing the inputs to the runtime to
+/// receiving the outputs; calls that returned an error are counted in
+/// `failures` but not timed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RunStats {
+    pub runs: u64,
+    pub failures: u64,
+    pub total_time: Duration,
+    pub min_time: Option<Duration>,
+    pub max_time: Option<Duration>,
+    pub last_time: Option<Duration>,
+    /// Checked runs that returned an output aliasing an input; see
+    /// [`AOTIModelBuilder::check_output_aliasing`](crate::AOTIModelBuilder::check_output_aliasing).
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub aliased_runs: u64,
+}
+
+impl RunStats {
+    pub(crate) fn record(&mut self, elapsed: Duration, ok: bool) {
+        if !ok {
+            self.failures += 1;
+            return;
+        }
+        self.runs += 1;
+        self.total_time += elapsed;
+        self.min_time = Some(self.min_time.map_or(elapsed, |t| t.min(elapsed)));
+        self.max_time = Some(self.max_time.map_or(elapsed, |t| t.max(elapsed)));
+        self.last_time = Some(elapsed);
+    }
+
+    /// Mean time of the successful runs, if there were any.
+    pub fn mean_time(&self) -> Option<Duration> {
+        let runs = u32::try_from(self.runs).ok().filter(|&n| n > 0)?;
+        Some(self.total_time / runs)
+    }
+}
+
+/// An output of a run whose memory overlaps one of the run's inputs, so
+/// writing to that input (or to another tensor sharing its storage)
+/// changes the output too.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OutputAlias {
+    pub output: usize,
+    pub input: usize,
+}
+
+/// Everything known about a loaded model, as returned by
+/// [`AOTIModel::summary`](crate::AOTIModel::summary).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ModelSummary {
+    pub package_path: String,
+    pub model_name: String,
+    /// The model's device, e.g. `"cpu"` or `"cuda:0"`.
+    pub device: String,
+    pub metadata: ModelMetadata,
+    pub call_spec: CallSpec,
+    pub constant_fqns: Vec<String>,
+    pub stats: RunStats,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stats_track_successes_and_failures() {
+        let mut stats = RunStats::default();
+        assert_eq!(stats.mean_time(), None);
+        stats.record(Duration::from_millis(4), true);
+        stats.record(Duration::from_millis(2), true);
+        stats.record(Duration::from_millis(100), false);
+        assert_eq!(stats.runs, 2);
+        assert_eq!(stats.failures, 1);
+        assert_eq!(stats.min_time, Some(Duration::from_millis(2)));
+        assert_eq!(stats.max_time, Some(Duration::from_millis(4)));
+        assert_eq!(stats.mean_time(), Some(Duration::from_millis(3)));
+    }
+
+    #[test]
+    fn call_spec_needs_two_entries() {
+        let spec = CallSpec::try_from(vec!["in".to_string(), "out".to_string()]).unwrap();
+        assert_eq!(spec.out_spec, "out");
+        assert!(matches!(
+            CallSpec::try_from(vec!["in".to_string()]),
+            Err(Error::Model(_))
+        ));
+    }
+
+    #[test]
+    fn constant_stats_total_per_dtype() {
+        let constant = |name: &str, dtype: Kind, shape: Vec<i64>| {
+            let bytes = shape.iter().product::<i64>() as u64 * dtype.elt_size_in_bytes() as u64;
+            ConstantInfo {
+                name: name.into(),
+                dtype,
+                shape,
+                bytes,
+            }
+        };
+        let stats = ConstantStats::of(&[
+            constant("bias", Kind::Half, vec![8]),
+            constant("weight", Kind::Half, vec![8, 4]),
+            constant("steps", Kind::Int64, vec![]),
+            constant("scale", Kind::Float, vec![4]),
+        ]);
+        assert_eq!(
+            (stats.constants, stats.parameters, stats.bytes),
+            (4, 45, 104)
+        );
+        let by_dtype: Vec<_> = stats
+            .by_dtype
+            .iter()
+            .map(|d| (d.dtype, d.constants, d.parameters, d.bytes))
+            .collect();
+        assert_eq!(
+            by_dtype,
+            [
+                (Kind::Half, 2, 40, 80),
+                (Kind::Float, 1, 4, 16),
+                (Kind::Int64, 1, 1, 8)
+            ]
+        );
+        assert_eq!(ConstantStats::of(&[]), ConstantStats::default());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn metadata_serializes_as_flat_map() {
+        let metadata = ModelMetadata::from(HashMap::from([(
+            "AOTI_DEVICE_KEY".to_string(),
+            "cpu".to_string(),
+        )]));
+        let json = serde_json::to_string(&metadata).unwrap();
+        assert_eq!(json, r#"{"AOTI_DEVICE_KEY":"cpu"}"#);
+        assert_eq!(
+            serde_json::from_str::<ModelMetadata>(&json).unwrap(),
+            metadata
+        );
+
+        let constant = ConstantInfo {
+            name: "weight".into(),
+            dtype: Kind::BFloat16,
+            shape: vec![2],
+            bytes: 4,
+        };
+        let json = serde_json::to_value(&constant).unwrap();
+        assert_eq!(json["dtype"], "BFloat16");
+        assert_eq!(
+            serde_json::from_value::<ConstantInfo>(json).unwrap(),
+            constant
+        );
+        assert!(
+            serde_json::from_str::<ConstantInfo>(
+                r#"{"name": "w", "dtype": "Float128", "shape": [], "bytes": 0}"#
+            )
+            .is_err()
+        );
+    }
+}
@@ -0,0 +1,111 @@
+//! Real packages for end-to-end tests, exported on demand (feature
+//! `test-support`), so neither this crate nor its users need to check
+//! binary `.pt2` fixtures into git.
+//!
+//! [`add_one`] exports a model computing `x + 1` through the local Python
+//! and PyTorch, as the `export` feature does, once per process:
+//!
+//! ```ignore
+//! let Ok(package) = aoti_rs::test_support::add_one() else {
+//!     eprintln!("skipping: no Python with PyTorch");
+//!     return;
+//! };
+//! let mut model = AOTIModel::<Cpu>::load(&package)?;
+//! ```
+//!
+//! The interpreter is `$AOTI_RS_PYTHON`, or `python3` on the `PATH`.
+//! Exporting compiles C++, so the first call takes tens of seconds.
+
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use tempfile::TempDir;
+
+use crate::Error;
+use crate::export::run_script;
+
+/// Exports the add-one model to the package path given as the argument.
+const ADD_ONE: &str = r#"
+import sys
+
+import torch
+
+class AddOne(torch.nn.Module):
+    def forward(self, x):
+        return x + 1
+
+batch = torch.export.Dim("batch", min=1, max=1024)
+program = torch.export.export(
+    AddOne(), (torch.randn(2, 4),), dynamic_shapes=({0: batch},)
+)
+torch._inductor.aoti_compile_and_package(program, package_path=sys.argv[1])
+"#;
+
+/// The name [`add_one`]'s package is exported under, and loads with by
+/// default.
+pub const ADD_ONE_MODEL_NAME: &str = "model";
+
+/// The interpreter exports run under.
+pub fn python() -> PathBuf {
+    std::env::var_os("AOTI_RS_PYTHON").map_or_else(|| PathBuf::from("python3"), PathBuf::from)
+}
+
+/// Export the add-one model into `dir`, returning the package's path. It
+/// takes one `Float` input of shape `[batch, 4]`, for any batch from 1 to
+/// 1024, and returns it plus one, on the CPU.
+pub fn export_add_one(dir: &Path) -> Result<PathBuf, Error> {
+    let package = dir.join("add_one.pt2");
+    let arg = package
+        .to_str()
+        .ok_or_else(|| Error::InvalidPath(format!("{} is not valid UTF-8", package.display())))?;
+    run_script(&python(), ADD_ONE, arg)?;
+    Ok(package)
+}
+
+/// The add-one package ([`export_add_one`]), exported into a temporary
+/// directory the first time it's asked for and kept for the rest of the
+/// process. A failed export is returned again rather than retried.
+pub fn add_one() -> Result<&'static Path, Error> {
+    static PACKAGE: OnceLock<Result<(TempDir, PathBuf), String>> = OnceLock::new();
+    let package = PACKAGE.get_or_init(|| {
+        let dir = TempDir::new().map_err(|e| e.to_string())?;
+        let package = export_add_one(dir.path()).map_err(|e| e.to_string())?;
+        Ok((dir, package))
+    });
+    match package {
+        Ok((_, package)) => Ok(package),
+        Err(err) => Err(Error::Export(err.clone())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_paths_are_rejected_before_exporting() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let dir = Path::new(std::ffi::OsStr::from_bytes(b"/tmp/\xff"));
+        assert!(matches!(export_add_one(dir), Err(Error::InvalidPath(_))));
+    }
+
+    // The fake runtime can't load real packages.
+    #[cfg(not(feature = "fake"))]
+    #[test]
+    fn the_exported_package_adds_one() {
+        use crate::{AOTIModel, Cpu, DeviceTensor};
+
+        let Ok(package) = add_one() else {
+            eprintln!("skipping: couldn't export with {}", python().display());
+            return;
+        };
+        let mut model = AOTIModel::<Cpu>::load(&package).unwrap();
+        let x = tch::Tensor::from_slice(&[0.5f32; 12]).reshape([3, 4]);
+        let outputs = model.run(&[DeviceTensor::try_new(x).unwrap()]).unwrap();
+        assert_eq!(outputs.len(), 1);
+        assert_eq!(outputs[0].size(), [3, 4]);
+        assert_eq!(outputs[0].double_value(&[2, 3]), 1.5);
+    }
+}
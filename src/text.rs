@@ -0,0 +1,208 @@
+//! Tokenization for NLP models via the `tokenizers` crate (feature `text`).
+//!
+//! [`TextEncoder`] wraps a `tokenizers::Tokenizer` (typically loaded from a
+//! Hugging Face `tokenizer.json`) and turns a batch of strings into
+//! `[N, L]` `Int64` `input_ids` / `attention_mask` / `token_type_ids`
+//! tensors. Padding and truncation are applied by the tokenizer itself, so
+//! special tokens survive truncation exactly as they do in Python.
+
+use std::marker::PhantomData;
+use std::path::Path;
+
+use tch::Tensor;
+use tokenizers::{Encoding, PaddingParams, PaddingStrategy, Tokenizer, TruncationParams};
+
+use crate::{Cpu, DeviceTensor, Error, ModelMetadata};
+
+/// How a batch is padded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Padding {
+    /// Pad to the longest sequence in the batch.
+    Longest,
+    /// Pad every sequence to exactly this many tokens. Models exported with
+    /// a static sequence length need this.
+    Fixed(usize),
+}
+
+/// Tokenized model inputs, each `[N, L]` `Int64`.
+#[derive(Debug)]
+pub struct TextInputs {
+    pub input_ids: DeviceTensor<Cpu>,
+    pub attention_mask: DeviceTensor<Cpu>,
+    pub token_type_ids: DeviceTensor<Cpu>,
+}
+
+impl TextInputs {
+    /// `[input_ids, attention_mask]`, the argument order of most exported
+    /// Hugging Face encoders.
+    pub fn into_inputs(self) -> Vec<DeviceTensor<Cpu>> {
+        vec![self.input_ids, self.attention_mask]
+    }
+}
+
+/// Turns strings into padded, truncated token tensors.
+pub struct TextEncoder {
+    tokenizer: Tokenizer,
+    add_special_tokens: bool,
+}
+
+impl TextEncoder {
+    /// Wrap `tokenizer`, keeping whatever padding and truncation it was
+    /// saved with; padding defaults to [`Padding::Longest`] if it has none.
+    pub fn new(mut tokenizer: Tokenizer) -> Self {
+        if tokenizer.get_padding().is_none() {
+            tokenizer.with_padding(Some(PaddingParams::default()));
+        }
+        Self {
+            tokenizer,
+            add_special_tokens: true,
+        }
+    }
+
+    /// Load a `tokenizer.json`.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Ok(Self::new(
+            Tokenizer::from_file(path).map_err(Error::Tokenizer)?,
+        ))
+    }
+
+    /// Pad and truncate to the sequence length recorded in a package's
+    /// metadata under `max_seq_len`, if there is one.
+    pub fn for_metadata(self, metadata: &ModelMetadata) -> Result<Self, Error> {
+        let Some(len) = metadata.get("max_seq_len") else {
+            return Ok(self);
+        };
+        let len: usize = len
+            .trim()
+            .parse()
+            .map_err(|_| Error::Model(format!("metadata max_seq_len='{len}' is not a length")))?;
+        self.padding(Padding::Fixed(len)).truncation(Some(len))
+    }
+
+    /// Set the padding policy. The pad token and id come from the
+    /// tokenizer's own configuration.
+    pub fn padding(mut self, padding: Padding) -> Self {
+        let mut params = self.tokenizer.get_padding().cloned().unwrap_or_default();
+        params.strategy = match padding {
+            Padding::Longest => PaddingStrategy::BatchLongest,
+            Padding::Fixed(len) => PaddingStrategy::Fixed(len),
+        };
+        self.tokenizer.with_padding(Some(params));
+        self
+    }
+
+    /// Truncate sequences longer than `max_length` tokens (counting special
+    /// tokens), or, with `None`, never truncate.
+    pub fn truncation(mut self, max_length: Option<usize>) -> Result<Self, Error> {
+        let params = max_length.map(|max_length| TruncationParams {
+            max_length,
+            ..self.tokenizer.get_truncation().cloned().unwrap_or_default()
+        });
+        self.tokenizer
+            .with_truncation(params)
+            .map_err(Error::Tokenizer)?;
+        Ok(self)
+    }
+
+    /// Whether to add the tokenizer's special tokens (`[CLS]`, `</s>`, ...)
+    /// (default: true).
+    pub fn add_special_tokens(mut self, add: bool) -> Self {
+        self.add_special_tokens = add;
+        self
+    }
+
+    pub fn tokenizer(&self) -> &Tokenizer {
+        &self.tokenizer
+    }
+
+    /// Tokenize a batch of strings.
+    pub fn encode(&self, texts: &[&str]) -> Result<TextInputs, Error> {
+        if texts.is_empty() {
+            return Err(Error::InvalidInput("cannot encode an empty batch".into()));
+        }
+        let encodings = self
+            .tokenizer
+            .encode_batch(texts.to_vec(), self.add_special_tokens)
+            .map_err(Error::Tokenizer)?;
+        let len = encodings[0].len();
+        if let Some(e) = encodings.iter().find(|e| e.len() != len) {
+            // Only possible when the tokenizer's padding was removed.
+            return Err(Error::InvalidInput(format!(
+                "encodings have lengths {len} and {}; configure padding",
+                e.len()
+            )));
+        }
+        let stack = |field: fn(&Encoding) -> &[u32]| -> Result<DeviceTensor<Cpu>, Error> {
+            let ids: Vec<i64> = encodings
+                .iter()
+                .flat_map(|e| field(e).iter().map(|&id| i64::from(id)))
+                .collect();
+            Ok(DeviceTensor {
+                tensor: Tensor::f_from_slice(&ids)?.f_view([encodings.len() as i64, len as i64])?,
+                _device: PhantomData,
+            })
+        };
+        Ok(TextInputs {
+            input_ids: stack(Encoding::get_ids)?,
+            attention_mask: stack(Encoding::get_attention_mask)?,
+            token_type_ids: stack(Encoding::get_type_ids)?,
+        })
+    }
+}
+
+impl std::fmt::Debug for TextEncoder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TextEncoder")
+            .field("padding", &self.tokenizer.get_padding())
+            .field("truncation", &self.tokenizer.get_truncation())
+            .field("add_special_tokens", &self.add_special_tokens)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use tokenizers::models::wordlevel::WordLevel;
+    use tokenizers::pre_tokenizers::whitespace::Whitespace;
+
+    fn encoder() -> TextEncoder {
+        let vocab = [("[PAD]", 0), ("[UNK]", 1), ("hello", 2), ("world", 3)]
+            .into_iter()
+            .map(|(t, i)| (t.to_string(), i))
+            .collect();
+        let model = WordLevel::builder()
+            .vocab(vocab)
+            .unk_token("[UNK]".into())
+            .build()
+            .unwrap();
+        let mut tokenizer = Tokenizer::new(model);
+        tokenizer.with_pre_tokenizer(Some(Whitespace {}));
+        TextEncoder::new(tokenizer)
+    }
+
+    #[test]
+    fn pads_to_longest() {
+        let inputs = encoder().encode(&["hello world", "hello"]).unwrap();
+        assert_eq!(inputs.input_ids.size(), &[2, 2]);
+        assert_eq!(inputs.input_ids.int64_value(&[0, 1]), 3);
+        assert_eq!(inputs.input_ids.int64_value(&[1, 1]), 0);
+        assert_eq!(inputs.attention_mask.int64_value(&[1, 1]), 0);
+    }
+
+    #[test]
+    fn metadata_length_pads_and_truncates() {
+        let metadata = ModelMetadata::from(HashMap::from([(
+            "max_seq_len".to_string(),
+            "3".to_string(),
+        )]));
+        let encoder = encoder().for_metadata(&metadata).unwrap();
+        let inputs = encoder
+            .encode(&["hello", "hello world hello world"])
+            .unwrap();
+        assert_eq!(inputs.input_ids.size(), &[2, 3]);
+        assert_eq!(inputs.attention_mask.int64_value(&[0, 2]), 0);
+        assert_eq!(inputs.attention_mask.int64_value(&[1, 2]), 1);
+    }
+}
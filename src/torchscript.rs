@@ -0,0 +1,171 @@
+//! Cross-checking a package against an eager TorchScript export of the
+//! same model, the usual way to confirm AOT compilation kept its numerics.
+//!
+//! ```ignore
+//! let baseline = TorchScriptBaseline::load("model.ts", tch::Device::Cpu)?;
+//! let comparison = baseline.cross_check(&mut model, &inputs, Tolerances::new())?;
+//! assert!(comparison.passed(), "{comparison}");
+//! ```
+
+use std::marker::PhantomData;
+use std::path::Path;
+
+use tch::{CModule, IValue, Tensor};
+
+use crate::compare::{Comparison, Tolerances, compare_outputs};
+use crate::generate::Run;
+use crate::{Device, DeviceTensor, Error};
+
+fn wrap<D: Device>(tensor: Tensor) -> DeviceTensor<D> {
+    DeviceTensor {
+        tensor,
+        _device: PhantomData,
+    }
+}
+
+/// A TorchScript module whose `forward` takes the package's inputs and
+/// returns its outputs: a tensor, or tuples and lists of them, flattened
+/// in order.
+#[derive(Debug)]
+pub struct TorchScriptBaseline {
+    module: CModule,
+    device: tch::Device,
+}
+
+impl TorchScriptBaseline {
+    /// Load a module saved with `torch.jit.save`, in eval mode, onto
+    /// `device`.
+    pub fn load(path: impl AsRef<Path>, device: tch::Device) -> Result<Self, Error> {
+        Ok(Self::new(CModule::load_on_device(path, device)?, device))
+    }
+
+    /// Wrap a module already on `device`.
+    pub fn new(mut module: CModule, device: tch::Device) -> Self {
+        module.set_eval();
+        Self { module, device }
+    }
+
+    /// Run `forward` on `inputs`, copied to the module's device, without
+    /// tracking gradients.
+    pub fn run(&self, inputs: &[Tensor]) -> Result<Vec<Tensor>, Error> {
+        let inputs = inputs
+            .iter()
+            .map(|t| Ok(IValue::Tensor(t.f_to_device(self.device)?)))
+            .collect::<Result<Vec<_>, Error>>()?;
+        let output = tch::no_grad(|| self.module.forward_is(&inputs))?;
+        let mut outputs = Vec::new();
+        flatten(output, &mut outputs)?;
+        Ok(outputs)
+    }
+
+    /// Run the baseline and `model` on the same `inputs` and compare the
+    /// model's outputs with the baseline's, which are copied to the
+    /// model's device.
+    pub fn cross_check<D: Device>(
+        &self,
+        model: &mut impl Run<D>,
+        inputs: &[DeviceTensor<D>],
+        tolerances: impl Into<Tolerances>,
+    ) -> Result<Comparison, Error> {
+        let tensors: Vec<Tensor> = inputs.iter().map(|t| t.shallow_clone()).collect();
+        let expected = self
+            .run(&tensors)?
+            .into_iter()
+            .map(|t| Ok(wrap(t.f_to_device(model.device())?)))
+            .collect::<Result<Vec<_>, Error>>()?;
+        let actual = model.run(inputs)?;
+        compare_outputs(&expected, &actual, &tolerances.into())
+    }
+}
+
+fn flatten(value: IValue, outputs: &mut Vec<Tensor>) -> Result<(), Error> {
+    match value {
+        IValue::Tensor(t) => outputs.push(t),
+        IValue::TensorList(ts) => outputs.extend(ts),
+        IValue::Tuple(values) | IValue::GenericList(values) => {
+            for value in values {
+                flatten(value, outputs)?;
+            }
+        }
+        other => {
+            return Err(Error::Model(format!(
+                "TorchScript baseline returned {other:?}, not tensors"
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Cpu;
+    use crate::compare::Tolerance;
+
+    /// `x * 2` and `x + offset`, for an eager `x * 2` and `x + 1`.
+    struct Compiled {
+        offset: f64,
+    }
+
+    impl Run<Cpu> for Compiled {
+        fn run(&mut self, inputs: &[DeviceTensor<Cpu>]) -> Result<Vec<DeviceTensor<Cpu>>, Error> {
+            Ok(vec![
+                wrap(inputs[0].f_mul_scalar(2)?),
+                wrap(inputs[0].f_add_scalar(self.offset)?),
+            ])
+        }
+
+        fn device(&self) -> tch::Device {
+            tch::Device::Cpu
+        }
+    }
+
+    fn baseline() -> TorchScriptBaseline {
+        let x = Tensor::from_slice(&[1.0f32, 2.0]);
+        let module = CModule::create_by_tracing("Eager", "forward", &[x], &mut |xs| {
+            vec![&xs[0] * 2, &xs[0] + 1]
+        })
+        .unwrap();
+        TorchScriptBaseline::new(module, tch::Device::Cpu)
+    }
+
+    #[test]
+    fn package_outputs_are_checked_against_the_baseline() {
+        let baseline = baseline();
+        let inputs = [wrap::<Cpu>(Tensor::from_slice(&[3.0f32, -1.0]))];
+        let same = baseline
+            .cross_check(&mut Compiled { offset: 1.0 }, &inputs, Tolerances::new())
+            .unwrap();
+        assert!(same.passed(), "{same}");
+        let drifted = baseline
+            .cross_check(&mut Compiled { offset: 1.01 }, &inputs, Tolerance::EXACT)
+            .unwrap();
+        assert!(!drifted.passed());
+        assert!(drifted.outputs[0].passed());
+        assert!(
+            drifted.to_string().starts_with("output 1 differs"),
+            "{drifted}"
+        );
+    }
+
+    #[test]
+    fn nested_outputs_flatten_in_order() {
+        let (a, b, c) = (
+            Tensor::from_slice(&[1i64]),
+            Tensor::from_slice(&[2i64]),
+            Tensor::from_slice(&[3i64]),
+        );
+        let nested = IValue::Tuple(vec![
+            IValue::Tensor(a),
+            IValue::GenericList(vec![IValue::TensorList(vec![b, c])]),
+        ]);
+        let mut outputs = Vec::new();
+        flatten(nested, &mut outputs).unwrap();
+        let values: Vec<i64> = outputs.iter().map(|t| t.int64_value(&[0])).collect();
+        assert_eq!(values, [1, 2, 3]);
+        assert!(matches!(
+            flatten(IValue::Int(1), &mut outputs),
+            Err(Error::Model(_))
+        ));
+    }
+}
@@ -0,0 +1,76 @@
+//! `tracing` instrumentation of FFI calls (feature `tracing`).
+//!
+//! Every call into the runtime — loading a package, a run (op `run`, or
+//! `boxed_run` for donated inputs), and the call-spec and constant
+//! queries — runs inside a DEBUG-level
+//! `aoti_ffi` span with `op`, `model` and `device` fields (plus `path` for
+//! loads and input shapes for runs). When the call returns, the span gets
+//! `duration_us` (and output shapes for runs) and a DEBUG event, or a WARN
+//! event carrying the error. Any installed subscriber picks these up; no
+//! setup is needed here.
+
+use std::time::Duration;
+
+use tracing::Span;
+use tracing::field::debug;
+
+use crate::{Device, DeviceTensor, Error};
+
+/// Shapes of `tensors`, for span fields.
+pub(crate) fn shapes<D: Device>(tensors: &[DeviceTensor<D>]) -> Vec<Vec<i64>> {
+    tensors.iter().map(|t| t.size()).collect()
+}
+
+/// Record a finished call's duration and outcome on its span.
+pub(crate) fn finish<T>(span: &Span, elapsed: Duration, result: &Result<T, Error>) {
+    span.record("duration_us", elapsed.as_micros() as u64);
+    match result {
+        Ok(_) => tracing::debug!(parent: span, "FFI call finished"),
+        Err(err) => tracing::warn!(parent: span, error = %err, "FFI call failed"),
+    }
+}
+
+/// Like [`finish`] for runs, also recording the output shapes.
+pub(crate) fn finish_run<D: Device>(
+    span: &Span,
+    elapsed: Duration,
+    result: &Result<Vec<DeviceTensor<D>>, Error>,
+) {
+    if let Ok(outputs) = result {
+        span.record("outputs", debug(shapes(outputs)));
+    }
+    finish(span, elapsed, result);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Cpu;
+    use tch::Tensor;
+
+    #[test]
+    fn finishing_without_a_subscriber_is_harmless() {
+        let span = tracing::debug_span!(
+            "aoti_ffi",
+            outputs = tracing::field::Empty,
+            duration_us = tracing::field::Empty
+        );
+        let outputs = DeviceTensor::<Cpu>::try_new_all(vec![Tensor::from(1.0f32)]);
+        finish_run(&span, Duration::from_millis(1), &outputs);
+        finish(
+            &span,
+            Duration::ZERO,
+            &Err::<(), _>(Error::Model("boom".into())),
+        );
+    }
+
+    #[test]
+    fn shapes_are_listed_per_tensor() {
+        let tensors = DeviceTensor::<Cpu>::try_new_all(vec![
+            Tensor::zeros([2, 3], tch::kind::FLOAT_CPU),
+            Tensor::from(1.0f32),
+        ])
+        .unwrap();
+        assert_eq!(shapes(&tensors), [vec![2, 3], vec![]]);
+    }
+}
@@ -0,0 +1,242 @@
+//! UniFFI bindings (feature `uniffi`).
+//!
+//! A deliberately small load/run/metadata surface for Kotlin and Swift
+//! hosts, generated with `uniffi-bindgen` from the built library instead of
+//! hand-written JNI or Objective-C bridges:
+//!
+//! ```text
+//! cargo rustc --release --features uniffi --crate-type cdylib
+//! uniffi-bindgen generate --library target/release/libaoti_rs.so --language kotlin --out-dir out
+//! ```
+//!
+//! Tensors cross the boundary as [`AotiTensor`] records holding row-major,
+//! native-endian bytes; inputs are copied onto the model's device and
+//! outputs are copied back to host memory.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use tch::{Kind, Tensor};
+
+use crate::{AOTIModel, AnyAOTIModel, Device, Error, device_string};
+
+/// Element type of an [`AotiTensor`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ::uniffi::Enum)]
+pub enum AotiDtype {
+    Float32,
+    Float64,
+    Float16,
+    BFloat16,
+    Int8,
+    Int16,
+    Int32,
+    Int64,
+    UInt8,
+    Bool,
+}
+
+impl AotiDtype {
+    fn kind(self) -> Kind {
+        match self {
+            AotiDtype::Float32 => Kind::Float,
+            AotiDtype::Float64 => Kind::Double,
+            AotiDtype::Float16 => Kind::Half,
+            AotiDtype::BFloat16 => Kind::BFloat16,
+            AotiDtype::Int8 => Kind::Int8,
+            AotiDtype::Int16 => Kind::Int16,
+            AotiDtype::Int32 => Kind::Int,
+            AotiDtype::Int64 => Kind::Int64,
+            AotiDtype::UInt8 => Kind::Uint8,
+            AotiDtype::Bool => Kind::Bool,
+        }
+    }
+
+    fn from_kind(kind: Kind) -> Result<Self, Error> {
+        Ok(match kind {
+            Kind::Float => AotiDtype::Float32,
+            Kind::Double => AotiDtype::Float64,
+            Kind::Half => AotiDtype::Float16,
+            Kind::BFloat16 => AotiDtype::BFloat16,
+            Kind::Int8 => AotiDtype::Int8,
+            Kind::Int16 => AotiDtype::Int16,
+            Kind::Int => AotiDtype::Int32,
+            Kind::Int64 => AotiDtype::Int64,
+            Kind::Uint8 => AotiDtype::UInt8,
+            Kind::Bool => AotiDtype::Bool,
+            other => return Err(Error::UnsupportedDtype(format!("torch {other:?}"))),
+        })
+    }
+}
+
+/// A dense host tensor: `data` holds `product(shape)` elements of `dtype`
+/// in row-major order and native byte order.
+#[derive(Debug, Clone, PartialEq, ::uniffi::Record)]
+pub struct AotiTensor {
+    pub dtype: AotiDtype,
+    pub shape: Vec<i64>,
+    pub data: Vec<u8>,
+}
+
+impl AotiTensor {
+    fn to_tensor(&self) -> Result<Tensor, Error> {
+        let kind = self.dtype.kind();
+        // Host-supplied shapes may be negative or overflow.
+        let numel = self.shape.iter().try_fold(1usize, |acc, &d| {
+            usize::try_from(d).ok().and_then(|d| acc.checked_mul(d))
+        });
+        if numel.and_then(|n| n.checked_mul(kind.elt_size_in_bytes())) != Some(self.data.len()) {
+            return Err(Error::InvalidInput(format!(
+                "{} bytes do not hold a {:?} tensor of shape {:?}",
+                self.data.len(),
+                self.dtype,
+                self.shape
+            )));
+        }
+        Ok(Tensor::f_from_data_size(&self.data, &self.shape, kind)?)
+    }
+
+    fn from_tensor(tensor: &Tensor) -> Result<Self, Error> {
+        let dtype = AotiDtype::from_kind(tensor.f_kind()?)?;
+        let host = tensor.f_to_device(tch::Device::Cpu)?.f_contiguous()?;
+        let numel = host.numel();
+        let mut data = vec![0u8; numel * dtype.kind().elt_size_in_bytes()];
+        host.f_copy_data_u8(&mut data, numel)?;
+        Ok(Self {
+            dtype,
+            shape: host.size(),
+            data,
+        })
+    }
+}
+
+/// Errors surfaced to binding hosts, carrying the crate error's message.
+#[derive(Debug, thiserror::Error, ::uniffi::Error)]
+#[uniffi(flat_error)]
+pub enum AotiError {
+    #[error("{0}")]
+    InvalidInput(String),
+    #[error("{0}")]
+    Model(String),
+}
+
+impl From<Error> for AotiError {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::InvalidInput(_)
+            | Error::InvalidPath(_)
+            | Error::UnsupportedDtype(_)
+            | Error::TensorKindMismatch { .. } => AotiError::InvalidInput(err.to_string()),
+            _ => AotiError::Model(err.to_string()),
+        }
+    }
+}
+
+fn run_typed<D: Device>(
+    model: &mut AOTIModel<D>,
+    inputs: &[Tensor],
+) -> Result<Vec<AotiTensor>, Error> {
+    let inputs: Vec<_> = inputs.iter().map(|t| model.upload(t)).collect();
+    model
+        .run(inputs)?
+        .iter()
+        .map(|out| AotiTensor::from_tensor(out))
+        .collect()
+}
+
+/// A loaded model package. Calls are serialized internally, so one
+/// instance can be shared across host threads.
+#[derive(::uniffi::Object)]
+pub struct AotiModel {
+    model: Mutex<AnyAOTIModel>,
+    device: String,
+}
+
+#[::uniffi::export]
+impl AotiModel {
+    /// Load `model_name` (default `"model"`) from the package at `path`, on
+    /// the device its metadata names.
+    #[uniffi::constructor(default(model_name = None))]
+    pub fn load(path: String, model_name: Option<String>) -> Result<Arc<Self>, AotiError> {
+        let model = AnyAOTIModel::load_named(&path, model_name.as_deref().unwrap_or("model"))?;
+        let device = match &model {
+            AnyAOTIModel::Cpu(m) => device_string(m.device()),
+            #[cfg(aoti_cuda)]
+            AnyAOTIModel::Cuda(m) => device_string(m.device()),
+        };
+        Ok(Arc::new(Self {
+            model: Mutex::new(model),
+            device,
+        }))
+    }
+
+    /// The device the model runs on, e.g. `"cpu"` or `"cuda:0"`.
+    pub fn device(&self) -> String {
+        self.device.clone()
+    }
+
+    pub fn run(&self, inputs: Vec<AotiTensor>) -> Result<Vec<AotiTensor>, AotiError> {
+        let tensors = inputs
+            .iter()
+            .map(AotiTensor::to_tensor)
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut model = self
+            .model
+            .lock()
+            .map_err(|_| AotiError::Model("model was poisoned by an earlier panic".into()))?;
+        Ok(match &mut *model {
+            AnyAOTIModel::Cpu(m) => run_typed(m, &tensors),
+            #[cfg(aoti_cuda)]
+            AnyAOTIModel::Cuda(m) => run_typed(m, &tensors),
+        }?)
+    }
+
+    pub fn metadata(&self) -> Result<HashMap<String, String>, AotiError> {
+        let model = self
+            .model
+            .lock()
+            .map_err(|_| AotiError::Model("model was poisoned by an earlier panic".into()))?;
+        Ok(match &*model {
+            AnyAOTIModel::Cpu(m) => m.get_metadata(),
+            #[cfg(aoti_cuda)]
+            AnyAOTIModel::Cuda(m) => m.get_metadata(),
+        }?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tensor_records_round_trip() {
+        let t = Tensor::from_slice(&[1.5f32, -2.0, 3.25, 0.0]).view([2, 2]);
+        let record = AotiTensor::from_tensor(&t).unwrap();
+        assert_eq!(record.dtype, AotiDtype::Float32);
+        assert_eq!(record.shape, vec![2, 2]);
+        assert_eq!(record.data.len(), 16);
+        assert!(record.to_tensor().unwrap().equal(&t));
+    }
+
+    #[test]
+    fn short_buffers_are_rejected() {
+        let record = AotiTensor {
+            dtype: AotiDtype::Int64,
+            shape: vec![3],
+            data: vec![0; 16],
+        };
+        assert!(matches!(
+            AotiError::from(record.to_tensor().unwrap_err()),
+            AotiError::InvalidInput(_)
+        ));
+        // The element count overflows, so the shape is rejected.
+        let overflowing = AotiTensor {
+            dtype: AotiDtype::Int64,
+            shape: vec![1 << 62, 4],
+            data: Vec::new(),
+        };
+        assert!(matches!(
+            overflowing.to_tensor(),
+            Err(Error::InvalidInput(_))
+        ));
+    }
+}
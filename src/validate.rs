@@ -0,0 +1,330 @@
+//! Checks on inputs before they cross into the AOTI runtime.
+//!
+//! The compiled kernels trust their inputs: they index them with the
+//! strides the package was exported with and never check the input count.
+//! A wrong count, an undefined tensor, or a view whose elements overlap
+//! (e.g. an `expand`ed tensor, whose storage is smaller than its element
+//! count) would have them read or write outside the tensor's memory, so
+//! `run` rejects those first. Other layouts and all dtypes
+//! and shapes are left to the runtime, which reports its own errors.
+//!
+//! Inputs tracked by autograd are rejected too, as the runtime neither
+//! records their history nor expects leaves it may not write into,
+//! unless the model was built to detach them.
+//!
+//! The same goes for what else the runtime trusts: CUDA inputs on another
+//! GPU than the model's, and replacement constants whose dtype, shape or
+//! device differ from the values the kernels were compiled against.
+//!
+//! After a run, [`aliases`] can flag outputs that share memory with an
+//! input, as the runtime may return an input (or a view of one) as is,
+//! or reuse a donated input's buffer in place.
+
+use std::ops::Range;
+
+use serde_json::Value;
+use tch::Tensor;
+
+use crate::{Error, OutputAlias};
+
+/// Reject `inputs` if there aren't `expected` of them (when known) or any
+/// is undefined or could overlap itself in memory.
+pub(crate) fn check_inputs<'a>(
+    inputs: impl ExactSizeIterator<Item = &'a Tensor>,
+    expected: Option<usize>,
+) -> Result<(), Error> {
+    if let Some(expected) = expected
+        && inputs.len() != expected
+    {
+        return Err(Error::InvalidInput(format!(
+            "the model takes {expected} inputs, got {}",
+            inputs.len()
+        )));
+    }
+    for (i, input) in inputs.enumerate() {
+        if !input.defined() {
+            return Err(Error::InvalidInput(format!("input {i} is undefined")));
+        }
+        let (size, stride) = (input.size(), input.stride());
+        if !non_overlapping(&size, &stride) {
+            return Err(Error::InvalidInput(format!(
+                "input {i} has overlapping memory (size {size:?}, stride {stride:?}); \
+                 make it contiguous first"
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Reject `inputs` if any requires grad, unless `detach`; returns
+/// whether any does and so needs detaching.
+pub(crate) fn check_grad<'a>(
+    mut inputs: impl Iterator<Item = &'a Tensor>,
+    detach: bool,
+) -> Result<bool, Error> {
+    let Some(i) = inputs.position(Tensor::requires_grad) else {
+        return Ok(false);
+    };
+    if detach {
+        return Ok(true);
+    }
+    Err(Error::InvalidInput(format!(
+        "input {i} requires grad, which compiled models don't support; pass \
+         `tensor.detach()` or build the model with `detach_grad_inputs(true)`"
+    )))
+}
+
+/// Reject inputs on another device than the model's `device`, e.g. a
+/// different GPU: the kernels would dereference them on the wrong one.
+pub(crate) fn check_devices<'a>(
+    inputs: impl Iterator<Item = &'a Tensor>,
+    device: tch::Device,
+) -> Result<(), Error> {
+    for (i, input) in inputs.enumerate() {
+        let found = input.device();
+        if found != device {
+            return Err(Error::InvalidInput(format!(
+                "input {i} is on {found:?} but the model runs on {device:?}"
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Reject `value` as the new value of constant `name` unless it is defined
+/// and has the dtype, shape and device of the `current` one; the container
+/// copies it into place without checking.
+pub(crate) fn check_constant(name: &str, value: &Tensor, current: &Tensor) -> Result<(), Error> {
+    if !value.defined() {
+        return Err(Error::InvalidInput(format!("constant {name} is undefined")));
+    }
+    let found = (value.kind(), value.size(), value.device());
+    let expected = (current.kind(), current.size(), current.device());
+    if found != expected {
+        return Err(Error::InvalidInput(format!(
+            "constant {name} must be {:?} {:?} on {:?}, got {:?} {:?} on {:?}",
+            expected.0, expected.1, expected.2, found.0, found.1, found.2
+        )));
+    }
+    Ok(())
+}
+
+/// The bytes `tensor`'s elements span, if it has any. Torch strides are
+/// never negative, so they run from its data pointer to its last element.
+pub(crate) fn extent(tensor: &Tensor) -> Option<Range<usize>> {
+    if !tensor.defined() || tensor.numel() == 0 {
+        return None;
+    }
+    let last: i64 = tensor
+        .size()
+        .iter()
+        .zip(&tensor.stride())
+        .map(|(&size, &stride)| (size - 1) * stride)
+        .sum();
+    let element = tensor.kind().elt_size_in_bytes();
+    let start = tensor.data_ptr() as usize;
+    Some(start..start + (last as usize + 1) * element)
+}
+
+/// Each output whose memory overlaps an input's, given the inputs'
+/// [`extent`]s, taken before the run.
+pub(crate) fn aliases<'a>(
+    inputs: &[Option<Range<usize>>],
+    outputs: impl Iterator<Item = &'a Tensor>,
+) -> Vec<OutputAlias> {
+    let mut aliases = Vec::new();
+    for (output, tensor) in outputs.enumerate() {
+        let Some(extent) = self::extent(tensor) else {
+            continue;
+        };
+        for (input, range) in inputs.iter().enumerate() {
+            if let Some(range) = range
+                && range.start < extent.end
+                && extent.start < range.end
+            {
+                aliases.push(OutputAlias { output, input });
+            }
+        }
+    }
+    aliases
+}
+
+/// Whether no two elements of a `size`/`stride` view share memory. This
+/// is the usual sufficient check: taking dimensions by increasing stride,
+/// each must step past everything the ones before it span. It rejects
+/// some exotic interleaved views that don't actually overlap.
+fn non_overlapping(size: &[i64], stride: &[i64]) -> bool {
+    if size.len() != stride.len() || size.iter().any(|&s| s < 0) {
+        return false;
+    }
+    if size.contains(&0) {
+        return true;
+    }
+    let mut dims: Vec<(i64, i64)> = size
+        .iter()
+        .zip(stride)
+        .filter(|&(&size, _)| size != 1)
+        .map(|(&size, &stride)| (size, stride))
+        .collect();
+    dims.sort_unstable_by_key(|&(_, stride)| stride);
+    let mut extent = 0i64;
+    for (size, stride) in dims {
+        if stride <= extent {
+            return false;
+        }
+        match stride
+            .checked_mul(size - 1)
+            .and_then(|span| extent.checked_add(span))
+        {
+            Some(spanned) => extent = spanned,
+            None => return false,
+        }
+    }
+    true
+}
+
+/// The number of flat inputs a serialized pytree `in_spec` describes: the
+/// leaves of its tree, `[version, tree]` in PyTorch's JSON format.
+pub(crate) fn input_count(in_spec: &str) -> Result<usize, Error> {
+    let spec: Value = serde_json::from_str(in_spec)?;
+    let tree = match &spec {
+        Value::Array(parts) if parts.len() == 2 => &parts[1],
+        _ => return Err(Error::Model("in_spec is not [version, tree]".into())),
+    };
+    leaves(tree).ok_or_else(|| Error::Model("in_spec has a malformed tree".into()))
+}
+
+/// The flat inputs each argument in a serialized `in_spec` takes, its
+/// positional arguments then its keyword ones: none for an argument
+/// exported as `None`. `None` unless the tree is an `(args, kwargs)` pair.
+pub(crate) fn argument_inputs(in_spec: &str) -> Option<Vec<usize>> {
+    let spec: Value = serde_json::from_str(in_spec).ok()?;
+    let tree = match &spec {
+        Value::Array(parts) if parts.len() == 2 => &parts[1],
+        _ => return None,
+    };
+    let [args, kwargs] = children(tree)? else {
+        return None;
+    };
+    children(args)?
+        .iter()
+        .chain(children(kwargs)?)
+        .map(leaves)
+        .collect()
+}
+
+fn children(node: &Value) -> Option<&[Value]> {
+    let children = node.as_object()?.get("children_spec")?.as_array()?;
+    Some(children)
+}
+
+fn leaves(node: &Value) -> Option<usize> {
+    let node = node.as_object()?;
+    if node.get("type")?.is_null() {
+        return Some(1);
+    }
+    node.get("children_spec")?
+        .as_array()?
+        .iter()
+        .try_fold(0usize, |count, child| count.checked_add(leaves(child)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overlapping_and_undefined_inputs_are_rejected() {
+        let x = Tensor::zeros([2, 3], (tch::Kind::Float, tch::Device::Cpu));
+        let transposed = x.tr();
+        let sliced = x.slice(1, 0, 3, 2);
+        let expanded =
+            Tensor::zeros([1, 3], (tch::Kind::Float, tch::Device::Cpu)).expand([4, 3], false);
+        let empty = x.slice(0, 0, 0, 1);
+        assert!(check_inputs([&x, &transposed, &sliced, &empty].into_iter(), Some(4)).is_ok());
+        assert!(check_inputs([&x].into_iter(), Some(2)).is_err());
+        assert!(check_inputs([&x, &expanded].into_iter(), None).is_err());
+        assert!(check_inputs([&Tensor::new()].into_iter(), None).is_err());
+
+        assert!(non_overlapping(&[4, 1], &[1, 0]));
+        assert!(!non_overlapping(&[2, 2], &[1, 1]));
+        assert!(!non_overlapping(&[i64::MAX, 3], &[1, i64::MAX]));
+        assert!(!non_overlapping(&[2], &[1, 1]));
+    }
+
+    #[test]
+    fn inputs_requiring_grad_are_rejected_unless_detached() {
+        let x = Tensor::zeros([2], (tch::Kind::Float, tch::Device::Cpu));
+        let tracked = x.copy().set_requires_grad(true);
+        assert!(!check_grad([&x, &x].into_iter(), false).unwrap());
+        assert!(check_grad([&x, &tracked].into_iter(), true).unwrap());
+        let err = check_grad([&x, &tracked].into_iter(), false).unwrap_err();
+        assert!(err.to_string().contains("input 1 requires grad"));
+    }
+
+    #[test]
+    fn constants_must_keep_their_dtype_shape_and_device() {
+        let current = Tensor::zeros([2, 3], (tch::Kind::Float, tch::Device::Cpu));
+        assert!(check_constant("w", &current.ones_like(), &current).is_ok());
+        for bad in [
+            Tensor::new(),
+            current.to_kind(tch::Kind::Half),
+            current.reshape([3, 2]),
+        ] {
+            assert!(matches!(
+                check_constant("w", &bad, &current),
+                Err(Error::InvalidInput(_))
+            ));
+        }
+        assert!(check_devices([&current].into_iter(), tch::Device::Cpu).is_ok());
+        assert!(check_devices([&current].into_iter(), tch::Device::Cuda(0)).is_err());
+    }
+
+    #[test]
+    fn outputs_overlapping_inputs_are_aliases() {
+        let x = Tensor::arange(12, (tch::Kind::Float, tch::Device::Cpu)).reshape([3, 4]);
+        let y = x.copy();
+        let inputs = [extent(&x), extent(&y)];
+        let outputs = [x.copy(), x.slice(0, 2, 3, 1), y.tr(), x.slice(0, 0, 0, 1)];
+        assert_eq!(
+            aliases(&inputs, outputs.iter()),
+            [
+                OutputAlias {
+                    output: 1,
+                    input: 0
+                },
+                OutputAlias {
+                    output: 2,
+                    input: 1
+                }
+            ]
+        );
+        assert_eq!(extent(&x).map(|r| r.len()), Some(48));
+    }
+
+    #[test]
+    fn in_spec_leaves_are_counted() {
+        let leaf = r#"{"type": null, "context": null, "children_spec": []}"#;
+        let spec = format!(
+            r#"[1, {{"type": "builtins.tuple", "context": "null", "children_spec": [
+                {{"type": "builtins.tuple", "context": "null", "children_spec": [{leaf}, {leaf}]}},
+                {{"type": "builtins.dict", "context": "[]", "children_spec": [{leaf}]}}
+            ]}}]"#
+        );
+        assert_eq!(input_count(&spec).unwrap(), 3);
+        assert_eq!(argument_inputs(&spec).unwrap(), [1, 1, 1]);
+        let none = r#"{"type": "builtins.NoneType", "context": "null", "children_spec": []}"#;
+        let optional = format!(
+            r#"[1, {{"type": "builtins.tuple", "context": "null", "children_spec": [
+                {{"type": "builtins.tuple", "context": "null", "children_spec": [{leaf}, {none}]}},
+                {{"type": "builtins.dict", "context": "[]", "children_spec": []}}
+            ]}}]"#
+        );
+        assert_eq!(input_count(&optional).unwrap(), 1);
+        assert_eq!(argument_inputs(&optional).unwrap(), [1, 0]);
+        assert_eq!(argument_inputs(&format!("[1, {leaf}]")), None);
+        assert!(input_count("[1]").is_err());
+        assert!(input_count(r#"[1, {"type": "x", "children_spec": 3}]"#).is_err());
+        assert!(input_count("not json").is_err());
+    }
+}
@@ -0,0 +1,209 @@
+//! Detached package signatures (feature `signatures`).
+//!
+//! A package is trusted if its signature file holds an Ed25519 signature,
+//! by one of the configured keys, over the SHA-256 digest of the package
+//! file as stored (so over the ciphertext of an encrypted package). The
+//! signature file holds the 64 signature bytes, raw or hex-encoded, and by
+//! default sits beside the package as `<package>.sig`. For example, with
+//! Python's `cryptography`:
+//!
+//! ```text
+//! sig = private_key.sign(hashlib.sha256(open("model.pt2", "rb").read()).digest())
+//! open("model.pt2.sig", "w").write(sig.hex())
+//! ```
+//!
+//! Verification happens before the package is decrypted or extracted, so
+//! an untrusted package never reaches libtorch. The package is hashed as
+//! it is copied to a private temp file, which is what gets loaded, so
+//! replacing or rewriting the file after the check changes nothing.
+
+use std::fs::File;
+use std::io::{Seek, Write};
+use std::path::Path;
+
+use ed25519_dalek::{Signature, VerifyingKey};
+use sha2::{Digest, Sha256};
+
+use crate::Error;
+
+/// Public keys whose signatures make a package trusted; see
+/// [`AOTIModelBuilder::trusted_keys`](crate::AOTIModelBuilder::trusted_keys).
+#[derive(Debug, Clone, Default)]
+pub struct TrustedKeys(Vec<VerifyingKey>);
+
+impl TrustedKeys {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Trust the Ed25519 public key `key`. Fails with
+    /// [`Error::InvalidInput`] if it isn't a valid curve point.
+    pub fn with_key(mut self, key: &[u8; 32]) -> Result<Self, Error> {
+        let key = VerifyingKey::from_bytes(key)
+            .map_err(|e| Error::InvalidInput(format!("invalid Ed25519 public key: {e}")))?;
+        self.0.push(key);
+        Ok(self)
+    }
+
+    /// [`TrustedKeys::with_key`] for a hex-encoded key.
+    pub fn with_hex_key(self, key: &str) -> Result<Self, Error> {
+        let bytes = decode_hex(key.trim())
+            .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+            .ok_or_else(|| Error::InvalidInput("an Ed25519 public key is 64 hex digits".into()))?;
+        self.with_key(&bytes)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Check `package` against the detached signature in `signature`, failing
+/// with [`Error::UntrustedPackage`] unless a trusted key made it. Returns
+/// the verified bytes as an unnamed temp file, positioned at its start, to
+/// load instead of the path.
+pub(crate) fn verify_package(
+    package: impl AsRef<Path>,
+    signature: impl AsRef<Path>,
+    keys: &TrustedKeys,
+) -> Result<File, Error> {
+    let (package, signature) = (package.as_ref(), signature.as_ref());
+    let untrusted =
+        |reason: String| Error::UntrustedPackage(format!("{}: {reason}", package.display()));
+    let bytes = match std::fs::read(signature) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            return Err(untrusted(format!(
+                "no signature at {} ({e})",
+                signature.display()
+            )));
+        }
+    };
+    let signature = parse_signature(&bytes).ok_or_else(|| {
+        untrusted(format!(
+            "{} is not an Ed25519 signature",
+            signature.display()
+        ))
+    })?;
+    let mut copy = HashingWriter {
+        file: tempfile::tempfile()?,
+        hasher: Sha256::new(),
+    };
+    std::io::copy(&mut File::open(package)?, &mut copy)?;
+    let HashingWriter { mut file, hasher } = copy;
+    let digest = hasher.finalize();
+    if keys
+        .0
+        .iter()
+        .any(|key| key.verify_strict(&digest, &signature).is_ok())
+    {
+        file.rewind()?;
+        Ok(file)
+    } else {
+        Err(untrusted("the signature matches no trusted key".into()))
+    }
+}
+
+/// Writes to `file`, hashing what it writes.
+struct HashingWriter {
+    file: File,
+    hasher: Sha256,
+}
+
+impl Write for HashingWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.file.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// A signature file's contents: 64 raw bytes, or 128 hex digits.
+fn parse_signature(bytes: &[u8]) -> Option<Signature> {
+    let raw = match <[u8; 64]>::try_from(bytes) {
+        Ok(raw) => raw,
+        Err(_) => {
+            let text = std::str::from_utf8(bytes).ok()?.trim();
+            <[u8; 64]>::try_from(decode_hex(text)?).ok()?
+        }
+    };
+    Some(Signature::from_bytes(&raw))
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+    use std::io::{Read, Write};
+
+    fn signed(key: &SigningKey, data: &[u8]) -> (tempfile::TempDir, String, String) {
+        let dir = tempfile::tempdir().unwrap();
+        let package = dir.path().join("model.pt2");
+        std::fs::write(&package, data).unwrap();
+        let signature = key.sign(&Sha256::digest(data));
+        let sig_path = dir.path().join("model.pt2.sig");
+        let hex: String = signature
+            .to_bytes()
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect();
+        writeln!(File::create(&sig_path).unwrap(), "{hex}").unwrap();
+        let path = |p: std::path::PathBuf| p.to_str().unwrap().to_string();
+        (dir, path(package), path(sig_path))
+    }
+
+    #[test]
+    fn packages_signed_by_a_trusted_key_pass() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let (_dir, package, signature) = signed(&key, b"package bytes");
+        let trusted = TrustedKeys::new()
+            .with_key(SigningKey::from_bytes(&[8; 32]).verifying_key().as_bytes())
+            .unwrap()
+            .with_key(key.verifying_key().as_bytes())
+            .unwrap();
+        let mut verified = verify_package(&package, &signature, &trusted).unwrap();
+        // The copy is what gets loaded, whatever happens to the path.
+        std::fs::write(&package, b"swapped bytes").unwrap();
+        let mut bytes = Vec::new();
+        verified.read_to_end(&mut bytes).unwrap();
+        assert_eq!(bytes, b"package bytes");
+    }
+
+    #[test]
+    fn tampered_unsigned_or_foreign_packages_are_untrusted() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let (_dir, package, signature) = signed(&key, b"package bytes");
+        let trusted = TrustedKeys::new()
+            .with_key(key.verifying_key().as_bytes())
+            .unwrap();
+        let untrusted = |r: Result<File, Error>| matches!(r, Err(Error::UntrustedPackage(_)));
+
+        std::fs::write(&package, b"package bytez").unwrap();
+        assert!(untrusted(verify_package(&package, &signature, &trusted)));
+        assert!(untrusted(verify_package(
+            &package,
+            "/nonexistent.sig",
+            &trusted
+        )));
+        assert!(untrusted(verify_package(
+            &package,
+            &signature,
+            &TrustedKeys::new()
+        )));
+        assert!(TrustedKeys::new().with_hex_key("abcd").is_err());
+    }
+}
@@ -0,0 +1,262 @@
+//! Image preprocessing for vision models (feature `vision`).
+//!
+//! [`ImagePreprocess`] reproduces the standard torchvision evaluation
+//! transform: resize the shorter side, center-crop to the model's input
+//! size, scale to `[0, 1]`, normalize per channel, and lay the result out as
+//! `[3, H, W]` (or `[N, 3, H, W]` for a batch) `Float` tensors.
+//!
+//! The crop size can be taken from package metadata recorded at export
+//! time, so the Rust side can't drift from what the model was exported for:
+//! `input_height` and `input_width` (or a square `input_size`), plus
+//! optional comma-separated `image_mean` / `image_std` triples.
+//!
+//! Only `image`'s buffer types are used; enable the `image` crate's format
+//! features in your own manifest to decode files.
+
+use std::marker::PhantomData;
+
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, RgbImage};
+use tch::{Kind, Tensor};
+
+use crate::{Cpu, DeviceTensor, Error, ModelMetadata};
+
+/// ImageNet channel means, the torchvision default.
+pub const IMAGENET_MEAN: [f32; 3] = [0.485, 0.456, 0.406];
+/// ImageNet channel standard deviations, the torchvision default.
+pub const IMAGENET_STD: [f32; 3] = [0.229, 0.224, 0.225];
+
+/// Resize, center-crop, and normalize images into model inputs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImagePreprocess {
+    height: u32,
+    width: u32,
+    resize: Option<u32>,
+    mean: [f32; 3],
+    std: [f32; 3],
+    filter: FilterType,
+}
+
+impl ImagePreprocess {
+    /// Preprocess to a `height × width` crop with ImageNet normalization,
+    /// resizing the shorter side to `256/224` of the crop first (the
+    /// torchvision evaluation ratio) with bilinear filtering.
+    pub fn new(height: u32, width: u32) -> Self {
+        let resize = (f64::from(height.min(width)) * 256.0 / 224.0).round() as u32;
+        Self {
+            height,
+            width,
+            resize: Some(resize),
+            mean: IMAGENET_MEAN,
+            std: IMAGENET_STD,
+            filter: FilterType::Triangle,
+        }
+    }
+
+    /// Build from the input size (and, if present, normalization) recorded
+    /// in a package's metadata.
+    ///
+    /// Returns [`Error::Model`] if the metadata records no input size.
+    pub fn from_metadata(metadata: &ModelMetadata) -> Result<Self, Error> {
+        let dim = |key: &str| -> Result<Option<u32>, Error> {
+            metadata
+                .get(key)
+                .map(|v| {
+                    v.trim().parse().map_err(|_| {
+                        Error::Model(format!("metadata {key}='{v}' is not an image size"))
+                    })
+                })
+                .transpose()
+        };
+        let (height, width) = match (
+            dim("input_height")?,
+            dim("input_width")?,
+            dim("input_size")?,
+        ) {
+            (Some(h), Some(w), _) => (h, w),
+            (_, _, Some(size)) => (size, size),
+            _ => {
+                return Err(Error::Model(
+                    "metadata records no input_height/input_width or input_size".into(),
+                ));
+            }
+        };
+        let mut preprocess = Self::new(height, width);
+        if let Some(mean) = metadata.get("image_mean") {
+            preprocess.mean = parse_triple("image_mean", mean)?;
+        }
+        if let Some(std) = metadata.get("image_std") {
+            preprocess.std = parse_triple("image_std", std)?;
+        }
+        Ok(preprocess)
+    }
+
+    /// Resize the shorter side to `size` before cropping, or, with `None`,
+    /// resize straight to the crop size (ignoring aspect ratio).
+    pub fn resize(mut self, size: Option<u32>) -> Self {
+        self.resize = size;
+        self
+    }
+
+    /// Set the per-channel normalization applied after scaling to `[0, 1]`.
+    pub fn normalize(mut self, mean: [f32; 3], std: [f32; 3]) -> Self {
+        self.mean = mean;
+        self.std = std;
+        self
+    }
+
+    /// Set the resampling filter (default: [`FilterType::Triangle`],
+    /// i.e. bilinear).
+    pub fn filter(mut self, filter: FilterType) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// The `(height, width)` of the produced tensors.
+    pub fn output_size(&self) -> (u32, u32) {
+        (self.height, self.width)
+    }
+
+    /// Resize and center-crop `image`, returning the RGB pixels that will be
+    /// fed to the model.
+    pub fn crop(&self, image: &DynamicImage) -> RgbImage {
+        self.crop_rgb(&image.to_rgb8())
+    }
+
+    fn crop_rgb(&self, rgb: &RgbImage) -> RgbImage {
+        let (w, h) = rgb.dimensions();
+        let (rw, rh) = match self.resize {
+            Some(short) if w <= h => (short, scale(h, short, w)),
+            Some(short) => (scale(w, short, h), short),
+            None => (self.width, self.height),
+        };
+        let resized = if (rw, rh) == (w, h) {
+            rgb.clone()
+        } else {
+            imageops::resize(rgb, rw, rh, self.filter)
+        };
+        let x = rw.saturating_sub(self.width) / 2;
+        let y = rh.saturating_sub(self.height) / 2;
+        let cropped = imageops::crop_imm(&resized, x, y, self.width, self.height).to_image();
+        if cropped.dimensions() == (self.width, self.height) {
+            cropped
+        } else {
+            // The resized image is smaller than the crop along some axis;
+            // stretch it rather than hand the model a wrongly-shaped input.
+            imageops::resize(&cropped, self.width, self.height, self.filter)
+        }
+    }
+
+    fn rgb_to_chw(&self, rgb: &RgbImage) -> Result<Tensor, Error> {
+        let (w, h) = rgb.dimensions();
+        let mean = Tensor::f_from_slice(&self.mean)?.f_view([3, 1, 1])?;
+        let std = Tensor::f_from_slice(&self.std)?.f_view([3, 1, 1])?;
+        Ok(Tensor::f_from_slice(rgb.as_raw())?
+            .f_view([i64::from(h), i64::from(w), 3])?
+            .f_permute([2, 0, 1])?
+            .f_to_kind(Kind::Float)?
+            .f_div_scalar(255.0)?
+            .f_sub(&mean)?
+            .f_div(&std)?)
+    }
+
+    /// Preprocess one image into a `[3, H, W]` tensor.
+    pub fn image_to_tensor(&self, image: &DynamicImage) -> Result<DeviceTensor<Cpu>, Error> {
+        Ok(DeviceTensor {
+            tensor: self.rgb_to_chw(&self.crop(image))?,
+            _device: PhantomData,
+        })
+    }
+
+    /// Preprocess a raw, row-major RGB8 buffer into a `[3, H, W]` tensor.
+    pub fn rgb_to_tensor(
+        &self,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+    ) -> Result<DeviceTensor<Cpu>, Error> {
+        let rgb = RgbImage::from_raw(width, height, pixels.to_vec()).ok_or_else(|| {
+            Error::InvalidInput(format!(
+                "{} bytes is too few for a {width}x{height} RGB image",
+                pixels.len()
+            ))
+        })?;
+        Ok(DeviceTensor {
+            tensor: self.rgb_to_chw(&self.crop_rgb(&rgb))?,
+            _device: PhantomData,
+        })
+    }
+
+    /// Preprocess a batch of images into one `[N, 3, H, W]` tensor.
+    pub fn batch(&self, images: &[DynamicImage]) -> Result<DeviceTensor<Cpu>, Error> {
+        if images.is_empty() {
+            return Err(Error::InvalidInput(
+                "cannot preprocess an empty batch".into(),
+            ));
+        }
+        let tensors = images
+            .iter()
+            .map(|image| self.rgb_to_chw(&self.crop(image)))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(DeviceTensor {
+            tensor: Tensor::f_stack(&tensors, 0)?,
+            _device: PhantomData,
+        })
+    }
+}
+
+/// Scale `long` by `short_target / short`, truncating like torchvision does.
+fn scale(long: u32, short_target: u32, short: u32) -> u32 {
+    ((u64::from(long) * u64::from(short_target)) / u64::from(short.max(1))).max(1) as u32
+}
+
+fn parse_triple(key: &str, value: &str) -> Result<[f32; 3], Error> {
+    let parsed: Vec<f32> = value
+        .trim_matches(|c| c == '[' || c == ']')
+        .split(',')
+        .map(|v| v.trim().parse())
+        .collect::<Result<_, _>>()
+        .map_err(|_| Error::Model(format!("metadata {key}='{value}' is not a float list")))?;
+    <[f32; 3]>::try_from(parsed)
+        .map_err(|_| Error::Model(format!("metadata {key}='{value}' must have 3 entries")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn crops_to_metadata_size() {
+        let metadata = ModelMetadata::from(HashMap::from([
+            ("input_size".to_string(), "4".to_string()),
+            ("image_mean".to_string(), "0.5, 0.5, 0.5".to_string()),
+            ("image_std".to_string(), "[0.5, 0.5, 0.5]".to_string()),
+        ]));
+        let preprocess = ImagePreprocess::from_metadata(&metadata).unwrap();
+        let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(12, 6, image::Rgb([255, 0, 0])));
+
+        let t = preprocess.image_to_tensor(&image).unwrap();
+        assert_eq!(t.size(), &[3, 4, 4]);
+        assert_eq!(t.kind(), Kind::Float);
+        assert_eq!(t.double_value(&[0, 0, 0]), 1.0);
+        assert_eq!(t.double_value(&[1, 3, 3]), -1.0);
+    }
+
+    #[test]
+    fn short_rgb_buffer_is_rejected() {
+        let preprocess = ImagePreprocess::new(2, 2);
+        assert!(matches!(
+            preprocess.rgb_to_tensor(2, 2, &[0; 11]),
+            Err(Error::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn missing_input_size_is_an_error() {
+        assert!(matches!(
+            ImagePreprocess::from_metadata(&ModelMetadata::default()),
+            Err(Error::Model(_))
+        ));
+    }
+}
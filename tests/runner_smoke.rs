@@ -42,8 +42,9 @@ fn loads_and_runs_tiny_model() {
     assert_eq!(outputs[0].size(), &[2, 8]);
     assert_eq!(outputs[0].device(), tch::Device::Cpu);
 
-    // boxed_run consumes its inputs so the runtime may reuse their storage.
-    let outputs = model.boxed_run(vec![cpu_input()]).expect("boxed_run");
+    // Inputs passed by value are donated so the runtime may reuse their
+    // storage.
+    let outputs = model.run(vec![cpu_input()]).expect("donating run");
     assert_eq!(outputs.len(), 1);
     assert_eq!(outputs[0].size(), &[2, 8]);
 }
@@ -137,3 +138,126 @@ fn load_metadata_from_package_streams_json() {
         Some("cpu")
     );
 }
+
+#[test]
+fn package_lists_its_models_and_constants() {
+    let path = pt2_path();
+    if !std::path::Path::new(&path).exists() {
+        eprintln!("skipping: {path} does not exist");
+        return;
+    }
+    let models = aoti_rs::package_models(&path).expect("models");
+    assert!(models.contains(&model_name()), "{models:?}");
+    let mut model = AOTIModel::<Cpu>::builder(&path)
+        .model_name(model_name())
+        .build()
+        .expect("build");
+    for constant in model.constants().expect("constants") {
+        let numel: i64 = constant.shape.iter().product();
+        assert!(constant.bytes >= numel as u64, "{constant:?}");
+    }
+}
+
+#[test]
+fn build_reports_progress_and_loads_in_background() {
+    use aoti_rs::LoadPhase;
+
+    let path = pt2_path();
+    if !std::path::Path::new(&path).exists() {
+        eprintln!("skipping: {path} does not exist");
+        return;
+    }
+    let mut phases = Vec::new();
+    AOTIModel::<Cpu>::builder(&path)
+        .model_name(model_name())
+        .build_with_progress(|p| {
+            assert!(p.bytes_total.is_none_or(|total| p.bytes_done <= total));
+            phases.push(p.phase);
+        })
+        .expect("build");
+    phases.dedup();
+    assert_eq!(phases, [LoadPhase::Extract, LoadPhase::Load]);
+
+    let loading = AOTIModel::<Cpu>::builder(&path)
+        .model_name(model_name())
+        .build_async();
+    let mut model = loading.wait().expect("build_async");
+    model.run(&[cpu_input()]).expect("run");
+}
+
+#[test]
+fn pool_shutdown_refuses_new_runs() {
+    use aoti_rs::{AOTIModelPool, Error};
+    use std::time::Duration;
+
+    let path = pt2_path();
+    if !std::path::Path::new(&path).exists() {
+        eprintln!("skipping: {path} does not exist");
+        return;
+    }
+    let pool = AOTIModelPool::from_fn(2, |_| {
+        AOTIModel::<Cpu>::builder(&path)
+            .model_name(model_name())
+            .build()
+    })
+    .expect("pool");
+    pool.run(&[cpu_input()]).expect("run");
+    assert!(pool.shutdown(Duration::from_secs(1)));
+    assert!(pool.is_shut_down());
+    assert!(matches!(pool.run(&[cpu_input()]), Err(Error::ShutDown)));
+    assert!(pool.metadata().get("AOTI_DEVICE_KEY").is_some());
+}
+
+#[test]
+fn pool_skips_runs_past_their_deadline() {
+    use aoti_rs::{AOTIModelPool, Error};
+    use std::time::{Duration, Instant};
+
+    let path = pt2_path();
+    if !std::path::Path::new(&path).exists() {
+        eprintln!("skipping: {path} does not exist");
+        return;
+    }
+    let pool = AOTIModelPool::new(vec![
+        AOTIModel::<Cpu>::builder(&path)
+            .model_name(model_name())
+            .build()
+            .expect("load"),
+    ])
+    .expect("pool");
+    let later = Instant::now() + Duration::from_secs(60);
+    pool.run_before(later, &[cpu_input()]).expect("run");
+    assert!(matches!(
+        pool.run_before(Instant::now(), &[cpu_input()]),
+        Err(Error::DeadlineExceeded)
+    ));
+}
+
+#[test]
+fn pool_health_check_runs_probe() {
+    use aoti_rs::{AOTIModelPool, Health};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    let path = pt2_path();
+    if !std::path::Path::new(&path).exists() {
+        eprintln!("skipping: {path} does not exist");
+        return;
+    }
+    let pool = Arc::new(
+        AOTIModelPool::from_fn(1, |_| {
+            AOTIModel::<Cpu>::builder(&path)
+                .model_name(model_name())
+                .build()
+        })
+        .expect("pool"),
+    );
+    assert!(pool.health_check(Duration::from_secs(30)).is_ready());
+    pool.set_health_probe(vec![cpu_input()]);
+    assert!(pool.health_check(Duration::from_secs(30)).is_ready());
+    pool.shutdown(Duration::ZERO);
+    assert_eq!(
+        pool.health_check(Duration::from_secs(30)),
+        Health::ShuttingDown
+    );
+}